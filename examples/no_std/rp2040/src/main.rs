@@ -10,6 +10,7 @@ use {defmt_rtt as _, panic_probe as _};
 
 use bsp::hal::{clocks::init_clocks_and_plls, pac, usb::UsbBus, watchdog::Watchdog};
 
+use core::cell::RefCell;
 use core::fmt::Write as FmtWrite;
 
 use noline::builder::EditorBuilder;
@@ -44,6 +45,12 @@ impl<'a> SerialWrapper<'a> {
         self.ready = self.ready | self.poll();
         self.ready
     }
+
+    // The host sets DTR while the port is open
+    fn connected(&mut self) -> bool {
+        self.poll();
+        self.serial.dtr()
+    }
 }
 
 #[derive(Debug)]
@@ -74,6 +81,12 @@ impl<'a> ReadReady for SerialWrapper<'a> {
 impl<'a> Read for SerialWrapper<'a> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
         loop {
+            // Fail instead of waiting forever when the host closes the
+            // port, so the editor checks the link
+            if !self.connected() {
+                return Err(UsbError::InvalidState.into());
+            }
+
             while !self.read_ready()? {
                 continue;
             }
@@ -97,6 +110,10 @@ impl<'a> WriteReady for SerialWrapper<'a> {
 impl<'a> Write for SerialWrapper<'a> {
     fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
         loop {
+            if !self.connected() {
+                return Err(UsbError::InvalidState.into());
+            }
+
             while !self.write_ready()? {
                 continue;
             }
@@ -116,6 +133,29 @@ impl<'a> Write for SerialWrapper<'a> {
     }
 }
 
+// Serial port shared by the editor IO and the link state
+struct SharedSerial<'a, 'b>(&'b RefCell<SerialWrapper<'a>>);
+
+impl<'a, 'b> ErrorType for SharedSerial<'a, 'b> {
+    type Error = Error;
+}
+
+impl<'a, 'b> Read for SharedSerial<'a, 'b> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.0.borrow_mut().read(buf)
+    }
+}
+
+impl<'a, 'b> Write for SharedSerial<'a, 'b> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.0.borrow_mut().flush()
+    }
+}
+
 #[entry]
 fn main() -> ! {
     info!("Starting...");
@@ -165,14 +205,18 @@ fn main() -> ! {
 
     let prompt = "> ";
 
-    let mut io = SerialWrapper::new(usb_dev, serial);
+    let serial = RefCell::new(SerialWrapper::new(usb_dev, serial));
+    let mut io = SharedSerial(&serial);
 
     info!("Waiting for connection");
 
+    // The editor waits for the host to open the port before reading a
+    // line, and stops reading when it's closed
     let mut buffer = [0; 128];
     let mut history = [0; 128];
     let mut editor = EditorBuilder::from_slice(&mut buffer)
         .with_slice_history(&mut history)
+        .with_link_state(|| serial.borrow_mut().connected())
         .build_sync(&mut io)
         .unwrap();
 
@@ -186,11 +230,19 @@ fn main() -> ! {
                     writeln!(io, "Echo: \r").unwrap();
                 }
             }
+            Err(NolineError::Disconnected) => info!("Disconnected"),
             Err(err) => {
                 let error = match err {
-                    NolineError::IoError(_) => "IoError",
+                    NolineError::ReadError(_) => "ReadError",
+                    NolineError::WriteError(_) => "WriteError",
                     NolineError::ParserError => "ParserError",
                     NolineError::Aborted => "Aborted",
+                    NolineError::Eof => "Eof",
+                    NolineError::ProbeFailed(_) => "ProbeFailed",
+                    NolineError::IdleAborted => "IdleAborted",
+                    NolineError::OutputTooSmall(_) => "OutputTooSmall",
+                    NolineError::Disconnected => unreachable!(),
+                    _ => "Unknown",
                 };
                 writeln!(io, "Error: {}\r", error).unwrap();
             }
@@ -0,0 +1,65 @@
+//! Noline in the browser, with xterm.js as terminal
+//!
+//! Input from xterm.js `onData` is pushed to the editor, and output is
+//! written with xterm.js `write()`. Output is flow controlled, granting
+//! more credit from the `write()` callback when xterm.js has processed
+//! the output.
+
+use js_sys::{Function, Uint8Array};
+use noline::{
+    builder::EditorBuilder,
+    history::UnboundedHistory,
+    line_buffer::UnboundedBuffer,
+    push::PushIO,
+    sync_editor::{Editor, PollResult},
+};
+use wasm_bindgen::prelude::*;
+
+type Sink = Box<dyn FnMut(&[u8])>;
+
+#[wasm_bindgen]
+pub struct Session {
+    editor: Editor<UnboundedBuffer, UnboundedHistory>,
+    io: PushIO<Sink, 256>,
+}
+
+#[wasm_bindgen]
+impl Session {
+    /// Create session writing output with `write(bytes)`, allowing
+    /// `credit` bytes of output before more is granted
+    #[wasm_bindgen(constructor)]
+    pub fn new(write: Function, credit: usize) -> Result<Session, JsError> {
+        let sink: Sink = Box::new(move |bytes: &[u8]| {
+            let _ = write.call1(&JsValue::NULL, &Uint8Array::from(bytes));
+        });
+        let mut io = PushIO::new(sink, credit);
+
+        let editor = EditorBuilder::new_unbounded()
+            .with_unbounded_history()
+            .build_sync(&mut io)
+            .map_err(|err| JsError::new(&err.to_string()))?;
+
+        Ok(Session { editor, io })
+    }
+
+    /// Queue input from the terminal. Returns number of bytes queued,
+    /// push the rest after the next poll.
+    pub fn push_input(&mut self, data: &[u8]) -> usize {
+        self.io.push_input(data)
+    }
+
+    /// Allow `bytes` more bytes of output
+    pub fn output_credit(&mut self, bytes: usize) {
+        self.io.output_credit(bytes);
+    }
+
+    /// Handle queued input. Returns the line when it's done. The next
+    /// poll starts a new line, also after Ctrl+C.
+    pub fn poll(&mut self, prompt: &str) -> Result<Option<String>, JsError> {
+        match self.editor.readline_poll(prompt, &mut self.io) {
+            Ok(PollResult::Done(line)) => Ok(Some(line.to_string())),
+            Ok(PollResult::Pending | PollResult::Aborted) => Ok(None),
+            Err(err) => Err(JsError::new(&err.to_string())),
+        }
+    }
+}
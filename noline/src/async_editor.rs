@@ -2,32 +2,119 @@
 
 //! Implementation for async Editor
 
-use embedded_io_async::ReadExactError;
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use embedded_io_async::ErrorKind;
 
 use crate::{
-    core::{Line, Prompt},
+    complete::{Completer, NoCompleter},
+    core::{
+        copy_line, newline_pair, Line, LineState, Mask, Prompt, PromptSource, Settings,
+        DEFAULT_PROMPT,
+    },
     error::NolineError,
-    history::{get_history_entries, CircularSlice, History},
+    hint::{Hinter, NoHinter},
+    history::{serialized_slices, BorrowHistory, CircularSlice, History, HistoryLoader, NoHistory},
     line_buffer::{Buffer, LineBuffer},
-    output::{Output, OutputItem},
+    link::{AlwaysConnected, LinkState},
+    output::{ChunkPosition, Output, OutputItem, WRITE_CHUNK_CAPACITY},
+    read_buffer::ReadBuffer,
     terminal::Terminal,
+    undo::UndoBuffer,
+    validate::{NoValidator, Validator},
+    write_buffer::WriteBuffer,
 };
 
+// Future returning pending once, letting the executor run other tasks
+struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            Poll::Ready(())
+        } else {
+            self.yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
+
 /// Line editor for async IO
 ///
 /// It is recommended to use [`crate::builder::EditorBuilder`] to build an editor.
-pub struct Editor<B: Buffer, H: History> {
+///
+/// Reading a line is cancellation safe. If a `readline` future is
+/// dropped before the line is done, e.g. when racing it against a
+/// shutdown signal with `select`, the next `readline` call draws the
+/// line again from the start with the input typed so far and the
+/// cursor where it was, also when the future is dropped in the middle
+/// of a write. Input typed before the terminal was probed is lost.
+/// Output can be printed above the abandoned line with
+/// [`Editor::print_above`] before calling `readline` again.
+///
+/// Output is copied into a buffer of 64 bytes, see
+/// [`crate::builder::EditorBuilder::with_write_size`], and written a
+/// buffer at a time. The line buffer is borrowed, so the size of the
+/// `readline` future doesn't grow with it.
+pub struct Editor<
+    B: Buffer,
+    H: BorrowHistory,
+    C: Completer = NoCompleter,
+    L: LinkState = AlwaysConnected,
+    V: Validator = NoValidator,
+    T: Hinter = NoHinter,
+> {
     buffer: LineBuffer<B>,
     terminal: Terminal,
     history: H,
+    completer: Option<C>,
+    validator: Option<V>,
+    hinter: Option<T>,
+    pub(crate) kill_buffer: Option<LineBuffer<B>>,
+    pub(crate) undo_buffer: Option<UndoBuffer<B>>,
+    pub(crate) stash_buffer: Option<LineBuffer<B>>,
+    // Output held back while the IO doesn't take it
+    pub(crate) write_buffer: Option<WriteBuffer<B>>,
+    link_state: L,
+    pub(crate) settings: Settings,
+    // Prompt used by `readline_default`
+    pub(crate) prompt: &'static str,
+    // Set while reading a line after the probe, so a line abandoned
+    // by dropping the future is known to the next call
+    line_in_progress: bool,
+    // Cursor position in the abandoned line while erased by `hide`,
+    // for `unhide` or the next call to draw it again
+    hidden_at: Option<usize>,
+    // Input read but not handled yet
+    input: ReadBuffer,
+    // Insertions rejected by a full buffer in the current line
+    overflows: usize,
+    // Second byte of the newline pair the last line ended with, skipped
+    // if it's the next byte
+    pending_newline: Option<u8>,
 }
 
 impl<B, H> Editor<B, H>
 where
     B: Buffer,
-    H: History,
+    H: BorrowHistory,
 {
-    /// Create and initialize line editor
+    /// Create line editor without IO. The terminal size is probed when
+    /// reading the first line, use [`Editor::new_probed`] to know it
+    /// before that.
     pub async fn new<IO: embedded_io_async::Read + embedded_io_async::Write>(
         buffer: LineBuffer<B>,
         history: H,
@@ -39,50 +126,391 @@ where
             buffer,
             terminal,
             history,
+            completer: None,
+            validator: None,
+            hinter: None,
+            kill_buffer: None,
+            undo_buffer: None,
+            stash_buffer: None,
+            write_buffer: None,
+            link_state: AlwaysConnected,
+            settings: Settings::default(),
+            prompt: DEFAULT_PROMPT,
+            line_in_progress: false,
+            hidden_at: None,
+            input: ReadBuffer::new(),
+            overflows: 0,
+            pending_newline: None,
         })
     }
 
-    async fn handle_output<'b, 'item, IO, I>(
-        output: Output<'b, B, I>,
+    /// Create line editor and probe the terminal for its size, e.g. to
+    /// print a banner fitting the terminal before the first line. The
+    /// cursor is left where it was. Fails like [`Editor::readline`]
+    /// when the terminal doesn't reply, use [`Editor::new`] for dumb
+    /// terminals and pipes.
+    pub async fn new_probed<IO: embedded_io_async::Read + embedded_io_async::Write>(
+        buffer: LineBuffer<B>,
+        history: H,
+        io: &mut IO,
+    ) -> Result<Self, NolineError> {
+        let mut editor = Self::new(buffer, history, io).await?;
+        editor.refresh_size(io).await?;
+
+        Ok(editor)
+    }
+
+    pub(crate) fn with_completer<C: Completer>(self, completer: Option<C>) -> Editor<B, H, C> {
+        Editor {
+            buffer: self.buffer,
+            terminal: self.terminal,
+            history: self.history,
+            completer,
+            validator: self.validator,
+            hinter: self.hinter,
+            kill_buffer: self.kill_buffer,
+            undo_buffer: self.undo_buffer,
+            stash_buffer: self.stash_buffer,
+            write_buffer: self.write_buffer,
+            link_state: self.link_state,
+            settings: self.settings,
+            prompt: self.prompt,
+            line_in_progress: self.line_in_progress,
+            hidden_at: self.hidden_at,
+            input: self.input,
+            overflows: self.overflows,
+            pending_newline: self.pending_newline,
+        }
+    }
+}
+
+impl<B, H, C> Editor<B, H, C>
+where
+    B: Buffer,
+    H: BorrowHistory,
+    C: Completer,
+{
+    pub(crate) fn with_link_state<L: LinkState>(self, link_state: L) -> Editor<B, H, C, L> {
+        Editor {
+            buffer: self.buffer,
+            terminal: self.terminal,
+            history: self.history,
+            completer: self.completer,
+            validator: self.validator,
+            hinter: self.hinter,
+            kill_buffer: self.kill_buffer,
+            undo_buffer: self.undo_buffer,
+            stash_buffer: self.stash_buffer,
+            write_buffer: self.write_buffer,
+            link_state,
+            settings: self.settings,
+            prompt: self.prompt,
+            line_in_progress: self.line_in_progress,
+            hidden_at: self.hidden_at,
+            input: self.input,
+            overflows: self.overflows,
+            pending_newline: self.pending_newline,
+        }
+    }
+}
+
+impl<B, H, C, L> Editor<B, H, C, L>
+where
+    B: Buffer,
+    H: BorrowHistory,
+    C: Completer,
+    L: LinkState,
+{
+    pub(crate) fn with_validator<V: Validator>(
+        self,
+        validator: Option<V>,
+    ) -> Editor<B, H, C, L, V> {
+        Editor {
+            buffer: self.buffer,
+            terminal: self.terminal,
+            history: self.history,
+            completer: self.completer,
+            validator,
+            hinter: self.hinter,
+            kill_buffer: self.kill_buffer,
+            undo_buffer: self.undo_buffer,
+            stash_buffer: self.stash_buffer,
+            write_buffer: self.write_buffer,
+            link_state: self.link_state,
+            settings: self.settings,
+            prompt: self.prompt,
+            line_in_progress: self.line_in_progress,
+            hidden_at: self.hidden_at,
+            input: self.input,
+            overflows: self.overflows,
+            pending_newline: self.pending_newline,
+        }
+    }
+}
+
+impl<B, H, C, L, V> Editor<B, H, C, L, V>
+where
+    B: Buffer,
+    H: BorrowHistory,
+    C: Completer,
+    L: LinkState,
+    V: Validator,
+{
+    pub(crate) fn with_hinter<T: Hinter>(self, hinter: Option<T>) -> Editor<B, H, C, L, V, T> {
+        Editor {
+            buffer: self.buffer,
+            terminal: self.terminal,
+            history: self.history,
+            completer: self.completer,
+            validator: self.validator,
+            hinter,
+            kill_buffer: self.kill_buffer,
+            undo_buffer: self.undo_buffer,
+            stash_buffer: self.stash_buffer,
+            write_buffer: self.write_buffer,
+            link_state: self.link_state,
+            settings: self.settings,
+            prompt: self.prompt,
+            line_in_progress: self.line_in_progress,
+            hidden_at: self.hidden_at,
+            input: self.input,
+            overflows: self.overflows,
+            pending_newline: self.pending_newline,
+        }
+    }
+}
+
+impl<B, H, C, L, V, T> Editor<B, H, C, L, V, T>
+where
+    B: Buffer,
+    H: BorrowHistory,
+    C: Completer,
+    L: LinkState,
+    V: Validator,
+    T: Hinter,
+{
+    // IO errors are reported as a lost link when the link state says
+    // so, as that is likely why IO failed
+    fn check_link(err: NolineError, link: &mut L) -> NolineError {
+        match err {
+            NolineError::ReadError(_) | NolineError::WriteError(_) | NolineError::Aborted
+                if !link.connected() =>
+            {
+                NolineError::Disconnected
+            }
+            err => err,
+        }
+    }
+
+    // Output is copied into chunks of `write_size` bytes, which are
+    // written one at a time, so small items are written together. Only
+    // the chunk and its position in the output are kept while writing,
+    // not the output iterator.
+    //
+    // Output the IO doesn't accept is held in `held`, see
+    // [`Editor::write_all`].
+    async fn handle_output<'b, 'item: 'b, IO, P>(
+        output: &mut Output<'b, B, P, impl Completer, impl Hinter>,
         io: &mut IO,
+        link: &mut L,
+        mut held: Option<&mut WriteBuffer<B>>,
+        yield_every: usize,
+        write_size: usize,
     ) -> Result<Option<()>, NolineError>
     where
         IO: embedded_io_async::Read + embedded_io_async::Write,
-        I: Iterator<Item = &'item str> + Clone,
+        P: PromptSource<'item> + ?Sized,
     {
-        for item in output {
-            if let Some(bytes) = item.get_bytes() {
-                io.write(bytes).await?;
-            }
+        if !link.connected() {
+            return Err(NolineError::Disconnected);
+        }
 
-            io.flush().await?;
+        let mut result = Ok(None);
+        // Bytes written since the last flush
+        let mut written = 0;
+        let start = output.terminal().clone();
+        let mut position = ChunkPosition::default();
+        let mut chunk = [0; WRITE_CHUNK_CAPACITY];
+        let chunk = &mut chunk[..write_size];
 
-            match item {
-                OutputItem::EndOfString => return Ok(Some(())),
-                OutputItem::Abort => return Err(NolineError::Aborted),
+        loop {
+            // Keep writing if the line ends before the output does, so
+            // no output is lost
+            let len = output.fill_chunk(&start, &mut position, chunk, |item| match item {
+                OutputItem::EndOfString => result = Ok(Some(())),
+                OutputItem::Abort => result = Err(NolineError::Aborted),
+                OutputItem::Eof => result = Err(NolineError::Eof),
                 _ => (),
+            });
+
+            if len == 0 {
+                break;
+            }
+
+            Self::write_all(io, &chunk[..len], held.as_deref_mut())
+                .await
+                .map_err(|err| Self::check_link(err, link))?;
+            written += len;
+
+            if yield_every > 0 && written >= yield_every {
+                Self::flush(io, link).await?;
+                written = 0;
+                yield_now().await;
+            }
+        }
+
+        // Flush once for the whole output, which usually fits in a
+        // single chunk
+        if written > 0 {
+            Self::flush(io, link).await?;
+        }
+
+        result
+    }
+
+    // `write` may write only part of the bytes, e.g. when a ring
+    // buffer is nearly full, so write until all are written. Empty
+    // slices are never written, as some drivers, like usbd-serial,
+    // panic on them.
+    //
+    // Bytes left when `write` returns 0 are held in `held`, after any
+    // bytes already held, and written before more input is read, see
+    // [`Editor::write_all_held`]. Without `held`, or if they don't fit,
+    // writing fails.
+    async fn write_all<IO: embedded_io_async::Write>(
+        io: &mut IO,
+        mut bytes: &[u8],
+        held: Option<&mut WriteBuffer<B>>,
+    ) -> Result<(), NolineError> {
+        let holding = held.as_ref().is_some_and(|held| !held.is_empty());
+
+        while !bytes.is_empty() && !holding {
+            match io.write(bytes).await.map_err(NolineError::write_error)? {
+                0 => break,
+                len => bytes = &bytes[len..],
+            }
+        }
+
+        if bytes.is_empty() || held.is_some_and(|held| held.push(bytes)) {
+            Ok(())
+        } else {
+            Err(NolineError::WriteError(ErrorKind::WriteZero))
+        }
+    }
+
+    // Write bytes held by [`Editor::write_all`]. Returns false if the
+    // IO still doesn't accept all of them.
+    async fn write_held<IO: embedded_io_async::Write>(
+        io: &mut IO,
+        held: &mut WriteBuffer<B>,
+    ) -> Result<bool, NolineError> {
+        if held.is_empty() {
+            return Ok(true);
+        }
+
+        while !held.is_empty() {
+            match io
+                .write(held.front())
+                .await
+                .map_err(NolineError::write_error)?
+            {
+                0 => break,
+                len => held.consume(len),
             }
         }
 
-        Ok(None)
+        io.flush().await.map_err(NolineError::write_error)?;
+
+        Ok(held.is_empty())
+    }
+
+    // Write held bytes before reading input, yielding while the IO
+    // doesn't accept them, so the output of a key comes before the
+    // next key is handled
+    async fn write_all_held<IO: embedded_io_async::Write>(
+        io: &mut IO,
+        link: &mut L,
+        held: Option<&mut WriteBuffer<B>>,
+    ) -> Result<(), NolineError> {
+        if let Some(held) = held {
+            while !Self::write_held(io, held)
+                .await
+                .map_err(|err| Self::check_link(err, link))?
+            {
+                if !link.connected() {
+                    return Err(NolineError::Disconnected);
+                }
+
+                yield_now().await;
+            }
+        }
+
+        Ok(())
     }
 
-    async fn read_byte<IO>(io: &mut IO) -> Result<u8, NolineError>
+    async fn flush<IO: embedded_io_async::Write>(
+        io: &mut IO,
+        link: &mut L,
+    ) -> Result<(), NolineError> {
+        io.flush()
+            .await
+            .map_err(|err| Self::check_link(NolineError::write_error(err), link))
+    }
+
+    // Input is read in chunks of up to `read_size` bytes, and handed
+    // out a byte at a time. The probe reads a byte at a time, so keys
+    // typed after a reply are left for the line.
+    async fn read_byte<IO>(
+        io: &mut IO,
+        link: &mut L,
+        held: Option<&mut WriteBuffer<B>>,
+        input: &mut ReadBuffer,
+        read_size: usize,
+    ) -> Result<u8, NolineError>
     where
         IO: embedded_io_async::Read + embedded_io_async::Write,
     {
-        let mut buf = [0x8; 1];
+        // Input read before the link was lost is stale
+        if !link.connected() {
+            input.clear();
+            return Err(NolineError::Disconnected);
+        }
 
-        match io.read_exact(&mut buf).await {
-            Ok(_) => Ok(buf[0]),
-            Err(err) => match err {
-                ReadExactError::UnexpectedEof => Err(NolineError::Aborted),
-                ReadExactError::Other(err) => Err(err)?,
-            },
+        Self::write_all_held(io, link, held).await?;
+
+        if let Some(byte) = input.pop() {
+            return Ok(byte);
+        }
+
+        // Read a single byte when the buffer is full of bytes held back
+        // while probing
+        let mut single = [0; 1];
+        let space = input.space(read_size);
+        let full = space.is_empty();
+        let result = if full {
+            io.read(&mut single).await
+        } else {
+            io.read(space).await
+        };
+
+        match result {
+            Ok(0) => Err(Self::check_link(NolineError::Aborted, link)),
+            Ok(_) if full => Ok(single[0]),
+            Ok(len) => {
+                input.filled(len);
+                Ok(input.pop().unwrap())
+            }
+            Err(err) => Err(Self::check_link(NolineError::read_error(err), link)),
         }
     }
 
     /// Read line from `stdin`
+    ///
+    /// Returns [`NolineError::Aborted`] on Ctrl+C and
+    /// [`NolineError::Eof`] on Ctrl+D on an empty line. The unfinished
+    /// line is available from [`Editor::last_line`]. With a link state,
+    /// [`NolineError::Disconnected`] is returned when the link is lost,
+    /// see [`crate::link`].
     pub async fn readline<'b, 'item, IO, I>(
         &'b mut self,
         prompt: impl Into<Prompt<I>>,
@@ -90,47 +518,1971 @@ where
     ) -> Result<&'b str, NolineError>
     where
         IO: embedded_io_async::Read + embedded_io_async::Write,
-        I: Iterator<Item = &'item str> + Clone,
+        I: Iterator<Item = &'item str> + Clone + Send + Sync,
     {
-        let mut line = Line::new(
-            prompt,
-            &mut self.buffer,
-            &mut self.terminal,
-            &mut self.history,
-        );
+        let mut prompt: Prompt<I> = prompt.into();
+
+        self.readline_inner(&mut prompt, "", io, false, None).await
+    }
 
-        let mut reset = line.reset();
+    /// Read line from `stdin` like [`Editor::readline`], with the
+    /// prompt set with
+    /// [`crate::builder::EditorBuilder::with_prompt`]
+    pub async fn readline_default<IO>(&mut self, io: &mut IO) -> Result<&str, NolineError>
+    where
+        IO: embedded_io_async::Read + embedded_io_async::Write,
+    {
+        let mut prompt = Prompt::from(self.prompt);
 
-        Self::handle_output(reset.start(), io).await?;
+        self.readline_inner(&mut prompt, "", io, false, None).await
+    }
 
-        loop {
-            let byte = Self::read_byte(io).await?;
+    /// Read line like [`Editor::readline`], copying it to `out` and
+    /// returning its length in bytes. The editor isn't borrowed by the
+    /// line, so it can be used again while the line is handled, e.g. to
+    /// read a confirmation.
+    ///
+    /// Returns [`NolineError::OutputTooSmall`] if the line doesn't fit
+    /// in `out`, which is then left unchanged.
+    ///
+    /// Once the line is copied, the editor's buffer is truncated, so
+    /// [`Editor::last_line`] is empty and
+    /// [`Editor::readline_preserving_buffer`] starts from an empty line.
+    /// A line that doesn't fit stays in the buffer, like after
+    /// [`Editor::readline`].
+    pub async fn readline_into<'item, IO, I>(
+        &mut self,
+        prompt: impl Into<Prompt<I>>,
+        io: &mut IO,
+        out: &mut [u8],
+    ) -> Result<usize, NolineError>
+    where
+        IO: embedded_io_async::Read + embedded_io_async::Write,
+        I: Iterator<Item = &'item str> + Clone + Send + Sync,
+    {
+        let len = copy_line(self.readline(prompt, io).await?, out)?;
+
+        self.buffer.truncate();
+        Ok(len)
+    }
+
+    /// Read line from `stdin`, with `rprompt` shown at the right edge
+    /// of the first row. The right-side prompt is erased when the line
+    /// grows long enough to reach it, and is not part of the line.
+    pub async fn readline_with_rprompt<'b, 'item, IO, I>(
+        &'b mut self,
+        prompt: impl Into<Prompt<I>>,
+        rprompt: &str,
+        io: &mut IO,
+    ) -> Result<&'b str, NolineError>
+    where
+        IO: embedded_io_async::Read + embedded_io_async::Write,
+        I: Iterator<Item = &'item str> + Clone + Send + Sync,
+    {
+        let mut prompt: Prompt<I> = prompt.into();
+
+        self.readline_inner(&mut prompt, rprompt, io, false, None)
+            .await
+    }
+
+    /// Read line from `stdin`, starting with the content of the
+    /// previous line instead of an empty buffer. The cursor is placed
+    /// at the end of the line.
+    ///
+    /// This is useful for letting the user fix a rejected command
+    /// without retyping it. Note that the buffer also holds the
+    /// previous content if `readline` was aborted.
+    pub async fn readline_preserving_buffer<'b, 'item, IO, I>(
+        &'b mut self,
+        prompt: impl Into<Prompt<I>>,
+        io: &mut IO,
+    ) -> Result<&'b str, NolineError>
+    where
+        IO: embedded_io_async::Read + embedded_io_async::Write,
+        I: Iterator<Item = &'item str> + Clone + Send + Sync,
+    {
+        let mut prompt: Prompt<I> = prompt.into();
+
+        self.readline_inner(&mut prompt, "", io, true, None).await
+    }
+
+    /// Read line from `stdin`, starting with `initial` in the buffer
+    /// and the cursor at the end of it, e.g. to edit an existing
+    /// value. The initial text is edited like typed text.
+    ///
+    /// The initial text is truncated at a char boundary if it doesn't
+    /// fit in the buffer. A cancelled line is resumed as it was left,
+    /// without starting over with `initial`.
+    pub async fn readline_with_initial<'b, 'item, IO, I>(
+        &'b mut self,
+        prompt: impl Into<Prompt<I>>,
+        initial: &str,
+        io: &mut IO,
+    ) -> Result<&'b str, NolineError>
+    where
+        IO: embedded_io_async::Read + embedded_io_async::Write,
+        I: Iterator<Item = &'item str> + Clone + Send + Sync,
+    {
+        if !self.line_in_progress {
+            // The initial text is valid UTF-8
+            unsafe {
+                self.buffer.replace_with_slices(initial.as_bytes(), &[]);
+            }
+        }
+
+        let mut prompt: Prompt<I> = prompt.into();
+
+        self.readline_inner(&mut prompt, "", io, true, None).await
+    }
+
+    /// Read value with a fixed format like `___.___.___.___` for an
+    /// IP address. The template is displayed with `fill` marking the
+    /// cells to fill in, and the other chars of the template are
+    /// skipped by the cursor. Chars are accepted in a cell at char
+    /// index `index` of the template when `validator(c, index)`
+    /// returns true. Typing overwrites cells and deleting resets them
+    /// to `fill`, without shifting the rest of the line.
+    ///
+    /// Returns the template with the cells filled in, with unfilled
+    /// cells as `fill`. The template is truncated if it doesn't fit in
+    /// the buffer. Lines read this way are not added to history.
+    pub async fn readline_masked_template<'b, 'item, IO, I>(
+        &'b mut self,
+        prompt: impl Into<Prompt<I>>,
+        template: &str,
+        fill: char,
+        validator: fn(char, usize) -> bool,
+        io: &mut IO,
+    ) -> Result<&'b str, NolineError>
+    where
+        IO: embedded_io_async::Read + embedded_io_async::Write,
+        I: Iterator<Item = &'item str> + Clone + Send + Sync,
+    {
+        let mask = Mask::new(template, fill, validator);
+
+        let mut prompt: Prompt<I> = prompt.into();
+
+        self.readline_inner(&mut prompt, "", io, true, Some(&mask))
+            .await
+    }
 
-            if let Some(output) = reset.advance(byte) {
-                Self::handle_output(output, io).await?;
+    // Read a line with the prompt as `dyn PromptSource`, so the line
+    // editing is compiled once for all prompt types, like in the sync
+    // editor. The prompt is `Send` and `Sync`, so the future stays
+    // `Send`.
+    async fn readline_inner<'b, 'item, IO>(
+        &'b mut self,
+        prompt: &mut (dyn PromptSource<'item> + Send + Sync + 'item),
+        rprompt: &str,
+        io: &mut IO,
+        preserve_buffer: bool,
+        mask: Option<&Mask<'_>>,
+    ) -> Result<&'b str, NolineError>
+    where
+        IO: embedded_io_async::Read + embedded_io_async::Write,
+    {
+        // Yield between checks, as the link may be updated by another
+        // task
+        while !self.link_state.connected() {
+            yield_now().await;
+        }
+
+        let yield_every = self.settings.yield_every as usize;
+        let write_size = self.settings.write_size();
+        let read_size = self.settings.read_size();
+        let resume = self.line_in_progress;
+        let preserve_buffer = preserve_buffer || resume;
+
+        // A resumed line keeps its count
+        if !resume {
+            self.overflows = 0;
+        }
+
+        let link = &mut self.link_state;
+        let held = &mut self.write_buffer;
+        let overflows = &mut self.overflows;
+        let pending_newline = &mut self.pending_newline;
+        let input = &mut self.input;
+        let mut state = LineState::default();
+
+        // Line continuing from `state`, which is kept while waiting for
+        // input. The history is only borrowed while the line is used,
+        // so editors in other tasks can share it.
+        macro_rules! continue_line {
+            ($history:expr) => {{
+                let mut line = Line::new(
+                    &mut *prompt,
+                    &mut self.buffer,
+                    &mut self.terminal,
+                    &mut *$history,
+                    self.settings,
+                )
+                .with_completer(self.completer.as_ref())
+                .with_validator(self.validator.as_ref())
+                .with_hinter(self.hinter.as_ref())
+                .with_kill_buffer(self.kill_buffer.as_mut())
+                .with_undo_buffer(self.undo_buffer.as_mut())
+                .with_stash_buffer(self.stash_buffer.as_mut())
+                .with_rprompt(rprompt)
+                .with_state(core::mem::take(&mut state));
+
+                if let Some(mask) = mask {
+                    line.set_mask(mask);
+                }
+
+                line
+            }};
+        }
+
+        // Clear all rows of the abandoned line when probing, unless
+        // hidden
+        let resume_at = match self.hidden_at {
+            _ if !resume => None,
+            Some(pos) => Some(pos),
+            None => {
+                let mut history = Self::wait_for_history(&mut self.history).await;
+                let mut line = continue_line!(history);
+                let pos = line.current_position();
+
+                Self::handle_output(
+                    &mut line.move_to_start(),
+                    io,
+                    link,
+                    held.as_mut(),
+                    yield_every,
+                    write_size,
+                )
+                .await
+                .map_err(NolineError::during_probe)?;
+
+                state = line.into_state();
+                Some(pos)
+            }
+        };
+
+        {
+            let mut history = Self::wait_for_history(&mut self.history).await;
+            let mut line = continue_line!(history);
+            let mut reset = if preserve_buffer {
+                line.reset_preserving_buffer()
             } else {
-                break;
+                line.reset()
+            };
+
+            input.hold();
+
+            Self::handle_output(
+                &mut reset.start(),
+                io,
+                link,
+                held.as_mut(),
+                yield_every,
+                write_size,
+            )
+            .await
+            .map_err(NolineError::during_probe)?;
+
+            state = line.into_state();
+        }
+
+        while !state.is_reset_done() {
+            let byte = Self::read_byte(io, link, held.as_mut(), input, 1)
+                .await
+                .map_err(NolineError::during_probe)?;
+
+            let mut history = Self::wait_for_history(&mut self.history).await;
+            let mut line = continue_line!(history);
+            let mut reset = line.resume_reset();
+
+            if let Some(mut output) = reset.advance(byte).map_err(NolineError::ProbeFailed)? {
+                Self::handle_output(
+                    &mut output,
+                    io,
+                    link,
+                    held.as_mut(),
+                    yield_every,
+                    write_size,
+                )
+                .await
+                .map_err(NolineError::during_probe)?;
             }
+
+            state = line.into_state();
+        }
+
+        input.release();
+
+        {
+            let mut history = Self::wait_for_history(&mut self.history).await;
+            let mut line = continue_line!(history);
+
+            Self::handle_output(
+                &mut line.print_rprompt(),
+                io,
+                link,
+                held.as_mut(),
+                yield_every,
+                write_size,
+            )
+            .await?;
+
+            state = line.into_state();
+        }
+
+        self.line_in_progress = true;
+        self.hidden_at = None;
+
+        // Like `?`, ending the line in progress. Unlike an async block
+        // doing the same, it doesn't add the references it would hold
+        // to the future.
+        macro_rules! or_end_line {
+            ($result:expr) => {
+                match $result {
+                    Ok(value) => value,
+                    Err(err) => {
+                        self.line_in_progress = false;
+                        return Err(err);
+                    }
+                }
+            };
+        }
+
+        if preserve_buffer {
+            let mut history = Self::wait_for_history(&mut self.history).await;
+            let mut line = continue_line!(history);
+
+            or_end_line!(
+                Self::handle_output(
+                    &mut line.print_buffer(),
+                    io,
+                    link,
+                    held.as_mut(),
+                    yield_every,
+                    write_size,
+                )
+                .await
+            );
+
+            state = line.into_state();
+        }
+
+        if let Some(pos) = resume_at {
+            let mut history = Self::wait_for_history(&mut self.history).await;
+            let mut line = continue_line!(history);
+
+            or_end_line!(
+                Self::handle_output(
+                    &mut line.restore_cursor(pos),
+                    io,
+                    link,
+                    held.as_mut(),
+                    yield_every,
+                    write_size,
+                )
+                .await
+            );
+
+            state = line.into_state();
         }
 
         loop {
-            let byte = Self::read_byte(io).await?;
+            let byte = match state.take_type_ahead() {
+                Some(byte) => byte,
+                None => {
+                    or_end_line!(Self::read_byte(io, link, held.as_mut(), input, read_size).await)
+                }
+            };
+
+            if pending_newline.take() == Some(byte) {
+                continue;
+            }
+
+            let mut history = Self::wait_for_history(&mut self.history).await;
+            let mut line = continue_line!(history);
+            let mut output = line.advance(byte);
 
-            if Self::handle_output(line.advance(byte), io).await?.is_some() {
+            if output.is_overflow() {
+                *overflows += 1;
+            }
+
+            let done = or_end_line!(
+                Self::handle_output(
+                    &mut output,
+                    io,
+                    link,
+                    held.as_mut(),
+                    yield_every,
+                    write_size,
+                )
+                .await
+            )
+            .is_some();
+
+            state = line.into_state();
+
+            if done {
+                *pending_newline = newline_pair(byte);
                 break;
             }
         }
 
+        self.line_in_progress = false;
+
         Ok(self.buffer.as_str())
     }
 
-    /// Load history from iterator
-    pub fn load_history<'a>(&mut self, entries: impl Iterator<Item = &'a str>) -> usize {
-        self.history.load_entries(entries)
+    // Borrow the history, yielding while another editor sharing it has
+    // it borrowed
+    async fn wait_for_history(history: &mut H) -> H::Borrowed<'_> {
+        while history.is_borrowed() {
+            yield_now().await;
+        }
+
+        history.borrow_history()
     }
 
-    /// Get history as iterator over circular slices
-    pub fn get_history(&self) -> impl Iterator<Item = CircularSlice<'_>> {
-        get_history_entries(&self.history)
+    /// Number of insertions rejected because the line buffer was full,
+    /// counted from the start of the line last read or in progress.
+    /// With [`Echo::Local`], rejected characters are counted too, and
+    /// erased from the display.
+    ///
+    /// [`Echo::Local`]: crate::builder::Echo::Local
+    pub fn overflow_count(&self) -> usize {
+        self.overflows
+    }
+
+    /// Bytes left in the line buffer, or None if it is unbounded
+    pub fn remaining_capacity(&self) -> Option<usize> {
+        self.buffer.remaining_capacity()
+    }
+
+    /// Terminal size as rows and columns, as last probed. Before the
+    /// first probe, the size is assumed to be 24 rows and 80 columns.
+    pub fn terminal_size(&self) -> (usize, usize) {
+        (self.terminal.rows(), self.terminal.columns())
+    }
+
+    /// Cursor position as row and column from the top left corner,
+    /// counted from 0, as last known. The position is found when a
+    /// line starts and followed while editing. Before the first line,
+    /// it is assumed to be (0, 0).
+    pub fn cursor_position(&self) -> (usize, usize) {
+        let cursor = self.terminal.get_cursor();
+
+        (cursor.row, cursor.column)
+    }
+
+    /// Probe the terminal for its size again outside of
+    /// [`Editor::readline`], e.g. before printing output formatted to
+    /// the width of the terminal. The cursor is left where it was, and
+    /// input typed while probing is dropped. Nothing is sent with a
+    /// fixed terminal size.
+    pub async fn refresh_size<IO: embedded_io_async::Read + embedded_io_async::Write>(
+        &mut self,
+        io: &mut IO,
+    ) -> Result<(), NolineError> {
+        let cursor = self.terminal.get_cursor();
+        let yield_every = self.settings.yield_every as usize;
+        let write_size = self.settings.write_size();
+        let input = &mut self.input;
+        let link = &mut self.link_state;
+        let held = &mut self.write_buffer;
+        // Probing doesn't use the history, which may be shared
+        let mut history = NoHistory {};
+        let mut prompt = Prompt::from("");
+        let mut line: Line<'_, B, NoHistory, _> = Line::new(
+            &mut prompt,
+            &mut self.buffer,
+            &mut self.terminal,
+            &mut history,
+            self.settings,
+        );
+        let mut probe = line.probe_size();
+
+        Self::handle_output(
+            &mut probe.start(),
+            io,
+            link,
+            held.as_mut(),
+            yield_every,
+            write_size,
+        )
+        .await
+        .map_err(NolineError::during_probe)?;
+
+        while !probe.is_done() {
+            let byte = Self::read_byte(io, link, held.as_mut(), input, 1)
+                .await
+                .map_err(NolineError::during_probe)?;
+
+            if let Some(mut output) = probe.advance(byte).map_err(NolineError::ProbeFailed)? {
+                Self::handle_output(
+                    &mut output,
+                    io,
+                    link,
+                    held.as_mut(),
+                    yield_every,
+                    write_size,
+                )
+                .await
+                .map_err(NolineError::during_probe)?;
+            }
+        }
+
+        // An abandoned line is cleared using its rows at the old width
+        if !self.line_in_progress {
+            self.terminal.restore_cursor(cursor);
+        }
+
+        Ok(())
+    }
+
+    /// Write output held back because the IO didn't accept it, see
+    /// [`crate::builder::EditorBuilder::with_write_buffer`]. Returns
+    /// true when all output is written. Call it after a line is done
+    /// and before writing other output, so the output of the line comes
+    /// first. `readline` writes held output before reading input,
+    /// yielding until the IO accepts it.
+    pub async fn pump_output<IO: embedded_io_async::Write>(
+        &mut self,
+        io: &mut IO,
+    ) -> Result<bool, NolineError> {
+        match self.write_buffer.as_mut() {
+            Some(held) => Self::write_held(io, held)
+                .await
+                .map_err(|err| Self::check_link(err, &mut self.link_state)),
+            None => Ok(true),
+        }
+    }
+
+    /// Print `text` above a line abandoned by dropping a `readline`
+    /// future, e.g. log output when racing `readline` against a channel
+    /// of messages with `select`, and draw the line again below it with
+    /// the cursor in place. Pass the same prompt as to `readline`. Each
+    /// line of `text` is printed with a newline, and shouldn't contain
+    /// other control characters. Without a line in progress, the text
+    /// is just printed. The next `readline` call continues the line.
+    ///
+    /// The `readline` future borrows the editor, so it has to be
+    /// dropped first. A future dropped while probing the terminal at
+    /// the start of a line may leave the prompt, which isn't erased.
+    /// The right-side prompt of
+    /// [`Editor::readline_with_rprompt`] is drawn again by the next
+    /// `readline` call. While the line is hidden with
+    /// [`Editor::hide`], the text is printed where the cursor is.
+    pub async fn print_above<'item, IO, I>(
+        &mut self,
+        prompt: impl Into<Prompt<I>>,
+        text: &str,
+        io: &mut IO,
+    ) -> Result<(), NolineError>
+    where
+        IO: embedded_io_async::Read + embedded_io_async::Write,
+        I: Iterator<Item = &'item str> + Clone + Send + Sync,
+    {
+        let text = text.strip_suffix('\n').unwrap_or(text);
+        let yield_every = self.settings.yield_every as usize;
+        let write_size = self.settings.write_size();
+        let link = &mut self.link_state;
+        let held = &mut self.write_buffer;
+
+        if !self.line_in_progress || self.hidden_at.is_some() {
+            Self::print_lines(text, io, link, held.as_mut()).await?;
+
+            return Self::flush(io, link).await;
+        }
+
+        let mut history = NoHistory {};
+        let mut prompt: Prompt<I> = prompt.into();
+        let mut line: Line<'_, B, NoHistory, dyn PromptSource<'_> + Send + Sync> = Line::new(
+            &mut prompt,
+            &mut self.buffer,
+            &mut self.terminal,
+            &mut history,
+            self.settings,
+        );
+        let pos = line.current_position();
+
+        Self::handle_output(
+            &mut line.erase(),
+            io,
+            link,
+            held.as_mut(),
+            yield_every,
+            write_size,
+        )
+        .await?;
+        Self::print_lines(text, io, link, held.as_mut()).await?;
+        line.start_below(text);
+        Self::handle_output(
+            &mut line.redraw(),
+            io,
+            link,
+            held.as_mut(),
+            yield_every,
+            write_size,
+        )
+        .await?;
+        Self::handle_output(
+            &mut line.restore_cursor(pos),
+            io,
+            link,
+            held.as_mut(),
+            yield_every,
+            write_size,
+        )
+        .await?;
+
+        Self::flush(io, link).await
+    }
+
+    /// Erase a line abandoned by dropping a `readline` future and leave
+    /// the cursor where the prompt started, e.g. before running a
+    /// full-screen program. The buffer is kept until
+    /// [`Editor::unhide`] or the next `readline` call draws the line
+    /// again. Pass the same prompt as to `readline`. Without a line in
+    /// progress, nothing is done.
+    pub async fn hide<'item, IO, I>(
+        &mut self,
+        prompt: impl Into<Prompt<I>>,
+        io: &mut IO,
+    ) -> Result<(), NolineError>
+    where
+        IO: embedded_io_async::Read + embedded_io_async::Write,
+        I: Iterator<Item = &'item str> + Clone + Send + Sync,
+    {
+        if !self.line_in_progress || self.hidden_at.is_some() {
+            return Ok(());
+        }
+
+        let link = &mut self.link_state;
+        let mut history = NoHistory {};
+        let mut prompt: Prompt<I> = prompt.into();
+        let mut line: Line<'_, B, NoHistory, dyn PromptSource<'_> + Send + Sync> = Line::new(
+            &mut prompt,
+            &mut self.buffer,
+            &mut self.terminal,
+            &mut history,
+            self.settings,
+        );
+        let pos = line.current_position();
+
+        Self::handle_output(
+            &mut line.erase(),
+            io,
+            link,
+            self.write_buffer.as_mut(),
+            self.settings.yield_every as usize,
+            self.settings.write_size(),
+        )
+        .await?;
+        self.hidden_at = Some(pos);
+
+        Self::flush(io, link).await
+    }
+
+    /// Draw a line hidden with [`Editor::hide`] again, with the cursor
+    /// where it was, without waiting for the next `readline` call. The
+    /// terminal is probed first, like at the start of a line, so the
+    /// screen may have been scrolled or cleared while the line was
+    /// hidden. The line is drawn from the start of the row the cursor
+    /// is on. If this fails, the next `readline` call draws the line
+    /// again.
+    pub async fn unhide<'item, IO, I>(
+        &mut self,
+        prompt: impl Into<Prompt<I>>,
+        io: &mut IO,
+    ) -> Result<(), NolineError>
+    where
+        IO: embedded_io_async::Read + embedded_io_async::Write,
+        I: Iterator<Item = &'item str> + Clone + Send + Sync,
+    {
+        let Some(pos) = self.hidden_at else {
+            return Ok(());
+        };
+
+        let yield_every = self.settings.yield_every as usize;
+        let write_size = self.settings.write_size();
+        let input = &mut self.input;
+        let link = &mut self.link_state;
+        let held = &mut self.write_buffer;
+        let mut history = NoHistory {};
+        let mut prompt: Prompt<I> = prompt.into();
+        let mut line: Line<'_, B, NoHistory, dyn PromptSource<'_> + Send + Sync> = Line::new(
+            &mut prompt,
+            &mut self.buffer,
+            &mut self.terminal,
+            &mut history,
+            self.settings,
+        );
+        let mut reset = line.reset_preserving_buffer();
+
+        input.hold();
+
+        Self::handle_output(
+            &mut reset.start(),
+            io,
+            link,
+            held.as_mut(),
+            yield_every,
+            write_size,
+        )
+        .await
+        .map_err(NolineError::during_probe)?;
+
+        while !reset.is_done() {
+            let byte = Self::read_byte(io, link, held.as_mut(), input, 1)
+                .await
+                .map_err(NolineError::during_probe)?;
+
+            if let Some(mut output) = reset.advance(byte).map_err(NolineError::ProbeFailed)? {
+                Self::handle_output(
+                    &mut output,
+                    io,
+                    link,
+                    held.as_mut(),
+                    yield_every,
+                    write_size,
+                )
+                .await
+                .map_err(NolineError::during_probe)?;
+            }
+        }
+
+        input.release();
+
+        Self::handle_output(
+            &mut line.redraw(),
+            io,
+            link,
+            held.as_mut(),
+            yield_every,
+            write_size,
+        )
+        .await?;
+        Self::handle_output(
+            &mut line.restore_cursor(pos),
+            io,
+            link,
+            held.as_mut(),
+            yield_every,
+            write_size,
+        )
+        .await?;
+        self.hidden_at = None;
+
+        Self::flush(io, link).await
+    }
+
+    // Print each line of `text` with a newline
+    async fn print_lines<IO: embedded_io_async::Write>(
+        text: &str,
+        io: &mut IO,
+        link: &mut L,
+        mut held: Option<&mut WriteBuffer<B>>,
+    ) -> Result<(), NolineError> {
+        for line in text.split('\n') {
+            Self::write_all(io, line.as_bytes(), held.as_deref_mut())
+                .await
+                .map_err(|err| Self::check_link(err, link))?;
+            Self::write_all(io, b"\r\n", held.as_deref_mut())
+                .await
+                .map_err(|err| Self::check_link(err, link))?;
+        }
+
+        Ok(())
+    }
+
+    /// Set terminal size, e.g. when the terminal emulator window has
+    /// been resized. Like for [`Editor::print_above`], the `readline`
+    /// future has to be dropped first. A line abandoned that way is
+    /// drawn again with the new size by the next `readline` call,
+    /// assuming it still starts on the same row. The size is also
+    /// probed at the start of each line, so this is only needed
+    /// mid-line, or with
+    /// [`crate::builder::EditorBuilder::with_fixed_terminal_size`],
+    /// where it replaces the fixed size.
+    pub fn resize(&mut self, rows: usize, columns: usize) {
+        self.terminal.resize(rows, columns);
+        self.settings.resize(rows, columns);
+    }
+
+    /// Content of the line last read, also if `readline` returned an
+    /// error such as [`NolineError::Aborted`]. The content is kept
+    /// until the next `readline` call begins, or is dropped by
+    /// [`Editor::readline_into`] once it is copied.
+    pub fn last_line(&self) -> &str {
+        self.buffer.as_str()
+    }
+
+    /// Load history from iterator
+    pub fn load_history<'a>(&mut self, entries: impl Iterator<Item = &'a str>) -> usize {
+        self.history.borrow_history().load_entries(entries)
+    }
+
+    /// Remove all history entries
+    pub fn clear_history(&mut self) {
+        self.history.borrow_history().clear();
+    }
+
+    /// Add history entries read from `io` until end of input, like
+    /// [`crate::history::deserialize`]. Entries are decoded in the line
+    /// buffer, so entries longer than the buffer are truncated, and the
+    /// last line is cleared. Returns the number of entries added.
+    pub async fn restore_history<IO: embedded_io_async::Read>(
+        &mut self,
+        io: &mut IO,
+    ) -> Result<usize, NolineError> {
+        // The line buffer no longer holds the abandoned line
+        self.line_in_progress = false;
+
+        let mut loader = HistoryLoader::new(&mut self.buffer);
+        let mut chunk = [0; 32];
+
+        // Shared history is borrowed between reads only
+        loop {
+            match io.read(&mut chunk).await.map_err(NolineError::read_error)? {
+                0 => break,
+                len => {
+                    let mut history = Self::wait_for_history(&mut self.history).await;
+
+                    chunk[..len]
+                        .iter()
+                        .for_each(|&byte| loader.advance(byte, &mut *history));
+                }
+            }
+        }
+
+        let mut history = Self::wait_for_history(&mut self.history).await;
+
+        Ok(loader.finish(&mut *history))
+    }
+}
+
+// History owned by the editor. Shared history is accessed through its
+// `RefCell` instead.
+impl<B, H, C, L, V, T> Editor<B, H, C, L, V, T>
+where
+    B: Buffer,
+    H: History,
+    C: Completer,
+    L: LinkState,
+    V: Validator,
+    T: Hinter,
+{
+    /// Write history to `io`, in the format of
+    /// [`crate::history::serialize`]
+    pub async fn save_history<IO: embedded_io_async::Write>(
+        &self,
+        io: &mut IO,
+    ) -> Result<(), NolineError> {
+        for slice in serialized_slices(&self.history) {
+            io.write_all(slice)
+                .await
+                .map_err(NolineError::write_error)?;
+        }
+
+        Ok(())
+    }
+
+    /// Get history as iterator over circular slices
+    pub fn get_history(&self) -> impl Iterator<Item = CircularSlice<'_>> {
+        self.history.iter_entries()
+    }
+}
+
+#[cfg(any(test, feature = "alloc", feature = "std"))]
+mod alloc {
+    extern crate alloc;
+
+    use self::alloc::string::String;
+    use super::*;
+
+    impl<B, H, C, L, V, T> Editor<B, H, C, L, V, T>
+    where
+        B: Buffer,
+        H: BorrowHistory,
+        C: Completer,
+        L: LinkState,
+        V: Validator,
+        T: Hinter,
+    {
+        /// Read line like [`Editor::readline`], returning it as an owned
+        /// string so the editor isn't borrowed by the line
+        pub async fn readline_owned<'item, IO, I>(
+            &mut self,
+            prompt: impl Into<Prompt<I>>,
+            io: &mut IO,
+        ) -> Result<String, NolineError>
+        where
+            IO: embedded_io_async::Read + embedded_io_async::Write,
+            I: Iterator<Item = &'item str> + Clone + Send + Sync,
+        {
+            self.readline(prompt, io).await.map(Into::into)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+    use std::string::String;
+
+    use core::cell::{Cell, RefCell};
+    use core::pin::pin;
+    use core::task::Waker;
+
+    use super::*;
+    use crate::builder::EditorBuilder;
+    use crate::error::ProbeFailure;
+    use crate::history::{SharedHistory, UnboundedHistory};
+    use crate::line_buffer::SliceBuffer;
+    use crate::output::OutputAction;
+    use crate::terminal::Cursor;
+    use crate::testlib::MockTerminal;
+
+    // IO writing straight to the mock terminal. Terminal replies are
+    // read before typed input, and reading is pending when there is
+    // neither, until the link is lost. At most `max_write` bytes are
+    // written per call.
+    struct MockIO {
+        terminal: MockTerminal,
+        replies: VecDeque<u8>,
+        connected: Rc<Cell<bool>>,
+        max_write: usize,
+        typed: VecDeque<u8>,
+    }
+
+    impl embedded_io_async::ErrorType for MockIO {
+        type Error = embedded_io_async::ErrorKind;
+    }
+
+    impl embedded_io_async::Read for MockIO {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            while self.replies.is_empty() && self.typed.is_empty() {
+                if !self.connected.get() {
+                    return Err(embedded_io_async::ErrorKind::NotConnected);
+                }
+
+                yield_now().await;
+            }
+
+            let mut len = 0;
+
+            for place in buf.iter_mut() {
+                match self.replies.pop_front().or_else(|| self.typed.pop_front()) {
+                    Some(byte) => *place = byte,
+                    None => break,
+                }
+
+                len += 1;
+            }
+
+            Ok(len)
+        }
+    }
+
+    impl embedded_io_async::Write for MockIO {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            assert!(!buf.is_empty(), "empty write");
+
+            let len = buf.len().min(self.max_write);
+
+            for &byte in &buf[..len] {
+                if let Some(reply) = self.terminal.advance(byte) {
+                    self.replies.extend(reply);
+                }
+            }
+
+            Ok(len)
+        }
+    }
+
+    // Poll future to completion, returning output and number of
+    // times it was pending
+    fn block_on<F: Future>(future: F) -> (F::Output, usize) {
+        let mut future = pin!(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut pending = 0;
+
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return (output, pending),
+                Poll::Pending => pending += 1,
+            }
+        }
+    }
+
+    // Read a long line and insert at the start, redrawing all of it.
+    // Returns line, screen and number of yields.
+    fn redraw_long_line(yield_every: usize) -> (String, String, usize) {
+        let mut io = MockIO {
+            terminal: MockTerminal::new(10, 40, Cursor::new(0, 0)),
+            replies: VecDeque::new(),
+            connected: Rc::new(Cell::new(true)),
+            max_write: usize::MAX,
+            typed: "0123456789"
+                .repeat(20)
+                .bytes()
+                .chain([0x1, b'x', b'\r'])
+                .collect(),
+        };
+
+        let ((line, screen), yields) = block_on(async {
+            let mut editor = EditorBuilder::new_unbounded()
+                .with_yield_every(yield_every)
+                .build_async(&mut io)
+                .await
+                .unwrap();
+
+            let line = editor.readline("> ", &mut io).await.unwrap().to_string();
+
+            (line, io.terminal.screen_as_string())
+        });
+
+        (line, screen, yields)
+    }
+
+    #[test]
+    fn yield_every() {
+        let (line, screen, yields) = redraw_long_line(0);
+        assert_eq!(yields, 0);
+        assert_eq!(line, ["x", &"0123456789".repeat(20)].concat());
+
+        let (yielding_line, yielding_screen, yields) = redraw_long_line(16);
+        // Redrawing the line yields at least once per row
+        assert!(yields >= 200 / 40, "{yields} yields");
+        assert_eq!(yielding_line, line);
+        assert_eq!(yielding_screen, screen);
+    }
+
+    #[test]
+    fn partial_writes() {
+        let mut io = MockIO {
+            terminal: MockTerminal::new(4, 10, Cursor::new(0, 0)),
+            replies: VecDeque::new(),
+            connected: Rc::new(Cell::new(true)),
+            max_write: 3,
+            typed: "hello world\x01x\r".bytes().collect(),
+        };
+
+        let (line, _) = block_on(async {
+            let mut editor = EditorBuilder::new_unbounded()
+                .build_async(&mut io)
+                .await
+                .unwrap();
+
+            editor.readline("> ", &mut io).await.unwrap().to_string()
+        });
+
+        assert_eq!(line, "xhello world");
+        assert_eq!(io.terminal.screen_as_string(), "> xhello w\norld");
+
+        io.max_write = 0;
+        io.typed.extend("a".bytes());
+
+        let (editor, _) = block_on(EditorBuilder::new_unbounded().build_async(&mut io));
+        let mut editor = editor.unwrap();
+        let (line, _) = block_on(editor.readline("> ", &mut io));
+        assert!(matches!(
+            line,
+            Err(NolineError::ProbeFailed(ProbeFailure::IoError(
+                ErrorKind::WriteZero
+            )))
+        ));
+    }
+
+    // IO accepting a byte per write, and nothing every third write
+    struct BusyIO {
+        io: MockIO,
+        writes: usize,
+    }
+
+    impl embedded_io_async::ErrorType for BusyIO {
+        type Error = embedded_io_async::ErrorKind;
+    }
+
+    impl embedded_io_async::Read for BusyIO {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            self.io.read(buf).await
+        }
+    }
+
+    impl embedded_io_async::Write for BusyIO {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.writes += 1;
+
+            if self.writes.is_multiple_of(3) {
+                return Ok(0);
+            }
+
+            self.io.write(&buf[..1]).await
+        }
+    }
+
+    #[test]
+    fn held_output() {
+        let mut busy = BusyIO {
+            io: MockIO {
+                terminal: MockTerminal::new(4, 10, Cursor::new(0, 0)),
+                replies: VecDeque::new(),
+                connected: Rc::new(Cell::new(true)),
+                max_write: usize::MAX,
+                typed: "hello world\x01x\r".bytes().collect(),
+            },
+            writes: 0,
+        };
+
+        let (line, _) = block_on(async {
+            let mut editor = EditorBuilder::new_unbounded()
+                .with_unbounded_write_buffer()
+                .build_async(&mut busy)
+                .await
+                .unwrap();
+
+            let line = editor.readline("> ", &mut busy).await.unwrap().to_string();
+
+            // Output of the last key may still be held
+            while !editor.pump_output(&mut busy).await.unwrap() {}
+
+            line
+        });
+
+        // Output is written in order once the IO takes it
+        assert_eq!(line, "xhello world");
+        assert_eq!(busy.io.terminal.screen_as_string(), "> xhello w\norld");
+
+        // Without a write buffer, writing fails
+        busy.io.typed.extend("a".bytes());
+
+        let (editor, _) = block_on(EditorBuilder::new_unbounded().build_async(&mut busy));
+        let mut editor = editor.unwrap();
+        let (line, _) = block_on(editor.readline("> ", &mut busy));
+        assert!(matches!(
+            line,
+            Err(NolineError::ProbeFailed(ProbeFailure::IoError(
+                ErrorKind::WriteZero
+            )))
+        ));
+    }
+
+    #[test]
+    fn write_size() {
+        let read_line = |write_size| {
+            let mut io = MockIO {
+                terminal: MockTerminal::new(4, 10, Cursor::new(0, 0)),
+                replies: VecDeque::new(),
+                connected: Rc::new(Cell::new(true)),
+                max_write: 3,
+                typed: "hello world\x01x\r".bytes().collect(),
+            };
+
+            let (line, _) = block_on(async {
+                let mut editor = EditorBuilder::new_unbounded()
+                    .with_write_size(write_size)
+                    .build_async(&mut io)
+                    .await
+                    .unwrap();
+
+                editor.readline("> ", &mut io).await.unwrap().to_string()
+            });
+
+            (line, io.terminal.screen_as_string())
+        };
+
+        let expected = ("xhello world".to_string(), "> xhello w\norld".to_string());
+
+        // Items are split across chunks, and chunks across writes
+        for write_size in [0, 1, 2, 5, 64, 1000] {
+            assert_eq!(read_line(write_size), expected, "write size {write_size}");
+        }
+    }
+
+    #[test]
+    fn future_size() {
+        fn readline_future_size(buffer: &mut [u8]) -> usize {
+            let mut io = MockIO {
+                terminal: MockTerminal::new(4, 10, Cursor::new(0, 0)),
+                replies: VecDeque::new(),
+                connected: Rc::new(Cell::new(true)),
+                max_write: usize::MAX,
+                typed: VecDeque::new(),
+            };
+
+            let (editor, _) = block_on(EditorBuilder::from_slice(buffer).build_async(&mut io));
+            let mut editor = editor.unwrap();
+
+            let future = editor.readline("> ", &mut io);
+
+            core::mem::size_of_val(&future)
+        }
+
+        // The line buffer is borrowed, so its size doesn't add to the
+        // future. It was 1512 bytes while the output iterator was kept
+        // across writes. Keeping the line state apart from the line
+        // while waiting for input, so the history can be shared, took
+        // it from 1344 to 1536 bytes. Holding the prompt outside of the
+        // line as `dyn PromptSource` added 32 bytes, to 1568 bytes, in
+        // exchange for compiling the line once for all prompt types.
+        let size = readline_future_size(&mut [0; 64]);
+        assert_eq!(readline_future_size(&mut [0; 1024]), size);
+        assert!(size <= 196 * core::mem::size_of::<usize>(), "{size} bytes");
+    }
+
+    #[test]
+    fn output_future_size() {
+        let mut io = MockIO {
+            terminal: MockTerminal::new(4, 10, Cursor::new(0, 0)),
+            replies: VecDeque::new(),
+            connected: Rc::new(Cell::new(true)),
+            max_write: usize::MAX,
+            typed: VecDeque::new(),
+        };
+        let mut slice = [0; 16];
+        let buffer = LineBuffer::from_slice(&mut slice);
+        let mut link = AlwaysConnected;
+        let mut terminal = Terminal::default();
+        let prompt = Prompt::from("> ");
+        let mut output = Output::<_, _, NoCompleter>::new(
+            &prompt,
+            &buffer,
+            &mut terminal,
+            OutputAction::PrintBufferEnd,
+        );
+        let future = Editor::<SliceBuffer, NoHistory>::handle_output(
+            &mut output,
+            &mut io,
+            &mut link,
+            None,
+            0,
+            WRITE_CHUNK_CAPACITY,
+        );
+
+        // Only the chunk, its position and the terminal at the start of
+        // the output are kept while a chunk is written. Keeping the
+        // output iterator and the output itself made it 616 bytes. The
+        // write buffer adds 32 bytes.
+        let size = core::mem::size_of_val(&future);
+        assert!(size <= 54 * core::mem::size_of::<usize>(), "{size} bytes");
+    }
+
+    #[test]
+    fn read_error() {
+        let mut io = MockIO {
+            terminal: MockTerminal::new(4, 20, Cursor::new(0, 0)),
+            replies: VecDeque::new(),
+            connected: Rc::new(Cell::new(false)),
+            max_write: usize::MAX,
+            typed: "abc".bytes().collect(),
+        };
+
+        let (editor, _) = block_on(EditorBuilder::new_unbounded().build_async(&mut io));
+        let mut editor = editor.unwrap();
+        let (line, _) = block_on(editor.readline("> ", &mut io));
+
+        // Without link state, the failed read is reported as is
+        assert!(matches!(
+            line,
+            Err(NolineError::ReadError(ErrorKind::NotConnected))
+        ));
+    }
+
+    #[test]
+    fn validator() {
+        let mut io = MockIO {
+            terminal: MockTerminal::new(4, 20, Cursor::new(0, 0)),
+            replies: VecDeque::new(),
+            connected: Rc::new(Cell::new(true)),
+            max_write: usize::MAX,
+            typed: "foo\r\x01cmd \r".bytes().collect(),
+        };
+
+        let (line, _) = block_on(async {
+            let mut editor = EditorBuilder::new_unbounded()
+                .with_validator(|line: &str| line.starts_with("cmd"))
+                .build_async(&mut io)
+                .await
+                .unwrap();
+
+            editor.readline("> ", &mut io).await.unwrap().to_string()
+        });
+
+        assert_eq!(line, "cmd foo");
+        assert_eq!(io.terminal.screen_as_string(), "> cmd foo");
+    }
+
+    #[test]
+    fn default_prompt() {
+        let mut io = MockIO {
+            terminal: MockTerminal::new(4, 20, Cursor::new(0, 0)),
+            replies: VecDeque::new(),
+            connected: Rc::new(Cell::new(true)),
+            max_write: usize::MAX,
+            typed: "ab\rcd\ref\r".bytes().collect(),
+        };
+
+        block_on(async {
+            let mut editor = EditorBuilder::new_unbounded()
+                .with_prompt("$ ")
+                .build_async(&mut io)
+                .await
+                .unwrap();
+
+            assert_eq!(editor.readline_default(&mut io).await.unwrap(), "ab");
+            assert_eq!(editor.readline("# ", &mut io).await.unwrap(), "cd");
+            assert_eq!(editor.readline_default(&mut io).await.unwrap(), "ef");
+        });
+
+        assert_eq!(io.terminal.screen_as_string(), "$ ab\n# cd\n$ ef");
+    }
+
+    #[test]
+    fn new_probed() {
+        let mut io = MockIO {
+            terminal: MockTerminal::new(10, 30, Cursor::new(2, 5)),
+            replies: VecDeque::new(),
+            connected: Rc::new(Cell::new(true)),
+            max_write: usize::MAX,
+            typed: VecDeque::new(),
+        };
+
+        let (editor, _) = block_on(Editor::new_probed(
+            LineBuffer::new_unbounded(),
+            NoHistory {},
+            &mut io,
+        ));
+        let mut editor = editor.unwrap();
+
+        assert_eq!(editor.terminal_size(), (10, 30));
+        assert_eq!(io.terminal.get_cursor(), Cursor::new(2, 5));
+        assert_eq!(io.terminal.screen_as_string(), "");
+
+        io.typed.extend(b"ab\r");
+        let (line, _) = block_on(editor.readline("> ", &mut io));
+        assert_eq!(line.unwrap(), "ab");
+    }
+
+    #[test]
+    fn size_and_cursor_position() {
+        let mut io = MockIO {
+            terminal: MockTerminal::new(10, 30, Cursor::new(2, 5)),
+            replies: VecDeque::new(),
+            connected: Rc::new(Cell::new(true)),
+            max_write: usize::MAX,
+            typed: VecDeque::new(),
+        };
+
+        let (editor, _) = block_on(Editor::new(
+            LineBuffer::new_unbounded(),
+            NoHistory {},
+            &mut io,
+        ));
+        let mut editor = editor.unwrap();
+        assert_eq!(editor.terminal_size(), (24, 80));
+        assert_eq!(editor.cursor_position(), (0, 0));
+
+        io.typed.extend(b"ab\r");
+        let (line, _) = block_on(editor.readline("> ", &mut io));
+        assert_eq!(line.unwrap(), "ab");
+        assert_eq!(editor.terminal_size(), (10, 30));
+        assert_eq!(editor.cursor_position(), (3, 0));
+
+        io.terminal.resize(12, 40);
+        let (result, _) = block_on(editor.refresh_size(&mut io));
+        result.unwrap();
+        assert_eq!(editor.terminal_size(), (12, 40));
+        assert_eq!(editor.cursor_position(), (3, 0));
+        assert_eq!(io.terminal.get_cursor(), Cursor::new(3, 0));
+    }
+
+    #[test]
+    fn initial_line() {
+        let mut io = MockIO {
+            terminal: MockTerminal::new(4, 10, Cursor::new(0, 0)),
+            replies: VecDeque::new(),
+            connected: Rc::new(Cell::new(true)),
+            max_write: usize::MAX,
+            typed: "\x7f\x7f20\r".bytes().collect(),
+        };
+
+        let (editor, _) = block_on(EditorBuilder::new_unbounded().build_async(&mut io));
+        let mut editor = editor.unwrap();
+
+        let (line, _) = block_on(editor.readline_with_initial("> ", "192.168.1.10", &mut io));
+        assert_eq!(line.unwrap(), "192.168.1.20");
+        assert_eq!(io.terminal.screen_as_string(), "> 192.168.\n1.20");
+    }
+
+    #[test]
+    fn cancelled_readline() {
+        let mut io = MockIO {
+            terminal: MockTerminal::new(4, 10, Cursor::new(0, 0)),
+            replies: VecDeque::new(),
+            connected: Rc::new(Cell::new(true)),
+            max_write: usize::MAX,
+            typed: "hello wor".bytes().collect(),
+        };
+
+        let (editor, _) = block_on(EditorBuilder::new_unbounded().build_async(&mut io));
+        let mut editor = editor.unwrap();
+
+        // Drop future when it's waiting for more input, like when
+        // losing a race against a timeout
+        {
+            let mut future = pin!(editor.readline("> ", &mut io));
+            let mut cx = Context::from_waker(Waker::noop());
+
+            for _ in 0..10 {
+                assert!(future.as_mut().poll(&mut cx).is_pending());
+            }
+        }
+
+        assert_eq!(io.terminal.screen_as_string(), "> hello wo\nr");
+
+        io.typed.extend("ld\r".bytes());
+
+        let (line, _) = block_on(editor.readline("> ", &mut io));
+        assert_eq!(line.unwrap(), "hello world");
+        assert_eq!(io.terminal.screen_as_string(), "> hello wo\nrld");
+
+        // Next line starts empty
+        io.typed.extend("!\r".bytes());
+
+        let (line, _) = block_on(editor.readline("> ", &mut io));
+        assert_eq!(line.unwrap(), "!");
+    }
+
+    // IO writing at most `budget` bytes, after which writes are
+    // pending until the future is dropped
+    struct StallingIO {
+        io: MockIO,
+        budget: usize,
+    }
+
+    impl embedded_io_async::ErrorType for StallingIO {
+        type Error = embedded_io_async::ErrorKind;
+    }
+
+    impl embedded_io_async::Read for StallingIO {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            self.io.read(buf).await
+        }
+    }
+
+    impl embedded_io_async::Write for StallingIO {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            if self.budget == 0 {
+                core::future::pending::<()>().await;
+            }
+
+            let len = buf.len().min(self.budget);
+            self.budget -= len;
+
+            self.io.write(&buf[..len]).await
+        }
+    }
+
+    #[test]
+    fn cancelled_write() {
+        let mut io = StallingIO {
+            io: MockIO {
+                terminal: MockTerminal::new(4, 20, Cursor::new(0, 0)),
+                replies: VecDeque::new(),
+                connected: Rc::new(Cell::new(true)),
+                max_write: usize::MAX,
+                typed: "hello world".bytes().collect(),
+            },
+            budget: usize::MAX,
+        };
+
+        let (editor, _) = block_on(EditorBuilder::new_unbounded().build_async(&mut io));
+        let mut editor = editor.unwrap();
+
+        let mut cx = Context::from_waker(Waker::noop());
+
+        {
+            let mut future = pin!(editor.readline("> ", &mut io));
+
+            for _ in 0..10 {
+                assert!(future.as_mut().poll(&mut cx).is_pending());
+            }
+        }
+
+        // Drop future in the middle of writing the line with a char
+        // inserted at the start
+        io.budget = 3;
+        io.io.typed.extend("\x01X".bytes());
+
+        {
+            let mut future = pin!(editor.readline("> ", &mut io));
+
+            for _ in 0..10 {
+                assert!(future.as_mut().poll(&mut cx).is_pending());
+            }
+        }
+
+        assert_eq!(io.budget, 0);
+        assert_ne!(io.io.terminal.screen_as_string(), "> Xhello world");
+
+        // The line is drawn again whole
+        io.budget = usize::MAX;
+        io.io.typed.extend("!\r".bytes());
+
+        let (line, _) = block_on(editor.readline("> ", &mut io));
+        assert_eq!(line.unwrap(), "X!hello world");
+        assert_eq!(io.io.terminal.screen_as_string(), "> X!hello world");
+    }
+
+    #[test]
+    fn print_above() {
+        let mut io = MockIO {
+            terminal: MockTerminal::new(8, 10, Cursor::new(0, 0)),
+            replies: VecDeque::new(),
+            connected: Rc::new(Cell::new(true)),
+            max_write: usize::MAX,
+            typed: VecDeque::new(),
+        };
+
+        let (editor, _) = block_on(EditorBuilder::new_unbounded().build_async(&mut io));
+        let mut editor = editor.unwrap();
+
+        // Without a line, the text is just printed
+        let (result, _) = block_on(editor.print_above("> ", "boot\n", &mut io));
+        result.unwrap();
+        assert_eq!(io.terminal.screen_as_string(), "boot");
+        assert_eq!(io.terminal.get_cursor(), Cursor::new(1, 0));
+
+        // Message arrives while the line is being edited, with the
+        // cursor moved back
+        io.typed.extend("hello wor\x02\x02".bytes());
+
+        {
+            let mut future = pin!(editor.readline("> ", &mut io));
+            let mut cx = Context::from_waker(Waker::noop());
+
+            for _ in 0..10 {
+                assert!(future.as_mut().poll(&mut cx).is_pending());
+            }
+        }
+
+        assert_eq!(io.terminal.screen_as_string(), "boot\n> hello wo\nr");
+        assert_eq!(io.terminal.get_cursor(), Cursor::new(1, 9));
+
+        // The line is drawn again below right away, with the cursor in
+        // place
+        let (result, _) = block_on(editor.print_above("> ", "link up\nlink down", &mut io));
+        result.unwrap();
+        assert_eq!(
+            io.terminal.screen_as_string(),
+            "boot\nlink up\nlink down\n> hello wo\nr"
+        );
+        assert_eq!(io.terminal.get_cursor(), Cursor::new(3, 9));
+
+        let (result, _) = block_on(editor.print_above("> ", "link up", &mut io));
+        result.unwrap();
+        assert_eq!(io.terminal.get_cursor(), Cursor::new(4, 9));
+
+        // The next line continues where it was
+        io.typed.extend("X\r".bytes());
+
+        let (line, _) = block_on(editor.readline("> ", &mut io));
+        assert_eq!(line.unwrap(), "hello wXor");
+        assert_eq!(
+            io.terminal.screen_as_string(),
+            "boot\nlink up\nlink down\nlink up\n> hello wX\nor"
+        );
+    }
+
+    #[test]
+    fn print_above_wrapped() {
+        let mut io = MockIO {
+            terminal: MockTerminal::new(6, 10, Cursor::new(3, 0)),
+            replies: VecDeque::new(),
+            connected: Rc::new(Cell::new(true)),
+            max_write: usize::MAX,
+            typed: "abcdefghijklmnopqrstuvwx\x1b[D\x1b[D\x1b[D"
+                .bytes()
+                .collect(),
+        };
+
+        let (editor, _) = block_on(EditorBuilder::new_unbounded().build_async(&mut io));
+        let mut editor = editor.unwrap();
+
+        // Line wrapped on the three last rows, with the cursor on the
+        // last
+        {
+            let mut future = pin!(editor.readline("> ", &mut io));
+            let mut cx = Context::from_waker(Waker::noop());
+
+            for _ in 0..10 {
+                assert!(future.as_mut().poll(&mut cx).is_pending());
+            }
+        }
+
+        assert_eq!(io.terminal.get_cursor(), Cursor::new(5, 3));
+
+        // Printed where the line started, scrolling up to fit the line
+        // below
+        let (result, _) = block_on(editor.print_above("> ", "log 1\n", &mut io));
+        result.unwrap();
+        assert_eq!(io.terminal.row_as_string(2), "log 1");
+        assert_eq!(io.terminal.row_as_string(3), "> abcdefgh");
+        assert_eq!(io.terminal.row_as_string(4), "ijklmnopqr");
+        assert_eq!(io.terminal.row_as_string(5), "stuvwx");
+        assert_eq!(io.terminal.get_cursor(), Cursor::new(5, 3));
+
+        let (result, _) = block_on(editor.print_above("> ", "log 2\nlog 3", &mut io));
+        result.unwrap();
+        assert_eq!(
+            io.terminal.screen_as_string(),
+            "log 1\nlog 2\nlog 3\n> abcdefgh\nijklmnopqr\nstuvwx"
+        );
+        assert_eq!(io.terminal.get_cursor(), Cursor::new(5, 3));
+
+        // Home and End land on the line as drawn
+        io.typed.extend("\x01X\x05Y\r".bytes());
+
+        let (line, _) = block_on(editor.readline("> ", &mut io));
+        assert_eq!(line.unwrap(), "XabcdefghijklmnopqrstuvwxY");
+        // Scrolled by the newline ending the line
+        assert_eq!(
+            io.terminal.screen_as_string(),
+            "log 2\nlog 3\n> Xabcdefg\nhijklmnopq\nrstuvwxY"
+        );
+    }
+
+    #[test]
+    fn hide_and_unhide() {
+        let mut io = MockIO {
+            terminal: MockTerminal::new(6, 20, Cursor::new(1, 0)),
+            replies: VecDeque::new(),
+            connected: Rc::new(Cell::new(true)),
+            max_write: usize::MAX,
+            typed: "hello world\x1b[D\x1b[D\x1b[D\x1b[D\x1b[D"
+                .bytes()
+                .collect(),
+        };
+
+        let (editor, _) = block_on(EditorBuilder::new_unbounded().build_async(&mut io));
+        let mut editor = editor.unwrap();
+
+        {
+            let mut future = pin!(editor.readline("> ", &mut io));
+            let mut cx = Context::from_waker(Waker::noop());
+
+            for _ in 0..10 {
+                assert!(future.as_mut().poll(&mut cx).is_pending());
+            }
+        }
+
+        let (result, _) = block_on(editor.hide("> ", &mut io));
+        result.unwrap();
+        assert_eq!(io.terminal.screen_as_string(), "");
+        assert_eq!(io.terminal.get_cursor(), Cursor::new(1, 0));
+
+        // Full-screen program clearing the screen and moving the cursor
+        for &b in b"\x1b[1;1H\x1b[Jtop\r\nload 0.5\x1b[4;9Hxx" {
+            io.terminal.advance(b);
+        }
+
+        let (result, _) = block_on(editor.unhide("> ", &mut io));
+        result.unwrap();
+        assert_eq!(
+            io.terminal.screen_as_string(),
+            "top\nload 0.5\n> hello world"
+        );
+        assert_eq!(io.terminal.get_cursor(), Cursor::new(3, 8));
+
+        io.typed.extend("X\r".bytes());
+
+        let (line, _) = block_on(editor.readline("> ", &mut io));
+        assert_eq!(line.unwrap(), "hello Xworld");
+
+        // The next line draws a hidden line again too
+        io.typed.extend("ab".bytes());
+
+        {
+            let mut future = pin!(editor.readline("> ", &mut io));
+            let mut cx = Context::from_waker(Waker::noop());
+
+            for _ in 0..10 {
+                assert!(future.as_mut().poll(&mut cx).is_pending());
+            }
+        }
+
+        let (result, _) = block_on(editor.hide("> ", &mut io));
+        result.unwrap();
+        assert_eq!(io.terminal.row_as_string(4), "");
+
+        // Printed where the cursor is while hidden
+        let (result, _) = block_on(editor.print_above("> ", "log", &mut io));
+        result.unwrap();
+        assert_eq!(io.terminal.row_as_string(4), "log");
+
+        io.typed.extend("c\r".bytes());
+
+        let (line, _) = block_on(editor.readline("> ", &mut io));
+        assert_eq!(line.unwrap(), "abc");
+        // Scrolled by the newline ending the line
+        assert_eq!(
+            io.terminal.screen_as_string(),
+            "load 0.5\n> hello Xworld\nlog\n> abc"
+        );
+
+        // Nothing to hide without a line in progress
+        let (result, _) = block_on(editor.hide("> ", &mut io));
+        result.unwrap();
+        let (result, _) = block_on(editor.unhide("> ", &mut io));
+        result.unwrap();
+    }
+
+    #[test]
+    fn resize() {
+        let mut io = MockIO {
+            terminal: MockTerminal::new(4, 10, Cursor::new(0, 0)),
+            replies: VecDeque::new(),
+            connected: Rc::new(Cell::new(true)),
+            max_write: usize::MAX,
+            typed: "hello wor\x02".bytes().collect(),
+        };
+
+        let (editor, _) = block_on(
+            EditorBuilder::new_unbounded()
+                .with_fixed_terminal_size(4, 10)
+                .build_async(&mut io),
+        );
+        let mut editor = editor.unwrap();
+
+        {
+            let mut future = pin!(editor.readline("> ", &mut io));
+            let mut cx = Context::from_waker(Waker::noop());
+
+            for _ in 0..10 {
+                assert!(future.as_mut().poll(&mut cx).is_pending());
+            }
+        }
+
+        assert_eq!(io.terminal.screen_as_string(), "> hello wo\nr");
+        assert_eq!(io.terminal.get_cursor(), Cursor::new(1, 0));
+
+        // The terminal is widened, reflowing the line
+        io.terminal = MockTerminal::new(4, 20, Cursor::new(0, 0));
+        "> hello wor\x1b[D".bytes().for_each(|byte| {
+            io.terminal.advance(byte);
+        });
+
+        editor.resize(4, 20);
+        assert_eq!(editor.terminal_size(), (4, 20));
+        assert_eq!(editor.cursor_position(), (0, 10));
+
+        // The line is drawn again at the new width, also for the next
+        // line
+        io.typed.extend("l\rabcdefghijkl\r".bytes());
+
+        let (line, _) = block_on(editor.readline("> ", &mut io));
+        assert_eq!(line.unwrap(), "hello wolr");
+        assert_eq!(io.terminal.screen_as_string(), "> hello wolr");
+
+        let (line, _) = block_on(editor.readline("> ", &mut io));
+        assert_eq!(line.unwrap(), "abcdefghijkl");
+        assert_eq!(
+            io.terminal.screen_as_string(),
+            "> hello wolr\n> abcdefghijkl"
+        );
+    }
+
+    #[test]
+    fn readline_into() {
+        let mut io = MockIO {
+            terminal: MockTerminal::new(8, 20, Cursor::new(0, 0)),
+            replies: VecDeque::new(),
+            connected: Rc::new(Cell::new(true)),
+            max_write: usize::MAX,
+            typed: "rm *\ryes\rabc\rls\r".bytes().collect(),
+        };
+
+        let (editor, _) = block_on(EditorBuilder::new_unbounded().build_async(&mut io));
+        let mut editor = editor.unwrap();
+
+        let mut command = [0; 16];
+        let (len, _) = block_on(editor.readline_into("> ", &mut io, &mut command));
+        let command = core::str::from_utf8(&command[..len.unwrap()]).unwrap();
+
+        // The editor is used again while the command is held
+        let mut answer = [0; 16];
+        let (len, _) = block_on(editor.readline_into("sure? ", &mut io, &mut answer));
+
+        assert_eq!(command, "rm *");
+        assert_eq!(&answer[..len.unwrap()], b"yes");
+        assert_eq!(editor.last_line(), "");
+
+        let (result, _) = block_on(editor.readline_into("> ", &mut io, &mut [0; 2]));
+        assert!(matches!(result, Err(NolineError::OutputTooSmall(3))));
+        assert_eq!(editor.last_line(), "abc");
+
+        let (line, _) = block_on(editor.readline_owned("> ", &mut io));
+        let line = line.unwrap();
+
+        assert_eq!(line, "ls");
+        assert_eq!(
+            io.terminal.screen_as_string(),
+            "> rm *\nsure? yes\n> abc\n> ls"
+        );
+    }
+
+    #[test]
+    fn prompt_types() {
+        // IO without shared state, so futures using it can be `Send`
+        struct TypedIO(VecDeque<u8>);
+
+        impl embedded_io_async::ErrorType for TypedIO {
+            type Error = embedded_io_async::ErrorKind;
+        }
+
+        impl embedded_io_async::Read for TypedIO {
+            async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+                buf[0] = self
+                    .0
+                    .pop_front()
+                    .ok_or(embedded_io_async::ErrorKind::NotConnected)?;
+                Ok(1)
+            }
+        }
+
+        impl embedded_io_async::Write for TypedIO {
+            async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+                Ok(buf.len())
+            }
+        }
+
+        fn assert_send<T: Send>(value: T) -> T {
+            value
+        }
+
+        let mut io = TypedIO("ab\rcd\ref\r".bytes().collect());
+        let (editor, _) = block_on(
+            EditorBuilder::new_unbounded()
+                .with_fixed_terminal_size(4, 20)
+                .build_async(&mut io),
+        );
+        let mut editor = editor.unwrap();
+
+        // The prompt type is erased before the line is created, so all
+        // three share the same line and output code, and the futures
+        // are `Send`
+        let (line, _) = block_on(assert_send(editor.readline("> ", &mut io)));
+        assert_eq!(line.unwrap(), "ab");
+
+        let prompt = ["dev", ":", "> "].into_iter();
+        let (line, _) = block_on(assert_send(editor.readline(prompt, &mut io)));
+        assert_eq!(line.unwrap(), "cd");
+
+        let prompt = "usr/bin".split('/').chain(core::iter::once(" > "));
+        let (line, _) = block_on(assert_send(editor.readline(prompt, &mut io)));
+        assert_eq!(line.unwrap(), "ef");
+    }
+
+    #[test]
+    fn shared_history() {
+        let history = RefCell::new(UnboundedHistory::new());
+        let new_io = |typed: &str| MockIO {
+            terminal: MockTerminal::new(4, 20, Cursor::new(0, 0)),
+            replies: VecDeque::new(),
+            connected: Rc::new(Cell::new(true)),
+            max_write: usize::MAX,
+            typed: typed.bytes().collect(),
+        };
+        let mut uart = new_io("o");
+        let mut usb = new_io("one\r");
+
+        let (uart_editor, _) = block_on(
+            EditorBuilder::new_unbounded()
+                .with_history(SharedHistory::new(&history))
+                .build_async(&mut uart),
+        );
+        let (usb_editor, _) = block_on(
+            EditorBuilder::new_unbounded()
+                .with_history(SharedHistory::new(&history))
+                .build_async(&mut usb),
+        );
+        let (mut uart_editor, mut usb_editor) = (uart_editor.unwrap(), usb_editor.unwrap());
+
+        {
+            // Both lines are read at once, and the line in progress
+            // sees the entry added by the other
+            let mut cx = Context::from_waker(Waker::noop());
+            let mut uart_line = pin!(uart_editor.readline("> ", &mut uart));
+            let mut usb_line = pin!(usb_editor.readline("> ", &mut usb));
+
+            assert!(uart_line.as_mut().poll(&mut cx).is_pending());
+            assert!(matches!(
+                usb_line.as_mut().poll(&mut cx),
+                Poll::Ready(Ok("one"))
+            ));
+            assert!(uart_line.as_mut().poll(&mut cx).is_pending());
+        }
+
+        uart.typed.extend(b"\x1b[A\r");
+
+        let (line, _) = block_on(uart_editor.readline("> ", &mut uart));
+        assert_eq!(line.unwrap(), "one");
+
+        // The history is free between lines, and the repeated line
+        // isn't added again
+        assert_eq!(history.borrow().number_of_entries(), 1);
+    }
+
+    #[test]
+    fn link_lost() {
+        let connected = Rc::new(Cell::new(false));
+        let mut io = MockIO {
+            terminal: MockTerminal::new(4, 20, Cursor::new(0, 0)),
+            replies: VecDeque::new(),
+            connected: connected.clone(),
+            max_write: usize::MAX,
+            typed: "ab".bytes().collect(),
+        };
+
+        let link = {
+            let connected = connected.clone();
+            move || connected.get()
+        };
+        let (editor, _) = block_on(
+            EditorBuilder::new_unbounded()
+                .with_link_state(link)
+                .build_async(&mut io),
+        );
+        let mut editor = editor.unwrap();
+
+        {
+            let mut future = pin!(editor.readline("> ", &mut io));
+            let mut cx = Context::from_waker(Waker::noop());
+
+            // Waits for the link
+            for _ in 0..10 {
+                assert!(future.as_mut().poll(&mut cx).is_pending());
+            }
+
+            connected.set(true);
+            assert!(future.as_mut().poll(&mut cx).is_pending());
+
+            connected.set(false);
+            assert!(matches!(
+                future.as_mut().poll(&mut cx),
+                Poll::Ready(Err(NolineError::Disconnected))
+            ));
+        }
+
+        assert_eq!(io.terminal.screen_as_string(), "> ab");
+        assert_eq!(editor.last_line(), "ab");
+
+        // Next line starts from scratch
+        connected.set(true);
+        io.typed.extend("c\r".bytes());
+
+        let (line, _) = block_on(editor.readline("> ", &mut io));
+        assert_eq!(line.unwrap(), "c");
+        assert_eq!(io.terminal.screen_as_string(), "> c");
     }
 }
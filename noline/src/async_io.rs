@@ -0,0 +1,149 @@
+//! IO adapters for the async editor
+//!
+//! [`TokioIO`] implements the [`embedded_io_async`] traits for a reader
+//! and writer implementing tokio's [`AsyncRead`] and [`AsyncWrite`], so
+//! tokio streams can be used with [`crate::async_editor::Editor`].
+//! Requires feature `tokio`.
+//!
+//! Like with any IO, the terminal has to be in raw mode for the editor
+//! to get keys as they are typed, see the `std-async-tokio` example.
+//!
+//! ```no_run
+//! use noline::{async_io::TokioIO, builder::EditorBuilder};
+//!
+//! # async fn run() {
+//! let mut io = TokioIO::stdio();
+//! let mut editor = EditorBuilder::new_unbounded()
+//!     .with_unbounded_history()
+//!     .build_async(&mut io)
+//!     .await
+//!     .unwrap();
+//!
+//! while let Ok(line) = editor.readline("> ", &mut io).await {
+//!     // handle line
+//! }
+//! # }
+//! ```
+
+extern crate std;
+
+use std::io;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, Stdin, Stdout};
+
+/// Adapter implementing [`embedded_io_async`] traits for a tokio
+/// reader and writer, e.g. stdin and stdout, or the halves of a stream
+/// split with [`tokio::io::split`]
+pub struct TokioIO<R, W> {
+    reader: R,
+    writer: W,
+}
+
+impl<R, W> TokioIO<R, W> {
+    /// Read from `reader` and write to `writer`
+    pub fn new(reader: R, writer: W) -> Self {
+        Self { reader, writer }
+    }
+
+    /// Return the wrapped reader and writer
+    pub fn into_inner(self) -> (R, W) {
+        (self.reader, self.writer)
+    }
+}
+
+impl TokioIO<Stdin, Stdout> {
+    /// Read from stdin and write to stdout
+    pub fn stdio() -> Self {
+        Self::new(tokio::io::stdin(), tokio::io::stdout())
+    }
+}
+
+impl<R, W> embedded_io_async::ErrorType for TokioIO<R, W> {
+    type Error = io::Error;
+}
+
+impl<R: AsyncRead + Unpin, W> embedded_io_async::Read for TokioIO<R, W> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.reader.read(buf).await
+    }
+}
+
+impl<R, W: AsyncWrite + Unpin> embedded_io_async::Write for TokioIO<R, W> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.writer.write(buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.writer.flush().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::string::{String, ToString};
+
+    use super::*;
+    use crate::builder::EditorBuilder;
+    use crate::terminal::Cursor;
+    use crate::testlib::MockTerminal;
+
+    #[tokio::test]
+    async fn readline() {
+        let (editor_end, mut terminal_end) = tokio::io::duplex(64);
+        let (reader, writer) = tokio::io::split(editor_end);
+
+        let editor = async move {
+            let mut io = TokioIO::new(reader, writer);
+            let mut editor = EditorBuilder::new_unbounded()
+                .build_async(&mut io)
+                .await
+                .unwrap();
+
+            let line = editor.readline("> ", &mut io).await.unwrap().to_string();
+            let eof = editor.readline("> ", &mut io).await.is_err();
+
+            (line, eof)
+        };
+
+        // Answers the editor like a terminal, and types a line when the
+        // prompt is shown. Closing the stream ends the second readline.
+        let terminal = async move {
+            let mut terminal = MockTerminal::new(4, 20, Cursor::new(0, 0));
+            let mut typed = false;
+            let mut buf = [0; 64];
+
+            loop {
+                let len = terminal_end.read(&mut buf).await.unwrap();
+
+                if len == 0 {
+                    break;
+                }
+
+                for &byte in &buf[..len] {
+                    if let Some(reply) = terminal.advance(byte) {
+                        terminal_end.write_all(&reply).await.unwrap();
+                    }
+                }
+
+                if terminal.current_line_as_string() == "> " {
+                    if terminal.get_cursor() == Cursor::new(1, 2) {
+                        break;
+                    }
+
+                    if !typed {
+                        terminal_end.write_all(b"abc\r").await.unwrap();
+                        typed = true;
+                    }
+                }
+            }
+
+            terminal
+        };
+
+        let ((line, eof), terminal) = tokio::join!(editor, terminal);
+
+        assert_eq!(line, "abc");
+        assert!(eof);
+        assert_eq!(terminal.row_as_string(0), String::from("> abc"));
+    }
+}
@@ -1,17 +1,33 @@
 //! Builder for editors
 
-use core::marker::PhantomData;
+use core::{marker::PhantomData, num::NonZeroU16};
 
 use crate::{
     async_editor,
+    caps::TerminalCaps,
+    complete::{Completer, NoCompleter},
+    core::{Settings, DEFAULT_PROMPT},
     error::NolineError,
-    history::{History, NoHistory, SliceHistory},
+    hint::{Hinter, NoHinter},
+    history::{BorrowHistory, NoHistory, SliceHistory, StaticSlotHistory},
     line_buffer::{Buffer, LineBuffer, NoBuffer, SliceBuffer},
+    link::{AlwaysConnected, LinkState},
+    output::WRITE_CHUNK_CAPACITY,
+    read_buffer::READ_BUFFER_CAPACITY,
+    strings::Strings,
     sync_editor,
+    undo::UndoBuffer,
+    validate::{NoValidator, Validator},
+    write_buffer::WriteBuffer,
 };
 
 #[cfg(any(test, doc, feature = "alloc", feature = "std"))]
-use crate::{history::UnboundedHistory, line_buffer::UnboundedBuffer};
+use crate::{
+    history::{AllocHistory, UnboundedHistory},
+    line_buffer::UnboundedBuffer,
+};
+
+pub use crate::core::{ControlCharPolicy, Echo, EditingMode, OverflowPolicy, TabPolicy, TrimMode};
 
 /// Builder for [`sync_editor::Editor`] and [`async_editor::Editor`].
 ///
@@ -40,9 +56,26 @@ use crate::{history::UnboundedHistory, line_buffer::UnboundedBuffer};
 ///     .build_sync(&mut io)
 ///     .unwrap();
 /// ```
-pub struct EditorBuilder<B: Buffer, H: History> {
+pub struct EditorBuilder<
+    B: Buffer,
+    H: BorrowHistory,
+    C: Completer = NoCompleter,
+    L: LinkState = AlwaysConnected,
+    V: Validator = NoValidator,
+    T: Hinter = NoHinter,
+> {
     line_buffer: LineBuffer<B>,
+    kill_buffer: Option<LineBuffer<B>>,
+    undo_buffer: Option<UndoBuffer<B>>,
+    stash_buffer: Option<LineBuffer<B>>,
+    write_buffer: Option<WriteBuffer<B>>,
     history: H,
+    completer: Option<C>,
+    link_state: L,
+    validator: Option<V>,
+    hinter: Option<T>,
+    settings: Settings,
+    prompt: &'static str,
     _marker: PhantomData<(B, H)>,
 }
 
@@ -59,7 +92,17 @@ impl EditorBuilder<NoBuffer, NoHistory> {
     pub fn from_slice(buffer: &mut [u8]) -> EditorBuilder<SliceBuffer<'_>, NoHistory> {
         EditorBuilder {
             line_buffer: LineBuffer::from_slice(buffer),
+            kill_buffer: None,
+            undo_buffer: None,
+            stash_buffer: None,
+            write_buffer: None,
             history: NoHistory {},
+            completer: None,
+            link_state: AlwaysConnected,
+            validator: None,
+            hinter: None,
+            settings: Settings::default(),
+            prompt: DEFAULT_PROMPT,
             _marker: PhantomData,
         }
     }
@@ -76,45 +119,532 @@ impl EditorBuilder<NoBuffer, NoHistory> {
     pub fn new_unbounded() -> EditorBuilder<UnboundedBuffer, NoHistory> {
         EditorBuilder {
             line_buffer: LineBuffer::new_unbounded(),
+            kill_buffer: Some(LineBuffer::new_unbounded()),
+            undo_buffer: Some(UndoBuffer::new(UnboundedBuffer::new())),
+            stash_buffer: Some(LineBuffer::new_unbounded()),
+            write_buffer: None,
             history: NoHistory {},
+            completer: None,
+            link_state: AlwaysConnected,
+            validator: None,
+            hinter: None,
+            settings: Settings::default(),
+            prompt: DEFAULT_PROMPT,
             _marker: PhantomData,
         }
     }
 }
 
-impl<B: Buffer, H: History> EditorBuilder<B, H> {
+impl<'a, H: BorrowHistory, C: Completer, L: LinkState, V: Validator, T: Hinter>
+    EditorBuilder<SliceBuffer<'a>, H, C, L, V, T>
+{
+    /// Add static kill buffer, holding the text deleted by the last
+    /// Ctrl-K, Ctrl-U or Ctrl-W. Ctrl-Y inserts it at the cursor.
+    /// Deleted text that doesn't fit is truncated. Without a kill
+    /// buffer, Ctrl-Y rings the bell. Builders with an unbounded
+    /// buffer have an unbounded kill buffer.
+    ///
+    /// # Example
+    /// ```
+    /// use noline::builder::EditorBuilder;
+    ///
+    /// let mut buffer = [0; 100];
+    /// let mut kill_buffer = [0; 100];
+    /// let builder = EditorBuilder::from_slice(&mut buffer).with_kill_buffer(&mut kill_buffer);
+    /// ```
+    pub fn with_kill_buffer(mut self, buffer: &'a mut [u8]) -> Self {
+        self.kill_buffer = Some(LineBuffer::from_slice(buffer));
+        self
+    }
+
+    /// Add static undo buffer, recording edits to the line so they can
+    /// be undone with Ctrl-_ or Ctrl-X Ctrl-U, restoring the cursor
+    /// position. When the buffer is full, the oldest edits are dropped.
+    /// Each edit takes 8 bytes plus the deleted text, and the buffer
+    /// also holds a copy of the line. Without an undo buffer, undo
+    /// rings the bell. Builders with an unbounded buffer have an
+    /// unbounded undo buffer.
+    ///
+    /// # Example
+    /// ```
+    /// use noline::builder::EditorBuilder;
+    ///
+    /// let mut buffer = [0; 100];
+    /// let mut undo_buffer = [0; 256];
+    /// let builder = EditorBuilder::from_slice(&mut buffer).with_undo_buffer(&mut undo_buffer);
+    /// ```
+    pub fn with_undo_buffer(mut self, buffer: &'a mut [u8]) -> Self {
+        self.undo_buffer = Some(UndoBuffer::new(SliceBuffer::new(buffer)));
+        self
+    }
+
+    /// Add static stash buffer, keeping the line typed before
+    /// navigating history with Up. Navigating down past the newest
+    /// entry restores it. It should be as large as the line buffer, as
+    /// history isn't navigated from a line that doesn't fit. Without a
+    /// stash buffer, history is only navigated from an empty line, or
+    /// from the end of a line, where Up shows only entries starting
+    /// with it. Builders with an unbounded buffer have an unbounded stash
+    /// buffer.
+    ///
+    /// # Example
+    /// ```
+    /// use noline::builder::EditorBuilder;
+    ///
+    /// let mut buffer = [0; 100];
+    /// let mut stash_buffer = [0; 100];
+    /// let builder = EditorBuilder::from_slice(&mut buffer).with_stash_buffer(&mut stash_buffer);
+    /// ```
+    pub fn with_stash_buffer(mut self, buffer: &'a mut [u8]) -> Self {
+        self.stash_buffer = Some(LineBuffer::from_slice(buffer));
+        self
+    }
+
+    /// Add static write buffer, holding output that the IO doesn't
+    /// accept, i.e. when `write` returns 0. Held output is written
+    /// before more input is handled. With
+    /// [`sync_editor::Editor::readline_poll`], the caller can service
+    /// the device between polls instead of the editor spinning on it,
+    /// and the async editor yields until the IO accepts it. Output that
+    /// doesn't fit fails with [`embedded_io::ErrorKind::WriteZero`],
+    /// like without a write buffer. Builders with an unbounded buffer
+    /// can hold any amount of output with
+    /// [`EditorBuilder::with_unbounded_write_buffer`].
+    ///
+    /// # Example
+    /// ```
+    /// use noline::builder::EditorBuilder;
+    ///
+    /// let mut buffer = [0; 100];
+    /// let mut write_buffer = [0; 256];
+    /// let builder = EditorBuilder::from_slice(&mut buffer).with_write_buffer(&mut write_buffer);
+    /// ```
+    pub fn with_write_buffer(mut self, buffer: &'a mut [u8]) -> Self {
+        self.write_buffer = Some(WriteBuffer::new(SliceBuffer::new(buffer)));
+        self
+    }
+}
+
+#[cfg(any(test, doc, feature = "alloc", feature = "std"))]
+impl<H: BorrowHistory, C: Completer, L: LinkState, V: Validator, T: Hinter>
+    EditorBuilder<UnboundedBuffer, H, C, L, V, T>
+{
+    /// Add unbounded write buffer, holding any amount of output that
+    /// the IO doesn't accept, see [`EditorBuilder::with_write_buffer`].
+    /// Without it, output is never held, and writing fails when `write`
+    /// returns 0.
+    ///
+    /// # Example
+    /// ```
+    /// use noline::builder::EditorBuilder;
+    ///
+    /// let builder = EditorBuilder::new_unbounded().with_unbounded_write_buffer();
+    /// ```
+    pub fn with_unbounded_write_buffer(mut self) -> Self {
+        self.write_buffer = Some(WriteBuffer::new(UnboundedBuffer::new()));
+        self
+    }
+}
+
+impl<B: Buffer, H: BorrowHistory, C: Completer, L: LinkState, V: Validator, T: Hinter>
+    EditorBuilder<B, H, C, L, V, T>
+{
+    /// Add history, e.g. a custom [`crate::history::History`], one
+    /// borrowed from elsewhere, or a
+    /// [`crate::history::SharedHistory`] shared with other editors
+    ///
+    /// # Example
+    /// ```
+    /// use noline::builder::EditorBuilder;
+    /// use noline::history::SliceHistory;
+    ///
+    /// let mut storage = [0; 256];
+    /// let mut history = SliceHistory::new(&mut storage);
+    ///
+    /// let mut buffer = [0; 100];
+    /// let builder = EditorBuilder::from_slice(&mut buffer).with_history(&mut history);
+    /// ```
+    pub fn with_history<H2: BorrowHistory>(self, history: H2) -> EditorBuilder<B, H2, C, L, V, T> {
+        EditorBuilder {
+            line_buffer: self.line_buffer,
+            kill_buffer: self.kill_buffer,
+            undo_buffer: self.undo_buffer,
+            stash_buffer: self.stash_buffer,
+            write_buffer: self.write_buffer,
+            history,
+            completer: self.completer,
+            link_state: self.link_state,
+            validator: self.validator,
+            hinter: self.hinter,
+            settings: self.settings,
+            prompt: self.prompt,
+            _marker: PhantomData,
+        }
+    }
+
     /// Add static history
-    pub fn with_slice_history(self, buffer: &mut [u8]) -> EditorBuilder<B, SliceHistory<'_>> {
+    pub fn with_slice_history(
+        self,
+        buffer: &mut [u8],
+    ) -> EditorBuilder<B, SliceHistory<'_>, C, L, V, T> {
         EditorBuilder {
             line_buffer: self.line_buffer,
+            kill_buffer: self.kill_buffer,
+            undo_buffer: self.undo_buffer,
+            stash_buffer: self.stash_buffer,
+            write_buffer: self.write_buffer,
             history: SliceHistory::new(buffer),
+            completer: self.completer,
+            link_state: self.link_state,
+            validator: self.validator,
+            hinter: self.hinter,
+            settings: self.settings,
+            prompt: self.prompt,
             _marker: PhantomData,
         }
     }
 
+    /// Add static history keeping the `SLOTS` most recent entries of
+    /// at most `WIDTH` bytes each, see [`StaticSlotHistory`]
+    ///
+    /// # Example
+    /// ```
+    /// use noline::builder::EditorBuilder;
+    ///
+    /// let mut buffer = [0; 64];
+    /// let builder = EditorBuilder::from_slice(&mut buffer).with_slot_history::<8, 64>();
+    /// ```
+    pub fn with_slot_history<const SLOTS: usize, const WIDTH: usize>(
+        self,
+    ) -> EditorBuilder<B, StaticSlotHistory<SLOTS, WIDTH>, C, L, V, T> {
+        self.with_history(StaticSlotHistory::new())
+    }
+
     #[cfg(any(test, feature = "alloc", feature = "std"))]
     /// Add unbounded history
-    pub fn with_unbounded_history(self) -> EditorBuilder<B, UnboundedHistory> {
+    pub fn with_unbounded_history(self) -> EditorBuilder<B, UnboundedHistory, C, L, V, T> {
         EditorBuilder {
             line_buffer: self.line_buffer,
+            kill_buffer: self.kill_buffer,
+            undo_buffer: self.undo_buffer,
+            stash_buffer: self.stash_buffer,
+            write_buffer: self.write_buffer,
             history: UnboundedHistory::new(),
+            completer: self.completer,
+            link_state: self.link_state,
+            validator: self.validator,
+            hinter: self.hinter,
+            settings: self.settings,
+            prompt: self.prompt,
+            _marker: PhantomData,
+        }
+    }
+
+    #[cfg(any(test, feature = "alloc", feature = "std"))]
+    /// Add history keeping the `max_entries` most recent entries
+    pub fn with_alloc_history(
+        self,
+        max_entries: usize,
+    ) -> EditorBuilder<B, AllocHistory, C, L, V, T> {
+        EditorBuilder {
+            line_buffer: self.line_buffer,
+            kill_buffer: self.kill_buffer,
+            undo_buffer: self.undo_buffer,
+            stash_buffer: self.stash_buffer,
+            write_buffer: self.write_buffer,
+            history: AllocHistory::with_max_entries(max_entries),
+            completer: self.completer,
+            link_state: self.link_state,
+            validator: self.validator,
+            hinter: self.hinter,
+            settings: self.settings,
+            prompt: self.prompt,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Set policy for tab characters in input. Default is
+    /// [`TabPolicy::Expand`] to four spaces.
+    pub fn with_tab_policy(mut self, policy: TabPolicy) -> Self {
+        self.settings.tab = policy;
+        self
+    }
+
+    /// Set policy for control characters in input that aren't bound to
+    /// a key. Default is [`ControlCharPolicy::Bell`].
+    pub fn with_control_char_policy(mut self, policy: ControlCharPolicy) -> Self {
+        self.settings.control_chars = policy;
+        self
+    }
+
+    /// Set policy for typed or pasted text that doesn't fit in the line
+    /// buffer. Default is [`OverflowPolicy::Truncate`].
+    pub fn with_overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.settings.overflow = policy;
+        self
+    }
+
+    /// Set whitespace removed from accepted lines. Validators and
+    /// history get the trimmed line too, so lines differing only in
+    /// trailing whitespace are stored once. Default is
+    /// [`TrimMode::None`].
+    pub fn with_trim(mut self, mode: TrimMode) -> Self {
+        self.settings.trim = mode;
+        self
+    }
+
+    /// Add completer. With a completer, Tab completes the line
+    /// instead of inserting a tab. See [`crate::complete`].
+    pub fn with_completer<C2: Completer>(self, completer: C2) -> EditorBuilder<B, H, C2, L, V, T> {
+        EditorBuilder {
+            line_buffer: self.line_buffer,
+            kill_buffer: self.kill_buffer,
+            undo_buffer: self.undo_buffer,
+            stash_buffer: self.stash_buffer,
+            write_buffer: self.write_buffer,
+            history: self.history,
+            completer: Some(completer),
+            link_state: self.link_state,
+            validator: self.validator,
+            hinter: self.hinter,
+            settings: self.settings,
+            prompt: self.prompt,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Add link state, checked before reading and writing. When the
+    /// link is lost, `readline` returns [`NolineError::Disconnected`],
+    /// and the next call waits for the link before probing the
+    /// terminal again. See [`crate::link`].
+    pub fn with_link_state<L2: LinkState>(
+        self,
+        link_state: L2,
+    ) -> EditorBuilder<B, H, C, L2, V, T> {
+        EditorBuilder {
+            line_buffer: self.line_buffer,
+            kill_buffer: self.kill_buffer,
+            undo_buffer: self.undo_buffer,
+            stash_buffer: self.stash_buffer,
+            write_buffer: self.write_buffer,
+            history: self.history,
+            completer: self.completer,
+            link_state,
+            validator: self.validator,
+            hinter: self.hinter,
+            settings: self.settings,
+            prompt: self.prompt,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Add validator, checked when Enter is pressed. If the validator
+    /// rejects the line, the editor rings the bell and the line can
+    /// be edited further. See [`crate::validate`].
+    pub fn with_validator<V2: Validator>(self, validator: V2) -> EditorBuilder<B, H, C, L, V2, T> {
+        EditorBuilder {
+            line_buffer: self.line_buffer,
+            kill_buffer: self.kill_buffer,
+            undo_buffer: self.undo_buffer,
+            stash_buffer: self.stash_buffer,
+            write_buffer: self.write_buffer,
+            history: self.history,
+            completer: self.completer,
+            link_state: self.link_state,
+            validator: Some(validator),
+            hinter: self.hinter,
+            settings: self.settings,
+            prompt: self.prompt,
             _marker: PhantomData,
         }
     }
 
-    /// Build [`sync_editor::Editor`]. Is equivalent of calling [`sync_editor::Editor::new()`].
+    /// Add hinter, showing hints dimmed after characters typed at the
+    /// end of the line. Right or Ctrl-E accepts the hint. See
+    /// [`crate::hint`].
+    pub fn with_hinter<T2: Hinter>(self, hinter: T2) -> EditorBuilder<B, H, C, L, V, T2> {
+        EditorBuilder {
+            line_buffer: self.line_buffer,
+            kill_buffer: self.kill_buffer,
+            undo_buffer: self.undo_buffer,
+            stash_buffer: self.stash_buffer,
+            write_buffer: self.write_buffer,
+            history: self.history,
+            completer: self.completer,
+            link_state: self.link_state,
+            validator: self.validator,
+            hinter: Some(hinter),
+            settings: self.settings,
+            prompt: self.prompt,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Show completions highlighted before inserting them. The first
+    /// Tab previews the first candidate, a second Tab or a printable
+    /// character inserts it, and any other key discards it. Requires
+    /// a completer, see [`Self::with_completer`]. Default is off.
+    pub fn with_completion_preview(mut self, enabled: bool) -> Self {
+        self.settings.completion_preview = enabled;
+        self
+    }
+
+    /// Complete like bash. Tab inserts the prefix shared by all
+    /// candidates, and a second Tab lists them below the line.
+    /// Requires a completer, see [`Self::with_completer`]. Ignored with
+    /// [`Self::with_completion_preview`]. Default is off.
+    pub fn with_completion_list(mut self, enabled: bool) -> Self {
+        self.settings.completion_list = enabled;
+        self
+    }
+
+    /// Enable bracketed paste while reading a line, so pasted text
+    /// can't end the line or trigger editing commands. Newlines in
+    /// pasted text are inserted as spaces, and other control
+    /// characters except tab are dropped. The line ends when Enter is
+    /// pressed after pasting. Default is off, but pasted text is
+    /// handled this way whenever the terminal marks it.
+    pub fn with_bracketed_paste(mut self, enabled: bool) -> Self {
+        self.settings.bracketed_paste = enabled;
+        self
+    }
+
+    /// Erase by overwriting with spaces, for terminals without support
+    /// for erasing to the end of the screen. The right-side prompt is
+    /// not shown, and clearing the screen only clears the line being
+    /// edited. Default is off.
+    pub fn with_padded_erase(mut self, enabled: bool) -> Self {
+        self.settings.padded_erase = enabled;
+        self
+    }
+
+    /// Mark the end of the line with `!` when typed or pasted text
+    /// doesn't fit in the line buffer, in addition to ringing the bell.
+    /// The mark is erased when the line is edited. Only shown with
+    /// [`Echo::Full`] and [`Echo::Local`]. Default is off.
+    pub fn with_overflow_indicator(mut self, enabled: bool) -> Self {
+        self.settings.overflow_indicator = enabled;
+        self
+    }
+
+    /// Assume a terminal of `rows` by `columns` instead of probing the
+    /// size and cursor position, for terminals that never reply to
+    /// cursor position requests. The cursor is assumed to be at the
+    /// start of a row when reading a line, and is only moved relative
+    /// to where it is, using carriage return and cursor up, down and
+    /// forward. The right-side prompt is not shown, and clearing the
+    /// screen only prints the line again. Default is to probe, as is
+    /// a size of zero.
+    pub fn with_fixed_terminal_size(mut self, rows: u16, columns: u16) -> Self {
+        self.settings.fixed_size = NonZeroU16::new(rows).zip(NonZeroU16::new(columns));
+        self
+    }
+
+    /// Yield to the executor after every `bytes` bytes written by
+    /// [`async_editor::Editor`], so a long redraw doesn't keep other
+    /// tasks from running on executors without preemption. Ignored by
+    /// [`sync_editor::Editor`]. Default is 0, never yield.
+    pub fn with_yield_every(mut self, bytes: usize) -> Self {
+        self.settings.yield_every = bytes.try_into().unwrap_or(u32::MAX);
+        self
+    }
+
+    /// Read at most `bytes` bytes of input per read call, at most 64.
+    /// Reading more at a time is faster when input arrives in packets,
+    /// like over USB, or is pasted. Bytes left when a line is done are
+    /// kept for the next line. Probing the terminal reads a byte at a
+    /// time regardless. Default is 16.
+    pub fn with_read_size(mut self, bytes: usize) -> Self {
+        self.settings.read_size = bytes.clamp(1, READ_BUFFER_CAPACITY) as u8;
+        self
+    }
+
+    /// Write at most `bytes` bytes of output per write call of
+    /// [`async_editor::Editor`], at most 64. Output is copied into a
+    /// buffer of this size before writing, so small pieces of output
+    /// are written together. Ignored by [`sync_editor::Editor`].
+    /// Default is 64.
+    pub fn with_write_size(mut self, bytes: usize) -> Self {
+        self.settings.write_size = bytes.clamp(1, WRITE_CHUNK_CAPACITY) as u8;
+        self
+    }
+
+    /// Set echo policy, for terminals with local echo. Default is
+    /// [`Echo::Full`].
+    pub fn with_echo(mut self, echo: Echo) -> Self {
+        self.settings.echo = echo;
+        self
+    }
+
+    /// Set key bindings, emacs or vi. Default is
+    /// [`EditingMode::Emacs`].
+    pub fn with_editing_mode(mut self, mode: EditingMode) -> Self {
+        self.settings.editing_mode = mode;
+        self
+    }
+
+    /// Set prompt used by `readline_default` and
+    /// [`sync_editor::Editor::readline_poll_default`]. Default is
+    /// `"> "`.
+    pub fn with_prompt(mut self, prompt: &'static str) -> Self {
+        self.prompt = prompt;
+        self
+    }
+
+    /// Translate the text printed by the editor, like the reverse
+    /// search prompt. Default is [`crate::strings::English`].
+    pub fn with_strings(mut self, strings: &'static (dyn Strings + Sync)) -> Self {
+        self.settings.strings = Some(strings);
+        self
+    }
+
+    /// Write other control sequences than VT100 to move the cursor,
+    /// erase and so on. Default is [`crate::caps::Vt100`].
+    pub fn with_terminal_caps(mut self, caps: &'static (dyn TerminalCaps + Sync)) -> Self {
+        self.settings.caps = Some(caps);
+        self
+    }
+
+    /// Build [`sync_editor::Editor`]. Is equivalent of calling
+    /// [`sync_editor::Editor::new()`] and applying the builder settings.
     pub fn build_sync<IO: embedded_io::Read + embedded_io::Write>(
         self,
         io: &mut IO,
-    ) -> Result<sync_editor::Editor<B, H>, NolineError> {
-        sync_editor::Editor::new(self.line_buffer, self.history, io)
+    ) -> Result<sync_editor::Editor<B, H, C, L, V, T>, NolineError> {
+        let mut editor = sync_editor::Editor::new(self.line_buffer, self.history, io)?
+            .with_completer(self.completer)
+            .with_link_state(self.link_state)
+            .with_validator(self.validator)
+            .with_hinter(self.hinter);
+        editor.kill_buffer = self.kill_buffer;
+        editor.undo_buffer = self.undo_buffer;
+        editor.stash_buffer = self.stash_buffer;
+        editor.write_buffer = self.write_buffer;
+        editor.settings = self.settings;
+        editor.prompt = self.prompt;
+
+        Ok(editor)
     }
 
-    /// Build [`async_editor::Editor`]. Is equivalent of calling [`async_editor::Editor::new()`].
+    /// Build [`async_editor::Editor`]. Is equivalent of calling
+    /// [`async_editor::Editor::new()`] and applying the builder settings.
     pub async fn build_async<IO: embedded_io_async::Read + embedded_io_async::Write>(
         self,
         io: &mut IO,
-    ) -> Result<async_editor::Editor<B, H>, NolineError> {
-        async_editor::Editor::new(self.line_buffer, self.history, io).await
+    ) -> Result<async_editor::Editor<B, H, C, L, V, T>, NolineError> {
+        let mut editor = async_editor::Editor::new(self.line_buffer, self.history, io)
+            .await?
+            .with_completer(self.completer)
+            .with_link_state(self.link_state)
+            .with_validator(self.validator)
+            .with_hinter(self.hinter);
+        editor.kill_buffer = self.kill_buffer;
+        editor.undo_buffer = self.undo_buffer;
+        editor.stash_buffer = self.stash_buffer;
+        editor.write_buffer = self.write_buffer;
+        editor.settings = self.settings;
+        editor.prompt = self.prompt;
+
+        Ok(editor)
     }
 }
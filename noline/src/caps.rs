@@ -0,0 +1,223 @@
+//! Control sequences written by the editor
+//!
+//! The editor moves the cursor, erases and rings the bell with VT100
+//! control sequences. Terminals using other sequences can be driven by
+//! implementing [`TerminalCaps`] and adding it with
+//! [`crate::builder::EditorBuilder::with_terminal_caps`]. Methods that
+//! aren't implemented write the VT100 sequence.
+//!
+//! ```
+//! use noline::builder::EditorBuilder;
+//! use noline::caps::{Sequence, TerminalCaps};
+//!
+//! // Heath/Zenith style cursor addressing, with row and column
+//! // offset by 32
+//! struct Heath;
+//!
+//! impl TerminalCaps for Heath {
+//!     fn move_to(&self, seq: &mut Sequence, row: usize, column: usize) {
+//!         seq.push(b"\x1bY");
+//!         seq.push(&[32 + row.min(94) as u8, 32 + column.min(94) as u8]);
+//!     }
+//! }
+//!
+//! let mut buffer = [0; 100];
+//! let builder = EditorBuilder::from_slice(&mut buffer).with_terminal_caps(&Heath);
+//! ```
+//!
+//! The cursor position is always read from a VT100 cursor position
+//! report, `ESC [ row ; column R`, so terminals replying otherwise to
+//! [`TerminalCaps::request_position`] must be given a fixed size with
+//! [`crate::builder::EditorBuilder::with_fixed_terminal_size`].
+
+pub use crate::output::Style;
+
+/// Capacity of [`Sequence`] in bytes
+pub const SEQUENCE_CAPACITY: usize = 15;
+
+/// Control sequence with parameters, written by [`TerminalCaps`].
+/// Bytes pushed beyond [`SEQUENCE_CAPACITY`] are dropped.
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Sequence {
+    bytes: [u8; SEQUENCE_CAPACITY],
+    len: u8,
+}
+
+impl Sequence {
+    pub(crate) fn new() -> Self {
+        Self {
+            bytes: [0; SEQUENCE_CAPACITY],
+            len: 0,
+        }
+    }
+
+    /// Append bytes
+    pub fn push(&mut self, bytes: &[u8]) {
+        let start = self.len as usize;
+        let len = bytes.len().min(SEQUENCE_CAPACITY - start);
+
+        self.bytes[start..start + len].copy_from_slice(&bytes[..len]);
+        self.len += len as u8;
+    }
+
+    /// Append `n` as decimal digits
+    pub fn push_decimal(&mut self, n: usize) {
+        let mut digits = [0; 20];
+        let mut start = digits.len();
+        let mut n = n;
+
+        loop {
+            start -= 1;
+            digits[start] = b'0' + (n % 10) as u8;
+            n /= 10;
+
+            if n == 0 {
+                break;
+            }
+        }
+
+        self.push(&digits[start..]);
+    }
+
+    /// Bytes pushed so far
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+/// Control sequences written to the terminal. Rows and columns are
+/// counted from 0.
+pub trait TerminalCaps {
+    /// Move the cursor to `row` and `column` of the screen
+    fn move_to(&self, seq: &mut Sequence, row: usize, column: usize) {
+        seq.push(b"\x1b[");
+        seq.push_decimal(row + 1);
+        seq.push(b";");
+        seq.push_decimal(column + 1);
+        seq.push(b"H");
+    }
+
+    /// Move the cursor up by `rows`. Used in place of
+    /// [`TerminalCaps::move_to`] when the terminal size is fixed, as
+    /// the row of the cursor isn't known.
+    fn move_up(&self, seq: &mut Sequence, rows: usize) {
+        csi(seq, rows, b"A");
+    }
+
+    /// Move the cursor down by `rows`, like [`TerminalCaps::move_up`]
+    fn move_down(&self, seq: &mut Sequence, rows: usize) {
+        csi(seq, rows, b"B");
+    }
+
+    /// Move the cursor right by `columns`, like
+    /// [`TerminalCaps::move_up`]
+    fn move_right(&self, seq: &mut Sequence, columns: usize) {
+        csi(seq, columns, b"C");
+    }
+
+    /// Scroll the screen up by `rows`, adding blank rows at the bottom
+    fn scroll_up(&self, seq: &mut Sequence, rows: usize) {
+        csi(seq, rows, b"S");
+    }
+
+    /// Scroll the screen down by `rows`, adding blank rows at the top
+    fn scroll_down(&self, seq: &mut Sequence, rows: usize) {
+        csi(seq, rows, b"T");
+    }
+
+    /// Move the cursor to the start of the row
+    fn carriage_return(&self) -> &'static [u8] {
+        b"\r"
+    }
+
+    /// Erase from the cursor to the end of the screen
+    fn erase_below(&self) -> &'static [u8] {
+        b"\x1b[J"
+    }
+
+    /// Erase from the cursor to the end of the row. Only used with
+    /// [`crate::builder::EditorBuilder::with_padded_erase`], when
+    /// starting a line on a row with text of unknown length. Return an
+    /// empty slice for terminals without it.
+    fn erase_line(&self) -> &'static [u8] {
+        b"\x1b[K"
+    }
+
+    /// Ring the bell, e.g. when a key does nothing
+    fn bell(&self) -> &'static [u8] {
+        b"\x07"
+    }
+
+    /// Ask the terminal to report the cursor position
+    fn request_position(&self) -> &'static [u8] {
+        b"\x1b[6n"
+    }
+
+    /// Save the cursor position, to be restored by
+    /// [`TerminalCaps::restore_cursor`]
+    fn save_cursor(&self) -> &'static [u8] {
+        b"\x1b7"
+    }
+
+    /// Move the cursor back to the position saved by
+    /// [`TerminalCaps::save_cursor`]
+    fn restore_cursor(&self) -> &'static [u8] {
+        b"\x1b8"
+    }
+
+    /// Enable or disable bracketed paste
+    fn bracketed_paste(&self, enabled: bool) -> &'static [u8] {
+        if enabled {
+            b"\x1b[?2004h"
+        } else {
+            b"\x1b[?2004l"
+        }
+    }
+
+    /// Select the style of hints and other text shown in place of the
+    /// line. Return an empty slice to print them without styling, e.g.
+    /// when `NO_COLOR` is set.
+    fn style(&self, style: Style) -> &'static [u8] {
+        match style {
+            Style::Plain => b"\x1b[0m",
+            Style::Reverse => b"\x1b[7m",
+            Style::Dim => b"\x1b[2m",
+        }
+    }
+}
+
+fn csi(seq: &mut Sequence, n: usize, final_byte: &[u8]) {
+    seq.push(b"\x1b[");
+    seq.push_decimal(n);
+    seq.push(final_byte);
+}
+
+/// VT100 control sequences, used when no [`TerminalCaps`] is given
+pub struct Vt100;
+
+impl TerminalCaps for Vt100 {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequence() {
+        let mut seq = Sequence::new();
+
+        seq.push_decimal(0);
+        seq.push(b";");
+        seq.push_decimal(42);
+        seq.push(b";");
+        seq.push_decimal(9999);
+        assert_eq!(seq.as_bytes(), b"0;42;9999");
+
+        seq.push(b"abcdefgh");
+        assert_eq!(seq.as_bytes(), b"0;42;9999abcdef");
+
+        let mut seq = Sequence::new();
+        Vt100.move_to(&mut seq, 42, 43);
+        assert_eq!(seq.as_bytes(), b"\x1b[43;44H");
+    }
+}
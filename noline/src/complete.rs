@@ -0,0 +1,642 @@
+//! Tab completion
+//!
+//! Implement [`Completer`] and add it to the editor with
+//! [`crate::builder::EditorBuilder::with_completer`]. Pressing Tab
+//! completes the line with the first candidate, and pressing Tab
+//! again cycles through the remaining candidates. Shift+Tab cycles
+//! backwards, starting from the last candidate. Any other key accepts
+//! the current candidate.
+//!
+//! Candidates returned by [`Completer::complete`] must outlive the
+//! completer. For candidates computed on the fly, use [`FnCompleter`]
+//! or implement [`Completer::complete_into`].
+//!
+//! With [`crate::builder::EditorBuilder::with_completion_preview`],
+//! Tab instead shows the first candidate highlighted after the line,
+//! without accepting it. A second Tab or a printable character
+//! inserts it, and any other key discards it.
+//!
+//! With [`crate::builder::EditorBuilder::with_completion_list`], Tab
+//! completes like bash instead. The first Tab inserts the prefix
+//! shared by all candidates, and a second Tab lists them in columns
+//! below the line. The list is cut off with `...` before it fills the
+//! screen. Candidates are listed with the word being completed in
+//! front of them, and must not contain newlines.
+//!
+//! # Example
+//! ```
+//! use noline::complete::Completer;
+//!
+//! struct Fruit;
+//!
+//! impl Completer for Fruit {
+//!     fn complete(&self, line: &str, index: usize) -> Option<&str> {
+//!         ["apple", "apricot", "banana"]
+//!             .into_iter()
+//!             .filter_map(|fruit| fruit.strip_prefix(line))
+//!             .nth(index)
+//!     }
+//! }
+//!
+//! assert_eq!(Fruit.complete("ap", 0), Some("ple"));
+//! assert_eq!(Fruit.complete("ap", 1), Some("ricot"));
+//! assert_eq!(Fruit.complete("ap", 2), None);
+//! ```
+//!
+//! Files on an SD card, with names computed when listed:
+//! ```
+//! use noline::builder::EditorBuilder;
+//! use noline::complete::FnCompleter;
+//!
+//! let files = FnCompleter::<_, 16>::new(|line, index, out| {
+//!     if index < 10 {
+//!         let name = [b'f', b'i', b'l', b'e', b'0' + index as u8];
+//!
+//!         if let Some(candidate) = core::str::from_utf8(&name)
+//!             .ok()
+//!             .and_then(|name| name.strip_prefix(line))
+//!         {
+//!             out(candidate);
+//!         }
+//!     }
+//! });
+//!
+//! let mut buffer = [0; 64];
+//! let builder = EditorBuilder::from_slice(&mut buffer).with_completer(files);
+//! ```
+
+use crate::{
+    line_buffer::{Buffer, LineBuffer},
+    utf8::str_width,
+};
+
+/// Line being completed, see [`Completer::complete_into`]
+pub trait CompletionTarget {
+    /// Line typed by the user
+    fn line(&self) -> &str;
+
+    /// Append candidate to the line
+    fn append(&mut self, candidate: &str);
+}
+
+/// Provider of completion candidates
+pub trait Completer {
+    /// Return candidate number `index` for `line`, or `None` if there
+    /// are no more candidates. The candidate is the text to append to
+    /// the line, not the complete line.
+    fn complete(&self, line: &str, index: usize) -> Option<&str>;
+
+    /// Append candidate number `index` for the line to `target`.
+    /// Returns false if there are no more candidates.
+    ///
+    /// The editor completes lines with this method. The default
+    /// implementation appends the candidate returned by
+    /// [`Completer::complete`]. Override it for candidates that don't
+    /// outlive the completer.
+    fn complete_into(&self, target: &mut dyn CompletionTarget, index: usize) -> bool {
+        match self.complete(target.line(), index) {
+            Some(candidate) => {
+                target.append(candidate);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Empty completer used for builder
+pub struct NoCompleter {}
+
+impl Completer for NoCompleter {
+    fn complete(&self, _line: &str, _index: usize) -> Option<&str> {
+        None
+    }
+}
+
+/// Completer calling a function for candidates computed on the fly
+///
+/// The function is called with the line, the candidate index and a
+/// callback, and passes the candidate to the callback unless there are
+/// no more candidates. Candidates are copied into a buffer of `N`
+/// bytes, so the callback can be passed temporary strings. Longer
+/// candidates are rejected like candidates not fitting in the line
+/// buffer.
+pub struct FnCompleter<F, const N: usize> {
+    f: F,
+}
+
+impl<F, const N: usize> FnCompleter<F, N>
+where
+    F: Fn(&str, usize, &mut dyn FnMut(&str)),
+{
+    /// Create completer from function
+    pub fn new(f: F) -> Self {
+        Self { f }
+    }
+}
+
+impl<F, const N: usize> Completer for FnCompleter<F, N>
+where
+    F: Fn(&str, usize, &mut dyn FnMut(&str)),
+{
+    /// Always returns `None`, as candidates don't outlive the
+    /// completer. Use [`Completer::complete_into`].
+    fn complete(&self, _line: &str, _index: usize) -> Option<&str> {
+        None
+    }
+
+    fn complete_into(&self, target: &mut dyn CompletionTarget, index: usize) -> bool {
+        let mut candidate = [0; N];
+        let mut found = false;
+        let mut len = None;
+
+        (self.f)(target.line(), index, &mut |s: &str| {
+            found = true;
+
+            if let Some(dest) = candidate.get_mut(..s.len()) {
+                dest.copy_from_slice(s.as_bytes());
+                len = Some(s.len());
+            }
+        });
+
+        if let Some(len) = len {
+            // Copied from a string
+            target.append(unsafe { core::str::from_utf8_unchecked(&candidate[..len]) });
+        }
+
+        found
+    }
+}
+
+// Line buffer as completion target, keeping track of whether the
+// candidate fit
+pub(crate) struct BufferTarget<'a, B: Buffer> {
+    buffer: &'a mut LineBuffer<B>,
+    pub(crate) appended: bool,
+}
+
+impl<'a, B: Buffer> BufferTarget<'a, B> {
+    pub(crate) fn new(buffer: &'a mut LineBuffer<B>) -> Self {
+        Self {
+            buffer,
+            appended: false,
+        }
+    }
+}
+
+impl<B: Buffer> CompletionTarget for BufferTarget<'_, B> {
+    fn line(&self) -> &str {
+        self.buffer.as_str()
+    }
+
+    fn append(&mut self, candidate: &str) {
+        let len = self.buffer.as_str().chars().count();
+
+        self.appended = self.buffer.insert_str(len, candidate).is_ok();
+    }
+}
+
+// Line buffer as completion target, narrowing the text after the line
+// to the prefix shared by the candidates appended so far
+struct PrefixTarget<'a, B: Buffer> {
+    buffer: &'a mut LineBuffer<B>,
+    // Length of the line in bytes and chars
+    line_bytes: usize,
+    line_chars: usize,
+    appended: bool,
+}
+
+impl<B: Buffer> CompletionTarget for PrefixTarget<'_, B> {
+    fn line(&self) -> &str {
+        &self.buffer.as_str()[..self.line_bytes]
+    }
+
+    fn append(&mut self, candidate: &str) {
+        if !self.appended {
+            self.appended = true;
+
+            // Nothing is inserted if the first candidate doesn't fit,
+            // as the shared prefix is unknown
+            let _ = self.buffer.insert_str(self.line_chars, candidate);
+        } else {
+            let shared = self.buffer.as_str()[self.line_bytes..]
+                .chars()
+                .zip(candidate.chars())
+                .take_while(|(a, b)| a == b)
+                .count();
+
+            self.buffer.delete_after_char(self.line_chars + shared);
+        }
+    }
+}
+
+// Line as completion target, copying the candidate from byte `offset`
+// into `dest`, as much as fits
+struct CopyTarget<'a, 'd> {
+    line: &'a str,
+    offset: usize,
+    dest: &'d mut [u8],
+    len: usize,
+}
+
+impl CompletionTarget for CopyTarget<'_, '_> {
+    fn line(&self) -> &str {
+        self.line
+    }
+
+    fn append(&mut self, candidate: &str) {
+        let rest = candidate.as_bytes().get(self.offset..).unwrap_or(&[]);
+
+        self.len = rest.len().min(self.dest.len());
+        self.dest[..self.len].copy_from_slice(&rest[..self.len]);
+    }
+}
+
+// Copy candidate number `index` for the line from byte `offset` into
+// `dest`, as much as fits. Returns the number of bytes copied, or None
+// if there is no such candidate. Candidates computed on the fly are
+// printed this way, as they don't outlive the completer.
+pub(crate) fn copy_candidate<C: Completer + ?Sized>(
+    completer: &C,
+    line: &str,
+    index: usize,
+    offset: usize,
+    dest: &mut [u8],
+) -> Option<usize> {
+    let mut target = CopyTarget {
+        line,
+        offset,
+        dest,
+        len: 0,
+    };
+
+    completer
+        .complete_into(&mut target, index)
+        .then_some(target.len)
+}
+
+// Line as completion target, only counting candidates
+struct CountTarget<'a> {
+    line: &'a str,
+}
+
+impl CompletionTarget for CountTarget<'_> {
+    fn line(&self) -> &str {
+        self.line
+    }
+
+    fn append(&mut self, _candidate: &str) {}
+}
+
+// Line as completion target, measuring the candidate
+struct WidthTarget<'a> {
+    line: &'a str,
+    width: usize,
+}
+
+impl CompletionTarget for WidthTarget<'_> {
+    fn line(&self) -> &str {
+        self.line
+    }
+
+    fn append(&mut self, candidate: &str) {
+        self.width = str_width(candidate);
+    }
+}
+
+// Width of candidate number `index` for the line in columns, or None if
+// there is no such candidate
+pub(crate) fn candidate_width<C: Completer + ?Sized>(
+    completer: &C,
+    line: &str,
+    index: usize,
+) -> Option<usize> {
+    let mut target = WidthTarget { line, width: 0 };
+
+    completer
+        .complete_into(&mut target, index)
+        .then_some(target.width)
+}
+
+// Number of candidates for line
+fn count_candidates<C: Completer + ?Sized>(completer: &C, line: &str) -> usize {
+    let mut target = CountTarget { line };
+    let mut count = 0;
+
+    while completer.complete_into(&mut target, count) {
+        count += 1;
+    }
+
+    count
+}
+
+/// Keeps track of candidates for repeated completion
+#[derive(Default)]
+pub(crate) struct CompletionCycler {
+    // Length of line typed by user in chars, index of current candidate
+    // and number of candidates, or 0 until known. Narrow as the cycler
+    // is part of the line editor.
+    state: Option<(u32, u32, u32)>,
+}
+
+impl CompletionCycler {
+    /// Forget current completion
+    pub(crate) fn reset(&mut self) {
+        self.state = None;
+    }
+
+    // Candidates are cycled from the start again for lines too long to
+    // keep track of
+    fn set_state(&mut self, prefix_len: usize, index: usize, count: usize) {
+        self.state = u32::try_from(prefix_len)
+            .ok()
+            .zip(u32::try_from(index).ok())
+            .map(|(prefix_len, index)| (prefix_len, index, u32::try_from(count).unwrap_or(0)));
+    }
+
+    /// Replace current candidate in buffer with the next
+    /// one. Candidates wrap around after the last one. Return false if
+    /// there are no candidates, or the candidate doesn't fit in the
+    /// buffer.
+    pub(crate) fn complete<B: Buffer, C: Completer + ?Sized>(
+        &mut self,
+        completer: &C,
+        buffer: &mut LineBuffer<B>,
+    ) -> bool {
+        let (prefix_len, index, count) = match self.state {
+            Some((prefix_len, index, count)) => {
+                (prefix_len as usize, index as usize + 1, count as usize)
+            }
+            None => (buffer.as_str().chars().count(), 0, 0),
+        };
+
+        buffer.delete_after_char(prefix_len);
+
+        let mut target = BufferTarget::new(buffer);
+
+        let (index, count) = if completer.complete_into(&mut target, index) {
+            (index, count)
+        } else if index > 0 && completer.complete_into(&mut target, 0) {
+            // Wrapped around, so all candidates have been seen
+            (0, index)
+        } else {
+            return false;
+        };
+
+        self.set_state(prefix_len, index, count);
+
+        target.appended
+    }
+
+    /// Replace current candidate in buffer with the previous one,
+    /// starting from the last one. Candidates wrap around before the
+    /// first one. Return false like [`Self::complete`].
+    pub(crate) fn complete_prev<B: Buffer, C: Completer + ?Sized>(
+        &mut self,
+        completer: &C,
+        buffer: &mut LineBuffer<B>,
+    ) -> bool {
+        let (prefix_len, previous, count) = match self.state {
+            Some((prefix_len, index, count)) => (
+                prefix_len as usize,
+                (index as usize).checked_sub(1),
+                count as usize,
+            ),
+            None => (buffer.as_str().chars().count(), None, 0),
+        };
+
+        buffer.delete_after_char(prefix_len);
+
+        // The last candidate is only known after counting them
+        let (index, count) = match previous {
+            Some(index) => (index, count),
+            None => {
+                let count = match count {
+                    0 => count_candidates(completer, buffer.as_str()),
+                    count => count,
+                };
+
+                match count.checked_sub(1) {
+                    Some(index) => (index, count),
+                    None => return false,
+                }
+            }
+        };
+
+        let mut target = BufferTarget::new(buffer);
+
+        if !completer.complete_into(&mut target, index) {
+            self.state = None;
+            return false;
+        }
+
+        self.set_state(prefix_len, index, count);
+
+        target.appended
+    }
+
+    /// Insert the prefix shared by all candidates after the line,
+    /// which is the whole candidate if there is only one. Returns the
+    /// number of candidates. With more than one, the cycler is left
+    /// active, see [`Self::is_active`].
+    pub(crate) fn complete_prefix<B: Buffer, C: Completer + ?Sized>(
+        &mut self,
+        completer: &C,
+        buffer: &mut LineBuffer<B>,
+    ) -> usize {
+        let line_chars = buffer.as_str().chars().count();
+        let mut target = PrefixTarget {
+            line_bytes: buffer.as_str().len(),
+            buffer,
+            line_chars,
+            appended: false,
+        };
+
+        let mut count = 0;
+
+        while completer.complete_into(&mut target, count) {
+            count += 1;
+        }
+
+        if count > 1 {
+            self.set_state(line_chars, 0, count);
+        } else {
+            self.state = None;
+        }
+
+        count
+    }
+
+    /// True if the last key was a Tab leaving several candidates to
+    /// choose from
+    pub(crate) fn is_active(&self) -> bool {
+        self.state.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fruit;
+
+    impl Completer for Fruit {
+        fn complete(&self, line: &str, index: usize) -> Option<&str> {
+            ["apple", "apricot", "avocado", "banana"]
+                .into_iter()
+                .filter_map(|fruit| fruit.strip_prefix(line))
+                .nth(index)
+        }
+    }
+
+    #[test]
+    fn cycle() {
+        let mut buffer = LineBuffer::new_unbounded();
+        let mut cycler = CompletionCycler::default();
+
+        buffer.insert_str(0, "ap").unwrap();
+
+        assert!(cycler.complete(&Fruit, &mut buffer));
+        assert_eq!(buffer.as_str(), "apple");
+
+        assert!(cycler.complete(&Fruit, &mut buffer));
+        assert_eq!(buffer.as_str(), "apricot");
+
+        assert!(cycler.complete(&Fruit, &mut buffer));
+        assert_eq!(buffer.as_str(), "apple");
+
+        cycler.reset();
+
+        assert!(cycler.complete(&Fruit, &mut buffer));
+        assert_eq!(buffer.as_str(), "apple");
+
+        cycler.reset();
+        buffer.truncate();
+        buffer.insert_str(0, "c").unwrap();
+
+        assert!(!cycler.complete(&Fruit, &mut buffer));
+        assert_eq!(buffer.as_str(), "c");
+    }
+
+    #[test]
+    fn cycle_backwards() {
+        let mut buffer = LineBuffer::new_unbounded();
+        let mut cycler = CompletionCycler::default();
+
+        buffer.insert_str(0, "a").unwrap();
+
+        assert!(cycler.complete_prev(&Fruit, &mut buffer));
+        assert_eq!(buffer.as_str(), "avocado");
+
+        assert!(cycler.complete_prev(&Fruit, &mut buffer));
+        assert_eq!(buffer.as_str(), "apricot");
+
+        assert!(cycler.complete(&Fruit, &mut buffer));
+        assert_eq!(buffer.as_str(), "avocado");
+
+        // Wraps around both ways
+        assert!(cycler.complete(&Fruit, &mut buffer));
+        assert_eq!(buffer.as_str(), "apple");
+
+        assert!(cycler.complete_prev(&Fruit, &mut buffer));
+        assert_eq!(buffer.as_str(), "avocado");
+
+        cycler.reset();
+        buffer.truncate();
+        buffer.insert_str(0, "c").unwrap();
+
+        assert!(!cycler.complete_prev(&Fruit, &mut buffer));
+        assert_eq!(buffer.as_str(), "c");
+    }
+
+    #[test]
+    fn prefix() {
+        let mut buffer = LineBuffer::new_unbounded();
+        let mut cycler = CompletionCycler::default();
+
+        buffer.insert_str(0, "a").unwrap();
+
+        assert_eq!(cycler.complete_prefix(&Fruit, &mut buffer), 3);
+        assert_eq!(buffer.as_str(), "a");
+        assert!(cycler.is_active());
+
+        cycler.reset();
+        buffer.insert_str(1, "pr").unwrap();
+
+        assert_eq!(cycler.complete_prefix(&Fruit, &mut buffer), 1);
+        assert_eq!(buffer.as_str(), "apricot");
+        assert!(!cycler.is_active());
+    }
+
+    #[test]
+    fn candidate_widths() {
+        assert_eq!(candidate_width(&Fruit, "a", 0), Some(4));
+        assert_eq!(candidate_width(&Fruit, "a", 1), Some(6));
+        assert_eq!(candidate_width(&Fruit, "a", 2), Some(6));
+        assert_eq!(candidate_width(&Fruit, "a", 3), None);
+        assert_eq!(candidate_width(&Fruit, "c", 0), None);
+    }
+
+    // Files listed on the fly, like from an SD card
+    fn list_files(line: &str, index: usize, out: &mut dyn FnMut(&str)) {
+        if index < 10 {
+            let name = format!("file{}", index);
+
+            if let Some(candidate) = name.strip_prefix(line) {
+                out(candidate);
+            }
+        }
+    }
+
+    #[test]
+    fn fn_completer() {
+        let files = FnCompleter::<_, 8>::new(list_files);
+        let mut buffer = LineBuffer::new_unbounded();
+        let mut cycler = CompletionCycler::default();
+
+        buffer.insert_str(0, "fi").unwrap();
+
+        for i in 0..10 {
+            assert!(cycler.complete(&files, &mut buffer));
+            assert_eq!(buffer.as_str(), format!("file{}", i));
+        }
+
+        assert!(cycler.complete(&files, &mut buffer));
+        assert_eq!(buffer.as_str(), "file0");
+
+        assert_eq!(files.complete("fi", 0), None);
+
+        cycler.reset();
+        buffer.truncate();
+        buffer.insert_str(0, "x").unwrap();
+
+        assert!(!cycler.complete(&files, &mut buffer));
+        assert_eq!(buffer.as_str(), "x");
+    }
+
+    #[test]
+    fn fn_completer_candidate_too_long() {
+        let long = FnCompleter::<_, 4>::new(|_line, index, out| {
+            if index == 0 {
+                out("toolong");
+            }
+        });
+        let mut buffer = LineBuffer::new_unbounded();
+        let mut cycler = CompletionCycler::default();
+
+        assert!(!cycler.complete(&long, &mut buffer));
+        assert_eq!(buffer.as_str(), "");
+    }
+
+    #[test]
+    fn candidate_too_long() {
+        let mut array = [0; 4];
+        let mut buffer = LineBuffer::from_slice(&mut array);
+        let mut cycler = CompletionCycler::default();
+
+        buffer.insert_str(0, "b").unwrap();
+
+        assert!(!cycler.complete(&Fruit, &mut buffer));
+        assert_eq!(buffer.as_str(), "b");
+    }
+}
@@ -0,0 +1,357 @@
+//! Conformance tests for IO implementations. Requires feature `testing`.
+//!
+//! Runs the editor test cases through an IO implementation connected
+//! to [`MockTerminal`], checking both the lines returned by the editor
+//! and the resulting terminal screen. This catches bugs like missing
+//! flushes or dropped bytes in custom IO wrappers.
+//!
+//! Two drivers are provided:
+//! - [`run_io_conformance`] runs the terminal and the editor in
+//!   separate threads, connected by channels. Use
+//!   [`MockTerminal::take_io`] to get the channels and wrap them in
+//!   your IO implementation.
+//! - [`run_io_conformance_lockstep`] runs everything in the current
+//!   thread, with IO going through a [`MockPort`].
+//!
+//! # Example
+//! ```
+//! use noline::conformance::{run_io_conformance_lockstep, MockPort};
+//! use embedded_io::{ErrorType, Read, Write};
+//!
+//! // IO wrapper under test
+//! struct MyIO {
+//!     port: MockPort,
+//! }
+//!
+//! impl ErrorType for MyIO {
+//!     type Error = embedded_io::ErrorKind;
+//! }
+//!
+//! impl Read for MyIO {
+//!     fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+//!         self.port.read(buf)
+//!     }
+//! }
+//!
+//! impl Write for MyIO {
+//!     fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+//!         self.port.write(buf)
+//!     }
+//!
+//!     fn flush(&mut self) -> Result<(), Self::Error> {
+//!         self.port.flush()
+//!     }
+//! }
+//!
+//! run_io_conformance_lockstep(|port| MyIO { port }).unwrap();
+//! ```
+
+use core::cell::RefCell;
+use core::fmt;
+use core::time::Duration;
+use std::boxed::Box;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::string::{String, ToString};
+use std::sync::mpsc::{channel, Sender};
+use std::thread::{self, JoinHandle};
+use std::vec::Vec;
+
+use embedded_io::{ErrorType, Read, Write};
+
+use crate::builder::EditorBuilder;
+use crate::terminal::Cursor;
+
+pub use crate::testlib::{csi, test_cases, MockTerminal, TestCase, ToByteVec};
+
+const PROMPT: &str = "> ";
+const ROWS: usize = 20;
+const COLUMNS: usize = 80;
+
+/// Failed test case, with expected and actual result
+#[derive(Debug)]
+pub struct ConformanceFailure {
+    /// Index into [`test_cases`]
+    pub case: usize,
+    /// Input sent to the editor. Each entry is followed by a carriage return.
+    pub input: Vec<Vec<u8>>,
+    /// Lines expected from the editor
+    pub expected_lines: Vec<String>,
+    /// Lines returned by the editor
+    pub actual_lines: Vec<String>,
+    /// Expected terminal screen
+    pub expected_screen: String,
+    /// Actual terminal screen
+    pub actual_screen: String,
+    /// Output the terminal doesn't implement, see
+    /// [`MockTerminal::unsupported`]
+    pub unsupported: Vec<String>,
+}
+
+impl fmt::Display for ConformanceFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "test case {} failed", self.case)?;
+        writeln!(f, "input: {:?}", self.input)?;
+        writeln!(
+            f,
+            "lines: expected {:?}, got {:?}",
+            self.expected_lines, self.actual_lines
+        )?;
+        writeln!(f, "expected screen:\n{}", self.expected_screen)?;
+        write!(f, "actual screen:\n{}", self.actual_screen)?;
+
+        if !self.unsupported.is_empty() {
+            write!(f, "\nunsupported output: {:?}", self.unsupported)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn check_case(
+    index: usize,
+    case: TestCase,
+    prompt: &str,
+    lines: Vec<String>,
+    terminal: &mut MockTerminal,
+) -> Result<(), Box<ConformanceFailure>> {
+    let expected_screen = case.screen_as_string(prompt, terminal.columns);
+    let actual_screen = terminal.screen_as_string();
+
+    if lines == case.output && expected_screen == actual_screen && terminal.unsupported.is_empty() {
+        Ok(())
+    } else {
+        Err(Box::new(ConformanceFailure {
+            case: index,
+            input: case.input,
+            expected_lines: case.output,
+            actual_lines: lines,
+            expected_screen,
+            actual_screen,
+            unsupported: core::mem::take(&mut terminal.unsupported),
+        }))
+    }
+}
+
+/// Run single test case with terminal and editor in separate threads
+///
+/// `get_io` is called with the terminal before the terminal thread is
+/// started. `spawn_thread` should spawn a thread running an editor
+/// with the IO, sending every line read to the sender, until
+/// `readline` fails.
+pub fn run_case_threaded<IO: Send + 'static>(
+    index: usize,
+    case: TestCase,
+    prompt: &str,
+    get_io: impl FnOnce(&mut MockTerminal) -> IO,
+    spawn_thread: impl FnOnce(IO, Sender<String>) -> JoinHandle<()>,
+) -> Result<(), Box<ConformanceFailure>> {
+    let (string_tx, string_rx) = channel();
+
+    let mut term = MockTerminal::new(ROWS, COLUMNS, Cursor::new(0, 0));
+
+    let keyboard_tx = term.keyboard_tx.clone();
+
+    let io = get_io(&mut term);
+
+    let term = term.start_thread();
+    let handle = spawn_thread(io, string_tx);
+
+    let mut lines = Vec::new();
+
+    for seq in case.input.iter() {
+        // To avoid race with prompt reset, we need to wait a
+        // little. This is not ideal, but will do for now.
+        thread::sleep(Duration::from_millis(100));
+
+        for &b in seq {
+            keyboard_tx.send(b).unwrap();
+        }
+
+        keyboard_tx.send(0xd).unwrap();
+
+        match string_rx.recv_timeout(Duration::from_secs(1)) {
+            Ok(line) => lines.push(line),
+            Err(_) => break,
+        }
+    }
+
+    // Added delay to prevent race with terminal reset
+    thread::sleep(Duration::from_millis(100));
+
+    keyboard_tx.send(0x3).unwrap();
+
+    drop(keyboard_tx);
+    let mut term = term.join().unwrap();
+
+    handle.join().unwrap();
+
+    check_case(index, case, prompt, lines, &mut term)
+}
+
+/// Run all test cases through IO with [`crate::sync_editor::Editor`],
+/// with terminal and editor in separate threads.
+///
+/// `make_io` is called with a fresh terminal for every test case. Use
+/// [`MockTerminal::take_io`] to get the channels connected to the
+/// terminal.
+pub fn run_io_conformance<IO>(
+    make_io: impl Fn(&mut MockTerminal) -> IO,
+) -> Result<(), Box<ConformanceFailure>>
+where
+    IO: Read + Write + Send + 'static,
+{
+    for (index, case) in test_cases().into_iter().enumerate() {
+        run_case_threaded(index, case, PROMPT, &make_io, |mut io, string_tx| {
+            thread::spawn(move || {
+                let mut editor = EditorBuilder::new_unbounded()
+                    .with_unbounded_history()
+                    .build_sync(&mut io)
+                    .unwrap();
+
+                while let Ok(s) = editor.readline(PROMPT, &mut io) {
+                    if string_tx.send(s.to_string()).is_err() {
+                        break;
+                    }
+                }
+            })
+        })?;
+    }
+
+    Ok(())
+}
+
+struct PortState {
+    terminal: MockTerminal,
+    responses: VecDeque<u8>,
+    keyboard: VecDeque<u8>,
+}
+
+/// Single threaded connection to [`MockTerminal`]
+///
+/// Bytes written are handled by the terminal right away. Reading
+/// returns replies from the terminal first, then keyboard input, and
+/// end of file when both are exhausted. Writing an empty slice panics,
+/// like some drivers do.
+#[derive(Clone)]
+pub struct MockPort {
+    state: Rc<RefCell<PortState>>,
+}
+
+impl MockPort {
+    fn new(terminal: MockTerminal, keyboard: impl IntoIterator<Item = u8>) -> Self {
+        Self {
+            state: Rc::new(RefCell::new(PortState {
+                terminal,
+                responses: VecDeque::new(),
+                keyboard: keyboard.into_iter().collect(),
+            })),
+        }
+    }
+}
+
+impl ErrorType for MockPort {
+    type Error = embedded_io::ErrorKind;
+}
+
+impl Read for MockPort {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let mut state = self.state.borrow_mut();
+        let mut len = 0;
+
+        for place in buf.iter_mut() {
+            let byte = if let Some(byte) = state.responses.pop_front() {
+                byte
+            } else if let Some(byte) = state.keyboard.pop_front() {
+                byte
+            } else {
+                break;
+            };
+
+            *place = byte;
+            len += 1;
+        }
+
+        Ok(len)
+    }
+}
+
+impl Write for MockPort {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        assert!(!buf.is_empty(), "empty write");
+
+        let mut state = self.state.borrow_mut();
+
+        for &b in buf {
+            if let Some(response) = state.terminal.advance(b) {
+                state.responses.extend(response);
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Run single test case in the current thread
+///
+/// `run_editor` should run an editor with IO connected to the
+/// port until `readline` fails, returning the lines read.
+pub fn run_case_lockstep(
+    index: usize,
+    case: TestCase,
+    prompt: &str,
+    run_editor: impl FnOnce(MockPort) -> Vec<String>,
+) -> Result<(), Box<ConformanceFailure>> {
+    let keyboard = case
+        .input
+        .iter()
+        .flat_map(|seq| seq.iter().copied().chain([0xd]))
+        .chain([0x3])
+        .collect::<Vec<u8>>();
+
+    let port = MockPort::new(
+        MockTerminal::new(ROWS, COLUMNS, Cursor::new(0, 0)),
+        keyboard,
+    );
+
+    let lines = run_editor(port.clone());
+
+    let mut state = port.state.borrow_mut();
+
+    check_case(index, case, prompt, lines, &mut state.terminal)
+}
+
+/// Run all test cases through IO with [`crate::sync_editor::Editor`]
+/// in the current thread.
+///
+/// `make_io` is called with a fresh [`MockPort`] for every test case.
+pub fn run_io_conformance_lockstep<IO>(
+    make_io: impl Fn(MockPort) -> IO,
+) -> Result<(), Box<ConformanceFailure>>
+where
+    IO: Read + Write,
+{
+    for (index, case) in test_cases().into_iter().enumerate() {
+        run_case_lockstep(index, case, PROMPT, |port| {
+            let mut io = make_io(port);
+            let mut lines = Vec::new();
+
+            let mut editor = EditorBuilder::new_unbounded()
+                .with_unbounded_history()
+                .build_sync(&mut io)
+                .unwrap();
+
+            while let Ok(s) = editor.readline(PROMPT, &mut io) {
+                lines.push(s.to_string());
+            }
+
+            lines
+        })?;
+    }
+
+    Ok(())
+}
@@ -3,79 +3,266 @@
 //! Use [`Initializer`] to get [`crate::terminal::Terminal`] and then
 //! use [`Line`] to read a single line.
 
-use crate::history::{History, HistoryNavigator};
-use crate::input::{Action, ControlCharacter::*, Parser, CSI};
+use core::num::NonZeroU16;
+use core::ops::Range;
+
+use crate::caps::{TerminalCaps, Vt100};
+use crate::complete::{self, BufferTarget, Completer, CompletionCycler, NoCompleter};
+use crate::error::{InputSequence, NolineError, ProbeFailure};
+use crate::hint::{find_hint, Hint, HintBuffer, Hinter, NoHinter};
+use crate::history::{find_entry, CircularSlice, History, HistoryNavigator};
+use crate::input::{Action, ControlCharacter, ControlCharacter::*, Parser, CSI};
 use crate::line_buffer::Buffer;
 use crate::line_buffer::LineBuffer;
 use crate::output::CursorMove;
-use crate::output::{Output, OutputAction};
-use crate::terminal::{Cursor, Terminal};
+use crate::output::{
+    cell_offset, char_index, format_status, Output, OutputAction, Overlay, Style,
+    WRITE_CHUNK_CAPACITY,
+};
+use crate::read_buffer::DEFAULT_READ_SIZE;
+use crate::strings::{English, Strings};
+use crate::terminal::{Cursor, Position, Terminal};
+use crate::undo::UndoBuffer;
+use crate::utf8::{str_width, to_lowercase, to_uppercase, Utf8Char};
+use crate::validate::{NoValidator, Validator};
 
 use OutputAction::*;
 
+#[derive(Default)]
 enum ResetState {
+    #[default]
     New,
     GetSize,
     GetPosition,
     Done,
 }
 
-pub struct ResetHandle<'line, 'a, B: Buffer, H: History, I> {
-    line: &'line mut Line<'a, B, H, I>,
-    state: ResetState,
+// Printable input kept while probing the terminal
+const TYPE_AHEAD_CAPACITY: usize = 16;
+// Bytes received while probing before giving up on the terminal
+const PROBE_MAX_BYTES: u8 = 64;
+
+// Input received while probing the terminal. Printable chars are
+// replayed when the probe is done, so early typing isn't lost. Other
+// input is discarded. Also replays the key clearing a status message,
+// see [`Line::show_cursor_position`].
+#[derive(Default, Copy, Clone)]
+pub(crate) struct TypeAhead {
+    bytes: [u8; TYPE_AHEAD_CAPACITY],
+    len: u8,
+    read: u8,
+    // Bytes received other than cursor position reports
+    received: u8,
+    // Sequence being parsed, or the first one other than a cursor
+    // position report once `unexpected` is set
+    sequence: InputSequence,
+    unexpected: bool,
 }
 
-impl<'line, 'a, 'item, 'output, B, H, I> ResetHandle<'line, 'a, B, H, I>
+impl TypeAhead {
+    // Keep printable char, if there is room
+    fn push(&mut self, bytes: &[u8]) {
+        if self.read == self.len {
+            self.len = 0;
+            self.read = 0;
+        }
+
+        let len = self.len as usize;
+
+        if len + bytes.len() <= TYPE_AHEAD_CAPACITY {
+            self.bytes[len..len + bytes.len()].copy_from_slice(bytes);
+            self.len += bytes.len() as u8;
+        }
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        (self.read < self.len).then(|| {
+            self.read += 1;
+            self.bytes[self.read as usize - 1]
+        })
+    }
+}
+
+pub struct ResetHandle<
+    'line,
+    'a,
+    B: Buffer,
+    H: History,
+    P: ?Sized,
+    C: Completer + ?Sized,
+    V: Validator + ?Sized,
+    T: Hinter + ?Sized,
+> {
+    line: &'line mut Line<'a, B, H, P, C, V, T>,
+    // Done when the size is known, without printing the prompt
+    size_only: bool,
+}
+
+impl<'line, 'a, 'item: 'a, B, H, P, C, V, T> ResetHandle<'line, 'a, B, H, P, C, V, T>
 where
-    I: Iterator<Item = &'item str> + Clone + 'a,
+    P: PromptSource<'item> + ?Sized,
     B: Buffer,
     H: History,
-    'item: 'output,
+    C: Completer + ?Sized,
+    V: Validator + ?Sized,
+    T: Hinter + ?Sized,
 {
-    fn new(line: &'line mut Line<'a, B, H, I>) -> Self {
+    fn new(line: &'line mut Line<'a, B, H, P, C, V, T>) -> Self {
+        line.reset_state = ResetState::New;
+        line.type_ahead = TypeAhead::default();
+
         Self {
             line,
-            state: ResetState::New,
+            size_only: false,
         }
     }
 
-    pub fn start(&mut self) -> Output<'_, B, I> {
-        assert!(matches!(self.state, ResetState::New));
-        self.state = ResetState::GetSize;
+    pub fn start(&mut self) -> Output<'_, B, P, C, T> {
+        assert!(matches!(self.line.reset_state, ResetState::New));
+
+        // Without probing, the cursor is assumed to be at the start
+        // of a row. Which row doesn't matter with relative movement.
+        if let Some((rows, columns)) = self.line.settings.fixed_size {
+            self.line
+                .terminal
+                .resize(rows.get().into(), columns.get().into());
+            self.line.reset_state = ResetState::Done;
+
+            if self.size_only {
+                return self.line.generate_output(Nothing);
+            }
+
+            self.line.terminal.reset(Cursor::new(0, 0));
+            self.line.prompt.regenerate();
+
+            return self.line.generate_output(ClearAndPrintPrompt);
+        }
+
+        self.line.reset_state = ResetState::GetSize;
 
         self.line.generate_output(ProbeSize)
     }
 
-    pub fn advance(&mut self, byte: u8) -> Option<Output<'_, B, I>> {
+    // True when the probe is done, or was never started because the
+    // terminal size is fixed
+    pub fn is_done(&self) -> bool {
+        self.line.is_reset_done()
+    }
+
+    // Advance probe by one byte. Returns `Ok(None)` when the probe
+    // is done, or an error if the terminal sent too much other input
+    // without replying.
+    pub fn advance(&mut self, byte: u8) -> Result<Option<Output<'_, B, P, C, T>>, ProbeFailure> {
+        let type_ahead = &mut self.line.type_ahead;
+
+        if !type_ahead.unexpected {
+            if self.line.parser.is_ground() {
+                type_ahead.sequence.clear();
+            }
+
+            type_ahead.sequence.push(byte);
+        }
+
         let action = self.line.parser.advance(byte);
 
         match action {
-            Action::ControlSequenceIntroducer(CSI::CPR(x, y)) => match self.state {
+            Action::ControlSequenceIntroducer(CSI::CPR(x, y)) => match self.line.reset_state {
                 ResetState::New => panic!("Invalid state"),
+                ResetState::GetSize if self.size_only => {
+                    self.line.terminal.resize(x, y);
+                    self.line.reset_state = ResetState::Done;
+                    Ok(None)
+                }
                 ResetState::GetSize => {
                     self.line.terminal.resize(x, y);
-                    self.state = ResetState::GetPosition;
-                    Some(self.line.generate_output(ClearAndPrintPrompt))
+                    self.line.reset_state = ResetState::GetPosition;
+                    self.line.prompt.regenerate();
+                    Ok(Some(self.line.generate_output(ClearAndPrintPrompt)))
                 }
                 ResetState::GetPosition => {
                     #[cfg(test)]
                     dbg!(x, y);
+
+                    // The prompt was printed from the start of the row,
+                    // so the column is known. A mismatch means the reply
+                    // was meant for someone else, e.g. another editor
+                    // probing on the same IO.
+                    if x == 0
+                        || x > self.line.terminal.rows()
+                        || y != self.line.terminal.get_cursor().column + 1
+                    {
+                        return Err(ProbeFailure::UnexpectedReply(x, y));
+                    }
                     self.line.terminal.reset(Cursor::new(x - 1, y - 1));
-                    self.state = ResetState::Done;
-                    None
+                    self.line.reset_state = ResetState::Done;
+                    Ok(None)
                 }
                 ResetState::Done => panic!("Invalid state"),
             },
-            Action::Ignore => Some(self.line.generate_output(Nothing)),
-            _ => None,
+            action => {
+                let type_ahead = &mut self.line.type_ahead;
+
+                if let Action::Print(c) = action {
+                    type_ahead.push(c.as_bytes());
+                }
+
+                // Sequences ignored whole, like mouse reports, are
+                // not unexpected
+                if !type_ahead.unexpected && !matches!(action, Action::Ignore) {
+                    type_ahead.unexpected = true;
+                }
+
+                type_ahead.received = type_ahead.received.saturating_add(1);
+
+                if type_ahead.received > PROBE_MAX_BYTES {
+                    return Err(ProbeFailure::UnexpectedInput(type_ahead.sequence));
+                }
+
+                Ok(Some(self.line.generate_output(Nothing)))
+            }
         }
     }
 }
 
-#[cfg_attr(test, derive(Debug))]
+// Prompt used by `readline_default` unless set with the builder
+pub(crate) const DEFAULT_PROMPT: &str = "> ";
+
+// Byte completing a newline pair started by `byte`. Some terminals
+// send CR LF or LF CR for Enter, and the second byte mustn't end the
+// next line as well.
+pub(crate) fn newline_pair(byte: u8) -> Option<u8> {
+    match byte {
+        b'\r' => Some(b'\n'),
+        b'\n' => Some(b'\r'),
+        _ => None,
+    }
+}
+
+// Copy a finished line to the caller's buffer for `readline_into`,
+// returning its length
+pub(crate) fn copy_line(line: &str, out: &mut [u8]) -> Result<usize, NolineError> {
+    let out = out
+        .get_mut(..line.len())
+        .ok_or(NolineError::OutputTooSmall(line.len()))?;
+
+    out.copy_from_slice(line.as_bytes());
+
+    Ok(line.len())
+}
+
+/// Prompt printed before the line, made of one or more parts. Created
+/// from a `&str` or an iterator of `&str`, or with [`Prompt::from_fn`]
+/// for a prompt generated each time it is drawn.
+#[derive(Debug)]
 pub struct Prompt<I> {
     parts: I,
-    len: usize,
+    // Display width of the prompt as last drawn. Kept small, as the
+    // prompt is part of each line on the stack.
+    len: u32,
+    // Length declared by user, parts are printed as is
+    declared: bool,
+    // Generates new parts, for prompts created with `Prompt::from_fn`
+    regenerate: Option<fn(&I) -> I>,
 }
 
 impl<'a, I> Prompt<I>
@@ -84,13 +271,61 @@ where
 {
     fn new(parts: I) -> Self {
         Self {
-            len: parts.clone().map(|part| part.len()).sum(),
+            len: Self::parts_len(&parts),
             parts,
+            declared: false,
+            regenerate: None,
+        }
+    }
+
+    fn parts_len(parts: &I) -> u32 {
+        let len: usize = parts.clone().map(str_width).sum();
+
+        len.try_into().unwrap_or(u32::MAX)
+    }
+
+    // Generate new parts before the prompt is drawn again. The cursor
+    // is placed by char index in the buffer, which doesn't change with
+    // the length of the prompt, so the whole line must be drawn again.
+    pub(crate) fn regenerate(&mut self) {
+        if let Some(regenerate) = self.regenerate {
+            self.parts = regenerate(&self.parts);
+
+            if !self.declared {
+                self.len = Self::parts_len(&self.parts);
+            }
+        }
+    }
+
+    // Use the length of the prompt as drawn by a previous instance,
+    // which may have been generated differently
+    pub(crate) fn set_drawn_len(&mut self, len: usize) {
+        self.len = len as u32;
+    }
+
+    /// Prompt with a display width differing from the number of
+    /// chars, e.g. when it contains escape sequences for colors. The
+    /// parts are printed as is, and must not move the cursor other
+    /// than by printing `len` chars.
+    pub fn with_display_len(parts: impl Into<Self>, len: usize) -> Self {
+        Self {
+            len: len.try_into().unwrap_or(u32::MAX),
+            declared: true,
+            ..parts.into()
         }
     }
 
+    /// Display width of prompt
     pub fn len(&self) -> usize {
-        self.len
+        self.len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub(crate) fn has_display_len(&self) -> bool {
+        self.declared
     }
 }
 
@@ -116,6 +351,59 @@ impl<'a> Iterator for StrIter<'a> {
     }
 }
 
+/// Parts of a prompt created with [`Prompt::from_fn`]
+#[derive(Clone)]
+pub struct PromptFn<F, I> {
+    generate: F,
+    parts: I,
+}
+
+impl<'a, F, I> Iterator for PromptFn<F, I>
+where
+    I: Iterator<Item = &'a str>,
+{
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.parts.next()
+    }
+}
+
+impl<F, I> PromptFn<F, I>
+where
+    F: Fn() -> I + Clone,
+{
+    fn regenerate(&self) -> Self {
+        Self {
+            generate: self.generate.clone(),
+            parts: (self.generate)(),
+        }
+    }
+}
+
+impl<'a, F, I> Prompt<PromptFn<F, I>>
+where
+    F: Fn() -> I + Clone,
+    I: Iterator<Item = &'a str> + Clone,
+{
+    /// Prompt generated by `generate` each time it is drawn: when the
+    /// line starts, when the screen is cleared with Ctrl-L and when the
+    /// line is redrawn, e.g. after the terminal was resized. Between
+    /// draws, the display width of the prompt as drawn is kept, so a
+    /// prompt of another width moves the line only when drawn again.
+    pub fn from_fn(generate: F) -> Self {
+        let parts = PromptFn {
+            parts: generate(),
+            generate,
+        };
+
+        Self {
+            regenerate: Some(PromptFn::regenerate),
+            ..Self::new(parts)
+        }
+    }
+}
+
 impl<'a> From<&'a str> for Prompt<StrIter<'a>> {
     fn from(value: &'a str) -> Self {
         Self::new(StrIter { s: Some(value) })
@@ -131,6 +419,552 @@ where
     }
 }
 
+// What the line needs from a prompt, implemented by [`Prompt`]. The
+// editors hold prompts as `dyn PromptSource`, so the line and its
+// output are compiled once for all prompt types, not once for each.
+// The async editor adds `Send + Sync`, so its futures stay `Send`.
+pub trait PromptSource<'a> {
+    // Part at `index`, or None after the last part
+    fn part(&self, index: usize) -> Option<&'a str>;
+
+    fn len(&self) -> usize;
+
+    fn has_display_len(&self) -> bool;
+
+    fn set_drawn_len(&mut self, len: usize);
+
+    fn regenerate(&mut self);
+}
+
+impl<'a, 'item: 'a, I> PromptSource<'a> for Prompt<I>
+where
+    I: Iterator<Item = &'item str> + Clone,
+{
+    fn part(&self, index: usize) -> Option<&'a str> {
+        self.parts.clone().nth(index)
+    }
+
+    fn len(&self) -> usize {
+        Prompt::len(self)
+    }
+
+    fn has_display_len(&self) -> bool {
+        Prompt::has_display_len(self)
+    }
+
+    fn set_drawn_len(&mut self, len: usize) {
+        Prompt::set_drawn_len(self, len)
+    }
+
+    fn regenerate(&mut self) {
+        Prompt::regenerate(self)
+    }
+}
+
+// Iterator over the parts of a prompt. Each part is found by
+// iterating the parts from the start, which is cheap for the few
+// parts of a prompt.
+pub(crate) struct PromptParts<'a, P: ?Sized> {
+    prompt: &'a P,
+    index: usize,
+}
+
+impl<'a, 'item: 'a, P: PromptSource<'item> + ?Sized> PromptParts<'a, P> {
+    pub(crate) fn new(prompt: &'a P) -> Self {
+        Self { prompt, index: 0 }
+    }
+}
+
+impl<'a, 'item: 'a, P: PromptSource<'item> + ?Sized> Iterator for PromptParts<'a, P> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let part = self.prompt.part(self.index)?;
+        self.index += 1;
+        Some(part)
+    }
+}
+
+/// Policy for tab characters in input, e.g. in pasted text
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TabPolicy {
+    /// Expand tab to the given number of spaces. With 0, tabs are
+    /// dropped.
+    Expand(usize),
+    /// Keep tab in line buffer. The tab is displayed as spaces up to
+    /// the next tab stop, every 8 columns, or the end of the row.
+    Preserve,
+}
+
+impl Default for TabPolicy {
+    fn default() -> Self {
+        Self::Expand(4)
+    }
+}
+
+/// Policy for control characters in input that aren't bound to a
+/// key, like BEL, and for Escape before a character that isn't. Text
+/// pasted without bracketed paste may contain them.
+///
+/// Control characters bound to keys, like Ctrl-A, can't be told from
+/// typed keys, and are always handled as keys. Tabs are handled by
+/// [`TabPolicy`] when there's no completer.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum ControlCharPolicy {
+    /// Ring the bell
+    #[default]
+    Bell,
+    /// Drop them silently, as well as unknown escape sequences. The
+    /// character after Escape is inserted as if typed, if printable.
+    Strip,
+    /// Insert them in caret notation, e.g. `^G` for BEL, and `^[x` for
+    /// Escape before `x`. Unknown escape sequences are dropped.
+    ///
+    /// This is lossy: the line returned holds the caret text as typed
+    /// chars, so BEL and a typed `^G` can't be told apart, and the raw
+    /// bytes can't be recovered.
+    Insert,
+}
+
+/// Policy for text that doesn't fit in the line buffer
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum OverflowPolicy {
+    /// Keep the text that fits, and ring the bell for the rest. Enter
+    /// accepts the truncated line.
+    #[default]
+    Truncate,
+    /// Like [`OverflowPolicy::Truncate`], but Enter rings the bell
+    /// instead of accepting the line, until it's shorter than when
+    /// text was refused. A paste too long for the buffer can then only
+    /// be submitted once the line was edited.
+    RejectLine,
+}
+
+/// Whitespace removed from lines accepted with Enter, before they are
+/// returned and added to history. The line on the screen is left as
+/// typed. Whitespace is as for [`str::trim`].
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum TrimMode {
+    /// Keep lines as typed
+    #[default]
+    None,
+    /// Remove trailing whitespace
+    Trailing,
+    /// Remove leading and trailing whitespace
+    Both,
+}
+
+/// Echo policy for editing output
+///
+/// Terminals with local echo display typed characters themselves, so
+/// noline echoing them as well makes every character appear twice.
+/// The policy only affects output while editing. The prompt and the
+/// final newline are always printed.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum Echo {
+    /// Echo input and all editing output
+    #[default]
+    Full,
+    /// Don't echo anything. Editing still works, but is not visible.
+    None,
+    /// Echo printed text only, suppressing cursor movement, erasing
+    /// and bells. Typing at the end of the line is displayed
+    /// correctly, other editing is not.
+    PrintableOnly,
+    /// The terminal echoes typed printable characters itself, like
+    /// over high latency links with local echo in the application.
+    /// Typing at the end of the line prints nothing, while typing
+    /// anywhere else redraws the rest of the line so the display
+    /// stays correct. Other editing is echoed in full.
+    Local,
+}
+
+/// Key bindings used for editing
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum EditingMode {
+    /// Emacs key bindings
+    #[default]
+    Emacs,
+    /// Vi key bindings. Lines start in insert mode, where the emacs
+    /// key bindings apply. Escape switches to normal mode, taking
+    /// commands: h and l move the cursor, 0 and $ jump to the start
+    /// and end of the line, x deletes the character under the cursor,
+    /// dd clears the line, u undoes the last edit, k and j move
+    /// through history, and i and a return to insert mode before or
+    /// after the cursor.
+    ///
+    /// Escape also starts escape sequences, like those sent by arrow
+    /// keys, so normal mode is entered when the key after Escape
+    /// arrives.
+    Vi,
+}
+
+/// Editor settings passed on to [`Line`]
+#[derive(Default, Copy, Clone)]
+pub(crate) struct Settings {
+    pub(crate) tab: TabPolicy,
+    pub(crate) control_chars: ControlCharPolicy,
+    pub(crate) overflow: OverflowPolicy,
+    pub(crate) trim: TrimMode,
+    pub(crate) echo: Echo,
+    pub(crate) editing_mode: EditingMode,
+    pub(crate) completion_preview: bool,
+    pub(crate) completion_list: bool,
+    pub(crate) bracketed_paste: bool,
+    pub(crate) padded_erase: bool,
+    // Mark the end of the line when an insertion doesn't fit
+    pub(crate) overflow_indicator: bool,
+    // Assumed terminal size (rows, columns), skipping the probe
+    pub(crate) fixed_size: Option<(NonZeroU16, NonZeroU16)>,
+    // Bytes written by the async editor between yields, 0 for never.
+    // Narrower than usize to keep the line editor state small.
+    pub(crate) yield_every: u32,
+    // Most bytes read by a single read call, 0 for the default
+    pub(crate) read_size: u8,
+    // Most bytes written by a single write call of the async editor, 0
+    // for the default
+    pub(crate) write_size: u8,
+    // Text printed by the editor, English if None
+    pub(crate) strings: Option<&'static (dyn Strings + Sync)>,
+    // Control sequences written to the terminal, VT100 if None
+    pub(crate) caps: Option<&'static (dyn TerminalCaps + Sync)>,
+}
+
+impl Settings {
+    pub(crate) fn strings(&self) -> &'static (dyn Strings + Sync) {
+        self.strings.unwrap_or(&English)
+    }
+
+    pub(crate) fn caps(&self) -> &'static (dyn TerminalCaps + Sync) {
+        self.caps.unwrap_or(&Vt100)
+    }
+
+    pub(crate) fn read_size(&self) -> usize {
+        match self.read_size {
+            0 => DEFAULT_READ_SIZE,
+            size => size.into(),
+        }
+    }
+
+    pub(crate) fn write_size(&self) -> usize {
+        match self.write_size {
+            0 => WRITE_CHUNK_CAPACITY,
+            size => size.into(),
+        }
+    }
+
+    // Keep a fixed size in step with a resize, as it isn't probed
+    // when the next line starts
+    pub(crate) fn resize(&mut self, rows: usize, columns: usize) {
+        if self.fixed_size.is_some() {
+            let size = |n: usize| NonZeroU16::new(n.clamp(1, u16::MAX.into()) as u16);
+
+            self.fixed_size = size(rows).zip(size(columns));
+        }
+    }
+}
+
+const SEARCH_HEADER_CAPACITY: usize = 64;
+// Room for the translated prompt and separator, the rest is for the
+// query
+const SEARCH_TEXT_CAPACITY: usize = 32;
+
+// Longest prefix of `s` of at most `len` bytes, ending at a char
+// boundary
+fn truncate_str(s: &str, len: usize) -> &str {
+    let mut end = len.min(s.len());
+
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    &s[..end]
+}
+
+// State of reverse incremental history search
+struct Search {
+    // Prompt, query and separator, printed in place of the prompt
+    header: [u8; SEARCH_HEADER_CAPACITY],
+    prompt_len: u8,
+    query_len: u8,
+    separator_len: u8,
+    // Index of matching history entry. The entry is only drawn while
+    // searching, and copied into the buffer when it's accepted.
+    entry: Option<usize>,
+}
+
+impl Search {
+    fn new(strings: &dyn Strings) -> Self {
+        let prompt = truncate_str(strings.reverse_search_prompt(), SEARCH_TEXT_CAPACITY);
+        let separator = truncate_str(
+            strings.reverse_search_separator(),
+            SEARCH_TEXT_CAPACITY - prompt.len(),
+        );
+
+        let mut search = Self {
+            header: [0; SEARCH_HEADER_CAPACITY],
+            prompt_len: prompt.len() as u8,
+            query_len: 0,
+            separator_len: separator.len() as u8,
+            entry: None,
+        };
+
+        search.header[..prompt.len()].copy_from_slice(prompt.as_bytes());
+        search.header[prompt.len()..(prompt.len() + separator.len())]
+            .copy_from_slice(separator.as_bytes());
+
+        search
+    }
+
+    // Byte index of the end of the query
+    fn query_end(&self) -> usize {
+        (self.prompt_len + self.query_len) as usize
+    }
+
+    fn header(&self) -> &str {
+        let len = self.query_end() + self.separator_len as usize;
+
+        // Header is only ever written from strings and whole chars
+        unsafe { core::str::from_utf8_unchecked(&self.header[..len]) }
+    }
+
+    fn query(&self) -> &str {
+        &self.header()[(self.prompt_len as usize)..self.query_end()]
+    }
+
+    fn push(&mut self, c: Utf8Char) -> bool {
+        let bytes = c.as_bytes();
+        let start = self.query_end();
+        let separator = start..(start + self.separator_len as usize);
+
+        if separator.end + bytes.len() > SEARCH_HEADER_CAPACITY {
+            return false;
+        }
+
+        self.header.copy_within(separator, start + bytes.len());
+        self.header[start..(start + bytes.len())].copy_from_slice(bytes);
+        self.query_len += bytes.len() as u8;
+
+        true
+    }
+
+    fn pop(&mut self) -> bool {
+        if let Some(c) = self.query().chars().next_back() {
+            let end = self.query_end();
+            let separator = end..(end + self.separator_len as usize);
+
+            self.header.copy_within(separator, end - c.len_utf8());
+            self.query_len -= c.len_utf8() as u8;
+
+            true
+        } else {
+            false
+        }
+    }
+}
+
+// History entry at index shown by [`Line::history_peek`], empty
+// unless it's valid UTF-8
+fn peek_slices<H: History + ?Sized>(history: &H, index: usize) -> (&[u8], &[u8]) {
+    history
+        .get_entry(index)
+        .filter(CircularSlice::is_utf8)
+        .map_or((&[], &[]), |entry| entry.get_slices())
+}
+
+// Bytes and length fit in 16 bytes, leaving room for the tag of
+// [`DisplayOverride`] in the padding of [`Stale`]
+const KEY_RECORD_CAPACITY: usize = 15;
+
+// Bytes of the key in progress, replayed if the key isn't handled
+// where it was typed. A key that doesn't fit isn't handled where it
+// was typed, so its last bytes are left to the parser, see
+// [`KeyRecord::is_full`].
+#[derive(Copy, Clone)]
+struct KeyRecord {
+    bytes: [u8; KEY_RECORD_CAPACITY],
+    len: u8,
+}
+
+impl KeyRecord {
+    fn new() -> Self {
+        Self {
+            bytes: [0; KEY_RECORD_CAPACITY],
+            len: 0,
+        }
+    }
+
+    fn record(&mut self, byte: u8) {
+        if let Some(place) = self.bytes.get_mut(self.len as usize) {
+            *place = byte;
+            self.len += 1;
+        }
+    }
+
+    // No navigation key or hint key is this long, so the override
+    // ends while the key is still in progress. The parser holds the
+    // bytes read so far and handles the key as usual.
+    fn is_full(&self) -> bool {
+        self.len as usize == KEY_RECORD_CAPACITY
+    }
+
+    fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    fn bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+// Navigation keys handled without output while more input is ready,
+// see [`Line::set_input_pending`]. The screen shows the line as it
+// was before the first of them.
+#[derive(Copy, Clone)]
+struct Stale {
+    // Char index the cursor is moved to when the line is drawn
+    cursor: usize,
+    // The buffer changed, so the whole line is drawn again
+    redraw: bool,
+    // Replayed if it isn't navigation
+    key: KeyRecord,
+}
+
+impl Stale {
+    fn new(cursor: usize) -> Self {
+        Self {
+            cursor,
+            redraw: false,
+            key: KeyRecord::new(),
+        }
+    }
+
+    // Buffer replaced, with cursor at the end of it
+    fn replaced(&mut self, len: usize) -> bool {
+        self.redraw = true;
+        self.cursor = len;
+        true
+    }
+}
+
+// Text displayed in place of the end of the line without being in
+// the buffer, see [`OutputAction::PrintOverride`]
+#[derive(Copy, Clone)]
+enum DisplayOverride {
+    // First completion candidate shown after the line
+    Completion {
+        cursor: usize,
+        // Replayed if it doesn't commit the candidate
+        key: KeyRecord,
+    },
+    // History entry shown in place of the line, with the line left
+    // in the buffer
+    History {
+        index: usize,
+        cursor: usize,
+    },
+    // Status message shown after the line until the next key
+    Status {
+        cursor: usize,
+    },
+    // Line not drawn since navigation keys changed it
+    Stale(Stale),
+    // Hint shown after the line, with the cursor at the end of the
+    // line. The hint is only inserted into the buffer when accepted.
+    Hint {
+        // Replayed if it doesn't accept the hint
+        key: KeyRecord,
+    },
+}
+
+// State of bracketed paste. While pasting, newlines are inserted as
+// spaces and other control characters are dropped.
+#[derive(Default, Copy, Clone, PartialEq, Eq)]
+enum Paste {
+    #[default]
+    Off,
+    Active,
+    // CR pasted, a following LF is part of the same newline
+    AfterCarriageReturn,
+}
+
+// Mode of the vi key bindings, always insert for emacs
+#[derive(Default, Copy, Clone, PartialEq, Eq)]
+enum ViMode {
+    #[default]
+    Insert,
+    Normal,
+    // d typed in normal mode, waiting for the second d
+    Delete,
+}
+
+// Last word of a history entry inserted by Alt-., ending at the
+// cursor. Pressing Alt-. again replaces it with the last word of an
+// older entry. Fields are small to fit in the padding of [`Line`].
+#[derive(Copy, Clone)]
+struct LastArg {
+    index: u16,
+    // Length of the word in chars
+    len: u16,
+}
+
+// Fixed-format template for the line, see [`Line::set_mask`]
+#[derive(Copy, Clone)]
+pub(crate) struct Mask<'a> {
+    template: &'a str,
+    // Template char marking cells to fill in
+    fill: char,
+    // Accept char for cell at char index
+    validator: fn(char, usize) -> bool,
+}
+
+impl<'a> Mask<'a> {
+    pub(crate) fn new(template: &'a str, fill: char, validator: fn(char, usize) -> bool) -> Self {
+        Self {
+            template,
+            fill,
+            validator,
+        }
+    }
+
+    fn is_cell(&self, index: usize) -> bool {
+        self.template.chars().nth(index) == Some(self.fill)
+    }
+}
+
+// State of a line kept between instances of [`Line`], for reading a
+// line incrementally. See [`Line::with_state`].
+#[derive(Default)]
+pub(crate) struct LineState {
+    reset_state: ResetState,
+    parser: Parser,
+    history_position: Option<usize>,
+    history_prefix_len: u32,
+    cycler: CompletionCycler,
+    search: Option<Search>,
+    display_override: Option<DisplayOverride>,
+    overwrite: bool,
+    ctrl_x: bool,
+    vi_mode: ViMode,
+    paste: Paste,
+    type_ahead: TypeAhead,
+    last_arg: Option<LastArg>,
+    rejected_len: Option<usize>,
+    // Display width of the prompt as drawn, unknown for a new line
+    prompt_len: Option<usize>,
+}
+
+impl LineState {
+    pub(crate) fn is_reset_done(&self) -> bool {
+        matches!(self.reset_state, ResetState::Done)
+    }
+
+    // See [`Line::take_type_ahead`]
+    pub(crate) fn take_type_ahead(&mut self) -> Option<u8> {
+        self.type_ahead.pop()
+    }
+}
+
 // State machine for reading single line.
 //
 // Provide input by calling [`Line::advance`], returning
@@ -140,818 +974,5425 @@ where
 // line, get cursor position and print prompt. Call [`Line::advance`]
 // for each byte read from input and print bytes from
 // [`crate::output::Output`] to output.
-pub struct Line<'a, B: Buffer, H: History, I> {
+pub struct Line<
+    'a,
+    B: Buffer,
+    H: History,
+    P: ?Sized,
+    C: Completer + ?Sized = NoCompleter,
+    V: Validator + ?Sized = NoValidator,
+    T: Hinter + ?Sized = NoHinter,
+> {
     buffer: &'a mut LineBuffer<B>,
     terminal: &'a mut Terminal,
+    reset_state: ResetState,
     parser: Parser,
-    prompt: Prompt<I>,
+    prompt: &'a mut P,
     nav: HistoryNavigator<'a, H>,
+    settings: Settings,
+    completer: Option<&'a C>,
+    // Checks the line before accepting it
+    validator: Option<&'a V>,
+    // Shows hints after text typed at the end of the line
+    hinter: Option<&'a T>,
+    // Text deleted by the last kill, inserted by Ctrl-Y
+    kill: Option<&'a mut LineBuffer<B>>,
+    // Edits undone by Ctrl-_
+    undo: Option<&'a mut UndoBuffer<B>>,
+    // Line typed before navigating history
+    stash: Option<&'a mut LineBuffer<B>>,
+    cycler: CompletionCycler,
+    search: Option<Search>,
+    display_override: Option<DisplayOverride>,
+    // Printable characters replace the character under the cursor
+    // instead of being inserted. Toggled by Insert.
+    overwrite: bool,
+    // Ctrl-X typed, the next key completes the chord
+    ctrl_x: bool,
+    vi_mode: ViMode,
+    // More input is ready after the byte passed to [`Line::advance`]
+    input_pending: bool,
+    paste: Paste,
+    type_ahead: TypeAhead,
+    last_arg: Option<LastArg>,
+    // Length of the line when text was refused with
+    // [`OverflowPolicy::RejectLine`]. Enter is refused until the line
+    // is shorter.
+    rejected_len: Option<usize>,
+    rprompt: &'a str,
+    // Input is constrained to the cells of the template
+    mask: Option<&'a Mask<'a>>,
 }
 
-impl<'a, 'item, 'output, B: Buffer, H: History, I> Line<'a, B, H, I>
-where
-    I: Iterator<Item = &'item str> + Clone + 'a,
-    'item: 'output,
-    'a: 'output,
+impl<
+        'a,
+        'item: 'a,
+        B: Buffer,
+        H: History,
+        P: PromptSource<'item> + ?Sized,
+        C: Completer + ?Sized,
+        V: Validator + ?Sized,
+        T: Hinter + ?Sized,
+    > Line<'a, B, H, P, C, V, T>
 {
     pub fn new(
-        prompt: impl Into<Prompt<I>>,
+        prompt: &'a mut P,
         buffer: &'a mut LineBuffer<B>,
         terminal: &'a mut Terminal,
         history: &'a mut H,
+        settings: Settings,
     ) -> Self {
+        terminal.set_padded_erase(settings.padded_erase);
+        terminal.set_relative_movement(settings.fixed_size.is_some());
+        terminal.set_caps(settings.caps());
+
         Self {
             buffer,
             terminal,
+            reset_state: ResetState::New,
             parser: Parser::new(),
-            prompt: prompt.into(),
+            prompt,
             nav: HistoryNavigator::new(history),
+            settings,
+            completer: None,
+            validator: None,
+            hinter: None,
+            kill: None,
+            undo: None,
+            stash: None,
+            cycler: CompletionCycler::default(),
+            search: None,
+            display_override: None,
+            overwrite: false,
+            ctrl_x: false,
+            vi_mode: ViMode::Insert,
+            input_pending: false,
+            paste: Paste::Off,
+            type_ahead: TypeAhead::default(),
+            last_arg: None,
+            rejected_len: None,
+            rprompt: "",
+            mask: None,
         }
     }
 
-    // Truncate buffer, clear line and print prompt
-    pub fn reset(&mut self) -> ResetHandle<'_, 'a, B, H, I> {
-        self.buffer.truncate();
-        ResetHandle::new(self)
+    // Complete line with completer on Tab, instead of inserting tab
+    pub fn with_completer(mut self, completer: Option<&'a C>) -> Self {
+        self.completer = completer;
+        self
     }
 
-    fn generate_output(&mut self, action: OutputAction) -> Output<'_, B, I> {
-        Output::new(&self.prompt, self.buffer, self.terminal, action)
+    // Ring the bell instead of accepting the line if the validator
+    // rejects it
+    pub fn with_validator(mut self, validator: Option<&'a V>) -> Self {
+        self.validator = validator;
+        self
     }
 
-    fn current_position(&self) -> usize {
-        let pos = self.terminal.current_offset() as usize;
-        pos - self.prompt.len()
+    // Show hints after chars typed at the end of the line
+    pub fn with_hinter(mut self, hinter: Option<&'a T>) -> Self {
+        self.hinter = hinter;
+        self
     }
 
-    fn history_move_up(&mut self) -> Output<'_, B, I> {
-        let entry = if self.nav.is_active() {
-            self.nav.move_up()
-        } else if self.buffer.len() == 0 {
-            self.nav.reset();
-            self.nav.move_up()
-        } else {
-            Err(())
-        };
+    // Keep text deleted by Ctrl-K, Ctrl-U and Ctrl-W in `kill`, to be
+    // inserted again with Ctrl-Y. Without a kill buffer, Ctrl-Y rings
+    // the bell.
+    pub fn with_kill_buffer(mut self, kill: Option<&'a mut LineBuffer<B>>) -> Self {
+        self.kill = kill;
+        self
+    }
 
-        if let Ok(entry) = entry {
-            let (slice1, slice2) = entry.get_slices();
+    // Record edits in `undo`, to be undone with Ctrl-_ or Ctrl-X Ctrl-U.
+    // Without an undo buffer, these ring the bell.
+    pub fn with_undo_buffer(mut self, undo: Option<&'a mut UndoBuffer<B>>) -> Self {
+        self.undo = undo;
+        self
+    }
 
-            self.buffer.truncate();
-            unsafe {
-                self.buffer.insert_bytes(0, slice1).unwrap();
-                self.buffer.insert_bytes(slice1.len(), slice2).unwrap();
-            }
+    // Keep the line in `stash` when navigating history from a line
+    // with text, restoring it after the newest entry. Without a stash,
+    // history is only navigated from an empty line, or with a prefix
+    // from the end of the line.
+    pub fn with_stash_buffer(mut self, stash: Option<&'a mut LineBuffer<B>>) -> Self {
+        self.stash = stash;
+        self
+    }
 
-            self.generate_output(ClearAndPrintBuffer)
-        } else {
-            self.generate_output(RingBell)
+    // Prompt shown at the right edge of the first row, until the line
+    // reaches it. Call [`Line::print_rprompt`] when the reset is done.
+    pub fn with_rprompt(mut self, rprompt: &'a str) -> Self {
+        self.rprompt = rprompt;
+        self
+    }
+
+    // Fill the buffer with the template, truncated if it doesn't fit.
+    // Printable chars overwrite the cells, and the cursor skips the
+    // other chars of the template. Use [`Line::reset_preserving_buffer`]
+    // and call [`Line::print_buffer`] when the reset is done.
+    pub(crate) fn set_mask(&mut self, mask: &'a Mask<'a>) {
+        // The template is valid UTF-8
+        unsafe {
+            self.buffer
+                .replace_with_slices(mask.template.as_bytes(), &[]);
         }
+
+        self.mask = Some(mask);
     }
 
-    fn history_move_down(&mut self) -> Output<'_, B, I> {
-        let entry = if self.nav.is_active() {
-            self.nav.move_down()
-        } else {
-            return self.generate_output(RingBell);
-        };
+    // Continue with state from a previous instance, see
+    // [`Line::into_state`]
+    pub(crate) fn with_state(mut self, state: LineState) -> Self {
+        self.reset_state = state.reset_state;
+        self.parser = state.parser;
+        self.nav
+            .restore_position(state.history_position, state.history_prefix_len);
+        self.cycler = state.cycler;
+        self.search = state.search;
+        self.display_override = state.display_override;
+        self.overwrite = state.overwrite;
+        self.ctrl_x = state.ctrl_x;
+        self.vi_mode = state.vi_mode;
+        self.paste = state.paste;
+        self.type_ahead = state.type_ahead;
+        self.last_arg = state.last_arg;
+        self.rejected_len = state.rejected_len;
+
+        if let Some(len) = state.prompt_len {
+            self.prompt.set_drawn_len(len);
+        }
 
-        if let Ok(entry) = entry {
-            let (slice1, slice2) = entry.get_slices();
+        self
+    }
 
-            self.buffer.truncate();
-            unsafe {
-                self.buffer.insert_bytes(0, slice1).unwrap();
-                self.buffer.insert_bytes(slice1.len(), slice2).unwrap();
+    pub(crate) fn into_state(self) -> LineState {
+        LineState {
+            reset_state: self.reset_state,
+            parser: self.parser,
+            history_position: self.nav.position(),
+            history_prefix_len: self.nav.prefix_len(),
+            cycler: self.cycler,
+            search: self.search,
+            display_override: self.display_override,
+            overwrite: self.overwrite,
+            ctrl_x: self.ctrl_x,
+            vi_mode: self.vi_mode,
+            paste: self.paste,
+            type_ahead: self.type_ahead,
+            last_arg: self.last_arg,
+            rejected_len: self.rejected_len,
+            prompt_len: Some(self.prompt.len()),
+        }
+    }
+
+    // Tell if more input is ready after the next byte. While it is,
+    // repeated navigation keys are applied without drawing the line,
+    // which is drawn once when the input runs out or another key is
+    // typed. A stuck arrow key then doesn't flood the output.
+    pub(crate) fn set_input_pending(&mut self, pending: bool) {
+        self.input_pending = pending;
+    }
+
+    // Next byte typed while probing the terminal, to be passed to
+    // [`Line::advance`] before reading more input
+    pub(crate) fn take_type_ahead(&mut self) -> Option<u8> {
+        self.type_ahead.pop()
+    }
+
+    pub(crate) fn has_type_ahead(&self) -> bool {
+        self.type_ahead.read < self.type_ahead.len
+    }
+
+    // True when the reset is done and input is handled by
+    // [`Line::advance`]
+    pub(crate) fn is_reset_done(&self) -> bool {
+        matches!(self.reset_state, ResetState::Done)
+    }
+
+    // Continue reset started by [`Line::reset`] in a previous instance
+    pub(crate) fn resume_reset(&mut self) -> ResetHandle<'_, 'a, B, H, P, C, V, T> {
+        ResetHandle {
+            line: self,
+            size_only: false,
+        }
+    }
+
+    // Probe the size of the terminal, leaving the cursor and the line
+    // as they are. Input typed while probing is dropped with the line.
+    pub(crate) fn probe_size(&mut self) -> ResetHandle<'_, 'a, B, H, P, C, V, T> {
+        ResetHandle {
+            size_only: true,
+            ..ResetHandle::new(self)
+        }
+    }
+
+    // Truncate buffer, clear line and print prompt
+    pub fn reset(&mut self) -> ResetHandle<'_, 'a, B, H, P, C, V, T> {
+        self.buffer.truncate();
+        self.clear_undo();
+        ResetHandle::new(self)
+    }
+
+    // Like [`Line::reset`], but keep buffer content from the previous
+    // line. Call [`Line::print_buffer`] when the reset is done to
+    // redraw the buffer with the cursor at the end.
+    pub fn reset_preserving_buffer(&mut self) -> ResetHandle<'_, 'a, B, H, P, C, V, T> {
+        self.clear_undo();
+        ResetHandle::new(self)
+    }
+
+    pub fn print_buffer(&mut self) -> Output<'_, B, P, C, T> {
+        match self.mask {
+            // Cursor starts at the first cell
+            Some(mask) => {
+                let first = self.next_cell(mask, 0);
+                self.generate_output(ClearOverride(0, first))
             }
-        } else {
-            self.nav.reset();
-            self.buffer.truncate();
+            None => self.generate_output(ClearAndPrintBuffer),
         }
+    }
 
-        self.generate_output(ClearAndPrintBuffer)
+    pub fn print_rprompt(&mut self) -> Output<'_, B, P, C, T> {
+        self.generate_output(PrintRprompt)
     }
 
-    // Advance state machine by one byte. Returns output iterator over
-    // 0 or more byte slices.
-    pub(crate) fn advance(&mut self, byte: u8) -> Output<'_, B, I> {
-        let action = self.parser.advance(byte);
+    // Print prompt and buffer again, e.g. after the terminal was
+    // resized with [`Terminal::resize`]. The cursor is left at the end
+    // of the line, use [`Line::restore_cursor`] to move it back.
+    pub fn redraw(&mut self) -> Output<'_, B, P, C, T> {
+        self.search_output()
+    }
 
-        #[cfg(test)]
-        dbg!(action);
+    // Char index of first cell at or after `from`, or end of line
+    fn next_cell(&self, mask: &Mask, from: usize) -> usize {
+        let len = self.buffer.as_str().chars().count();
+
+        (from..len).find(|&i| mask.is_cell(i)).unwrap_or(len)
+    }
+
+    fn previous_cell(&self, mask: &Mask, before: usize) -> Option<usize> {
+        (0..before).rev().find(|&i| mask.is_cell(i))
+    }
+
+    // Movement skips the template, printable chars overwrite cells and
+    // deleting resets cells to the fill char, without shifting the rest
+    // of the line.
+    fn handle_mask_action(&mut self, action: Action) -> Output<'_, B, P, C, T> {
+        let Some(mask) = self.mask else {
+            return self.generate_output(RingBell);
+        };
+        let pos = self.current_position();
+        let len = self.buffer.as_str().chars().count();
+        let mut fill = [0; 4];
+        let fill = &*mask.fill.encode_utf8(&mut fill);
 
         match action {
             Action::Print(c) => {
-                let pos = self.current_position();
-
-                if self.buffer.insert_utf8_char(pos, c).is_ok() {
-                    self.generate_output(PrintBufferAndMoveCursorForward)
+                let mut bytes = [0; 4];
+
+                if pos < len
+                    && mask.is_cell(pos)
+                    && (mask.validator)(c.as_char(), pos)
+                    && self.replace_char(pos, c.as_char().encode_utf8(&mut bytes))
+                {
+                    let next = self.next_cell(mask, pos + 1);
+                    self.generate_output(PrintBufferAndMoveCursorForward(next - pos))
                 } else {
                     self.generate_output(RingBell)
                 }
             }
-            Action::ControlCharacter(c) => match c {
-                CtrlA => self.generate_output(MoveCursor(CursorMove::Start)),
-                CtrlB => self.generate_output(MoveCursor(CursorMove::Back)),
-                CtrlC => self.generate_output(Abort),
-                CtrlD => {
-                    let len = self.buffer.len();
+            Action::ControlCharacter(CtrlH | Backspace) => match self.previous_cell(mask, pos) {
+                Some(previous) if self.replace_char(previous, fill) => self.generate_output(
+                    MoveCursorAndEraseAndPrintBuffer(previous as isize - pos as isize),
+                ),
+                _ => self.generate_output(RingBell),
+            },
+            Action::ControlCharacter(CtrlD)
+            | Action::ControlSequenceIntroducer(CSI::Delete | CSI::DeleteMod(_)) => {
+                if pos < len && mask.is_cell(pos) && self.replace_char(pos, fill) {
+                    self.generate_output(EraseAndPrintBuffer)
+                } else {
+                    self.generate_output(RingBell)
+                }
+            }
+            Action::ControlCharacter(CtrlA)
+            | Action::ControlSequenceIntroducer(CSI::Home | CSI::HomeMod(_) | CSI::CUP(1, _)) => {
+                let first = self.next_cell(mask, 0);
+                self.move_cursor_to(pos, first)
+            }
+            Action::ControlCharacter(CtrlB) | Action::ControlSequenceIntroducer(CSI::CUB(_)) => {
+                match self.previous_cell(mask, pos) {
+                    Some(previous) => self.move_cursor_to(pos, previous),
+                    None => self.generate_output(RingBell),
+                }
+            }
+            Action::ControlCharacter(CtrlE)
+            | Action::ControlSequenceIntroducer(CSI::End | CSI::EndMod(_)) => {
+                self.move_cursor_to(pos, len)
+            }
+            Action::ControlCharacter(CtrlF) | Action::ControlSequenceIntroducer(CSI::CUF(_)) => {
+                let next = self.next_cell(mask, pos + 1);
+                self.move_cursor_to(pos, next.max(pos))
+            }
+            Action::ControlCharacter(CarriageReturn | LineFeed) => self.generate_output(Done),
+            _ => self.generate_output(RingBell),
+        }
+    }
 
-                    if len > 0 {
-                        let pos = self.current_position();
+    // Actions handled as usual when reading a template
+    fn passes_mask(action: Action) -> bool {
+        matches!(
+            action,
+            Action::Ignore
+                | Action::ControlCharacter(CtrlC)
+                | Action::ControlSequenceIntroducer(
+                    CSI::CPR(_, _) | CSI::PasteStart | CSI::PasteEnd
+                )
+        )
+    }
 
-                        if pos < len {
-                            self.buffer.delete(pos);
+    fn move_word_back(&mut self) -> Output<'_, B, P, C, T> {
+        let pos = self.current_position();
+        let word_start = self.buffer.previous_word_start(pos);
 
-                            self.generate_output(EraseAndPrintBuffer)
-                        } else {
-                            self.generate_output(RingBell)
-                        }
-                    } else {
-                        self.generate_output(Abort)
-                    }
-                }
-                CtrlE => self.generate_output(MoveCursor(CursorMove::End)),
-                CtrlF => self.generate_output(MoveCursor(CursorMove::Forward)),
-                CtrlK => {
-                    let pos = self.current_position();
+        self.move_cursor_to(pos, word_start)
+    }
 
-                    self.buffer.delete_after_char(pos);
+    fn move_word_forward(&mut self) -> Output<'_, B, P, C, T> {
+        let pos = self.current_position();
+        let word_end = self.buffer.next_word_end(pos);
 
-                    self.generate_output(EraseAfterCursor)
-                }
-                CtrlL => {
-                    self.buffer.delete_after_char(0);
-                    self.generate_output(ClearScreen)
-                }
-                CtrlN => self.history_move_down(),
-                CtrlP => self.history_move_up(),
-                CtrlT => {
-                    let pos = self.current_position();
+        self.move_cursor_to(pos, word_end)
+    }
 
-                    if pos > 0 && pos < self.buffer.as_str().chars().count() {
-                        self.buffer.swap_chars(pos);
-                        self.generate_output(MoveCursorBackAndPrintBufferAndMoveForward)
-                    } else {
-                        self.generate_output(RingBell)
-                    }
-                }
-                CtrlU => {
-                    self.buffer.delete_after_char(0);
-                    self.generate_output(ClearLine)
-                }
-                CtrlW => {
-                    let pos = self.current_position();
-                    let move_cursor = -(self.buffer.delete_previous_word(pos) as isize);
-                    self.generate_output(MoveCursorAndEraseAndPrintBuffer(move_cursor))
-                }
-                CarriageReturn | LineFeed => {
-                    if self.buffer.len() > 0 {
-                        let _ = self.nav.history.add_entry(self.buffer.as_str());
-                    }
+    fn move_next_word_start(&mut self) -> Output<'_, B, P, C, T> {
+        let pos = self.current_position();
+        let word_start = self.buffer.next_word_start(pos);
 
-                    self.generate_output(Done)
-                }
-                CtrlH | Backspace => {
-                    let pos = self.current_position();
-                    if pos > 0 {
-                        self.buffer.delete(pos - 1);
-                        self.generate_output(MoveCursorAndEraseAndPrintBuffer(-1))
-                    } else {
-                        self.generate_output(RingBell)
-                    }
-                }
-                _ => self.generate_output(RingBell),
-            },
-            Action::ControlSequenceIntroducer(csi) => match csi {
-                CSI::CUF(_) => self.generate_output(MoveCursor(CursorMove::Forward)),
-                CSI::CUB(_) => self.generate_output(MoveCursor(CursorMove::Back)),
-                CSI::Home => self.generate_output(MoveCursor(CursorMove::Start)),
-                CSI::Delete => {
-                    let len = self.buffer.len();
-                    let pos = self.current_position();
+        self.move_cursor_to(pos, word_start)
+    }
 
-                    if pos < len {
-                        self.buffer.delete(pos);
+    // Move cursor to the start of the line, e.g. to draw a line
+    // abandoned midway again from the start
+    pub fn move_to_start(&mut self) -> Output<'_, B, P, C, T> {
+        self.generate_output(MoveCursor(CursorMove::Start))
+    }
 
-                        self.generate_output(EraseAndPrintBuffer)
-                    } else {
-                        self.generate_output(RingBell)
-                    }
-                }
-                CSI::End => self.generate_output(MoveCursor(CursorMove::End)),
-                CSI::CPR(row, column) => {
-                    let cursor = Cursor::new(row - 1, column - 1);
-                    self.terminal.reset(cursor);
-                    self.generate_output(Nothing)
-                }
-                CSI::Unknown(_) => self.generate_output(RingBell),
-                CSI::CUU(_) => self.history_move_up(),
-                CSI::CUD(_) => self.history_move_down(),
-                CSI::CUP(_, _) => self.generate_output(RingBell),
-                CSI::ED(_) => self.generate_output(RingBell),
-                CSI::DSR => self.generate_output(RingBell),
-                CSI::SU(_) => self.generate_output(RingBell),
-                CSI::SD(_) => self.generate_output(RingBell),
-            },
-            Action::EscapeSequence(_) => self.generate_output(RingBell),
-            Action::Ignore => self.generate_output(Nothing),
-            Action::InvalidUtf8 => self.generate_output(RingBell),
+    // Erase prompt and buffer, leaving the cursor where the prompt
+    // started
+    pub fn erase(&mut self) -> Output<'_, B, P, C, T> {
+        self.generate_output(EraseLine)
+    }
+
+    // Start the line again below `text`, printed after [`Line::erase`]
+    // with a newline after each of its lines. Each line takes at least
+    // one row, and the terminal scrolls if the rows run out.
+    pub fn start_below(&mut self, text: &str) {
+        let columns = self.terminal.columns();
+        let rows: usize = text
+            .split('\n')
+            .map(|line| str_width(line).div_ceil(columns).max(1))
+            .sum();
+        let row = (self.terminal.get_cursor().row + rows).min(self.terminal.rows() - 1);
+
+        self.terminal.reset(Cursor::new(row, 0));
+    }
+
+    // Move cursor back to char index `pos` after [`Line::redraw`]
+    pub fn restore_cursor(&mut self, pos: usize) -> Output<'_, B, P, C, T> {
+        if self.search.is_some() {
+            return self.generate_output(Nothing);
         }
+
+        let steps = pos as isize - self.current_position() as isize;
+
+        self.generate_output(MoveCursor(CursorMove::Steps(steps)))
     }
-}
 
-#[cfg(test)]
-pub(crate) mod tests {
-    use std::vec::Vec;
+    fn generate_output(&mut self, action: OutputAction) -> Output<'_, B, P, C, T> {
+        Output::new(&*self.prompt, self.buffer, self.terminal, action)
+            .with_rprompt(self.rprompt)
+            .with_bracketed_paste(self.settings.bracketed_paste)
+    }
 
-    use std::string::String;
+    // Char index of the cursor in the buffer
+    pub fn current_position(&self) -> usize {
+        char_index(
+            self.buffer.as_str(),
+            self.prompt.len(),
+            self.terminal.columns(),
+            self.terminal.current_offset() as usize,
+        )
+    }
 
-    use crate::history::{NoHistory, SliceHistory, UnboundedHistory};
-    use crate::line_buffer::UnboundedBuffer;
-    use crate::terminal::Cursor;
-    use crate::testlib::{csi, MockTerminal, ToByteVec};
+    // Move to the row above in a buffer with newlines, or to the
+    // previous history entry from the first row
+    fn move_up(&mut self) -> Output<'_, B, P, C, T> {
+        let row = self.terminal.get_position().row;
 
-    use super::*;
+        if row == 0 || !self.buffer.as_str().contains('\n') {
+            return self.history_move_up();
+        }
 
-    struct Editor<B: Buffer, H: History> {
-        buffer: LineBuffer<B>,
-        terminal: Terminal,
-        history: H,
+        let columns = self.terminal.columns();
+        let offset = self.terminal.current_offset() as usize - columns;
+        let pos = self.current_position();
+        let new_pos = char_index(
+            self.buffer.as_str(),
+            self.prompt.len(),
+            columns,
+            offset.max(self.prompt.len()),
+        );
+
+        self.move_cursor_to(pos, new_pos)
     }
 
-    impl<B: Buffer, H: History> Editor<B, H> {
-        fn new(buffer: LineBuffer<B>, history: H) -> Self {
-            let terminal = Terminal::default();
+    // Move to the row below in a buffer with newlines, or to the next
+    // history entry from the last row
+    fn move_down(&mut self) -> Output<'_, B, P, C, T> {
+        let s = self.buffer.as_str();
+        let columns = self.terminal.columns();
+        let len = s.chars().count();
+        let last_row = cell_offset(s, self.prompt.len(), columns, len) / columns;
 
-            Self {
-                buffer,
-                terminal,
-                history,
-            }
+        if self.terminal.get_position().row >= last_row || !s.contains('\n') {
+            return self.history_move_down();
         }
 
-        fn get_line(
-            &mut self,
-            prompt: &'static str,
-            mockterm: &mut MockTerminal,
-        ) -> Line<'_, B, H, StrIter> {
-            let cursor = mockterm.get_cursor();
-            let mut line = Line::new(
-                prompt,
-                &mut self.buffer,
-                &mut self.terminal,
-                &mut self.history,
-            );
+        let offset = self.terminal.current_offset() as usize + columns;
+        let pos = self.current_position();
+        let new_pos = char_index(s, self.prompt.len(), columns, offset).min(len);
 
-            let mut reset = line.reset();
+        self.move_cursor_to(pos, new_pos)
+    }
 
-            let mut reset_start: Vec<u8> = reset
-                .start()
-                .into_iter()
-                .filter_map(|item| item.get_bytes().map(|bytes| bytes.to_vec()))
-                .flatten()
-                .collect();
+    fn move_to_line_start(&mut self) -> Output<'_, B, P, C, T> {
+        let pos = self.current_position();
+        let start = self.buffer.line_start(pos);
 
-            while !reset_start.is_empty() {
-                let term_response: Vec<u8> = reset_start
-                    .into_iter()
-                    .filter_map(|b| mockterm.advance(b))
-                    .flat_map(|output| output.into_iter())
-                    .collect();
+        self.generate_output(MoveCursor(CursorMove::Steps(start as isize - pos as isize)))
+    }
 
-                reset_start = term_response
-                    .iter()
-                    .copied()
-                    .filter_map(|b| {
-                        reset.advance(b).map(|output| {
-                            output
-                                .into_iter()
-                                .map(|item| item.get_bytes().map(|bytes| bytes.to_vec()))
-                                .collect::<Vec<_>>()
-                        })
-                    })
-                    .flatten()
-                    .flatten()
-                    .flatten()
-                    .collect();
+    fn move_to_line_end(&mut self) -> Output<'_, B, P, C, T> {
+        let pos = self.current_position();
+        let end = self.buffer.line_end(pos);
+
+        self.generate_output(MoveCursor(CursorMove::Steps(end as isize - pos as isize)))
+    }
+
+    fn history_move_up(&mut self) -> Output<'_, B, P, C, T> {
+        if self.history_up() {
+            self.generate_output(ClearAndPrintBuffer)
+        } else {
+            self.generate_output(RingBell)
+        }
+    }
+
+    fn history_move_down(&mut self) -> Output<'_, B, P, C, T> {
+        if self.history_down() {
+            self.generate_output(ClearAndPrintBuffer)
+        } else {
+            self.generate_output(RingBell)
+        }
+    }
+
+    // Replace buffer with the previous history entry. With the cursor
+    // at the end of a line with text, only entries starting with the
+    // line are shown. Return false if there is none.
+    fn history_up(&mut self) -> bool {
+        if !self.nav.is_active() {
+            let prefix_len = if self.current_position() == self.buffer.char_len() {
+                self.buffer.len()
+            } else {
+                0
+            };
+
+            if !self.stash_line(prefix_len) {
+                return false;
             }
 
-            assert_eq!(mockterm.current_line_as_string(), prompt);
-            assert_eq!(mockterm.get_cursor(), Cursor::new(cursor.row, prompt.len()));
+            self.nav.reset();
+            self.nav.set_prefix_len(prefix_len);
+        }
+
+        let prefix = truncate_str(self.buffer.as_str(), self.nav.prefix_len() as usize);
+
+        if let Ok(entry) = self.nav.move_up(prefix.as_bytes()) {
+            let (slice1, slice2) = entry.get_slices();
+
+            // History entries longer than the buffer capacity are
+            // truncated. The navigator only returns valid UTF-8.
+            unsafe {
+                self.buffer.replace_with_slices(slice1, slice2);
+            }
+
+            true
+        } else {
+            false
+        }
+    }
+
+    // Replace buffer with the next history entry, or the line typed
+    // before navigating after the last. Return false if not navigating
+    // history.
+    fn history_down(&mut self) -> bool {
+        let prefix = truncate_str(self.buffer.as_str(), self.nav.prefix_len() as usize);
+
+        let entry = if self.nav.is_active() {
+            self.nav.move_down(prefix.as_bytes())
+        } else {
+            return false;
+        };
+
+        if let Ok(entry) = entry {
+            let (slice1, slice2) = entry.get_slices();
+
+            // History entries longer than the buffer capacity are
+            // truncated. The navigator only returns valid UTF-8.
+            unsafe {
+                self.buffer.replace_with_slices(slice1, slice2);
+            }
+        } else {
+            let prefix_len = prefix.chars().count();
+
+            self.nav.reset();
+
+            match self.stash.as_deref() {
+                // The stash was copied from the line buffer, so it is
+                // valid UTF-8 and fits
+                Some(stash) => unsafe {
+                    self.buffer
+                        .replace_with_slices(stash.as_str().as_bytes(), &[]);
+                },
+                // Navigating from a line with text shows entries
+                // starting with it, so it's kept at the start
+                None => self.buffer.delete_after_char(prefix_len),
+            }
+        }
+
+        true
+    }
+
+    // Keep the line before navigating history. Return false if it
+    // can't be kept, so navigating would lose it.
+    fn stash_line(&mut self, prefix_len: usize) -> bool {
+        match self.stash.as_deref_mut() {
+            // The line buffer holds valid UTF-8. A truncated stash
+            // would be restored, so the prefix doesn't help.
+            Some(stash) => {
+                !unsafe { stash.replace_with_slices(self.buffer.as_str().as_bytes(), &[]) }
+            }
+            // The line is the prefix, so it can be restored without a
+            // stash
+            None => self.buffer.is_empty() || prefix_len != 0,
+        }
+    }
+
+    fn is_navigation(action: Action) -> bool {
+        matches!(
+            action,
+            Action::ControlCharacter(CtrlB | CtrlF | CtrlN | CtrlP)
+                | Action::ControlSequenceIntroducer(
+                    CSI::CUU(_) | CSI::CUD(_) | CSI::CUF(_) | CSI::CUB(_)
+                )
+        )
+    }
+
+    // Apply navigation key without drawing the line while more input
+    // is ready. Any other key draws the line, and is replayed.
+    fn handle_stale_action(&mut self, mut stale: Stale, action: Action) -> Output<'_, B, P, C, T> {
+        let len = self.buffer.as_str().chars().count();
+
+        let moved = match action {
+            Action::Ignore if stale.key.is_full() => {
+                return self.draw_stale(stale);
+            }
+            Action::Ignore => {
+                self.display_override = Some(DisplayOverride::Stale(stale));
+                return self.generate_output(Nothing);
+            }
+            Action::ControlCharacter(CtrlP) | Action::ControlSequenceIntroducer(CSI::CUU(_)) => {
+                self.history_up() && stale.replaced(self.buffer.as_str().chars().count())
+            }
+            Action::ControlCharacter(CtrlN) | Action::ControlSequenceIntroducer(CSI::CUD(_)) => {
+                self.history_down() && stale.replaced(self.buffer.as_str().chars().count())
+            }
+            Action::ControlCharacter(CtrlB) | Action::ControlSequenceIntroducer(CSI::CUB(_))
+                if stale.cursor > 0 =>
+            {
+                stale.cursor -= 1;
+                true
+            }
+            Action::ControlCharacter(CtrlF) | Action::ControlSequenceIntroducer(CSI::CUF(_))
+                if stale.cursor < len =>
+            {
+                stale.cursor += 1;
+                true
+            }
+            Action::ControlCharacter(CtrlB | CtrlF)
+            | Action::ControlSequenceIntroducer(CSI::CUB(_) | CSI::CUF(_)) => false,
+            _ => {
+                self.type_ahead.push(stale.key.bytes());
+
+                return self.draw_stale(stale);
+            }
+        };
+
+        if !self.input_pending {
+            return self.draw_stale(stale);
+        }
+
+        stale.key.clear();
+        self.display_override = Some(DisplayOverride::Stale(stale));
+
+        if moved {
+            self.generate_output(Nothing)
+        } else {
+            self.generate_output(RingBell)
+        }
+    }
+
+    // Draw line after navigation keys were applied without drawing it
+    fn draw_stale(&mut self, stale: Stale) -> Output<'_, B, P, C, T> {
+        if stale.redraw {
+            return self.generate_output(ClearOverride(0, stale.cursor));
+        }
+
+        let steps = stale.cursor as isize - self.current_position() as isize;
+
+        if steps != 0 {
+            self.generate_output(MoveCursor(CursorMove::Steps(steps)))
+        } else {
+            self.generate_output(Nothing)
+        }
+    }
+
+    fn move_cursor_to(&mut self, pos: usize, new_pos: usize) -> Output<'_, B, P, C, T> {
+        if new_pos != pos {
+            self.generate_output(MoveCursor(CursorMove::Steps(
+                new_pos as isize - pos as isize,
+            )))
+        } else {
+            self.generate_output(RingBell)
+        }
+    }
+
+    fn search_output(&mut self) -> Output<'_, B, P, C, T> {
+        match &self.search {
+            Some(search) => {
+                let output = Output::new(
+                    &*self.prompt,
+                    self.buffer,
+                    self.terminal,
+                    ClearAndPrintSearch,
+                )
+                .with_rprompt(self.rprompt);
+
+                match search.entry {
+                    Some(index) => {
+                        let (slice1, slice2) = peek_slices(&*self.nav.history, index);
+
+                        output.with_overlay(Overlay::Search(search.header(), slice1, slice2))
+                    }
+                    None => output.with_text(search.header()),
+                }
+            }
+            None => {
+                self.prompt.regenerate();
+
+                Output::new(
+                    &*self.prompt,
+                    self.buffer,
+                    self.terminal,
+                    ClearAndPrintPromptAndBuffer,
+                )
+                .with_rprompt(self.rprompt)
+            }
+        }
+    }
+
+    fn search_start(&mut self) -> Output<'_, B, P, C, T> {
+        self.nav.reset();
+        self.search = Some(Search::new(self.settings.strings()));
+
+        self.search_output()
+    }
+
+    // Show most recent entry before `before` matching query. Return
+    // false if there is no match.
+    fn search_update(&mut self, search: &mut Search, before: usize) -> bool {
+        let index = find_entry(&*self.nav.history, search.query(), before).filter(|&index| {
+            self.nav
+                .history
+                .get_entry(index)
+                .is_some_and(|entry| entry.is_utf8())
+        });
+
+        if index.is_some() {
+            search.entry = index;
+        }
+
+        index.is_some()
+    }
+
+    fn handle_search_action(
+        &mut self,
+        mut search: Search,
+        action: Action,
+    ) -> Output<'_, B, P, C, T> {
+        let entries = self.nav.history.number_of_entries();
+        // Current match is checked first when the query changes
+        let current = search.entry.map_or(entries, |index| index + 1);
+
+        let found = match action {
+            Action::Print(c) => {
+                let pushed = search.push(c);
+                let found = pushed && self.search_update(&mut search, current);
+
+                // Keep query matching
+                if pushed && !found {
+                    search.pop();
+                }
+
+                found
+            }
+            Action::ControlCharacter(CtrlR) => {
+                let before = search.entry.unwrap_or(entries);
+
+                !search.query().is_empty() && self.search_update(&mut search, before)
+            }
+            Action::ControlCharacter(CtrlH | Backspace) => {
+                let popped = search.pop();
+
+                if popped && search.query().is_empty() {
+                    search.entry = None;
+                } else if popped {
+                    self.search_update(&mut search, current);
+                }
+
+                popped
+            }
+            // Accept match, or keep original line if there is none.
+            // The key is replayed, so Enter submits the line and
+            // cursor keys continue editing it.
+            Action::ControlCharacter(
+                c @ (CarriageReturn | LineFeed | CtrlA | CtrlB | CtrlE | CtrlF),
+            ) => {
+                if let Some(index) = search.entry {
+                    let (slice1, slice2) = peek_slices(&*self.nav.history, index);
+
+                    // Entries longer than the buffer capacity are truncated
+                    unsafe {
+                        self.buffer.replace_with_slices(slice1, slice2);
+                    }
+                }
+
+                self.type_ahead.push(&[c as u8]);
+
+                return self.search_output();
+            }
+            Action::ControlCharacter(CtrlG) | Action::EscapeSequence(_) => {
+                return self.search_output();
+            }
+            Action::ControlCharacter(CtrlC) => return self.generate_output(Abort),
+            Action::Ignore => {
+                self.search = Some(search);
+
+                return self.generate_output(Nothing);
+            }
+            _ => false,
+        };
+
+        self.search = Some(search);
+
+        if found {
+            self.search_output()
+        } else {
+            self.generate_output(RingBell)
+        }
+    }
+
+    fn complete(&mut self, completer: &C) -> Output<'_, B, P, C, T> {
+        if self.cycler.complete(completer, self.buffer) {
+            self.generate_output(ClearAndPrintBuffer)
+        } else {
+            self.generate_output(RingBell)
+        }
+    }
+
+    fn complete_prev(&mut self, completer: &C) -> Output<'_, B, P, C, T> {
+        if self.cycler.complete_prev(completer, self.buffer) {
+            self.generate_output(ClearAndPrintBuffer)
+        } else {
+            self.generate_output(RingBell)
+        }
+    }
+
+    // Insert the prefix shared by the candidates, or list them if Tab
+    // was just pressed
+    fn complete_or_list(&mut self, completer: &'a C) -> Output<'_, B, P, C, T> {
+        let original_len = self.buffer.as_str().chars().count();
+
+        if self.cycler.is_active() {
+            if complete::copy_candidate(completer, self.buffer.as_str(), 0, 0, &mut []).is_none() {
+                return self.generate_output(RingBell);
+            }
+
+            let line = self.buffer.as_str();
+
+            // Candidates are printed from the completer. The list stays
+            // on screen, and keys are handled as usual.
+            return Output::new(&*self.prompt, self.buffer, self.terminal, PrintList)
+                .with_overlay(Overlay::Candidates(completer, line))
+                .with_rprompt(self.rprompt);
+        }
+
+        let count = self.cycler.complete_prefix(completer, self.buffer);
+
+        // Ambiguous completions ring the bell, unless a shared prefix
+        // was inserted
+        if count > 0 && self.buffer.as_str().chars().count() > original_len {
+            self.generate_output(ClearAndPrintBuffer)
+        } else {
+            self.generate_output(RingBell)
+        }
+    }
+
+    // Show the first candidate highlighted after the line, without
+    // changing the buffer
+    fn preview_start(&mut self, completer: &'a C) -> Output<'_, B, P, C, T> {
+        let found = complete::copy_candidate(completer, self.buffer.as_str(), 0, 0, &mut [0])
+            .is_some_and(|len| len > 0);
+
+        if found {
+            self.display_override = Some(DisplayOverride::Completion {
+                cursor: self.current_position(),
+                key: KeyRecord::new(),
+            });
+
+            let line = self.buffer.as_str();
+
+            Output::new(
+                &*self.prompt,
+                self.buffer,
+                self.terminal,
+                PrintOverride(line.chars().count()),
+            )
+            .with_overlay(Overlay::Candidates(completer, line))
+            .with_style(Style::Reverse)
+            .with_rprompt(self.rprompt)
+        } else {
+            self.generate_output(RingBell)
+        }
+    }
+
+    // Tab or printable commits the preview into the buffer, keys
+    // still in progress keep it, and Backspace or Escape discards it.
+    // Any other key discards it and is replayed, as is a printable
+    // after committing, so it's inserted like any typed char.
+    fn handle_preview_action(
+        &mut self,
+        cursor: usize,
+        key: KeyRecord,
+        action: Action,
+    ) -> Output<'_, B, P, C, T> {
+        let len = self.buffer.as_str().chars().count();
+
+        let committed = match action {
+            Action::ControlCharacter(Tab) | Action::Print(_) => self.insert_preview(),
+            _ => false,
+        };
+
+        match action {
+            Action::Print(_) if committed => self.type_ahead.push(key.bytes()),
+            Action::Ignore if !key.is_full() => {
+                self.display_override = Some(DisplayOverride::Completion { cursor, key });
+                return self.generate_output(Nothing);
+            }
+            Action::ControlCharacter(Tab | CtrlH | Backspace)
+            | Action::EscapeSequence(_)
+            | Action::Ignore => (),
+            _ => self.type_ahead.push(key.bytes()),
+        }
+
+        if committed {
+            let end = self.buffer.as_str().chars().count();
+            self.generate_output(ClearOverride(len, end))
+        } else {
+            self.buffer.delete_after_char(len);
+            self.generate_output(ClearOverride(len, cursor))
+        }
+    }
+
+    // Append the previewed candidate to the buffer. Returns false if it
+    // doesn't fit.
+    fn insert_preview(&mut self) -> bool {
+        match self.completer {
+            Some(completer) => {
+                let mut target = BufferTarget::new(self.buffer);
+
+                completer.complete_into(&mut target, 0) && target.appended
+            }
+            None => false,
+        }
+    }
+
+    // Second key of a Ctrl-X chord
+    fn handle_ctrl_x_action(&mut self, action: Action) -> Output<'_, B, P, C, T> {
+        self.ctrl_x = false;
+
+        match action {
+            Action::Print(c) if c.as_char() == '=' => self.show_cursor_position(),
+            Action::ControlCharacter(CtrlU) => self.undo(),
+            _ => self.generate_output(RingBell),
+        }
+    }
+
+    // Action for a key with vi key bindings. Commands in normal mode
+    // are translated to the emacs key doing the same, and keys without
+    // a command give None.
+    fn vi_action(&mut self, action: Action) -> Option<Action> {
+        if self.settings.editing_mode != EditingMode::Vi || self.paste != Paste::Off {
+            return Some(action);
+        }
+
+        match action {
+            // Escape followed by a key, which is handled in normal mode
+            Action::EscapeSequence(byte) => {
+                self.vi_mode = ViMode::Normal;
+
+                if byte == Escape as u8 {
+                    Some(Action::Ignore)
+                } else if byte.is_ascii_control() {
+                    Some(Action::control_character(byte))
+                } else {
+                    self.vi_command(char::from(byte))
+                }
+            }
+            Action::Print(c) if self.vi_mode != ViMode::Insert => self.vi_command(c.as_char()),
+            Action::Ignore => Some(action),
+            _ => {
+                if self.vi_mode == ViMode::Delete {
+                    self.vi_mode = ViMode::Normal;
+                }
+
+                Some(action)
+            }
+        }
+    }
+
+    // Command typed in vi normal mode
+    fn vi_command(&mut self, c: char) -> Option<Action> {
+        if self.vi_mode == ViMode::Delete {
+            self.vi_mode = ViMode::Normal;
+
+            return match c {
+                'd' => Some(Action::ControlCharacter(CtrlU)),
+                _ => None,
+            };
+        }
+
+        match c {
+            'h' => Some(Action::ControlCharacter(CtrlB)),
+            'l' => Some(Action::ControlCharacter(CtrlF)),
+            '0' => Some(Action::ControlCharacter(CtrlA)),
+            '$' => Some(Action::ControlCharacter(CtrlE)),
+            'x' => Some(Action::ControlSequenceIntroducer(CSI::Delete)),
+            'k' => Some(Action::ControlCharacter(CtrlP)),
+            'j' => Some(Action::ControlCharacter(CtrlN)),
+            'u' => Some(Action::ControlCharacter(US)),
+            'd' => {
+                self.vi_mode = ViMode::Delete;
+                Some(Action::Ignore)
+            }
+            'i' => {
+                self.vi_mode = ViMode::Insert;
+                Some(Action::Ignore)
+            }
+            'a' => {
+                self.vi_mode = ViMode::Insert;
+
+                if self.current_position() < self.buffer.as_str().chars().count() {
+                    Some(Action::ControlCharacter(CtrlF))
+                } else {
+                    Some(Action::Ignore)
+                }
+            }
+            _ => None,
+        }
+    }
+
+    // Ctrl-X =, show position of the cursor and the char under it
+    // after the end of the line until the next key, without moving the
+    // cursor or changing the line
+    fn show_cursor_position(&mut self) -> Output<'_, B, P, C, T> {
+        let cursor = self.current_position();
+        let end = self.buffer.as_str().chars().count();
+        let strings = self.settings.strings();
+        let c = self.buffer.char_at(cursor);
+
+        let (Ok(column), Ok(len)) = (u32::try_from(cursor + 1), u32::try_from(end)) else {
+            return self.generate_output(RingBell);
+        };
+
+        // Formatted as it's printed, so only checked for length here
+        if format_status(strings, column as usize, len as usize, c, 0, &mut []).is_err() {
+            return self.generate_output(RingBell);
+        }
+
+        self.display_override = Some(DisplayOverride::Status { cursor });
+
+        Output::new(
+            &*self.prompt,
+            self.buffer,
+            self.terminal,
+            PrintOverride(end),
+        )
+        .with_overlay(Overlay::Status(strings, column, len, c))
+        .with_style(Style::Dim)
+        .with_rprompt(self.rprompt)
+    }
+
+    // Show history entry at index in place of the line
+    fn history_peek(&mut self, index: usize, cursor: usize) -> Output<'_, B, P, C, T> {
+        self.display_override = Some(DisplayOverride::History { index, cursor });
+
+        let (slice1, slice2) = peek_slices(&*self.nav.history, index);
+
+        Output::new(&*self.prompt, self.buffer, self.terminal, PrintOverride(0))
+            .with_overlay(Overlay::Entry(slice1, slice2))
+            .with_style(Style::Dim)
+            .with_rprompt(self.rprompt)
+    }
+
+    fn history_peek_start(&mut self) -> Output<'_, B, P, C, T> {
+        match self.nav.history.number_of_entries() {
+            0 => self.generate_output(RingBell),
+            entries => {
+                self.nav.reset();
+
+                let cursor = self.current_position();
+
+                self.history_peek(entries - 1, cursor)
+            }
+        }
+    }
+
+    // Alt+Up and Alt+Down move to other entries, Enter and cursor
+    // movement commit the entry into the buffer, keys still in
+    // progress keep it, and any other key restores the line.
+    fn handle_history_peek_action(
+        &mut self,
+        index: usize,
+        cursor: usize,
+        action: Action,
+    ) -> Output<'_, B, P, C, T> {
+        let peek = DisplayOverride::History { index, cursor };
+
+        match action {
+            Action::ControlSequenceIntroducer(CSI::CUUMod(3)) => {
+                if index > 0 {
+                    self.history_peek(index - 1, cursor)
+                } else {
+                    self.display_override = Some(peek);
+                    self.generate_output(RingBell)
+                }
+            }
+            Action::ControlSequenceIntroducer(CSI::CUDMod(3)) => {
+                if index + 1 < self.nav.history.number_of_entries() {
+                    self.history_peek(index + 1, cursor)
+                } else {
+                    self.display_override = Some(peek);
+                    self.generate_output(RingBell)
+                }
+            }
+            Action::ControlCharacter(CarriageReturn | LineFeed | CtrlA | CtrlB | CtrlE | CtrlF)
+            | Action::ControlSequenceIntroducer(
+                CSI::CUF(_)
+                | CSI::CUB(_)
+                | CSI::CUFMod(_)
+                | CSI::CUBMod(_)
+                | CSI::Home
+                | CSI::HomeMod(_)
+                | CSI::CUP(1, _)
+                | CSI::End
+                | CSI::EndMod(_),
+            ) => {
+                let (slice1, slice2) = peek_slices(&*self.nav.history, index);
+
+                // Entries longer than the buffer capacity are truncated
+                unsafe {
+                    self.buffer.replace_with_slices(slice1, slice2);
+                }
+
+                let end = self.buffer.as_str().chars().count();
+                self.generate_output(ClearOverride(0, end))
+            }
+            Action::Ignore => {
+                self.display_override = Some(peek);
+                self.generate_output(Nothing)
+            }
+            _ => self.generate_output(ClearOverride(0, cursor)),
+        }
+    }
+
+    // Replace the kill buffer with the chars in range, truncated if
+    // they don't fit. Nothing is kept if the range is empty.
+    fn kill(&mut self, range: Range<usize>) {
+        if let Some(kill) = self.kill.as_deref_mut() {
+            let s = self.buffer.char_slice(range);
+
+            if !s.is_empty() {
+                // The slice is a valid UTF-8 string
+                unsafe {
+                    kill.replace_with_slices(s.as_bytes(), &[]);
+                }
+            }
+        }
+    }
+
+    // Record the last edit before handling the next key
+    fn undo_checkpoint(&mut self) {
+        if self.undo.is_some() {
+            let pos = self.current_position();
+
+            if let Some(undo) = self.undo.as_deref_mut() {
+                undo.checkpoint(self.buffer.as_str(), pos);
+            }
+        }
+    }
+
+    fn clear_undo(&mut self) {
+        if let Some(undo) = self.undo.as_deref_mut() {
+            undo.clear();
+        }
+    }
+
+    // Ctrl-_, restore the line and cursor position from before the
+    // last edit
+    fn undo(&mut self) -> Output<'_, B, P, C, T> {
+        match self.undo.as_deref_mut().and_then(|undo| undo.undo()) {
+            Some((line, cursor)) => {
+                // The line was in the line buffer before, so it is
+                // valid UTF-8 and fits
+                unsafe {
+                    self.buffer.replace_with_slices(line, &[]);
+                }
+
+                self.generate_output(ClearOverride(0, cursor))
+            }
+            None => self.generate_output(RingBell),
+        }
+    }
+
+    fn yank(&mut self) -> Output<'_, B, P, C, T> {
+        let pos = self.current_position();
+
+        let inserted = match self.kill.as_deref() {
+            Some(kill) if !kill.is_empty() => self
+                .buffer
+                .insert_str(pos, kill.as_str())
+                .map(|_| kill.as_str().chars().count()),
+            _ => return self.generate_output(RingBell),
+        };
+
+        match inserted {
+            Ok(len) => self.generate_output(PrintBufferAndMoveCursorForward(len)),
+            Err(()) => self.overflow(),
+        }
+    }
+
+    // Ring the bell for an insertion that doesn't fit in the buffer.
+    // The mark at the end of the line needs cursor movement, so it's
+    // only shown when that is echoed.
+    fn overflow(&mut self) -> Output<'_, B, P, C, T> {
+        self.reject_insertion();
+
+        let indicator = self.settings.overflow_indicator
+            && matches!(self.settings.echo, Echo::Full | Echo::Local);
+
+        self.generate_output(Overflow(indicator))
+    }
+
+    // Line as it is accepted, see [`TrimMode`]
+    fn trimmed(&self) -> &str {
+        let s = self.buffer.as_str();
+
+        match self.settings.trim {
+            TrimMode::None => s,
+            TrimMode::Trailing => s.trim_end(),
+            TrimMode::Both => s.trim(),
+        }
+    }
+
+    // Trim the buffer of a line being accepted. Only done once the
+    // line is accepted, as the cursor may be in the trimmed part.
+    fn trim(&mut self) {
+        let s = self.buffer.as_str();
+        let end = s.trim_end().chars().count();
+
+        let start = match self.settings.trim {
+            TrimMode::None => return,
+            TrimMode::Trailing => 0,
+            TrimMode::Both => s.chars().count() - s.trim_start().chars().count(),
+        };
+
+        self.buffer.delete_after_char(end);
+        self.buffer.delete_before_char(start.min(end));
+    }
+
+    // Remember that text was refused, if the line is then rejected
+    fn reject_insertion(&mut self) {
+        if self.settings.overflow == OverflowPolicy::RejectLine {
+            self.rejected_len = Some(self.buffer.len());
+        }
+    }
+
+    // Map the chars from the cursor to the end of the word, for Alt-u,
+    // Alt-l and Alt-c. The cursor moves past the word.
+    fn map_word(&mut self, f: impl FnMut(char) -> char) -> Output<'_, B, P, C, T> {
+        let pos = self.current_position();
+        let end = self.buffer.map_word_chars(pos, f);
+
+        if end > pos {
+            self.generate_output(ClearOverride(pos, end))
+        } else {
+            self.generate_output(RingBell)
+        }
+    }
+
+    // Insert the last word of the previous history entry, or replace
+    // the word inserted by the previous Alt-. with the last word of the
+    // entry before that
+    fn yank_last_arg(&mut self, last_arg: Option<LastArg>) -> Output<'_, B, P, C, T> {
+        let (before, len) = match last_arg {
+            Some(LastArg { index, len }) => (index as usize, len as usize),
+            None => (self.nav.history.number_of_entries(), 0),
+        };
+        let start = self.current_position() - len;
+
+        let found = (0..before).rev().find_map(|index| {
+            let entry = self.nav.history.get_entry(index)?;
+
+            match entry.is_utf8().then(|| entry.last_word())? {
+                ([], []) => None,
+                word => Some((index, word)),
+            }
+        });
+
+        let Some((index, (slice1, slice2))) = found else {
+            self.last_arg = last_arg;
+            return self.generate_output(RingBell);
+        };
+
+        let new_len = slice1
+            .iter()
+            .chain(slice2)
+            .filter(|&&b| b & 0xc0 != 0x80)
+            .count();
+
+        // The new word is inserted after the old one, which is kept if
+        // the new one doesn't fit. Entries were checked to be valid
+        // UTF-8, and words are split at ASCII whitespace.
+        if unsafe { self.buffer.insert_slices(start + len, slice1, slice2) }.is_err() {
+            self.last_arg = last_arg;
+            return self.overflow();
+        }
+
+        for _ in 0..len {
+            self.buffer.delete(start);
+        }
+
+        // Words and histories too long for [`LastArg`] can't be
+        // replaced by the next Alt-.
+        self.last_arg = match (u16::try_from(index), u16::try_from(new_len)) {
+            (Ok(index), Ok(len)) => Some(LastArg { index, len }),
+            _ => None,
+        };
+
+        self.generate_output(ClearOverride(start, start + new_len))
+    }
+
+    // Handle control char `c` not bound to a key, or Escape before
+    // `byte` not bound to a key
+    fn unbound_control(&mut self, c: ControlCharacter, byte: Option<u8>) -> Output<'_, B, P, C, T> {
+        // Caret notation, e.g. `^A` for 0x1
+        fn caret(byte: u8) -> [u8; 2] {
+            [b'^', byte ^ 0x40]
+        }
+
+        let printable = byte.filter(|b| (0x20..0x7f).contains(b));
+        let mut bytes = [0; 4];
+
+        let len = match self.settings.control_chars {
+            ControlCharPolicy::Bell => return self.generate_output(RingBell),
+            ControlCharPolicy::Strip => match printable {
+                Some(b) => {
+                    bytes[0] = b;
+                    1
+                }
+                None => return self.generate_output(Nothing),
+            },
+            ControlCharPolicy::Insert => {
+                bytes[..2].copy_from_slice(&caret(c as u8));
+
+                match (printable, byte) {
+                    (Some(b), _) => {
+                        bytes[2] = b;
+                        3
+                    }
+                    (None, Some(b)) => {
+                        bytes[2..].copy_from_slice(&caret(b));
+                        4
+                    }
+                    (None, None) => 2,
+                }
+            }
+        };
+
+        // Only ASCII was written
+        let s = core::str::from_utf8(&bytes[..len]).unwrap_or_default();
+        let pos = self.current_position();
+
+        if self.buffer.insert_str(pos, s).is_ok() {
+            self.generate_output(PrintBufferAndMoveCursorForward(len))
+        } else {
+            self.overflow()
+        }
+    }
+
+    fn insert_tab(&mut self) -> Output<'_, B, P, C, T> {
+        const SPACES: &str = "        ";
+
+        let pos = self.current_position();
+
+        let (s, len) = match self.settings.tab {
+            TabPolicy::Expand(0) => return self.generate_output(Nothing),
+            TabPolicy::Expand(n) => (SPACES, n),
+            TabPolicy::Preserve => ("\t", 1),
+        };
+
+        // Checked up front, so a tab is never expanded partly
+        if self
+            .buffer
+            .remaining_capacity()
+            .is_some_and(|capacity| capacity < len)
+        {
+            return self.overflow();
+        }
+
+        for chunk in (0..len).step_by(s.len()) {
+            let s = &s[..s.len().min(len - chunk)];
+
+            // Fits, as checked above
+            let _ = self.buffer.insert_str(pos + chunk, s);
+        }
+
+        self.generate_output(PrintBufferAndMoveCursorForward(len))
+    }
+
+    // Replace char at pos with `s`, or append at end of line. Return
+    // false if it doesn't fit.
+    fn replace_char(&mut self, pos: usize, s: &str) -> bool {
+        let mut old = [0; 4];
+        let old = self
+            .buffer
+            .as_str()
+            .chars()
+            .nth(pos)
+            .map(|old_char| &*old_char.encode_utf8(&mut old));
+
+        if old.is_some() {
+            self.buffer.delete(pos);
+        }
+
+        if self.buffer.insert_str(pos, s).is_ok() {
+            true
+        } else {
+            // The replaced char fits, as it was there before
+            if let Some(old) = old {
+                let _ = self.buffer.insert_str(pos, old);
+            }
+
+            false
+        }
+    }
+
+    // Typing at the end of the line only prints the new char, instead
+    // of reprinting and moving the cursor back in place
+    fn print_inserted_char(&mut self, pos: usize) -> Output<'_, B, P, C, T> {
+        if self.buffer.char_at(pos + 1).is_none() {
+            match self.hinter {
+                Some(hinter) => self.print_hint(hinter),
+                None => self.generate_output(PrintBufferEnd),
+            }
+        } else {
+            self.generate_output(PrintBufferAndMoveCursorForward(1))
+        }
+    }
+
+    // Show hint after char typed at the end of the line. Hints are
+    // left out while more input is ready, as the next key would
+    // discard them, and without full echo.
+    fn print_hint(&mut self, hinter: &'a T) -> Output<'_, B, P, C, T> {
+        let hint = if self.settings.echo == Echo::Full
+            && self.paste == Paste::Off
+            && self.mask.is_none()
+            && !self.input_pending
+            && !self.has_type_ahead()
+        {
+            find_hint(
+                hinter,
+                self.buffer.as_str(),
+                &*self.nav.history,
+                self.buffer.remaining_capacity(),
+            )
+        } else {
+            None
+        };
+
+        // The output is made here rather than by `generate_output`, as
+        // an entry of the hint borrows the history
+        let overlay = match hint {
+            Some(Hint::Text) => Some(Overlay::Hint(hinter, self.buffer.as_str())),
+            Some(Hint::Entry(slice1, slice2)) => Some(Overlay::Entry(slice1, slice2)),
+            None => None,
+        };
+
+        let action = match overlay {
+            Some(_) => {
+                self.display_override = Some(DisplayOverride::Hint {
+                    key: KeyRecord::new(),
+                });
+
+                PrintHint
+            }
+            None => PrintBufferEnd,
+        };
+
+        let output = Output::new(&*self.prompt, self.buffer, self.terminal, action)
+            .with_rprompt(self.rprompt)
+            .with_bracketed_paste(self.settings.bracketed_paste);
+
+        match overlay {
+            Some(overlay) => output.with_overlay(overlay).with_style(Style::Dim),
+            None => output,
+        }
+    }
+
+    // Right, Ctrl-F, End and Ctrl-E accept the hint, keys still in
+    // progress keep it, and any other key discards it and is replayed
+    fn handle_hint_action(&mut self, key: KeyRecord, action: Action) -> Output<'_, B, P, C, T> {
+        let len = self.buffer.as_str().chars().count();
+
+        match (action, self.hinter) {
+            (
+                Action::ControlCharacter(CtrlE | CtrlF)
+                | Action::ControlSequenceIntroducer(CSI::CUF(_) | CSI::End | CSI::EndMod(_)),
+                Some(hinter),
+            ) => {
+                hinter.hint_into(&mut HintBuffer::new(self.buffer, &*self.nav.history));
+
+                let end = self.buffer.as_str().chars().count();
+                self.generate_output(ClearOverride(len, end))
+            }
+            (Action::Ignore, _) if !key.is_full() => {
+                self.display_override = Some(DisplayOverride::Hint { key });
+                self.generate_output(Nothing)
+            }
+            _ => {
+                if !matches!(action, Action::Ignore) {
+                    self.type_ahead.push(key.bytes());
+                }
+
+                self.generate_output(ClearOverride(len, len))
+            }
+        }
+    }
+
+    // With local echo, the terminal has already printed the char at
+    // the cursor and moved one column forward, unless the cursor was
+    // in the last column. The cursor is moved to match, and the line
+    // printed again from where the terminal got it wrong.
+    fn print_local_echo(
+        &mut self,
+        pos: usize,
+        inserted: bool,
+        width: usize,
+    ) -> Output<'_, B, P, C, T> {
+        let position = self.terminal.get_position();
+
+        // The terminal either wraps on the next char or leaves the
+        // cursor in place, so reprinting from here works for both.
+        // Where a wide char ends up depends on the room left on the
+        // row, so it's printed again too.
+        if width > 1 || position.column + 1 == self.terminal.columns() {
+            return if inserted {
+                self.print_inserted_char(pos)
+            } else {
+                self.generate_output(EraseAndPrintBuffer)
+            };
+        }
+
+        self.terminal
+            .move_cursor(Position::new(position.row, position.column + 1));
+
+        if !inserted {
+            self.generate_output(MoveCursorAndEraseAndPrintBuffer(-1))
+        } else if self.overwrite || self.buffer.char_at(pos + 1).is_none() {
+            self.generate_output(Nothing)
+        } else {
+            self.generate_output(MoveCursorBackAndPrintBufferAndMoveForward)
+        }
+    }
+
+    // Advance state machine by one byte. Returns output iterator over
+    // 0 or more byte slices.
+    pub(crate) fn advance(&mut self, byte: u8) -> Output<'_, B, P, C, T> {
+        // Keys are complete when a status message is shown, so this is
+        // the first byte of the next key. It's replayed after the
+        // message is cleared.
+        let action = match self.display_override {
+            Some(DisplayOverride::Status { .. }) => {
+                self.type_ahead.push(&[byte]);
+                Action::Ignore
+            }
+            Some(DisplayOverride::Stale(Stale { ref mut key, .. }))
+            | Some(DisplayOverride::Completion { ref mut key, .. })
+            | Some(DisplayOverride::Hint { ref mut key, .. }) => {
+                key.record(byte);
+                self.parser.advance(byte)
+            }
+            _ => self.parser.advance(byte),
+        };
+
+        #[cfg(test)]
+        dbg!(action);
+
+        // Text was deleted since an insertion was refused
+        if self.rejected_len.is_some_and(|len| self.buffer.len() < len) {
+            self.rejected_len = None;
+        }
+
+        let echo = self.settings.echo;
+        let output = self.handle_action(action).with_echo(echo);
+
+        #[cfg(feature = "defmt")]
+        defmt::trace!("{} -> {}", action, output.action());
+
+        output
+    }
+
+    // Translate action while pasting, so pasted text can't end the
+    // line or trigger editing commands
+    fn paste_action(&mut self, action: Action) -> Action {
+        let after_carriage_return = self.paste == Paste::AfterCarriageReturn;
+
+        if !matches!(action, Action::Ignore) {
+            self.paste = Paste::Active;
+        }
+
+        match action {
+            Action::ControlSequenceIntroducer(CSI::PasteEnd) => {
+                self.paste = Paste::Off;
+                Action::Ignore
+            }
+            Action::ControlCharacter(LineFeed) if after_carriage_return => Action::Ignore,
+            Action::ControlCharacter(c @ (CarriageReturn | LineFeed)) => {
+                if c == CarriageReturn {
+                    self.paste = Paste::AfterCarriageReturn;
+                }
+
+                Action::Print(Utf8Char::SPACE)
+            }
+            Action::Print(_) | Action::ControlCharacter(Tab) | Action::Ignore => action,
+            _ => Action::Ignore,
+        }
+    }
+
+    fn handle_action(&mut self, action: Action) -> Output<'_, B, P, C, T> {
+        let action = match self.paste {
+            Paste::Off => action,
+            _ => self.paste_action(action),
+        };
+
+        if let Action::ControlSequenceIntroducer(CSI::PasteStart) = action {
+            self.paste = Paste::Active;
+        }
+
+        // Any other key ends a run of Alt-.
+        let last_arg = match action {
+            Action::Ignore => self.last_arg,
+            _ => self.last_arg.take(),
+        };
+
+        if self.mask.is_some() && !Self::passes_mask(action) {
+            return self.handle_mask_action(action);
+        }
+
+        if let Some(search) = self.search.take() {
+            return self.handle_search_action(search, action);
+        }
+
+        match self.display_override.take() {
+            Some(DisplayOverride::Completion { cursor, key }) => {
+                return self.handle_preview_action(cursor, key, action);
+            }
+            Some(DisplayOverride::History { index, cursor }) => {
+                return self.handle_history_peek_action(index, cursor, action);
+            }
+            // The key is replayed by [`Line::advance`]
+            Some(DisplayOverride::Status { cursor }) => {
+                let len = self.buffer.as_str().chars().count();
+                return self.generate_output(ClearOverride(len, cursor));
+            }
+            Some(DisplayOverride::Stale(stale)) => {
+                return self.handle_stale_action(stale, action);
+            }
+            Some(DisplayOverride::Hint { key }) => {
+                return self.handle_hint_action(key, action);
+            }
+            None => (),
+        }
+
+        // Rows of a buffer with newlines are navigated one by one
+        if self.input_pending && Self::is_navigation(action) && !self.buffer.as_str().contains('\n')
+        {
+            let stale = Stale::new(self.current_position());
+
+            return self.handle_stale_action(stale, action);
+        }
+
+        if self.ctrl_x && !matches!(action, Action::Ignore) {
+            return self.handle_ctrl_x_action(action);
+        }
+
+        let action = match self.vi_action(action) {
+            Some(action) => action,
+            None => return self.generate_output(RingBell),
+        };
+
+        if !matches!(action, Action::Ignore) {
+            self.undo_checkpoint();
+        }
+
+        // Bytes of an escape sequence are ignored until it's complete,
+        // so the start of Shift-Tab doesn't end the cycle
+        if !matches!(
+            action,
+            Action::Ignore
+                | Action::ControlCharacter(Tab)
+                | Action::ControlSequenceIntroducer(CSI::CBT)
+        ) {
+            self.cycler.reset();
+        }
+
+        match action {
+            Action::Print(c) => {
+                let pos = self.current_position();
+
+                let inserted = if self.overwrite {
+                    let mut bytes = [0; 4];
+                    self.replace_char(pos, c.as_char().encode_utf8(&mut bytes))
+                } else {
+                    self.buffer.insert_utf8_char(pos, c).is_ok()
+                };
+
+                if self.settings.echo == Echo::Local {
+                    if !inserted {
+                        self.reject_insertion();
+                    }
+
+                    self.print_local_echo(pos, inserted, c.width())
+                        .with_overflow(!inserted)
+                } else if inserted {
+                    self.print_inserted_char(pos)
+                } else {
+                    self.overflow()
+                }
+            }
+            Action::ControlCharacter(c) => match c {
+                CtrlA => self.move_to_line_start(),
+                CtrlB => self.generate_output(MoveCursor(CursorMove::Back)),
+                CtrlC => self.generate_output(Abort),
+                CtrlD => {
+                    let len = self.buffer.len();
+
+                    if len > 0 {
+                        let pos = self.current_position();
+
+                        if pos < len {
+                            self.buffer.delete(pos);
+
+                            self.generate_output(EraseAndPrintBuffer)
+                        } else {
+                            self.generate_output(RingBell)
+                        }
+                    } else {
+                        self.generate_output(Eof)
+                    }
+                }
+                CtrlE => self.move_to_line_end(),
+                CtrlF => self.generate_output(MoveCursor(CursorMove::Forward)),
+                CtrlK => {
+                    let pos = self.current_position();
+
+                    self.kill(pos..self.buffer.len());
+                    self.buffer.delete_after_char(pos);
+
+                    self.generate_output(EraseAfterCursor)
+                }
+                CtrlL => {
+                    let position = self.current_position();
+
+                    self.prompt.regenerate();
+                    self.generate_output(ClearScreen(position))
+                }
+                CtrlN => self.move_down(),
+                CtrlP => self.move_up(),
+                CtrlR => self.search_start(),
+                CtrlT => {
+                    let pos = self.current_position();
+
+                    if pos > 0 && pos < self.buffer.as_str().chars().count() {
+                        self.buffer.swap_chars(pos);
+                        self.generate_output(MoveCursorBackAndPrintBufferAndMoveForward)
+                    } else {
+                        self.generate_output(RingBell)
+                    }
+                }
+                CtrlU => {
+                    self.kill(0..self.buffer.len());
+                    self.buffer.delete_after_char(0);
+                    self.generate_output(ClearLine)
+                }
+                CtrlX => {
+                    self.ctrl_x = true;
+                    self.generate_output(Nothing)
+                }
+                CtrlY => self.yank(),
+                US => self.undo(),
+                Tab if self.paste != Paste::Off => self.insert_tab(),
+                Tab => match self.completer {
+                    Some(completer) if self.settings.completion_preview => {
+                        self.preview_start(completer)
+                    }
+                    Some(completer) if self.settings.completion_list => {
+                        self.complete_or_list(completer)
+                    }
+                    Some(completer) => self.complete(completer),
+                    None => self.insert_tab(),
+                },
+                CtrlW => {
+                    let pos = self.current_position();
+
+                    self.kill(self.buffer.previous_word_delete_start(pos)..pos);
+                    let move_cursor = -(self.buffer.delete_previous_word(pos) as isize);
+                    self.generate_output(MoveCursorAndEraseAndPrintBuffer(move_cursor))
+                }
+                CarriageReturn | LineFeed => {
+                    if self.rejected_len.is_some() {
+                        return self.generate_output(RingBell);
+                    }
+
+                    if let Some(validator) = self.validator {
+                        if !validator.validate(self.trimmed()) {
+                            return self.generate_output(RingBell);
+                        }
+                    }
+
+                    self.trim();
+
+                    if !self.buffer.is_empty() {
+                        let _ = self.nav.history.add_entry_deduped(self.buffer.as_str());
+                    }
+
+                    self.generate_output(Done)
+                }
+                CtrlH | Backspace => {
+                    let pos = self.current_position();
+                    if pos > 0 {
+                        self.buffer.delete(pos - 1);
+                        self.generate_output(MoveCursorAndEraseAndPrintBuffer(-1))
+                    } else {
+                        self.generate_output(RingBell)
+                    }
+                }
+                c => self.unbound_control(c, None),
+            },
+            Action::ControlSequenceIntroducer(csi) => match csi {
+                CSI::CUF(_) => self.generate_output(MoveCursor(CursorMove::Forward)),
+                CSI::CUB(_) => self.generate_output(MoveCursor(CursorMove::Back)),
+                // Ctrl and Alt move by word, forward to the start of the
+                // next word like in most terminal applications
+                CSI::CUFMod(3 | 5) => self.move_next_word_start(),
+                CSI::CUBMod(3 | 5) => self.move_word_back(),
+                CSI::CUFMod(_) => self.generate_output(MoveCursor(CursorMove::Forward)),
+                CSI::CUBMod(_) => self.generate_output(MoveCursor(CursorMove::Back)),
+                // Home is sent as cursor position 1;1 by xterm, with modifiers
+                // in place of the column
+                CSI::Home | CSI::HomeMod(_) | CSI::CUP(1, _) => self.move_to_line_start(),
+                CSI::Insert | CSI::InsertMod(_) => {
+                    self.overwrite = !self.overwrite;
+                    self.generate_output(Nothing)
+                }
+                CSI::Delete | CSI::DeleteMod(_) => {
+                    let len = self.buffer.len();
+                    let pos = self.current_position();
+
+                    if pos < len {
+                        self.buffer.delete(pos);
+
+                        self.generate_output(EraseAndPrintBuffer)
+                    } else {
+                        self.generate_output(RingBell)
+                    }
+                }
+                CSI::End | CSI::EndMod(_) => self.move_to_line_end(),
+                CSI::CPR(row, column) => {
+                    let cursor = Cursor::new(row - 1, column - 1);
+                    self.terminal.reset(cursor);
+                    self.generate_output(Nothing)
+                }
+                // The bytes of unknown sequences aren't kept, so they
+                // can't be inserted
+                CSI::Unknown(_) => match self.settings.control_chars {
+                    ControlCharPolicy::Bell => self.generate_output(RingBell),
+                    _ => self.generate_output(Nothing),
+                },
+                CSI::CUU(_) => self.move_up(),
+                CSI::CUD(_) => self.move_down(),
+                CSI::CUUMod(3) => self.history_peek_start(),
+                CSI::CUUMod(_) => self.history_move_up(),
+                CSI::CUDMod(_) => self.history_move_down(),
+                CSI::CUP(_, _) => self.generate_output(RingBell),
+                CSI::ED(_) => self.generate_output(RingBell),
+                CSI::EL(_) => self.generate_output(RingBell),
+                CSI::DSR => self.generate_output(RingBell),
+                CSI::SU(_) => self.generate_output(RingBell),
+                CSI::SD(_) => self.generate_output(RingBell),
+                CSI::SGR(_) => self.generate_output(RingBell),
+                CSI::SM(_) => self.generate_output(RingBell),
+                CSI::RM(_) => self.generate_output(RingBell),
+                CSI::PasteStart | CSI::PasteEnd => self.generate_output(Nothing),
+                CSI::CBT => match self.completer {
+                    Some(completer) => self.complete_prev(completer),
+                    None => self.generate_output(RingBell),
+                },
+            },
+            Action::EscapeSequence(b'b') => self.move_word_back(),
+            Action::EscapeSequence(b'f') => self.move_word_forward(),
+            // Alt+Enter continues the line on the next row
+            Action::EscapeSequence(b'\r') => {
+                let pos = self.current_position();
+
+                if self.buffer.insert_str(pos, "\n").is_ok() {
+                    self.generate_output(PrintBufferAndMoveCursorForward(1))
+                } else {
+                    self.overflow()
+                }
+            }
+            Action::EscapeSequence(b'.') => self.yank_last_arg(last_arg),
+            Action::EscapeSequence(b't') => {
+                let pos = self.current_position();
+
+                // Cursor moves to the end of the second word
+                match self.buffer.transpose_words(pos) {
+                    Some(words) => self.generate_output(ClearOverride(words.start, words.end)),
+                    None => self.generate_output(RingBell),
+                }
+            }
+            Action::EscapeSequence(b'd') => {
+                let pos = self.current_position();
+
+                if self.buffer.delete_next_word(pos) > 0 {
+                    self.generate_output(EraseAndPrintBuffer)
+                } else {
+                    self.generate_output(RingBell)
+                }
+            }
+            Action::EscapeSequence(b'u') => self.map_word(to_uppercase),
+            Action::EscapeSequence(b'l') => self.map_word(to_lowercase),
+            // Capitalize the first char of the word
+            Action::EscapeSequence(b'c') => {
+                let mut first = true;
+
+                self.map_word(move |c| {
+                    if core::mem::take(&mut first) {
+                        to_uppercase(c)
+                    } else {
+                        to_lowercase(c)
+                    }
+                })
+            }
+            Action::EscapeSequence(byte) => self.unbound_control(Escape, Some(byte)),
+            Action::Ignore => self.generate_output(Nothing),
+            Action::InvalidUtf8 => self.generate_output(RingBell),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use std::string::String;
+
+    use crate::complete::FnCompleter;
+    use crate::hint::HistoryHinter;
+    use crate::history::{NoHistory, SliceHistory, StaticSlotHistory, UnboundedHistory};
+    use crate::line_buffer::UnboundedBuffer;
+    use crate::terminal::Cursor;
+    use crate::testlib::{csi, MockTerminal, ToByteVec};
+
+    use super::*;
+
+    struct Editor<B: Buffer, H: History> {
+        buffer: LineBuffer<B>,
+        terminal: Terminal,
+        history: H,
+        settings: Settings,
+        completer: Option<&'static dyn Completer>,
+        validator: Option<&'static dyn Validator>,
+        hinter: Option<&'static HistoryHinter>,
+        kill_buffer: Option<LineBuffer<B>>,
+        undo_buffer: Option<UndoBuffer<B>>,
+        stash_buffer: Option<LineBuffer<B>>,
+        // Prompt of the line returned by `get_line`
+        prompt: Box<dyn PromptSource<'static>>,
+    }
+
+    impl<B: Buffer, H: History> Editor<B, H> {
+        fn new(buffer: LineBuffer<B>, history: H) -> Self {
+            let terminal = Terminal::default();
+
+            Self {
+                buffer,
+                terminal,
+                history,
+                settings: Settings::default(),
+                completer: None,
+                validator: None,
+                hinter: None,
+                kill_buffer: None,
+                undo_buffer: None,
+                stash_buffer: None,
+                prompt: Box::new(Prompt::from("")),
+            }
+        }
+
+        fn get_line(
+            &mut self,
+            prompt: &'static str,
+            mockterm: &mut MockTerminal,
+        ) -> Line<'_, B, H, dyn PromptSource<'static>, dyn Completer, dyn Validator, HistoryHinter>
+        {
+            self.get_line_inner(prompt, "", mockterm, false, None)
+        }
+
+        fn get_line_with_rprompt(
+            &mut self,
+            prompt: &'static str,
+            rprompt: &'static str,
+            mockterm: &mut MockTerminal,
+        ) -> Line<'_, B, H, dyn PromptSource<'static>, dyn Completer, dyn Validator, HistoryHinter>
+        {
+            self.get_line_inner(prompt, rprompt, mockterm, false, None)
+        }
+
+        fn get_line_with_prompt<I>(
+            &mut self,
+            prompt: Prompt<I>,
+            mockterm: &mut MockTerminal,
+        ) -> Line<'_, B, H, dyn PromptSource<'static>, dyn Completer, dyn Validator, HistoryHinter>
+        where
+            I: Iterator<Item = &'static str> + Clone + 'static,
+        {
+            self.get_line_inner(prompt, "", mockterm, false, None)
+        }
+
+        fn get_line_preserving_buffer(
+            &mut self,
+            prompt: &'static str,
+            mockterm: &mut MockTerminal,
+        ) -> Line<'_, B, H, dyn PromptSource<'static>, dyn Completer, dyn Validator, HistoryHinter>
+        {
+            self.get_line_inner(prompt, "", mockterm, true, None)
+        }
+
+        fn get_line_masked<'m>(
+            &'m mut self,
+            prompt: &'static str,
+            mask: &'m Mask<'m>,
+            mockterm: &mut MockTerminal,
+        ) -> Line<'m, B, H, dyn PromptSource<'static>, dyn Completer, dyn Validator, HistoryHinter>
+        {
+            self.get_line_inner(prompt, "", mockterm, true, Some(mask))
+        }
+
+        fn get_line_inner<'m, I>(
+            &'m mut self,
+            prompt: impl Into<Prompt<I>>,
+            rprompt: &'static str,
+            mockterm: &mut MockTerminal,
+            preserve_buffer: bool,
+            mask: Option<&'m Mask<'m>>,
+        ) -> Line<'m, B, H, dyn PromptSource<'static>, dyn Completer, dyn Validator, HistoryHinter>
+        where
+            I: Iterator<Item = &'static str> + Clone + 'static,
+        {
+            let cursor = mockterm.get_cursor();
+            let prompt = prompt.into();
+            // Prompt printed as is can't be compared with the screen
+            let expected = (!prompt.has_display_len()).then(|| prompt.iter().collect::<String>());
+            self.prompt = Box::new(prompt);
+            let mut line = Line::new(
+                &mut *self.prompt,
+                &mut self.buffer,
+                &mut self.terminal,
+                &mut self.history,
+                self.settings,
+            );
+
+            // Set in place, as each builder call takes a copy of the line
+            // on the stack in unoptimized builds
+            line.completer = self.completer;
+            line.validator = self.validator;
+            line.hinter = self.hinter;
+            line.kill = self.kill_buffer.as_mut();
+            line.undo = self.undo_buffer.as_mut();
+            line.stash = self.stash_buffer.as_mut();
+            line.rprompt = rprompt;
+
+            if let Some(mask) = mask {
+                line.set_mask(mask);
+            }
+
+            let mut reset = if preserve_buffer {
+                line.reset_preserving_buffer()
+            } else {
+                line.reset()
+            };
+
+            let mut reset_start: Vec<u8> = reset
+                .start()
+                .into_iter()
+                .filter_map(|item| item.get_bytes().map(|bytes| bytes.to_vec()))
+                .flatten()
+                .collect();
+
+            while !reset_start.is_empty() {
+                let term_response: Vec<u8> = reset_start
+                    .into_iter()
+                    .filter_map(|b| mockterm.advance(b))
+                    .flat_map(|output| output.into_iter())
+                    .collect();
+
+                reset_start = term_response
+                    .iter()
+                    .copied()
+                    .filter_map(|b| {
+                        reset.advance(b).unwrap().map(|output| {
+                            output
+                                .into_iter()
+                                .map(|item| item.get_bytes().map(|bytes| bytes.to_vec()))
+                                .collect::<Vec<_>>()
+                        })
+                    })
+                    .flatten()
+                    .flatten()
+                    .flatten()
+                    .collect();
+            }
+
+            if let Some(prompt) = expected {
+                assert_eq!(mockterm.current_line_as_string(), prompt);
+                assert_eq!(mockterm.get_cursor(), Cursor::new(cursor.row, prompt.len()));
+            }
+
+            print_output(mockterm, line.print_rprompt());
+
+            if preserve_buffer {
+                print_output(mockterm, line.print_buffer());
+            }
+
+            line
+        }
+    }
+
+    // Separate from [`Editor::get_line_inner`] to keep its stack frame
+    // small
+    fn print_output<B: Buffer>(
+        mockterm: &mut MockTerminal,
+        output: Output<'_, B, dyn PromptSource<'static>, dyn Completer, HistoryHinter>,
+    ) {
+        for item in output {
+            if let Some(bytes) = item.get_bytes() {
+                for &b in bytes {
+                    mockterm.advance(b);
+                }
+            }
+        }
+    }
+
+    fn advance<'a, B: Buffer, H: History>(
+        terminal: &mut MockTerminal,
+        noline: &mut Line<
+            'a,
+            B,
+            H,
+            dyn PromptSource<'static>,
+            dyn Completer,
+            dyn Validator,
+            HistoryHinter,
+        >,
+        input: impl ToByteVec,
+    ) -> core::result::Result<(), ()> {
+        terminal.bell = false;
+
+        for input in input.to_byte_vec() {
+            let mut byte = Some(input);
+
+            // Replay keys like the editors do
+            while let Some(input) = byte {
+                for item in noline.advance(input) {
+                    if let Some(bytes) = item.get_bytes() {
+                        for &b in bytes {
+                            terminal.advance(b);
+                        }
+                    }
+                }
+
+                byte = noline.take_type_ahead();
+            }
+        }
+
+        assert_eq!(noline.terminal.get_cursor(), terminal.cursor);
+
+        dbg!(terminal.screen_as_string());
+
+        if terminal.bell {
+            Err(())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn get_terminal_and_editor(
+        rows: usize,
+        columns: usize,
+        origin: Cursor,
+    ) -> (MockTerminal, Editor<UnboundedBuffer, NoHistory>) {
+        let terminal = MockTerminal::new(rows, columns, origin);
+
+        let editor = Editor::new(LineBuffer::new_unbounded(), NoHistory {});
+
+        assert_eq!(terminal.get_cursor(), origin);
+
+        (terminal, editor)
+    }
+
+    #[test]
+    fn reset() {
+        let mut prompt = Prompt::from("> ");
+        let (terminal, mut editor) = get_terminal_and_editor(4, 10, Cursor::new(1, 0));
+        let mut line: Line<'_, _, _, _> = Line::new(
+            &mut prompt,
+            &mut editor.buffer,
+            &mut editor.terminal,
+            &mut editor.history,
+            editor.settings,
+        );
+
+        dbg!(terminal.get_cursor());
+
+        let mut reset = line.reset();
+
+        let probe = reset
+            .start()
+            .into_iter()
+            .flat_map(|item| item.get_bytes().unwrap().to_vec())
+            .collect::<Vec<u8>>();
+
+        assert_eq!(probe, b"\x1b7\x1b[999;999H\x1b[6n\x1b8");
+
+        let output = b"\x1b[91;45R"
+            .iter()
+            .copied()
+            .flat_map(|b| reset.advance(b).unwrap().unwrap().into_vec())
+            .collect::<Vec<_>>();
+
+        dbg!(terminal.get_cursor());
+
+        assert_eq!(output, b"\r\x1b[J> \x1b[6n");
+
+        let output = b"\x1b[2;3R"
+            .iter()
+            .copied()
+            .flat_map(|b| {
+                if let Some(output) = reset.advance(b).unwrap() {
+                    output.into_vec()
+                } else {
+                    Vec::new()
+                }
+            })
+            .collect::<Vec<_>>();
+
+        dbg!(terminal.get_cursor());
+
+        assert_eq!(output, b"");
+
+        assert_eq!(line.terminal.get_size(), (91, 45));
+    }
+
+    #[test]
+    fn mock_editor() {
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 20, Cursor::new(1, 0));
+
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 0));
+
+        let line = editor.get_line(prompt, &mut terminal);
+
+        dbg!(&line.terminal);
+        assert_eq!(terminal.get_cursor(), line.terminal.get_cursor());
+    }
+
+    #[test]
+    fn movecursor() {
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 10, Cursor::new(1, 0));
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        advance(&mut terminal, &mut line, "Hello, World!").unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(2, 5));
+
+        advance(&mut terminal, &mut line, [csi::LEFT; 6]).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 9));
+
+        advance(&mut terminal, &mut line, CtrlA).unwrap();
+
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 2));
+
+        assert!(advance(&mut terminal, &mut line, csi::LEFT).is_err());
+
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 2));
+
+        advance(&mut terminal, &mut line, CtrlE).unwrap();
+
+        assert_eq!(terminal.get_cursor(), Cursor::new(2, 5));
+
+        assert!(advance(&mut terminal, &mut line, csi::RIGHT).is_err());
+
+        assert_eq!(terminal.get_cursor(), Cursor::new(2, 5));
+
+        advance(&mut terminal, &mut line, csi::HOME).unwrap();
+
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 2));
+
+        advance(&mut terminal, &mut line, csi::END).unwrap();
+
+        assert_eq!(terminal.get_cursor(), Cursor::new(2, 5));
+
+        // Modifiers don't change Home and End
+        advance(&mut terminal, &mut line, "\x1b[1;5H").unwrap();
+
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 2));
+
+        advance(&mut terminal, &mut line, "\x1b[4;5~").unwrap();
+
+        assert_eq!(terminal.get_cursor(), Cursor::new(2, 5));
+    }
+
+    #[test]
+    fn cursor_scroll() {
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 10, Cursor::new(3, 0));
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        advance(&mut terminal, &mut line, "23456789").unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(3, 0));
+    }
+
+    #[test]
+    fn clear_line() {
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 20, Cursor::new(1, 0));
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        dbg!(terminal.get_cursor());
+        dbg!(&line.terminal);
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 2));
+        dbg!(terminal.screen_as_string());
+
+        advance(&mut terminal, &mut line, "Hello, World!").unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 15));
+        assert_eq!(terminal.screen_as_string(), "> Hello, World!");
+
+        advance(&mut terminal, &mut line, CtrlU).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 2));
+        assert_eq!(terminal.screen_as_string(), "> ");
+
+        advance(&mut terminal, &mut line, "Hello, World!").unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 15));
+        assert_eq!(terminal.screen_as_string(), "> Hello, World!");
+    }
+
+    #[test]
+    fn clear_screen() {
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 20, Cursor::new(1, 0));
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        advance(&mut terminal, &mut line, "Hello, World!").unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 15));
+        assert_eq!(terminal.screen_as_string(), "> Hello, World!");
+
+        // The line is kept, and printed again at the top
+        advance(&mut terminal, &mut line, CtrlL).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 15));
+        assert_eq!(terminal.screen_as_string(), "> Hello, World!");
+
+        advance(&mut terminal, &mut line, CtrlU).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 2));
+        assert_eq!(terminal.screen_as_string(), "> ");
+    }
+
+    #[test]
+    fn clear_screen_wrapped_line() {
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 10, Cursor::new(2, 0));
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        advance(&mut terminal, &mut line, "abcdefghijkl").unwrap();
+        advance(&mut terminal, &mut line, CtrlA).unwrap();
+        advance(&mut terminal, &mut line, [csi::RIGHT, csi::RIGHT]).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(2, 4));
+        assert_eq!(terminal.screen_as_string(), "> abcdefgh\nijkl");
+
+        // The cursor is kept at the same char
+        advance(&mut terminal, &mut line, CtrlL).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 4));
+        assert_eq!(terminal.screen_as_string(), "> abcdefgh\nijkl");
+
+        advance(&mut terminal, &mut line, "X").unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 5));
+        assert_eq!(terminal.screen_as_string(), "> abXcdefg\nhijkl");
+
+        advance(&mut terminal, &mut line, [CtrlE, CtrlL]).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 5));
+        assert_eq!(terminal.screen_as_string(), "> abXcdefg\nhijkl");
+    }
+
+    #[test]
+    fn colored_prompt() {
+        let prompt = Prompt::with_display_len("\x1b[32m> \x1b[0m", 2);
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 10, Cursor::new(1, 0));
+
+        let mut line = editor.get_line_with_prompt(prompt, &mut terminal);
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 2));
+        assert_eq!(terminal.styled_as_string(32), "> ");
+
+        advance(&mut terminal, &mut line, "abcdefghij").unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(2, 2));
+        assert_eq!(terminal.screen_as_string(), "> abcdefgh\nij");
+
+        advance(&mut terminal, &mut line, CtrlA).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 2));
+
+        advance(&mut terminal, &mut line, [csi::RIGHT, csi::RIGHT]).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 4));
+
+        advance(&mut terminal, &mut line, CtrlE).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(2, 2));
+
+        advance(&mut terminal, &mut line, CtrlL).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 2));
+        assert_eq!(terminal.styled_as_string(32), "> ");
+        assert_eq!(terminal.screen_as_string(), "> abcdefgh\nij");
+
+        advance(&mut terminal, &mut line, "xyz").unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 5));
+    }
+
+    #[test]
+    fn prompt_from_fn() {
+        const PROMPTS: [&str; 3] = ["> ", "long> ", ""];
+
+        static CURRENT: AtomicUsize = AtomicUsize::new(0);
+
+        let prompt = Prompt::from_fn(|| core::iter::once(PROMPTS[CURRENT.load(Ordering::Relaxed)]));
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 10, Cursor::new(1, 0));
+
+        let mut line = editor.get_line_with_prompt(prompt, &mut terminal);
+
+        advance(&mut terminal, &mut line, "abc").unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 5));
+
+        // Cursor movement uses the width of the prompt as drawn
+        CURRENT.store(1, Ordering::Relaxed);
+        advance(&mut terminal, &mut line, csi::LEFT).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 4));
+        assert_eq!(terminal.screen_as_string(), "> abc");
+
+        // Redrawing keeps the cursor at the same char, after the
+        // longer prompt
+        let pos = line.current_position();
+        let mut output = line.redraw().into_vec();
+        output.extend(line.restore_cursor(pos).into_vec());
+
+        for b in output {
+            terminal.advance(b);
+        }
+
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 8));
+        assert_eq!(terminal.screen_as_string(), "long> abc");
+
+        advance(&mut terminal, &mut line, "x").unwrap();
+        assert_eq!(terminal.screen_as_string(), "long> abxc");
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 9));
+
+        // Cleared screen starts with the shorter prompt
+        CURRENT.store(2, Ordering::Relaxed);
+        advance(&mut terminal, &mut line, CtrlL).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 3));
+        assert_eq!(terminal.screen_as_string(), "abxc");
+
+        advance(&mut terminal, &mut line, ["xyz", csi::HOME]).unwrap();
+        assert_eq!(terminal.screen_as_string(), "abxxyzc");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 0));
+    }
+
+    #[test]
+    fn scroll() {
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 10, Cursor::new(0, 0));
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        advance(&mut terminal, &mut line, "aaaaaaaa").unwrap();
+        advance(&mut terminal, &mut line, "bbbbbbbbbb").unwrap();
+        advance(&mut terminal, &mut line, "cccccccccc").unwrap();
+        advance(&mut terminal, &mut line, "ddddddddd").unwrap();
+
+        assert_eq!(terminal.get_cursor(), Cursor::new(3, 9));
+
+        assert_eq!(
+            terminal.screen_as_string(),
+            "> aaaaaaaa\nbbbbbbbbbb\ncccccccccc\nddddddddd"
+        );
+
+        advance(&mut terminal, &mut line, "d").unwrap();
+
+        assert_eq!(terminal.get_cursor(), Cursor::new(3, 0));
+
+        assert_eq!(
+            terminal.screen_as_string(),
+            "bbbbbbbbbb\ncccccccccc\ndddddddddd"
+        );
+
+        advance(&mut terminal, &mut line, "eeeeeeeeee").unwrap();
+
+        assert_eq!(
+            terminal.screen_as_string(),
+            "cccccccccc\ndddddddddd\neeeeeeeeee"
+        );
+
+        // advance(&mut terminal, &mut noline, CtrlA);
+
+        // assert_eq!(terminal.get_cursor(), Cursor::new(0, 2));
+        // assert_eq!(
+        //     terminal.screen_as_string(),
+        //     "> aaaaaaaa\nbbbbbbbbbb\ncccccccccc\ndddddddddd"
+        // );
+
+        // advance(&mut terminal, &mut noline, CtrlE);
+        // assert_eq!(terminal.get_cursor(), Cursor::new(3, 0));
+        // assert_eq!(
+        //     terminal.screen_as_string(),
+        //     "cccccccccc\ndddddddddd\neeeeeeeeee"
+        // );
+    }
+
+    #[cfg(not(feature = "ascii-only"))]
+    #[test]
+    fn swap() {
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 10, Cursor::new(0, 0));
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        advance(&mut terminal, &mut line, "æøå").unwrap();
+        assert_eq!(terminal.screen_as_string(), "> æøå");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 5));
+
+        assert!(advance(&mut terminal, &mut line, CtrlT).is_err());
+
+        assert_eq!(terminal.screen_as_string(), "> æøå");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 5));
+
+        advance(&mut terminal, &mut line, csi::LEFT).unwrap();
+
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 4));
+
+        advance(&mut terminal, &mut line, CtrlT).unwrap();
+
+        assert_eq!(line.buffer.as_str(), "æåø");
+        assert_eq!(terminal.screen_as_string(), "> æåø");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 4));
+
+        advance(&mut terminal, &mut line, CtrlA).unwrap();
+
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 2));
+
+        assert!(advance(&mut terminal, &mut line, CtrlT).is_err());
+        assert_eq!(terminal.screen_as_string(), "> æåø");
+    }
+
+    #[test]
+    fn erase_after_cursor() {
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 10, Cursor::new(0, 0));
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        advance(&mut terminal, &mut line, "rm -rf /").unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 0));
+        assert_eq!(terminal.screen_as_string(), "> rm -rf /");
+
+        advance(&mut terminal, &mut line, CtrlA).unwrap();
+        advance(&mut terminal, &mut line, [CtrlF; 3]).unwrap();
+
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 5));
+
+        advance(&mut terminal, &mut line, CtrlK).unwrap();
+        assert_eq!(line.buffer.as_str(), "rm ");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 5));
+        assert_eq!(terminal.screen_as_string(), "> rm ");
+    }
+
+    #[test]
+    fn delete_previous_word() {
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(1, 40, Cursor::new(0, 0));
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        advance(&mut terminal, &mut line, "rm file1 file2 file3").unwrap();
+        assert_eq!(terminal.screen_as_string(), "> rm file1 file2 file3");
+
+        advance(&mut terminal, &mut line, [CtrlB; 5]).unwrap();
+
+        advance(&mut terminal, &mut line, CtrlW).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 11));
+        assert_eq!(line.buffer.as_str(), "rm file1 file3");
+        assert_eq!(terminal.screen_as_string(), "> rm file1 file3");
+
+        advance(&mut terminal, &mut line, CtrlW).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> rm file3");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 5));
+    }
+
+    #[test]
+    fn delete_next_word() {
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(1, 40, Cursor::new(0, 0));
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        advance(&mut terminal, &mut line, "rm file1   file2 file3").unwrap();
+        assert!(advance(&mut terminal, &mut line, "\x1bd").is_err());
+
+        advance(&mut terminal, &mut line, CtrlA).unwrap();
+        advance(&mut terminal, &mut line, [CtrlF; 5]).unwrap();
+
+        advance(&mut terminal, &mut line, "\x1bd").unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 7));
+        assert_eq!(line.buffer.as_str(), "rm fi   file2 file3");
+        assert_eq!(terminal.screen_as_string(), "> rm fi   file2 file3");
+
+        advance(&mut terminal, &mut line, "\x1bd").unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 7));
+        assert_eq!(line.buffer.as_str(), "rm fi file3");
+        assert_eq!(terminal.screen_as_string(), "> rm fi file3");
+    }
+
+    #[cfg(not(feature = "ascii-only"))]
+    #[test]
+    fn transpose_words() {
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(1, 40, Cursor::new(0, 0));
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        // Last two words at the end of the line
+        advance(&mut terminal, &mut line, "ls æøå  wörd").unwrap();
+        advance(&mut terminal, &mut line, "\x1bt").unwrap();
+        assert_eq!(line.buffer.as_str(), "ls wörd  æøå");
+        assert_eq!(terminal.screen_as_string(), "> ls wörd  æøå");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 14));
+
+        // Cursor inside a word
+        advance(&mut terminal, &mut line, [CtrlB; 7]).unwrap();
+        advance(&mut terminal, &mut line, "\x1bt").unwrap();
+        assert_eq!(line.buffer.as_str(), "wörd ls  æøå");
+        assert_eq!(terminal.screen_as_string(), "> wörd ls  æøå");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 9));
+
+        // Cursor in the space between words
+        advance(&mut terminal, &mut line, CtrlF).unwrap();
+        advance(&mut terminal, &mut line, "\x1bt").unwrap();
+        assert_eq!(line.buffer.as_str(), "wörd æøå  ls");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 14));
+
+        // No word before the first
+        advance(&mut terminal, &mut line, CtrlA).unwrap();
+        assert!(advance(&mut terminal, &mut line, "\x1bt").is_err());
+        assert_eq!(line.buffer.as_str(), "wörd æøå  ls");
+
+        // Single word
+        advance(&mut terminal, &mut line, [CtrlE, CtrlU]).unwrap();
+        advance(&mut terminal, &mut line, "wörd").unwrap();
+        assert!(advance(&mut terminal, &mut line, "\x1bt").is_err());
+        assert_eq!(terminal.screen_as_string(), "> wörd");
+    }
+
+    #[test]
+    fn word_case() {
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(1, 40, Cursor::new(0, 0));
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        advance(&mut terminal, &mut line, "set myValue  other_NAME x").unwrap();
+        advance(&mut terminal, &mut line, CtrlA).unwrap();
+
+        advance(&mut terminal, &mut line, "\x1bu").unwrap();
+        assert_eq!(line.buffer.as_str(), "SET myValue  other_NAME x");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 5));
+
+        // From the cursor, inside the word
+        advance(&mut terminal, &mut line, [CtrlF; 3]).unwrap();
+        advance(&mut terminal, &mut line, "\x1bu").unwrap();
+        assert_eq!(line.buffer.as_str(), "SET myVALUE  other_NAME x");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 13));
+
+        // Spaces before the word are skipped
+        advance(&mut terminal, &mut line, "\x1bc").unwrap();
+        assert_eq!(line.buffer.as_str(), "SET myVALUE  Other_name x");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 25));
+
+        advance(&mut terminal, &mut line, [CtrlA]).unwrap();
+        advance(&mut terminal, &mut line, "\x1bl\x1bl").unwrap();
+        assert_eq!(line.buffer.as_str(), "set myvalue  Other_name x");
+        assert_eq!(terminal.screen_as_string(), "> set myvalue  Other_name x");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 13));
+
+        // No word after the cursor
+        advance(&mut terminal, &mut line, CtrlE).unwrap();
+        assert!(advance(&mut terminal, &mut line, "\x1bu").is_err());
+        assert_eq!(line.buffer.as_str(), "set myvalue  Other_name x");
+    }
+
+    #[cfg(not(feature = "ascii-only"))]
+    #[test]
+    fn word_case_utf8() {
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(1, 40, Cursor::new(0, 0));
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        // 'ß' uppercases to two chars, so it's kept. 'ı' uppercases to
+        // one byte less, and the Kelvin sign lowercases to two less.
+        advance(&mut terminal, &mut line, "æøå straße ÆØÅ ı\u{212a}").unwrap();
+        advance(&mut terminal, &mut line, CtrlA).unwrap();
+
+        advance(&mut terminal, &mut line, "\x1bu\x1bu").unwrap();
+        assert_eq!(line.buffer.as_str(), "ÆØÅ STRAßE ÆØÅ ı\u{212a}");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 12));
+
+        advance(&mut terminal, &mut line, "\x1bc\x1bc").unwrap();
+        assert_eq!(line.buffer.as_str(), "ÆØÅ STRAßE Æøå Ik");
+        assert_eq!(terminal.screen_as_string(), "> ÆØÅ STRAßE Æøå Ik");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 19));
+
+        advance(&mut terminal, &mut line, CtrlA).unwrap();
+        advance(&mut terminal, &mut line, "\x1bl").unwrap();
+        assert_eq!(line.buffer.as_str(), "æøå STRAßE Æøå Ik");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 5));
+    }
+
+    #[test]
+    fn validator() {
+        let prompt = "> ";
+        let mut terminal = MockTerminal::new(4, 20, Cursor::new(0, 0));
+        let mut editor = Editor::new(LineBuffer::new_unbounded(), UnboundedHistory::new());
+        editor.validator = Some(&|line: &str| !line.is_empty() && line.starts_with("cmd"));
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        // Rejected lines are kept for editing, and not added to history
+        assert!(advance(&mut terminal, &mut line, "\r").is_err());
+        advance(&mut terminal, &mut line, "foo").unwrap();
+        assert!(advance(&mut terminal, &mut line, "\r").is_err());
+        assert_eq!(terminal.screen_as_string(), "> foo");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 5));
+        assert_eq!(line.nav.history.number_of_entries(), 0);
+
+        advance(&mut terminal, &mut line, CtrlA).unwrap();
+        advance(&mut terminal, &mut line, "cmd ").unwrap();
+        advance(&mut terminal, &mut line, "\r").unwrap();
+        assert_eq!(line.buffer.as_str(), "cmd foo");
+        assert_eq!(line.nav.history.number_of_entries(), 1);
+    }
+
+    #[test]
+    fn multi_line() {
+        // Newlines are printed as spaces filling the row
+        fn screen(terminal: &MockTerminal) -> String {
+            (0..terminal.rows)
+                .map(|row| terminal.row_as_string(row))
+                .filter(|row| !row.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+
+        let prompt = "> ";
+        let mut terminal = MockTerminal::new(6, 20, Cursor::new(0, 0));
+        let mut editor = Editor::new(LineBuffer::new_unbounded(), UnboundedHistory::new());
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        advance(&mut terminal, &mut line, "select *").unwrap();
+        advance(&mut terminal, &mut line, "\x1b\r").unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 0));
+        advance(&mut terminal, &mut line, "from t").unwrap();
+        assert_eq!(screen(&terminal), "> select *\nfrom t");
+
+        // Ctrl-A and Ctrl-E move within the row
+        advance(&mut terminal, &mut line, CtrlA).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 0));
+        advance(&mut terminal, &mut line, CtrlE).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 6));
+
+        advance(&mut terminal, &mut line, [CtrlB; 2]).unwrap();
+        advance(&mut terminal, &mut line, "X").unwrap();
+        assert_eq!(screen(&terminal), "> select *\nfromX t");
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 5));
+
+        // Up and Down move between rows before reaching history
+        advance(&mut terminal, &mut line, csi::UP).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 5));
+        advance(&mut terminal, &mut line, "Y").unwrap();
+        assert_eq!(screen(&terminal), "> selYect *\nfromX t");
+
+        advance(&mut terminal, &mut line, csi::DOWN).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 6));
+        assert!(advance(&mut terminal, &mut line, csi::DOWN).is_err());
+
+        // Cursor ends up after the newline, at the end of the first row
+        advance(&mut terminal, &mut line, csi::UP).unwrap();
+        advance(&mut terminal, &mut line, CtrlE).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 11));
+
+        // Deleting the newline joins the rows
+        advance(&mut terminal, &mut line, CtrlD).unwrap();
+        assert_eq!(screen(&terminal), "> selYect *fromX t");
+        advance(&mut terminal, &mut line, "\x1b\r").unwrap();
+        assert_eq!(screen(&terminal), "> selYect *\nfromX t");
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 0));
+
+        advance(&mut terminal, &mut line, CtrlE).unwrap();
+        advance(&mut terminal, &mut line, "\r").unwrap();
+        assert_eq!(line.buffer.as_str(), "selYect *\nfromX t");
+
+        // History keeps the newlines
+        let mut line = editor.get_line(prompt, &mut terminal);
+        advance(&mut terminal, &mut line, csi::UP).unwrap();
+        assert_eq!(line.buffer.as_str(), "selYect *\nfromX t");
+        assert_eq!(terminal.row_as_string(2), "> selYect *");
+        assert_eq!(terminal.row_as_string(3), "fromX t");
+        assert_eq!(terminal.get_cursor(), Cursor::new(3, 7));
+    }
+
+    #[test]
+    fn yank() {
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(2, 20, Cursor::new(0, 0));
+
+        // Nothing to yank without a kill buffer
+        let mut line = editor.get_line(prompt, &mut terminal);
+        advance(&mut terminal, &mut line, "abc").unwrap();
+        advance(&mut terminal, &mut line, CtrlW).unwrap();
+        assert!(advance(&mut terminal, &mut line, CtrlY).is_err());
+
+        editor.kill_buffer = Some(LineBuffer::new_unbounded());
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        assert!(advance(&mut terminal, &mut line, CtrlY).is_err());
+
+        advance(&mut terminal, &mut line, "cp src dst").unwrap();
+        advance(&mut terminal, &mut line, CtrlW).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> cp src ");
+
+        // Killing nothing keeps the kill buffer
+        advance(&mut terminal, &mut line, CtrlK).unwrap();
+
+        advance(&mut terminal, &mut line, [CtrlB; 4]).unwrap();
+        advance(&mut terminal, &mut line, CtrlY).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> cp dstsrc ");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 8));
+
+        // Consecutive kills replace the kill buffer
+        advance(&mut terminal, &mut line, CtrlK).unwrap();
+        advance(&mut terminal, &mut line, CtrlW).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> cp ");
+        advance(&mut terminal, &mut line, [CtrlY, CtrlY]).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> cp dstdst");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 11));
+
+        advance(
+            &mut terminal,
+            &mut line,
+            [CtrlA, CtrlF, CtrlF, CtrlF, CtrlK],
+        )
+        .unwrap();
+        advance(&mut terminal, &mut line, CtrlU).unwrap();
+        advance(&mut terminal, &mut line, [CtrlY, CtrlY, CtrlY, CtrlY]).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> cp cp cp cp ");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 14));
+
+        advance(&mut terminal, &mut line, [CtrlY, CtrlY]).unwrap();
+        assert_eq!(line.buffer.as_str(), "cp cp cp cp cp cp ");
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 0));
+
+        // The kill buffer is kept between lines
+        let mut line = editor.get_line(prompt, &mut terminal);
+        advance(&mut terminal, &mut line, CtrlY).unwrap();
+        assert_eq!(line.buffer.as_str(), "cp ");
+    }
+
+    #[test]
+    fn yank_full_buffer() {
+        let prompt = "> ";
+        let mut terminal = MockTerminal::new(1, 20, Cursor::new(0, 0));
+        let mut buffer = [0; 8];
+        let mut kill_buffer = [0; 4];
+        let mut editor = Editor::new(LineBuffer::from_slice(&mut buffer), NoHistory {});
+        editor.kill_buffer = Some(LineBuffer::from_slice(&mut kill_buffer));
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        advance(&mut terminal, &mut line, "ab cd").unwrap();
+        advance(&mut terminal, &mut line, CtrlW).unwrap();
+        advance(&mut terminal, &mut line, [CtrlY, CtrlY]).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> ab cdcd");
+
+        // Yank is all or nothing
+        assert!(advance(&mut terminal, &mut line, CtrlY).is_err());
+        assert_eq!(line.buffer.as_str(), "ab cdcd");
+
+        // Kills that don't fit the kill buffer are truncated
+        advance(&mut terminal, &mut line, [CtrlU, CtrlY]).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> ab c");
+    }
+
+    #[test]
+    fn delete() {
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(1, 40, Cursor::new(0, 0));
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 2));
+        assert_eq!(terminal.screen_as_string(), "> ");
+        advance(&mut terminal, &mut line, "abcde").unwrap();
+
+        advance(&mut terminal, &mut line, CtrlD).unwrap_err();
+
+        advance(&mut terminal, &mut line, CtrlA).unwrap();
+
+        advance(&mut terminal, &mut line, CtrlD).unwrap();
+        assert_eq!(line.buffer.as_str(), "bcde");
+        assert_eq!(terminal.screen_as_string(), "> bcde");
+
+        advance(&mut terminal, &mut line, [csi::RIGHT; 3]).unwrap();
+        advance(&mut terminal, &mut line, CtrlD).unwrap();
+        assert_eq!(line.buffer.as_str(), "bcd");
+        assert_eq!(terminal.screen_as_string(), "> bcd");
+
+        advance(&mut terminal, &mut line, CtrlD).unwrap_err();
+
+        advance(&mut terminal, &mut line, CtrlA).unwrap();
+
+        advance(&mut terminal, &mut line, csi::DELETE).unwrap();
+        assert_eq!(line.buffer.as_str(), "cd");
+        assert_eq!(terminal.screen_as_string(), "> cd");
+
+        advance(&mut terminal, &mut line, csi::DELETE).unwrap();
+        assert_eq!(line.buffer.as_str(), "d");
+        assert_eq!(terminal.screen_as_string(), "> d");
+
+        advance(&mut terminal, &mut line, "\x1b[3;5~").unwrap();
+        assert_eq!(line.buffer.as_str(), "");
+        assert_eq!(terminal.screen_as_string(), "> ");
+    }
+
+    #[cfg(not(feature = "ascii-only"))]
+    #[test]
+    fn overwrite() {
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(1, 40, Cursor::new(0, 0));
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        advance(&mut terminal, &mut line, "abc").unwrap();
+        advance(&mut terminal, &mut line, [csi::LEFT; 2]).unwrap();
+        advance(&mut terminal, &mut line, csi::INSERT).unwrap();
+
+        advance(&mut terminal, &mut line, "x").unwrap();
+        assert_eq!(line.buffer.as_str(), "axc");
+        assert_eq!(terminal.screen_as_string(), "> axc");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 4));
+
+        // Appends at end of line
+        advance(&mut terminal, &mut line, "yz").unwrap();
+        assert_eq!(line.buffer.as_str(), "axyz");
+        assert_eq!(terminal.screen_as_string(), "> axyz");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 6));
+
+        advance(&mut terminal, &mut line, CtrlA).unwrap();
+        advance(&mut terminal, &mut line, "æø").unwrap();
+        assert_eq!(line.buffer.as_str(), "æøyz");
+        assert_eq!(terminal.screen_as_string(), "> æøyz");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 4));
+
+        advance(&mut terminal, &mut line, csi::LEFT).unwrap();
+        advance(&mut terminal, &mut line, "o").unwrap();
+        assert_eq!(line.buffer.as_str(), "æoyz");
+        assert_eq!(terminal.screen_as_string(), "> æoyz");
+
+        // Insert toggles back to inserting
+        advance(&mut terminal, &mut line, csi::INSERT).unwrap();
+        advance(&mut terminal, &mut line, "å").unwrap();
+        assert_eq!(line.buffer.as_str(), "æoåyz");
+        assert_eq!(terminal.screen_as_string(), "> æoåyz");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 5));
+
+        advance(&mut terminal, &mut line, csi::INSERT).unwrap();
+        advance(&mut terminal, &mut line, "\r").unwrap();
+
+        // Next line starts in insert mode
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        advance(&mut terminal, &mut line, "ab").unwrap();
+        advance(&mut terminal, &mut line, csi::LEFT).unwrap();
+        advance(&mut terminal, &mut line, "c").unwrap();
+        assert_eq!(line.buffer.as_str(), "acb");
+    }
+
+    #[cfg(feature = "ascii-only")]
+    #[test]
+    fn ascii_only() {
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(1, 40, Cursor::new(0, 0));
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        advance(&mut terminal, &mut line, "abc").unwrap();
+        assert!(advance(&mut terminal, &mut line, "æ").is_err());
+        assert_eq!(line.buffer.as_str(), "abc");
+        assert_eq!(terminal.screen_as_string(), "> abc");
+    }
+
+    #[test]
+    fn bracketed_paste() {
+        const PASTE_START: &str = "\x1b[200~";
+        const PASTE_END: &str = "\x1b[201~";
+
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 40, Cursor::new(0, 0));
+        editor.settings.bracketed_paste = true;
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+        assert!(terminal.bracketed_paste);
+
+        // Newlines in pasted text don't end the line
+        advance(
+            &mut terminal,
+            &mut line,
+            [PASTE_START, "line1\nline2", PASTE_END],
+        )
+        .unwrap();
+        assert_eq!(line.buffer.as_str(), "line1 line2");
+        assert_eq!(terminal.screen_as_string(), "> line1 line2");
+
+        // CR LF is a single newline, tabs are expanded and other
+        // control characters and sequences are dropped
+        advance(
+            &mut terminal,
+            &mut line,
+            [PASTE_START, "\r\na\x03\x1b[Db\tc\r", PASTE_END],
+        )
+        .unwrap();
+        assert_eq!(line.buffer.as_str(), "line1 line2 ab    c ");
+
+        advance(&mut terminal, &mut line, "\r").unwrap();
+        assert!(!terminal.bracketed_paste);
+        assert_eq!(terminal.screen_as_string(), "> line1 line2 ab    c ");
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 0));
+
+        // Marked paste is handled also when not enabled by noline
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 40, Cursor::new(0, 0));
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+        assert!(!terminal.bracketed_paste);
+
+        advance(&mut terminal, &mut line, [PASTE_START, "x\ny", PASTE_END]).unwrap();
+        assert_eq!(line.buffer.as_str(), "x y");
+    }
+
+    #[test]
+    fn masked_template() {
+        let prompt = "IP: ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 40, Cursor::new(0, 0));
+        let mask = Mask::new("___.___.___.___", '_', |c, _| c.is_ascii_digit());
+
+        let mut line = editor.get_line_masked(prompt, &mask, &mut terminal);
+        assert_eq!(terminal.screen_as_string(), "IP: ___.___.___.___");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 4));
+
+        // Cursor skips the dots
+        advance(&mut terminal, &mut line, "192168").unwrap();
+        assert_eq!(terminal.screen_as_string(), "IP: 192.168.___.___");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 12));
+
+        assert!(advance(&mut terminal, &mut line, "a").is_err());
+        assert_eq!(terminal.screen_as_string(), "IP: 192.168.___.___");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 12));
+
+        advance(&mut terminal, &mut line, [Backspace, Backspace]).unwrap();
+        assert_eq!(terminal.screen_as_string(), "IP: 192.1__.___.___");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 9));
+
+        // Backspace across a dot
+        advance(&mut terminal, &mut line, [Backspace, Backspace]).unwrap();
+        assert_eq!(terminal.screen_as_string(), "IP: 19_.___.___.___");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 6));
+
+        // Movement and delete don't shift the rest of the line
+        advance(&mut terminal, &mut line, "2100").unwrap();
+        advance(&mut terminal, &mut line, [CtrlA, CtrlF, CtrlF, CtrlF]).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 8));
+        advance(&mut terminal, &mut line, [csi::LEFT, csi::DELETE]).unwrap();
+        assert_eq!(terminal.screen_as_string(), "IP: 19_.100.___.___");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 6));
+
+        advance(&mut terminal, &mut line, "2").unwrap();
+        advance(&mut terminal, &mut line, CtrlE).unwrap();
+        assert!(advance(&mut terminal, &mut line, "0").is_err());
+        assert!(advance(&mut terminal, &mut line, csi::RIGHT).is_err());
+        advance(&mut terminal, &mut line, Backspace).unwrap();
+        advance(&mut terminal, &mut line, "1").unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 19));
+        assert_eq!(terminal.screen_as_string(), "IP: 192.100.___.__1");
+
+        advance(&mut terminal, &mut line, "\r").unwrap();
+        assert_eq!(line.buffer.as_str(), "192.100.___.__1");
+    }
+
+    #[test]
+    fn padded_erase() {
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 10, Cursor::new(0, 0));
+        editor.settings.padded_erase = true;
+        terminal.ignore_erase = true;
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        advance(&mut terminal, &mut line, "abcdefgh").unwrap();
+        advance(&mut terminal, &mut line, CtrlU).unwrap();
+        assert_eq!(terminal.screen_as_string(), ">         ");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 2));
+
+        // Deleting in the middle pads the freed cell
+        advance(&mut terminal, &mut line, "abcdef").unwrap();
+        advance(&mut terminal, &mut line, [csi::LEFT, csi::LEFT, csi::LEFT]).unwrap();
+        advance(&mut terminal, &mut line, Backspace).unwrap();
+        assert_eq!(terminal.row_as_string(0), "> abdef");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 4));
+
+        // Padding continues on the next row when the line wraps
+        advance(&mut terminal, &mut line, CtrlU).unwrap();
+        advance(&mut terminal, &mut line, "abcdefghijklmno").unwrap();
+        assert_eq!(terminal.screen_as_string(), "> abcdefgh\nijklmno");
+        advance(&mut terminal, &mut line, [CtrlA, CtrlK]).unwrap();
+        assert_eq!(terminal.row_as_string(0), ">");
+        assert_eq!(terminal.row_as_string(1), "");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 2));
+
+        advance(&mut terminal, &mut line, "xyz").unwrap();
+        assert_eq!(terminal.row_as_string(0), "> xyz");
+
+        // The row is erased when starting a line, as the length of the
+        // text on it is unknown
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 10, Cursor::new(0, 0));
+        editor.settings.padded_erase = true;
+        terminal.ignore_erase = true;
+
+        for &byte in b"old text\r" {
+            terminal.advance(byte);
+        }
+
+        editor.get_line(prompt, &mut terminal);
+        assert_eq!(terminal.screen_as_string(), "> ");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 2));
+    }
+
+    #[test]
+    fn fixed_terminal_size() {
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 10, Cursor::new(0, 0));
+        editor.settings.fixed_size = NonZeroU16::new(4).zip(NonZeroU16::new(10));
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+        assert_eq!(line.terminal.rows(), 4);
+
+        advance(&mut terminal, &mut line, "abcdefghijklmnopqrstu").unwrap();
+        assert_eq!(terminal.screen_as_string(), "> abcdefgh\nijklmnopqr\nstu");
+        assert_eq!(terminal.get_cursor(), Cursor::new(2, 3));
+
+        advance(&mut terminal, &mut line, CtrlA).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 2));
+
+        advance(&mut terminal, &mut line, [csi::RIGHT, csi::RIGHT]).unwrap();
+        advance(&mut terminal, &mut line, "X").unwrap();
+        assert_eq!(terminal.screen_as_string(), "> abXcdefg\nhijklmnopq\nrstu");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 5));
+
+        advance(&mut terminal, &mut line, [CtrlE, Backspace]).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> abXcdefg\nhijklmnopq\nrst");
+        assert_eq!(terminal.get_cursor(), Cursor::new(2, 3));
+
+        // Clearing the screen prints the line again in place
+        advance(&mut terminal, &mut line, CtrlL).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> abXcdefg\nhijklmnopq\nrst");
+        assert_eq!(terminal.get_cursor(), Cursor::new(2, 3));
+    }
+
+    #[test]
+    fn overwrite_full_buffer() {
+        let mut array = [0; 4];
+        let mut terminal = MockTerminal::new(20, 80, Cursor::new(0, 0));
+        let mut editor: Editor<_, NoHistory> =
+            Editor::new(LineBuffer::from_slice(&mut array), NoHistory {});
+
+        let mut line = editor.get_line("> ", &mut terminal);
+
+        advance(&mut terminal, &mut line, "abcd").unwrap();
+        advance(&mut terminal, &mut line, [csi::HOME, csi::INSERT]).unwrap();
+
+        advance(&mut terminal, &mut line, "x").unwrap();
+        assert_eq!(line.buffer.as_str(), "xbcd");
+        assert_eq!(terminal.screen_as_string(), "> xbcd");
+
+        // Multi-byte char doesn't fit, line is kept
+        assert!(advance(&mut terminal, &mut line, "æ").is_err());
+        assert_eq!(line.buffer.as_str(), "xbcd");
+        assert_eq!(terminal.screen_as_string(), "> xbcd");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 3));
+    }
+
+    #[test]
+    fn backspace() {
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(1, 40, Cursor::new(0, 0));
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        assert!(advance(&mut terminal, &mut line, Backspace).is_err());
+
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 2));
+        assert_eq!(terminal.screen_as_string(), "> ");
+        advance(&mut terminal, &mut line, "hello").unwrap();
+
+        advance(&mut terminal, &mut line, Backspace).unwrap();
+        assert_eq!(line.buffer.as_str(), "hell");
+        assert_eq!(terminal.screen_as_string(), "> hell");
+
+        advance(&mut terminal, &mut line, [csi::LEFT; 2]).unwrap();
+        advance(&mut terminal, &mut line, Backspace).unwrap();
+        assert_eq!(line.buffer.as_str(), "hll");
+        assert_eq!(terminal.screen_as_string(), "> hll");
+
+        advance(&mut terminal, &mut line, CtrlA).unwrap();
+        advance(&mut terminal, &mut line, Backspace).unwrap_err();
+    }
+
+    #[test]
+    fn slice_buffer() {
+        let mut array = [0; 20];
+        let mut terminal = MockTerminal::new(20, 80, Cursor::new(0, 0));
+        let mut editor: Editor<_, NoHistory> =
+            Editor::new(LineBuffer::from_slice(&mut array), NoHistory {});
+
+        let mut line = editor.get_line("> ", &mut terminal);
+
+        let input: String = (0..20).map(|_| "a").collect();
+
+        advance(&mut terminal, &mut line, input.as_str()).unwrap();
+
+        assert_eq!(advance(&mut terminal, &mut line, "a"), Err(()));
+
+        assert_eq!(line.buffer.as_str(), input);
+
+        advance(&mut terminal, &mut line, Backspace).unwrap();
+    }
+
+    #[test]
+    fn history() {
+        fn test<H: History>(history: H) {
+            let mut terminal = MockTerminal::new(20, 80, Cursor::new(0, 0));
+            let mut editor: Editor<_, H> = Editor::new(LineBuffer::new_unbounded(), history);
+
+            let mut line = editor.get_line("> ", &mut terminal);
+
+            advance(&mut terminal, &mut line, "this is a line\r").unwrap();
+
+            let mut line = editor.get_line("> ", &mut terminal);
+
+            assert_eq!(terminal.screen_as_string(), "> this is a line\n> ");
+
+            assert!(advance(&mut terminal, &mut line, csi::UP).is_ok());
+
+            assert_eq!(
+                terminal.screen_as_string(),
+                "> this is a line\n> this is a line"
+            );
+
+            assert!(advance(&mut terminal, &mut line, csi::DOWN).is_ok());
+
+            assert_eq!(terminal.screen_as_string(), "> this is a line\n> ");
+
+            advance(&mut terminal, &mut line, "another line\r").unwrap();
+
+            let mut line = editor.get_line("> ", &mut terminal);
+            advance(&mut terminal, &mut line, "yet another line\r").unwrap();
+
+            let mut line = editor.get_line("> ", &mut terminal);
+
+            assert_eq!(
+                terminal.screen_as_string(),
+                "> this is a line\n> another line\n> yet another line\n> "
+            );
+
+            assert!(advance(&mut terminal, &mut line, csi::UP).is_ok());
+
+            assert_eq!(
+                terminal.screen_as_string(),
+                "> this is a line\n> another line\n> yet another line\n> yet another line"
+            );
+
+            assert!(advance(&mut terminal, &mut line, csi::UP).is_ok());
+
+            assert_eq!(
+                terminal.screen_as_string(),
+                "> this is a line\n> another line\n> yet another line\n> another line"
+            );
+
+            assert!(advance(&mut terminal, &mut line, csi::UP).is_ok());
+
+            assert_eq!(
+                terminal.screen_as_string(),
+                "> this is a line\n> another line\n> yet another line\n> this is a line"
+            );
+
+            assert!(advance(&mut terminal, &mut line, csi::UP).is_err());
+
+            assert_eq!(
+                terminal.screen_as_string(),
+                "> this is a line\n> another line\n> yet another line\n> this is a line"
+            );
+
+            assert!(advance(&mut terminal, &mut line, csi::DOWN).is_ok());
+
+            assert_eq!(
+                terminal.screen_as_string(),
+                "> this is a line\n> another line\n> yet another line\n> another line"
+            );
+
+            assert!(advance(&mut terminal, &mut line, csi::DOWN).is_ok());
+
+            assert_eq!(
+                terminal.screen_as_string(),
+                "> this is a line\n> another line\n> yet another line\n> yet another line"
+            );
+            assert!(advance(&mut terminal, &mut line, csi::DOWN).is_ok());
+
+            assert_eq!(
+                terminal.screen_as_string(),
+                "> this is a line\n> another line\n> yet another line\n> "
+            );
+
+            assert!(advance(&mut terminal, &mut line, csi::DOWN).is_err());
+
+            assert_eq!(
+                terminal.screen_as_string(),
+                "> this is a line\n> another line\n> yet another line\n> "
+            );
+
+            // Repeated lines are stored once
+            advance(&mut terminal, &mut line, "another line\r").unwrap();
+
+            let mut line = editor.get_line("> ", &mut terminal);
+            advance(&mut terminal, &mut line, "another line\r").unwrap();
+
+            assert_eq!(editor.history.number_of_entries(), 4);
+        }
+
+        test(UnboundedHistory::new());
+        let mut buffer = [0; 128];
+        test(SliceHistory::new(&mut buffer));
+        test(StaticSlotHistory::<8, 32>::new());
+    }
+
+    #[test]
+    fn ss3_arrows() {
+        // Cursor keys in application mode
+        const UP: &str = "\x1bOA";
+        const DOWN: &str = "\x1bOB";
+        const LEFT: &str = "\x1bOD";
+
+        let mut terminal = MockTerminal::new(20, 80, Cursor::new(0, 0));
+        let mut editor = Editor::new(LineBuffer::new_unbounded(), UnboundedHistory::new());
+
+        for entry in ["first", "second"] {
+            let mut line = editor.get_line("> ", &mut terminal);
+            advance(&mut terminal, &mut line, [entry, "\r"]).unwrap();
+        }
+
+        let mut line = editor.get_line("> ", &mut terminal);
+
+        advance(&mut terminal, &mut line, [UP, UP]).unwrap();
+        assert_eq!(terminal.current_line_as_string(), "> first");
+
+        advance(&mut terminal, &mut line, DOWN).unwrap();
+        assert_eq!(terminal.current_line_as_string(), "> second");
+
+        // F1 is unknown and leaves the line as it is
+        assert!(advance(&mut terminal, &mut line, "\x1bOP").is_err());
+
+        advance(&mut terminal, &mut line, [LEFT, "X", "\r"]).unwrap();
+        assert_eq!(editor.buffer.as_str(), "seconXd");
+    }
+
+    #[test]
+    fn preserve_buffer() {
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 20, Cursor::new(0, 0));
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+        advance(&mut terminal, &mut line, "rm -rf\r").unwrap();
+
+        let mut line = editor.get_line_preserving_buffer(prompt, &mut terminal);
+
+        assert_eq!(line.buffer.as_str(), "rm -rf");
+        assert_eq!(terminal.screen_as_string(), "> rm -rf\n> rm -rf");
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 8));
+        assert_eq!(line.terminal.get_cursor(), terminal.get_cursor());
+
+        advance(&mut terminal, &mut line, [Backspace, Backspace, Backspace]).unwrap();
+        advance(&mut terminal, &mut line, "*\r").unwrap();
+
+        assert_eq!(terminal.screen_as_string(), "> rm -rf\n> rm *");
+
+        let line = editor.get_line(prompt, &mut terminal);
+
+        assert_eq!(line.buffer.as_str(), "");
+        assert_eq!(terminal.screen_as_string(), "> rm -rf\n> rm *\n> ");
+    }
+
+    #[test]
+    fn reverse_search() {
+        fn test<H: History>(mut history: H) {
+            let mut terminal = MockTerminal::new(20, 80, Cursor::new(0, 0));
+
+            history.load_entries(["ls -l", "cd /tmp", "cat æøå", "ls /tmp"].into_iter());
+
+            let mut editor: Editor<_, H> = Editor::new(LineBuffer::new_unbounded(), history);
+            let mut line = editor.get_line("> ", &mut terminal);
+
+            advance(&mut terminal, &mut line, "old").unwrap();
+
+            advance(&mut terminal, &mut line, CtrlR).unwrap();
+            assert_eq!(terminal.screen_as_string(), "(reverse-i-search)`': ");
+
+            advance(&mut terminal, &mut line, "ls").unwrap();
+            assert_eq!(
+                terminal.screen_as_string(),
+                "(reverse-i-search)`ls': ls /tmp"
+            );
+
+            advance(&mut terminal, &mut line, CtrlR).unwrap();
+            assert_eq!(terminal.screen_as_string(), "(reverse-i-search)`ls': ls -l");
+            assert_eq!(terminal.get_cursor(), Cursor::new(0, 29));
+
+            assert!(advance(&mut terminal, &mut line, CtrlR).is_err());
+            assert!(advance(&mut terminal, &mut line, "x").is_err());
+            assert_eq!(terminal.screen_as_string(), "(reverse-i-search)`ls': ls -l");
+
+            advance(&mut terminal, &mut line, Backspace).unwrap();
+            assert_eq!(terminal.screen_as_string(), "(reverse-i-search)`l': ls -l");
+
+            // Abort restores original line
+            advance(&mut terminal, &mut line, CtrlG).unwrap();
+            assert_eq!(terminal.screen_as_string(), "> old");
+            assert_eq!(terminal.get_cursor(), Cursor::new(0, 5));
+            assert_eq!(line.buffer.as_str(), "old");
+
+            #[cfg(not(feature = "ascii-only"))]
+            {
+                advance(&mut terminal, &mut line, CtrlR).unwrap();
+                advance(&mut terminal, &mut line, "æ").unwrap();
+                assert_eq!(
+                    terminal.screen_as_string(),
+                    "(reverse-i-search)`æ': cat æøå"
+                );
+
+                advance(&mut terminal, &mut line, "\x1b\x1b").unwrap();
+                assert_eq!(terminal.screen_as_string(), "> old");
+                assert_eq!(line.buffer.as_str(), "old");
+            }
+
+            // Accept match and continue editing
+            advance(&mut terminal, &mut line, [CtrlA, CtrlK]).unwrap();
+            advance(&mut terminal, &mut line, CtrlR).unwrap();
+            advance(&mut terminal, &mut line, "tmp").unwrap();
+            advance(&mut terminal, &mut line, CtrlR).unwrap();
+            assert_eq!(
+                terminal.screen_as_string(),
+                "(reverse-i-search)`tmp': cd /tmp"
+            );
+
+            advance(&mut terminal, &mut line, CtrlE).unwrap();
+            assert_eq!(terminal.screen_as_string(), "> cd /tmp");
+            assert_eq!(terminal.get_cursor(), Cursor::new(0, 9));
+            assert_eq!(line.buffer.as_str(), "cd /tmp");
+
+            advance(&mut terminal, &mut line, [CtrlB; 4]).unwrap();
+            advance(&mut terminal, &mut line, "/var").unwrap();
+            assert_eq!(terminal.screen_as_string(), "> cd /var/tmp");
+            assert_eq!(line.buffer.as_str(), "cd /var/tmp");
+
+            // Enter accepts the match and submits the line
+            advance(&mut terminal, &mut line, [CtrlA, CtrlK]).unwrap();
+            advance(&mut terminal, &mut line, CtrlR).unwrap();
+            advance(&mut terminal, &mut line, "cd").unwrap();
+            advance(&mut terminal, &mut line, CarriageReturn).unwrap();
+            assert_eq!(line.buffer.as_str(), "cd /tmp");
+            assert_eq!(terminal.screen_as_string(), "> cd /tmp");
+            assert_eq!(terminal.get_cursor(), Cursor::new(1, 0));
+        }
+
+        test(UnboundedHistory::new());
+        let mut buffer = [0; 40];
+        test(SliceHistory::new(&mut buffer));
+        test(StaticSlotHistory::<8, 32>::new());
+
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 40, Cursor::new(0, 0));
+        let mut line = editor.get_line("> ", &mut terminal);
+
+        advance(&mut terminal, &mut line, CtrlR).unwrap();
+        assert!(advance(&mut terminal, &mut line, "a").is_err());
+
+        advance(&mut terminal, &mut line, CarriageReturn).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> ");
+
+        // The match is kept out of the buffer until it's accepted, so
+        // it only has to fit on its own
+        let mut array = [0; 16];
+        let mut history = UnboundedHistory::new();
+
+        history.load_entries(["0123456789abcdef"].into_iter());
+
+        let mut terminal = MockTerminal::new(4, 40, Cursor::new(0, 0));
+        let mut editor = Editor::new(LineBuffer::from_slice(&mut array), history);
+        let mut line = editor.get_line("> ", &mut terminal);
+
+        advance(&mut terminal, &mut line, "abcdefgh").unwrap();
+        advance(&mut terminal, &mut line, CtrlR).unwrap();
+        advance(&mut terminal, &mut line, "0").unwrap();
+        assert_eq!(line.buffer.as_str(), "abcdefgh");
+        assert_eq!(line.buffer.remaining_capacity(), Some(8));
+
+        advance(&mut terminal, &mut line, CtrlE).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> 0123456789abcdef");
+        assert_eq!(line.buffer.as_str(), "0123456789abcdef");
+    }
+
+    #[cfg(not(feature = "ascii-only"))]
+    #[test]
+    fn yank_last_arg() {
+        const ALT_DOT: &str = "\x1b.";
+
+        let mut terminal = MockTerminal::new(4, 20, Cursor::new(0, 0));
+        let mut history = UnboundedHistory::new();
+
+        history.load_entries(["mkdir /tmp/x", "echo blåbær", "cp a b.txt  "].into_iter());
+
+        let mut editor = Editor::new(LineBuffer::new_unbounded(), history);
+        let mut line = editor.get_line("> ", &mut terminal);
+
+        advance(&mut terminal, &mut line, "cat ").unwrap();
+        advance(&mut terminal, &mut line, ALT_DOT).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> cat b.txt");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 11));
+
+        // Repeated presses replace the word with one from older entries
+        advance(&mut terminal, &mut line, ALT_DOT).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> cat blåbær");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 12));
+
+        advance(&mut terminal, &mut line, ALT_DOT).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> cat /tmp/x");
+
+        assert!(advance(&mut terminal, &mut line, ALT_DOT).is_err());
+        assert_eq!(line.buffer.as_str(), "cat /tmp/x");
+
+        // Other keys start over from the previous entry
+        advance(&mut terminal, &mut line, " ").unwrap();
+        advance(&mut terminal, &mut line, ALT_DOT).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> cat /tmp/x b.txt");
+
+        advance(&mut terminal, &mut line, CtrlA).unwrap();
+        advance(&mut terminal, &mut line, [ALT_DOT, ALT_DOT]).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> blåbærcat /tmp/x b\n.txt");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 8));
+
+        advance(&mut terminal, &mut line, ALT_DOT).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> /tmp/xcat /tmp/x b\n.txt");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 8));
+
+        // The new word must fit with the old one still in the buffer
+        let mut array = [0; 8];
+        let mut history = UnboundedHistory::new();
+
+        history.load_entries(["echo abcde", "echo x"].into_iter());
+
+        let mut editor = Editor::new(LineBuffer::from_slice(&mut array), history);
+        let mut line = editor.get_line("> ", &mut terminal);
+
+        advance(&mut terminal, &mut line, "ab ").unwrap();
+        advance(&mut terminal, &mut line, ALT_DOT).unwrap();
+        assert!(advance(&mut terminal, &mut line, ALT_DOT).is_err());
+        assert_eq!(line.buffer.as_str(), "ab x");
+    }
+
+    #[test]
+    fn history_entry_longer_than_buffer() {
+        let mut terminal = MockTerminal::new(20, 80, Cursor::new(0, 0));
+        let mut array = [0; 32];
+        let mut history_buffer = [0; 256];
+        let mut history = SliceHistory::new(&mut history_buffer);
+
+        let long_entry = "x".repeat(31) + &"æøå".repeat(23);
+
+        assert_eq!(long_entry.chars().count(), 100);
+        assert_eq!(
+            history.load_entries([long_entry.as_str(), "short"].into_iter()),
+            2
+        );
+
+        let mut editor = Editor::new(LineBuffer::from_slice(&mut array), history);
+        let mut line = editor.get_line("> ", &mut terminal);
+        let truncated = "x".repeat(31);
+
+        advance(&mut terminal, &mut line, csi::UP).unwrap();
+        assert_eq!(line.buffer.as_str(), "short");
+
+        advance(&mut terminal, &mut line, csi::UP).unwrap();
+        assert_eq!(line.buffer.as_str(), truncated);
+        assert_eq!(terminal.screen_as_string(), format!("> {}", truncated));
+
+        advance(&mut terminal, &mut line, csi::DOWN).unwrap();
+        assert_eq!(line.buffer.as_str(), "short");
+
+        advance(&mut terminal, &mut line, csi::UP).unwrap();
+        assert_eq!(line.buffer.as_str(), truncated);
+
+        // Editing the truncated entry is limited by buffer capacity
+        advance(&mut terminal, &mut line, "y").unwrap();
+        assert!(advance(&mut terminal, &mut line, "z").is_err());
+        assert_eq!(line.buffer.as_str(), truncated.clone() + "y");
+
+        advance(&mut terminal, &mut line, [Backspace, Backspace]).unwrap();
+        advance(&mut terminal, &mut line, csi::HOME).unwrap();
+        advance(&mut terminal, &mut line, "a").unwrap();
+
+        assert_eq!(line.buffer.as_str(), "a".to_string() + &"x".repeat(30));
+        assert_eq!(
+            terminal.screen_as_string(),
+            format!("> a{}", "x".repeat(30))
+        );
+    }
+
+    #[test]
+    fn complete() {
+        struct Fruit;
+
+        impl Completer for Fruit {
+            fn complete(&self, line: &str, index: usize) -> Option<&str> {
+                ["apple", "apricot", "banana"]
+                    .into_iter()
+                    .filter_map(|fruit| fruit.strip_prefix(line))
+                    .nth(index)
+            }
+        }
+
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 20, Cursor::new(0, 0));
+
+        editor.completer = Some(&Fruit);
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        advance(&mut terminal, &mut line, "ap\t").unwrap();
+        assert_eq!(terminal.screen_as_string(), "> apple");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 7));
+
+        advance(&mut terminal, &mut line, Tab).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> apricot");
+
+        advance(&mut terminal, &mut line, Tab).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> apple");
+
+        // Other keys reset the cycler
+        advance(&mut terminal, &mut line, [Backspace, Tab]).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> apple");
+
+        advance(&mut terminal, &mut line, "s").unwrap();
+        assert!(advance(&mut terminal, &mut line, Tab).is_err());
+        assert_eq!(line.buffer.as_str(), "apples");
+        assert_eq!(terminal.screen_as_string(), "> apples");
+
+        advance(&mut terminal, &mut line, CtrlU).unwrap();
+        advance(&mut terminal, &mut line, [Tab, Tab, Tab]).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> banana");
+    }
+
+    #[test]
+    fn mouse_and_focus_ignored() {
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 20, Cursor::new(0, 0));
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        // Mouse reports and focus events between keys don't ring the
+        // bell or end up in the line
+        advance(
+            &mut terminal,
+            &mut line,
+            [
+                "ab",
+                "\x1b[<35;10;4M",
+                "c",
+                "\x1b[M !!",
+                "\x1b[I",
+                csi::LEFT,
+                "\x1b[<0;3;1m",
+                "d",
+                "\x1b[O",
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(line.buffer.as_str(), "abdc");
+        assert_eq!(terminal.screen_as_string(), "> abdc");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 5));
+    }
+
+    #[test]
+    fn complete_backwards() {
+        struct Fruit;
+
+        impl Completer for Fruit {
+            fn complete(&self, line: &str, index: usize) -> Option<&str> {
+                ["apple", "apricot", "avocado"]
+                    .into_iter()
+                    .filter_map(|fruit| fruit.strip_prefix(line))
+                    .nth(index)
+            }
+        }
+
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 20, Cursor::new(0, 0));
+
+        editor.completer = Some(&Fruit);
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        advance(&mut terminal, &mut line, ["a\t", "\t"]).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> apricot");
+
+        advance(&mut terminal, &mut line, csi::SHIFT_TAB).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> apple");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 7));
+
+        // Wraps around to the last candidate
+        advance(&mut terminal, &mut line, csi::SHIFT_TAB).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> avocado");
+
+        // Starts from the last candidate
+        advance(&mut terminal, &mut line, [CtrlU]).unwrap();
+        advance(&mut terminal, &mut line, ["ap", csi::SHIFT_TAB]).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> apricot");
+
+        advance(&mut terminal, &mut line, csi::SHIFT_TAB).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> apple");
+
+        // Other keys reset the cycler
+        advance(&mut terminal, &mut line, [Backspace; 3]).unwrap();
+        advance(&mut terminal, &mut line, csi::SHIFT_TAB).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> apricot");
+
+        advance(&mut terminal, &mut line, CtrlU).unwrap();
+        assert!(advance(&mut terminal, &mut line, ["b", csi::SHIFT_TAB]).is_err());
+        assert_eq!(terminal.screen_as_string(), "> b");
+    }
+
+    #[test]
+    fn fn_completer() {
+        let files = FnCompleter::<_, 8>::new(|line, index, out| {
+            if index < 10 {
+                let name = std::format!("file{}", index);
+
+                if let Some(candidate) = name.strip_prefix(line) {
+                    out(candidate);
+                }
+            }
+        });
+
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 20, Cursor::new(0, 0));
+
+        editor.completer = Some(Box::leak(Box::new(files)));
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        advance(&mut terminal, &mut line, "f\t\t").unwrap();
+        assert_eq!(line.buffer.as_str(), "file1");
+        assert_eq!(terminal.screen_as_string(), "> file1");
+
+        line.settings.completion_preview = true;
+
+        advance(&mut terminal, &mut line, CtrlU).unwrap();
+        advance(&mut terminal, &mut line, "fi\t").unwrap();
+        assert_eq!(line.buffer.as_str(), "fi");
+        assert_eq!(terminal.screen_as_string(), "> file0");
+        assert_eq!(terminal.highlighted_as_string(), "le0");
+
+        advance(&mut terminal, &mut line, Backspace).unwrap();
+        assert_eq!(line.buffer.as_str(), "fi");
+        assert_eq!(terminal.screen_as_string(), "> fi");
+
+        advance(&mut terminal, &mut line, "\t\t").unwrap();
+        assert_eq!(line.buffer.as_str(), "file0");
+        assert_eq!(terminal.highlighted_as_string(), "");
+    }
+
+    #[test]
+    fn completion_preview_wraps() {
+        let long = FnCompleter::<_, 64>::new(|line, index, out| {
+            let name = std::format!("{}_computed_on_the_fly", "long_candidate");
+
+            if index == 0 {
+                if let Some(candidate) = name.strip_prefix(line) {
+                    out(candidate);
+                }
+            }
+        });
+
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 20, Cursor::new(0, 0));
+
+        editor.completer = Some(Box::leak(Box::new(long)));
+        editor.settings.completion_preview = true;
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        // Preview is printed a chunk at a time and wraps like the line
+        advance(&mut terminal, &mut line, "lo\t").unwrap();
+        assert_eq!(line.buffer.as_str(), "lo");
+        assert_eq!(
+            terminal.screen_as_string(),
+            "> long_candidate_com\nputed_on_the_fly"
+        );
+        assert_eq!(
+            terminal.highlighted_as_string(),
+            "ng_candidate_computed_on_the_fly"
+        );
+
+        advance(&mut terminal, &mut line, "!").unwrap();
+        assert_eq!(line.buffer.as_str(), "long_candidate_computed_on_the_fly!");
+        assert_eq!(
+            terminal.screen_as_string(),
+            "> long_candidate_com\nputed_on_the_fly!"
+        );
+    }
+
+    #[test]
+    fn completion_preview() {
+        struct Fruit;
+
+        impl Completer for Fruit {
+            fn complete(&self, line: &str, index: usize) -> Option<&str> {
+                ["apple", "banana"]
+                    .into_iter()
+                    .filter_map(|fruit| fruit.strip_prefix(line))
+                    .nth(index)
+            }
+        }
+
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 20, Cursor::new(0, 0));
+
+        editor.completer = Some(&Fruit);
+        editor.settings.completion_preview = true;
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        // Second Tab commits
+        advance(&mut terminal, &mut line, "ap\t").unwrap();
+        assert_eq!(line.buffer.as_str(), "ap");
+        assert_eq!(terminal.screen_as_string(), "> apple");
+        assert_eq!(terminal.highlighted_as_string(), "ple");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 7));
+
+        advance(&mut terminal, &mut line, Tab).unwrap();
+        assert_eq!(line.buffer.as_str(), "apple");
+        assert_eq!(terminal.screen_as_string(), "> apple");
+        assert_eq!(terminal.highlighted_as_string(), "");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 7));
+
+        // Backspace discards
+        advance(&mut terminal, &mut line, CtrlU).unwrap();
+        advance(&mut terminal, &mut line, "b\t").unwrap();
+        assert_eq!(line.buffer.as_str(), "b");
+        assert_eq!(terminal.screen_as_string(), "> banana");
+        assert_eq!(terminal.highlighted_as_string(), "anana");
+
+        advance(&mut terminal, &mut line, Backspace).unwrap();
+        assert_eq!(line.buffer.as_str(), "b");
+        assert_eq!(terminal.screen_as_string(), "> b");
+        assert_eq!(terminal.highlighted_as_string(), "");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 3));
+
+        // Preview is shown at the end of the line, Escape discards it
+        advance(&mut terminal, &mut line, [csi::LEFT, "\t"]).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> banana");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 8));
+
+        advance(&mut terminal, &mut line, "\x1b").unwrap();
+        assert_eq!(terminal.screen_as_string(), "> banana");
+
+        advance(&mut terminal, &mut line, "x").unwrap();
+        assert_eq!(line.buffer.as_str(), "b");
+        assert_eq!(terminal.screen_as_string(), "> b");
+        // Cursor is restored to where it was before Tab
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 2));
+
+        // Printable commits and is appended
+        advance(&mut terminal, &mut line, "\ts").unwrap();
+        assert_eq!(line.buffer.as_str(), "bananas");
+        assert_eq!(terminal.screen_as_string(), "> bananas");
+        assert_eq!(terminal.highlighted_as_string(), "");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 9));
+
+        // No candidate
+        assert!(advance(&mut terminal, &mut line, Tab).is_err());
+        assert_eq!(line.buffer.as_str(), "bananas");
+        assert_eq!(terminal.screen_as_string(), "> bananas");
+
+        // Other keys discard the preview and are handled, so Enter
+        // submits the line as typed
+        advance(&mut terminal, &mut line, [CtrlU]).unwrap();
+        advance(&mut terminal, &mut line, "ap\t").unwrap();
+        advance(&mut terminal, &mut line, CarriageReturn).unwrap();
+        assert_eq!(line.buffer.as_str(), "ap");
+        assert_eq!(terminal.screen_as_string(), "> ap");
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 0));
+
+        // A printable that doesn't fit after the candidate rings the
+        // bell, keeping the candidate
+        let mut array = [0; 5];
+        let mut editor: Editor<_, _> =
+            Editor::new(LineBuffer::from_slice(&mut array), UnboundedHistory::new());
+
+        editor.completer = Some(&Fruit);
+        editor.settings.completion_preview = true;
+
+        let mut terminal = MockTerminal::new(4, 20, Cursor::new(0, 0));
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        advance(&mut terminal, &mut line, "ap\t").unwrap();
+        assert!(advance(&mut terminal, &mut line, "s").is_err());
+        assert_eq!(line.buffer.as_str(), "apple");
+        assert_eq!(terminal.screen_as_string(), "> apple");
+        assert_eq!(terminal.highlighted_as_string(), "");
+    }
+
+    #[test]
+    fn completion_list() {
+        struct Fruit;
+
+        impl Completer for Fruit {
+            fn complete(&self, line: &str, index: usize) -> Option<&str> {
+                let word = line.rsplit(' ').next().unwrap();
+
+                ["apple", "apricot", "avocado", "banana"]
+                    .into_iter()
+                    .filter_map(|fruit| fruit.strip_prefix(word))
+                    .nth(index)
+            }
+        }
+
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(6, 20, Cursor::new(0, 0));
+
+        editor.completer = Some(&Fruit);
+        editor.settings.completion_list = true;
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        // No shared prefix to insert
+        assert!(advance(&mut terminal, &mut line, "eat a\t").is_err());
+        assert_eq!(terminal.screen_as_string(), "> eat a");
+
+        // Candidates are listed after the word being completed, and
+        // the line is printed again below them
+        advance(&mut terminal, &mut line, Tab).unwrap();
+        assert_eq!(
+            terminal.screen_as_string(),
+            "> eat a\napple    apricot\navocado\n> eat a"
+        );
+        assert_eq!(terminal.get_cursor(), Cursor::new(3, 7));
+
+        // Editing continues on the new line
+        advance(&mut terminal, &mut line, "v\t").unwrap();
+        assert_eq!(line.buffer.as_str(), "eat avocado");
+        assert_eq!(terminal.row_as_string(3), "> eat avocado");
+        assert_eq!(terminal.get_cursor(), Cursor::new(3, 13));
+
+        advance(&mut terminal, &mut line, [CtrlA, CtrlF]).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(3, 3));
+
+        advance(&mut terminal, &mut line, CarriageReturn).unwrap();
+        assert_eq!(line.buffer.as_str(), "eat avocado");
+    }
+
+    #[test]
+    fn completion_list_cut_off() {
+        let registers = FnCompleter::<_, 8>::new(|line, index, out| {
+            if let Some(name) = (0..100)
+                .map(|i| std::format!("reg{:02}", i))
+                .filter(|name| name.starts_with(line))
+                .nth(index)
+            {
+                out(&name[line.len()..]);
+            }
+        });
+
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(6, 20, Cursor::new(0, 0));
+
+        editor.completer = Some(Box::leak(Box::new(registers)));
+        editor.settings.completion_list = true;
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        // Shared prefix inserted
+        advance(&mut terminal, &mut line, "r\t").unwrap();
+        assert_eq!(terminal.screen_as_string(), "> reg");
+
+        // The list stops before pushing the line off the screen
+        advance(&mut terminal, &mut line, Tab).unwrap();
+        assert_eq!(
+            terminal.screen_as_string(),
+            [
+                "reg00  reg01  reg02",
+                "reg03  reg04  reg05",
+                "reg06  reg07  reg08",
+                "reg09  reg10  reg11",
+                "...",
+                "> reg",
+            ]
+            .join("\n")
+        );
+        assert_eq!(terminal.get_cursor(), Cursor::new(5, 5));
+
+        // Candidates aren't part of the line
+        assert!(advance(&mut terminal, &mut line, "9\t").is_err());
+        advance(&mut terminal, &mut line, Tab).unwrap();
+        assert_eq!(
+            terminal.screen_as_string(),
+            [
+                "> reg9",
+                "reg90  reg91  reg92",
+                "reg93  reg94  reg95",
+                "reg96  reg97  reg98",
+                "reg99",
+                "> reg9",
+            ]
+            .join("\n")
+        );
+        assert_eq!(terminal.get_cursor(), Cursor::new(5, 6));
+
+        // Nothing left to complete
+        assert!(advance(&mut terminal, &mut line, "5\t").is_err());
+        assert_eq!(line.buffer.as_str(), "reg95");
+        assert_eq!(terminal.row_as_string(5), "> reg95");
+    }
+
+    #[test]
+    fn completion_list_full_buffer() {
+        let notes = FnCompleter::<_, 32>::new(|line, index, out| {
+            if let Some(name) = line.strip_prefix("ls ") {
+                if let Some(note) = (1..=3)
+                    .map(|day| std::format!("notes-2026-10-{:02}.txt", day))
+                    .filter(|note| note.starts_with(name))
+                    .nth(index)
+                {
+                    out(&note[name.len()..]);
+                }
+            }
+        });
+
+        let mut buffer = [0; 8];
+        let mut terminal = MockTerminal::new(6, 30, Cursor::new(0, 0));
+        let mut editor: Editor<_, _> =
+            Editor::new(LineBuffer::from_slice(&mut buffer), NoHistory {});
+
+        editor.completer = Some(Box::leak(Box::new(notes)));
+        editor.settings.completion_list = true;
+
+        let mut line = editor.get_line("> ", &mut terminal);
+
+        // Candidates longer than the buffer are listed from the
+        // completer without going through the buffer
+        assert!(advance(&mut terminal, &mut line, "ls \t").is_err());
+        advance(&mut terminal, &mut line, Tab).unwrap();
+        assert_eq!(
+            terminal.screen_as_string(),
+            [
+                "> ls ",
+                "notes-2026-10-01.txt",
+                "notes-2026-10-02.txt",
+                "notes-2026-10-03.txt",
+                "> ls ",
+            ]
+            .join("\n")
+        );
+        assert_eq!(line.buffer.as_str(), "ls ");
+        assert_eq!(terminal.get_cursor(), Cursor::new(4, 5));
+    }
+
+    #[test]
+    fn hints() {
+        static HINTER: HistoryHinter = HistoryHinter;
+
+        let prompt = "> ";
+        let mut terminal = MockTerminal::new(6, 20, Cursor::new(0, 0));
+        let mut editor: Editor<_, _> =
+            Editor::new(LineBuffer::new_unbounded(), UnboundedHistory::new());
+        editor.hinter = Some(&HINTER);
+
+        for entry in ["git commit -m fixes", "git status"] {
+            let mut line = editor.get_line(prompt, &mut terminal);
+            advance(&mut terminal, &mut line, entry).unwrap();
+            advance(&mut terminal, &mut line, "\r").unwrap();
+            assert_eq!(line.buffer.as_str(), entry);
+        }
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        // The most recent entry starting with the line is shown dimmed
+        // after the cursor
+        advance(&mut terminal, &mut line, "gi").unwrap();
+        assert_eq!(terminal.row_as_string(3), "> git status");
+        assert_eq!(terminal.dimmed_as_string(), "t status");
+        assert_eq!(terminal.get_cursor(), Cursor::new(3, 4));
+
+        // Hints are cut off before the end of the row
+        advance(&mut terminal, &mut line, "t c").unwrap();
+        assert_eq!(terminal.row_as_string(3), "> git commit -m fix");
+        assert_eq!(terminal.row_as_string(4), "");
+        assert_eq!(terminal.dimmed_as_string(), "ommit -m fix");
+        assert_eq!(terminal.get_cursor(), Cursor::new(3, 7));
+
+        // Typing a char not matching the hint clears it
+        advance(&mut terminal, &mut line, "x").unwrap();
+        assert_eq!(line.buffer.as_str(), "git cx");
+        assert_eq!(terminal.row_as_string(3), "> git cx");
+        assert_eq!(terminal.dimmed_as_string(), "");
+        assert_eq!(terminal.get_cursor(), Cursor::new(3, 8));
+
+        // Other keys clear the hint before they are handled
+        advance(&mut terminal, &mut line, [Backspace]).unwrap();
+        advance(&mut terminal, &mut line, "o").unwrap();
+        assert_eq!(terminal.dimmed_as_string(), "mmit -m fix");
+        advance(&mut terminal, &mut line, csi::LEFT).unwrap();
+        assert_eq!(line.buffer.as_str(), "git co");
+        assert_eq!(terminal.row_as_string(3), "> git co");
+        assert_eq!(terminal.dimmed_as_string(), "");
+        assert_eq!(terminal.get_cursor(), Cursor::new(3, 7));
+
+        // Right at the end of the line accepts the hint
+        advance(&mut terminal, &mut line, csi::RIGHT).unwrap();
+        advance(&mut terminal, &mut line, "m").unwrap();
+        advance(&mut terminal, &mut line, csi::RIGHT).unwrap();
+        assert_eq!(line.buffer.as_str(), "git commit -m fixes");
+        assert_eq!(terminal.row_as_string(3), "> git commit -m fixe");
+        assert_eq!(terminal.row_as_string(4), "s");
+        assert_eq!(terminal.dimmed_as_string(), "");
+        assert_eq!(terminal.get_cursor(), Cursor::new(4, 1));
+
+        // Enter discards the hint
+        advance(&mut terminal, &mut line, [CtrlU]).unwrap();
+        advance(&mut terminal, &mut line, "git\r").unwrap();
+        assert_eq!(line.buffer.as_str(), "git");
+    }
+
+    #[test]
+    fn history_prefix_search() {
+        let prompt = "> ";
+        let mut terminal = MockTerminal::new(20, 80, Cursor::new(0, 0));
+        let mut editor: Editor<_, _> =
+            Editor::new(LineBuffer::new_unbounded(), UnboundedHistory::new());
+
+        for entry in [
+            "set baud 9600",
+            "get baud",
+            "reset",
+            "se",
+            "set led on",
+            "status",
+        ] {
+            let mut line = editor.get_line(prompt, &mut terminal);
+            advance(&mut terminal, &mut line, entry).unwrap();
+            advance(&mut terminal, &mut line, "\r").unwrap();
+        }
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        // Entries starting with the line are shown, newest first
+        advance(&mut terminal, &mut line, "se").unwrap();
+        advance(&mut terminal, &mut line, csi::UP).unwrap();
+        assert_eq!(line.buffer.as_str(), "set led on");
+        assert_eq!(terminal.current_line_as_string(), "> set led on");
+        assert_eq!(terminal.get_cursor(), Cursor::new(6, 12));
+
+        // The prefix is kept, and entries equal to it are skipped
+        advance(&mut terminal, &mut line, CtrlP).unwrap();
+        assert_eq!(line.buffer.as_str(), "set baud 9600");
+        assert!(advance(&mut terminal, &mut line, csi::UP).is_err());
+        assert_eq!(line.buffer.as_str(), "set baud 9600");
+
+        advance(&mut terminal, &mut line, csi::DOWN).unwrap();
+        assert_eq!(line.buffer.as_str(), "set led on");
+
+        // Going past the newest match returns to the typed line
+        advance(&mut terminal, &mut line, CtrlN).unwrap();
+        assert_eq!(line.buffer.as_str(), "se");
+        assert_eq!(terminal.current_line_as_string(), "> se");
+        assert_eq!(terminal.get_cursor(), Cursor::new(6, 4));
+        assert!(advance(&mut terminal, &mut line, csi::DOWN).is_err());
+
+        // No match leaves the line as is
+        advance(&mut terminal, &mut line, "x").unwrap();
+        assert!(advance(&mut terminal, &mut line, csi::UP).is_err());
+        assert_eq!(line.buffer.as_str(), "sex");
+        advance(&mut terminal, &mut line, csi::DOWN).unwrap();
+        assert_eq!(line.buffer.as_str(), "sex");
+
+        // An empty line navigates all entries
+        advance(&mut terminal, &mut line, [CtrlU, CtrlP, CtrlP]).unwrap();
+        assert_eq!(line.buffer.as_str(), "set led on");
+    }
+
+    #[test]
+    fn history_stash() {
+        let prompt = "> ";
+        let mut terminal = MockTerminal::new(20, 80, Cursor::new(0, 0));
+        let mut editor: Editor<_, _> =
+            Editor::new(LineBuffer::new_unbounded(), UnboundedHistory::new());
+
+        // Without a stash, history is navigated from an empty line only
+        let mut line = editor.get_line(prompt, &mut terminal);
+        assert!(advance(&mut terminal, &mut line, "abc").is_ok());
+        assert!(advance(&mut terminal, &mut line, csi::UP).is_err());
+        assert_eq!(line.buffer.as_str(), "abc");
+        advance(&mut terminal, &mut line, "\r").unwrap();
+
+        editor.stash_buffer = Some(LineBuffer::new_unbounded());
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+        advance(&mut terminal, &mut line, "second\r").unwrap();
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+        advance(&mut terminal, &mut line, "xyz").unwrap();
+
+        // From the end of the line, only entries starting with it are
+        // shown
+        assert!(advance(&mut terminal, &mut line, csi::UP).is_err());
+        assert_eq!(line.buffer.as_str(), "xyz");
+        advance(&mut terminal, &mut line, csi::DOWN).unwrap();
+
+        advance(&mut terminal, &mut line, [csi::LEFT, csi::UP]).unwrap();
+        assert_eq!(line.buffer.as_str(), "second");
+        assert_eq!(terminal.current_line_as_string(), "> second");
+
+        advance(&mut terminal, &mut line, csi::DOWN).unwrap();
+        assert_eq!(line.buffer.as_str(), "xyz");
+        assert_eq!(terminal.current_line_as_string(), "> xyz");
+        assert_eq!(terminal.get_cursor(), Cursor::new(2, 5));
+
+        // Entries are edited while navigating, the stash is kept
+        advance(&mut terminal, &mut line, [CtrlA, CtrlP, CtrlP]).unwrap();
+        assert_eq!(line.buffer.as_str(), "abc");
+        advance(&mut terminal, &mut line, "d").unwrap();
+        advance(&mut terminal, &mut line, [CtrlN]).unwrap();
+        assert_eq!(line.buffer.as_str(), "second");
+        advance(&mut terminal, &mut line, [CtrlN]).unwrap();
+        assert_eq!(line.buffer.as_str(), "xyz");
+        assert_eq!(terminal.current_line_as_string(), "> xyz");
+        assert_eq!(terminal.get_cursor(), Cursor::new(2, 5));
+
+        assert!(advance(&mut terminal, &mut line, [CtrlN]).is_err());
+        assert_eq!(line.buffer.as_str(), "xyz");
+
+        // Navigating from an empty line returns to it
+        advance(&mut terminal, &mut line, [CtrlU, CtrlP, CtrlN]).unwrap();
+        assert_eq!(line.buffer.as_str(), "");
+        assert_eq!(terminal.current_line_as_string(), "> ");
+    }
+
+    #[test]
+    fn history_stash_full() {
+        let prompt = "> ";
+        let mut terminal = MockTerminal::new(20, 80, Cursor::new(0, 0));
+        let mut buffer = [0; 20];
+        let mut stash = [0; 4];
+        let mut editor: Editor<_, _> =
+            Editor::new(LineBuffer::from_slice(&mut buffer), UnboundedHistory::new());
+        editor.stash_buffer = Some(LineBuffer::from_slice(&mut stash));
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+        advance(&mut terminal, &mut line, "second\r").unwrap();
+
+        // Navigating from a line that doesn't fit in the stash would
+        // lose it, also with entries starting with it
+        let mut line = editor.get_line(prompt, &mut terminal);
+        advance(&mut terminal, &mut line, "secon").unwrap();
+        assert!(advance(&mut terminal, &mut line, csi::UP).is_err());
+        assert!(advance(&mut terminal, &mut line, [CtrlA, CtrlP]).is_err());
+        assert_eq!(line.buffer.as_str(), "secon");
+        assert_eq!(terminal.current_line_as_string(), "> secon");
+
+        // A line that fits is restored
+        advance(&mut terminal, &mut line, [CtrlE, CtrlU]).unwrap();
+        advance(&mut terminal, &mut line, "sec").unwrap();
+        advance(&mut terminal, &mut line, csi::UP).unwrap();
+        assert_eq!(line.buffer.as_str(), "second");
+        advance(&mut terminal, &mut line, csi::DOWN).unwrap();
+        assert_eq!(line.buffer.as_str(), "sec");
+    }
+
+    #[test]
+    fn undo() {
+        let prompt = "> ";
+        let mut terminal = MockTerminal::new(20, 80, Cursor::new(0, 0));
+        let mut editor: Editor<_, _> = Editor::new(LineBuffer::new_unbounded(), NoHistory::new());
+        editor.undo_buffer = Some(UndoBuffer::new(UnboundedBuffer::new()));
+
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        // Nothing to undo on a fresh line
+        assert!(advance(&mut terminal, &mut line, [US]).is_err());
+
+        advance(&mut terminal, &mut line, "abc def ghi").unwrap();
+        advance(
+            &mut terminal,
+            &mut line,
+            [csi::LEFT, csi::LEFT, csi::LEFT, csi::LEFT],
+        )
+        .unwrap();
+        advance(&mut terminal, &mut line, [CtrlW]).unwrap();
+        assert_eq!(line.buffer.as_str(), "abc  ghi");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 6));
+
+        // The word is restored, with the cursor where it was
+        advance(&mut terminal, &mut line, [US]).unwrap();
+        assert_eq!(line.buffer.as_str(), "abc def ghi");
+        assert_eq!(terminal.current_line_as_string(), "> abc def ghi");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 9));
+
+        advance(&mut terminal, &mut line, [CtrlE, Backspace, Backspace]).unwrap();
+        advance(&mut terminal, &mut line, "!").unwrap();
+        assert_eq!(line.buffer.as_str(), "abc def g!");
+
+        // Undo walks back through the edits, with typed text undone
+        // as a whole
+        advance(&mut terminal, &mut line, [CtrlX, CtrlU]).unwrap();
+        assert_eq!(line.buffer.as_str(), "abc def g");
+        assert_eq!(terminal.current_line_as_string(), "> abc def g");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 11));
+
+        advance(&mut terminal, &mut line, [US]).unwrap();
+        assert_eq!(line.buffer.as_str(), "abc def gh");
+
+        advance(&mut terminal, &mut line, [US]).unwrap();
+        assert_eq!(line.buffer.as_str(), "abc def ghi");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 13));
+
+        advance(&mut terminal, &mut line, [US]).unwrap();
+        assert_eq!(line.buffer.as_str(), "");
+        assert_eq!(terminal.current_line_as_string(), "> ");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 2));
+
+        assert!(advance(&mut terminal, &mut line, [US]).is_err());
+        assert_eq!(line.buffer.as_str(), "");
+
+        // Edits of the previous line can't be undone
+        advance(&mut terminal, &mut line, "xyz\r").unwrap();
 
-            line
-        }
+        let mut line = editor.get_line(prompt, &mut terminal);
+        assert!(advance(&mut terminal, &mut line, [US]).is_err());
     }
 
-    fn advance<'a, B: Buffer, H: History>(
-        terminal: &mut MockTerminal,
-        noline: &mut Line<'a, B, H, StrIter<'a>>,
-        input: impl ToByteVec,
-    ) -> core::result::Result<(), ()> {
-        terminal.bell = false;
+    #[test]
+    fn vi_mode() {
+        let prompt = "> ";
+        let mut terminal = MockTerminal::new(20, 80, Cursor::new(0, 0));
+        let mut editor: Editor<_, _> =
+            Editor::new(LineBuffer::new_unbounded(), UnboundedHistory::new());
+        editor.settings.editing_mode = EditingMode::Vi;
 
-        for input in input.to_byte_vec() {
-            for item in noline.advance(input) {
-                if let Some(bytes) = item.get_bytes() {
-                    for &b in bytes {
-                        terminal.advance(b);
-                    }
-                }
-            }
-        }
+        let mut line = editor.get_line(prompt, &mut terminal);
+        advance(&mut terminal, &mut line, "echo hello").unwrap();
+        advance(&mut terminal, &mut line, "\r").unwrap();
 
-        assert_eq!(noline.terminal.get_cursor(), terminal.cursor);
+        let mut line = editor.get_line(prompt, &mut terminal);
 
-        dbg!(terminal.screen_as_string());
+        // Lines start in insert mode
+        advance(&mut terminal, &mut line, "abc def").unwrap();
+        assert_eq!(terminal.current_line_as_string(), "> abc def");
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 9));
 
-        if terminal.bell {
-            Err(())
-        } else {
-            Ok(())
-        }
-    }
+        // Escape switches to normal mode when the next key arrives
+        advance(&mut terminal, &mut line, "\x1b").unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 9));
+        advance(&mut terminal, &mut line, "h").unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 8));
+        advance(&mut terminal, &mut line, "hh").unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 6));
+        advance(&mut terminal, &mut line, "l").unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 7));
+        advance(&mut terminal, &mut line, "0").unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 2));
 
-    fn get_terminal_and_editor(
-        rows: usize,
-        columns: usize,
-        origin: Cursor,
-    ) -> (MockTerminal, Editor<UnboundedBuffer, NoHistory>) {
-        let terminal = MockTerminal::new(rows, columns, origin);
+        advance(&mut terminal, &mut line, "x").unwrap();
+        assert_eq!(line.buffer.as_str(), "bc def");
+        assert_eq!(terminal.current_line_as_string(), "> bc def");
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 2));
 
-        let editor = Editor::new(LineBuffer::new_unbounded(), NoHistory {});
+        // Unbound keys ring the bell without editing
+        assert!(advance(&mut terminal, &mut line, "q").is_err());
+        assert_eq!(line.buffer.as_str(), "bc def");
+
+        // i inserts before the cursor
+        advance(&mut terminal, &mut line, "ia").unwrap();
+        assert_eq!(line.buffer.as_str(), "abc def");
+        assert_eq!(terminal.current_line_as_string(), "> abc def");
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 3));
+
+        // a inserts after the cursor
+        advance(&mut terminal, &mut line, ["\x1b", "$", "a", "!"]).unwrap();
+        assert_eq!(line.buffer.as_str(), "abc def!");
+        assert_eq!(terminal.current_line_as_string(), "> abc def!");
+        advance(&mut terminal, &mut line, ["\x1b", "0", "a", "-"]).unwrap();
+        assert_eq!(line.buffer.as_str(), "a-bc def!");
+        assert_eq!(terminal.current_line_as_string(), "> a-bc def!");
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 4));
+
+        // d followed by anything but d is cancelled
+        assert!(advance(&mut terminal, &mut line, ["\x1b", "d", "l"]).is_err());
+        assert_eq!(line.buffer.as_str(), "a-bc def!");
+
+        // Arrow keys work in both modes
+        advance(&mut terminal, &mut line, csi::LEFT).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 3));
 
-        assert_eq!(terminal.get_cursor(), origin);
+        advance(&mut terminal, &mut line, "dd").unwrap();
+        assert_eq!(line.buffer.as_str(), "");
+        assert_eq!(terminal.current_line_as_string(), "> ");
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 2));
 
-        (terminal, editor)
+        // k and j move through history
+        advance(&mut terminal, &mut line, "k").unwrap();
+        assert_eq!(line.buffer.as_str(), "echo hello");
+        assert_eq!(terminal.current_line_as_string(), "> echo hello");
+        advance(&mut terminal, &mut line, "j").unwrap();
+        assert_eq!(line.buffer.as_str(), "");
+        assert_eq!(terminal.current_line_as_string(), "> ");
+
+        advance(&mut terminal, &mut line, ["k", "i", "x", "\r"]).unwrap();
+        assert_eq!(line.buffer.as_str(), "echo hellox");
+
+        // The next line starts in insert mode again
+        let mut line = editor.get_line(prompt, &mut terminal);
+        advance(&mut terminal, &mut line, "hjkl").unwrap();
+        assert_eq!(line.buffer.as_str(), "hjkl");
     }
 
     #[test]
-    fn reset() {
+    fn history_peek() {
         let prompt = "> ";
-        let (terminal, mut editor) = get_terminal_and_editor(4, 10, Cursor::new(1, 0));
-        let mut line = Line::new(
-            prompt,
-            &mut editor.buffer,
-            &mut editor.terminal,
-            &mut editor.history,
-        );
+        let mut terminal = MockTerminal::new(4, 20, Cursor::new(0, 0));
+        let mut editor: Editor<_, _> =
+            Editor::new(LineBuffer::new_unbounded(), UnboundedHistory::new());
 
-        dbg!(terminal.get_cursor());
+        let mut line = editor.get_line(prompt, &mut terminal);
+        assert!(advance(&mut terminal, &mut line, csi::ALT_UP).is_err());
 
-        let mut reset = line.reset();
+        for entry in ["first", "second"] {
+            let mut line = editor.get_line(prompt, &mut terminal);
+            advance(&mut terminal, &mut line, entry).unwrap();
+            advance(&mut terminal, &mut line, "\r").unwrap();
+        }
 
-        let probe = reset
-            .start()
-            .into_iter()
-            .flat_map(|item| item.get_bytes().unwrap().to_vec())
-            .collect::<Vec<u8>>();
+        let mut line = editor.get_line(prompt, &mut terminal);
+        advance(&mut terminal, &mut line, "ab").unwrap();
+        advance(&mut terminal, &mut line, csi::LEFT).unwrap();
 
-        assert_eq!(probe, b"\x1b7\x1b[999;999H\x1b[6n\x1b8");
+        // Entry is shown dimmed in place of the line, which is left in
+        // the buffer
+        advance(&mut terminal, &mut line, csi::ALT_UP).unwrap();
+        assert_eq!(line.buffer.as_str(), "ab");
+        assert_eq!(terminal.row_as_string(2), "> second");
+        assert_eq!(terminal.dimmed_as_string(), "second");
+        assert_eq!(terminal.get_cursor(), Cursor::new(2, 8));
 
-        let output = b"\x1b[91;45R"
-            .iter()
-            .copied()
-            .flat_map(|b| reset.advance(b).unwrap().into_vec())
-            .collect::<Vec<_>>();
+        advance(&mut terminal, &mut line, csi::ALT_UP).unwrap();
+        assert_eq!(line.buffer.as_str(), "ab");
+        assert_eq!(terminal.row_as_string(2), "> first");
+        assert_eq!(terminal.dimmed_as_string(), "first");
 
-        dbg!(terminal.get_cursor());
+        assert!(advance(&mut terminal, &mut line, csi::ALT_UP).is_err());
+        assert_eq!(terminal.row_as_string(2), "> first");
 
-        assert_eq!(output, b"\r\x1b[J> \x1b[6n");
+        advance(&mut terminal, &mut line, csi::ALT_DOWN).unwrap();
+        assert_eq!(terminal.row_as_string(2), "> second");
 
-        let output = b"\x1b[2;3R"
-            .iter()
-            .copied()
-            .flat_map(|b| {
-                if let Some(output) = reset.advance(b) {
-                    output.into_vec()
-                } else {
-                    Vec::new()
-                }
-            })
-            .collect::<Vec<_>>();
+        // Other keys restore the line and cursor
+        advance(&mut terminal, &mut line, Backspace).unwrap();
+        assert_eq!(line.buffer.as_str(), "ab");
+        assert_eq!(terminal.row_as_string(2), "> ab");
+        assert_eq!(terminal.dimmed_as_string(), "");
+        assert_eq!(terminal.get_cursor(), Cursor::new(2, 3));
 
-        dbg!(terminal.get_cursor());
+        // Cursor movement commits the entry
+        advance(&mut terminal, &mut line, csi::ALT_UP).unwrap();
+        advance(&mut terminal, &mut line, csi::HOME).unwrap();
+        assert_eq!(line.buffer.as_str(), "second");
+        assert_eq!(terminal.row_as_string(2), "> second");
+        assert_eq!(terminal.dimmed_as_string(), "");
+        assert_eq!(terminal.get_cursor(), Cursor::new(2, 8));
 
-        assert_eq!(output, b"");
+        // Enter commits the entry, which is then editable
+        advance(&mut terminal, &mut line, CtrlU).unwrap();
+        advance(&mut terminal, &mut line, "x").unwrap();
+        advance(&mut terminal, &mut line, [csi::ALT_UP, csi::ALT_UP]).unwrap();
+        assert_eq!(line.buffer.as_str(), "x");
+        advance(&mut terminal, &mut line, "\r").unwrap();
+        assert_eq!(line.buffer.as_str(), "first");
+        assert_eq!(terminal.row_as_string(2), "> first");
+        assert_eq!(terminal.dimmed_as_string(), "");
 
-        assert_eq!(line.terminal.get_size(), (91, 45));
+        advance(&mut terminal, &mut line, Backspace).unwrap();
+        assert_eq!(line.buffer.as_str(), "firs");
+        assert_eq!(terminal.row_as_string(2), "> firs");
+
+        // Plain Up still replaces the buffer
+        advance(&mut terminal, &mut line, CtrlU).unwrap();
+        advance(&mut terminal, &mut line, csi::UP).unwrap();
+        assert_eq!(line.buffer.as_str(), "second");
+        assert_eq!(terminal.dimmed_as_string(), "");
     }
 
     #[test]
-    fn mock_editor() {
+    #[cfg(not(feature = "ascii-only"))]
+    fn history_peek_wrapped() {
         let prompt = "> ";
-        let (mut terminal, mut editor) = get_terminal_and_editor(4, 20, Cursor::new(1, 0));
+        let entry = "æøå, entry ÆØÅ";
+
+        // Entries wrap around the end of the history at every byte of
+        // the entry, and don't fit in the line buffer
+        for skip in 0..entry.len() {
+            let mut buffer = [0; 7];
+            let mut history = [0; 24];
+            let mut terminal = MockTerminal::new(4, 40, Cursor::new(0, 0));
+            let mut editor: Editor<_, _> = Editor::new(
+                LineBuffer::from_slice(&mut buffer),
+                SliceHistory::new(&mut history),
+            );
 
-        assert_eq!(terminal.get_cursor(), Cursor::new(1, 0));
+            for line in ["x".repeat(skip).as_str(), "y", entry] {
+                editor.history.add_entry(line).unwrap();
+            }
 
-        let line = editor.get_line(prompt, &mut terminal);
+            let mut line = editor.get_line(prompt, &mut terminal);
+            advance(&mut terminal, &mut line, csi::ALT_UP).unwrap();
+            assert_eq!(terminal.screen_as_string(), std::format!("> {entry}"));
+            assert_eq!(terminal.dimmed_as_string(), entry);
+            assert_eq!(line.buffer.as_str(), "");
 
-        dbg!(&line.terminal);
-        assert_eq!(terminal.get_cursor(), line.terminal.get_cursor());
+            // Committed entry is truncated to fit the buffer
+            advance(&mut terminal, &mut line, CtrlE).unwrap();
+            assert_eq!(line.buffer.as_str(), "æøå,");
+            assert_eq!(terminal.screen_as_string(), "> æøå,");
+        }
     }
 
     #[test]
-    fn movecursor() {
+    fn rprompt() {
         let prompt = "> ";
-        let (mut terminal, mut editor) = get_terminal_and_editor(4, 10, Cursor::new(1, 0));
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 20, Cursor::new(1, 0));
 
-        let mut line = editor.get_line(prompt, &mut terminal);
+        let mut line = editor.get_line_with_rprompt(prompt, "12:00", &mut terminal);
 
-        advance(&mut terminal, &mut line, "Hello, World!").unwrap();
-        assert_eq!(terminal.get_cursor(), Cursor::new(2, 5));
+        assert_eq!(terminal.row_as_string(1), ">              12:00");
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 2));
 
-        advance(&mut terminal, &mut line, [csi::LEFT; 6]).unwrap();
-        assert_eq!(terminal.get_cursor(), Cursor::new(1, 9));
+        advance(&mut terminal, &mut line, "abcdefghijkl").unwrap();
+        assert_eq!(terminal.row_as_string(1), "> abcdefghijkl 12:00");
 
-        advance(&mut terminal, &mut line, CtrlA).unwrap();
+        // Erasing keeps the right-side prompt
+        advance(&mut terminal, &mut line, [Backspace, CtrlW]).unwrap();
+        assert_eq!(terminal.row_as_string(1), ">              12:00");
 
-        assert_eq!(terminal.get_cursor(), Cursor::new(1, 2));
+        advance(&mut terminal, &mut line, "abcdefghijklm").unwrap();
+        assert_eq!(terminal.row_as_string(1), "> abcdefghijklm12:00");
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 15));
 
-        assert!(advance(&mut terminal, &mut line, csi::LEFT).is_err());
+        // Disappears when the line reaches it
+        advance(&mut terminal, &mut line, "n").unwrap();
+        assert_eq!(line.buffer.as_str(), "abcdefghijklmn");
+        assert_eq!(terminal.row_as_string(1), "> abcdefghijklmn");
 
-        assert_eq!(terminal.get_cursor(), Cursor::new(1, 2));
+        // and doesn't reappear
+        advance(&mut terminal, &mut line, [Backspace, Backspace]).unwrap();
+        assert_eq!(terminal.row_as_string(1), "> abcdefghijkl");
 
-        advance(&mut terminal, &mut line, CtrlE).unwrap();
+        advance(&mut terminal, &mut line, [CtrlA]).unwrap();
+        advance(&mut terminal, &mut line, "0123456789").unwrap();
+        assert_eq!(terminal.screen_as_string(), "> 0123456789abcdefgh\nijkl");
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 12));
 
-        assert_eq!(terminal.get_cursor(), Cursor::new(2, 5));
+        advance(&mut terminal, &mut line, CtrlU).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> ");
 
-        assert!(advance(&mut terminal, &mut line, csi::RIGHT).is_err());
+        // Overwritten by insertion in the middle of the line
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 20, Cursor::new(0, 0));
 
-        assert_eq!(terminal.get_cursor(), Cursor::new(2, 5));
+        let mut line = editor.get_line_with_rprompt(prompt, "12:00", &mut terminal);
 
-        advance(&mut terminal, &mut line, csi::HOME).unwrap();
+        advance(&mut terminal, &mut line, ["abcdefghijkl", csi::LEFT, "xy"]).unwrap();
+        assert_eq!(terminal.row_as_string(0), "> abcdefghijkxyl");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 15));
 
-        assert_eq!(terminal.get_cursor(), Cursor::new(1, 2));
+        advance(&mut terminal, &mut line, "\r").unwrap();
+        assert_eq!(terminal.screen_as_string(), "> abcdefghijkxyl");
 
-        advance(&mut terminal, &mut line, csi::END).unwrap();
+        // Not shown if the line doesn't fit
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 12, Cursor::new(0, 0));
 
-        assert_eq!(terminal.get_cursor(), Cursor::new(2, 5));
+        editor.get_line_with_rprompt("prompt ", "12:00", &mut terminal);
+
+        assert_eq!(terminal.row_as_string(0), "prompt");
+
+        // or if it's as wide as the terminal
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 5, Cursor::new(0, 0));
+
+        editor.get_line_with_rprompt("", "12:00", &mut terminal);
+
+        assert_eq!(terminal.row_as_string(0), "");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 0));
     }
 
     #[test]
-    fn cursor_scroll() {
+    fn rprompt_clear_screen() {
         let prompt = "> ";
-        let (mut terminal, mut editor) = get_terminal_and_editor(4, 10, Cursor::new(3, 0));
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 20, Cursor::new(2, 0));
+
+        let mut line = editor.get_line_with_rprompt(prompt, "12:00", &mut terminal);
+
+        advance(&mut terminal, &mut line, "ab").unwrap();
+        assert_eq!(terminal.row_as_string(2), "> ab           12:00");
+
+        // Printed again at the top with the line
+        advance(&mut terminal, &mut line, CtrlL).unwrap();
+        assert_eq!(terminal.row_as_string(0), "> ab           12:00");
+        assert_eq!(terminal.row_as_string(2), "");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 4));
+
+        // Still erased when the line reaches it
+        advance(&mut terminal, &mut line, "cdefghijklmn").unwrap();
+        assert_eq!(terminal.row_as_string(0), "> abcdefghijklmn");
+
+        // and not printed again by the next clear
+        advance(&mut terminal, &mut line, CtrlL).unwrap();
+        assert_eq!(terminal.row_as_string(0), "> abcdefghijklmn");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 16));
+    }
+
+    #[test]
+    fn control_char_policy() {
+        // Pasted without bracketed paste
+        const PASTED: &str = "ab\tc\x07d\x1bxe\x1b[99~f";
+
+        let prompt = "> ";
+
+        for (policy, expected) in [
+            (ControlCharPolicy::Bell, "ab    cdef"),
+            (ControlCharPolicy::Strip, "ab    cdxef"),
+            (ControlCharPolicy::Insert, "ab    c^Gd^[xef"),
+        ] {
+            let (mut terminal, mut editor) = get_terminal_and_editor(4, 40, Cursor::new(0, 0));
+            editor.settings.control_chars = policy;
+
+            let mut line = editor.get_line(prompt, &mut terminal);
+
+            let rang = advance(&mut terminal, &mut line, PASTED).is_err();
+            assert_eq!(rang, policy == ControlCharPolicy::Bell);
+            assert_eq!(line.buffer.as_str(), expected);
+            assert_eq!(terminal.screen_as_string(), format!("> {}", expected));
+            assert_eq!(terminal.get_cursor(), Cursor::new(0, 2 + expected.len()));
+
+            // Bound control characters are still keys
+            advance(&mut terminal, &mut line, [CtrlA]).unwrap();
+            advance(&mut terminal, &mut line, "\x1bf").unwrap();
+            assert_eq!(terminal.get_cursor(), Cursor::new(0, 4));
+        }
+
+        // Tabs can be kept, displayed up to the next tab stop
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 40, Cursor::new(0, 0));
+        editor.settings.control_chars = ControlCharPolicy::Strip;
+        editor.settings.tab = TabPolicy::Preserve;
 
         let mut line = editor.get_line(prompt, &mut terminal);
 
-        advance(&mut terminal, &mut line, "23456789").unwrap();
-        assert_eq!(terminal.get_cursor(), Cursor::new(3, 0));
+        advance(&mut terminal, &mut line, PASTED).unwrap();
+        assert_eq!(line.buffer.as_str(), "ab\tcdxef");
+        assert_eq!(terminal.screen_as_string(), "> ab    cdxef");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 13));
     }
 
     #[test]
-    fn clear_line() {
+    fn paste_tab() {
         let prompt = "> ";
-        let (mut terminal, mut editor) = get_terminal_and_editor(4, 20, Cursor::new(1, 0));
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 20, Cursor::new(0, 0));
 
         let mut line = editor.get_line(prompt, &mut terminal);
 
-        dbg!(terminal.get_cursor());
-        dbg!(&line.terminal);
-        assert_eq!(terminal.get_cursor(), Cursor::new(1, 2));
-        dbg!(terminal.screen_as_string());
+        advance(&mut terminal, &mut line, "a\tb").unwrap();
+        assert_eq!(line.buffer.as_str(), "a    b");
+        assert_eq!(terminal.screen_as_string(), "> a    b");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 8));
 
-        advance(&mut terminal, &mut line, "Hello, World!").unwrap();
-        assert_eq!(terminal.get_cursor(), Cursor::new(1, 15));
-        assert_eq!(terminal.screen_as_string(), "> Hello, World!");
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 20, Cursor::new(0, 0));
+        editor.settings.tab = TabPolicy::Preserve;
 
-        advance(&mut terminal, &mut line, CtrlU).unwrap();
-        assert_eq!(terminal.get_cursor(), Cursor::new(1, 2));
-        assert_eq!(terminal.screen_as_string(), "> ");
+        let mut line = editor.get_line(prompt, &mut terminal);
 
-        advance(&mut terminal, &mut line, "Hello, World!").unwrap();
-        assert_eq!(terminal.get_cursor(), Cursor::new(1, 15));
-        assert_eq!(terminal.screen_as_string(), "> Hello, World!");
+        advance(&mut terminal, &mut line, "a\tb").unwrap();
+        assert_eq!(line.buffer.as_str(), "a\tb");
+        assert_eq!(terminal.screen_as_string(), "> a     b");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 9));
+
+        // The cursor moves over the whole tab
+        advance(&mut terminal, &mut line, csi::LEFT).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 8));
+        advance(&mut terminal, &mut line, csi::LEFT).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 3));
+
+        // Tabs after the cursor move to the next tab stop
+        advance(&mut terminal, &mut line, "c\t").unwrap();
+        assert_eq!(line.buffer.as_str(), "ac\t\tb");
+        assert_eq!(terminal.screen_as_string(), "> ac            b");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 8));
+
+        advance(&mut terminal, &mut line, Backspace).unwrap();
+        assert_eq!(line.buffer.as_str(), "ac\tb");
+        assert_eq!(terminal.screen_as_string(), "> ac    b");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 4));
+
+        // A tab at the end of a row fills it
+        advance(&mut terminal, &mut line, [CtrlE]).unwrap();
+        advance(&mut terminal, &mut line, "0123456789\tx").unwrap();
+        assert_eq!(terminal.screen_as_string(), "> ac    b0123456789 \nx");
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 1));
+
+        advance(&mut terminal, &mut line, csi::LEFT).unwrap();
+        advance(&mut terminal, &mut line, csi::LEFT).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 19));
+
+        // Tabs are expanded to any width, or dropped with 0
+        for (width, expected) in [(0, "ab"), (1, "a b"), (10, "a          b")] {
+            let (mut terminal, mut editor) = get_terminal_and_editor(4, 20, Cursor::new(0, 0));
+            editor.settings.tab = TabPolicy::Expand(width);
+
+            let mut line = editor.get_line(prompt, &mut terminal);
+
+            advance(&mut terminal, &mut line, "a\tb").unwrap();
+            assert_eq!(line.buffer.as_str(), expected);
+            assert_eq!(terminal.screen_as_string(), format!("> {}", expected));
+        }
+
+        // A tab is expanded whole or not at all
+        let mut array = [0; 6];
+        let mut terminal = MockTerminal::new(4, 20, Cursor::new(0, 0));
+        let mut editor: Editor<_, NoHistory> =
+            Editor::new(LineBuffer::from_slice(&mut array), NoHistory {});
+        let mut line = editor.get_line(prompt, &mut terminal);
+
+        advance(&mut terminal, &mut line, "abc").unwrap();
+        assert!(advance(&mut terminal, &mut line, "\t").is_err());
+        assert_eq!(line.buffer.as_str(), "abc");
     }
 
+    #[cfg(not(feature = "ascii-only"))]
     #[test]
-    fn clear_screen() {
+    fn small_stack() {
+        // Guard against regressions in stack usage. 16 KiB is the
+        // smallest stack most hosts allow. Test builds are unoptimized
+        // and print debug output, so a release build on target needs
+        // a lot less.
+        const STACK_SIZE: usize = 16 * 1024;
+
+        std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(|| {
+                let mut buffer = [0; 64];
+                let mut history = [0; 128];
+                let mut terminal = MockTerminal::new(4, 10, Cursor::new(3, 0));
+                let mut editor = Editor::new(
+                    LineBuffer::from_slice(&mut buffer),
+                    SliceHistory::new(&mut history),
+                );
+
+                let mut line = editor.get_line("> ", &mut terminal);
+                advance(&mut terminal, &mut line, "Hello, World!\r").unwrap();
+
+                let mut line = editor.get_line("> ", &mut terminal);
+                advance(&mut terminal, &mut line, "æøå, this wraps the line").unwrap();
+                advance(
+                    &mut terminal,
+                    &mut line,
+                    [CtrlA, CtrlF, CtrlT, CtrlE, CtrlW],
+                )
+                .unwrap();
+                advance(
+                    &mut terminal,
+                    &mut line,
+                    [csi::LEFT, csi::HOME, csi::DELETE],
+                )
+                .unwrap();
+                advance(&mut terminal, &mut line, [CtrlU, CtrlL]).unwrap();
+                advance(&mut terminal, &mut line, [csi::UP, csi::DOWN]).unwrap();
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    fn advance_to_vec<'a, B: Buffer, H: History>(
+        line: &mut Line<
+            'a,
+            B,
+            H,
+            dyn PromptSource<'static>,
+            dyn Completer,
+            dyn Validator,
+            HistoryHinter,
+        >,
+        input: impl ToByteVec,
+    ) -> Vec<u8> {
+        input
+            .to_byte_vec()
+            .into_iter()
+            .flat_map(|b| {
+                line.advance(b)
+                    .into_iter()
+                    .filter_map(|item| item.get_bytes().map(|bytes| bytes.to_vec()))
+                    .flatten()
+                    .collect::<Vec<u8>>()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn echo() {
         let prompt = "> ";
-        let (mut terminal, mut editor) = get_terminal_and_editor(4, 20, Cursor::new(1, 0));
 
-        let mut line = editor.get_line(prompt, &mut terminal);
+        for echo in [Echo::None, Echo::PrintableOnly] {
+            let (mut terminal, mut editor) = get_terminal_and_editor(4, 20, Cursor::new(0, 0));
+            editor.settings.echo = echo;
 
-        advance(&mut terminal, &mut line, "Hello, World!").unwrap();
-        assert_eq!(terminal.get_cursor(), Cursor::new(1, 15));
-        assert_eq!(terminal.screen_as_string(), "> Hello, World!");
+            let mut line = editor.get_line(prompt, &mut terminal);
 
-        advance(&mut terminal, &mut line, CtrlL).unwrap();
-        assert_eq!(terminal.get_cursor(), Cursor::new(0, 2));
-        assert_eq!(terminal.screen_as_string(), "> ");
+            let output = advance_to_vec(&mut line, "Hello");
 
-        advance(&mut terminal, &mut line, "Hello, World!").unwrap();
-        assert_eq!(terminal.get_cursor(), Cursor::new(0, 15));
-        assert_eq!(terminal.screen_as_string(), "> Hello, World!");
+            match echo {
+                Echo::None => assert_eq!(output, b""),
+                _ => assert_eq!(output, b"Hello"),
+            }
+
+            assert_eq!(line.buffer.as_str(), "Hello");
+            assert_eq!(line.terminal.get_cursor(), Cursor::new(0, 7));
+
+            assert_eq!(advance_to_vec(&mut line, [Backspace, CtrlA]), b"");
+            assert_eq!(advance_to_vec(&mut line, csi::LEFT), b"");
+            assert_eq!(advance_to_vec(&mut line, "\r"), b"\n\r");
+            assert_eq!(line.buffer.as_str(), "Hell");
+        }
     }
 
     #[test]
-    fn scroll() {
-        let prompt = "> ";
+    fn local_echo() {
+        // The terminal prints typed chars before the editor gets them
+        fn type_locally<'a, B: Buffer, H: History>(
+            terminal: &mut MockTerminal,
+            line: &mut Line<
+                'a,
+                B,
+                H,
+                dyn PromptSource<'static>,
+                dyn Completer,
+                dyn Validator,
+                HistoryHinter,
+            >,
+            s: &str,
+        ) {
+            for c in s.chars() {
+                let s = c.to_string();
+
+                for b in s.bytes() {
+                    terminal.advance(b);
+                }
+
+                assert_eq!(advance(terminal, line, s.as_str()), Ok(()));
+            }
+        }
+
         let (mut terminal, mut editor) = get_terminal_and_editor(4, 10, Cursor::new(0, 0));
+        editor.settings.echo = Echo::Local;
 
-        let mut line = editor.get_line(prompt, &mut terminal);
+        let mut line = editor.get_line("> ", &mut terminal);
 
-        advance(&mut terminal, &mut line, "aaaaaaaa").unwrap();
-        advance(&mut terminal, &mut line, "bbbbbbbbbb").unwrap();
-        advance(&mut terminal, &mut line, "cccccccccc").unwrap();
-        advance(&mut terminal, &mut line, "ddddddddd").unwrap();
+        type_locally(&mut terminal, &mut line, "Hello");
+        assert_eq!(terminal.screen_as_string(), "> Hello");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 7));
+        assert_eq!(line.terminal.get_cursor(), Cursor::new(0, 7));
 
-        assert_eq!(terminal.get_cursor(), Cursor::new(3, 9));
+        // Nothing but the typed char is printed at the end of the line
+        assert_eq!(advance_to_vec(&mut line, "a"), b"");
+        terminal.advance(b'a');
 
-        assert_eq!(
-            terminal.screen_as_string(),
-            "> aaaaaaaa\nbbbbbbbbbb\ncccccccccc\nddddddddd"
-        );
+        // Filling the row wraps
+        type_locally(&mut terminal, &mut line, "bc");
+        assert_eq!(terminal.screen_as_string(), "> Helloabc");
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 0));
 
-        advance(&mut terminal, &mut line, "d").unwrap();
+        type_locally(&mut terminal, &mut line, "d");
 
-        assert_eq!(terminal.get_cursor(), Cursor::new(3, 0));
+        // Inserting before the end overwrites a char on the terminal,
+        // which is printed again
+        assert_eq!(advance(&mut terminal, &mut line, CtrlA), Ok(()));
+        type_locally(&mut terminal, &mut line, "X");
+        assert_eq!(terminal.screen_as_string(), "> XHelloab\ncd");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 3));
 
-        assert_eq!(
-            terminal.screen_as_string(),
-            "bbbbbbbbbb\ncccccccccc\ndddddddddd"
-        );
+        // Other editing is echoed in full
+        assert_eq!(advance(&mut terminal, &mut line, Backspace), Ok(()));
+        assert_eq!(terminal.screen_as_string(), "> Helloabc\nd");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 2));
 
-        advance(&mut terminal, &mut line, "eeeeeeeeee").unwrap();
+        assert_eq!(advance(&mut terminal, &mut line, csi::INSERT), Ok(()));
+        type_locally(&mut terminal, &mut line, "J");
+        assert_eq!(terminal.screen_as_string(), "> Jelloabc\nd");
 
-        assert_eq!(
-            terminal.screen_as_string(),
-            "cccccccccc\ndddddddddd\neeeeeeeeee"
-        );
+        assert_eq!(advance(&mut terminal, &mut line, CtrlE), Ok(()));
+        type_locally(&mut terminal, &mut line, "e");
+        assert_eq!(terminal.screen_as_string(), "> Jelloabc\nde");
 
-        // advance(&mut terminal, &mut noline, CtrlA);
+        assert_eq!(line.buffer.as_str(), "Jelloabcde");
+        assert_eq!(terminal.get_cursor(), line.terminal.get_cursor());
+    }
 
-        // assert_eq!(terminal.get_cursor(), Cursor::new(0, 2));
-        // assert_eq!(
-        //     terminal.screen_as_string(),
-        //     "> aaaaaaaa\nbbbbbbbbbb\ncccccccccc\ndddddddddd"
-        // );
+    #[test]
+    fn append_at_end_of_line() {
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 20, Cursor::new(0, 0));
+        let mut line = editor.get_line("> ", &mut terminal);
+
+        for c in "abcdefghijklmnopq".chars() {
+            assert_eq!(advance_to_vec(&mut line, c.to_string().as_str()), [c as u8]);
+        }
+
+        // Filling the row wraps to the next
+        assert_eq!(advance_to_vec(&mut line, "r"), b"r\n\r");
+        assert_eq!(line.terminal.get_cursor(), Cursor::new(1, 0));
+
+        #[cfg(not(feature = "ascii-only"))]
+        for c in ['æ', 'ø', 'å', '€'] {
+            let s = c.to_string();
+            assert_eq!(advance_to_vec(&mut line, s.as_str()), s.as_bytes());
+        }
 
-        // advance(&mut terminal, &mut noline, CtrlE);
-        // assert_eq!(terminal.get_cursor(), Cursor::new(3, 0));
-        // assert_eq!(
-        //     terminal.screen_as_string(),
-        //     "cccccccccc\ndddddddddd\neeeeeeeeee"
-        // );
+        // Inserting before the end moves the cursor back after
+        // reprinting the rest of the line
+        advance_to_vec(&mut line, CtrlA);
+        assert!(advance_to_vec(&mut line, "x").ends_with(b"\x1b[1;4H"));
+
+        advance_to_vec(&mut line, CtrlE);
+        assert_eq!(advance_to_vec(&mut line, "s"), b"s");
     }
 
+    #[cfg(not(feature = "ascii-only"))]
     #[test]
-    fn swap() {
+    fn wide_chars() {
         let prompt = "> ";
         let (mut terminal, mut editor) = get_terminal_and_editor(4, 10, Cursor::new(0, 0));
 
         let mut line = editor.get_line(prompt, &mut terminal);
 
-        advance(&mut terminal, &mut line, "æøå").unwrap();
-        assert_eq!(terminal.screen_as_string(), "> æøå");
-        assert_eq!(terminal.get_cursor(), Cursor::new(0, 5));
+        advance(&mut terminal, &mut line, "ab你好cd").unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 0));
+        assert_eq!(terminal.row_as_string(0), "> ab你好cd");
 
-        assert!(advance(&mut terminal, &mut line, CtrlT).is_err());
+        advance(&mut terminal, &mut line, CtrlA).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 2));
 
-        assert_eq!(terminal.screen_as_string(), "> æøå");
-        assert_eq!(terminal.get_cursor(), Cursor::new(0, 5));
+        advance(&mut terminal, &mut line, CtrlE).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 0));
 
-        advance(&mut terminal, &mut line, csi::LEFT).unwrap();
+        advance(&mut terminal, &mut line, [csi::LEFT; 3]).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 6));
 
+        advance(&mut terminal, &mut line, Backspace).unwrap();
         assert_eq!(terminal.get_cursor(), Cursor::new(0, 4));
+        assert_eq!(terminal.row_as_string(0), "> ab好cd");
+        assert_eq!(line.buffer.as_str(), "ab好cd");
 
-        advance(&mut terminal, &mut line, CtrlT).unwrap();
+        // A wide char reaching the last column starts on the next row
+        advance(&mut terminal, &mut line, CtrlE).unwrap();
+        advance(&mut terminal, &mut line, "x😂").unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 2));
+        assert_eq!(terminal.row_as_string(0), "> ab好cdx");
+        assert_eq!(terminal.row_as_string(1), "😂");
 
-        assert_eq!(line.buffer.as_str(), "æåø");
-        assert_eq!(terminal.screen_as_string(), "> æåø");
-        assert_eq!(terminal.get_cursor(), Cursor::new(0, 4));
+        advance(&mut terminal, &mut line, csi::LEFT).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 0));
 
-        advance(&mut terminal, &mut line, CtrlA).unwrap();
+        advance(&mut terminal, &mut line, csi::LEFT).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 8));
 
-        assert_eq!(terminal.get_cursor(), Cursor::new(0, 2));
+        advance(&mut terminal, &mut line, csi::RIGHT).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 0));
 
-        assert!(advance(&mut terminal, &mut line, CtrlT).is_err());
-        assert_eq!(terminal.screen_as_string(), "> æåø");
+        // and moves back when there is room for it
+        advance(&mut terminal, &mut line, Backspace).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 8));
+        assert_eq!(terminal.row_as_string(0), "> ab好cd😂");
+        assert_eq!(terminal.row_as_string(1), "");
+
+        advance(&mut terminal, &mut line, CtrlE).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 0));
+
+        advance(&mut terminal, &mut line, "z").unwrap();
+        assert_eq!(line.buffer.as_str(), "ab好cd😂z");
+        assert_eq!(terminal.row_as_string(1), "z");
     }
 
+    #[cfg(not(feature = "ascii-only"))]
     #[test]
-    fn erase_after_cursor() {
+    fn move_word() {
+        const ALT_B: &str = "\x1bb";
+        const ALT_F: &str = "\x1bf";
+
         let prompt = "> ";
         let (mut terminal, mut editor) = get_terminal_and_editor(4, 10, Cursor::new(0, 0));
 
         let mut line = editor.get_line(prompt, &mut terminal);
 
-        advance(&mut terminal, &mut line, "rm -rf /").unwrap();
-        assert_eq!(terminal.get_cursor(), Cursor::new(1, 0));
-        assert_eq!(terminal.screen_as_string(), "> rm -rf /");
+        advance(&mut terminal, &mut line, "æøå  word2 ord3").unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 7));
 
-        advance(&mut terminal, &mut line, CtrlA).unwrap();
-        advance(&mut terminal, &mut line, [CtrlF; 3]).unwrap();
+        assert!(advance(&mut terminal, &mut line, ALT_F).is_err());
 
-        assert_eq!(terminal.get_cursor(), Cursor::new(0, 5));
+        advance(&mut terminal, &mut line, ALT_B).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 3));
 
-        advance(&mut terminal, &mut line, CtrlK).unwrap();
-        assert_eq!(line.buffer.as_str(), "rm ");
-        assert_eq!(terminal.get_cursor(), Cursor::new(0, 5));
-        assert_eq!(terminal.screen_as_string(), "> rm ");
-    }
+        advance(&mut terminal, &mut line, ALT_B).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 7));
 
-    #[test]
-    fn delete_previous_word() {
-        let prompt = "> ";
-        let (mut terminal, mut editor) = get_terminal_and_editor(1, 40, Cursor::new(0, 0));
+        advance(&mut terminal, &mut line, ALT_B).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 2));
 
-        let mut line = editor.get_line(prompt, &mut terminal);
+        assert!(advance(&mut terminal, &mut line, ALT_B).is_err());
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 2));
 
-        advance(&mut terminal, &mut line, "rm file1 file2 file3").unwrap();
-        assert_eq!(terminal.screen_as_string(), "> rm file1 file2 file3");
+        advance(&mut terminal, &mut line, ALT_F).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 5));
 
-        advance(&mut terminal, &mut line, [CtrlB; 5]).unwrap();
+        advance(&mut terminal, &mut line, ALT_F).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 2));
 
-        advance(&mut terminal, &mut line, CtrlW).unwrap();
-        assert_eq!(terminal.get_cursor(), Cursor::new(0, 11));
-        assert_eq!(line.buffer.as_str(), "rm file1 file3");
-        assert_eq!(terminal.screen_as_string(), "> rm file1 file3");
+        advance(&mut terminal, &mut line, ALT_F).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 7));
 
-        advance(&mut terminal, &mut line, CtrlW).unwrap();
-        assert_eq!(terminal.screen_as_string(), "> rm file3");
-        assert_eq!(terminal.get_cursor(), Cursor::new(0, 5));
+        advance(&mut terminal, &mut line, "x").unwrap();
+        assert_eq!(line.buffer.as_str(), "æøå  word2 ord3x");
     }
 
     #[test]
-    fn delete() {
+    fn cursor_position_status() {
         let prompt = "> ";
-        let (mut terminal, mut editor) = get_terminal_and_editor(1, 40, Cursor::new(0, 0));
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 50, Cursor::new(0, 0));
 
         let mut line = editor.get_line(prompt, &mut terminal);
 
-        assert_eq!(terminal.get_cursor(), Cursor::new(0, 2));
-        assert_eq!(terminal.screen_as_string(), "> ");
-        advance(&mut terminal, &mut line, "abcde").unwrap();
+        advance(&mut terminal, &mut line, "abcd").unwrap();
+        advance(&mut terminal, &mut line, [CtrlA, CtrlF]).unwrap();
+        advance(&mut terminal, &mut line, CtrlX).unwrap();
+        advance(&mut terminal, &mut line, "=").unwrap();
+        assert_eq!(
+            terminal.screen_as_string(),
+            "> abcd  col 2 of 4, char 'b' (U+0062)"
+        );
+        assert_eq!(
+            terminal.dimmed_as_string(),
+            "  col 2 of 4, char 'b' (U+0062)"
+        );
+        assert_eq!(line.buffer.as_str(), "abcd");
 
-        advance(&mut terminal, &mut line, CtrlD).unwrap_err();
+        // Next key clears the status and is handled as usual
+        advance(&mut terminal, &mut line, csi::RIGHT).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> abcd");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 4));
+        assert_eq!(line.buffer.as_str(), "abcd");
 
-        advance(&mut terminal, &mut line, CtrlA).unwrap();
+        advance(&mut terminal, &mut line, [CtrlE, CtrlX]).unwrap();
+        advance(&mut terminal, &mut line, "=").unwrap();
+        assert_eq!(
+            terminal.screen_as_string(),
+            "> abcd  col 5 of 4, end of line"
+        );
 
-        advance(&mut terminal, &mut line, CtrlD).unwrap();
-        assert_eq!(line.buffer.as_str(), "bcde");
-        assert_eq!(terminal.screen_as_string(), "> bcde");
+        advance(&mut terminal, &mut line, "x").unwrap();
+        assert_eq!(terminal.screen_as_string(), "> abcdx");
+        assert_eq!(line.buffer.as_str(), "abcdx");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 7));
+
+        // Other keys after Ctrl-X are rejected
+        advance(&mut terminal, &mut line, CtrlX).unwrap();
+        assert!(advance(&mut terminal, &mut line, "y").is_err());
+        assert_eq!(line.buffer.as_str(), "abcdx");
+        assert_eq!(terminal.screen_as_string(), "> abcdx");
+
+        #[cfg(not(feature = "ascii-only"))]
+        {
+            advance(&mut terminal, &mut line, CtrlU).unwrap();
+            advance(&mut terminal, &mut line, "aæ😀b").unwrap();
+            advance(&mut terminal, &mut line, [csi::LEFT, csi::LEFT]).unwrap();
+            advance(&mut terminal, &mut line, CtrlX).unwrap();
+            advance(&mut terminal, &mut line, "=").unwrap();
+            assert_eq!(
+                terminal.screen_as_string(),
+                "> aæ😀b  col 3 of 4, char '😀' (U+1F600)"
+            );
 
-        advance(&mut terminal, &mut line, [csi::RIGHT; 3]).unwrap();
-        advance(&mut terminal, &mut line, CtrlD).unwrap();
-        assert_eq!(line.buffer.as_str(), "bcd");
-        assert_eq!(terminal.screen_as_string(), "> bcd");
+            advance(&mut terminal, &mut line, csi::LEFT).unwrap();
+            advance(&mut terminal, &mut line, CtrlX).unwrap();
+            advance(&mut terminal, &mut line, "=").unwrap();
+            assert_eq!(
+                terminal.screen_as_string(),
+                "> aæ😀b  col 2 of 4, char 'æ' (U+00E6)"
+            );
 
-        advance(&mut terminal, &mut line, CtrlD).unwrap_err();
+            advance(&mut terminal, &mut line, CtrlD).unwrap();
+            assert_eq!(terminal.screen_as_string(), "> a😀b");
+            assert_eq!(line.buffer.as_str(), "a😀b");
+            assert_eq!(terminal.get_cursor(), Cursor::new(0, 3));
+        }
+    }
 
-        advance(&mut terminal, &mut line, CtrlA).unwrap();
+    #[test]
+    fn cursor_position_status_full_buffer() {
+        let mut buffer = [0; 4];
+        let mut terminal = MockTerminal::new(4, 20, Cursor::new(0, 0));
+        let mut editor: Editor<_, _> =
+            Editor::new(LineBuffer::from_slice(&mut buffer), NoHistory {});
 
-        advance(&mut terminal, &mut line, csi::DELETE).unwrap();
-        assert_eq!(line.buffer.as_str(), "cd");
-        assert_eq!(terminal.screen_as_string(), "> cd");
+        let mut line = editor.get_line("> ", &mut terminal);
 
-        advance(&mut terminal, &mut line, csi::DELETE).unwrap();
-        assert_eq!(line.buffer.as_str(), "d");
-        assert_eq!(terminal.screen_as_string(), "> d");
+        // The status isn't kept in the line buffer, so it's shown with
+        // the buffer full, wrapping at the edge of the screen
+        advance(&mut terminal, &mut line, "abcd").unwrap();
+        advance(&mut terminal, &mut line, CtrlX).unwrap();
+        advance(&mut terminal, &mut line, "=").unwrap();
+        assert_eq!(
+            terminal.screen_as_string(),
+            "> abcd  col 5 of 4, \nend of line"
+        );
+        assert_eq!(line.buffer.as_str(), "abcd");
+
+        advance(&mut terminal, &mut line, CtrlA).unwrap();
+        assert_eq!(terminal.screen_as_string(), "> abcd");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 2));
     }
 
     #[test]
-    fn backspace() {
-        let prompt = "> ";
-        let (mut terminal, mut editor) = get_terminal_and_editor(1, 40, Cursor::new(0, 0));
+    fn translated_strings() {
+        struct Mock;
 
-        let mut line = editor.get_line(prompt, &mut terminal);
+        impl Strings for Mock {
+            fn reverse_search_prompt(&self) -> &str {
+                "[search "
+            }
 
-        assert!(advance(&mut terminal, &mut line, Backspace).is_err());
+            fn reverse_search_separator(&self) -> &str {
+                "] "
+            }
 
-        assert_eq!(terminal.get_cursor(), Cursor::new(0, 2));
-        assert_eq!(terminal.screen_as_string(), "> ");
-        advance(&mut terminal, &mut line, "hello").unwrap();
+            fn cursor_position(
+                &self,
+                f: &mut dyn core::fmt::Write,
+                column: usize,
+                len: usize,
+                _c: Option<char>,
+            ) -> core::fmt::Result {
+                write!(f, "{}/{}", column, len)
+            }
+        }
 
-        advance(&mut terminal, &mut line, Backspace).unwrap();
-        assert_eq!(line.buffer.as_str(), "hell");
-        assert_eq!(terminal.screen_as_string(), "> hell");
+        struct Long;
 
-        advance(&mut terminal, &mut line, [csi::LEFT; 2]).unwrap();
-        advance(&mut terminal, &mut line, Backspace).unwrap();
-        assert_eq!(line.buffer.as_str(), "hll");
-        assert_eq!(terminal.screen_as_string(), "> hll");
+        impl Strings for Long {
+            fn reverse_search_prompt(&self) -> &str {
+                "a very long prompt for searching history: "
+            }
+        }
 
-        advance(&mut terminal, &mut line, CtrlA).unwrap();
-        advance(&mut terminal, &mut line, Backspace).unwrap_err();
-    }
+        let mut terminal = MockTerminal::new(4, 80, Cursor::new(0, 0));
+        let mut history = UnboundedHistory::new();
+        history.load_entries(["ls -l", "cd /tmp"].into_iter());
 
-    #[test]
-    fn slice_buffer() {
-        let mut array = [0; 20];
-        let mut terminal = MockTerminal::new(20, 80, Cursor::new(0, 0));
-        let mut editor: Editor<_, NoHistory> =
-            Editor::new(LineBuffer::from_slice(&mut array), NoHistory {});
+        let mut editor = Editor::new(LineBuffer::new_unbounded(), history);
+        editor.settings.strings = Some(&Mock);
 
         let mut line = editor.get_line("> ", &mut terminal);
 
-        let input: String = (0..20).map(|_| "a").collect();
+        advance(&mut terminal, &mut line, CtrlR).unwrap();
+        assert_eq!(terminal.screen_as_string(), "[search ] ");
 
-        advance(&mut terminal, &mut line, input.as_str()).unwrap();
+        advance(&mut terminal, &mut line, "ls").unwrap();
+        assert_eq!(terminal.screen_as_string(), "[search ls] ls -l");
 
-        assert_eq!(advance(&mut terminal, &mut line, "a"), Err(()));
+        advance(&mut terminal, &mut line, Backspace).unwrap();
+        assert_eq!(terminal.screen_as_string(), "[search l] ls -l");
 
-        assert_eq!(line.buffer.as_str(), input);
+        advance(&mut terminal, &mut line, [CtrlE, CtrlX]).unwrap();
+        advance(&mut terminal, &mut line, "=").unwrap();
+        assert_eq!(terminal.screen_as_string(), "> ls -l  6/5");
 
-        advance(&mut terminal, &mut line, Backspace).unwrap();
+        advance(&mut terminal, &mut line, CtrlU).unwrap();
+
+        // Text that doesn't fit is truncated
+        line.settings.strings = Some(&Long);
+        advance(&mut terminal, &mut line, CtrlR).unwrap();
+        assert_eq!(
+            terminal.screen_as_string(),
+            "a very long prompt for searching"
+        );
+        advance(&mut terminal, &mut line, "cd").unwrap();
+        assert_eq!(
+            terminal.screen_as_string(),
+            "a very long prompt for searchingcdcd /tmp"
+        );
     }
 
     #[test]
-    fn history() {
-        fn test<H: History>(history: H) {
-            let mut terminal = MockTerminal::new(20, 80, Cursor::new(0, 0));
-            let mut editor: Editor<_, H> = Editor::new(LineBuffer::new_unbounded(), history);
+    fn home_end_and_ctrl_arrows() {
+        const HOME: &str = "\x1b[H";
+        const END: &str = "\x1b[F";
+        const CTRL_LEFT: &str = "\x1b[1;5D";
+        const CTRL_RIGHT: &str = "\x1b[1;5C";
+        const SHIFT_LEFT: &str = "\x1b[1;2D";
 
-            let mut line = editor.get_line("> ", &mut terminal);
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 10, Cursor::new(0, 0));
 
-            advance(&mut terminal, &mut line, "this is a line\r").unwrap();
+        let mut line = editor.get_line(prompt, &mut terminal);
 
-            let mut line = editor.get_line("> ", &mut terminal);
+        advance(&mut terminal, &mut line, "one two three").unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 5));
 
-            assert_eq!(terminal.screen_as_string(), "> this is a line\n> ");
+        advance(&mut terminal, &mut line, HOME).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 2));
 
-            assert!(advance(&mut terminal, &mut line, csi::UP).is_ok());
+        advance(&mut terminal, &mut line, END).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 5));
 
-            assert_eq!(
-                terminal.screen_as_string(),
-                "> this is a line\n> this is a line"
-            );
+        advance(&mut terminal, &mut line, CTRL_LEFT).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 0));
 
-            assert!(advance(&mut terminal, &mut line, csi::DOWN).is_ok());
+        advance(&mut terminal, &mut line, CTRL_LEFT).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 6));
 
-            assert_eq!(terminal.screen_as_string(), "> this is a line\n> ");
+        advance(&mut terminal, &mut line, CTRL_RIGHT).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 0));
 
-            advance(&mut terminal, &mut line, "another line\r").unwrap();
+        // Other modifiers move a single character
+        advance(&mut terminal, &mut line, SHIFT_LEFT).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 9));
 
-            let mut line = editor.get_line("> ", &mut terminal);
-            advance(&mut terminal, &mut line, "yet another line\r").unwrap();
+        advance(&mut terminal, &mut line, "x").unwrap();
+        assert_eq!(line.buffer.as_str(), "one twox three");
+    }
 
-            let mut line = editor.get_line("> ", &mut terminal);
+    #[cfg(not(feature = "ascii-only"))]
+    #[test]
+    fn ctrl_and_alt_arrows_by_word() {
+        const CTRL_RIGHT: &str = "\x1b[1;5C";
+        const ALT_RIGHT: &str = "\x1b[1;3C";
+        const ALT_LEFT: &str = "\x1b[1;3D";
 
-            assert_eq!(
-                terminal.screen_as_string(),
-                "> this is a line\n> another line\n> yet another line\n> "
-            );
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 20, Cursor::new(0, 0));
 
-            assert!(advance(&mut terminal, &mut line, csi::UP).is_ok());
+        let mut line = editor.get_line(prompt, &mut terminal);
 
-            assert_eq!(
-                terminal.screen_as_string(),
-                "> this is a line\n> another line\n> yet another line\n> yet another line"
-            );
+        advance(&mut terminal, &mut line, "æøå  wörd ord3").unwrap();
+        advance(&mut terminal, &mut line, CtrlA).unwrap();
 
-            assert!(advance(&mut terminal, &mut line, csi::UP).is_ok());
+        advance(&mut terminal, &mut line, CTRL_RIGHT).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 7));
 
-            assert_eq!(
-                terminal.screen_as_string(),
-                "> this is a line\n> another line\n> yet another line\n> another line"
-            );
+        advance(&mut terminal, &mut line, ALT_RIGHT).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 12));
 
-            assert!(advance(&mut terminal, &mut line, csi::UP).is_ok());
+        // The last word moves to the end of the line
+        advance(&mut terminal, &mut line, CTRL_RIGHT).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 16));
+        assert!(advance(&mut terminal, &mut line, CTRL_RIGHT).is_err());
 
-            assert_eq!(
-                terminal.screen_as_string(),
-                "> this is a line\n> another line\n> yet another line\n> this is a line"
-            );
+        advance(&mut terminal, &mut line, ALT_LEFT).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 12));
 
-            assert!(advance(&mut terminal, &mut line, csi::UP).is_err());
+        advance(&mut terminal, &mut line, ALT_LEFT).unwrap();
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 7));
 
-            assert_eq!(
-                terminal.screen_as_string(),
-                "> this is a line\n> another line\n> yet another line\n> this is a line"
-            );
+        advance(&mut terminal, &mut line, "x").unwrap();
+        assert_eq!(line.buffer.as_str(), "æøå  xwörd ord3");
+    }
 
-            assert!(advance(&mut terminal, &mut line, csi::DOWN).is_ok());
+    // Resize mock terminal and line, then redraw
+    fn resize<'a, B: Buffer, H: History>(
+        terminal: &mut MockTerminal,
+        line: &mut Line<
+            'a,
+            B,
+            H,
+            dyn PromptSource<'static>,
+            dyn Completer,
+            dyn Validator,
+            HistoryHinter,
+        >,
+        rows: usize,
+        columns: usize,
+    ) {
+        terminal.resize(rows, columns);
+        line.terminal.resize(rows, columns);
 
-            assert_eq!(
-                terminal.screen_as_string(),
-                "> this is a line\n> another line\n> yet another line\n> another line"
-            );
+        let pos = line.current_position();
+        let mut output = line.redraw().into_vec();
+        output.extend(line.restore_cursor(pos).into_vec());
 
-            assert!(advance(&mut terminal, &mut line, csi::DOWN).is_ok());
+        for b in output {
+            terminal.advance(b);
+        }
 
-            assert_eq!(
-                terminal.screen_as_string(),
-                "> this is a line\n> another line\n> yet another line\n> yet another line"
-            );
-            assert!(advance(&mut terminal, &mut line, csi::DOWN).is_ok());
+        assert_eq!(line.terminal.get_cursor(), terminal.cursor);
+        assert_eq!(line.current_position(), pos);
+    }
 
-            assert_eq!(
-                terminal.screen_as_string(),
-                "> this is a line\n> another line\n> yet another line\n> "
-            );
+    #[test]
+    fn resize_mid_line() {
+        let prompt = "> ";
+        let (mut terminal, mut editor) = get_terminal_and_editor(4, 10, Cursor::new(1, 0));
 
-            assert!(advance(&mut terminal, &mut line, csi::DOWN).is_err());
+        let mut line = editor.get_line(prompt, &mut terminal);
 
-            assert_eq!(
-                terminal.screen_as_string(),
-                "> this is a line\n> another line\n> yet another line\n> "
-            );
-        }
+        let char_at_cursor = |terminal: &MockTerminal| {
+            let cursor = terminal.get_cursor();
+            terminal
+                .row_as_string(cursor.row)
+                .chars()
+                .nth(cursor.column)
+        };
 
-        test(UnboundedHistory::new());
-        let mut buffer = [0; 128];
-        test(SliceHistory::new(&mut buffer));
+        advance(
+            &mut terminal,
+            &mut line,
+            ["abcdefghijklmnop", csi::LEFT, csi::LEFT, csi::LEFT],
+        )
+        .unwrap();
+        assert_eq!(terminal.screen_as_string(), "> abcdefgh\nijklmnop");
+        assert_eq!(terminal.get_cursor(), Cursor::new(2, 5));
+        assert_eq!(char_at_cursor(&terminal), Some('n'));
+
+        // Shrinking wraps the line at the new width, scrolling since it
+        // no longer fits below the first row
+        resize(&mut terminal, &mut line, 4, 6);
+        assert_eq!(terminal.screen_as_string(), "> abcd\nefghij\nklmnop");
+        assert_eq!(terminal.get_cursor(), Cursor::new(2, 3));
+        assert_eq!(char_at_cursor(&terminal), Some('n'));
+
+        resize(&mut terminal, &mut line, 4, 20);
+        assert_eq!(terminal.screen_as_string(), "> abcdefghijklmnop");
+        assert_eq!(terminal.get_cursor(), Cursor::new(0, 15));
+        assert_eq!(char_at_cursor(&terminal), Some('n'));
+
+        advance(&mut terminal, &mut line, "x").unwrap();
+        assert_eq!(terminal.screen_as_string(), "> abcdefghijklmxnop");
+
+        resize(&mut terminal, &mut line, 2, 10);
+        assert_eq!(terminal.screen_as_string(), "> abcdefgh\nijklmxnop");
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 6));
+        assert_eq!(char_at_cursor(&terminal), Some('n'));
+
+        // Editing continues with the new size
+        advance(&mut terminal, &mut line, [CtrlE]).unwrap();
+        advance(&mut terminal, &mut line, "qr").unwrap();
+        assert_eq!(terminal.screen_as_string(), "ijklmxnopq\nr");
+        assert_eq!(terminal.get_cursor(), Cursor::new(1, 1));
+        assert_eq!(line.buffer.as_str(), "abcdefghijklmxnopqr");
     }
 }
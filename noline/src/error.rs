@@ -1,9 +1,315 @@
 //! Error types
 
+use core::fmt;
+
 /// Enum to hold various error types
+///
+/// New variants may be added in minor releases, so matches on it need
+/// a wildcard arm.
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
 pub enum NolineError {
     ParserError,
+    /// Line was aborted with Ctrl+C, or input ended
     Aborted,
+    /// Ctrl+D was pressed on an empty line
+    Eof,
+    /// Reading input failed
+    ReadError(embedded_io::ErrorKind),
+    /// Writing or flushing output failed
+    WriteError(embedded_io::ErrorKind),
+    /// Probing the terminal for size and cursor position failed
+    ProbeFailed(ProbeFailure),
+    /// Link to the terminal was lost, see [`crate::link`]
+    Disconnected,
+    /// The idle hook of
+    /// [`crate::sync_editor::Editor::readline_with_idle`] stopped
+    /// reading the line
+    IdleAborted,
+    /// Line read by [`crate::sync_editor::Editor::readline_into`] or
+    /// [`crate::async_editor::Editor::readline_into`] doesn't fit in the
+    /// output buffer. Holds the length of the line in bytes.
+    OutputTooSmall(usize),
+}
+
+/// Reason for [`NolineError::ProbeFailed`]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ProbeFailure {
+    /// Terminal sent too much input other than cursor position
+    /// reports, e.g. because it doesn't support them. Holds the first
+    /// offending input sequence.
+    UnexpectedInput(InputSequence),
+    /// Cursor position report doesn't match the probe, e.g. because
+    /// another editor is probing on the same IO. Holds the reported
+    /// row and column.
+    UnexpectedReply(usize, usize),
+    /// Input ended before the terminal replied
+    Timeout,
+    /// IO error while probing
     IoError(embedded_io::ErrorKind),
 }
+
+/// Bytes of one input sequence, like a key or an escape sequence.
+/// Sequences longer than [`InputSequence::CAPACITY`] are truncated.
+#[derive(Copy, Clone, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct InputSequence {
+    bytes: [u8; InputSequence::CAPACITY],
+    len: u8,
+}
+
+impl InputSequence {
+    /// Bytes kept of a sequence
+    pub const CAPACITY: usize = 8;
+
+    pub(crate) fn push(&mut self, byte: u8) {
+        if let Some(slot) = self.bytes.get_mut(self.len as usize) {
+            *slot = byte;
+            self.len += 1;
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Bytes of the sequence
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+impl From<&[u8]> for InputSequence {
+    fn from(bytes: &[u8]) -> Self {
+        let mut sequence = Self::default();
+
+        for &byte in bytes {
+            sequence.push(byte);
+        }
+
+        sequence
+    }
+}
+
+impl fmt::Debug for InputSequence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "b\"{}\"", self.as_bytes().escape_ascii())
+    }
+}
+
+impl NolineError {
+    pub(crate) fn read_error<E: embedded_io::Error>(err: E) -> Self {
+        NolineError::ReadError(err.kind())
+    }
+
+    pub(crate) fn write_error<E: embedded_io::Error>(err: E) -> Self {
+        NolineError::WriteError(err.kind())
+    }
+
+    pub(crate) fn during_probe(self) -> Self {
+        match self {
+            NolineError::Aborted => NolineError::ProbeFailed(ProbeFailure::Timeout),
+            NolineError::ReadError(kind) | NolineError::WriteError(kind) => {
+                NolineError::ProbeFailed(ProbeFailure::IoError(kind))
+            }
+            err => err,
+        }
+    }
+}
+
+impl fmt::Display for ProbeFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProbeFailure::UnexpectedInput(sequence) => write!(
+                f,
+                "unexpected input \"{}\" while waiting for cursor position report",
+                sequence.as_bytes().escape_ascii()
+            ),
+            ProbeFailure::UnexpectedReply(row, column) => write!(
+                f,
+                "cursor position report {};{} doesn't match probe",
+                row, column
+            ),
+            ProbeFailure::Timeout => {
+                write!(f, "terminal did not reply to cursor position query")
+            }
+            ProbeFailure::IoError(kind) => write!(f, "IO error while probing terminal: {:?}", kind),
+        }
+    }
+}
+
+impl fmt::Display for NolineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NolineError::ParserError => write!(f, "unable to parse input"),
+            NolineError::Aborted => write!(f, "aborted"),
+            NolineError::Eof => write!(f, "end of file"),
+            NolineError::ReadError(kind) => write!(f, "read error: {:?}", kind),
+            NolineError::WriteError(kind) => write!(f, "write error: {:?}", kind),
+            NolineError::ProbeFailed(failure) => {
+                write!(f, "terminal probe failed: {}", failure)
+            }
+            NolineError::Disconnected => write!(f, "disconnected"),
+            NolineError::IdleAborted => write!(f, "aborted by idle hook"),
+            NolineError::OutputTooSmall(len) => {
+                write!(f, "line of {} bytes doesn't fit in output buffer", len)
+            }
+        }
+    }
+}
+
+impl core::error::Error for ProbeFailure {}
+
+impl core::error::Error for NolineError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            NolineError::ProbeFailed(failure) => Some(failure),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+mod io_error {
+    extern crate std;
+
+    use super::*;
+    use std::io;
+
+    /// Converts to an error of the matching kind, keeping the message.
+    /// Abort, also by the idle hook, is [`io::ErrorKind::Interrupted`],
+    /// end of file is [`io::ErrorKind::UnexpectedEof`], a lost link is
+    /// [`io::ErrorKind::NotConnected`] and failure to parse input or
+    /// probe the terminal is [`io::ErrorKind::InvalidData`], unless the
+    /// probe failed because of IO. An output buffer too small for the
+    /// line is [`io::ErrorKind::InvalidInput`].
+    impl From<NolineError> for io::Error {
+        fn from(err: NolineError) -> Self {
+            let kind = match err {
+                NolineError::Aborted | NolineError::IdleAborted => io::ErrorKind::Interrupted,
+                NolineError::Eof => io::ErrorKind::UnexpectedEof,
+                NolineError::Disconnected => io::ErrorKind::NotConnected,
+                NolineError::ReadError(kind)
+                | NolineError::WriteError(kind)
+                | NolineError::ProbeFailed(ProbeFailure::IoError(kind)) => kind.into(),
+                NolineError::ParserError | NolineError::ProbeFailed(_) => {
+                    io::ErrorKind::InvalidData
+                }
+                NolineError::OutputTooSmall(_) => io::ErrorKind::InvalidInput,
+            };
+
+            io::Error::new(kind, err)
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::error::Error;
+    use std::io;
+    use std::string::{String, ToString};
+
+    use super::*;
+
+    #[test]
+    fn into_io_error() {
+        fn convert(err: NolineError) -> (io::ErrorKind, String) {
+            let message = err.to_string();
+            let err = io::Error::from(err);
+
+            assert_eq!(err.to_string(), message);
+
+            (err.kind(), message)
+        }
+
+        assert_eq!(
+            convert(NolineError::Aborted),
+            (io::ErrorKind::Interrupted, "aborted".into())
+        );
+        assert_eq!(
+            convert(NolineError::Eof),
+            (io::ErrorKind::UnexpectedEof, "end of file".into())
+        );
+        assert_eq!(
+            convert(NolineError::ReadError(embedded_io::ErrorKind::TimedOut)),
+            (io::ErrorKind::TimedOut, "read error: TimedOut".into())
+        );
+        assert_eq!(
+            convert(NolineError::WriteError(embedded_io::ErrorKind::BrokenPipe)),
+            (io::ErrorKind::BrokenPipe, "write error: BrokenPipe".into())
+        );
+        assert_eq!(
+            convert(NolineError::IdleAborted),
+            (io::ErrorKind::Interrupted, "aborted by idle hook".into())
+        );
+        assert_eq!(
+            convert(NolineError::Disconnected),
+            (io::ErrorKind::NotConnected, "disconnected".into())
+        );
+        assert_eq!(
+            convert(NolineError::OutputTooSmall(12)),
+            (
+                io::ErrorKind::InvalidInput,
+                "line of 12 bytes doesn't fit in output buffer".into()
+            )
+        );
+        assert_eq!(
+            convert(NolineError::ParserError),
+            (io::ErrorKind::InvalidData, "unable to parse input".into())
+        );
+        assert_eq!(
+            convert(NolineError::ProbeFailed(ProbeFailure::Timeout)),
+            (
+                io::ErrorKind::InvalidData,
+                "terminal probe failed: terminal did not reply to cursor position query".into()
+            )
+        );
+        assert_eq!(
+            convert(NolineError::ProbeFailed(ProbeFailure::IoError(
+                embedded_io::ErrorKind::TimedOut
+            )))
+            .0,
+            io::ErrorKind::TimedOut
+        );
+    }
+
+    #[test]
+    fn source() {
+        let err = NolineError::ProbeFailed(ProbeFailure::UnexpectedInput(
+            b"\x1b[?1;2c".as_slice().into(),
+        ));
+
+        assert_eq!(
+            err.source().unwrap().to_string(),
+            "unexpected input \"\\x1b[?1;2c\" while waiting for cursor position report"
+        );
+        assert!(NolineError::Aborted.source().is_none());
+    }
+
+    #[test]
+    fn debug() {
+        use std::format;
+
+        use crate::keys::KeyEvent;
+        use crate::sync_editor::PollResult;
+
+        assert_eq!(
+            format!(
+                "{:?}",
+                NolineError::ReadError(embedded_io::ErrorKind::TimedOut)
+            ),
+            "ReadError(TimedOut)"
+        );
+        assert_eq!(
+            format!(
+                "{:?}",
+                ProbeFailure::UnexpectedInput(b"x".as_slice().into())
+            ),
+            "UnexpectedInput(b\"x\")"
+        );
+        assert_eq!(format!("{:?}", KeyEvent::Enter), "Enter");
+        assert_eq!(format!("{:?}", PollResult::Pending), "Pending");
+    }
+}
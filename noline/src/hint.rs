@@ -0,0 +1,414 @@
+//! Hints shown after the line
+//!
+//! Add a [`Hinter`] to the editor with
+//! [`crate::builder::EditorBuilder::with_hinter`]. After a character
+//! is typed at the end of the line, the hint is shown dimmed after the
+//! cursor. The hint isn't part of the line until Right, Ctrl-F, End or
+//! Ctrl-E accepts it, inserting it into the line. Any other key
+//! discards it before being handled.
+//!
+//! [`HistoryHinter`] suggests the rest of the most recent history
+//! entry starting with the line, like the autosuggestions of fish:
+//! ```
+//! use noline::builder::EditorBuilder;
+//! use noline::hint::HistoryHinter;
+//!
+//! let mut buffer = [0; 64];
+//! let mut history = [0; 256];
+//! let builder = EditorBuilder::from_slice(&mut buffer)
+//!     .with_slice_history(&mut history)
+//!     .with_hinter(HistoryHinter);
+//! ```
+//!
+//! Hints are only shown on the row of the cursor, and are cut off at
+//! the end of it. Hints that don't fit in the line buffer aren't shown,
+//! except for hints from history, which are cut to fit.
+
+use crate::{
+    history::{CircularSlice, History},
+    line_buffer::{Buffer, LineBuffer},
+};
+
+/// Line being hinted, see [`Hinter::hint_into`]
+pub trait HintTarget {
+    /// Line typed by the user
+    fn line(&self) -> &str;
+
+    /// Append hint to the line
+    fn append(&mut self, hint: &str);
+
+    /// Append the rest of the most recent history entry starting with
+    /// the line. Returns false if there is no such entry.
+    fn append_from_history(&mut self) -> bool;
+}
+
+/// Provider of hints
+pub trait Hinter {
+    /// Append the hint for the line to `target`, the text to show
+    /// after it. Returns false if there is no hint.
+    ///
+    /// The default implementation suggests the rest of the most
+    /// recent history entry starting with the line.
+    ///
+    /// The hint is asked for again while it is printed, and once more
+    /// if it is accepted, so it must be the same every time for the
+    /// same line. A hint is either appended text or the rest of a
+    /// history entry, hints mixing the two aren't shown.
+    fn hint_into(&self, target: &mut dyn HintTarget) -> bool {
+        target.append_from_history()
+    }
+}
+
+/// Hinter showing no hints, used when no [`Hinter`] is given
+pub struct NoHinter;
+
+impl Hinter for NoHinter {
+    fn hint_into(&self, _target: &mut dyn HintTarget) -> bool {
+        false
+    }
+}
+
+/// Hinter suggesting the rest of the most recent history entry
+/// starting with the line
+pub struct HistoryHinter;
+
+impl Hinter for HistoryHinter {}
+
+// Rest of the most recent history entry starting with the line, longer
+// than the line
+fn history_rest<'h, H: History + ?Sized>(
+    line: &str,
+    history: &'h H,
+) -> Option<(&'h [u8], &'h [u8])> {
+    let line = line.as_bytes();
+    let len = line.len();
+
+    let (slice1, slice2) = (0..history.number_of_entries())
+        .rev()
+        .filter_map(|index| history.get_entry(index))
+        .filter(CircularSlice::is_utf8)
+        .map(|entry| entry.get_slices())
+        .find(|(slice1, slice2)| {
+            slice1.len() + slice2.len() > len
+                && slice1.iter().chain(slice2.iter()).take(len).eq(line)
+        })?;
+
+    let skip = len.min(slice1.len());
+
+    Some((&slice1[skip..], &slice2[len - skip..]))
+}
+
+// Hint for the line, found without changing the line
+pub(crate) enum Hint<'h> {
+    // Text appended by the hinter, copied from it again when printed,
+    // see [`copy_hint`]
+    Text,
+    // Rest of a history entry, in two parts where the history wraps
+    // around, cut to fit in the buffer
+    Entry(&'h [u8], &'h [u8]),
+}
+
+// Line as hint target, finding the hint the hinter would append
+struct ProbeTarget<'a, 'h, H: History + ?Sized> {
+    line: &'a str,
+    history: &'h H,
+    // Bytes of text appended, or None if some can't be inserted
+    text: Option<usize>,
+    entry: Option<(&'h [u8], &'h [u8])>,
+    mixed: bool,
+}
+
+impl<H: History + ?Sized> HintTarget for ProbeTarget<'_, '_, H> {
+    fn line(&self) -> &str {
+        self.line
+    }
+
+    fn append(&mut self, hint: &str) {
+        if cfg!(feature = "ascii-only") && !hint.is_ascii() {
+            self.text = None;
+        }
+
+        self.text = self.text.map(|len| len + hint.len());
+    }
+
+    fn append_from_history(&mut self) -> bool {
+        let rest = history_rest(self.line, self.history);
+
+        self.mixed |= rest.is_some() && self.entry.is_some();
+        self.entry = self.entry.or(rest);
+
+        rest.is_some()
+    }
+}
+
+// Find the hint for the line, if there is one that fits in
+// `capacity` bytes, or any if None
+pub(crate) fn find_hint<'h, T: Hinter + ?Sized, H: History + ?Sized>(
+    hinter: &T,
+    line: &str,
+    history: &'h H,
+    capacity: Option<usize>,
+) -> Option<Hint<'h>> {
+    let mut target = ProbeTarget {
+        line,
+        history,
+        text: Some(0),
+        entry: None,
+        mixed: false,
+    };
+
+    hinter.hint_into(&mut target);
+
+    match (target.text, target.entry) {
+        _ if target.mixed => None,
+        (Some(0), Some((slice1, slice2))) => {
+            let len = slice1.len() + slice2.len();
+            let bytes = || slice1.iter().chain(slice2.iter());
+            let mut cut = capacity.map_or(len, |capacity| capacity.min(len));
+
+            // Back up to the start of the character containing the cut
+            while cut > 0 && cut < len && bytes().nth(cut).is_some_and(|b| b & 0xc0 == 0x80) {
+                cut -= 1;
+            }
+
+            let split = cut.min(slice1.len());
+
+            (cut > 0).then(|| Hint::Entry(&slice1[..split], &slice2[..(cut - split)]))
+        }
+        (Some(len), None) if len > 0 && capacity.is_none_or(|capacity| len <= capacity) => {
+            Some(Hint::Text)
+        }
+        _ => None,
+    }
+}
+
+// Line as hint target, copying the hint from byte `offset` into `dest`,
+// as much as fits
+struct CopyTarget<'a, 'd> {
+    line: &'a str,
+    offset: usize,
+    dest: &'d mut [u8],
+    len: usize,
+}
+
+impl HintTarget for CopyTarget<'_, '_> {
+    fn line(&self) -> &str {
+        self.line
+    }
+
+    fn append(&mut self, hint: &str) {
+        let bytes = hint.as_bytes();
+        let skip = self.offset.min(bytes.len());
+        let rest = &bytes[skip..];
+        let len = rest.len().min(self.dest.len() - self.len);
+
+        self.offset -= skip;
+        self.dest[self.len..(self.len + len)].copy_from_slice(&rest[..len]);
+        self.len += len;
+    }
+
+    // Hints from history are printed from the entry, see [`Hint`]
+    fn append_from_history(&mut self) -> bool {
+        false
+    }
+}
+
+// Copy the hint for the line from byte `offset` into `dest`, as much as
+// fits. Returns the number of bytes copied. Hints are printed this way,
+// as they don't outlive the hinter.
+pub(crate) fn copy_hint<T: Hinter + ?Sized>(
+    hinter: &T,
+    line: &str,
+    offset: usize,
+    dest: &mut [u8],
+) -> usize {
+    let mut target = CopyTarget {
+        line,
+        offset,
+        dest,
+        len: 0,
+    };
+
+    hinter.hint_into(&mut target);
+    target.len
+}
+
+// Line buffer and history the hint is taken from, when the hint is
+// accepted
+pub(crate) struct HintBuffer<'b, B: Buffer, H: History + ?Sized> {
+    buffer: &'b mut LineBuffer<B>,
+    history: &'b H,
+}
+
+impl<'b, B: Buffer, H: History + ?Sized> HintBuffer<'b, B, H> {
+    pub(crate) fn new(buffer: &'b mut LineBuffer<B>, history: &'b H) -> Self {
+        Self { buffer, history }
+    }
+}
+
+impl<B: Buffer, H: History + ?Sized> HintTarget for HintBuffer<'_, B, H> {
+    fn line(&self) -> &str {
+        self.buffer.as_str()
+    }
+
+    fn append(&mut self, hint: &str) {
+        let len = self.buffer.as_str().chars().count();
+
+        // Hints that don't fit are dropped, rather than ringing the
+        // bell for keys typed by the user
+        if self.buffer.insert_str(len, hint).is_err() {
+            self.buffer.delete_after_char(len);
+        }
+    }
+
+    fn append_from_history(&mut self) -> bool {
+        match history_rest(self.buffer.as_str(), self.history) {
+            Some((slice1, slice2)) => {
+                // The line is a prefix of the entry, so the rest
+                // starts at a char boundary, and the entry was checked
+                // to be valid UTF-8
+                unsafe {
+                    self.buffer.append_slices(slice1, slice2);
+                }
+
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::history::SliceHistory;
+
+    use super::*;
+
+    fn hint(line: &str, entries: &[&str]) -> Option<std::string::String> {
+        let mut storage = [0; 64];
+        let mut history = SliceHistory::new(&mut storage);
+        history.load_entries(entries.iter().copied());
+
+        let mut buffer = LineBuffer::new_unbounded();
+        buffer.insert_str(0, line).unwrap();
+
+        let mut target = HintBuffer::new(&mut buffer, &history);
+
+        if HistoryHinter.hint_into(&mut target) {
+            Some(buffer.as_str()[line.len()..].into())
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn history_hint() {
+        assert_eq!(hint("ab", &["abc", "abd", "x"]).as_deref(), Some("d"));
+        assert_eq!(hint("ab", &["abc", "ab"]).as_deref(), Some("c"));
+        assert_eq!(hint("abc", &["abc"]), None);
+        assert_eq!(hint("b", &["abc"]), None);
+        assert_eq!(hint("ab", &[]), None);
+    }
+
+    #[test]
+    fn history_hint_wrapping_entry() {
+        // The last entry wraps around the end of the history buffer
+        let entries = [
+            "0123456789012345678901234567890123456789",
+            "01234567890123456789",
+            "abcdefghijklmnopqrstuvwxyz",
+        ];
+
+        assert_eq!(
+            hint("abcdef", &entries).as_deref(),
+            Some("ghijklmnopqrstuvwxyz")
+        );
+    }
+
+    // Suggests a unit after a number, appended in two parts
+    struct UnitHinter;
+
+    impl Hinter for UnitHinter {
+        fn hint_into(&self, target: &mut dyn HintTarget) -> bool {
+            if !target.line().ends_with(|c: char| c.is_ascii_digit()) {
+                return false;
+            }
+
+            target.append(" milli");
+            target.append("seconds");
+            true
+        }
+    }
+
+    // Appends text and the rest of a history entry
+    struct MixedHinter;
+
+    impl Hinter for MixedHinter {
+        fn hint_into(&self, target: &mut dyn HintTarget) -> bool {
+            target.append("x");
+            target.append_from_history()
+        }
+    }
+
+    #[test]
+    fn text_hint() {
+        let history = crate::history::NoHistory::new();
+
+        assert!(matches!(
+            find_hint(&UnitHinter, "wait 5", &history, None),
+            Some(Hint::Text)
+        ));
+        assert!(find_hint(&UnitHinter, "wait", &history, None).is_none());
+
+        // Text is copied a chunk at a time, across the appended parts
+        let mut dest = [0; 4];
+        let len = copy_hint(&UnitHinter, "wait 5", 4, &mut dest);
+        assert_eq!(&dest[..len], b"lise");
+
+        let len = copy_hint(&UnitHinter, "wait 5", 12, &mut dest);
+        assert_eq!(&dest[..len], b"s");
+
+        // Hints are only shown if they fit
+        assert!(find_hint(&UnitHinter, "wait 5", &history, Some(13)).is_some());
+        assert!(find_hint(&UnitHinter, "wait 5", &history, Some(12)).is_none());
+    }
+
+    #[test]
+    fn entry_hint() {
+        let mut storage = [0; 64];
+        let mut history = SliceHistory::new(&mut storage);
+        history.load_entries(["abcdef"].into_iter());
+
+        let slices = |hint| match hint {
+            Some(Hint::Entry(slice1, slice2)) => [slice1, slice2].concat(),
+            _ => panic!("no entry"),
+        };
+
+        assert_eq!(
+            slices(find_hint(&HistoryHinter, "ab", &history, None)),
+            b"cdef"
+        );
+
+        // Hints from history are cut to fit
+        assert_eq!(
+            slices(find_hint(&HistoryHinter, "ab", &history, Some(3))),
+            b"cde"
+        );
+        assert!(find_hint(&HistoryHinter, "ab", &history, Some(0)).is_none());
+
+        assert!(find_hint(&MixedHinter, "ab", &history, None).is_none());
+    }
+
+    #[test]
+    fn dropped_hint() {
+        let mut storage = [0; 4];
+        let mut buffer = LineBuffer::from_slice(&mut storage);
+        buffer.insert_str(0, "ab").unwrap();
+
+        let history = crate::history::NoHistory::new();
+        let mut target = HintBuffer::new(&mut buffer, &history);
+        target.append("cdef");
+
+        assert_eq!(buffer.as_str(), "ab");
+    }
+}
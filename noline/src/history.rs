@@ -1,13 +1,44 @@
 //! Line history
+//!
+//! History can be persisted, e.g. to flash or a file, with
+//! [`serialize`] and [`deserialize`]. Entries are stored oldest first,
+//! each followed by a NUL byte, like [`SliceHistory`] stores them
+//! internally.
+//!
+//! Editors own their history, unless it's shared between them with
+//! [`SharedHistory`], e.g. for a UART and a USB console on the same
+//! device:
+//! ```
+//! use core::cell::RefCell;
+//!
+//! use noline::builder::EditorBuilder;
+//! use noline::history::{SharedHistory, SliceHistory};
+//!
+//! let mut storage = [0; 256];
+//! let history = RefCell::new(SliceHistory::new(&mut storage));
+//!
+//! let mut uart_buffer = [0; 64];
+//! let uart = EditorBuilder::from_slice(&mut uart_buffer)
+//!     .with_history(SharedHistory::new(&history));
+//!
+//! let mut usb_buffer = [0; 64];
+//! let usb = EditorBuilder::from_slice(&mut usb_buffer)
+//!     .with_history(SharedHistory::new(&history));
+//! ```
 
 use core::{
-    iter::{Chain, Zip},
-    ops::Range,
+    cell::{RefCell, RefMut},
+    fmt::{self, Write},
+    iter::{Chain, Copied, Zip},
+    ops::{DerefMut, Range},
     slice,
+    str::from_utf8,
 };
 
-#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
-#[cfg_attr(test, derive(Debug))]
+use crate::line_buffer::{Buffer, LineBuffer};
+use crate::utf8::{Utf8Decoder, Utf8DecoderStatus};
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
 struct CircularIndex {
     index: usize,
     size: usize,
@@ -56,12 +87,12 @@ impl Window {
         self.end.diff(self.start) as usize
     }
 
+    // Room must be made before widening, as the window never covers
+    // more than the whole buffer
     fn widen(&mut self) {
-        self.end.increment();
+        debug_assert!(self.len() < self.size);
 
-        if self.end.diff(self.start) as usize > self.size {
-            self.start.increment();
-        }
+        self.end.increment();
     }
 
     fn narrow(&mut self) {
@@ -79,7 +110,7 @@ impl Window {
     }
 }
 
-#[cfg_attr(test, derive(Debug))]
+#[derive(Debug)]
 enum CircularRange {
     Consecutive(Range<usize>),
     Split(Range<usize>, Range<usize>),
@@ -148,6 +179,233 @@ impl<'a> CircularSlice<'a> {
 
         (&self.buffer[range1], &self.buffer[range2])
     }
+
+    /// Return true if slice is equal to `bytes`
+    pub(crate) fn eq_bytes(&self, bytes: &[u8]) -> bool {
+        let (slice1, slice2) = self.get_slices();
+
+        slice1.len() + slice2.len() == bytes.len()
+            && bytes.starts_with(slice1)
+            && bytes.ends_with(slice2)
+    }
+
+    /// Return length in bytes
+    pub fn len(&self) -> usize {
+        let (slice1, slice2) = self.get_slices();
+
+        slice1.len() + slice2.len()
+    }
+
+    /// Return true if the slice is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Return true if the slice doesn't wrap around, so it can be
+    /// borrowed as a single slice
+    pub fn is_contiguous(&self) -> bool {
+        matches!(self.get_slices(), (_, []) | ([], _))
+    }
+
+    /// Copy slice into `buffer`, returning the number of bytes copied.
+    /// Nothing is copied if it doesn't fit.
+    #[allow(clippy::result_unit_err)]
+    pub fn copy_to(&self, buffer: &mut [u8]) -> Result<usize, ()> {
+        let (slice1, slice2) = self.get_slices();
+        let len = slice1.len() + slice2.len();
+        let buffer = buffer.get_mut(..len).ok_or(())?;
+
+        buffer[..slice1.len()].copy_from_slice(slice1);
+        buffer[slice1.len()..].copy_from_slice(slice2);
+
+        Ok(len)
+    }
+
+    /// Return an iterator over the bytes of the slice
+    pub fn iter_bytes(&self) -> Chain<Copied<slice::Iter<'a, u8>>, Copied<slice::Iter<'a, u8>>> {
+        let (slice1, slice2) = self.get_slices();
+
+        slice1.iter().copied().chain(slice2.iter().copied())
+    }
+
+    // Split slice into strings: the part before a char split by the
+    // wrap around, the split char copied into `split_char`, and the
+    // rest. None if the slice isn't valid UTF-8.
+    fn str_parts<'b>(&self, split_char: &'b mut [u8; 4]) -> Option<[&'b str; 3]>
+    where
+        'a: 'b,
+    {
+        let (slice1, slice2) = self.get_slices();
+
+        let (head, start) = match from_utf8(slice1) {
+            Ok(head) => (head, &[][..]),
+            // Incomplete char at the end, continued in the second slice
+            Err(err) if err.error_len().is_none() => {
+                let (head, start) = slice1.split_at(err.valid_up_to());
+                (from_utf8(head).ok()?, start)
+            }
+            Err(_) => return None,
+        };
+
+        let continuation = slice2
+            .iter()
+            .take(split_char.len() - start.len())
+            .take_while(|&&b| b & 0xc0 == 0x80)
+            .count();
+        let len = start.len() + continuation;
+
+        split_char[..start.len()].copy_from_slice(start);
+        split_char[start.len()..len].copy_from_slice(&slice2[..continuation]);
+
+        Some([
+            head,
+            from_utf8(&split_char[..len]).ok()?,
+            from_utf8(&slice2[continuation..]).ok()?,
+        ])
+    }
+
+    // True if the slice is valid UTF-8, also across the wrap around
+    pub(crate) fn is_utf8(&self) -> bool {
+        self.str_parts(&mut [0; 4]).is_some()
+    }
+
+    fn byte(&self, index: usize) -> u8 {
+        let (slice1, slice2) = self.get_slices();
+
+        if index < slice1.len() {
+            slice1[index]
+        } else {
+            slice2[index - slice1.len()]
+        }
+    }
+
+    /// Return true if slice starts with `prefix`, also across the wrap
+    /// around.
+    pub(crate) fn starts_with(&self, prefix: &[u8]) -> bool {
+        let (slice1, slice2) = self.get_slices();
+        let split = prefix.len().min(slice1.len());
+
+        slice1.starts_with(&prefix[..split]) && slice2.starts_with(&prefix[split..])
+    }
+
+    /// Return true if slice contains `needle`, also across the wrap
+    /// around.
+    pub(crate) fn contains(&self, needle: &[u8]) -> bool {
+        let len = self.len();
+
+        needle.len() <= len
+            && (0..=(len - needle.len())).any(|start| {
+                needle
+                    .iter()
+                    .enumerate()
+                    .all(|(i, &b)| self.byte(start + i) == b)
+            })
+    }
+
+    /// Return the last word delimited by whitespace, split in two like
+    /// [`CircularSlice::get_slices`]. Both slices are empty if there
+    /// are no words.
+    pub(crate) fn last_word(&self) -> (&'a [u8], &'a [u8]) {
+        let is_space = |index: &usize| self.byte(*index).is_ascii_whitespace();
+
+        let end = (0..self.len())
+            .rev()
+            .find(|index| !is_space(index))
+            .map_or(0, |index| index + 1);
+        let start = (0..end).rev().find(is_space).map_or(0, |index| index + 1);
+
+        let (slice1, slice2) = self.get_slices();
+        let split = slice1.len();
+
+        if end <= split {
+            (&slice1[start..end], &[])
+        } else if start >= split {
+            (&slice2[start - split..end - split], &[])
+        } else {
+            (&slice1[start..], &slice2[..end - split])
+        }
+    }
+
+    /// Return slice as string. If the slice wraps around, it's copied
+    /// into `buffer`, and None is returned if it doesn't fit.
+    pub fn as_str<'b>(&self, buffer: &'b mut [u8]) -> Option<&'b str>
+    where
+        'a: 'b,
+    {
+        let bytes = match self.get_slices() {
+            (slice, []) | ([], slice) => slice,
+            _ => {
+                let len = self.copy_to(buffer).ok()?;
+                &buffer[..len]
+            }
+        };
+
+        from_utf8(bytes).ok()
+    }
+}
+
+impl PartialEq<str> for CircularSlice<'_> {
+    fn eq(&self, other: &str) -> bool {
+        self.eq_bytes(other.as_bytes())
+    }
+}
+
+impl PartialEq<&str> for CircularSlice<'_> {
+    fn eq(&self, other: &&str) -> bool {
+        self.eq_bytes(other.as_bytes())
+    }
+}
+
+/// Writes the slice as a string, also when it wraps around. Invalid
+/// UTF-8 is replaced like [`CircularSlice::as_cow`]. With feature
+/// `alloc` or `std`, this also provides `to_string`.
+impl fmt::Display for CircularSlice<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut split_char = [0; 4];
+
+        match self.str_parts(&mut split_char) {
+            Some(parts) => parts.iter().try_for_each(|part| f.write_str(part)),
+            None => self.write_lossy(f),
+        }
+    }
+}
+
+impl CircularSlice<'_> {
+    // Write chars one by one, replacing each invalid sequence with
+    // U+FFFD. Pending bytes start at a char boundary, so they are
+    // either a char, the start of one, or invalid.
+    fn write_lossy(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut pending = [0; 4];
+        let mut len = 0;
+
+        for byte in self.iter_bytes() {
+            pending[len] = byte;
+            len += 1;
+
+            while len > 0 {
+                match from_utf8(&pending[..len]) {
+                    Ok(s) => {
+                        f.write_str(s)?;
+                        len = 0;
+                    }
+                    Err(err) => match err.error_len() {
+                        Some(invalid) => {
+                            f.write_char(char::REPLACEMENT_CHARACTER)?;
+                            pending.copy_within(invalid..len, 0);
+                            len -= invalid;
+                        }
+                        None => break,
+                    },
+                }
+            }
+        }
+
+        if len > 0 {
+            f.write_char(char::REPLACEMENT_CHARACTER)?;
+        }
+
+        Ok(())
+    }
 }
 
 impl<'a> IntoIterator for CircularSlice<'a> {
@@ -166,7 +424,10 @@ impl<'a> IntoIterator for CircularSlice<'a> {
 
 /// Trait for line history
 pub trait History {
-    /// Return entry at index, or None if out of bounds
+    /// Return entry at index, or None if out of bounds. None is also
+    /// allowed for indices below [`History::number_of_entries`], e.g.
+    /// for entries pruned lazily, and these are skipped when navigating
+    /// history.
     fn get_entry(&self, index: usize) -> Option<CircularSlice<'_>>;
 
     /// Add new entry at the end
@@ -175,30 +436,168 @@ pub trait History {
     /// Return number of entries in history
     fn number_of_entries(&self) -> usize;
 
+    /// Remove all entries. Does nothing by default, for histories that
+    /// can't be cleared.
+    fn clear(&mut self) {}
+
+    /// Add new entry at the end, unless it is equal to the most
+    /// recent entry
+    fn add_entry_deduped<'a>(&mut self, entry: &'a str) -> Result<(), &'a str> {
+        let last = self.number_of_entries().checked_sub(1);
+
+        if last
+            .and_then(|index| self.get_entry(index))
+            .is_some_and(|last| last.eq_bytes(entry.as_bytes()))
+        {
+            Ok(())
+        } else {
+            self.add_entry(entry)
+        }
+    }
+
     /// Add entries from an iterator
     fn load_entries<'a, I: Iterator<Item = &'a str>>(&mut self, entries: I) -> usize {
         entries
             .take_while(|entry| self.add_entry(entry).is_ok())
             .count()
     }
+
+    /// Return an iterator over entries, oldest first. Use
+    /// [`CircularSlice::as_str`] to get entries as strings.
+    fn iter_entries(&self) -> impl Iterator<Item = CircularSlice<'_>> {
+        (0..self.number_of_entries()).filter_map(|index| self.get_entry(index))
+    }
 }
 
-/// Return an iterator over history entries
-///
-/// # Note
-///
-/// This should ideally be in the [`History`] trait, but is
-/// until `type_alias_impl_trait` is stable.
-pub(crate) fn get_history_entries<H: History>(
+/// Write history entries to `io`, oldest first, each followed by a
+/// NUL byte
+pub fn serialize<H: History, W: embedded_io::Write>(
     history: &H,
-) -> impl Iterator<Item = CircularSlice<'_>> {
-    (0..(history.number_of_entries())).filter_map(|index| history.get_entry(index))
+    io: &mut W,
+) -> Result<(), W::Error> {
+    for slice in serialized_slices(history) {
+        io.write_all(slice)?;
+    }
+
+    Ok(())
+}
+
+/// Return iterator over the byte slices written by [`serialize`], for
+/// writers other than [`embedded_io::Write`]
+pub(crate) fn serialized_slices<H: History>(history: &H) -> impl Iterator<Item = &[u8]> {
+    history.iter_entries().flat_map(|entry| {
+        let (slice1, slice2) = entry.get_slices();
+
+        [slice1, slice2, &[0x0]]
+    })
+}
+
+/// Add entries written by [`serialize`] to `history`, reading `io`
+/// until end of input. Each entry is decoded in `buffer`, and entries
+/// that don't fit are truncated at a character boundary. Invalid
+/// UTF-8 is skipped. Returns the number of entries added.
+pub fn deserialize<H: History, R: embedded_io::Read>(
+    io: &mut R,
+    history: &mut H,
+    buffer: &mut [u8],
+) -> Result<usize, R::Error> {
+    deserialize_with(io, history, &mut LineBuffer::from_slice(buffer))
+}
+
+pub(crate) fn deserialize_with<H: History, R: embedded_io::Read, B: Buffer>(
+    io: &mut R,
+    history: &mut H,
+    buffer: &mut LineBuffer<B>,
+) -> Result<usize, R::Error> {
+    let mut loader = HistoryLoader::new(buffer);
+    let mut chunk = [0; 32];
+
+    loop {
+        match io.read(&mut chunk)? {
+            0 => break,
+            len => chunk[..len]
+                .iter()
+                .for_each(|&byte| loader.advance(byte, history)),
+        }
+    }
+
+    Ok(loader.finish(history))
+}
+
+/// Decoder of serialized history, adding entries as they are read
+pub(crate) struct HistoryLoader<'a, B: Buffer> {
+    buffer: &'a mut LineBuffer<B>,
+    decoder: Utf8Decoder,
+    added: usize,
+}
+
+impl<'a, B: Buffer> HistoryLoader<'a, B> {
+    pub(crate) fn new(buffer: &'a mut LineBuffer<B>) -> Self {
+        buffer.truncate();
+
+        Self {
+            buffer,
+            decoder: Utf8Decoder::new(),
+            added: 0,
+        }
+    }
+
+    fn add_entry<H: History>(&mut self, history: &mut H) {
+        if !self.buffer.is_empty() && history.add_entry(self.buffer.as_str()).is_ok() {
+            self.added += 1;
+        }
+
+        self.buffer.truncate();
+        self.decoder = Utf8Decoder::new();
+    }
+
+    pub(crate) fn advance<H: History>(&mut self, byte: u8, history: &mut H) {
+        if byte == 0x0 {
+            self.add_entry(history);
+            return;
+        }
+
+        match self.decoder.advance(byte) {
+            Utf8DecoderStatus::Done(c) => {
+                // Chars are valid UTF-8, and are dropped if the buffer
+                // is full
+                unsafe {
+                    self.buffer.append_slices(c.as_bytes(), &[]);
+                }
+
+                self.decoder = Utf8Decoder::new();
+            }
+            Utf8DecoderStatus::Error => self.decoder = Utf8Decoder::new(),
+            #[cfg(not(feature = "ascii-only"))]
+            Utf8DecoderStatus::Continuation => (),
+        }
+    }
+
+    /// Add entry not ended by a NUL byte, and return the number of
+    /// entries added
+    pub(crate) fn finish<H: History>(mut self, history: &mut H) -> usize {
+        self.add_entry(history);
+        self.added
+    }
+}
+
+/// Return index of the most recent entry before `before` containing
+/// `query`
+pub(crate) fn find_entry<H: History>(history: &H, query: &str, before: usize) -> Option<usize> {
+    (0..before.min(history.number_of_entries()))
+        .rev()
+        .find(|&index| {
+            history
+                .get_entry(index)
+                .is_some_and(|entry| entry.is_utf8() && entry.contains(query.as_bytes()))
+        })
 }
 
 /// Static history backed by array
 pub struct SliceHistory<'a> {
     buffer: &'a mut [u8],
     window: Window,
+    max_entries: Option<usize>,
 }
 
 impl<'a> SliceHistory<'a> {
@@ -207,12 +606,30 @@ impl<'a> SliceHistory<'a> {
         Self {
             window: Window::new(buffer.len()),
             buffer,
+            max_entries: None,
+        }
+    }
+
+    /// Create new static history keeping at most `max_entries`
+    /// entries. Older entries are also removed when the buffer is full.
+    pub fn with_max_entries(buffer: &'a mut [u8], max_entries: usize) -> Self {
+        Self {
+            max_entries: Some(max_entries),
+            ..Self::new(buffer)
         }
     }
 
-    fn get_available_range(&self) -> CircularRange {
-        let len = self.buffer.len();
-        CircularRange::new(self.window.end(), self.window.end(), len, len)
+    // Remove the oldest entry with its delimiter, so the window always
+    // starts at an entry
+    fn remove_oldest_entry(&mut self) {
+        while self.window.len() > 0 {
+            let byte = self.buffer[self.window.start()];
+            self.window.narrow();
+
+            if byte == 0x0 {
+                break;
+            }
+        }
     }
 
     fn get_buffer(&self) -> CircularSlice<'_> {
@@ -230,9 +647,11 @@ impl<'a> SliceHistory<'a> {
                 .into_iter()
                 .filter_map(|(index, b)| if *b == 0x0 { Some(index) } else { None });
 
+        // An entry ending at the end of the buffer is followed by one
+        // starting at the beginning
         [self.window.start()]
             .into_iter()
-            .chain(delimeters.clone().map(|i| i + 1))
+            .chain(delimeters.clone().map(|i| (i + 1) % self.buffer.len()))
             .zip(delimeters.chain([self.window.end()]))
             .filter_map(|(start, end)| {
                 if start != end {
@@ -256,29 +675,28 @@ impl<'a> SliceHistory<'a> {
 
 impl<'a> History for SliceHistory<'a> {
     fn add_entry<'b>(&mut self, entry: &'b str) -> Result<(), &'b str> {
-        if entry.len() + 1 > self.buffer.len() {
+        let len = entry.len() + 1;
+
+        if len > self.buffer.len() {
             return Err(entry);
         }
 
-        for (_, b) in self
-            .get_available_range()
-            .into_iter()
-            .zip(entry.as_bytes().iter())
-        {
-            self.buffer[self.window.end()] = *b;
-            self.window.widen();
+        // Make room for the entry and its delimiter before writing, so
+        // old entries are removed whole and every entry ends with a
+        // delimiter
+        while self.buffer.len() - self.window.len() < len {
+            self.remove_oldest_entry();
         }
 
-        if self.buffer[self.window.end()] != 0x0 {
-            self.buffer[self.window.end()] = 0x0;
-
+        for &b in entry.as_bytes().iter().chain(&[0x0]) {
+            self.buffer[self.window.end()] = b;
             self.window.widen();
+        }
 
-            while self.buffer[self.window.start()] != 0x0 {
-                self.window.narrow();
+        if let Some(max_entries) = self.max_entries {
+            while self.number_of_entries() > max_entries {
+                self.remove_oldest_entry();
             }
-        } else {
-            self.window.widen();
         }
 
         Ok(())
@@ -288,11 +706,89 @@ impl<'a> History for SliceHistory<'a> {
         self.get_entries().count()
     }
 
+    fn clear(&mut self) {
+        self.buffer.fill(0x0);
+        self.window = Window::new(self.buffer.len());
+    }
+
     fn get_entry(&self, index: usize) -> Option<CircularSlice<'_>> {
         self.get_entries().nth(index)
     }
 }
 
+/// Static history with fixed-size entry slots
+///
+/// Keeps the `SLOTS` most recent entries of at most `WIDTH` bytes
+/// each, so the number of entries kept doesn't depend on their length,
+/// unlike with [`SliceHistory`]. The oldest entry is removed when a new
+/// one is added to a full history, and longer entries are rejected.
+pub struct StaticSlotHistory<const SLOTS: usize, const WIDTH: usize> {
+    slots: [[u8; WIDTH]; SLOTS],
+    lens: [usize; SLOTS],
+    // Slot of the oldest entry
+    start: usize,
+    count: usize,
+}
+
+impl<const SLOTS: usize, const WIDTH: usize> StaticSlotHistory<SLOTS, WIDTH> {
+    /// Create new empty history
+    pub fn new() -> Self {
+        Self {
+            slots: [[0; WIDTH]; SLOTS],
+            lens: [0; SLOTS],
+            start: 0,
+            count: 0,
+        }
+    }
+}
+
+impl<const SLOTS: usize, const WIDTH: usize> Default for StaticSlotHistory<SLOTS, WIDTH> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const SLOTS: usize, const WIDTH: usize> History for StaticSlotHistory<SLOTS, WIDTH> {
+    fn get_entry(&self, index: usize) -> Option<CircularSlice<'_>> {
+        if index >= self.count {
+            return None;
+        }
+
+        let slot = (self.start + index) % SLOTS;
+        let len = self.lens[slot];
+
+        Some(CircularSlice::new(&self.slots[slot], 0, len, len))
+    }
+
+    fn add_entry<'a>(&mut self, entry: &'a str) -> Result<(), &'a str> {
+        if SLOTS == 0 || entry.len() > WIDTH {
+            return Err(entry);
+        }
+
+        let slot = (self.start + self.count) % SLOTS;
+
+        if self.count < SLOTS {
+            self.count += 1;
+        } else {
+            self.start = (self.start + 1) % SLOTS;
+        }
+
+        self.slots[slot][..entry.len()].copy_from_slice(entry.as_bytes());
+        self.lens[slot] = entry.len();
+
+        Ok(())
+    }
+
+    fn number_of_entries(&self) -> usize {
+        self.count
+    }
+
+    fn clear(&mut self) {
+        self.start = 0;
+        self.count = 0;
+    }
+}
+
 /// Emtpy implementation for Editors with no history
 pub struct NoHistory {}
 
@@ -322,10 +818,112 @@ impl History for NoHistory {
     }
 }
 
+impl<H: History + ?Sized> History for &mut H {
+    fn get_entry(&self, index: usize) -> Option<CircularSlice<'_>> {
+        (**self).get_entry(index)
+    }
+
+    fn add_entry<'a>(&mut self, entry: &'a str) -> Result<(), &'a str> {
+        (**self).add_entry(entry)
+    }
+
+    fn number_of_entries(&self) -> usize {
+        (**self).number_of_entries()
+    }
+
+    fn clear(&mut self) {
+        (**self).clear()
+    }
+
+    fn add_entry_deduped<'a>(&mut self, entry: &'a str) -> Result<(), &'a str> {
+        (**self).add_entry_deduped(entry)
+    }
+}
+
+/// History as used by an editor, borrowed for each call reading a
+/// line, or by the async editor for each key. Implemented for every
+/// [`History`], owned or borrowed by the editor, and for
+/// [`SharedHistory`].
+pub trait BorrowHistory {
+    /// History borrowed
+    type History: History;
+
+    /// Borrow of the history, released when dropped
+    type Borrowed<'b>: DerefMut<Target = Self::History>
+    where
+        Self: 'b;
+
+    /// Borrow history while reading a line
+    fn borrow_history(&mut self) -> Self::Borrowed<'_>;
+
+    /// Tell if the history is borrowed elsewhere, e.g. by another
+    /// editor sharing it. The async editor yields until it's released
+    /// before borrowing it. Default is false.
+    fn is_borrowed(&self) -> bool {
+        false
+    }
+}
+
+impl<H: History> BorrowHistory for H {
+    type History = H;
+    type Borrowed<'b>
+        = &'b mut H
+    where
+        Self: 'b;
+
+    fn borrow_history(&mut self) -> Self::Borrowed<'_> {
+        self
+    }
+}
+
+/// History shared between editors
+///
+/// A line added by one editor is reachable from the others when they
+/// read their next line. Sync editors borrow the history while they
+/// read a line, so they must take turns, like they do in the same
+/// thread. Reading a line while another sync editor is in the middle
+/// of one panics like any other [`RefCell`] borrowed twice.
+///
+/// Async editors borrow the history only while they handle a key and
+/// write its output, not while they wait for input, and yield while
+/// another editor has it borrowed. Async editors in concurrent tasks
+/// can therefore share it.
+///
+/// The history can be accessed through the [`RefCell`] while no editor
+/// has it borrowed, e.g. between lines.
+pub struct SharedHistory<'a, H: History> {
+    history: &'a RefCell<H>,
+}
+
+impl<'a, H: History> SharedHistory<'a, H> {
+    pub fn new(history: &'a RefCell<H>) -> Self {
+        Self { history }
+    }
+}
+
+impl<'a, H: History> BorrowHistory for SharedHistory<'a, H> {
+    type History = H;
+    type Borrowed<'b>
+        = RefMut<'a, H>
+    where
+        Self: 'b;
+
+    fn borrow_history(&mut self) -> Self::Borrowed<'_> {
+        self.history.borrow_mut()
+    }
+
+    fn is_borrowed(&self) -> bool {
+        self.history.try_borrow_mut().is_err()
+    }
+}
+
 /// Wrapper used for history navigation in [`core::Line`]
 pub(crate) struct HistoryNavigator<'a, H: History> {
     pub(crate) history: &'a mut H,
     position: Option<usize>,
+    // Length in bytes of the prefix entries are filtered by, 0 for all
+    // entries. Narrower than usize to keep the line editor state small.
+    prefix_len: u32,
 }
 
 impl<'a, H: History> HistoryNavigator<'a, H> {
@@ -333,9 +931,33 @@ impl<'a, H: History> HistoryNavigator<'a, H> {
         Self {
             history,
             position: None,
+            prefix_len: 0,
         }
     }
 
+    // Position and prefix length saved by
+    // [`HistoryNavigator::position`] and
+    // [`HistoryNavigator::prefix_len`] when a line is read
+    // incrementally
+    pub(crate) fn restore_position(&mut self, position: Option<usize>, prefix_len: u32) {
+        self.position = position;
+        self.prefix_len = prefix_len;
+    }
+
+    pub(crate) fn position(&self) -> Option<usize> {
+        self.position
+    }
+
+    pub(crate) fn prefix_len(&self) -> u32 {
+        self.prefix_len
+    }
+
+    // Only show entries starting with the first `len` bytes of the
+    // line, until reset
+    pub(crate) fn set_prefix_len(&mut self, len: usize) {
+        self.prefix_len = len.try_into().unwrap_or(0);
+    }
+
     fn set_position(&mut self, position: usize) -> usize {
         *self.position.insert(position)
     }
@@ -346,32 +968,55 @@ impl<'a, H: History> HistoryNavigator<'a, H> {
             .get_or_insert_with(|| self.history.number_of_entries())
     }
 
-    pub(crate) fn move_up(&mut self) -> Result<CircularSlice<'_>, ()> {
+    // Entry at index, if it is valid UTF-8. Entries are copied into
+    // the line buffer, which must stay valid whatever the `History`
+    // implementation returns.
+    fn valid_entry(history: &H, index: usize) -> Option<CircularSlice<'_>> {
+        history.get_entry(index).filter(CircularSlice::is_utf8)
+    }
+
+    // True if the entry at index is valid and starts with `prefix`.
+    // Entries equal to the prefix are skipped, as they would leave the
+    // line unchanged.
+    fn matches(history: &H, index: usize, prefix: &[u8]) -> bool {
+        Self::valid_entry(history, index).is_some_and(|entry| {
+            prefix.is_empty() || (entry.len() > prefix.len() && entry.starts_with(prefix))
+        })
+    }
+
+    // Indices without an entry, which `History` implementations are
+    // allowed to have, are skipped, as are invalid entries and entries
+    // not starting with `prefix`.
+    pub(crate) fn move_up(&mut self, prefix: &[u8]) -> Result<CircularSlice<'_>, ()> {
         let position = self.get_position();
+        let history = &*self.history;
 
-        if position > 0 {
-            let position = self.set_position(position - 1);
+        let position = (0..position.min(history.number_of_entries()))
+            .rev()
+            .find(|&index| Self::matches(history, index, prefix))
+            .ok_or(())?;
 
-            Ok(self.history.get_entry(position).unwrap())
-        } else {
-            Err(())
-        }
+        let position = self.set_position(position);
+
+        Self::valid_entry(self.history, position).ok_or(())
     }
 
-    pub(crate) fn move_down(&mut self) -> Result<CircularSlice<'_>, ()> {
-        let new_position = self.get_position() + 1;
+    pub(crate) fn move_down(&mut self, prefix: &[u8]) -> Result<CircularSlice<'_>, ()> {
+        let position = self.get_position();
+        let history = &*self.history;
 
-        if new_position < self.history.number_of_entries() {
-            let position = self.set_position(new_position);
+        let position = ((position + 1)..history.number_of_entries())
+            .find(|&index| Self::matches(history, index, prefix))
+            .ok_or(())?;
 
-            Ok(self.history.get_entry(position).unwrap())
-        } else {
-            Err(())
-        }
+        let position = self.set_position(position);
+
+        Self::valid_entry(self.history, position).ok_or(())
     }
 
     pub(crate) fn reset(&mut self) {
         self.position = None;
+        self.prefix_len = 0;
     }
 
     pub(crate) fn is_active(&self) -> bool {
@@ -383,38 +1028,81 @@ impl<'a, H: History> HistoryNavigator<'a, H> {
 mod alloc {
     use super::*;
     use alloc::{
+        collections::VecDeque,
         string::{String, ToString},
-        vec::Vec,
     };
 
     extern crate alloc;
 
-    /// Unbounded history backed by [`Vec<String>`]
-    pub struct UnboundedHistory {
-        buffer: Vec<String>,
+    #[cfg(any(test, doc, feature = "alloc", feature = "std"))]
+    impl<'a> CircularSlice<'a> {
+        /// Return slice as string, borrowed unless it wraps around.
+        /// Invalid UTF-8 is replaced like [`String::from_utf8_lossy`].
+        pub fn as_cow(&self) -> alloc::borrow::Cow<'a, str> {
+            match self.get_slices() {
+                (slice, []) | ([], slice) => String::from_utf8_lossy(slice),
+                (slice1, slice2) => String::from_utf8_lossy(&[slice1, slice2].concat())
+                    .into_owned()
+                    .into(),
+            }
+        }
+    }
+
+    /// History backed by [`VecDeque<String>`], optionally keeping only
+    /// the most recent entries
+    pub struct AllocHistory {
+        buffer: VecDeque<String>,
+        max_entries: Option<usize>,
     }
 
-    impl UnboundedHistory {
+    /// [`AllocHistory`] without a limit on the number of entries
+    pub type UnboundedHistory = AllocHistory;
+
+    impl AllocHistory {
+        /// Create history without a limit on the number of entries
         pub fn new() -> Self {
-            Self { buffer: Vec::new() }
+            Self {
+                buffer: VecDeque::new(),
+                max_entries: None,
+            }
+        }
+
+        /// Create history keeping at most `max_entries` entries. The
+        /// oldest entry is removed when a new one is added to a full
+        /// history. With 0, no entries are kept.
+        pub fn with_max_entries(max_entries: usize) -> Self {
+            Self {
+                buffer: VecDeque::new(),
+                max_entries: Some(max_entries),
+            }
         }
     }
 
-    impl Default for UnboundedHistory {
+    impl Default for AllocHistory {
         fn default() -> Self {
             Self::new()
         }
     }
 
-    impl History for UnboundedHistory {
+    impl History for AllocHistory {
         fn get_entry(&self, index: usize) -> Option<CircularSlice<'_>> {
-            let s = self.buffer[index].as_str();
+            let s = self.buffer.get(index)?.as_str();
 
             Some(CircularSlice::new(s.as_bytes(), 0, s.len(), s.len()))
         }
 
         fn add_entry<'a>(&mut self, entry: &'a str) -> Result<(), &'a str> {
-            self.buffer.push(entry.to_string());
+            if let Some(max_entries) = self.max_entries {
+                if max_entries == 0 {
+                    return Err(entry);
+                }
+
+                while self.buffer.len() >= max_entries {
+                    self.buffer.pop_front();
+                }
+            }
+
+            self.buffer.push_back(entry.to_string());
 
             #[cfg(test)]
             dbg!(entry);
@@ -425,11 +1113,15 @@ mod alloc {
         fn number_of_entries(&self) -> usize {
             self.buffer.len()
         }
+
+        fn clear(&mut self) {
+            self.buffer.clear();
+        }
     }
 }
 
 #[cfg(any(test, doc, feature = "alloc", feature = "std"))]
-pub use alloc::UnboundedHistory;
+pub use alloc::{AllocHistory, UnboundedHistory};
 
 #[cfg(test)]
 mod tests {
@@ -511,11 +1203,121 @@ mod tests {
     }
 
     #[test]
-    fn static_history() {
-        let mut buffer = [0; 10];
+    fn circular_slice_contains() {
+        let slice = CircularSlice::new("defabc".as_bytes(), 3, 3, 6);
+
+        assert!(slice.contains(b""));
+        assert!(slice.contains(b"abc"));
+        assert!(slice.contains(b"cd"));
+        assert!(slice.contains(b"abcdef"));
+        assert!(!slice.contains(b"fa"));
+        assert!(!slice.contains(b"abcdefa"));
+    }
+
+    #[test]
+    fn circular_slice_starts_with() {
+        let slice = CircularSlice::new("defabc".as_bytes(), 3, 3, 6);
+
+        assert!(slice.starts_with(b""));
+        assert!(slice.starts_with(b"ab"));
+        assert!(slice.starts_with(b"abcd"));
+        assert!(slice.starts_with(b"abcdef"));
+        assert!(!slice.starts_with(b"abd"));
+        assert!(!slice.starts_with(b"abcdefa"));
+    }
+
+    #[test]
+    fn circular_slice_last_word() {
+        let last_word = |buffer: &'static [u8], start| {
+            let slice = CircularSlice::new(buffer, start, start, buffer.len());
+            let (slice1, slice2) = slice.last_word();
+
+            (from_utf8(slice1).unwrap(), from_utf8(slice2).unwrap())
+        };
+
+        assert_eq!(last_word(b"ab cd ef", 0), ("ef", ""));
+        assert_eq!(last_word(b"d efab c", 4), ("ef", ""));
+        assert_eq!(last_word(b"d  ab c", 3), ("c", "d"));
+        assert_eq!(last_word(b"dab c", 1), ("c", "d"));
+        assert_eq!(last_word(b"cdab", 2), ("ab", "cd"));
+        assert_eq!(last_word(b" \t", 0), ("", ""));
+        assert_eq!(last_word(b"", 0), ("", ""));
+    }
+
+    #[test]
+    fn circular_slice_eq() {
+        let slice = CircularSlice::new("defabc".as_bytes(), 3, 3, 6);
+
+        assert!(slice.eq_bytes(b"abcdef"));
+        assert!(!slice.eq_bytes(b"abcde"));
+        assert!(!slice.eq_bytes(b"abcdefg"));
+        assert!(!slice.eq_bytes(b"defabc"));
+
+        assert!(CircularSlice::new(b"", 0, 0, 0).eq_bytes(b""));
+    }
+
+    #[test]
+    fn dedup() {
+        let mut buffer = [0; 8];
+        let mut history = SliceHistory::new(&mut buffer);
+
+        history.add_entry_deduped("abcde").unwrap();
+        history.add_entry_deduped("abcde").unwrap();
+
+        assert_eq!(
+            history.get_entries().collect::<Vec<String>>(),
+            vec!["abcde"]
+        );
+
+        // Wraps around the end of the buffer
+        history.add_entry_deduped("fgh").unwrap();
+
+        assert_eq!(history.get_entry(0).unwrap().get_ranges(), (6..8, 0..1));
+
+        history.add_entry_deduped("fgh").unwrap();
+
+        assert_eq!(history.get_entries().collect::<Vec<String>>(), vec!["fgh"]);
+        assert_eq!(history.get_entry(0).unwrap().get_ranges(), (6..8, 0..1));
+
+        history.add_entry_deduped("fg").unwrap();
+        history.add_entry_deduped("fgh").unwrap();
+
+        assert_eq!(
+            history.get_entries().collect::<Vec<String>>(),
+            vec!["fg", "fgh"]
+        );
+
+        let mut history = UnboundedHistory::new();
+
+        history.load_entries(["ls", "ls", "cd", "ls"].into_iter());
+        history.add_entry_deduped("ls").unwrap();
+
+        assert_eq!(
+            history.iter_entries().collect::<Vec<String>>(),
+            vec!["ls", "ls", "cd", "ls"]
+        );
+    }
+
+    #[test]
+    fn find() {
+        let mut buffer = [0; 32];
+        let mut history = SliceHistory::new(&mut buffer);
+
+        history.load_entries(["ls", "cd /tmp", "ls -l", "cat file"].into_iter());
+
+        assert_eq!(find_entry(&history, "ls", 4), Some(2));
+        assert_eq!(find_entry(&history, "ls", 2), Some(0));
+        assert_eq!(find_entry(&history, "ls", 0), None);
+        assert_eq!(find_entry(&history, "tmp", 10), Some(1));
+        assert_eq!(find_entry(&history, "rm", 4), None);
+    }
+
+    #[test]
+    fn static_history() {
+        let mut buffer = [0; 10];
         let mut history: SliceHistory = SliceHistory::new(&mut buffer);
 
-        assert_eq!(history.get_available_range().get_ranges(), (0..10, 0..0));
+        assert_eq!(history.window.len(), 0);
 
         assert_eq!(
             history.get_entries().collect::<Vec<String>>(),
@@ -582,20 +1384,539 @@ mod tests {
         );
     }
 
+    #[test]
+    fn max_entries() {
+        let mut buffer = [0; 10];
+        let mut history = SliceHistory::with_max_entries(&mut buffer, 2);
+
+        history.load_entries(["ab", "cd", "ef"].into_iter());
+
+        assert_eq!(
+            history.get_entries().collect::<Vec<String>>(),
+            vec!["cd", "ef"]
+        );
+
+        // Evicted by size
+        history.add_entry("0123456").unwrap();
+
+        assert_eq!(
+            history.get_entries().collect::<Vec<String>>(),
+            vec!["0123456"]
+        );
+
+        history.add_entry("x").unwrap();
+
+        assert_eq!(
+            history.get_entries().collect::<Vec<String>>(),
+            vec!["0123456", "x"]
+        );
+
+        // Partially overwritten entry is evicted by size, without
+        // evicting by count
+        history.add_entry("y").unwrap();
+
+        assert_eq!(
+            history.get_entries().collect::<Vec<String>>(),
+            vec!["x", "y"]
+        );
+
+        // Evicted by count after wrapping around
+        history.add_entry("z").unwrap();
+
+        assert_eq!(
+            history.get_entries().collect::<Vec<String>>(),
+            vec!["y", "z"]
+        );
+
+        history.add_entry("abcdefg").unwrap();
+
+        assert_eq!(
+            history.get_entries().collect::<Vec<String>>(),
+            vec!["z", "abcdefg"]
+        );
+
+        history.clear();
+
+        assert_eq!(history.number_of_entries(), 0);
+
+        history.load_entries(["a", "b", "c"].into_iter());
+
+        assert_eq!(
+            history.get_entries().collect::<Vec<String>>(),
+            vec!["b", "c"]
+        );
+
+        let mut buffer = [0; 10];
+        let mut history = SliceHistory::with_max_entries(&mut buffer, 0);
+
+        history.add_entry("a").unwrap();
+        assert_eq!(history.number_of_entries(), 0);
+
+        let mut history = UnboundedHistory::new();
+
+        history.load_entries(["a", "b"].into_iter());
+        history.clear();
+        assert_eq!(history.number_of_entries(), 0);
+    }
+
+    #[test]
+    fn slot_history() {
+        let mut history: StaticSlotHistory<3, 4> = StaticSlotHistory::new();
+
+        assert_eq!(
+            history.iter_entries().collect::<Vec<String>>(),
+            Vec::<String>::new()
+        );
+
+        history.add_entry("abc").unwrap();
+
+        assert_eq!(history.iter_entries().collect::<Vec<String>>(), vec!["abc"]);
+
+        history.add_entry("defg").unwrap();
+        history.add_entry("").unwrap();
+
+        assert_eq!(
+            history.iter_entries().collect::<Vec<String>>(),
+            vec!["abc", "defg", ""]
+        );
+
+        // Oldest entry is evicted whatever the length of the new one
+        history.add_entry("h").unwrap();
+
+        assert_eq!(
+            history.iter_entries().collect::<Vec<String>>(),
+            vec!["defg", "", "h"]
+        );
+
+        assert!(history.add_entry("01234").is_err());
+        assert_eq!(history.number_of_entries(), 3);
+
+        assert_eq!(history.load_entries(["i", "j", "k", "l"].into_iter()), 4);
+
+        assert_eq!(
+            history.iter_entries().collect::<Vec<String>>(),
+            vec!["j", "k", "l"]
+        );
+
+        // Deduped against the most recent entry, across wrap around
+        history.add_entry_deduped("l").unwrap();
+        assert_eq!(history.number_of_entries(), 3);
+
+        assert!(history.get_entry(3).is_none());
+
+        history.clear();
+
+        assert_eq!(history.number_of_entries(), 0);
+        assert!(history.get_entry(0).is_none());
+
+        history.add_entry("m").unwrap();
+
+        assert_eq!(history.iter_entries().collect::<Vec<String>>(), vec!["m"]);
+
+        let mut history: StaticSlotHistory<0, 4> = StaticSlotHistory::new();
+
+        assert!(history.add_entry("a").is_err());
+        assert_eq!(history.number_of_entries(), 0);
+    }
+
     #[test]
     fn navigator() {
         let mut history = UnboundedHistory::new();
         let mut navigator = HistoryNavigator::new(&mut history);
 
-        assert!(navigator.move_up().is_err());
-        assert!(navigator.move_down().is_err());
+        assert!(navigator.move_up(b"").is_err());
+        assert!(navigator.move_down(b"").is_err());
 
         navigator.history.add_entry("line 1").unwrap();
         navigator.reset();
 
-        assert!(navigator.move_up().is_ok());
-        assert!(navigator.move_up().is_err());
+        assert!(navigator.move_up(b"").is_ok());
+        assert!(navigator.move_up(b"").is_err());
 
-        assert!(navigator.move_down().is_err());
+        assert!(navigator.move_down(b"").is_err());
+    }
+
+    #[test]
+    fn navigator_with_prefix() {
+        let mut history = UnboundedHistory::new();
+
+        history.load_entries(["set a", "get a", "se", "set b", "reset"].into_iter());
+
+        let mut navigator = HistoryNavigator::new(&mut history);
+
+        // Entries equal to the prefix are skipped
+        assert!(navigator.move_up(b"se").unwrap().eq_bytes(b"set b"));
+        assert!(navigator.move_up(b"se").unwrap().eq_bytes(b"set a"));
+        assert!(navigator.move_up(b"se").is_err());
+        assert!(navigator.move_down(b"se").unwrap().eq_bytes(b"set b"));
+        assert!(navigator.move_down(b"se").is_err());
+    }
+
+    #[test]
+    fn navigator_with_slot_history() {
+        let mut history: StaticSlotHistory<3, 8> = StaticSlotHistory::new();
+
+        // Wraps around, with the oldest entry in the last slot
+        history.load_entries(["ls", "cd /", "ls -l", "pwd", "ls /"].into_iter());
+
+        let mut navigator = HistoryNavigator::new(&mut history);
+
+        assert!(navigator.move_up(b"").unwrap().eq_bytes(b"ls /"));
+        assert!(navigator.move_up(b"").unwrap().eq_bytes(b"pwd"));
+        assert!(navigator.move_up(b"").unwrap().eq_bytes(b"ls -l"));
+        assert!(navigator.move_up(b"").is_err());
+        assert!(navigator.move_down(b"").unwrap().eq_bytes(b"pwd"));
+
+        navigator.reset();
+
+        assert!(navigator.move_up(b"ls").unwrap().eq_bytes(b"ls /"));
+        assert!(navigator.move_up(b"ls").unwrap().eq_bytes(b"ls -l"));
+        assert!(navigator.move_up(b"ls").is_err());
+    }
+
+    // History without entries at some indices
+    struct GappyHistory {
+        history: UnboundedHistory,
+        gaps: &'static [usize],
+    }
+
+    impl History for GappyHistory {
+        fn get_entry(&self, index: usize) -> Option<CircularSlice<'_>> {
+            if self.gaps.contains(&index) {
+                None
+            } else {
+                self.history.get_entry(index)
+            }
+        }
+
+        fn add_entry<'a>(&mut self, entry: &'a str) -> Result<(), &'a str> {
+            self.history.add_entry(entry)
+        }
+
+        fn number_of_entries(&self) -> usize {
+            self.history.number_of_entries()
+        }
+
+        fn clear(&mut self) {
+            self.history.clear()
+        }
+    }
+
+    #[test]
+    fn navigator_with_gaps() {
+        fn entry(result: Result<CircularSlice<'_>, ()>) -> String {
+            let bytes = result.unwrap().into_iter().map(|(_, b)| *b).collect();
+
+            String::from_utf8(bytes).unwrap()
+        }
+
+        let mut history = GappyHistory {
+            history: UnboundedHistory::new(),
+            gaps: &[0, 2, 3, 5],
+        };
+
+        history.load_entries(["0", "1", "2", "3", "4", "5"].into_iter());
+
+        assert_eq!(
+            history.iter_entries().collect::<Vec<String>>(),
+            vec!["1", "4"]
+        );
+
+        let mut navigator = HistoryNavigator::new(&mut history);
+
+        assert_eq!(entry(navigator.move_up(b"")), "4");
+        assert_eq!(entry(navigator.move_up(b"")), "1");
+        assert!(navigator.move_up(b"").is_err());
+        assert_eq!(navigator.position(), Some(1));
+
+        assert_eq!(entry(navigator.move_down(b"")), "4");
+        assert!(navigator.move_down(b"").is_err());
+        assert_eq!(navigator.position(), Some(4));
+
+        // Nothing but gaps
+        navigator.history.gaps = &[0, 1, 2, 3, 4, 5];
+        navigator.reset();
+
+        assert!(navigator.move_up(b"").is_err());
+        assert!(navigator.move_down(b"").is_err());
+    }
+
+    // History with an entry cut in the middle of a char
+    struct InvalidHistory;
+
+    impl History for InvalidHistory {
+        fn get_entry(&self, index: usize) -> Option<CircularSlice<'_>> {
+            let bytes = match index {
+                0 => "ok".as_bytes(),
+                1 => &"aæ".as_bytes()[..2],
+                _ => return None,
+            };
+
+            Some(CircularSlice::new(bytes, 0, bytes.len(), bytes.len()))
+        }
+
+        fn add_entry<'a>(&mut self, entry: &'a str) -> Result<(), &'a str> {
+            Err(entry)
+        }
+
+        fn number_of_entries(&self) -> usize {
+            2
+        }
+    }
+
+    #[test]
+    fn navigator_skips_invalid_entries() {
+        let mut history = InvalidHistory;
+        let mut navigator = HistoryNavigator::new(&mut history);
+
+        assert!(navigator.move_up(b"").unwrap().eq_bytes(b"ok"));
+        assert!(navigator.move_down(b"").is_err());
+        assert_eq!(find_entry(&history, "a", 2), None);
+    }
+
+    // Xorshift, for reproducible pseudo-random tests
+    fn random(state: &mut u32) -> usize {
+        *state ^= *state << 13;
+        *state ^= *state >> 17;
+        *state ^= *state << 5;
+        *state as usize
+    }
+
+    #[test]
+    fn random_entries() {
+        const CHARS: [&str; 6] = ["a", "b", " ", "æ", "€", "😂"];
+
+        let mut state = 0x2545_f491;
+
+        for size in 5..=64 {
+            let mut buffer = vec![0xff; size];
+            let mut history = SliceHistory::new(&mut buffer);
+            let mut added = Vec::new();
+
+            for _ in 0..100 {
+                // Entries of 1 to size + 1 bytes, some too long to add
+                let len = 1 + random(&mut state) % (size + 1);
+                let mut entry = String::new();
+
+                while let Some(c) = Some(CHARS[random(&mut state) % CHARS.len()])
+                    .filter(|c| entry.len() + c.len() <= len)
+                {
+                    entry.push_str(c);
+                }
+
+                if entry.is_empty() {
+                    entry.push('a');
+                }
+
+                match history.add_entry(&entry) {
+                    Ok(()) => added.push(entry),
+                    Err(_) => assert!(entry.len() >= size),
+                }
+
+                // The most recent entries are kept as they were added,
+                // as many as fit with their delimiters
+                let entries = history.get_entries().collect::<Vec<String>>();
+                let kept = &added[added.len() - entries.len()..];
+                let used: usize = kept.iter().map(|entry| entry.len() + 1).sum();
+
+                assert_eq!(entries, kept);
+                assert!(used <= size);
+
+                if let Some(dropped) = added.len().checked_sub(entries.len() + 1) {
+                    assert!(used + added[dropped].len() + 1 > size);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn as_str() {
+        let mut buffer = [0; 10];
+        let mut history = SliceHistory::new(&mut buffer);
+
+        history.load_entries(["ab", "cdefg", "hij"].into_iter());
+
+        let entry = history.get_entry(1).unwrap();
+        assert_eq!(entry.get_ranges(), (9..10, 0..2));
+        assert_eq!(entry.as_str(&mut [0; 2]), None);
+        assert_eq!(entry.as_str(&mut [0; 3]), Some("hij"));
+
+        let entry = history.get_entry(0).unwrap();
+        assert_eq!(entry.as_str(&mut []), Some("cdefg"));
+    }
+
+    // Serialize to a byte vector
+    fn serialized<H: History>(history: &H) -> Vec<u8> {
+        let mut buffer = [0; 100];
+        let mut io = &mut buffer[..];
+
+        serialize(history, &mut io).unwrap();
+
+        let len = 100 - io.len();
+        buffer[..len].to_vec()
+    }
+
+    #[test]
+    fn serialize_round_trip() {
+        let mut buffer = [0; 16];
+        let mut history = SliceHistory::new(&mut buffer);
+
+        history.load_entries(["abcdefg", "hij", "klmnop", "qr"].into_iter());
+
+        // Entry wraps around
+        assert_eq!(history.get_entry(1).unwrap().get_ranges(), (12..16, 0..2));
+
+        let bytes = serialized(&history);
+        assert_eq!(bytes, b"hij\0klmnop\0qr\0");
+
+        let mut unbounded = UnboundedHistory::new();
+        unbounded.add_entry("old").unwrap();
+
+        assert_eq!(
+            deserialize(&mut &bytes[..], &mut unbounded, &mut [0; 8]),
+            Ok(3)
+        );
+        assert_eq!(
+            unbounded.iter_entries().collect::<Vec<String>>(),
+            vec!["old", "hij", "klmnop", "qr"]
+        );
+
+        let bytes = serialized(&unbounded);
+        let mut buffer = [0; 16];
+        let mut history = SliceHistory::new(&mut buffer);
+
+        assert_eq!(
+            deserialize(&mut &bytes[..], &mut history, &mut [0; 8]),
+            Ok(4)
+        );
+        // Oldest entry is removed to make room
+        assert_eq!(serialized(&history), b"hij\0klmnop\0qr\0");
+    }
+
+    #[test]
+    fn deserialize_damaged() {
+        let mut history = UnboundedHistory::new();
+
+        // Long entries are truncated, invalid UTF-8 and empty entries
+        // are skipped, and the last entry needs no delimiter
+        let bytes = b"ab\0\xffcd\0\0long entry\0ef";
+
+        assert_eq!(
+            deserialize(&mut &bytes[..], &mut history, &mut [0; 4]),
+            Ok(4)
+        );
+        assert_eq!(
+            history.iter_entries().collect::<Vec<String>>(),
+            vec!["ab", "cd", "long", "ef"]
+        );
+    }
+
+    // Check accessors of slice holding `entry`
+    fn check_accessors(slice: &CircularSlice<'_>, entry: &str, wraps: bool) {
+        assert_eq!(slice.len(), entry.len());
+        assert_eq!(slice.is_empty(), entry.is_empty());
+        assert_eq!(slice.is_contiguous(), !wraps);
+        assert!(*slice == entry);
+        assert!(*slice == *entry);
+        assert!(*slice != "other");
+        assert!(slice.iter_bytes().eq(entry.bytes()));
+
+        let mut buffer = [0; 16];
+        assert_eq!(slice.copy_to(&mut buffer), Ok(entry.len()));
+        assert_eq!(&buffer[..entry.len()], entry.as_bytes());
+
+        if !entry.is_empty() {
+            let mut short = [0; 16];
+            assert_eq!(slice.copy_to(&mut short[..entry.len() - 1]), Err(()));
+            assert_eq!(short, [0; 16]);
+        }
+
+        assert_eq!(slice.as_str(&mut [0; 16]), Some(entry));
+        assert_eq!(std::format!("{}", slice), entry);
+        assert_eq!(slice.to_string(), entry);
+        assert_eq!(slice.as_cow(), entry);
+        assert_eq!(
+            matches!(slice.as_cow(), std::borrow::Cow::Borrowed(_)),
+            !wraps
+        );
+    }
+
+    #[test]
+    fn circular_slice_accessors() {
+        const CAPACITY: usize = 12;
+
+        for entry in ["a", "abc", "æøå", "a€b", "🦀x", "abcdefghij"] {
+            // A filler moves the start of the entry, so it ends before,
+            // at and after the wrap point, splitting chars at every
+            // byte. The filler is removed when the entry wraps.
+            for start in 0..=CAPACITY {
+                let mut buffer = [0; CAPACITY];
+                let mut history = SliceHistory::new(&mut buffer);
+
+                if start > 0 {
+                    history.add_entry(&"x".repeat(start - 1)).unwrap();
+                }
+
+                history.add_entry(entry).unwrap();
+
+                // No empty entry after one ending at the end of the
+                // buffer
+                let filler_kept = start > 1 && start + entry.len() < CAPACITY;
+                assert_eq!(history.number_of_entries(), 1 + filler_kept as usize);
+
+                let slice = history.get_entry(history.number_of_entries() - 1).unwrap();
+                let wraps = start % CAPACITY + entry.len() > CAPACITY;
+
+                check_accessors(&slice, entry, wraps);
+            }
+        }
+    }
+
+    #[test]
+    fn empty_circular_slice() {
+        let buffer = [b'x'; 8];
+
+        for start in 0..8 {
+            check_accessors(&CircularSlice::new(&buffer, start, start, 0), "", false);
+        }
+
+        let mut history = UnboundedHistory::new();
+        history.add_entry("").unwrap();
+        check_accessors(&history.get_entry(0).unwrap(), "", false);
+    }
+
+    #[test]
+    fn circular_slice_invalid_utf8() {
+        let buffer = *b"\xa6bc\xff\xc3";
+        let slice = CircularSlice::new(&buffer, 3, 1, 3);
+
+        assert_eq!(slice.to_string(), "\u{fffd}æ");
+        assert_eq!(slice.as_cow(), "\u{fffd}æ");
+        assert_eq!(slice.as_str(&mut [0; 8]), None);
+
+        // Char split by the wrap around
+        let buffer = *b"\xa6bc\xff\xc3";
+        let slice = CircularSlice::new(&buffer, 4, 1, 2);
+        assert_eq!(slice.to_string(), "æ");
+
+        // Replaced like `as_cow`, with the invalid and truncated
+        // sequences split by the wrap around
+        for bytes in [
+            &b"a\xe2\x82b\xf0\x9f\xa6"[..],
+            b"\xff\xfe\xc3\xa6\xed\xa0\x80",
+            b"\xf0\x9f\xa6\x80\x80\xc3",
+        ] {
+            let mut buffer = [0; 16];
+
+            for start in 0..buffer.len() {
+                let end = (start + bytes.len()) % buffer.len();
+                for (i, &b) in bytes.iter().enumerate() {
+                    buffer[(start + i) % buffer.len()] = b;
+                }
+
+                let slice = CircularSlice::new(&buffer, start, end, bytes.len());
+                assert_eq!(slice.to_string(), String::from_utf8_lossy(bytes));
+            }
+        }
     }
 }
@@ -5,6 +5,7 @@ use crate::utf8::{Utf8Char, Utf8Decoder, Utf8DecoderStatus};
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Eq, PartialEq, Copy, Clone, IntoPrimitive, TryFromPrimitive)]
 #[repr(u8)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ControlCharacter {
     NUL = 0x0,
     CtrlA = 0x1,
@@ -52,20 +53,43 @@ impl ControlCharacter {
 
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum CSI {
     CUU(usize),
     CUD(usize),
+    // Cursor up and down with modifier, e.g. 3 for Alt
+    CUUMod(usize),
+    CUDMod(usize),
     CUF(usize),
     CUB(usize),
+    // Cursor forward and back with modifier, e.g. 5 for Ctrl
+    CUFMod(usize),
+    CUBMod(usize),
     CPR(usize, usize),
     CUP(usize, usize),
     ED(usize),
+    EL(usize),
     DSR,
     SU(usize),
     SD(usize),
+    SGR(usize),
+    // Set and reset mode. Private mode marker is ignored.
+    SM(usize),
+    RM(usize),
     Home,
+    Insert,
     Delete,
     End,
+    // Home, Insert, Delete and End with modifier
+    HomeMod(usize),
+    InsertMod(usize),
+    DeleteMod(usize),
+    EndMod(usize),
+    // Start and end of bracketed paste
+    PasteStart,
+    PasteEnd,
+    // Cursor backward tabulation, sent for Shift+Tab
+    CBT,
     Unknown(u8),
 }
 
@@ -74,35 +98,62 @@ impl CSI {
         let c = byte as char;
 
         Some(match c {
-            'A' => Self::CUU(arg1.unwrap_or(1)),
-            'B' => Self::CUD(arg1.unwrap_or(1)),
-            'C' => Self::CUF(arg1.unwrap_or(1)),
-            'D' => Self::CUB(arg1.unwrap_or(1)),
+            'A' => match arg2 {
+                Some(modifier) => Self::CUUMod(modifier),
+                None => Self::CUU(arg1.unwrap_or(1)),
+            },
+            'B' => match arg2 {
+                Some(modifier) => Self::CUDMod(modifier),
+                None => Self::CUD(arg1.unwrap_or(1)),
+            },
+            'C' => match arg2 {
+                Some(modifier) => Self::CUFMod(modifier),
+                None => Self::CUF(arg1.unwrap_or(1)),
+            },
+            'D' => match arg2 {
+                Some(modifier) => Self::CUBMod(modifier),
+                None => Self::CUB(arg1.unwrap_or(1)),
+            },
+            // Sent for End by xterm. Home is sent as `CUP(1, 1)`, with the
+            // modifier in place of the column.
+            'F' => match arg2 {
+                Some(modifier) => Self::EndMod(modifier),
+                None => Self::End,
+            },
             'H' => Self::CUP(arg1.unwrap_or(1), arg2.unwrap_or(1)),
             'J' => Self::ED(arg1.unwrap_or(0)),
-            'R' => Self::CPR(arg1.unwrap(), arg2.unwrap()),
+            'K' => Self::EL(arg1.unwrap_or(0)),
+            'R' => match (arg1, arg2) {
+                (Some(row), Some(column)) => Self::CPR(row, column),
+                _ => Self::Unknown(byte),
+            },
             'S' => Self::SU(arg1.unwrap_or(1)),
             'T' => Self::SD(arg1.unwrap_or(1)),
+            'Z' => Self::CBT,
+            'h' => Self::SM(arg1.unwrap_or(0)),
+            'l' => Self::RM(arg1.unwrap_or(0)),
+            'm' => Self::SGR(arg1.unwrap_or(0)),
             'n' => Self::DSR,
-            '~' => {
-                if let Some(arg) = arg1 {
-                    match arg {
-                        1 => Self::Home,
-                        3 => Self::Delete,
-                        4 => Self::End,
-                        _ => Self::Unknown(byte),
-                    }
-                } else {
-                    Self::Unknown(byte)
-                }
-            }
+            '~' => match (arg1, arg2) {
+                (Some(1), None) => Self::Home,
+                (Some(2), None) => Self::Insert,
+                (Some(3), None) => Self::Delete,
+                (Some(4), None) => Self::End,
+                (Some(1), Some(modifier)) => Self::HomeMod(modifier),
+                (Some(2), Some(modifier)) => Self::InsertMod(modifier),
+                (Some(3), Some(modifier)) => Self::DeleteMod(modifier),
+                (Some(4), Some(modifier)) => Self::EndMod(modifier),
+                (Some(200), _) => Self::PasteStart,
+                (Some(201), _) => Self::PasteEnd,
+                _ => Self::Unknown(byte),
+            },
             _ => Self::Unknown(byte),
         })
     }
 }
 
-#[cfg_attr(test, derive(Debug))]
-#[derive(Eq, PartialEq, Copy, Clone)]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Action {
     Ignore,
     Print(Utf8Char),
@@ -117,29 +168,55 @@ impl Action {
         Action::EscapeSequence(byte)
     }
 
-    fn control_character(byte: u8) -> Self {
+    pub(crate) fn control_character(byte: u8) -> Self {
         Action::ControlCharacter(ControlCharacter::new(byte).unwrap())
     }
 
-    fn csi(byte: u8, arg1: Option<usize>, arg2: Option<usize>) -> Self {
-        Action::ControlSequenceIntroducer(CSI::new(byte, arg1, arg2).unwrap())
+    fn csi(byte: u8, arg1: Option<u16>, arg2: Option<u16>) -> Self {
+        Action::ControlSequenceIntroducer(
+            CSI::new(byte, arg1.map(usize::from), arg2.map(usize::from)).unwrap(),
+        )
     }
 }
 
 #[derive(Debug, Eq, PartialEq)]
 enum State {
     Ground,
+    #[cfg(not(feature = "ascii-only"))]
     Utf8Sequence(Option<Utf8Decoder>),
     EscapeSequence,
+    // `ESC O`, sent instead of CSI for cursor keys in application mode
+    SS3,
     CSIStart,
-    CSIArg1(Option<usize>),
-    CSIArg2(Option<usize>, Option<usize>),
+    CSIArg1(Option<u16>),
+    CSIArg2(Option<u16>, Option<u16>),
+    // Mouse report in SGR format, `CSI < button ; column ; row M`, or
+    // `m` for release
+    SgrMouse,
+    // Bytes left of a mouse report in X10 format, `CSI M` followed by
+    // button, column and row as single bytes
+    X10Mouse(u8),
+}
+
+// Accumulate decimal CSI argument. Saturates instead of overflowing
+// on bogus input.
+fn push_digit(value: Option<u16>, byte: u8) -> u16 {
+    value
+        .unwrap_or(0)
+        .saturating_mul(10)
+        .saturating_add((byte - 0x30) as u16)
 }
 
 pub struct Parser {
     state: State,
 }
 
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Parser {
     pub fn new() -> Self {
         Self {
@@ -147,6 +224,11 @@ impl Parser {
         }
     }
 
+    // True when not in the middle of a sequence
+    pub(crate) fn is_ground(&self) -> bool {
+        self.state == State::Ground
+    }
+
     pub fn advance(&mut self, byte: u8) -> Action {
         match self.state {
             State::Ground => match byte {
@@ -159,6 +241,7 @@ impl Parser {
                     let mut decoder = Utf8Decoder::new();
 
                     match decoder.advance(byte) {
+                        #[cfg(not(feature = "ascii-only"))]
                         Utf8DecoderStatus::Continuation => {
                             self.state = State::Utf8Sequence(Some(decoder));
                             Action::Ignore
@@ -168,6 +251,7 @@ impl Parser {
                     }
                 }
             },
+            #[cfg(not(feature = "ascii-only"))]
             State::Utf8Sequence(ref mut decoder) => {
                 let mut decoder = decoder.take().unwrap();
 
@@ -186,19 +270,55 @@ impl Parser {
                     }
                 }
             }
-            State::EscapeSequence => {
-                if byte == 0x5b {
+            State::EscapeSequence => match byte {
+                0x5b => {
                     self.state = State::CSIStart;
                     Action::Ignore
-                } else {
+                }
+                0x4f => {
+                    self.state = State::SS3;
+                    Action::Ignore
+                }
+                _ => {
                     self.state = State::Ground;
                     Action::escape_sequence(byte)
                 }
-            }
+            },
+            // Final byte has the same meaning as for CSI without
+            // arguments. Others, like F1-F4 and the keypad, are unknown.
+            // Anything else ends the sequence and is handled as usual.
+            State::SS3 => match byte {
+                b'A' | b'B' | b'C' | b'D' | b'F' | b'H' => {
+                    self.state = State::Ground;
+                    Action::csi(byte, None, None)
+                }
+                0x40..=0x7e => {
+                    self.state = State::Ground;
+                    Action::ControlSequenceIntroducer(CSI::Unknown(byte))
+                }
+                _ => {
+                    self.state = State::Ground;
+                    self.advance(byte)
+                }
+            },
+            // Mouse reports and focus events are sent when enabled by
+            // the application, or by a terminal multiplexer, and are
+            // ignored whole
             State::CSIStart => match byte {
+                b'<' => {
+                    self.state = State::SgrMouse;
+                    Action::Ignore
+                }
+                b'M' => {
+                    self.state = State::X10Mouse(3);
+                    Action::Ignore
+                }
+                b'I' | b'O' => {
+                    self.state = State::Ground;
+                    Action::Ignore
+                }
                 0x30..=0x39 => {
-                    let value: usize = (byte - 0x30) as usize;
-                    self.state = State::CSIArg1(Some(value));
+                    self.state = State::CSIArg1(Some(push_digit(None, byte)));
                     Action::Ignore
                 }
                 0x3b => {
@@ -213,8 +333,7 @@ impl Parser {
             },
             State::CSIArg1(value) => match byte {
                 0x30..=0x39 => {
-                    let value: usize = value.unwrap_or(0) * 10 + (byte - 0x30) as usize;
-                    self.state = State::CSIArg1(Some(value));
+                    self.state = State::CSIArg1(Some(push_digit(value, byte)));
                     Action::Ignore
                 }
                 0x3b => {
@@ -229,8 +348,7 @@ impl Parser {
             },
             State::CSIArg2(arg1, arg2) => match byte {
                 0x30..=0x39 => {
-                    let arg2: usize = arg2.unwrap_or(0) * 10 + (byte - 0x30) as usize;
-                    self.state = State::CSIArg2(arg1, Some(arg2));
+                    self.state = State::CSIArg2(arg1, Some(push_digit(arg2, byte)));
                     Action::Ignore
                 }
                 0x40..=0x7e => {
@@ -239,6 +357,21 @@ impl Parser {
                 }
                 _ => Action::Ignore,
             },
+            State::SgrMouse => {
+                if let 0x40..=0x7e = byte {
+                    self.state = State::Ground;
+                }
+
+                Action::Ignore
+            }
+            State::X10Mouse(remaining) => {
+                self.state = match remaining {
+                    1 => State::Ground,
+                    remaining => State::X10Mouse(remaining - 1),
+                };
+
+                Action::Ignore
+            }
         }
     }
 }
@@ -268,11 +401,14 @@ pub(crate) mod tests {
         assert_eq!(parser.advance(0x7), Action::ControlCharacter(CtrlG));
         assert_eq!(parser.advance(0x3), Action::ControlCharacter(CtrlC));
 
-        let actions = input_sequence(&mut parser, "æ");
-        assert_eq!(
-            actions,
-            [Action::Ignore, Action::Print(Utf8Char::from_str("æ"))]
-        );
+        #[cfg(not(feature = "ascii-only"))]
+        {
+            let actions = input_sequence(&mut parser, "æ");
+            assert_eq!(
+                actions,
+                [Action::Ignore, Action::Print(Utf8Char::from_str("æ"))]
+            );
+        }
 
         let mut actions = input_sequence(&mut parser, "\x1b[312;836R");
         assert_eq!(
@@ -297,6 +433,76 @@ pub(crate) mod tests {
             Action::ControlSequenceIntroducer(CSI::CUD(10))
         );
 
+        let mut actions = input_sequence(&mut parser, "\x1b[1;3A");
+
+        assert_eq!(
+            actions.pop().unwrap(),
+            Action::ControlSequenceIntroducer(CSI::CUUMod(3))
+        );
+
+        let mut actions = input_sequence(&mut parser, "\x1b[1;3B");
+
+        assert_eq!(
+            actions.pop().unwrap(),
+            Action::ControlSequenceIntroducer(CSI::CUDMod(3))
+        );
+
+        let mut actions = input_sequence(&mut parser, "\x1b[1;5C");
+
+        assert_eq!(
+            actions.pop().unwrap(),
+            Action::ControlSequenceIntroducer(CSI::CUFMod(5))
+        );
+
+        let mut actions = input_sequence(&mut parser, "\x1b[1;5D");
+
+        assert_eq!(
+            actions.pop().unwrap(),
+            Action::ControlSequenceIntroducer(CSI::CUBMod(5))
+        );
+
+        let mut actions = input_sequence(&mut parser, "\x1b[1;3C");
+
+        assert_eq!(
+            actions.pop().unwrap(),
+            Action::ControlSequenceIntroducer(CSI::CUFMod(3))
+        );
+
+        let mut actions = input_sequence(&mut parser, "\x1b[1;3D");
+
+        assert_eq!(
+            actions.pop().unwrap(),
+            Action::ControlSequenceIntroducer(CSI::CUBMod(3))
+        );
+
+        let mut actions = input_sequence(&mut parser, "\x1b[3C");
+
+        assert_eq!(
+            actions.pop().unwrap(),
+            Action::ControlSequenceIntroducer(CSI::CUF(3))
+        );
+
+        let mut actions = input_sequence(&mut parser, "\x1b[F");
+
+        assert_eq!(
+            actions.pop().unwrap(),
+            Action::ControlSequenceIntroducer(CSI::End)
+        );
+
+        let mut actions = input_sequence(&mut parser, "\x1b[1~");
+
+        assert_eq!(
+            actions.pop().unwrap(),
+            Action::ControlSequenceIntroducer(CSI::Home)
+        );
+
+        let mut actions = input_sequence(&mut parser, "\x1b[4~");
+
+        assert_eq!(
+            actions.pop().unwrap(),
+            Action::ControlSequenceIntroducer(CSI::End)
+        );
+
         let mut actions = input_sequence(&mut parser, "\x1b[H");
 
         assert_eq!(
@@ -339,4 +545,159 @@ pub(crate) mod tests {
             Action::ControlSequenceIntroducer(CSI::CUP(1, 10))
         );
     }
+
+    #[test]
+    fn bracketed_paste() {
+        let mut parser = Parser::new();
+
+        let mut actions = input_sequence(&mut parser, "\x1b[?2004h");
+        assert_eq!(
+            actions.pop().unwrap(),
+            Action::ControlSequenceIntroducer(CSI::SM(2004))
+        );
+
+        let mut actions = input_sequence(&mut parser, "\x1b[?2004l");
+        assert_eq!(
+            actions.pop().unwrap(),
+            Action::ControlSequenceIntroducer(CSI::RM(2004))
+        );
+
+        let actions = input_sequence(&mut parser, "\x1b[200~a\n\x1b[201~");
+        assert_eq!(
+            actions,
+            [
+                Action::Ignore,
+                Action::Ignore,
+                Action::Ignore,
+                Action::Ignore,
+                Action::Ignore,
+                Action::ControlSequenceIntroducer(CSI::PasteStart),
+                Action::Print(Utf8Char::from_str("a")),
+                Action::ControlCharacter(LineFeed),
+                Action::Ignore,
+                Action::Ignore,
+                Action::Ignore,
+                Action::Ignore,
+                Action::Ignore,
+                Action::ControlSequenceIntroducer(CSI::PasteEnd),
+            ]
+        );
+    }
+
+    #[test]
+    fn cpr_missing_argument() {
+        let mut parser = Parser::new();
+
+        let mut actions = input_sequence(&mut parser, "\x1b[5R");
+
+        assert_eq!(
+            actions.pop().unwrap(),
+            Action::ControlSequenceIntroducer(CSI::Unknown(b'R'))
+        );
+    }
+
+    #[test]
+    fn mouse_and_focus_ignored() {
+        let mut parser = Parser::new();
+
+        for seq in [
+            "\x1b[<35;10;4M",
+            "\x1b[<0;1;1m",
+            "\x1b[M !\"",
+            "\x1b[I",
+            "\x1b[O",
+        ] {
+            let actions = input_sequence(&mut parser, seq);
+
+            assert!(
+                actions.iter().all(|action| *action == Action::Ignore),
+                "{seq:?}: {actions:?}"
+            );
+            assert_eq!(parser.state, State::Ground);
+        }
+
+        // Coordinates past 94 are sent as bytes above 0x7f
+        for &byte in b"\x1b[M#\xff\x80" {
+            assert_eq!(parser.advance(byte), Action::Ignore);
+        }
+
+        assert_eq!(parser.state, State::Ground);
+
+        let actions = input_sequence(&mut parser, "a\x1b[<64;5;5Mb\x1b[Mabcc\x1b[Id");
+
+        assert_eq!(
+            actions
+                .into_iter()
+                .filter(|action| *action != Action::Ignore)
+                .collect::<Vec<_>>(),
+            ["a", "b", "c", "d"].map(|s| Action::Print(Utf8Char::from_str(s)))
+        );
+    }
+
+    #[test]
+    fn cbt() {
+        let mut parser = Parser::new();
+
+        let mut actions = input_sequence(&mut parser, "\x1b[Z");
+
+        assert_eq!(
+            actions.pop().unwrap(),
+            Action::ControlSequenceIntroducer(CSI::CBT)
+        );
+    }
+
+    #[test]
+    fn ss3() {
+        let mut parser = Parser::new();
+
+        for (seq, csi) in [
+            ("\x1bOA", CSI::CUU(1)),
+            ("\x1bOB", CSI::CUD(1)),
+            ("\x1bOC", CSI::CUF(1)),
+            ("\x1bOD", CSI::CUB(1)),
+            ("\x1bOH", CSI::CUP(1, 1)),
+            ("\x1bOF", CSI::End),
+            // F1
+            ("\x1bOP", CSI::Unknown(b'P')),
+        ] {
+            assert_eq!(
+                input_sequence(&mut parser, seq),
+                [
+                    Action::Ignore,
+                    Action::Ignore,
+                    Action::ControlSequenceIntroducer(csi)
+                ]
+            );
+            assert_eq!(parser.state, State::Ground);
+        }
+
+        // Sequence cut short by a control character
+        assert_eq!(
+            input_sequence(&mut parser, "\x1bO\x03"),
+            [
+                Action::Ignore,
+                Action::Ignore,
+                Action::ControlCharacter(CtrlC)
+            ]
+        );
+        assert_eq!(parser.state, State::Ground);
+
+        // Other escape sequences are unaffected
+        assert_eq!(
+            input_sequence(&mut parser, "\x1bo"),
+            [Action::Ignore, Action::EscapeSequence(b'o')]
+        );
+    }
+
+    #[test]
+    fn csi_argument_overflow() {
+        let mut parser = Parser::new();
+
+        let mut actions = input_sequence(&mut parser, "\x1b[99999999999999999999;5H");
+
+        assert_eq!(
+            actions.pop().unwrap(),
+            Action::ControlSequenceIntroducer(CSI::CUP(u16::MAX as usize, 5))
+        );
+    }
 }
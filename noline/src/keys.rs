@@ -0,0 +1,361 @@
+//! Key decoder
+//!
+//! Decodes input using the same parser as the editors, for
+//! applications that need to read keys outside of `readline`, e.g. for
+//! menu navigation.
+//!
+//! # Example
+//! ```
+//! # #[cfg(not(feature = "ascii-only"))]
+//! # {
+//! use noline::keys::{Decoder, KeyEvent};
+//!
+//! let mut decoder = Decoder::new();
+//!
+//! let keys: Vec<KeyEvent> = "a\x1b[Aæ\x01\r"
+//!     .bytes()
+//!     .filter_map(|byte| decoder.advance(byte))
+//!     .collect();
+//!
+//! assert_eq!(
+//!     keys,
+//!     [
+//!         KeyEvent::Char('a'),
+//!         KeyEvent::Up,
+//!         KeyEvent::Char('æ'),
+//!         KeyEvent::Ctrl('a'),
+//!         KeyEvent::Enter,
+//!     ]
+//! );
+//! # }
+//! ```
+
+use crate::input::{Action, ControlCharacter, Parser, CSI};
+
+/// Decoded key
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum KeyEvent {
+    /// Printable character
+    Char(char),
+    /// Control character, e.g. `Ctrl('a')` for Ctrl+A. Enter, Tab and
+    /// Backspace are reported as separate keys. Many terminals send
+    /// Ctrl+H for backspace.
+    Ctrl(char),
+    /// Carriage return or line feed
+    Enter,
+    /// Tab
+    Tab,
+    /// Backspace (DEL)
+    Backspace,
+    /// Escape followed by ASCII character, usually sent for Alt+key
+    Alt(char),
+    /// Arrow up
+    Up,
+    /// Arrow down
+    Down,
+    /// Arrow left
+    Left,
+    /// Arrow right
+    Right,
+    /// Home
+    Home,
+    /// End
+    End,
+    /// Insert
+    Insert,
+    /// Delete
+    Delete,
+    /// Start of pasted text, sent by terminals with bracketed paste
+    /// enabled
+    PasteStart,
+    /// End of pasted text
+    PasteEnd,
+    /// Cursor position report from terminal. Row and column start at 1.
+    CursorPosition { row: usize, column: usize },
+    /// Special key sent with modifiers, e.g. Ctrl+Right or Shift+Tab
+    Modified(Key, Modifiers),
+    /// Unrecognized escape or control sequence
+    Unknown,
+    /// Invalid UTF-8 sequence
+    InvalidUtf8,
+}
+
+/// Special key held with modifiers, see [`KeyEvent::Modified`]
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Key {
+    /// Arrow up
+    Up,
+    /// Arrow down
+    Down,
+    /// Arrow left
+    Left,
+    /// Arrow right
+    Right,
+    /// Home
+    Home,
+    /// End
+    End,
+    /// Insert
+    Insert,
+    /// Delete
+    Delete,
+    /// Tab, only sent with Shift
+    Tab,
+}
+
+/// Modifier keys, decoded from the modifier parameter of xterm style
+/// key sequences. Combine with `|` and test with
+/// [`Modifiers::contains`].
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Modifiers(u8);
+
+impl Modifiers {
+    /// No modifiers
+    pub const NONE: Self = Self(0);
+    /// Shift
+    pub const SHIFT: Self = Self(1);
+    /// Alt
+    pub const ALT: Self = Self(2);
+    /// Ctrl
+    pub const CTRL: Self = Self(4);
+    /// Meta
+    pub const META: Self = Self(8);
+
+    // Parameter is 1 plus the modifier bits
+    fn from_parameter(parameter: usize) -> Self {
+        Self((parameter.saturating_sub(1) & 0xf) as u8)
+    }
+
+    /// True if all modifiers in `other` are held
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// True if no modifiers are held
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl core::ops::BitOr for Modifiers {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl KeyEvent {
+    // Key with modifiers from parameter, or the plain key if there
+    // are none
+    fn modified(key: Key, parameter: usize) -> Self {
+        let modifiers = Modifiers::from_parameter(parameter);
+
+        if !modifiers.is_empty() {
+            return Self::Modified(key, modifiers);
+        }
+
+        match key {
+            Key::Up => Self::Up,
+            Key::Down => Self::Down,
+            Key::Left => Self::Left,
+            Key::Right => Self::Right,
+            Key::Home => Self::Home,
+            Key::End => Self::End,
+            Key::Insert => Self::Insert,
+            Key::Delete => Self::Delete,
+            Key::Tab => Self::Tab,
+        }
+    }
+
+    fn from_control_character(c: ControlCharacter) -> Self {
+        match c {
+            ControlCharacter::CarriageReturn | ControlCharacter::LineFeed => Self::Enter,
+            ControlCharacter::Tab => Self::Tab,
+            ControlCharacter::Backspace => Self::Backspace,
+            c => {
+                let byte: u8 = c.into();
+
+                Self::Ctrl(match byte {
+                    0x1..=0x1a => (byte + 0x60) as char,
+                    _ => (byte + 0x40) as char,
+                })
+            }
+        }
+    }
+
+    fn from_csi(csi: CSI) -> Self {
+        match csi {
+            CSI::CUU(_) => Self::Up,
+            CSI::CUD(_) => Self::Down,
+            CSI::CUF(_) => Self::Right,
+            CSI::CUB(_) => Self::Left,
+            CSI::CUUMod(modifier) => Self::modified(Key::Up, modifier),
+            CSI::CUDMod(modifier) => Self::modified(Key::Down, modifier),
+            CSI::CUFMod(modifier) => Self::modified(Key::Right, modifier),
+            CSI::CUBMod(modifier) => Self::modified(Key::Left, modifier),
+            CSI::CPR(row, column) => Self::CursorPosition { row, column },
+            // Home is sent as cursor position 1;1 by xterm, with the
+            // modifier in place of the column
+            CSI::Home | CSI::CUP(1, 1) => Self::Home,
+            CSI::CUP(1, modifier) | CSI::HomeMod(modifier) => Self::modified(Key::Home, modifier),
+            CSI::Insert => Self::Insert,
+            CSI::Delete => Self::Delete,
+            CSI::End => Self::End,
+            CSI::InsertMod(modifier) => Self::modified(Key::Insert, modifier),
+            CSI::DeleteMod(modifier) => Self::modified(Key::Delete, modifier),
+            CSI::EndMod(modifier) => Self::modified(Key::End, modifier),
+            CSI::CBT => Self::Modified(Key::Tab, Modifiers::SHIFT),
+            CSI::PasteStart => Self::PasteStart,
+            CSI::PasteEnd => Self::PasteEnd,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Decoder turning bytes into [`KeyEvent`]s
+pub struct Decoder {
+    parser: Parser,
+}
+
+impl Decoder {
+    /// Create new decoder
+    pub fn new() -> Self {
+        Self {
+            parser: Parser::new(),
+        }
+    }
+
+    /// Advance decoder by one byte. Returns `None` until a key
+    /// sequence is complete.
+    pub fn advance(&mut self, byte: u8) -> Option<KeyEvent> {
+        match self.parser.advance(byte) {
+            Action::Ignore => None,
+            Action::Print(c) => Some(KeyEvent::Char(c.as_char())),
+            Action::InvalidUtf8 => Some(KeyEvent::InvalidUtf8),
+            Action::ControlCharacter(c) => Some(KeyEvent::from_control_character(c)),
+            Action::EscapeSequence(byte) if byte.is_ascii() => Some(KeyEvent::Alt(byte as char)),
+            Action::EscapeSequence(_) => Some(KeyEvent::Unknown),
+            Action::ControlSequenceIntroducer(csi) => Some(KeyEvent::from_csi(csi)),
+        }
+    }
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+
+    fn decode(input: &[u8]) -> Vec<KeyEvent> {
+        let mut decoder = Decoder::new();
+
+        input
+            .iter()
+            .filter_map(|&byte| decoder.advance(byte))
+            .collect()
+    }
+
+    #[test]
+    fn decoder() {
+        assert_eq!(decode(b"a"), [KeyEvent::Char('a')]);
+        #[cfg(not(feature = "ascii-only"))]
+        {
+            assert_eq!(decode("æ".as_bytes()), [KeyEvent::Char('æ')]);
+            assert_eq!(decode("😀".as_bytes()), [KeyEvent::Char('😀')]);
+        }
+        assert_eq!(decode(b"\xff"), [KeyEvent::InvalidUtf8]);
+
+        assert_eq!(decode(b"\x07"), [KeyEvent::Ctrl('g')]);
+        assert_eq!(decode(b"\x03"), [KeyEvent::Ctrl('c')]);
+        assert_eq!(decode(b"\x08"), [KeyEvent::Ctrl('h')]);
+        assert_eq!(decode(b"\x00"), [KeyEvent::Ctrl('@')]);
+        assert_eq!(decode(b"\x1f"), [KeyEvent::Ctrl('_')]);
+        assert_eq!(decode(b"\r\n"), [KeyEvent::Enter, KeyEvent::Enter]);
+        assert_eq!(decode(b"\t"), [KeyEvent::Tab]);
+        assert_eq!(decode(b"\x7f"), [KeyEvent::Backspace]);
+
+        assert_eq!(decode(b"\x1bb"), [KeyEvent::Alt('b')]);
+        assert_eq!(decode(b"\x1b\xc3"), [KeyEvent::Unknown]);
+
+        assert_eq!(decode(b"\x1b[A"), [KeyEvent::Up]);
+        assert_eq!(decode(b"\x1b[10B"), [KeyEvent::Down]);
+        assert_eq!(decode(b"\x1b[C"), [KeyEvent::Right]);
+        assert_eq!(decode(b"\x1b[D"), [KeyEvent::Left]);
+        assert_eq!(decode(b"\x1b[1~"), [KeyEvent::Home]);
+        assert_eq!(decode(b"\x1b[2~"), [KeyEvent::Insert]);
+        assert_eq!(decode(b"\x1b[3~"), [KeyEvent::Delete]);
+        assert_eq!(
+            decode(b"\x1b[200~a\x1b[201~"),
+            [
+                KeyEvent::PasteStart,
+                KeyEvent::Char('a'),
+                KeyEvent::PasteEnd
+            ]
+        );
+        assert_eq!(decode(b"\x1b[4~"), [KeyEvent::End]);
+        assert_eq!(decode(b"\x1b[H"), [KeyEvent::Home]);
+        assert_eq!(decode(b"\x1b[F"), [KeyEvent::End]);
+        assert_eq!(
+            decode(b"\x1b[1;5C"),
+            [KeyEvent::Modified(Key::Right, Modifiers::CTRL)]
+        );
+        assert_eq!(
+            decode(b"\x1b[1;3D"),
+            [KeyEvent::Modified(Key::Left, Modifiers::ALT)]
+        );
+        assert_eq!(
+            decode(b"\x1b[1;2A\x1b[1;6B"),
+            [
+                KeyEvent::Modified(Key::Up, Modifiers::SHIFT),
+                KeyEvent::Modified(Key::Down, Modifiers::SHIFT | Modifiers::CTRL)
+            ]
+        );
+        assert_eq!(decode(b"\x1b[1;1C"), [KeyEvent::Right]);
+        assert_eq!(
+            decode(b"\x1b[1;5H\x1b[1;5F"),
+            [
+                KeyEvent::Modified(Key::Home, Modifiers::CTRL),
+                KeyEvent::Modified(Key::End, Modifiers::CTRL)
+            ]
+        );
+        assert_eq!(
+            decode(b"\x1b[2;3~\x1b[3;5~\x1b[1;2~\x1b[4;9~"),
+            [
+                KeyEvent::Modified(Key::Insert, Modifiers::ALT),
+                KeyEvent::Modified(Key::Delete, Modifiers::CTRL),
+                KeyEvent::Modified(Key::Home, Modifiers::SHIFT),
+                KeyEvent::Modified(Key::End, Modifiers::META)
+            ]
+        );
+        assert_eq!(
+            decode(b"\x1b[Z"),
+            [KeyEvent::Modified(Key::Tab, Modifiers::SHIFT)]
+        );
+
+        let modifiers = Modifiers::CTRL | Modifiers::ALT;
+        assert!(modifiers.contains(Modifiers::CTRL));
+        assert!(!modifiers.contains(Modifiers::SHIFT));
+        assert!(Modifiers::NONE.is_empty());
+        assert_eq!(decode(b"\x1b[9~"), [KeyEvent::Unknown]);
+        assert_eq!(
+            decode(b"\x1b[312;836R"),
+            [KeyEvent::CursorPosition {
+                row: 312,
+                column: 836
+            }]
+        );
+        assert_eq!(decode(b"\x1b[5R"), [KeyEvent::Unknown]);
+    }
+}
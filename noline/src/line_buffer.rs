@@ -3,6 +3,24 @@
 //! Can be backed by [`std::vec::Vec<u8>`] for dynamic allocation or
 //! [`StaticBuffer`] for static allocation. Custom implementation can
 //! be provided with the [`Buffer`] trait.
+//!
+//! [`LineBuffer`] can also be used on its own to edit strings, e.g.
+//! commands stored in a configuration. Indices are in chars, not bytes,
+//! except for [`LineBuffer::len`]. Insertions that don't fit in a
+//! bounded buffer fail, leaving the buffer unchanged:
+//! ```
+//! use noline::line_buffer::LineBuffer;
+//!
+//! let mut array = [0; 16];
+//! let mut buffer = LineBuffer::from_slice(&mut array);
+//!
+//! buffer.push_str("set baud 9600").unwrap();
+//! buffer.replace_range(9..13, "115200").unwrap();
+//! assert_eq!(buffer.as_str(), "set baud 115200");
+//!
+//! assert!(buffer.push_str(" 8n1").is_err());
+//! assert_eq!(buffer.as_str(), "set baud 115200");
+//! ```
 
 use crate::utf8::Utf8Char;
 use core::{ops::Range, str::from_utf8_unchecked};
@@ -27,6 +45,25 @@ pub trait Buffer {
 
     /// Return byte slice into buffer from 0 up to buffer length.
     fn as_slice(&self) -> &[u8];
+
+    /// Replace byte at index. The default implementation removes the
+    /// byte and inserts the new one, override it if the buffer can
+    /// replace a byte in place.
+    fn replace_byte(&mut self, index: usize, byte: u8) {
+        self.remove_byte(index);
+        self.insert_byte(index, byte);
+    }
+}
+
+/// Inconsistency found by [`LineBuffer::validate`]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum InvalidBuffer {
+    /// [`Buffer::buffer_len`] doesn't match length of [`Buffer::as_slice`]
+    LengthMismatch { buffer_len: usize, slice_len: usize },
+    /// Buffer length exceeds [`Buffer::capacity`]
+    CapacityExceeded { buffer_len: usize, capacity: usize },
+    /// Buffer content is not valid UTF-8
+    InvalidUtf8 { valid_up_to: usize },
 }
 
 /// High level interface to line buffer
@@ -49,11 +86,78 @@ impl<B: Buffer> LineBuffer<B> {
         self.buf.as_slice()
     }
 
-    /// Return buffer length
+    /// Return buffer length in bytes, see [`LineBuffer::char_len`]
     pub fn len(&self) -> usize {
         self.buf.buffer_len()
     }
 
+    /// Return number of chars in buffer
+    pub fn char_len(&self) -> usize {
+        if cfg!(feature = "ascii-only") {
+            self.len()
+        } else {
+            self.as_str().chars().count()
+        }
+    }
+
+    /// Return true if buffer is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Return number of bytes that can be inserted before the buffer
+    /// is full, or None if unbounded
+    pub fn remaining_capacity(&self) -> Option<usize> {
+        self.buf
+            .capacity()
+            .map(|capacity| capacity.saturating_sub(self.len()))
+    }
+
+    /// Check that the underlying [`Buffer`] is consistent and holds
+    /// valid UTF-8.
+    ///
+    /// The editor relies on the buffer only ever holding valid UTF-8.
+    /// A custom [`Buffer`] implementation that gets this wrong will
+    /// trip a debug assertion after the offending operation, but
+    /// defensive applications can also call this in release builds,
+    /// e.g. before using the line returned by `readline`. If
+    /// validation fails, [`LineBuffer::truncate`] resets the buffer to
+    /// a known good state.
+    pub fn validate(&self) -> Result<(), InvalidBuffer> {
+        let buffer_len = self.buf.buffer_len();
+        let slice = self.buf.as_slice();
+
+        if buffer_len != slice.len() {
+            return Err(InvalidBuffer::LengthMismatch {
+                buffer_len,
+                slice_len: slice.len(),
+            });
+        }
+
+        if let Some(capacity) = self.buf.capacity() {
+            if buffer_len > capacity {
+                return Err(InvalidBuffer::CapacityExceeded {
+                    buffer_len,
+                    capacity,
+                });
+            }
+        }
+
+        core::str::from_utf8(slice).map_err(|err| InvalidBuffer::InvalidUtf8 {
+            valid_up_to: err.valid_up_to(),
+        })?;
+
+        Ok(())
+    }
+
+    fn debug_validate(&self) {
+        debug_assert!(
+            self.validate().is_ok(),
+            "Invalid line buffer: {:?}",
+            self.validate()
+        );
+    }
+
     /// Return buffer as string. The buffer should only hold a valid
     /// UTF-8, so this function is infallible.
     pub fn as_str(&self) -> &str {
@@ -61,6 +165,15 @@ impl<B: Buffer> LineBuffer<B> {
         unsafe { from_utf8_unchecked(self.as_slice()) }
     }
 
+    #[cfg(feature = "ascii-only")]
+    fn char_ranges(&self) -> impl Iterator<Item = (Range<usize>, char)> + '_ {
+        self.as_slice()
+            .iter()
+            .enumerate()
+            .map(|(i, &byte)| (i..(i + 1), byte as char))
+    }
+
+    #[cfg(not(feature = "ascii-only"))]
     fn char_ranges(&self) -> impl Iterator<Item = (Range<usize>, char)> + '_ {
         let s = self.as_str();
 
@@ -69,6 +182,12 @@ impl<B: Buffer> LineBuffer<B> {
             .map(|((start, c), (end, _))| (start..end, c))
     }
 
+    #[cfg(feature = "ascii-only")]
+    fn get_byte_position(&self, char_index: usize) -> usize {
+        char_index.min(self.buf.buffer_len())
+    }
+
+    #[cfg(not(feature = "ascii-only"))]
     fn get_byte_position(&self, char_index: usize) -> usize {
         let s = self.as_str();
 
@@ -86,11 +205,7 @@ impl<B: Buffer> LineBuffer<B> {
         if let Some((range, _)) = ranges.next() {
             drop(ranges);
 
-            let pos = range.start;
-
-            for _ in range {
-                self.buf.remove_byte(pos);
-            }
+            self.delete_range(range);
         }
     }
 
@@ -99,6 +214,15 @@ impl<B: Buffer> LineBuffer<B> {
         let pos = self.get_byte_position(char_index);
 
         self.buf.truncate_buffer(pos);
+
+        self.debug_validate();
+    }
+
+    /// Delete buffer before character index
+    pub fn delete_before_char(&mut self, char_index: usize) {
+        let pos = self.get_byte_position(char_index);
+
+        self.delete_range(0..pos);
     }
 
     /// Truncate buffer
@@ -106,26 +230,104 @@ impl<B: Buffer> LineBuffer<B> {
         self.delete_after_char(0);
     }
 
+    /// Remove all chars, same as [`LineBuffer::truncate`]
+    pub fn clear(&mut self) {
+        self.truncate();
+    }
+
+    /// Remove and return the last char, if any
+    pub fn pop(&mut self) -> Option<char> {
+        let (range, c) = self.char_ranges().last()?;
+
+        self.delete_range(range);
+
+        Some(c)
+    }
+
+    /// Remove the chars in a range of char indices. Indices past the
+    /// end are treated as the end.
+    pub fn remove_char_range(&mut self, range: Range<usize>) {
+        let start = self.get_byte_position(range.start);
+        let end = self.get_byte_position(range.end);
+
+        self.delete_range(start..end.max(start));
+    }
+
+    /// Replace the chars in a range of char indices with a string.
+    /// Indices past the end are treated as the end. Fails if the result
+    /// doesn't fit in a bounded buffer, or, with the `ascii-only`
+    /// feature, if the string isn't ASCII, leaving the buffer
+    /// unchanged.
+    #[allow(clippy::result_unit_err)]
+    pub fn replace_range(&mut self, range: Range<usize>, s: &str) -> Result<(), ()> {
+        let start = self.get_byte_position(range.start);
+        let end = self.get_byte_position(range.end).max(start);
+
+        if cfg!(feature = "ascii-only") && !s.is_ascii() {
+            return Err(());
+        }
+
+        if let Some(capacity) = self.buf.capacity() {
+            if self.len() - (end - start) + s.len() > capacity {
+                return Err(());
+            }
+        }
+
+        self.delete_range(start..end);
+
+        // Checked to fit, at a char boundary
+        unsafe { self.insert_bytes(start, s.as_bytes()) }
+    }
+
+    /// Append string. Fails like [`LineBuffer::insert_str`].
+    #[allow(clippy::result_unit_err)]
+    pub fn push_str(&mut self, s: &str) -> Result<(), ()> {
+        unsafe { self.insert_bytes(self.len(), s.as_bytes()) }
+    }
+
+    /// Iterate over the chars with their char indices, unlike
+    /// [`str::char_indices`], which gives byte indices
+    pub fn char_indices(&self) -> impl Iterator<Item = (usize, char)> + '_ {
+        self.char_ranges().map(|(_, c)| c).enumerate()
+    }
+
     fn delete_range(&mut self, range: Range<usize>) {
         let pos = range.start;
         for _ in range {
             self.buf.remove_byte(pos);
         }
+
+        self.debug_validate();
     }
 
-    /// Delete previous word from character index
-    pub fn delete_previous_word(&mut self, char_index: usize) -> usize {
+    /// Return the characters in a range of character indices. Indices
+    /// past the end are treated as the end.
+    pub fn char_slice(&self, range: Range<usize>) -> &str {
+        let start = self.get_byte_position(range.start);
+        let end = self.get_byte_position(range.end);
+
+        &self.as_str()[start..end]
+    }
+
+    /// Return character index of the start of the text deleted by
+    /// [`LineBuffer::delete_previous_word`]
+    pub fn previous_word_delete_start(&self, char_index: usize) -> usize {
         let mut word_start = 0;
-        let mut word_end = 0;
 
-        for (i, (range, c)) in self.char_ranges().enumerate().take(char_index) {
+        for (i, c) in self.as_str().chars().enumerate().take(char_index) {
             if c == ' ' && i < char_index - 1 {
-                word_start = range.end;
+                word_start = i + 1;
             }
-
-            word_end = range.end;
         }
 
+        word_start
+    }
+
+    /// Delete previous word from character index
+    pub fn delete_previous_word(&mut self, char_index: usize) -> usize {
+        let word_start = self.get_byte_position(self.previous_word_delete_start(char_index));
+        let word_end = self.get_byte_position(char_index);
+
         let deleted = self.as_str()[word_start..word_end].chars().count();
 
         self.delete_range(word_start..word_end);
@@ -133,6 +335,211 @@ impl<B: Buffer> LineBuffer<B> {
         deleted
     }
 
+    /// Delete from character index to the end of the current or next
+    /// word. Return number of characters deleted.
+    pub fn delete_next_word(&mut self, char_index: usize) -> usize {
+        let word_end = self.next_word_end(char_index);
+        let start = self.get_byte_position(char_index);
+        let end = self.get_byte_position(word_end);
+
+        self.delete_range(start..end);
+
+        word_end - char_index
+    }
+
+    /// Return character index of the start of the word before
+    /// character index. Consecutive spaces are treated as one
+    /// separator.
+    pub fn previous_word_start(&self, char_index: usize) -> usize {
+        let mut word_start = 0;
+        let mut prev = ' ';
+
+        for (i, c) in self.as_str().chars().enumerate().take(char_index) {
+            if c != ' ' && prev == ' ' {
+                word_start = i;
+            }
+
+            prev = c;
+        }
+
+        word_start
+    }
+
+    /// Return character index of the end of the word after character
+    /// index. Consecutive spaces are treated as one separator.
+    pub fn next_word_end(&self, char_index: usize) -> usize {
+        let mut in_word = false;
+        let mut end = char_index;
+
+        for (i, c) in self.as_str().chars().enumerate().skip(char_index) {
+            if c == ' ' {
+                if in_word {
+                    return i;
+                }
+            } else {
+                in_word = true;
+            }
+
+            end = i + 1;
+        }
+
+        end
+    }
+
+    /// Return character index of the start of the word after
+    /// character index, or the end of the buffer if there is none.
+    /// Consecutive spaces are treated as one separator.
+    pub fn next_word_start(&self, char_index: usize) -> usize {
+        let mut in_word = true;
+        let mut end = char_index;
+
+        for (i, c) in self.as_str().chars().enumerate().skip(char_index) {
+            if c == ' ' {
+                in_word = false;
+            } else if !in_word {
+                return i;
+            }
+
+            end = i + 1;
+        }
+
+        end
+    }
+
+    /// Return the character index ranges of the words in the buffer.
+    /// Words are separated by one or more spaces.
+    pub fn word_ranges(&self) -> impl Iterator<Item = Range<usize>> + '_ {
+        let mut chars = self.as_str().chars().enumerate().peekable();
+
+        core::iter::from_fn(move || {
+            let (start, _) = chars.find(|&(_, c)| c != ' ')?;
+            let mut end = start + 1;
+
+            while chars.next_if(|&(_, c)| c != ' ').is_some() {
+                end += 1;
+            }
+
+            Some(start..end)
+        })
+    }
+
+    /// Swap the word at or after character index with the word before
+    /// it, or the last two words at the end of the line. Return the
+    /// character index range from the start of the first word to the
+    /// end of the second, or `None` if there is no word before to swap
+    /// with.
+    pub fn transpose_words(&mut self, char_index: usize) -> Option<Range<usize>> {
+        let mut first = None;
+        let mut second = None;
+
+        for word in self.word_ranges() {
+            let after = word.end > char_index;
+
+            first = second.replace(word);
+
+            if after {
+                break;
+            }
+        }
+
+        let (first, second) = (first?, second?);
+        let start = self.get_byte_position(first.start);
+        let first_len = self.get_byte_position(first.end) - start;
+        let second_start = self.get_byte_position(second.start);
+        let end = self.get_byte_position(second.end);
+
+        // Move the second word in front of the first, then the first
+        // word to the end, after the separator
+        for i in 0..(end - second_start) {
+            let byte = self.buf.remove_byte(second_start + i);
+            self.buf.insert_byte(start + i, byte);
+        }
+
+        for _ in 0..first_len {
+            let byte = self.buf.remove_byte(start + end - second_start);
+            self.buf.insert_byte(end - 1, byte);
+        }
+
+        self.debug_validate();
+
+        Some(first.start..second.end)
+    }
+
+    /// Replace the chars from character index to the end of the word
+    /// at or after it with `f(c)`, skipping the spaces before the word.
+    /// The replacement may have a different UTF-8 length. Chars are
+    /// kept if their replacement doesn't fit in the buffer, or is
+    /// non-ASCII with the `ascii-only` feature. Return the character
+    /// index of the end of the word.
+    pub fn map_word_chars(&mut self, char_index: usize, mut f: impl FnMut(char) -> char) -> usize {
+        let end = self.next_word_end(char_index);
+
+        for i in char_index..end {
+            let index = self.get_byte_position(i);
+            let Some(c) = self.as_str()[index..].chars().next() else {
+                break;
+            };
+
+            let mapped = if c == ' ' { c } else { f(c) };
+
+            if mapped == c {
+                continue;
+            }
+
+            let (mut old, mut new) = ([0; 4], [0; 4]);
+            let old = c.encode_utf8(&mut old).as_bytes();
+
+            for _ in 0..old.len() {
+                self.buf.remove_byte(index);
+            }
+
+            // The replaced char was removed, so it fits back in
+            unsafe {
+                if self
+                    .insert_bytes(index, mapped.encode_utf8(&mut new).as_bytes())
+                    .is_err()
+                {
+                    self.insert_bytes(index, old).unwrap();
+                }
+            }
+        }
+
+        end
+    }
+
+    /// Return character index of the start of the line containing
+    /// character index, after the newline before it, if any
+    pub fn line_start(&self, char_index: usize) -> usize {
+        self.as_str()
+            .chars()
+            .take(char_index)
+            .enumerate()
+            .filter(|&(_, c)| c == '\n')
+            .last()
+            .map_or(0, |(i, _)| i + 1)
+    }
+
+    /// Return character index of the end of the line containing
+    /// character index, at the newline after it, if any
+    pub fn line_end(&self, char_index: usize) -> usize {
+        let mut end = char_index;
+
+        for (i, c) in self.as_str().chars().enumerate().skip(char_index) {
+            if c == '\n' {
+                return i;
+            }
+
+            end = i + 1;
+        }
+
+        end
+    }
+
+    /// Character at character index, if any
+    pub fn char_at(&self, char_index: usize) -> Option<char> {
+        self.as_str().chars().nth(char_index)
+    }
+
     /// Swap characters at index
     pub fn swap_chars(&mut self, char_index: usize) {
         let mut ranges = self.char_ranges().skip(char_index - 1);
@@ -145,17 +552,25 @@ impl<B: Buffer> LineBuffer<B> {
                     let byte = self.buf.remove_byte(remove);
                     self.buf.insert_byte(insert, byte);
                 }
+
+                self.debug_validate();
             }
         }
     }
 
-    /// Insert bytes at index
+    /// Insert bytes at index. With the `ascii-only` feature, non-ASCII
+    /// bytes are rejected.
     ///
     /// # Safety
     ///
     /// The caller must ensure that the input bytes are a valid UTF-8
     /// sequence and that the byte index aligns with a valid UTF-8 character index.
+    #[allow(clippy::result_unit_err)]
     pub unsafe fn insert_bytes(&mut self, index: usize, bytes: &[u8]) -> Result<(), ()> {
+        if cfg!(feature = "ascii-only") && !bytes.is_ascii() {
+            return Err(());
+        }
+
         if let Some(capacity) = self.buf.capacity() {
             if bytes.len() > capacity - self.buf.buffer_len() {
                 return Err(());
@@ -166,6 +581,43 @@ impl<B: Buffer> LineBuffer<B> {
             self.buf.insert_byte(index + i, *byte);
         }
 
+        self.debug_validate();
+
+        Ok(())
+    }
+
+    /// Insert the concatenation of two byte slices at char position,
+    /// all or nothing. With the `ascii-only` feature, non-ASCII bytes
+    /// are rejected.
+    ///
+    /// # Safety
+    ///
+    /// See [`LineBuffer::replace_with_slices`].
+    #[allow(clippy::result_unit_err)]
+    pub unsafe fn insert_slices(
+        &mut self,
+        char_index: usize,
+        slice1: &[u8],
+        slice2: &[u8],
+    ) -> Result<(), ()> {
+        if cfg!(feature = "ascii-only") && !(slice1.is_ascii() && slice2.is_ascii()) {
+            return Err(());
+        }
+
+        if let Some(capacity) = self.buf.capacity() {
+            if slice1.len() + slice2.len() > capacity - self.buf.buffer_len() {
+                return Err(());
+            }
+        }
+
+        let index = self.get_byte_position(char_index);
+
+        for (i, byte) in slice1.iter().chain(slice2.iter()).enumerate() {
+            self.buf.insert_byte(index + i, *byte);
+        }
+
+        self.debug_validate();
+
         Ok(())
     }
 
@@ -177,10 +629,66 @@ impl<B: Buffer> LineBuffer<B> {
         }
     }
 
-    /// Insert string at char position
+    /// Insert string at char position. Fails if the string doesn't fit
+    /// in a bounded buffer, or, with the `ascii-only` feature, isn't
+    /// ASCII, leaving the buffer unchanged.
+    #[allow(clippy::result_unit_err)]
     pub fn insert_str(&mut self, char_index: usize, s: &str) -> Result<(), ()> {
         unsafe { self.insert_bytes(self.get_byte_position(char_index), s.as_bytes()) }
     }
+
+    /// Replace buffer content with the concatenation of two byte
+    /// slices. If the content doesn't fit, it's truncated at a
+    /// character boundary. Return true if the content was truncated.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the concatenated slices are a
+    /// valid UTF-8 sequence. A character may be split across the
+    /// slices.
+    pub unsafe fn replace_with_slices(&mut self, slice1: &[u8], slice2: &[u8]) -> bool {
+        self.buf.truncate_buffer(0);
+        self.append_slices(slice1, slice2)
+    }
+
+    /// Append the concatenation of two byte slices to the buffer,
+    /// truncated at a character boundary if it doesn't fit. Return
+    /// true if the content was truncated. With the `ascii-only`
+    /// feature, the content is also truncated at the first non-ASCII
+    /// byte.
+    ///
+    /// # Safety
+    ///
+    /// See [`LineBuffer::replace_with_slices`].
+    pub unsafe fn append_slices(&mut self, slice1: &[u8], slice2: &[u8]) -> bool {
+        let start = self.buf.buffer_len();
+        let len = slice1.len() + slice2.len();
+        let bytes = || slice1.iter().chain(slice2.iter()).copied();
+
+        let mut cut = self
+            .buf
+            .capacity()
+            .map_or(len, |capacity| (capacity - start).min(len));
+
+        if cfg!(feature = "ascii-only") {
+            cut = bytes().take(cut).take_while(u8::is_ascii).count();
+        }
+
+        // Back up to the start of the character containing the cut
+        if cut < len {
+            while cut > 0 && bytes().nth(cut).is_some_and(|b| b & 0xc0 == 0x80) {
+                cut -= 1;
+            }
+        }
+
+        for (i, byte) in bytes().take(cut).enumerate() {
+            self.buf.insert_byte(start + i, byte);
+        }
+
+        self.debug_validate();
+
+        cut < len
+    }
 }
 
 /// Emtpy buffer used for builder
@@ -261,6 +769,10 @@ impl<'a> Buffer for SliceBuffer<'a> {
     fn as_slice(&self) -> &[u8] {
         &self.data[0..self.len]
     }
+
+    fn replace_byte(&mut self, index: usize, byte: u8) {
+        self.data[..self.len][index] = byte;
+    }
 }
 
 #[cfg(any(test, doc, feature = "alloc", feature = "std"))]
@@ -289,6 +801,12 @@ mod alloc {
         }
     }
 
+    impl Default for UnboundedBuffer {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
     impl Buffer for UnboundedBuffer {
         fn buffer_len(&self) -> usize {
             self.vec.len()
@@ -313,6 +831,10 @@ mod alloc {
         fn as_slice(&self) -> &[u8] {
             self.vec.as_slice()
         }
+
+        fn replace_byte(&mut self, index: usize, byte: u8) {
+            self.vec[index] = byte;
+        }
     }
 }
 
@@ -333,6 +855,9 @@ mod tests {
         }
 
         buf.remove_byte(19);
+
+        buf.replace_byte(0, 0x31);
+        assert_eq!(&buf.as_slice()[..2], b"10");
     }
 
     fn insert_str<B: Buffer>(buf: &mut LineBuffer<B>, index: usize, s: &str) {
@@ -376,21 +901,27 @@ mod tests {
 
         assert_eq!(buf.as_str(), "heLlo");
 
-        insert_str(buf, 5, " æå");
+        #[cfg(not(feature = "ascii-only"))]
+        {
+            insert_str(buf, 5, " æå");
 
-        assert_eq!(buf.as_str(), "heLlo æå");
+            assert_eq!(buf.as_str(), "heLlo æå");
 
-        insert_str(buf, 7, "ø");
+            insert_str(buf, 7, "ø");
 
-        assert_eq!(buf.as_str(), "heLlo æøå");
+            assert_eq!(buf.as_str(), "heLlo æøå");
 
-        buf.delete(8);
+            buf.delete(8);
 
-        assert_eq!(buf.as_str(), "heLlo æø");
+            assert_eq!(buf.as_str(), "heLlo æø");
 
-        buf.delete(7);
+            buf.delete(7);
 
-        assert_eq!(buf.as_str(), "heLlo æ");
+            assert_eq!(buf.as_str(), "heLlo æ");
+        }
+
+        #[cfg(feature = "ascii-only")]
+        insert_str(buf, 5, " a");
 
         buf.delete_previous_word(7);
 
@@ -405,6 +936,205 @@ mod tests {
         buf.delete_previous_word(12);
 
         assert_eq!(buf.as_str(), "word1 word3");
+
+        assert_eq!(buf.delete_next_word(11), 0);
+        assert_eq!(buf.as_str(), "word1 word3");
+
+        assert_eq!(buf.delete_next_word(2), 3);
+        assert_eq!(buf.as_str(), "wo word3");
+
+        #[cfg(not(feature = "ascii-only"))]
+        {
+            buf.truncate();
+            insert_str(buf, 0, "æøå   word2");
+
+            assert_eq!(buf.delete_next_word(3), 8);
+            assert_eq!(buf.as_str(), "æøå");
+        }
+    }
+
+    fn test_line_buffer_editing<B: Buffer>(buf: &mut LineBuffer<B>) {
+        buf.clear();
+        assert_eq!(buf.char_len(), 0);
+        assert_eq!(buf.pop(), None);
+
+        buf.push_str("Hello").unwrap();
+        buf.push_str(", World!").unwrap();
+        assert_eq!(buf.as_str(), "Hello, World!");
+        assert_eq!(buf.char_len(), 13);
+
+        assert_eq!(buf.pop(), Some('!'));
+        assert_eq!(buf.as_str(), "Hello, World");
+
+        buf.remove_char_range(5..7);
+        assert_eq!(buf.as_str(), "HelloWorld");
+
+        buf.remove_char_range(8..20);
+        assert_eq!(buf.as_str(), "HelloWor");
+
+        buf.remove_char_range(20..30);
+        assert_eq!(buf.as_str(), "HelloWor");
+
+        buf.replace_range(5..8, ", there").unwrap();
+        assert_eq!(buf.as_str(), "Hello, there");
+
+        buf.replace_range(0..5, "Hi").unwrap();
+        assert_eq!(buf.as_str(), "Hi, there");
+
+        buf.replace_range(9..9, "!").unwrap();
+        assert_eq!(buf.as_str(), "Hi, there!");
+
+        assert_eq!(
+            buf.char_indices().take(3).collect::<Vec<_>>(),
+            [(0, 'H'), (1, 'i'), (2, ',')]
+        );
+
+        #[cfg(not(feature = "ascii-only"))]
+        {
+            buf.replace_range(0..2, "Hæ").unwrap();
+            assert_eq!(buf.as_str(), "Hæ, there!");
+            assert_eq!(buf.char_len(), 10);
+            assert_eq!(buf.len(), 11);
+
+            assert_eq!(
+                buf.char_indices().skip(1).take(2).collect::<Vec<_>>(),
+                [(1, 'æ'), (2, ',')]
+            );
+
+            buf.remove_char_range(1..2);
+            assert_eq!(buf.as_str(), "H, there!");
+
+            buf.push_str("ø").unwrap();
+            assert_eq!(buf.pop(), Some('ø'));
+            assert_eq!(buf.as_str(), "H, there!");
+        }
+
+        buf.clear();
+        assert!(buf.is_empty());
+    }
+
+    #[cfg(not(feature = "ascii-only"))]
+    #[test]
+    fn word_boundaries() {
+        let mut buf = LineBuffer::new_unbounded();
+
+        insert_str(&mut buf, 0, "æøå  word2 ord3");
+
+        assert_eq!(buf.previous_word_start(0), 0);
+        assert_eq!(buf.previous_word_start(2), 0);
+        assert_eq!(buf.previous_word_start(5), 0);
+        assert_eq!(buf.previous_word_start(6), 5);
+        assert_eq!(buf.previous_word_start(11), 5);
+        assert_eq!(buf.previous_word_start(15), 11);
+
+        assert_eq!(buf.next_word_end(0), 3);
+        assert_eq!(buf.next_word_end(3), 10);
+        assert_eq!(buf.next_word_end(4), 10);
+        assert_eq!(buf.next_word_end(10), 15);
+        assert_eq!(buf.next_word_end(15), 15);
+
+        assert_eq!(buf.next_word_start(0), 5);
+        assert_eq!(buf.next_word_start(3), 5);
+        assert_eq!(buf.next_word_start(5), 11);
+        assert_eq!(buf.next_word_start(11), 15);
+        assert_eq!(buf.next_word_start(15), 15);
+
+        assert_eq!(buf.previous_word_delete_start(0), 0);
+        assert_eq!(buf.previous_word_delete_start(5), 4);
+        assert_eq!(buf.previous_word_delete_start(10), 5);
+        assert_eq!(buf.previous_word_delete_start(11), 5);
+
+        assert_eq!(buf.char_slice(1..3), "øå");
+        assert_eq!(buf.char_slice(5..10), "word2");
+        assert_eq!(buf.char_slice(11..20), "ord3");
+        assert_eq!(buf.char_slice(20..30), "");
+    }
+
+    #[cfg(not(feature = "ascii-only"))]
+    #[test]
+    fn map_word_chars() {
+        let mut array = [0; 14];
+        let mut buf = LineBuffer::from_slice(&mut array);
+
+        // 'ɐ' is two bytes and 'Ɐ' three
+        insert_str(&mut buf, 0, "æ ɐɐɐ xy");
+
+        assert_eq!(buf.map_word_chars(0, |c| c.to_ascii_uppercase()), 1);
+        assert_eq!(buf.as_str(), "æ ɐɐɐ xy");
+
+        // Only the chars that fit are replaced
+        assert_eq!(buf.map_word_chars(1, |_| 'Ɐ'), 5);
+        assert_eq!(buf.as_str(), "æ ⱯⱯɐ xy");
+
+        assert_eq!(buf.map_word_chars(2, |_| 'a'), 5);
+        assert_eq!(buf.as_str(), "æ aaa xy");
+
+        assert_eq!(buf.map_word_chars(7, |c| c.to_ascii_uppercase()), 8);
+        assert_eq!(buf.as_str(), "æ aaa xY");
+        assert_eq!(buf.map_word_chars(8, |_| 'z'), 8);
+    }
+
+    #[cfg(not(feature = "ascii-only"))]
+    #[test]
+    fn transpose_words() {
+        let transpose = |s: &str, char_index: usize| {
+            let mut buf = LineBuffer::new_unbounded();
+
+            insert_str(&mut buf, 0, s);
+
+            let end = buf.transpose_words(char_index);
+
+            (buf.as_str().to_string(), end)
+        };
+
+        let mut buf = LineBuffer::new_unbounded();
+        insert_str(&mut buf, 0, " æøå  word2 ord3 ");
+        assert_eq!(buf.word_ranges().collect::<Vec<_>>(), [1..4, 6..11, 12..16]);
+
+        // Cursor in the second word, or in the space before it
+        assert_eq!(
+            transpose("æøå  wörd ord3", 7),
+            ("wörd  æøå ord3".into(), Some(0..9))
+        );
+        assert_eq!(
+            transpose("æøå  wörd ord3", 4),
+            ("wörd  æøå ord3".into(), Some(0..9))
+        );
+        assert_eq!(
+            transpose("æøå  wörd ord3", 3),
+            ("wörd  æøå ord3".into(), Some(0..9))
+        );
+
+        // Last two words at the end
+        assert_eq!(
+            transpose("æøå  wörd ord3 ", 15),
+            ("æøå  ord3 wörd ".into(), Some(5..14))
+        );
+
+        assert_eq!(transpose("æøå  wörd", 0), ("æøå  wörd".into(), None));
+        assert_eq!(transpose("  æøå ", 4), ("  æøå ".into(), None));
+        assert_eq!(transpose("", 0), ("".into(), None));
+    }
+
+    #[test]
+    fn line_boundaries() {
+        let mut buf = LineBuffer::new_unbounded();
+
+        insert_str(&mut buf, 0, "select *\nfrom t\n\n;");
+
+        assert_eq!(buf.line_start(0), 0);
+        assert_eq!(buf.line_start(8), 0);
+        assert_eq!(buf.line_start(9), 9);
+        assert_eq!(buf.line_start(14), 9);
+        assert_eq!(buf.line_start(16), 16);
+        assert_eq!(buf.line_start(18), 17);
+
+        assert_eq!(buf.line_end(0), 8);
+        assert_eq!(buf.line_end(8), 8);
+        assert_eq!(buf.line_end(9), 15);
+        assert_eq!(buf.line_end(16), 16);
+        assert_eq!(buf.line_end(17), 18);
+        assert_eq!(buf.line_end(18), 18);
     }
 
     #[test]
@@ -413,28 +1143,259 @@ mod tests {
         let mut buf = LineBuffer::from_slice(&mut array);
 
         test_line_buffer(&mut buf);
+        test_line_buffer_editing(&mut buf);
 
         buf.delete_after_char(0);
 
         assert_eq!(buf.len(), 0);
+        assert_eq!(buf.remaining_capacity(), Some(80));
 
         for i in 0..80 {
             assert!(buf.insert_utf8_char(i, Utf8Char::from_str("a")).is_ok());
         }
 
+        assert_eq!(buf.remaining_capacity(), Some(0));
         assert!(buf.insert_utf8_char(80, Utf8Char::from_str("a")).is_err());
     }
 
+    #[test]
+    fn slice_line_buffer_full() {
+        let mut array = [0; 8];
+        let mut buf = LineBuffer::from_slice(&mut array);
+
+        buf.push_str("abcdef").unwrap();
+        assert!(buf.push_str("ghi").is_err());
+        assert_eq!(buf.as_str(), "abcdef");
+
+        // Fits once the replaced chars are removed
+        assert!(buf.replace_range(0..1, "xyzw").is_err());
+        assert_eq!(buf.as_str(), "abcdef");
+        buf.replace_range(0..2, "xyzw").unwrap();
+        assert_eq!(buf.as_str(), "xyzwcdef");
+
+        assert!(buf.push_str("g").is_err());
+        buf.push_str("").unwrap();
+
+        buf.remove_char_range(2..6);
+        assert_eq!(buf.as_str(), "xyef");
+        assert_eq!(buf.remaining_capacity(), Some(4));
+    }
+
     #[test]
     fn test_alloc_line_buffer() {
         let mut buf = LineBuffer::new_unbounded();
 
         test_line_buffer(&mut buf);
+        test_line_buffer_editing(&mut buf);
 
         buf.delete_after_char(0);
 
         for i in 0..1000 {
             assert!(buf.insert_utf8_char(i, Utf8Char::from_str("a")).is_ok());
         }
+
+        assert_eq!(buf.remaining_capacity(), None);
+    }
+
+    #[cfg(feature = "ascii-only")]
+    #[test]
+    fn ascii_only() {
+        let mut buf = LineBuffer::new_unbounded();
+
+        assert!(buf.insert_str(0, "æ").is_err());
+        assert_eq!(buf.as_str(), "");
+
+        insert_str(&mut buf, 0, "abc");
+        assert_eq!(buf.get_byte_position(2), 2);
+        assert_eq!(buf.get_byte_position(5), 3);
+
+        buf.swap_chars(2);
+        assert_eq!(buf.as_str(), "acb");
+
+        let truncated = unsafe { buf.append_slices(b"de", "fæg".as_bytes()) };
+        assert!(truncated);
+        assert_eq!(buf.as_str(), "acbdef");
+    }
+
+    /// Buffer that reports one byte more than it holds
+    struct BrokenBuffer {
+        inner: SliceBuffer<'static>,
+    }
+
+    impl BrokenBuffer {
+        fn new() -> Self {
+            Self {
+                inner: SliceBuffer::new(std::vec![0; 20].leak()),
+            }
+        }
+    }
+
+    impl Buffer for BrokenBuffer {
+        fn buffer_len(&self) -> usize {
+            self.inner.buffer_len() + 1
+        }
+
+        fn capacity(&self) -> Option<usize> {
+            self.inner.capacity()
+        }
+
+        fn truncate_buffer(&mut self, index: usize) {
+            self.inner.truncate_buffer(index)
+        }
+
+        fn insert_byte(&mut self, index: usize, byte: u8) {
+            self.inner.insert_byte(index, byte)
+        }
+
+        fn remove_byte(&mut self, index: usize) -> u8 {
+            self.inner.remove_byte(index)
+        }
+
+        fn as_slice(&self) -> &[u8] {
+            self.inner.as_slice()
+        }
+    }
+
+    #[cfg(not(feature = "ascii-only"))]
+    #[test]
+    fn replace_with_slices() {
+        let mut array = [0; 8];
+        let mut buf = LineBuffer::from_slice(&mut array);
+
+        insert_str(&mut buf, 0, "abc");
+
+        // Character split across slices
+        let bytes = "abcæø".as_bytes();
+        let truncated = unsafe { buf.replace_with_slices(&bytes[..4], &bytes[4..]) };
+
+        assert!(!truncated);
+        assert_eq!(buf.as_str(), "abcæø");
+
+        // Cut in the middle of a character
+        let bytes = "abcdefgæ".as_bytes();
+        let truncated = unsafe { buf.replace_with_slices(&bytes[..3], &bytes[3..]) };
+
+        assert!(truncated);
+        assert_eq!(buf.as_str(), "abcdefg");
+
+        let bytes = "æøåæøå".as_bytes();
+        let truncated = unsafe { buf.replace_with_slices(bytes, &[]) };
+
+        assert!(truncated);
+        assert_eq!(buf.as_str(), "æøåæ");
+
+        let truncated = unsafe { buf.replace_with_slices(&[], &[]) };
+
+        assert!(!truncated);
+        assert_eq!(buf.as_str(), "");
+
+        insert_str(&mut buf, 0, "æø");
+
+        let truncated = unsafe { buf.append_slices(b"ab", "cæ".as_bytes()) };
+
+        assert!(truncated);
+        assert_eq!(buf.as_str(), "æøabc");
+
+        buf.delete_before_char(2);
+
+        assert_eq!(buf.as_str(), "abc");
+    }
+
+    #[cfg(not(feature = "ascii-only"))]
+    #[test]
+    fn insert_slices() {
+        let mut array = [0; 8];
+        let mut buf = LineBuffer::from_slice(&mut array);
+
+        insert_str(&mut buf, 0, "ab");
+
+        // Character split across slices
+        let bytes = "cæ".as_bytes();
+        assert!(unsafe { buf.insert_slices(1, &bytes[..2], &bytes[2..]) }.is_ok());
+        assert_eq!(buf.as_str(), "acæb");
+
+        // Nothing is inserted if it doesn't fit
+        assert!(unsafe { buf.insert_slices(4, b"de", b"fg") }.is_err());
+        assert_eq!(buf.as_str(), "acæb");
+    }
+
+    #[cfg(not(feature = "ascii-only"))]
+    #[test]
+    fn validate() {
+        let mut array = [0; 20];
+        let mut buf = LineBuffer::from_slice(&mut array);
+
+        assert_eq!(buf.validate(), Ok(()));
+
+        insert_str(&mut buf, 0, "æøå");
+
+        assert_eq!(buf.validate(), Ok(()));
+
+        buf.buf.remove_byte(1);
+
+        assert_eq!(
+            buf.validate(),
+            Err(InvalidBuffer::InvalidUtf8 { valid_up_to: 0 })
+        );
+
+        buf.truncate();
+
+        assert_eq!(buf.validate(), Ok(()));
+
+        let buf = LineBuffer {
+            buf: BrokenBuffer::new(),
+        };
+
+        assert_eq!(
+            buf.validate(),
+            Err(InvalidBuffer::LengthMismatch {
+                buffer_len: 1,
+                slice_len: 0
+            })
+        );
+    }
+
+    #[test]
+    fn char_at() {
+        let mut buf = LineBuffer::new_unbounded();
+
+        insert_str(&mut buf, 0, "abc");
+
+        assert_eq!(buf.char_at(0), Some('a'));
+        assert_eq!(buf.char_at(2), Some('c'));
+        assert_eq!(buf.char_at(3), None);
+
+        #[cfg(not(feature = "ascii-only"))]
+        {
+            insert_str(&mut buf, 1, "é😀");
+
+            assert_eq!(buf.char_at(1), Some('é'));
+            assert_eq!(buf.char_at(2), Some('😀'));
+            assert_eq!(buf.char_at(3), Some('b'));
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Invalid line buffer")]
+    fn broken_buffer() {
+        let mut buf = LineBuffer {
+            buf: BrokenBuffer::new(),
+        };
+
+        let _ = buf.insert_str(0, "a");
+    }
+
+    #[test]
+    #[cfg(all(debug_assertions, not(feature = "ascii-only")))]
+    #[should_panic(expected = "Invalid line buffer")]
+    fn invalid_utf8_insert() {
+        let mut buf = LineBuffer::new_unbounded();
+
+        insert_str(&mut buf, 0, "æ");
+
+        unsafe {
+            let _ = buf.insert_bytes(1, b"a");
+        }
     }
 }
@@ -0,0 +1,64 @@
+//! Connection state of the terminal
+//!
+//! On links like USB CDC, the host can close the port while a line is
+//! being read, after which writes fail or block forever. Implement
+//! [`LinkState`] and add it to the editor with
+//! [`crate::builder::EditorBuilder::with_link_state`], and the editor
+//! checks it before reading or writing.
+//!
+//! When the link is lost mid-line, `readline` returns
+//! [`NolineError::Disconnected`]. The unfinished line is available
+//! from `last_line`, and the next `readline` waits until the link is
+//! back and probes the terminal again, as the host may have connected
+//! a different terminal.
+//!
+//! Closures returning `bool` implement [`LinkState`], spinning while
+//! the sync editor waits for a connection:
+//! ```
+//! use core::sync::atomic::{AtomicBool, Ordering};
+//! use noline::builder::EditorBuilder;
+//!
+//! // Set from e.g. the USB interrupt handler
+//! static DTR: AtomicBool = AtomicBool::new(false);
+//!
+//! let mut buffer = [0; 64];
+//! let builder = EditorBuilder::from_slice(&mut buffer)
+//!     .with_link_state(|| DTR.load(Ordering::Relaxed));
+//! ```
+//!
+//! [`NolineError::Disconnected`]: crate::error::NolineError::Disconnected
+
+/// Connection supervisor consulted by the editor
+pub trait LinkState {
+    /// Return true if the terminal is connected. While waiting for a
+    /// connection, the editor calls this in a loop, so implementations
+    /// that have to poll the link to see changes should do so here.
+    fn connected(&mut self) -> bool;
+
+    /// Called by the sync editor between calls to
+    /// [`LinkState::connected`] while waiting for a connection. The
+    /// default spins, so implement this to sleep or wait for an
+    /// interrupt instead. The async editor yields to other tasks and
+    /// doesn't call this.
+    fn wait(&mut self) {
+        core::hint::spin_loop();
+    }
+}
+
+/// Link that is always connected, used when no [`LinkState`] is given
+pub struct AlwaysConnected;
+
+impl LinkState for AlwaysConnected {
+    fn connected(&mut self) -> bool {
+        true
+    }
+}
+
+impl<F> LinkState for F
+where
+    F: FnMut() -> bool,
+{
+    fn connected(&mut self) -> bool {
+        self()
+    }
+}
@@ -0,0 +1,260 @@
+//! Line editor driven one byte at a time, without IO traits
+//!
+//! For event loops that neither block nor poll, like callbacks from a
+//! JavaScript terminal, [`LineEditorCore`] takes each byte of input as
+//! it arrives and hands the output to a sink closure before returning.
+//! Nothing is read or written behind the caller's back, so bytes can be
+//! shuttled through any channel.
+//!
+//! The terminal size is probed at the start of each line, and the
+//! replies from the terminal are fed like any other input. Use
+//! [`crate::builder::EditorBuilder::with_fixed_terminal_size`] and
+//! [`LineEditorCore::from_editor`] to skip the probe.
+//!
+//! ```
+//! use noline::line_buffer::LineBuffer;
+//! use noline::history::NoHistory;
+//! use noline::no_io::LineEditorCore;
+//! use noline::sync_editor::PollResult;
+//!
+//! let mut buffer = [0; 64];
+//! let mut core =
+//!     LineEditorCore::new(LineBuffer::from_slice(&mut buffer), NoHistory {}, "> ").unwrap();
+//! let mut output = Vec::new();
+//! let mut sink = |bytes: &[u8]| output.extend_from_slice(bytes);
+//!
+//! // Prints the prompt after the terminal replies with its size and
+//! // the cursor position
+//! assert_eq!(core.start(&mut sink).unwrap(), PollResult::Pending);
+//!
+//! let mut line = None;
+//!
+//! for &byte in b"\x1b[24;80R\x1b[1;3Rhello\r" {
+//!     if let PollResult::Done(done) = core.feed(byte, &mut sink).unwrap() {
+//!         line = Some(done.to_string());
+//!     }
+//! }
+//!
+//! assert_eq!(line.as_deref(), Some("hello"));
+//! assert!(output.ends_with(b"hello\n\r"));
+//! ```
+
+use core::convert::Infallible;
+
+use crate::complete::{Completer, NoCompleter};
+use crate::error::NolineError;
+use crate::hint::{Hinter, NoHinter};
+use crate::history::History;
+use crate::line_buffer::{Buffer, LineBuffer};
+use crate::link::{AlwaysConnected, LinkState};
+use crate::sync_editor::{Editor, PollResult};
+use crate::validate::{NoValidator, Validator};
+
+// IO holding at most one byte of input, writing to a sink
+struct FeedIO<'s, S> {
+    input: Option<u8>,
+    sink: &'s mut S,
+}
+
+impl<S> embedded_io::ErrorType for FeedIO<'_, S> {
+    type Error = Infallible;
+}
+
+impl<S> embedded_io::Read for FeedIO<'_, S> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        match (buf.first_mut(), self.input.take()) {
+            (Some(place), Some(byte)) => {
+                *place = byte;
+                Ok(1)
+            }
+            _ => Ok(0),
+        }
+    }
+}
+
+impl<S> embedded_io::ReadReady for FeedIO<'_, S> {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.input.is_some())
+    }
+}
+
+impl<S: FnMut(&[u8])> embedded_io::Write for FeedIO<'_, S> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        (self.sink)(buf);
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Line editor fed one byte at a time, with output written to a sink
+/// closure. Lines are read with [`Editor::readline_poll`].
+pub struct LineEditorCore<
+    'p,
+    B: Buffer,
+    H: History,
+    C: Completer = NoCompleter,
+    L: LinkState = AlwaysConnected,
+    V: Validator = NoValidator,
+    T: Hinter = NoHinter,
+> {
+    editor: Editor<B, H, C, L, V, T>,
+    prompt: &'p str,
+}
+
+impl<'p, B, H> LineEditorCore<'p, B, H>
+where
+    B: Buffer,
+    H: History,
+{
+    /// Create editor with default settings, reading lines with
+    /// `prompt`. Fails like [`Editor::new`].
+    pub fn new(buffer: LineBuffer<B>, history: H, prompt: &'p str) -> Result<Self, NolineError> {
+        // Creating the editor does no IO, so output is dropped
+        let mut io = FeedIO {
+            input: None,
+            sink: &mut |_: &[u8]| (),
+        };
+
+        let editor = Editor::new(buffer, history, &mut io)?;
+
+        Ok(Self { editor, prompt })
+    }
+}
+
+impl<'p, B, H, C, L, V, T> LineEditorCore<'p, B, H, C, L, V, T>
+where
+    B: Buffer,
+    H: History,
+    C: Completer,
+    L: LinkState,
+    V: Validator,
+    T: Hinter,
+{
+    /// Use editor built with [`crate::builder::EditorBuilder`], reading
+    /// lines with `prompt`
+    pub fn from_editor(editor: Editor<B, H, C, L, V, T>, prompt: &'p str) -> Self {
+        Self { editor, prompt }
+    }
+
+    /// Start a line without waiting for input, probing the terminal or
+    /// printing the prompt. Starting is optional, as feeding input
+    /// starts a line too. With a line in progress, input typed while
+    /// probing is handled, so this returns like [`LineEditorCore::feed`].
+    pub fn start<S: FnMut(&[u8])>(&mut self, sink: &mut S) -> Result<PollResult<'_>, NolineError> {
+        let mut io = FeedIO { input: None, sink };
+
+        self.editor.readline_poll(self.prompt, &mut io)
+    }
+
+    /// Handle a byte of input, writing output to `sink`. Returns the
+    /// line when it's done, see [`Editor::readline_poll`]. The next
+    /// byte starts a new line.
+    pub fn feed<S: FnMut(&[u8])>(
+        &mut self,
+        byte: u8,
+        sink: &mut S,
+    ) -> Result<PollResult<'_>, NolineError> {
+        let mut io = FeedIO {
+            input: Some(byte),
+            sink,
+        };
+
+        self.editor.readline_poll(self.prompt, &mut io)
+    }
+
+    /// Change the prompt, used from the next line
+    pub fn set_prompt(&mut self, prompt: &'p str) {
+        self.prompt = prompt;
+    }
+
+    /// The underlying editor, e.g. for history and the last line
+    pub fn editor(&mut self) -> &mut Editor<B, H, C, L, V, T> {
+        &mut self.editor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::builder::EditorBuilder;
+    use crate::history::UnboundedHistory;
+    use crate::terminal::Cursor;
+    use crate::testlib::MockTerminal;
+
+    // Bytes pass between editor and terminal only through the sink and
+    // `feed`, like with a callback-driven host
+    #[test]
+    fn shuttle_bytes() {
+        let mut terminal = MockTerminal::new(4, 20, Cursor::new(1, 0));
+        let mut core =
+            LineEditorCore::new(LineBuffer::new_unbounded(), UnboundedHistory::new(), "> ")
+                .unwrap();
+        let mut input: VecDeque<u8> = VecDeque::new();
+        let mut lines = Vec::new();
+
+        // Terminal replies are fed before typed input
+        let mut sink = |bytes: &[u8]| {
+            for &byte in bytes {
+                if let Some(reply) = terminal.advance(byte) {
+                    input.extend(reply);
+                }
+            }
+        };
+
+        core.start(&mut sink).unwrap();
+
+        let mut typed: VecDeque<u8> = b"one\r\x1b[Atwo\x01\x06X\r".iter().copied().collect();
+
+        while let Some(byte) = input.pop_front().or_else(|| typed.pop_front()) {
+            let mut sink = |bytes: &[u8]| {
+                for &byte in bytes {
+                    if let Some(reply) = terminal.advance(byte) {
+                        input.extend(reply);
+                    }
+                }
+            };
+
+            if let PollResult::Done(line) = core.feed(byte, &mut sink).unwrap() {
+                lines.push(line.to_string());
+                core.start(&mut sink).unwrap();
+            }
+        }
+
+        assert_eq!(lines, ["one", "oXnetwo"]);
+        assert_eq!(terminal.screen_as_string(), "> one\n> oXnetwo\n> ");
+        assert_eq!(core.editor().get_history().count(), 2);
+    }
+
+    #[test]
+    fn from_editor() {
+        let mut output = Vec::new();
+        let mut sink = |bytes: &[u8]| output.extend_from_slice(bytes);
+        let mut io = FeedIO {
+            input: None,
+            sink: &mut sink,
+        };
+        let editor = EditorBuilder::new_unbounded()
+            .with_fixed_terminal_size(4, 20)
+            .build_sync(&mut io)
+            .unwrap();
+        let mut core = LineEditorCore::from_editor(editor, "$ ");
+
+        // No probe with a fixed size, so the line is done right away
+        for &byte in b"ab" {
+            assert_eq!(core.feed(byte, &mut sink).unwrap(), PollResult::Pending);
+        }
+
+        core.set_prompt("# ");
+
+        assert_eq!(core.feed(b'\r', &mut sink).unwrap(), PollResult::Done("ab"));
+        assert_eq!(core.feed(0x3, &mut sink).unwrap(), PollResult::Aborted);
+        assert_eq!(output, b"\r\x1b[J$ ab\n\r\r\x1b[J# \n\r");
+    }
+}
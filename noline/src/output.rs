@@ -1,189 +1,205 @@
-use core::marker::PhantomData;
+use core::{
+    fmt::{self, Write},
+    iter::Empty,
+    str::from_utf8,
+};
 
 use crate::{
-    core::Prompt,
+    caps::{Sequence, TerminalCaps, SEQUENCE_CAPACITY},
+    complete::{candidate_width, copy_candidate, Completer, NoCompleter},
+    core::{Echo, PromptParts, PromptSource},
+    hint::{copy_hint, Hinter, NoHinter},
     line_buffer::{Buffer, LineBuffer},
-    terminal::{Cursor, Position, Terminal},
+    strings::Strings,
+    terminal::{Cursor, Position, RightPrompt, Terminal},
+    utf8::{char_width, str_width},
 };
 
-#[cfg_attr(test, derive(Debug))]
+// Items yielded by the output iterator. `EndOfString`, `Abort` and
+// `Eof` end the line and must be the last item of an output.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum OutputItem<'a> {
     Slice(&'a [u8]),
-    UintToBytes(UintToBytes<4>),
+    Sequence(Sequence),
     EndOfString,
     Abort,
+    Eof,
 }
 
 impl<'a> OutputItem<'a> {
     pub fn get_bytes(&self) -> Option<&[u8]> {
         match self {
             Self::Slice(slice) => Some(slice),
-            Self::UintToBytes(uint) => Some(uint.as_bytes()),
-            Self::EndOfString | Self::Abort => None,
+            Self::Sequence(seq) => Some(seq.as_bytes()),
+            Self::EndOfString | Self::Abort | Self::Eof => None,
         }
     }
 }
 
-#[cfg_attr(test, derive(Debug))]
-#[derive(Copy, Clone)]
+// Most bytes copied by [`Output::fill_chunk`] at a time
+pub(crate) const WRITE_CHUNK_CAPACITY: usize = 64;
+
+// Where [`Output::fill_chunk`] continues: items copied so far and
+// bytes copied of the next
+#[derive(Default)]
+pub(crate) struct ChunkPosition {
+    item: usize,
+    offset: usize,
+}
+
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum CursorMove {
     Forward,
     Back,
     Start,
-    End,
+    Steps(isize),
 }
 
-#[cfg_attr(test, derive(Debug))]
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum OutputAction {
     Nothing,
     MoveCursor(CursorMove),
     ClearAndPrintPrompt,
     ClearAndPrintBuffer,
-    PrintBufferAndMoveCursorForward,
+    PrintBufferAndMoveCursorForward(usize),
+    PrintBufferEnd,
     EraseAfterCursor,
+    EraseLine,
     EraseAndPrintBuffer,
-    ClearScreen,
+    // Clear the screen and print the line at the top, with the cursor
+    // at char index
+    ClearScreen(usize),
     ClearLine,
     MoveCursorBackAndPrintBufferAndMoveForward,
     MoveCursorAndEraseAndPrintBuffer(isize),
+    // Print the search header in place of the prompt, followed by the
+    // matching history entry
+    ClearAndPrintSearch,
+    ClearAndPrintPromptAndBuffer,
+    PrintOverride(usize),
+    ClearOverride(usize, usize),
+    // Print the rest of the buffer, followed by the hint
+    PrintHint,
+    PrintRprompt,
+    // List the completion candidates for the line below it, ending
+    // with `...` if some don't fit, and print the line again with the
+    // cursor at the end
+    PrintList,
     RingBell,
+    // Ring the bell for an insertion that doesn't fit, and mark the end
+    // of the line if true
+    Overflow(bool),
     ProbeSize,
     Done,
     Abort,
+    Eof,
 }
 
-#[cfg_attr(test, derive(Debug))]
-#[derive(Copy, Clone)]
-pub struct UintToBytes<const N: usize> {
-    bytes: [u8; N],
-}
-
-impl<const N: usize> UintToBytes<N> {
-    fn from_uint<I: Into<usize>>(n: I) -> Option<Self> {
-        let mut n: usize = n.into();
-
-        if n < 10_usize.pow(N as u32) {
-            let mut bytes = [0; N];
-
-            for i in (0..N).rev() {
-                bytes[i] = 0x30 + (n % 10) as u8;
-                n /= 10;
-
-                if n == 0 {
-                    break;
-                }
-            }
-
-            Some(Self { bytes })
-        } else {
-            None
-        }
-    }
-
-    pub fn as_bytes(&self) -> &[u8] {
-        let start = self.bytes.iter().take_while(|&&b| b == 0).count();
-        &self.bytes[start..]
-    }
-}
-
-#[cfg_attr(test, derive(Debug))]
+#[derive(Debug)]
 enum MoveCursorState {
-    New,
-    ScrollPrefix,
     Scroll,
-    ScrollFinalByte,
-    MovePrefix,
-    Row,
-    Separator,
-    Column,
-    MoveFinalByte,
+    Move,
+    CarriageReturn,
+    Columns,
     Done,
 }
 
-#[cfg_attr(test, derive(Debug))]
+#[derive(Debug)]
 struct MoveCursor {
     state: MoveCursorState,
     cursor: Cursor,
     scroll: isize,
+    movement: Movement,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Movement {
+    Absolute,
+    // Relative to the current position, with `cursor.row` as the
+    // number of rows to move
+    Up,
+    Down,
 }
 
 impl MoveCursor {
     fn new(cursor: Cursor, scroll: isize) -> Self {
         Self {
-            state: MoveCursorState::New,
+            state: MoveCursorState::Scroll,
             cursor,
             scroll,
+            movement: Movement::Absolute,
+        }
+    }
+
+    fn relative(cursor: Cursor, scroll: isize, from_row: usize) -> Self {
+        Self {
+            cursor: Cursor::new(cursor.row.abs_diff(from_row), cursor.column),
+            movement: if cursor.row < from_row {
+                Movement::Up
+            } else {
+                Movement::Down
+            },
+            ..Self::new(cursor, scroll)
         }
     }
 }
 
-impl Iterator for MoveCursor {
-    type Item = OutputItem<'static>;
+impl MoveCursor {
+    fn next(&mut self, caps: &dyn TerminalCaps) -> Option<OutputItem<'static>> {
+        // Each state writes at most one sequence, built into an item in
+        // one place to keep the stack frame small
+        let mut seq = Sequence::new();
 
-    fn next(&mut self) -> Option<Self::Item> {
         loop {
             match self.state {
-                MoveCursorState::ScrollPrefix => {
-                    self.state = MoveCursorState::Scroll;
-                    break Some(OutputItem::Slice("\x1b[".as_bytes()));
-                }
                 MoveCursorState::Scroll => {
-                    self.state = MoveCursorState::ScrollFinalByte;
-
-                    break Some(OutputItem::UintToBytes(
-                        UintToBytes::from_uint(self.scroll.unsigned_abs()).unwrap(),
-                    ));
-                }
-                MoveCursorState::ScrollFinalByte => {
-                    self.state = MoveCursorState::MovePrefix;
+                    self.state = MoveCursorState::Move;
 
-                    break Some(OutputItem::Slice(if self.scroll > 0 {
-                        "S".as_bytes()
-                    } else {
-                        "T".as_bytes()
-                    }));
-                }
-                MoveCursorState::New => {
-                    if self.scroll != 0 {
-                        self.state = MoveCursorState::ScrollPrefix;
-                    } else {
-                        self.state = MoveCursorState::MovePrefix;
+                    match self.scroll {
+                        0 => continue,
+                        rows if rows > 0 => caps.scroll_up(&mut seq, rows as usize),
+                        rows => caps.scroll_down(&mut seq, rows.unsigned_abs()),
                     }
-                    continue;
-                }
-                MoveCursorState::MovePrefix => {
-                    self.state = MoveCursorState::Row;
-                    break Some(OutputItem::Slice("\x1b[".as_bytes()));
                 }
-                MoveCursorState::Row => {
-                    self.state = MoveCursorState::Separator;
-                    break Some(OutputItem::UintToBytes(
-                        UintToBytes::from_uint(self.cursor.row + 1).unwrap(),
-                    ));
-                }
-                MoveCursorState::Separator => {
-                    self.state = MoveCursorState::Column;
-                    break Some(OutputItem::Slice(";".as_bytes()));
-                }
-                MoveCursorState::Column => {
-                    self.state = MoveCursorState::MoveFinalByte;
+                MoveCursorState::Move => match self.movement {
+                    Movement::Absolute => {
+                        self.state = MoveCursorState::Done;
+                        caps.move_to(&mut seq, self.cursor.row, self.cursor.column);
+                    }
+                    movement => {
+                        self.state = MoveCursorState::CarriageReturn;
 
-                    break Some(OutputItem::UintToBytes(
-                        UintToBytes::from_uint(self.cursor.column + 1).unwrap(),
-                    ));
+                        match (self.cursor.row, movement) {
+                            (0, _) => continue,
+                            (rows, Movement::Up) => caps.move_up(&mut seq, rows),
+                            (rows, _) => caps.move_down(&mut seq, rows),
+                        }
+                    }
+                },
+                MoveCursorState::CarriageReturn => {
+                    self.state = MoveCursorState::Columns;
+                    break Some(OutputItem::Slice(caps.carriage_return()));
                 }
-                MoveCursorState::MoveFinalByte => {
+                MoveCursorState::Columns => {
                     self.state = MoveCursorState::Done;
-                    break Some(OutputItem::Slice("H".as_bytes()));
+
+                    match self.cursor.column {
+                        0 => continue,
+                        columns => caps.move_right(&mut seq, columns),
+                    }
                 }
                 MoveCursorState::Done => break None,
             }
+
+            break Some(OutputItem::Sequence(seq));
         }
     }
 }
 
-#[cfg_attr(test, derive(Debug))]
+#[derive(Debug)]
 enum MoveCursorToPosition {
     Position(Position),
     Move(MoveCursor),
@@ -198,10 +214,17 @@ impl MoveCursorToPosition {
         loop {
             match self {
                 MoveCursorToPosition::Position(position) => {
+                    let from_row = terminal.get_cursor().row;
                     let scroll = terminal.move_cursor(*position);
                     let cursor = terminal.get_cursor();
 
-                    *self = MoveCursorToPosition::Move(MoveCursor::new(cursor, scroll));
+                    // Scrolling doesn't move the cursor, so the row
+                    // moved from is the same after scrolling
+                    *self = MoveCursorToPosition::Move(if terminal.relative_movement() {
+                        MoveCursor::relative(cursor, scroll, from_row)
+                    } else {
+                        MoveCursor::new(cursor, scroll)
+                    });
                     continue;
                 }
                 MoveCursorToPosition::Move(move_cursor) => break Some(move_cursor),
@@ -210,27 +233,44 @@ impl MoveCursorToPosition {
     }
 }
 
+/// Style of text shown in place of the line, like hints
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Style {
+    Plain,
+    /// Reverse video, for a completion preview
+    Reverse,
+    /// Faint, for hints
+    Dim,
+}
+
+const SPACES: &str = "                ";
+
 enum PrintableItem<'a> {
     Str(&'a str),
+    // Spaces in place of a tab or newline
+    Spaces(&'static str),
     Newline,
+    // Escape sequence, not moving the cursor
+    Sgr(Style),
 }
 
 struct Printable<'a, I> {
     s: &'a str,
     newline: bool,
     iter: Option<I>,
+    style: Style,
+    // Style set, must be reset when done
+    styled: bool,
 }
 
-impl<'a, 'item, I> Printable<'a, I>
-where
-    I: Iterator<Item = &'item str>,
-    'item: 'a,
-{
+impl<'a, I: Iterator<Item = &'a str>> Printable<'a, I> {
     fn from_str(s: &'a str) -> Self {
         Self {
             s,
             newline: false,
             iter: None,
+            style: Style::Plain,
+            styled: false,
         }
     }
 
@@ -239,17 +279,43 @@ where
             s: "",
             newline: false,
             iter: Some(iter),
+            style: Style::Plain,
+            styled: false,
         }
     }
 
-    fn next_item(&mut self, max_chars: usize) -> Option<PrintableItem<'a>> {
+    fn with_style(mut self, style: Style) -> Self {
+        self.style = style;
+        self
+    }
+
+    fn next_item(&mut self, max_chars: usize, columns: usize) -> Option<PrintableItem<'a>> {
+        if self.style != Style::Plain && !self.styled {
+            self.styled = true;
+            return Some(PrintableItem::Sgr(self.style));
+        }
+
+        let item = self.next_text_item(max_chars, columns);
+
+        if item.is_none() && self.styled {
+            self.styled = false;
+            self.style = Style::Plain;
+            return Some(PrintableItem::Sgr(Style::Plain));
+        }
+
+        item
+    }
+
+    // Next text to print, at most `max_chars` columns wide
+    fn next_text_item(&mut self, max_chars: usize, columns: usize) -> Option<PrintableItem<'a>> {
         if self.newline {
             self.newline = false;
             Some(PrintableItem::Newline)
         } else {
+            // Empty parts are skipped, so no empty slices are output
             let s = if self.s.is_empty() {
                 if let Some(iter) = &mut self.iter {
-                    iter.next()?
+                    iter.find(|part| !part.is_empty())?
                 } else {
                     return None;
                 }
@@ -257,16 +323,54 @@ where
                 self.s
             };
 
-            let split_at_char = max_chars.min(s.chars().count());
-            let split_at_byte = s
-                .char_indices()
-                .nth(split_at_char)
-                .map(|(index, _)| index)
-                .unwrap_or(s.len());
+            // A tab is displayed as spaces up to the next tab stop,
+            // overwriting what was there, like a terminal would leave
+            // it after moving the cursor with the tab
+            if let Some(rest) = s.strip_prefix('\t') {
+                let width = tab_width(columns - max_chars, columns);
+
+                if width >= max_chars {
+                    self.newline = true
+                }
+
+                self.s = rest;
+                return Some(PrintableItem::Spaces(&SPACES[..width]));
+            }
+
+            // A newline is displayed as spaces filling the rest of the
+            // row, so the text after it starts on the next row
+            if let Some(rest) = s.strip_prefix('\n') {
+                if max_chars <= SPACES.len() {
+                    self.newline = true;
+                    self.s = rest;
+                    return Some(PrintableItem::Spaces(&SPACES[..max_chars]));
+                }
+
+                self.s = s;
+                return Some(PrintableItem::Spaces(SPACES));
+            }
+
+            let head = &s[..s.find(['\t', '\n']).unwrap_or(s.len())];
+
+            let split_at_byte = byte_position_in_columns(head, max_chars);
+
+            // A wide char doesn't fit in the last column, which is
+            // filled with a space to start the char on the next row.
+            // It's printed anyway if it's wider than the terminal.
+            if split_at_byte == 0 && max_chars < columns {
+                self.newline = true;
+                self.s = s;
+                return Some(PrintableItem::Spaces(&SPACES[..max_chars]));
+            }
+
+            let split_at_byte = match split_at_byte {
+                0 => head.chars().next().map_or(0, char::len_utf8),
+                split_at_byte => split_at_byte,
+            };
 
             let (s, rest) = s.split_at(split_at_byte);
 
-            if split_at_char == max_chars {
+            if str_width(s) >= max_chars {
                 self.newline = true
             }
 
@@ -276,31 +380,572 @@ where
     }
 }
 
+// Rows of candidates listed by [`OutputAction::PrintList`], leaving a
+// row for `...` and one for the line
+fn list_rows(terminal: &Terminal) -> usize {
+    terminal.rows().saturating_sub(2).max(1)
+}
+
+// Most candidates [`OutputAction::PrintList`] can show, reached when
+// they are all one column wide
+fn list_capacity(terminal: &Terminal) -> usize {
+    list_rows(terminal) * ((terminal.columns() + 1) / 3).max(1)
+}
+
+#[derive(Copy, Clone)]
+enum ListPhase {
+    Newline,
+    Word,
+    Candidate,
+    // Spaces up to the next column
+    Padding,
+    More,
+    Done,
+}
+
+// Candidates printed in columns below the line, each after the word
+// being completed. Rows are kept narrower than the terminal, so they
+// never wrap. Candidates are copied from the completer a chunk at a
+// time, and the cursor column tells how much room is left, keeping
+// [`Step`] small.
+struct CandidateList<'a, C: ?Sized> {
+    completer: &'a C,
+    line: &'a str,
+    // Candidate being printed, and bytes of it printed
+    index: u16,
+    offset: u16,
+    // Width of a column, two spaces apart
+    width: u16,
+    phase: ListPhase,
+}
+
+impl<'a, C: Completer + ?Sized> CandidateList<'a, C> {
+    fn new(completer: &'a C, line: &'a str, terminal: &Terminal) -> Self {
+        let max_width = terminal.columns().saturating_sub(1).max(1);
+        let word = line.rsplit(char::is_whitespace).next().unwrap_or("");
+        let word_width = str_width(&word[..byte_position_in_columns(word, max_width)]);
+
+        // Candidates past the capacity aren't shown
+        let width = (0..=list_capacity(terminal))
+            .map_while(|index| candidate_width(completer, line, index))
+            .map(|width| word_width + width)
+            .max()
+            .unwrap_or(0)
+            .clamp(1, max_width);
+
+        Self {
+            completer,
+            line,
+            index: 0,
+            offset: 0,
+            width: u16::try_from(width).unwrap_or(u16::MAX),
+            phase: ListPhase::Newline,
+        }
+    }
+
+    // Word being completed, cut off if too wide
+    fn word(&self) -> &'a str {
+        let word = self.line.rsplit(char::is_whitespace).next().unwrap_or("");
+
+        &word[..byte_position_in_columns(word, self.width.into())]
+    }
+
+    fn has_candidate(&self) -> bool {
+        self.index < u16::MAX
+            && copy_candidate(self.completer, self.line, self.index.into(), 0, &mut []).is_some()
+    }
+
+    // Column where the current candidate starts
+    fn column_start(&self, per_row: usize) -> usize {
+        usize::from(self.index) % per_row * (usize::from(self.width) + 2)
+    }
+
+    // Move the cursor past text printed on the row
+    fn move_right(terminal: &mut Terminal, columns: usize) {
+        let position = terminal.get_position();
+        terminal.move_cursor(Position::new(position.row, position.column + columns));
+    }
+
+    fn next(&mut self, terminal: &mut Terminal) -> Option<OutputItem<'a>> {
+        let width = usize::from(self.width);
+        let per_row = ((terminal.columns() + 1) / (width + 2)).max(1);
+
+        loop {
+            match self.phase {
+                ListPhase::Newline => {
+                    let position = terminal.get_position();
+                    terminal.move_cursor(Position::new(position.row + 1, 0));
+
+                    self.phase = if !self.has_candidate() {
+                        ListPhase::Done
+                    } else if usize::from(self.index) / per_row == list_rows(terminal) {
+                        ListPhase::More
+                    } else {
+                        ListPhase::Word
+                    };
+
+                    return Some(str_item("\n\r"));
+                }
+                ListPhase::Word => {
+                    let word = self.word();
+
+                    self.phase = ListPhase::Candidate;
+                    self.offset = 0;
+
+                    if !word.is_empty() {
+                        Self::move_right(terminal, str_width(word));
+                        return Some(str_item(word));
+                    }
+                }
+                ListPhase::Candidate => {
+                    let mut bytes = [0; SEQUENCE_CAPACITY];
+                    let len = match self.offset {
+                        u16::MAX => 0,
+                        offset => copy_candidate(
+                            self.completer,
+                            self.line,
+                            self.index.into(),
+                            offset.into(),
+                            &mut bytes,
+                        )
+                        .unwrap_or(0),
+                    };
+                    let chunk = valid_prefix(&bytes[..len]);
+
+                    let room = (self.column_start(per_row) + width)
+                        .saturating_sub(terminal.get_position().column);
+                    let s = &chunk[..byte_position_in_columns(chunk, room)];
+
+                    if s.is_empty() {
+                        self.index += 1;
+
+                        self.phase =
+                            if usize::from(self.index) % per_row == 0 || !self.has_candidate() {
+                                ListPhase::Newline
+                            } else {
+                                ListPhase::Padding
+                            };
+
+                        continue;
+                    }
+
+                    self.offset =
+                        u16::try_from(usize::from(self.offset) + s.len()).unwrap_or(u16::MAX);
+                    Self::move_right(terminal, str_width(s));
+
+                    let mut seq = Sequence::new();
+                    seq.push(s.as_bytes());
+                    return Some(OutputItem::Sequence(seq));
+                }
+                ListPhase::Padding => {
+                    let spaces = self
+                        .column_start(per_row)
+                        .saturating_sub(terminal.get_position().column);
+                    let len = spaces.min(SPACES.len());
+
+                    if len == spaces {
+                        self.phase = ListPhase::Word;
+                    }
+
+                    if len > 0 {
+                        Self::move_right(terminal, len);
+                        return Some(str_item(&SPACES[..len]));
+                    }
+                }
+                ListPhase::More => {
+                    let position = terminal.get_position();
+                    terminal.move_cursor(Position::new(position.row + 1, 0));
+
+                    self.phase = ListPhase::Done;
+
+                    return Some(str_item("...\n\r"));
+                }
+                ListPhase::Done => {
+                    // The line is printed again from here
+                    terminal.reset(terminal.get_cursor());
+
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+// Longest status shown by Ctrl-X =, see [`Strings::cursor_position`]
+const STATUS_CAPACITY: usize = 64;
+const STATUS_SEPARATOR: &str = "  ";
+
+// Writer copying formatted text from byte `offset` into `dest`, as
+// much as fits, and failing once the text is longer than
+// [`STATUS_CAPACITY`]
+struct StatusWindow<'d> {
+    offset: usize,
+    dest: &'d mut [u8],
+    len: usize,
+    copied: usize,
+}
+
+impl Write for StatusWindow<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let start = self.len;
+        self.len += s.len();
+
+        if self.len > STATUS_CAPACITY {
+            return Err(fmt::Error);
+        }
+
+        let skipped = self.offset.saturating_sub(start).min(s.len());
+        let rest = &s.as_bytes()[skipped..];
+        let len = rest.len().min(self.dest.len() - self.copied);
+
+        self.dest[self.copied..self.copied + len].copy_from_slice(&rest[..len]);
+        self.copied += len;
+
+        Ok(())
+    }
+}
+
+// Format the status shown by Ctrl-X = after the line, and copy it from
+// byte `offset` into `dest`. Returns the number of bytes copied, or an
+// error if the status is too long to show.
+pub(crate) fn format_status(
+    strings: &(dyn Strings + Sync),
+    column: usize,
+    len: usize,
+    c: Option<char>,
+    offset: usize,
+    dest: &mut [u8],
+) -> Result<usize, fmt::Error> {
+    let mut window = StatusWindow {
+        offset,
+        dest,
+        len: 0,
+        copied: 0,
+    };
+
+    window.write_str(STATUS_SEPARATOR)?;
+    strings.cursor_position(&mut window, column, len, c)?;
+
+    Ok(window.copied)
+}
+
+// Text shown in place of the end of the line without being in the
+// buffer, see [`OutputAction::PrintOverride`]. The text is copied
+// out a chunk at a time, so it needn't be in one piece.
+pub(crate) enum Overlay<'a, C: ?Sized, T: ?Sized> {
+    // Text printed as is, set by [`Output::with_text`]
+    Text(&'a str),
+    // History entry, in two parts where the history wraps around
+    Entry(&'a [u8], &'a [u8]),
+    // Header of a history search, followed by the matching entry. The
+    // header is printed on its own, so it isn't kept by
+    // [`OverlayPrinter`].
+    Search(&'a str, &'a [u8], &'a [u8]),
+    // Completion candidates for the line, the first is previewed and
+    // all are listed
+    Candidates(&'a C, &'a str),
+    // Hint for the line, see [`crate::hint::Hint::Text`]
+    Hint(&'a T, &'a str),
+    // Cursor column, line length and char under the cursor, shown by
+    // Ctrl-X =
+    Status(&'static (dyn Strings + Sync), u32, u32, Option<char>),
+}
+
+impl<C: ?Sized, T: ?Sized> Clone for Overlay<'_, C, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<C: ?Sized, T: ?Sized> Copy for Overlay<'_, C, T> {}
+
+impl<C: Completer + ?Sized, T: Hinter + ?Sized> Overlay<'_, C, T> {
+    // Copy the text from byte `offset` into `chunk`, cut off before a
+    // char that doesn't fit
+    fn chunk<'c>(&self, offset: usize, chunk: &'c mut [u8; SEQUENCE_CAPACITY]) -> &'c str {
+        let len = match *self {
+            Overlay::Text(text) => {
+                let text = text.as_bytes().get(offset..).unwrap_or(&[]);
+                let len = text.len().min(chunk.len());
+
+                chunk[..len].copy_from_slice(&text[..len]);
+                len
+            }
+            Overlay::Entry(slice1, slice2) | Overlay::Search(_, slice1, slice2) => {
+                let mut len = 0;
+                // Entries are cut at the first non-ASCII byte, as they
+                // would be in the buffer
+                let bytes = slice1.iter().chain(slice2).skip(offset);
+
+                for byte in bytes
+                    .take_while(|byte| !cfg!(feature = "ascii-only") || byte.is_ascii())
+                    .take(chunk.len())
+                {
+                    chunk[len] = *byte;
+                    len += 1;
+                }
+
+                len
+            }
+            Overlay::Candidates(completer, line) => {
+                copy_candidate(completer, line, 0, offset, chunk).unwrap_or(0)
+            }
+            Overlay::Hint(hinter, line) => copy_hint(hinter, line, offset, chunk),
+            Overlay::Status(strings, column, len, c) => {
+                format_status(strings, column as usize, len as usize, c, offset, chunk).unwrap_or(0)
+            }
+        };
+
+        valid_prefix(&chunk[..len])
+    }
+}
+
+// Text of a chunk, cut off before a char split between chunks
+fn valid_prefix(chunk: &[u8]) -> &str {
+    match from_utf8(chunk) {
+        Ok(s) => s,
+        Err(err) => from_utf8(&chunk[..err.valid_up_to()]).unwrap_or(""),
+    }
+}
+
+// State of the printable kept between chunks of an [`Overlay`]. The
+// offset is counted in bytes, and text past 64 KiB isn't printed.
+#[derive(Copy, Clone)]
+struct PrintState {
+    offset: u16,
+    newline: bool,
+    style: Style,
+    styled: bool,
+    // Cut off before the last column of the row, see
+    // [`OverlayPrinter::clipped`]
+    clip: bool,
+}
+
+// Printing an [`Overlay`] a chunk at a time. The state is kept in each
+// variant, next to the tag, so [`Step`] doesn't grow.
+enum OverlayPrinter<'a, C: ?Sized, T: ?Sized> {
+    Text(&'a str, PrintState),
+    Entry(&'a [u8], &'a [u8], PrintState),
+    Candidates(&'a C, &'a str, PrintState),
+    Hint(&'a T, &'a str, PrintState),
+    Status(
+        &'static (dyn Strings + Sync),
+        u32,
+        u32,
+        Option<char>,
+        PrintState,
+    ),
+}
+
+impl<'a, C: Completer + ?Sized, T: Hinter + ?Sized> OverlayPrinter<'a, C, T> {
+    fn new(overlay: Overlay<'a, C, T>, style: Style) -> Self {
+        let state = PrintState {
+            offset: 0,
+            newline: false,
+            style,
+            styled: false,
+            clip: false,
+        };
+
+        match overlay {
+            Overlay::Text(text) => Self::Text(text, state),
+            Overlay::Entry(slice1, slice2) | Overlay::Search(_, slice1, slice2) => {
+                Self::Entry(slice1, slice2, state)
+            }
+            Overlay::Candidates(completer, line) => Self::Candidates(completer, line, state),
+            Overlay::Hint(hinter, line) => Self::Hint(hinter, line, state),
+            Overlay::Status(strings, column, len, c) => {
+                Self::Status(strings, column, len, c, state)
+            }
+        }
+    }
+
+    // Print only what fits on the row of the cursor, leaving the last
+    // column free
+    fn clipped(mut self) -> Self {
+        self.parts().1.clip = true;
+        self
+    }
+
+    fn parts(&mut self) -> (Overlay<'a, C, T>, &mut PrintState) {
+        match self {
+            Self::Text(text, state) => (Overlay::Text(text), state),
+            Self::Entry(slice1, slice2, state) => (Overlay::Entry(slice1, slice2), state),
+            Self::Candidates(completer, line, state) => {
+                (Overlay::Candidates(*completer, line), state)
+            }
+            Self::Hint(hinter, line, state) => (Overlay::Hint(*hinter, line), state),
+            Self::Status(strings, column, len, c, state) => {
+                (Overlay::Status(*strings, *column, *len, *c), state)
+            }
+        }
+    }
+
+    fn next(&mut self, terminal: &mut Terminal) -> Option<OutputItem<'a>> {
+        let (overlay, state) = self.parts();
+        let offset = match state.offset {
+            u16::MAX => usize::MAX,
+            offset => offset.into(),
+        };
+        let mut bytes = [0; SEQUENCE_CAPACITY];
+        let mut chunk = overlay.chunk(offset, &mut bytes);
+        let mut clipped = false;
+
+        if state.clip {
+            let columns = terminal.columns_remaining().saturating_sub(1);
+            let end = byte_position_in_columns(chunk, columns);
+
+            clipped = end < chunk.len();
+            chunk = &chunk[..end];
+        }
+
+        let mut printable: Printable<'_, Empty<&str>> = Printable {
+            s: chunk,
+            newline: state.newline,
+            iter: None,
+            style: state.style,
+            styled: state.styled,
+        };
+
+        // Text is copied, as it doesn't outlive the chunk
+        let item = print_next(&mut printable, terminal, |s| {
+            let mut seq = Sequence::new();
+            seq.push(s.as_bytes());
+            OutputItem::Sequence(seq)
+        });
+
+        let printed = chunk.len() - printable.s.len();
+
+        // The rest of a clipped overlay is skipped once the row is full
+        state.offset = if clipped && printable.s.is_empty() {
+            u16::MAX
+        } else {
+            u16::try_from(offset.saturating_add(printed)).unwrap_or(u16::MAX)
+        };
+        state.newline = printable.newline;
+        state.style = printable.style;
+        state.styled = printable.styled;
+
+        item
+    }
+}
+
+fn str_item(s: &str) -> OutputItem<'_> {
+    OutputItem::Slice(s.as_bytes())
+}
+
+// Next output printing `printable`, keeping track of the cursor.
+// Returns None when all of it is printed. Text of the printable is
+// output as `text` makes it.
+fn print_next<'a, 'b, I: Iterator<Item = &'b str>>(
+    printable: &mut Printable<'b, I>,
+    terminal: &mut Terminal,
+    text: impl FnOnce(&'b str) -> OutputItem<'a>,
+) -> Option<OutputItem<'a>> {
+    if let RightPrompt::Overwritten = terminal.right_prompt() {
+        terminal.set_right_prompt(RightPrompt::Hidden);
+        return Some(OutputItem::Slice(terminal.caps().erase_below()));
+    }
+
+    let columns_remaining = terminal.columns_remaining();
+
+    if let Some(item) = printable.next_item(columns_remaining, terminal.columns()) {
+        let item = match item {
+            PrintableItem::Str(s) | PrintableItem::Spaces(s) => {
+                let len = str_width(s);
+
+                if let RightPrompt::Visible(column) = terminal.right_prompt() {
+                    if terminal.get_position().row == 0
+                        && terminal.get_cursor().column + len > column
+                    {
+                        terminal.set_right_prompt(if len < terminal.columns_remaining() {
+                            RightPrompt::Overwritten
+                        } else {
+                            RightPrompt::Hidden
+                        });
+                    }
+                }
+
+                let position = terminal.relative_position(len as isize);
+                terminal.move_cursor(position);
+
+                let offset = terminal.current_offset();
+
+                if offset > terminal.line_end() {
+                    terminal.set_line_end(offset);
+                }
+
+                match item {
+                    PrintableItem::Spaces(spaces) => OutputItem::Slice(spaces.as_bytes()),
+                    _ => text(s),
+                }
+            }
+            PrintableItem::Newline => OutputItem::Slice(b"\n\r"),
+            PrintableItem::Sgr(style) => OutputItem::Slice(terminal.caps().style(style)),
+        };
+
+        Some(item)
+    } else {
+        None
+    }
+}
+
+// Printing right-side prompt without moving the cursor
+#[derive(Copy, Clone)]
+enum RpromptPhase {
+    Save,
+    Move,
+    Text,
+    Restore,
+}
+
 // #[cfg_attr(test, derive(Debug))]
-enum Step<'a, I> {
-    Print(Printable<'a, I>),
+enum Step<'a, P: ?Sized, C: ?Sized = NoCompleter, T: ?Sized = NoHinter> {
+    Print(Printable<'a, PromptParts<'a, P>>),
+    // Print text, then move the cursor to position
+    PrintAndMove(Printable<'a, Empty<&'a str>>, Position),
+    // Print parts as is, moving the cursor by width
+    PrintRaw(PromptParts<'a, P>, usize),
     Move(MoveCursorToPosition),
     MoveCursorToEdge,
     GetPosition,
     SavePosition,
     RestorePosition,
+    // Enable or disable bracketed paste
+    BracketedPaste(bool),
     ClearLine,
-    Erase,
+    // Erase to the end of the screen, after `ClearLine`
+    EraseBelow,
+    // Erase to the end of the row, after `ClearLine` with padded erase
+    EraseRow,
+    Erase(&'a str),
+    // Overwrite chars with spaces in place of erasing, then move back
+    // to position
+    Pad {
+        remaining: usize,
+        position: Position,
+        newline: bool,
+    },
+    Rprompt(&'a str, RpromptPhase),
+    List(CandidateList<'a, C>),
+    PrintOverlay(OverlayPrinter<'a, C, T>),
     Newline,
     Bell,
     EndOfString,
     Abort,
+    Eof,
     Done,
 }
 
-impl<'a, 'item, I> Step<'a, I>
+impl<'a, 'item: 'a, P, C, T> Step<'a, P, C, T>
 where
-    I: Iterator<Item = &'item str>,
-    'item: 'a,
+    P: PromptSource<'item> + ?Sized,
+    C: Completer + ?Sized,
+    T: Hinter + ?Sized,
 {
     fn transition(
         &mut self,
-        new_state: Step<'a, I>,
+        new_state: Step<'a, P, C, T>,
         output: OutputItem<'a>,
     ) -> Option<OutputItem<'a>> {
         *self = new_state;
@@ -310,26 +955,52 @@ where
     fn advance(&mut self, terminal: &mut Terminal) -> Option<OutputItem<'a>> {
         match self {
             Print(printable) => {
-                if let Some(item) = printable.next_item(terminal.columns_remaining()) {
-                    let s = match item {
-                        PrintableItem::Str(s) => {
-                            let position = terminal.relative_position(s.chars().count() as isize);
-                            terminal.move_cursor(position);
+                let item = print_next(printable, terminal, str_item);
 
-                            s
-                        }
-                        PrintableItem::Newline => "\n\r",
-                    };
+                if item.is_none() {
+                    *self = Step::Done;
+                }
+
+                item
+            }
+            PrintAndMove(printable, position) => {
+                if let Some(item) = print_next(printable, terminal, str_item) {
+                    return Some(item);
+                }
+
+                *self = Move(MoveCursorToPosition::new(*position));
+                self.advance(terminal)
+            }
+            PrintRaw(parts, width) => {
+                for part in parts.by_ref() {
+                    if !part.is_empty() {
+                        return Some(OutputItem::Slice(part.as_bytes()));
+                    }
+                }
+
+                let width = *width;
+                let position = terminal.relative_position(width as isize);
+                terminal.move_cursor(position);
+
+                let offset = terminal.current_offset();
+
+                if offset > terminal.line_end() {
+                    terminal.set_line_end(offset);
+                }
 
-                    Some(OutputItem::Slice(s.as_bytes()))
+                // Wrap like Print when the last column was reached
+                if width > 0 && terminal.get_cursor().column == 0 {
+                    self.transition(Step::Done, OutputItem::Slice(b"\n\r"))
                 } else {
                     *self = Step::Done;
                     None
                 }
             }
             Move(pos) => {
+                let caps = terminal.caps();
+
                 if let Some(move_cursor) = pos.get_move_cursor(terminal) {
-                    if let Some(byte) = move_cursor.next() {
+                    if let Some(byte) = move_cursor.next(caps) {
                         return Some(byte);
                     }
                 }
@@ -337,8 +1008,114 @@ where
                 *self = Step::Done;
                 None
             }
-            MoveCursorToEdge => self.transition(Step::Done, OutputItem::Slice(b"\x1b[999;999H")),
-            Erase => self.transition(Step::Done, OutputItem::Slice("\x1b[J".as_bytes())),
+            MoveCursorToEdge => {
+                let mut seq = Sequence::new();
+                terminal.caps().move_to(&mut seq, 998, 998);
+
+                self.transition(Step::Done, OutputItem::Sequence(seq))
+            }
+            Erase(_) if terminal.padded_erase() => {
+                let offset = terminal.current_offset();
+                let remaining = terminal.line_end() - offset;
+
+                if remaining <= 0 {
+                    *self = Step::Done;
+                    return None;
+                }
+
+                terminal.set_line_end(offset);
+
+                *self = Pad {
+                    remaining: remaining as usize,
+                    position: terminal.get_position(),
+                    newline: false,
+                };
+
+                self.advance(terminal)
+            }
+            Erase(rprompt) => {
+                // Erasing from the first row also erases the right-side
+                // prompt, so print it again
+                let new_state = match terminal.right_prompt() {
+                    RightPrompt::Visible(column) if terminal.get_position().row == 0 => {
+                        if terminal.get_cursor().column < column {
+                            Rprompt(rprompt, RpromptPhase::Save)
+                        } else {
+                            terminal.set_right_prompt(RightPrompt::Hidden);
+                            Step::Done
+                        }
+                    }
+                    _ => Step::Done,
+                };
+
+                self.transition(new_state, OutputItem::Slice(terminal.caps().erase_below()))
+            }
+            Pad {
+                remaining,
+                position,
+                newline,
+            } => {
+                if *newline {
+                    *newline = false;
+                    return Some(OutputItem::Slice(b"\n\r"));
+                }
+
+                if *remaining == 0 {
+                    *self = Move(MoveCursorToPosition::new(*position));
+                    return self.advance(terminal);
+                }
+
+                let columns_remaining = terminal.columns_remaining();
+                let len = (*remaining).min(columns_remaining).min(SPACES.len());
+
+                // Full row, continue on the next like printed text
+                *newline = len == columns_remaining;
+                *remaining -= len;
+
+                let next = terminal.relative_position(len as isize);
+                terminal.move_cursor(next);
+
+                Some(OutputItem::Slice(&SPACES.as_bytes()[..len]))
+            }
+            Rprompt(rprompt, phase) => {
+                let rprompt = *rprompt;
+
+                let cursor = match terminal.right_prompt() {
+                    RightPrompt::Visible(column) => {
+                        terminal.position_to_cursor(Position::new(0, column))
+                    }
+                    _ => None,
+                };
+
+                let Some(cursor) = cursor else {
+                    terminal.set_right_prompt(RightPrompt::Hidden);
+                    *self = Step::Done;
+                    return None;
+                };
+
+                let caps = terminal.caps();
+
+                let (phase, item) = match phase {
+                    RpromptPhase::Save => {
+                        (RpromptPhase::Move, OutputItem::Slice(caps.save_cursor()))
+                    }
+                    RpromptPhase::Move => {
+                        let mut seq = Sequence::new();
+                        caps.move_to(&mut seq, cursor.row, cursor.column);
+
+                        (RpromptPhase::Text, OutputItem::Sequence(seq))
+                    }
+                    RpromptPhase::Text => {
+                        (RpromptPhase::Restore, OutputItem::Slice(rprompt.as_bytes()))
+                    }
+                    RpromptPhase::Restore => {
+                        return self
+                            .transition(Step::Done, OutputItem::Slice(caps.restore_cursor()))
+                    }
+                };
+
+                self.transition(Rprompt(rprompt, phase), item)
+            }
             Newline => {
                 let mut position = terminal.get_position();
                 position.row += 1;
@@ -347,44 +1124,116 @@ where
 
                 self.transition(Step::Done, OutputItem::Slice("\n\r".as_bytes()))
             }
-            Bell => self.transition(Step::Done, OutputItem::Slice("\x07".as_bytes())),
+            PrintOverlay(printer) => {
+                let item = printer.next(terminal);
+
+                if item.is_none() {
+                    *self = Step::Done;
+                }
+
+                item
+            }
+            List(list) => {
+                let item = list.next(terminal);
+
+                if item.is_none() {
+                    *self = Step::Done;
+                }
+
+                item
+            }
+            Bell => self.transition(Step::Done, OutputItem::Slice(terminal.caps().bell())),
             EndOfString => self.transition(Step::Done, OutputItem::EndOfString),
             Abort => self.transition(Step::Done, OutputItem::Abort),
+            Eof => self.transition(Step::Done, OutputItem::Eof),
             ClearLine => {
                 terminal.move_cursor_to_start_of_line();
+                terminal.set_right_prompt(RightPrompt::Hidden);
+
+                // Length of the old content is unknown, so it can't be
+                // overwritten
+                let new_state = if terminal.padded_erase() {
+                    EraseRow
+                } else {
+                    EraseBelow
+                };
 
-                self.transition(Step::Done, OutputItem::Slice("\r\x1b[J".as_bytes()))
+                self.transition(
+                    new_state,
+                    OutputItem::Slice(terminal.caps().carriage_return()),
+                )
+            }
+            EraseBelow => {
+                self.transition(Step::Done, OutputItem::Slice(terminal.caps().erase_below()))
+            }
+            EraseRow => match terminal.caps().erase_line() {
+                [] => {
+                    *self = Step::Done;
+                    None
+                }
+                seq => self.transition(Step::Done, OutputItem::Slice(seq)),
+            },
+            GetPosition => self.transition(
+                Step::Done,
+                OutputItem::Slice(terminal.caps().request_position()),
+            ),
+            SavePosition => {
+                self.transition(Step::Done, OutputItem::Slice(terminal.caps().save_cursor()))
+            }
+            RestorePosition => self.transition(
+                Step::Done,
+                OutputItem::Slice(terminal.caps().restore_cursor()),
+            ),
+            BracketedPaste(enabled) => {
+                let enabled = *enabled;
+                self.transition(
+                    Step::Done,
+                    OutputItem::Slice(terminal.caps().bracketed_paste(enabled)),
+                )
             }
-            GetPosition => self.transition(Step::Done, OutputItem::Slice("\x1b[6n".as_bytes())),
-            SavePosition => self.transition(Step::Done, OutputItem::Slice(b"\x1b7")),
-            RestorePosition => self.transition(Step::Done, OutputItem::Slice(b"\x1b8")),
             Done => None,
         }
     }
+
+    fn is_echoed(&self, echo: Echo) -> bool {
+        match self {
+            // Bracketed paste must be disabled when the line is done
+            Newline | BracketedPaste(_) | EndOfString | Abort | Eof | Done => true,
+            Print(_) => !matches!(echo, Echo::None),
+            _ => matches!(echo, Echo::Full | Echo::Local),
+        }
+    }
 }
 
 use Step::*;
 
-pub struct OutputIter<'a, 'item, I> {
+pub struct OutputIter<'a, P: ?Sized, C: ?Sized = NoCompleter, T: ?Sized = NoHinter> {
     terminal: &'a mut Terminal,
-    steps: [Option<Step<'a, I>>; 4],
-    pos: usize,
-    _marker: PhantomData<&'item ()>,
+    steps: [Option<Step<'a, P, C, T>>; 4],
+    pos: u8,
+    echo: Echo,
 }
 
-impl<'a, 'item, I> Iterator for OutputIter<'a, 'item, I>
+impl<'a, 'item: 'a, P, C, T> Iterator for OutputIter<'a, P, C, T>
 where
-    I: Iterator<Item = &'item str>,
-    'item: 'a,
+    P: PromptSource<'item> + ?Sized,
+    C: Completer + ?Sized,
+    T: Hinter + ?Sized,
 {
     type Item = OutputItem<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
-            if let Some(step) = self.steps.get_mut(self.pos) {
+            if let Some(step) = self.steps.get_mut(self.pos as usize) {
                 if let Some(step) = step.as_mut() {
+                    let echo = step.is_echoed(self.echo);
+
                     if let Some(item) = step.advance(self.terminal) {
-                        break Some(item);
+                        // Steps not echoed are still advanced to keep
+                        // the terminal position up to date
+                        if echo {
+                            break Some(item);
+                        }
                     } else {
                         self.pos += 1;
                     }
@@ -398,7 +1247,90 @@ where
     }
 }
 
-fn byte_position(s: &str, char_pos: usize) -> usize {
+// Columns between tab stops
+pub(crate) const TAB_STOP: usize = 8;
+
+// Cells taken by a tab at cell `offset`, reaching the next tab stop or
+// the end of the row
+fn tab_width(offset: usize, columns: usize) -> usize {
+    let column = offset % columns;
+
+    (TAB_STOP - column % TAB_STOP).min(columns - column)
+}
+
+// Offset in cells from the start of the line to char `char_index` of
+// `s`, printed after a prompt of `start` cells. A newline fills the
+// rest of its row, a tab reaches the next tab stop, wide chars take
+// two cells, and chars after the end take one cell each.
+pub(crate) fn cell_offset(s: &str, start: usize, columns: usize, char_index: usize) -> usize {
+    let mut offset = start;
+    let mut len = 0;
+
+    for c in s.chars() {
+        if len == char_index {
+            return offset + usize::from(wraps_early(offset, c, columns));
+        }
+
+        offset = next_cell_offset(offset, c, columns);
+        len += 1;
+    }
+
+    offset + (char_index - len)
+}
+
+// Index of the char at cell `offset`, the inverse of [`cell_offset`].
+// The cells filled by a newline or a tab belong to it, as does the cell left
+// empty before a wide char wrapped to the next row, and cells after
+// the end count as one char each.
+pub(crate) fn char_index(s: &str, start: usize, columns: usize, offset: usize) -> usize {
+    let mut cell = start;
+    let mut len = 0;
+
+    for c in s.chars() {
+        let next = next_cell_offset(cell, c, columns);
+
+        if offset < next {
+            return len;
+        }
+
+        cell = next;
+        len += 1;
+    }
+
+    len + offset.saturating_sub(cell)
+}
+
+fn next_cell_offset(offset: usize, c: char, columns: usize) -> usize {
+    match c {
+        '\n' => offset + columns - offset % columns,
+        '\t' => offset + tab_width(offset, columns),
+        _ => offset + usize::from(wraps_early(offset, c, columns)) + char_width(c),
+    }
+}
+
+// True if `c` is a wide char reaching the last column of a row, and
+// is printed at the start of the next row instead
+fn wraps_early(offset: usize, c: char, columns: usize) -> bool {
+    char_width(c) > 1 && columns > 1 && offset % columns == columns - 1
+}
+
+// Byte index of the end of the longest prefix of `s` fitting in
+// `columns` columns
+pub(crate) fn byte_position_in_columns(s: &str, columns: usize) -> usize {
+    let mut width = 0;
+
+    for (pos, c) in s.char_indices() {
+        width += char_width(c);
+
+        if width > columns {
+            return pos;
+        }
+    }
+
+    s.len()
+}
+
+pub(crate) fn byte_position(s: &str, char_pos: usize) -> usize {
     s.char_indices()
         .skip(char_pos)
         .map(|(pos, _)| pos)
@@ -406,20 +1338,29 @@ fn byte_position(s: &str, char_pos: usize) -> usize {
         .unwrap_or(s.len())
 }
 
-pub struct Output<'a, B: Buffer, I> {
-    prompt: &'a Prompt<I>,
+pub struct Output<'a, B: Buffer, P: ?Sized, C: ?Sized = NoCompleter, T: ?Sized = NoHinter> {
+    prompt: &'a P,
     buffer: &'a LineBuffer<B>,
     terminal: &'a mut Terminal,
     action: OutputAction,
+    echo: Echo,
+    text: Overlay<'a, C, T>,
+    style: Style,
+    rprompt: &'a str,
+    bracketed_paste: bool,
+    // Insertion rejected without [`OutputAction::Overflow`]
+    overflow: bool,
 }
 
-impl<'a, 'item, B, I> Output<'a, B, I>
+impl<'a, 'item: 'a, B, P, C, T> Output<'a, B, P, C, T>
 where
     B: Buffer,
-    I: Iterator<Item = &'item str> + Clone,
+    P: PromptSource<'item> + ?Sized,
+    C: Completer + ?Sized,
+    T: Hinter + ?Sized,
 {
     pub fn new(
-        prompt: &'a Prompt<I>,
+        prompt: &'a P,
         buffer: &'a LineBuffer<B>,
         terminal: &'a mut Terminal,
         action: OutputAction,
@@ -429,11 +1370,235 @@ where
             buffer,
             terminal,
             action,
+            echo: Echo::Full,
+            text: Overlay::Text(""),
+            style: Style::Plain,
+            rprompt: "",
+            bracketed_paste: false,
+            overflow: false,
+        }
+    }
+
+    pub fn with_echo(mut self, echo: Echo) -> Self {
+        self.echo = echo;
+        self
+    }
+
+    // Mark output of a rejected insertion, for output other than
+    // [`OutputAction::Overflow`]
+    pub fn with_overflow(mut self, overflow: bool) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    // True if an insertion was rejected by a full buffer
+    pub fn is_overflow(&self) -> bool {
+        self.overflow || matches!(self.action, OutputAction::Overflow(_))
+    }
+
+    #[cfg(feature = "defmt")]
+    pub(crate) fn action(&self) -> OutputAction {
+        self.action
+    }
+
+    // Text printed by [`OutputAction::ClearAndPrintSearch`] and
+    // [`OutputAction::PrintOverride`]
+    pub fn with_text(mut self, text: &'a str) -> Self {
+        self.text = Overlay::Text(text);
+        self
+    }
+
+    // Text printed by [`OutputAction::PrintOverride`] that isn't a
+    // string, replacing the text set by [`Output::with_text`]
+    pub(crate) fn with_overlay(mut self, overlay: Overlay<'a, C, T>) -> Self {
+        self.text = overlay;
+        self
+    }
+
+    // Style of text printed by [`OutputAction::PrintOverride`]
+    pub fn with_style(mut self, style: Style) -> Self {
+        self.style = style;
+        self
+    }
+
+    // Right-side prompt, printed by [`OutputAction::PrintRprompt`] and
+    // after erasing the first row
+    pub fn with_rprompt(mut self, rprompt: &'a str) -> Self {
+        self.rprompt = rprompt;
+        self
+    }
+
+    // Enable bracketed paste with the prompt and disable it when the
+    // line is done
+    pub fn with_bracketed_paste(mut self, enabled: bool) -> Self {
+        self.bracketed_paste = enabled;
+        self
+    }
+
+    pub(crate) fn terminal(&self) -> &Terminal {
+        self.terminal
+    }
+
+    // Output borrowing this one, to iterate it more than once
+    fn reborrow(&mut self) -> Output<'_, B, P, C, T> {
+        Output {
+            prompt: self.prompt,
+            buffer: self.buffer,
+            terminal: self.terminal,
+            action: self.action,
+            echo: self.echo,
+            text: self.text,
+            style: self.style,
+            rprompt: self.rprompt,
+            bracketed_paste: self.bracketed_paste,
+            overflow: self.overflow,
+        }
+    }
+
+    // Copy output from `position` into `chunk`, calling `ended` for
+    // items ending the line. The output is iterated from the start with
+    // the terminal reset to `start`, so no iterator is kept between
+    // chunks, e.g. while a chunk is written. Returns the number of
+    // bytes copied, which is 0 once all output is copied and the
+    // terminal is up to date.
+    pub(crate) fn fill_chunk(
+        &mut self,
+        start: &Terminal,
+        position: &mut ChunkPosition,
+        chunk: &mut [u8],
+        mut ended: impl FnMut(&OutputItem<'_>),
+    ) -> usize {
+        self.terminal.clone_from(start);
+
+        let mut len = 0;
+
+        for item in self.reborrow().into_iter().skip(position.item) {
+            let bytes = match item.get_bytes() {
+                Some(bytes) => bytes,
+                None => {
+                    ended(&item);
+                    &[]
+                }
+            };
+            let copied = (bytes.len() - position.offset).min(chunk.len() - len);
+
+            chunk[len..len + copied].copy_from_slice(&bytes[position.offset..][..copied]);
+            len += copied;
+
+            if position.offset + copied < bytes.len() {
+                position.offset += copied;
+                break;
+            }
+
+            position.item += 1;
+            position.offset = 0;
+
+            if len == chunk.len() {
+                break;
+            }
+        }
+
+        len
+    }
+
+    // Step printing the prompt
+    fn prompt_step(&self) -> Step<'a, P, C, T> {
+        if self.prompt.has_display_len() {
+            PrintRaw(PromptParts::new(self.prompt), self.prompt.len())
+        } else {
+            Print(Printable::from_iter(PromptParts::new(self.prompt)))
+        }
+    }
+
+    // Step enabling or disabling bracketed paste, if used
+    fn bracketed_paste_step(&self, enable: bool) -> Step<'a, P, C, T> {
+        if self.bracketed_paste {
+            BracketedPaste(enable)
+        } else {
+            Done
         }
     }
 
+    // Step printing the text of [`OutputAction::PrintOverride`]
+    fn override_step(&self) -> Step<'a, P, C, T> {
+        match self.text {
+            Overlay::Text(text) => Print(Printable::from_str(text).with_style(self.style)),
+            overlay => PrintOverlay(OverlayPrinter::new(overlay, self.style)),
+        }
+    }
+
+    // Text set by [`Output::with_text`], or the header of a search
+    fn text(&self) -> &'a str {
+        match self.text {
+            Overlay::Text(text) | Overlay::Search(text, _, _) => text,
+            _ => "",
+        }
+    }
+
+    // Ring the bell and mark the end of the line. The mark is erased
+    // with the rest of the line when it is edited. Kept out of
+    // `into_iter`, where every action adds to the stack frame in
+    // unoptimized builds.
+    fn overflow_steps(&self) -> [Option<Step<'a, P, C, T>>; 4] {
+        [
+            Some(Bell),
+            Some(Move(MoveCursorToPosition::new(
+                self.char_position(self.buffer.as_str().chars().count()),
+            ))),
+            Some(Print(Printable::from_str("!"))),
+            Some(Move(MoveCursorToPosition::new(
+                self.terminal.get_position(),
+            ))),
+        ]
+    }
+
+    // Print the buffer from the cursor to the end, then the hint, and
+    // move back to the start of the hint.
+    // The hint is cut off before the end of the row, so it never
+    // wraps, and can't scroll the line away from the cursor.
+    fn hint_steps(&self) -> [Option<Step<'a, P, C, T>>; 4] {
+        let line = self.buffer_after_position(self.terminal.get_position());
+        let position = self.char_position(self.buffer.as_str().chars().count());
+
+        [
+            Some(Print(Printable::from_str(line))),
+            Some(PrintOverlay(
+                OverlayPrinter::new(self.text, self.style).clipped(),
+            )),
+            Some(Move(MoveCursorToPosition::new(position))),
+            None,
+        ]
+    }
+
+    // Move to the end of the line and list the candidates from the
+    // completer. The line is printed again below them.
+    fn list_steps(&self) -> [Option<Step<'a, P, C, T>>; 4] {
+        let line = self.buffer.as_str();
+        let list = match self.text {
+            Overlay::Candidates(completer, line) => {
+                List(CandidateList::new(completer, line, self.terminal))
+            }
+            _ => Done,
+        };
+
+        [
+            Some(Move(MoveCursorToPosition::new(
+                self.char_position(line.chars().count()),
+            ))),
+            Some(list),
+            Some(self.prompt_step()),
+            Some(Print(Printable::from_str(line))),
+        ]
+    }
+
+    // Index of the char at position
     fn offset_from_position(&self, position: Position) -> usize {
-        self.terminal.offset_from_position(position) as usize - self.prompt.len()
+        char_index(
+            self.buffer.as_str(),
+            self.prompt.len(),
+            self.terminal.columns(),
+            self.terminal.offset_from_position(position) as usize,
+        )
     }
 
     fn current_offset(&self) -> usize {
@@ -449,24 +1614,35 @@ where
         &s[pos..]
     }
 
-    fn new_position(&self, cursor_move: CursorMove) -> Position {
+    // Position of char in buffer
+    fn char_position(&self, char_index: usize) -> Position {
+        let offset = cell_offset(
+            self.buffer.as_str(),
+            self.prompt.len(),
+            self.terminal.columns(),
+            char_index,
+        );
+
+        self.terminal
+            .relative_position(offset as isize - self.terminal.current_offset())
+    }
+
+    // Char index the cursor is moved to, possibly outside the buffer
+    fn new_index(&self, cursor_move: CursorMove) -> isize {
+        let pos = self.current_offset() as isize;
+
         match cursor_move {
-            CursorMove::Forward => self.terminal.relative_position(1),
-            CursorMove::Back => self.terminal.relative_position(-1),
-            CursorMove::Start => {
-                let pos = self.current_offset() as isize;
-                self.terminal.relative_position(-pos)
-            }
-            CursorMove::End => {
-                let pos = self.current_offset() as isize;
-                let len = self.buffer.as_str().chars().count() as isize;
-                #[cfg(test)]
-                dbg!(pos, len);
-                self.terminal.relative_position(len - pos)
-            }
+            CursorMove::Forward => pos + 1,
+            CursorMove::Back => pos - 1,
+            CursorMove::Start => 0,
+            CursorMove::Steps(steps) => pos + steps,
         }
     }
 
+    fn new_position(&self, cursor_move: CursorMove) -> Position {
+        self.char_position(self.new_index(cursor_move).max(0) as usize)
+    }
+
     #[cfg(test)]
     pub fn into_vec(self) -> Vec<u8> {
         self.into_iter()
@@ -475,14 +1651,15 @@ where
     }
 }
 
-impl<'a, 'item, B, I> IntoIterator for Output<'a, B, I>
+impl<'a, 'item: 'a, B, P, C, T> IntoIterator for Output<'a, B, P, C, T>
 where
     B: Buffer,
-    I: Iterator<Item = &'item str> + Clone,
-    'item: 'a,
+    P: PromptSource<'item> + ?Sized,
+    C: Completer + ?Sized,
+    T: Hinter + ?Sized,
 {
     type Item = OutputItem<'a>;
-    type IntoIter = OutputIter<'a, 'item, I>;
+    type IntoIter = OutputIter<'a, P, C, T>;
 
     fn into_iter(self) -> Self::IntoIter {
         fn pack<T, const IN: usize, const OUT: usize>(array: [T; IN]) -> [Option<T>; OUT] {
@@ -490,7 +1667,7 @@ where
                 assert!(IN <= OUT);
             }
 
-            let mut steps = [(); OUT].map(|()| None);
+            let mut steps = [const { None }; OUT];
 
             for (i, step) in array.into_iter().enumerate() {
                 steps[i] = Some(step);
@@ -501,13 +1678,10 @@ where
 
         let steps = match self.action {
             OutputAction::MoveCursor(cursor_move) => {
-                let position = self.new_position(cursor_move);
-
-                let offset =
-                    self.terminal.offset_from_position(position) - self.prompt.len() as isize;
+                let index = self.new_index(cursor_move);
                 let buffer_len = self.buffer.as_str().chars().count() as isize;
 
-                if offset >= 0 && offset <= buffer_len {
+                if index >= 0 && index <= buffer_len {
                     pack([Move(MoveCursorToPosition::new(
                         self.new_position(cursor_move),
                     ))])
@@ -515,46 +1689,63 @@ where
                     pack([Bell])
                 }
             }
-            OutputAction::PrintBufferAndMoveCursorForward => pack([
+            OutputAction::PrintBufferAndMoveCursorForward(steps) => pack([
                 Print(Printable::from_str(
                     self.buffer_after_position(self.terminal.get_position()),
                 )),
                 Move(MoveCursorToPosition::new(
-                    self.terminal.relative_position(1),
+                    self.new_position(CursorMove::Steps(steps as isize)),
                 )),
             ]),
-            OutputAction::EraseAfterCursor => pack([Erase]),
+            // The cursor ends up after the printed text, so no move is
+            // needed
+            OutputAction::PrintBufferEnd => pack([Print(Printable::from_str(
+                self.buffer_after_position(self.terminal.get_position()),
+            ))]),
+            OutputAction::EraseAfterCursor => pack([Erase(self.rprompt)]),
+            OutputAction::EraseLine => pack([
+                Move(MoveCursorToPosition::new(Position::new(0, 0))),
+                Erase(self.rprompt),
+            ]),
             OutputAction::EraseAndPrintBuffer => {
                 let position = self.terminal.get_position();
 
                 pack([
-                    Erase,
+                    Erase(self.rprompt),
                     Print(Printable::from_str(self.buffer_after_position(position))),
                     Move(MoveCursorToPosition::new(position)),
                 ])
             }
 
-            OutputAction::ClearScreen => {
-                let rows = self.terminal.scroll_to_top();
-                self.terminal.move_cursor(Position::new(0, 0));
+            OutputAction::ClearScreen(cursor) => {
+                // The screen can't be cleared without moving the cursor
+                // to an absolute position, so only the line is printed
+                // again
+                let position = self.char_position(cursor);
+                let start = if self.terminal.relative_movement() {
+                    MoveCursorToPosition::new(Position::new(0, 0))
+                } else {
+                    let rows = self.terminal.scroll_to_top();
+                    self.terminal.move_cursor(Position::new(0, 0));
+
+                    MoveCursorToPosition::Move(MoveCursor::new(Cursor::new(0, 0), rows))
+                };
 
                 pack([
-                    Move(MoveCursorToPosition::Move(MoveCursor::new(
-                        Cursor::new(0, 0),
-                        rows,
-                    ))),
-                    Erase,
-                    Print(Printable::from_iter(self.prompt.iter())),
+                    Move(start),
+                    Erase(self.rprompt),
+                    self.prompt_step(),
+                    PrintAndMove(Printable::from_str(self.buffer.as_str()), position),
                 ])
             }
             OutputAction::ClearLine => pack([
                 Move(MoveCursorToPosition::new(
                     self.new_position(CursorMove::Start),
                 )),
-                Erase,
+                Erase(self.rprompt),
             ]),
             OutputAction::MoveCursorBackAndPrintBufferAndMoveForward => {
-                let position = self.terminal.relative_position(-1);
+                let position = self.new_position(CursorMove::Back);
 
                 pack([
                     Move(MoveCursorToPosition::new(position)),
@@ -563,27 +1754,95 @@ where
                 ])
             }
             OutputAction::MoveCursorAndEraseAndPrintBuffer(steps) => {
-                let position = self.terminal.relative_position(steps);
+                let position = self.new_position(CursorMove::Steps(steps));
 
                 pack([
                     Move(MoveCursorToPosition::new(position)),
-                    Erase,
+                    Erase(self.rprompt),
                     Print(Printable::from_str(self.buffer_after_position(position))),
                     Move(MoveCursorToPosition::new(position)),
                 ])
             }
-            OutputAction::RingBell => pack([Bell]),
+            OutputAction::ClearAndPrintSearch => pack([
+                Move(MoveCursorToPosition::new(Position::new(0, 0))),
+                Erase(self.rprompt),
+                Print(Printable::from_str(self.text())),
+                match self.text {
+                    Overlay::Search(..) => self.override_step(),
+                    _ => Done,
+                },
+            ]),
+            // Display text in place of the buffer from char index
+            // `start`, without changing the buffer
+            OutputAction::PrintOverride(start) => pack([
+                Move(MoveCursorToPosition::new(self.char_position(start))),
+                Erase(self.rprompt),
+                self.override_step(),
+            ]),
+            // Print the buffer from char index `start`, with cursor at
+            // char index `cursor`. Restores display of the buffer after
+            // [`OutputAction::PrintOverride`].
+            OutputAction::ClearOverride(start, cursor) => {
+                let position = self.char_position(start);
+                let cursor = self.char_position(cursor);
+
+                pack([
+                    Move(MoveCursorToPosition::new(position)),
+                    Erase(self.rprompt),
+                    Print(Printable::from_str(self.buffer_after_position(position))),
+                    Move(MoveCursorToPosition::new(cursor)),
+                ])
+            }
+            OutputAction::PrintHint => self.hint_steps(),
+            OutputAction::PrintList => self.list_steps(),
+            OutputAction::ClearAndPrintPromptAndBuffer => pack([
+                Move(MoveCursorToPosition::new(Position::new(0, 0))),
+                Erase(self.rprompt),
+                self.prompt_step(),
+                Print(Printable::from_str(self.buffer.as_str())),
+            ]),
+            OutputAction::PrintRprompt => {
+                let width = str_width(self.rprompt);
+                let columns = self.terminal.columns();
+                let line_len = self.prompt.len() + str_width(self.buffer.as_str());
+
+                // Not shown without erase support, as it can't be
+                // erased when the line reaches it, or with relative
+                // movement, as it's printed at an absolute position
+                if width > 0
+                    && width < columns
+                    && line_len < columns - width
+                    && !self.terminal.padded_erase()
+                    && !self.terminal.relative_movement()
+                {
+                    self.terminal
+                        .set_right_prompt(RightPrompt::Visible(columns - width));
+
+                    pack([Rprompt(self.rprompt, RpromptPhase::Save)])
+                } else {
+                    pack([])
+                }
+            }
+            OutputAction::RingBell | OutputAction::Overflow(false) => pack([Bell]),
+            OutputAction::Overflow(true) => self.overflow_steps(),
             OutputAction::ClearAndPrintPrompt => pack([
+                self.bracketed_paste_step(true),
                 ClearLine,
-                Print(Printable::from_iter(self.prompt.iter())),
-                GetPosition,
+                self.prompt_step(),
+                // The position is known without asking when moving
+                // relative to the cursor
+                if self.terminal.relative_movement() {
+                    Done
+                } else {
+                    GetPosition
+                },
             ]),
             OutputAction::ClearAndPrintBuffer => {
                 let position = self.new_position(CursorMove::Start);
 
                 pack([
                     Move(MoveCursorToPosition::new(position)),
-                    Erase,
+                    Erase(self.rprompt),
                     Print(Printable::from_str(self.buffer.as_str())),
                 ])
             }
@@ -591,16 +1850,16 @@ where
                 pack([SavePosition, MoveCursorToEdge, GetPosition, RestorePosition])
             }
 
-            OutputAction::Done => pack([Newline, EndOfString]),
-            OutputAction::Abort => pack([Newline, Abort]),
+            OutputAction::Done => pack([self.bracketed_paste_step(false), Newline, EndOfString]),
+            OutputAction::Abort => pack([self.bracketed_paste_step(false), Newline, Abort]),
+            OutputAction::Eof => pack([self.bracketed_paste_step(false), Newline, Eof]),
             OutputAction::Nothing => pack([]),
         };
-
         OutputIter {
             terminal: self.terminal,
             steps,
             pos: 0,
-            _marker: PhantomData,
+            echo: self.echo,
         }
     }
 }
@@ -609,43 +1868,22 @@ where
 mod tests {
     use std::string::String;
 
-    use crate::core::StrIter;
+    use crate::{caps::Vt100, core::Prompt};
 
     use super::*;
 
     use std::vec::Vec;
 
     #[test]
-    fn uint_to_bytes() {
-        fn to_string<const N: usize>(n: usize) -> String {
-            let uint: UintToBytes<N> = UintToBytes::from_uint(n).unwrap();
-
-            String::from_utf8(uint.as_bytes().to_vec()).unwrap()
-        }
-
-        assert_eq!(to_string::<4>(0), "0");
-
-        assert_eq!(to_string::<4>(42), "42");
-
-        assert_eq!(to_string::<4>(10), "10");
+    fn move_cursor() {
+        fn to_string(mut cm: MoveCursor) -> String {
+            let mut bytes = Vec::new();
 
-        assert_eq!(to_string::<4>(9999), "9999");
-    }
+            while let Some(item) = cm.next(&Vt100) {
+                bytes.extend_from_slice(item.get_bytes().unwrap());
+            }
 
-    #[test]
-    fn move_cursor() {
-        fn to_string(cm: MoveCursor) -> String {
-            String::from_utf8(
-                cm.flat_map(|item| {
-                    if let Some(bytes) = item.get_bytes() {
-                        bytes.to_vec()
-                    } else {
-                        vec![]
-                    }
-                })
-                .collect(),
-            )
-            .unwrap()
+            String::from_utf8(bytes).unwrap()
         }
 
         assert_eq!(
@@ -682,11 +1920,24 @@ mod tests {
             to_string(MoveCursor::new(Cursor::new(0, 0), -1)),
             "\x1b[1T\x1b[1;1H"
         );
+
+        assert_eq!(
+            to_string(MoveCursor::relative(Cursor::new(1, 3), 0, 3)),
+            "\x1b[2A\r\x1b[3C"
+        );
+
+        assert_eq!(
+            to_string(MoveCursor::relative(Cursor::new(3, 0), 1, 3)),
+            "\x1b[1S\r"
+        );
     }
 
     #[test]
     fn step() {
-        fn to_string<'a>(mut step: Step<'a, StrIter<'a>>, terminal: &mut Terminal) -> String {
+        fn to_string<'a>(
+            mut step: Step<'a, dyn PromptSource<'a>>,
+            terminal: &mut Terminal,
+        ) -> String {
             let mut bytes = Vec::new();
 
             while let Some(item) = step.advance(terminal) {
@@ -735,7 +1986,7 @@ mod tests {
 
         assert_eq!(terminal.get_position(), Position::new(0, 3));
 
-        assert_eq!(to_string(Step::Erase, &mut terminal), "\x1b[J");
+        assert_eq!(to_string(Step::Erase(""), &mut terminal), "\x1b[J");
         assert_eq!(to_string(Step::Newline, &mut terminal), "\n\r");
         assert_eq!(to_string(Step::Bell, &mut terminal), "\x07");
         assert_eq!(to_string(Step::Done, &mut terminal), "");
@@ -743,7 +1994,7 @@ mod tests {
 
     #[test]
     fn byte_iterator() {
-        fn to_string<B: Buffer>(output: Output<'_, B, StrIter>) -> String {
+        fn to_string<B: Buffer>(output: Output<'_, B, dyn PromptSource<'_>>) -> String {
             String::from_utf8(
                 output
                     .into_iter()
@@ -759,12 +2010,12 @@ mod tests {
             .unwrap()
         }
 
-        let prompt: Prompt<StrIter> = "> ".into();
+        let prompt: &dyn PromptSource<'_> = &Prompt::from("> ");
         let mut line_buffer = LineBuffer::new_unbounded();
         let mut terminal = Terminal::new(4, 10, Cursor::new(0, 0));
 
         let result = to_string(Output::new(
-            &prompt,
+            prompt,
             &line_buffer,
             &mut terminal,
             OutputAction::ClearAndPrintPrompt,
@@ -775,10 +2026,10 @@ mod tests {
         line_buffer.insert_str(0, "Hello, world!").unwrap();
 
         let result = to_string(Output::new(
-            &prompt,
+            prompt,
             &line_buffer,
             &mut terminal,
-            OutputAction::PrintBufferAndMoveCursorForward,
+            OutputAction::PrintBufferAndMoveCursorForward(1),
         ));
 
         assert_eq!(result, "Hello, w\n\rorld!\x1b[1;4H");
@@ -786,7 +2037,7 @@ mod tests {
         assert_eq!(terminal.get_cursor(), Cursor::new(0, 3));
 
         let result = to_string(Output::new(
-            &prompt,
+            prompt,
             &line_buffer,
             &mut terminal,
             OutputAction::MoveCursor(CursorMove::Start),
@@ -796,9 +2047,166 @@ mod tests {
         assert_eq!(terminal.get_cursor(), Cursor::new(0, 2));
     }
 
+    #[test]
+    fn cell_offsets() {
+        let s = "ab\ncd\n\ne";
+
+        // The first newline fills columns 4-9, the next a whole row
+        for (index, offset) in [(0, 2), (2, 4), (3, 10), (5, 12), (6, 20), (7, 30), (8, 31)] {
+            assert_eq!(cell_offset(s, 2, 10, index), offset);
+            assert_eq!(char_index(s, 2, 10, offset), index);
+        }
+
+        assert_eq!(char_index(s, 2, 10, 7), 2);
+        assert_eq!(char_index(s, 2, 10, 25), 6);
+        assert_eq!(cell_offset(s, 2, 10, 10), 33);
+        assert_eq!(char_index(s, 2, 10, 33), 10);
+        assert_eq!(
+            cell_offset("abc", 2, 10, 3),
+            char_index("abc", 2, 10, 5) + 2
+        );
+    }
+
+    #[cfg(not(feature = "ascii-only"))]
+    #[test]
+    fn wide_cell_offsets() {
+        let s = "ab你好c😂d";
+
+        // The emoji doesn't fit in column 9, so it starts on the next
+        // row
+        for (index, offset) in [(0, 2), (2, 4), (3, 6), (4, 8), (5, 10), (6, 12), (7, 13)] {
+            assert_eq!(cell_offset(s, 2, 10, index), offset);
+            assert_eq!(char_index(s, 2, 10, offset), index);
+        }
+
+        // The second cell of a wide char, and the cell left empty
+        // before it, belong to the char
+        assert_eq!(char_index(s, 2, 10, 5), 2);
+        assert_eq!(char_index(s, 2, 10, 9), 5);
+        assert_eq!(char_index(s, 2, 10, 11), 5);
+
+        assert_eq!(byte_position_in_columns(s, 3), 2);
+        assert_eq!(byte_position_in_columns(s, 4), 5);
+        assert_eq!(byte_position_in_columns(s, 20), s.len());
+    }
+
+    #[test]
+    fn terminal_markers_last() {
+        // Adding an action fails to compile until it is added to the
+        // list below
+        fn covered(action: OutputAction) {
+            use OutputAction::*;
+
+            match action {
+                Nothing
+                | MoveCursor(_)
+                | ClearAndPrintPrompt
+                | ClearAndPrintBuffer
+                | PrintBufferAndMoveCursorForward(_)
+                | PrintBufferEnd
+                | EraseAfterCursor
+                | EraseLine
+                | EraseAndPrintBuffer
+                | ClearScreen(_)
+                | ClearLine
+                | MoveCursorBackAndPrintBufferAndMoveForward
+                | MoveCursorAndEraseAndPrintBuffer(_)
+                | ClearAndPrintSearch
+                | ClearAndPrintPromptAndBuffer
+                | PrintOverride(_)
+                | ClearOverride(_, _)
+                | PrintHint
+                | PrintRprompt
+                | PrintList
+                | RingBell
+                | Overflow(_)
+                | ProbeSize
+                | Done
+                | Abort
+                | Eof => (),
+            }
+        }
+
+        let actions = [
+            OutputAction::Nothing,
+            OutputAction::MoveCursor(CursorMove::Forward),
+            OutputAction::MoveCursor(CursorMove::Back),
+            OutputAction::MoveCursor(CursorMove::Start),
+            OutputAction::MoveCursor(CursorMove::Steps(1)),
+            OutputAction::ClearAndPrintPrompt,
+            OutputAction::ClearAndPrintBuffer,
+            OutputAction::PrintBufferAndMoveCursorForward(0),
+            OutputAction::PrintBufferEnd,
+            OutputAction::EraseAfterCursor,
+            OutputAction::EraseLine,
+            OutputAction::EraseAndPrintBuffer,
+            OutputAction::ClearScreen(0),
+            OutputAction::ClearLine,
+            OutputAction::MoveCursorBackAndPrintBufferAndMoveForward,
+            OutputAction::MoveCursorAndEraseAndPrintBuffer(0),
+            OutputAction::ClearAndPrintSearch,
+            OutputAction::ClearAndPrintPromptAndBuffer,
+            OutputAction::PrintOverride(0),
+            OutputAction::ClearOverride(0, 3),
+            OutputAction::PrintHint,
+            OutputAction::PrintRprompt,
+            OutputAction::PrintList,
+            OutputAction::RingBell,
+            OutputAction::Overflow(false),
+            OutputAction::Overflow(true),
+            OutputAction::ProbeSize,
+            OutputAction::Done,
+            OutputAction::Abort,
+            OutputAction::Eof,
+        ];
+
+        let prompt: &dyn PromptSource<'_> = &Prompt::from("> ");
+        let mut line_buffer = LineBuffer::new_unbounded();
+        line_buffer.insert_str(0, "abc").unwrap();
+
+        for action in actions {
+            covered(action);
+
+            let mut terminal = Terminal::new(4, 10, Cursor::new(0, 0));
+            terminal.move_cursor(Position::new(0, 5));
+
+            let items =
+                Output::<_, _, NoCompleter>::new(prompt, &line_buffer, &mut terminal, action)
+                    .with_text("text")
+                    .with_style(Style::Dim)
+                    .with_rprompt("rprompt")
+                    .with_bracketed_paste(true)
+                    .into_iter()
+                    .collect::<Vec<_>>();
+
+            if let Some(index) = items.iter().position(|item| item.get_bytes().is_none()) {
+                assert_eq!(index, items.len() - 1, "{:?}: {:?}", action, items);
+            }
+        }
+
+        // Some drivers panic on empty writes, so empty prompt parts,
+        // buffer and text must not give empty slices
+        let prompt: &dyn PromptSource<'_> = &Prompt::from(["", "> ", ""].into_iter());
+        let line_buffer = LineBuffer::new_unbounded();
+
+        for action in actions {
+            let mut terminal = Terminal::new(4, 10, Cursor::new(0, 0));
+
+            for item in
+                Output::<_, _, NoCompleter>::new(prompt, &line_buffer, &mut terminal, action)
+                    .with_text("")
+            {
+                assert_ne!(item.get_bytes(), Some(&[][..]), "{:?}", action);
+            }
+        }
+    }
+
     #[test]
     fn split_utf8() {
-        fn to_string<'a>(mut step: Step<'a, StrIter<'a>>, terminal: &mut Terminal) -> String {
+        fn to_string<'a>(
+            mut step: Step<'a, dyn PromptSource<'a>>,
+            terminal: &mut Terminal,
+        ) -> String {
             let mut bytes = Vec::new();
 
             while let Some(item) = step.advance(terminal) {
@@ -822,4 +2230,15 @@ mod tests {
             "aadfåpadfå\n\raåfåaadåap\n\rpaåadå"
         );
     }
+
+    #[test]
+    fn output_size() {
+        // Output is created for every byte of input, so keep an eye on
+        // its size to bound stack usage.
+        assert!(
+            core::mem::size_of::<OutputIter<dyn PromptSource>>()
+                <= 26 * core::mem::size_of::<usize>()
+        );
+        assert!(core::mem::size_of::<crate::input::Parser>() <= 8);
+    }
 }
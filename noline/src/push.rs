@@ -0,0 +1,287 @@
+//! IO for hosts pushing input and taking output at their own pace
+//!
+//! Some hosts, like a browser running xterm.js, deliver input by
+//! callback and can't block waiting for it. [`PushIO`] queues pushed
+//! input for [`sync_editor::Editor::readline_poll`], and writes output
+//! to a sink closure as it's produced.
+//!
+//! Output is flow controlled by credits. Each byte written to the sink
+//! uses one byte of credit, and input is left in the queue while the
+//! credit is used up. The host grants more credit with
+//! [`PushIO::output_credit`] when the sink has caught up, e.g. from
+//! the callback of xterm.js `write()`, and polls the editor again.
+//! Output for a single byte of input is always written in full, so the
+//! sink may receive more than the credit, but never more than the
+//! output of one byte of input after the credit is used up.
+//!
+//! ```no_run
+//! # use noline::{builder::EditorBuilder, push::PushIO, sync_editor::PollResult};
+//! let mut io: PushIO<_> = PushIO::new(|bytes: &[u8]| { /* write to terminal */ }, 1024);
+//! let mut buffer = [0; 100];
+//! let mut editor = EditorBuilder::from_slice(&mut buffer)
+//!     .build_sync(&mut io)
+//!     .unwrap();
+//!
+//! // On input from the terminal
+//! io.push_input(b"ls\r");
+//!
+//! // After pushing input or granting credit
+//! match editor.readline_poll("> ", &mut io).unwrap() {
+//!     PollResult::Done(line) => { /* handle line */ }
+//!     PollResult::Pending | PollResult::Aborted => (),
+//! }
+//! ```
+//!
+//! [`sync_editor::Editor::readline_poll`]: crate::sync_editor::Editor::readline_poll
+
+use core::{convert::Infallible, fmt};
+
+/// Error from [`PushIO::try_push_input`] when the input doesn't fit in
+/// the queue. Holds the number of bytes free.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct QueueFull(pub usize);
+
+impl fmt::Display for QueueFull {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "input queue full, {} bytes free", self.0)
+    }
+}
+
+impl core::error::Error for QueueFull {}
+
+/// Input queue and flow-controlled output sink. Holds up to `N` bytes
+/// of input.
+pub struct PushIO<S, const N: usize = 64> {
+    sink: S,
+    input: [u8; N],
+    start: usize,
+    len: usize,
+    credit: usize,
+}
+
+impl<S, const N: usize> PushIO<S, N>
+where
+    S: FnMut(&[u8]),
+{
+    /// Create IO writing output to `sink`, with `credit` bytes of
+    /// output allowed before the host grants more
+    pub fn new(sink: S, credit: usize) -> Self {
+        Self {
+            sink,
+            input: [0; N],
+            start: 0,
+            len: 0,
+            credit,
+        }
+    }
+
+    /// Queue input for the editor. Returns the number of bytes queued,
+    /// which is less than `bytes.len()` when the queue is full. Push
+    /// the rest after polling the editor.
+    pub fn push_input(&mut self, bytes: &[u8]) -> usize {
+        let count = bytes.len().min(N - self.len);
+
+        for &byte in &bytes[..count] {
+            self.input[(self.start + self.len) % N] = byte;
+            self.len += 1;
+        }
+
+        count
+    }
+
+    /// Queue all of `bytes` for the editor, or nothing if they don't
+    /// fit, e.g. to drop input from a host that can't hold back the
+    /// rest. Poll the editor to make room.
+    pub fn try_push_input(&mut self, bytes: &[u8]) -> Result<(), QueueFull> {
+        let free = N - self.len;
+
+        if bytes.len() > free {
+            return Err(QueueFull(free));
+        }
+
+        self.push_input(bytes);
+
+        Ok(())
+    }
+
+    /// Allow `bytes` more bytes of output
+    pub fn output_credit(&mut self, bytes: usize) {
+        self.credit = self.credit.saturating_add(bytes);
+    }
+
+    /// True when output credit is used up, and no more input is
+    /// handled until more credit is granted
+    pub fn is_paused(&self) -> bool {
+        self.credit == 0
+    }
+
+    /// Number of bytes of input queued
+    pub fn pending_input(&self) -> usize {
+        self.len
+    }
+}
+
+impl<S, const N: usize> embedded_io::ErrorType for PushIO<S, N> {
+    type Error = Infallible;
+}
+
+impl<S, const N: usize> embedded_io::Read for PushIO<S, N> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let count = buf.len().min(self.len);
+
+        for place in &mut buf[..count] {
+            *place = self.input[self.start];
+            self.start = (self.start + 1) % N;
+            self.len -= 1;
+        }
+
+        Ok(count)
+    }
+}
+
+impl<S, const N: usize> embedded_io::ReadReady for PushIO<S, N> {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.len > 0 && self.credit > 0)
+    }
+}
+
+impl<S, const N: usize> embedded_io::Write for PushIO<S, N>
+where
+    S: FnMut(&[u8]),
+{
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        (self.sink)(buf);
+        self.credit = self.credit.saturating_sub(buf.len());
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::string::{String, ToString};
+    use std::vec::Vec;
+
+    use embedded_io::{Read, ReadReady, Write};
+
+    use super::*;
+    use crate::builder::EditorBuilder;
+    use crate::sync_editor::PollResult;
+    use crate::terminal::Cursor;
+    use crate::testlib::MockTerminal;
+
+    #[test]
+    fn queue_and_credit() {
+        let written = RefCell::new(Vec::new());
+        let mut io: PushIO<_, 4> = PushIO::new(
+            |bytes: &[u8]| written.borrow_mut().extend_from_slice(bytes),
+            0,
+        );
+
+        assert_eq!(io.push_input(b"abcdef"), 4);
+        assert_eq!(io.pending_input(), 4);
+        assert!(io.is_paused());
+        assert!(!io.read_ready().unwrap());
+
+        io.output_credit(2);
+        assert!(io.read_ready().unwrap());
+
+        let mut buf = [0; 3];
+        assert_eq!(io.read(&mut buf).unwrap(), 3);
+        assert_eq!(&buf, b"abc");
+
+        // Queue wraps around
+        assert_eq!(io.try_push_input(b"efgh"), Err(QueueFull(3)));
+        assert_eq!(io.pending_input(), 1);
+        assert_eq!(io.try_push_input(b"ef"), Ok(()));
+
+        let mut buf = [0; 4];
+        assert_eq!(io.read(&mut buf).unwrap(), 3);
+        assert_eq!(&buf[..3], b"def");
+
+        // Writing more than the credit pauses without losing output
+        io.write_all(b"xyz").unwrap();
+        assert!(io.is_paused());
+        assert!(!io.read_ready().unwrap());
+        assert_eq!(written.borrow().as_slice(), b"xyz");
+    }
+
+    // Read a line, granting `credit` bytes of output per round like a
+    // slow terminal. Returns line, output and screen.
+    fn throttled_readline(credit: usize) -> (String, Vec<u8>, String) {
+        let written = RefCell::new(Vec::new());
+        let mut io: PushIO<_, 8> = PushIO::new(
+            |bytes: &[u8]| written.borrow_mut().extend_from_slice(bytes),
+            credit,
+        );
+        let mut editor = EditorBuilder::new_unbounded().build_sync(&mut io).unwrap();
+        let mut terminal = MockTerminal::new(4, 10, Cursor::new(0, 0));
+        let mut replies = VecDeque::new();
+        let mut typed: VecDeque<u8> = "hello, world!\x01\x06\x06X\r".bytes().collect();
+        let mut probes = 0;
+        let mut shown = 0;
+
+        for _ in 0..10000 {
+            match editor.readline_poll("> ", &mut io).unwrap() {
+                PollResult::Done(line) => {
+                    let line = line.to_string();
+                    let output = written.borrow().clone();
+
+                    for &byte in &output[shown..] {
+                        terminal.advance(byte);
+                    }
+
+                    return (line, output, terminal.screen_as_string());
+                }
+                PollResult::Aborted => panic!("aborted"),
+                PollResult::Pending => (),
+            }
+
+            let output = written.borrow();
+
+            for &byte in &output[shown..] {
+                if let Some(reply) = terminal.advance(byte) {
+                    replies.extend(reply);
+                    probes += 1;
+                }
+            }
+
+            shown = output.len();
+
+            // Type once the size and position probes are answered
+            let count = io.push_input(replies.make_contiguous());
+            replies.drain(..count);
+
+            if probes == 2 && replies.is_empty() {
+                let count = io.push_input(typed.make_contiguous());
+                typed.drain(..count);
+            }
+
+            io.output_credit(credit);
+        }
+
+        panic!("line not done");
+    }
+
+    #[test]
+    fn throttled_output() {
+        let (line, output, screen) = throttled_readline(usize::MAX);
+        assert_eq!(line, "heXllo, world!");
+        assert_eq!(screen, "> heXllo, \nworld!");
+
+        // Output is the same, only split over more rounds
+        for credit in [1, 3, 16] {
+            assert_eq!(
+                throttled_readline(credit),
+                (line.clone(), output.clone(), screen.clone())
+            );
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! Input read in chunks by the editors, handed out a byte at a time
+
+// Most bytes read by a single read call
+pub(crate) const READ_BUFFER_CAPACITY: usize = 64;
+
+// Bytes read by a single read call unless set with
+// [`crate::builder::EditorBuilder::with_read_size`]
+pub(crate) const DEFAULT_READ_SIZE: usize = 16;
+
+// Bytes read but not yet handled. Bytes left when a line is done are
+// kept for the next line.
+//
+// Bytes kept from a previous line were typed before the terminal was
+// probed for the next line, so they are held back while probing and
+// handled once the probe is done. Only bytes read after them are
+// handed out while probing.
+pub(crate) struct ReadBuffer {
+    bytes: [u8; READ_BUFFER_CAPACITY],
+    start: usize,
+    end: usize,
+    // Bytes from `start` held back until [`ReadBuffer::release`]
+    held: usize,
+}
+
+impl ReadBuffer {
+    pub(crate) fn new() -> Self {
+        Self {
+            bytes: [0; READ_BUFFER_CAPACITY],
+            start: 0,
+            end: 0,
+            held: 0,
+        }
+    }
+
+    // Hold back the bytes read so far, before probing
+    pub(crate) fn hold(&mut self) {
+        self.held = self.end - self.start;
+    }
+
+    // Hand out held bytes again, when probing is done
+    pub(crate) fn release(&mut self) {
+        self.held = 0;
+    }
+
+    // True if a byte can be taken without reading
+    pub(crate) fn has_input(&self) -> bool {
+        self.end - self.start > self.held
+    }
+
+    // Take the next byte not held back
+    pub(crate) fn pop(&mut self) -> Option<u8> {
+        if !self.has_input() {
+            return None;
+        }
+
+        let index = self.start + self.held;
+        let byte = self.bytes[index];
+
+        if self.held == 0 {
+            self.start += 1;
+        } else {
+            self.bytes.copy_within(index + 1..self.end, index);
+            self.end -= 1;
+        }
+
+        Some(byte)
+    }
+
+    // Free space to read at most `size` bytes into, or an empty slice
+    // if the buffer is full of held bytes
+    pub(crate) fn space(&mut self, size: usize) -> &mut [u8] {
+        if self.start == self.end {
+            self.start = 0;
+            self.end = 0;
+        } else if self.start > 0 {
+            self.bytes.copy_within(self.start..self.end, 0);
+            self.end -= self.start;
+            self.start = 0;
+        }
+
+        let end = (self.end + size.max(1)).min(READ_BUFFER_CAPACITY);
+
+        &mut self.bytes[self.end..end]
+    }
+
+    // Add `len` bytes read into [`ReadBuffer::space`]
+    pub(crate) fn filled(&mut self, len: usize) {
+        self.end += len;
+    }
+
+    // Drop all bytes, e.g. when the link is lost
+    pub(crate) fn clear(&mut self) {
+        *self = Self::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill(buffer: &mut ReadBuffer, bytes: &[u8]) {
+        let space = buffer.space(bytes.len());
+        space.copy_from_slice(bytes);
+        buffer.filled(bytes.len());
+    }
+
+    #[test]
+    fn read_buffer() {
+        let mut buffer = ReadBuffer::new();
+
+        assert!(!buffer.has_input());
+        assert_eq!(buffer.space(100).len(), READ_BUFFER_CAPACITY);
+
+        fill(&mut buffer, b"abc");
+        assert_eq!(buffer.pop(), Some(b'a'));
+
+        // Held bytes are skipped until released
+        buffer.hold();
+        assert!(!buffer.has_input());
+        assert_eq!(buffer.pop(), None);
+
+        fill(&mut buffer, b"xy");
+        assert_eq!(buffer.pop(), Some(b'x'));
+
+        buffer.release();
+        assert_eq!(buffer.pop(), Some(b'b'));
+        assert_eq!(buffer.pop(), Some(b'c'));
+        assert_eq!(buffer.pop(), Some(b'y'));
+        assert_eq!(buffer.pop(), None);
+
+        // Full of held bytes
+        fill(&mut buffer, &[b'z'; READ_BUFFER_CAPACITY]);
+        buffer.hold();
+        assert!(buffer.space(1).is_empty());
+
+        buffer.clear();
+        assert!(!buffer.has_input());
+    }
+}
@@ -0,0 +1,289 @@
+//! Line editor for the terminal on `std`
+//!
+//! The editors read and write through [`embedded_io`] traits, and the
+//! terminal has to be in raw mode for them to get keys as they are
+//! typed. [`StdEditor`] does both for stdin and stdout, switching to
+//! raw mode while reading a line. Requires feature `termion`, and
+//! stdout must be a terminal.
+//!
+//! ```no_run
+//! use noline::std_editor::StdEditor;
+//!
+//! let mut editor = StdEditor::new()?;
+//!
+//! while let Ok(line) = editor.readline("> ") {
+//!     println!("Read: '{}'", line);
+//! }
+//!
+//! editor.save_history("history")?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! For a single line, use [`readline`].
+
+extern crate std;
+
+use std::fs::File;
+use std::io::{self, Stdin, Stdout};
+use std::path::Path;
+use std::string::String;
+
+use embedded_io::ErrorKind;
+use termion::raw::{IntoRawMode, RawTerminal};
+
+use crate::builder::EditorBuilder;
+use crate::history::UnboundedHistory;
+use crate::line_buffer::UnboundedBuffer;
+use crate::sync_editor::Editor;
+
+/// IO with a terminal that can be switched to raw mode
+pub trait RawModeIO: embedded_io::Read + embedded_io::Write {
+    /// Switch raw mode on or off
+    fn set_raw_mode(&mut self, raw: bool) -> io::Result<()>;
+}
+
+/// Stdin and stdout of a terminal
+pub struct StdIO {
+    stdin: Stdin,
+    stdout: RawTerminal<Stdout>,
+}
+
+impl StdIO {
+    /// Take stdin and stdout, leaving the terminal in its current mode
+    /// until raw mode is switched on. Fails if stdout is not a
+    /// terminal.
+    pub fn new() -> io::Result<Self> {
+        let stdout = io::stdout().into_raw_mode()?;
+        stdout.suspend_raw_mode()?;
+
+        Ok(Self {
+            stdin: io::stdin(),
+            stdout,
+        })
+    }
+}
+
+impl embedded_io::ErrorType for StdIO {
+    type Error = ErrorKind;
+}
+
+impl embedded_io::Read for StdIO {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        Ok(io::Read::read(&mut self.stdin, buf).map_err(|err| err.kind())?)
+    }
+}
+
+impl embedded_io::Write for StdIO {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        Ok(io::Write::write(&mut self.stdout, buf).map_err(|err| err.kind())?)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(io::Write::flush(&mut self.stdout).map_err(|err| err.kind())?)
+    }
+}
+
+impl RawModeIO for StdIO {
+    fn set_raw_mode(&mut self, raw: bool) -> io::Result<()> {
+        if raw {
+            self.stdout.activate_raw_mode()
+        } else {
+            self.stdout.suspend_raw_mode()
+        }
+    }
+}
+
+// File read and written through `embedded_io`
+struct FileIO(File);
+
+impl embedded_io::ErrorType for FileIO {
+    type Error = ErrorKind;
+}
+
+impl embedded_io::Read for FileIO {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        Ok(io::Read::read(&mut self.0, buf).map_err(|err| err.kind())?)
+    }
+}
+
+impl embedded_io::Write for FileIO {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        Ok(io::Write::write(&mut self.0, buf).map_err(|err| err.kind())?)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(io::Write::flush(&mut self.0).map_err(|err| err.kind())?)
+    }
+}
+
+/// Line editor with unbounded buffer and history, reading from stdin
+/// and writing to stdout by default
+pub struct StdEditor<IO = StdIO> {
+    editor: Editor<UnboundedBuffer, UnboundedHistory>,
+    io: IO,
+}
+
+impl StdEditor<StdIO> {
+    /// Create editor for stdin and stdout. Fails if stdout is not a
+    /// terminal.
+    pub fn new() -> io::Result<Self> {
+        Self::with_io(StdIO::new()?)
+    }
+}
+
+impl<IO: RawModeIO> StdEditor<IO> {
+    /// Create editor for other IO
+    pub fn with_io(mut io: IO) -> io::Result<Self> {
+        let editor = EditorBuilder::new_unbounded()
+            .with_unbounded_history()
+            .build_sync(&mut io)?;
+
+        Ok(Self { editor, io })
+    }
+
+    /// Read line, with the terminal in raw mode until the line is
+    /// done. Ctrl-C returns an error of kind
+    /// [`io::ErrorKind::Interrupted`], and Ctrl-D on an empty line
+    /// [`io::ErrorKind::UnexpectedEof`].
+    pub fn readline(&mut self, prompt: &str) -> io::Result<String> {
+        self.io.set_raw_mode(true)?;
+
+        let line = self.editor.readline(prompt, &mut self.io).map(String::from);
+
+        self.io.set_raw_mode(false)?;
+
+        Ok(line?)
+    }
+
+    /// Add history entries from file written by
+    /// [`StdEditor::save_history`]. Returns the number of entries
+    /// added.
+    pub fn load_history(&mut self, path: impl AsRef<Path>) -> io::Result<usize> {
+        let mut file = FileIO(File::open(path)?);
+
+        Ok(self.editor.restore_history(&mut file)?)
+    }
+
+    /// Write history to file, replacing its content
+    pub fn save_history(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut file = FileIO(File::create(path)?);
+
+        Ok(self.editor.save_history(&mut file)?)
+    }
+
+    /// The underlying editor, for settings not covered here
+    pub fn editor(&mut self) -> &mut Editor<UnboundedBuffer, UnboundedHistory> {
+        &mut self.editor
+    }
+}
+
+/// Read a single line from stdin, see [`StdEditor::readline`]
+pub fn readline(prompt: &str) -> io::Result<String> {
+    StdEditor::new()?.readline(prompt)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::terminal::Cursor;
+    use crate::testlib::MockTerminal;
+
+    // Terminal that only handles input in raw mode, like a real one
+    // that otherwise waits for Enter
+    struct MockRawIO {
+        terminal: MockTerminal,
+        input: VecDeque<u8>,
+        raw: bool,
+        modes: Vec<bool>,
+    }
+
+    impl MockRawIO {
+        fn new(input: &[u8]) -> Self {
+            Self {
+                terminal: MockTerminal::new(4, 20, Cursor::new(0, 0)),
+                input: input.iter().copied().collect(),
+                raw: false,
+                modes: Vec::new(),
+            }
+        }
+    }
+
+    impl embedded_io::ErrorType for MockRawIO {
+        type Error = ErrorKind;
+    }
+
+    impl embedded_io::Read for MockRawIO {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            assert!(self.raw);
+
+            match (buf.first_mut(), self.input.pop_front()) {
+                (Some(place), Some(byte)) => {
+                    *place = byte;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    impl embedded_io::Write for MockRawIO {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            assert!(self.raw);
+
+            for &b in buf {
+                if let Some(reply) = self.terminal.advance(b) {
+                    for byte in reply.into_iter().rev() {
+                        self.input.push_front(byte);
+                    }
+                }
+            }
+
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    impl RawModeIO for MockRawIO {
+        fn set_raw_mode(&mut self, raw: bool) -> io::Result<()> {
+            self.raw = raw;
+            self.modes.push(raw);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn readline() {
+        let mut editor = StdEditor::with_io(MockRawIO::new(b"abc\rdef\x03")).unwrap();
+
+        assert_eq!(editor.readline("> ").unwrap(), "abc");
+
+        // Raw mode is switched off also when reading fails
+        let err = editor.readline("> ").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+        assert_eq!(editor.io.modes, [true, false, true, false]);
+        assert_eq!(editor.io.terminal.screen_as_string(), "> abc\n> def");
+    }
+
+    #[test]
+    fn history() {
+        let path = std::env::temp_dir().join(std::format!("noline-history-{}", std::process::id()));
+
+        let mut editor = StdEditor::with_io(MockRawIO::new(b"one\rtwo\r")).unwrap();
+        editor.readline("> ").unwrap();
+        editor.readline("> ").unwrap();
+        editor.save_history(&path).unwrap();
+
+        let mut editor = StdEditor::with_io(MockRawIO::new(b"\x1b[A\x1b[A\r")).unwrap();
+        assert_eq!(editor.load_history(&path).unwrap(), 2);
+        assert_eq!(editor.readline("> ").unwrap(), "one");
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(editor.load_history(&path).is_err());
+    }
+}
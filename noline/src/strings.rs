@@ -0,0 +1,70 @@
+//! Text printed by the editor
+//!
+//! The few strings the editor prints on its own, like the reverse
+//! search prompt, can be translated by implementing [`Strings`] and
+//! adding it with [`crate::builder::EditorBuilder::with_strings`].
+//! Methods that aren't implemented return the English text.
+//!
+//! ```
+//! use noline::builder::EditorBuilder;
+//! use noline::strings::Strings;
+//!
+//! struct Norwegian;
+//!
+//! impl Strings for Norwegian {
+//!     fn reverse_search_prompt(&self) -> &str {
+//!         "(søk bakover)`"
+//!     }
+//! }
+//!
+//! let mut buffer = [0; 100];
+//! let builder = EditorBuilder::from_slice(&mut buffer).with_strings(&Norwegian);
+//! ```
+
+use core::fmt;
+
+/// Translatable text printed by the editor
+pub trait Strings {
+    /// Printed in place of the prompt before the query in reverse
+    /// history search. Together with
+    /// [`Strings::reverse_search_separator`], it should be at most 32
+    /// bytes, or the text is truncated.
+    fn reverse_search_prompt(&self) -> &str {
+        "(reverse-i-search)`"
+    }
+
+    /// Printed between the query and the matching entry in reverse
+    /// history search
+    fn reverse_search_separator(&self) -> &str {
+        "': "
+    }
+
+    /// Write the status shown by Ctrl-X =, given the column of the
+    /// cursor starting at 1, the number of chars in the line and the
+    /// char under the cursor, if any. The status is at most 64 bytes,
+    /// and isn't shown if it's longer.
+    fn cursor_position(
+        &self,
+        f: &mut dyn fmt::Write,
+        column: usize,
+        len: usize,
+        c: Option<char>,
+    ) -> fmt::Result {
+        match c {
+            Some(c) => write!(
+                f,
+                "col {} of {}, char '{}' (U+{:04X})",
+                column,
+                len,
+                c.escape_debug(),
+                c as u32
+            ),
+            None => write!(f, "col {} of {}, end of line", column, len),
+        }
+    }
+}
+
+/// English text, used when no [`Strings`] is given
+pub struct English;
+
+impl Strings for English {}
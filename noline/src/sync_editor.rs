@@ -4,45 +4,130 @@
 //! traits.
 //!
 //! Use the [`crate::builder::EditorBuilder`] to build an editor.
-use embedded_io::{Read, ReadExactError, Write};
+use core::ops::ControlFlow;
 
+use embedded_io::{Error, ErrorKind, Read, ReadReady, Write};
+
+use crate::complete::{Completer, NoCompleter};
 use crate::error::NolineError;
 
-use crate::history::{get_history_entries, CircularSlice, History};
+use crate::hint::{Hinter, NoHinter};
+use crate::history::{deserialize_with, serialize, BorrowHistory, CircularSlice, History};
 use crate::line_buffer::{Buffer, LineBuffer};
+use crate::link::{AlwaysConnected, LinkState};
+use crate::validate::{NoValidator, Validator};
 
-use crate::core::{Line, Prompt};
+use crate::core::{
+    copy_line, newline_pair, Line, LineState, Mask, Prompt, PromptSource, Settings, DEFAULT_PROMPT,
+};
 use crate::output::{Output, OutputItem};
+use crate::read_buffer::ReadBuffer;
 use crate::terminal::Terminal;
+use crate::undo::UndoBuffer;
+use crate::write_buffer::WriteBuffer;
 
 /// Line editor for synchronous IO
 ///
 /// It is recommended to use [`crate::builder::EditorBuilder`] to build an Editor.
-pub struct Editor<B, H>
+pub struct Editor<B, H, C = NoCompleter, L = AlwaysConnected, V = NoValidator, T = NoHinter>
 where
     B: Buffer,
-    H: History,
+    H: BorrowHistory,
+    C: Completer,
+    L: LinkState,
+    V: Validator,
+    T: Hinter,
 {
     buffer: LineBuffer<B>,
     terminal: Terminal,
     history: H,
+    completer: Option<C>,
+    validator: Option<V>,
+    hinter: Option<T>,
+    pub(crate) kill_buffer: Option<LineBuffer<B>>,
+    pub(crate) undo_buffer: Option<UndoBuffer<B>>,
+    pub(crate) stash_buffer: Option<LineBuffer<B>>,
+    // Output held back in [`Editor::readline_poll`]
+    pub(crate) write_buffer: Option<WriteBuffer<B>>,
+    link_state: L,
+    pub(crate) settings: Settings,
+    // Prompt used by `readline_default`
+    pub(crate) prompt: &'static str,
+    // Line in progress, read by [`Editor::readline_poll`]
+    poll: Option<LineState>,
+    // Line in progress must be printed again after resize
+    redraw: bool,
+    // Line in progress is hidden, with the cursor at this char index
+    hidden: Option<usize>,
+    // Input read but not handled yet
+    input: ReadBuffer,
+    // Insertions rejected by a full buffer in the current line
+    overflows: usize,
+    // Second byte of the newline pair the last line ended with, skipped
+    // if it's the next byte
+    pending_newline: Option<u8>,
+}
+
+/// Result of [`Editor::readline_poll`]
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PollResult<'a> {
+    /// Line is not done yet, poll again when more input is available
+    Pending,
+    /// Line is done
+    Done(&'a str),
+    /// Line was aborted with Ctrl+C
+    Aborted,
+}
+
+// IO calling `idle` until input is ready, for
+// [`Editor::readline_with_idle`]. Errors are reduced to their kind, so
+// stopping when `idle` breaks can be returned as a read error.
+struct IdleIO<'a, IO, F> {
+    io: &'a mut IO,
+    idle: F,
+    stopped: bool,
+}
+
+impl<IO, F> embedded_io::ErrorType for IdleIO<'_, IO, F> {
+    type Error = ErrorKind;
 }
 
-impl<E> From<E> for NolineError
+impl<IO, F> Read for IdleIO<'_, IO, F>
 where
-    E: embedded_io::Error,
+    IO: Read + ReadReady,
+    F: FnMut() -> ControlFlow<()>,
 {
-    fn from(value: E) -> Self {
-        NolineError::IoError(value.kind())
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        while !self.io.read_ready().map_err(|err| err.kind())? {
+            if (self.idle)().is_break() {
+                self.stopped = true;
+                return Err(ErrorKind::Interrupted);
+            }
+        }
+
+        self.io.read(buf).map_err(|err| err.kind())
+    }
+}
+
+impl<IO: Write, F> Write for IdleIO<'_, IO, F> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.io.write(buf).map_err(|err| err.kind())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.io.flush().map_err(|err| err.kind())
     }
 }
 
 impl<B, H> Editor<B, H>
 where
     B: Buffer,
-    H: History,
+    H: BorrowHistory,
 {
-    /// Create and initialize line editor
+    /// Create line editor without IO. The terminal size is probed when
+    /// reading the first line, use [`Editor::new_probed`] to know it
+    /// before that.
     pub fn new<IO: Read + Write>(
         buffer: LineBuffer<B>,
         history: H,
@@ -54,190 +139,1356 @@ where
             buffer,
             terminal,
             history,
+            completer: None,
+            validator: None,
+            hinter: None,
+            kill_buffer: None,
+            undo_buffer: None,
+            stash_buffer: None,
+            write_buffer: None,
+            link_state: AlwaysConnected,
+            settings: Settings::default(),
+            prompt: DEFAULT_PROMPT,
+            poll: None,
+            redraw: false,
+            hidden: None,
+            input: ReadBuffer::new(),
+            overflows: 0,
+            pending_newline: None,
         })
     }
 
-    fn handle_output<'a, 'item, IO, I>(
-        output: Output<'a, B, I>,
+    /// Create line editor and probe the terminal for its size, e.g. to
+    /// print a banner fitting the terminal before the first line. The
+    /// cursor is left where it was. Fails like [`Editor::readline`]
+    /// when the terminal doesn't reply, use [`Editor::new`] for dumb
+    /// terminals and pipes.
+    pub fn new_probed<IO: Read + Write>(
+        buffer: LineBuffer<B>,
+        history: H,
+        io: &mut IO,
+    ) -> Result<Self, NolineError> {
+        let mut editor = Self::new(buffer, history, io)?;
+        editor.refresh_size(io)?;
+
+        Ok(editor)
+    }
+
+    pub(crate) fn with_completer<C: Completer>(self, completer: Option<C>) -> Editor<B, H, C> {
+        Editor {
+            buffer: self.buffer,
+            terminal: self.terminal,
+            history: self.history,
+            completer,
+            validator: self.validator,
+            hinter: self.hinter,
+            kill_buffer: self.kill_buffer,
+            undo_buffer: self.undo_buffer,
+            stash_buffer: self.stash_buffer,
+            write_buffer: self.write_buffer,
+            link_state: self.link_state,
+            settings: self.settings,
+            prompt: self.prompt,
+            poll: self.poll,
+            redraw: self.redraw,
+            hidden: self.hidden,
+            input: self.input,
+            overflows: self.overflows,
+            pending_newline: self.pending_newline,
+        }
+    }
+}
+
+impl<B, H, C> Editor<B, H, C>
+where
+    B: Buffer,
+    H: BorrowHistory,
+    C: Completer,
+{
+    pub(crate) fn with_link_state<L: LinkState>(self, link_state: L) -> Editor<B, H, C, L> {
+        Editor {
+            buffer: self.buffer,
+            terminal: self.terminal,
+            history: self.history,
+            completer: self.completer,
+            validator: self.validator,
+            hinter: self.hinter,
+            kill_buffer: self.kill_buffer,
+            undo_buffer: self.undo_buffer,
+            stash_buffer: self.stash_buffer,
+            write_buffer: self.write_buffer,
+            link_state,
+            settings: self.settings,
+            prompt: self.prompt,
+            poll: self.poll,
+            redraw: self.redraw,
+            hidden: self.hidden,
+            input: self.input,
+            overflows: self.overflows,
+            pending_newline: self.pending_newline,
+        }
+    }
+}
+
+impl<B, H, C, L> Editor<B, H, C, L>
+where
+    B: Buffer,
+    H: BorrowHistory,
+    C: Completer,
+    L: LinkState,
+{
+    pub(crate) fn with_validator<V: Validator>(
+        self,
+        validator: Option<V>,
+    ) -> Editor<B, H, C, L, V> {
+        Editor {
+            buffer: self.buffer,
+            terminal: self.terminal,
+            history: self.history,
+            completer: self.completer,
+            validator,
+            hinter: self.hinter,
+            kill_buffer: self.kill_buffer,
+            undo_buffer: self.undo_buffer,
+            stash_buffer: self.stash_buffer,
+            write_buffer: self.write_buffer,
+            link_state: self.link_state,
+            settings: self.settings,
+            prompt: self.prompt,
+            poll: self.poll,
+            redraw: self.redraw,
+            hidden: self.hidden,
+            input: self.input,
+            overflows: self.overflows,
+            pending_newline: self.pending_newline,
+        }
+    }
+}
+
+impl<B, H, C, L, V> Editor<B, H, C, L, V>
+where
+    B: Buffer,
+    H: BorrowHistory,
+    C: Completer,
+    L: LinkState,
+    V: Validator,
+{
+    pub(crate) fn with_hinter<T: Hinter>(self, hinter: Option<T>) -> Editor<B, H, C, L, V, T> {
+        Editor {
+            buffer: self.buffer,
+            terminal: self.terminal,
+            history: self.history,
+            completer: self.completer,
+            validator: self.validator,
+            hinter,
+            kill_buffer: self.kill_buffer,
+            undo_buffer: self.undo_buffer,
+            stash_buffer: self.stash_buffer,
+            write_buffer: self.write_buffer,
+            link_state: self.link_state,
+            settings: self.settings,
+            prompt: self.prompt,
+            poll: self.poll,
+            redraw: self.redraw,
+            hidden: self.hidden,
+            input: self.input,
+            overflows: self.overflows,
+            pending_newline: self.pending_newline,
+        }
+    }
+}
+
+impl<B, H, C, L, V, T> Editor<B, H, C, L, V, T>
+where
+    B: Buffer,
+    H: BorrowHistory,
+    C: Completer,
+    L: LinkState,
+    V: Validator,
+    T: Hinter,
+{
+    // IO errors are reported as a lost link when the link state says
+    // so, as that is likely why IO failed
+    fn check_link(err: NolineError, link: &mut L) -> NolineError {
+        match err {
+            NolineError::ReadError(_) | NolineError::WriteError(_) | NolineError::Aborted
+                if !link.connected() =>
+            {
+                NolineError::Disconnected
+            }
+            err => err,
+        }
+    }
+
+    fn handle_output<'a, 'item, IO>(
+        output: Output<'a, B, dyn PromptSource<'item> + 'item, impl Completer, impl Hinter>,
         io: &mut IO,
+        link: &mut L,
     ) -> Result<Option<()>, NolineError>
     where
         IO: Read + Write,
-        I: Iterator<Item = &'item str> + Clone,
     {
-        for item in output {
-            if let Some(bytes) = item.get_bytes() {
-                io.write(bytes)?;
+        Self::handle_output_holding(output, io, link, None)
+    }
+
+    // Like [`Editor::handle_output`], holding output the IO doesn't
+    // accept in `held`, see [`Editor::write_all`]
+    fn handle_output_holding<'a, 'item, IO>(
+        output: Output<'a, B, dyn PromptSource<'item> + 'item, impl Completer, impl Hinter>,
+        io: &mut IO,
+        link: &mut L,
+        held: Option<&mut WriteBuffer<B>>,
+    ) -> Result<Option<()>, NolineError>
+    where
+        IO: Read + Write,
+    {
+        if !link.connected() {
+            return Err(NolineError::Disconnected);
+        }
+
+        Self::write_output(output, io, held).map_err(|err| match err {
+            NolineError::WriteError(_) => Self::check_link(err, link),
+            err => err,
+        })
+    }
+
+    // `write` may write only part of the bytes, e.g. when a ring
+    // buffer is nearly full, so write until all are written. Empty
+    // slices are never written, as some drivers, like usbd-serial,
+    // panic on them.
+    //
+    // Bytes left when `write` returns 0 are held in `held`, after any
+    // bytes already held, and written later with
+    // [`Editor::write_held`]. Without `held`, or if they don't fit,
+    // writing fails.
+    fn write_all<IO: Write>(
+        io: &mut IO,
+        mut bytes: &[u8],
+        held: Option<&mut WriteBuffer<B>>,
+    ) -> Result<(), NolineError> {
+        let holding = held.as_ref().is_some_and(|held| !held.is_empty());
+
+        while !bytes.is_empty() && !holding {
+            match io.write(bytes).map_err(NolineError::write_error)? {
+                0 => break,
+                len => bytes = &bytes[len..],
+            }
+        }
+
+        if bytes.is_empty() || held.is_some_and(|held| held.push(bytes)) {
+            Ok(())
+        } else {
+            Err(NolineError::WriteError(ErrorKind::WriteZero))
+        }
+    }
+
+    // Write bytes held by [`Editor::write_all`]. Returns false if the
+    // IO still doesn't accept all of them.
+    fn write_held<IO: Write>(io: &mut IO, held: &mut WriteBuffer<B>) -> Result<bool, NolineError> {
+        if held.is_empty() {
+            return Ok(true);
+        }
+
+        while !held.is_empty() {
+            match io.write(held.front()).map_err(NolineError::write_error)? {
+                0 => break,
+                len => held.consume(len),
+            }
+        }
+
+        io.flush().map_err(NolineError::write_error)?;
+
+        Ok(held.is_empty())
+    }
+
+    // Write held bytes before blocking on IO, failing if the IO doesn't
+    // accept them
+    fn write_all_held<IO: Write>(&mut self, io: &mut IO) -> Result<(), NolineError> {
+        if let Some(held) = self.write_buffer.as_mut() {
+            while !held.is_empty() {
+                let len = held.front().len();
+
+                Self::write_all(io, held.front(), None)?;
+                held.consume(len);
             }
+        }
+
+        Ok(())
+    }
+
+    // Erase a line that isn't drawn yet, from the start of the row
+    fn clear_row<IO: Write>(&mut self, io: &mut IO) -> Result<(), NolineError> {
+        let caps = self.settings.caps();
+
+        Self::write_all(io, caps.carriage_return(), self.write_buffer.as_mut())?;
+        Self::write_all(io, caps.erase_below(), self.write_buffer.as_mut())
+    }
+
+    fn write_output<'a, 'item, IO>(
+        output: Output<'a, B, dyn PromptSource<'item> + 'item, impl Completer, impl Hinter>,
+        io: &mut IO,
+        mut held: Option<&mut WriteBuffer<B>>,
+    ) -> Result<Option<()>, NolineError>
+    where
+        IO: Read + Write,
+    {
+        let mut result = Ok(None);
+        let mut written = false;
 
-            io.flush()?;
+        for item in output {
+            if let Some(bytes) = item.get_bytes().filter(|bytes| !bytes.is_empty()) {
+                Self::write_all(io, bytes, held.as_deref_mut())?;
+                written = true;
+            }
 
+            // Keep writing if the line ends before the output does,
+            // so no output is lost
             match item {
-                OutputItem::EndOfString => return Ok(Some(())),
-                OutputItem::Abort => return Err(NolineError::Aborted),
+                OutputItem::EndOfString => result = Ok(Some(())),
+                OutputItem::Abort => result = Err(NolineError::Aborted),
+                OutputItem::Eof => result = Err(NolineError::Eof),
                 _ => (),
             }
         }
 
-        Ok(None)
+        // Flush once for the whole output, as most items are only a
+        // few bytes
+        if written {
+            io.flush().map_err(NolineError::write_error)?;
+        }
+
+        result
     }
 
-    fn read_byte<IO>(io: &mut IO) -> Result<u8, NolineError>
+    // Input is read in chunks of up to `read_size` bytes, and handed
+    // out a byte at a time. The probe reads a byte at a time, so keys
+    // typed after a reply are left for the line.
+    fn read_byte<IO>(
+        io: &mut IO,
+        link: &mut L,
+        input: &mut ReadBuffer,
+        read_size: usize,
+    ) -> Result<u8, NolineError>
     where
         IO: Read + Write,
     {
-        let mut buf = [0x8; 1];
+        // Input read before the link was lost is stale
+        if !link.connected() {
+            input.clear();
+            return Err(NolineError::Disconnected);
+        }
 
-        match io.read_exact(&mut buf) {
-            Ok(_) => Ok(buf[0]),
-            Err(err) => match err {
-                ReadExactError::UnexpectedEof => Err(NolineError::Aborted),
-                ReadExactError::Other(err) => Err(err)?,
-            },
+        if let Some(byte) = input.pop() {
+            return Ok(byte);
+        }
+
+        // Read a single byte when the buffer is full of bytes held back
+        // while probing
+        let mut single = [0; 1];
+        let space = input.space(read_size);
+        let full = space.is_empty();
+        let result = if full {
+            io.read(&mut single)
+        } else {
+            io.read(space)
+        };
+
+        match result {
+            Ok(0) => Err(Self::check_link(NolineError::Aborted, link)),
+            Ok(_) if full => Ok(single[0]),
+            Ok(len) => {
+                input.filled(len);
+                Ok(input.pop().unwrap())
+            }
+            Err(err) => Err(Self::check_link(NolineError::read_error(err), link)),
         }
     }
 
     /// Read line from `stdin`
+    ///
+    /// Returns [`NolineError::Aborted`] on Ctrl+C and
+    /// [`NolineError::Eof`] on Ctrl+D on an empty line. The unfinished
+    /// line is available from [`Editor::last_line`]. With a link state,
+    /// [`NolineError::Disconnected`] is returned when the link is lost,
+    /// see [`crate::link`].
     pub fn readline<'a, 'item, IO, I>(
         &'a mut self,
         prompt: impl Into<Prompt<I>>,
         io: &mut IO,
-    ) -> Result<&str, NolineError>
+    ) -> Result<&'a str, NolineError>
     where
         IO: Read + Write,
         I: Iterator<Item = &'item str> + Clone,
     {
-        let mut line = Line::new(
-            prompt,
-            &mut self.buffer,
-            &mut self.terminal,
-            &mut self.history,
-        );
+        let mut prompt: Prompt<I> = prompt.into();
 
-        let mut reset = line.reset();
+        self.readline_inner(&mut prompt, "", io, false, None)
+    }
 
-        Self::handle_output(reset.start(), io)?;
+    /// Read line from `stdin` like [`Editor::readline`], with the
+    /// prompt set with
+    /// [`crate::builder::EditorBuilder::with_prompt`]
+    pub fn readline_default<IO>(&mut self, io: &mut IO) -> Result<&str, NolineError>
+    where
+        IO: Read + Write,
+    {
+        let mut prompt = Prompt::from(self.prompt);
 
-        loop {
-            let byte = Self::read_byte(io)?;
+        self.readline_inner(&mut prompt, "", io, false, None)
+    }
 
-            if let Some(output) = reset.advance(byte) {
-                Self::handle_output(output, io)?;
-            } else {
-                break;
-            }
-        }
+    /// Read line like [`Editor::readline`], copying it to `out` and
+    /// returning its length in bytes. The editor isn't borrowed by the
+    /// line, so it can be used again while the line is handled, e.g. to
+    /// read a confirmation.
+    ///
+    /// Returns [`NolineError::OutputTooSmall`] if the line doesn't fit
+    /// in `out`, which is then left unchanged.
+    ///
+    /// Once the line is copied, the editor's buffer is truncated, so
+    /// [`Editor::last_line`] is empty and
+    /// [`Editor::readline_preserving_buffer`] starts from an empty line.
+    /// A line that doesn't fit stays in the buffer, like after
+    /// [`Editor::readline`].
+    pub fn readline_into<'item, IO, I>(
+        &mut self,
+        prompt: impl Into<Prompt<I>>,
+        io: &mut IO,
+        out: &mut [u8],
+    ) -> Result<usize, NolineError>
+    where
+        IO: Read + Write,
+        I: Iterator<Item = &'item str> + Clone,
+    {
+        let len = copy_line(self.readline(prompt, io)?, out)?;
 
-        loop {
-            let byte = Self::read_byte(io)?;
+        self.buffer.truncate();
+        Ok(len)
+    }
 
-            if Self::handle_output(line.advance(byte), io)?.is_some() {
-                break;
-            }
-        }
+    /// Read line from `stdin`, with `rprompt` shown at the right edge
+    /// of the first row. The right-side prompt is erased when the line
+    /// grows long enough to reach it, and is not part of the line.
+    pub fn readline_with_rprompt<'a, 'item, IO, I>(
+        &'a mut self,
+        prompt: impl Into<Prompt<I>>,
+        rprompt: &str,
+        io: &mut IO,
+    ) -> Result<&'a str, NolineError>
+    where
+        IO: Read + Write,
+        I: Iterator<Item = &'item str> + Clone,
+    {
+        let mut prompt: Prompt<I> = prompt.into();
 
-        Ok(self.buffer.as_str())
+        self.readline_inner(&mut prompt, rprompt, io, false, None)
     }
 
-    /// Load history from iterator
-    pub fn load_history<'a>(&mut self, entries: impl Iterator<Item = &'a str>) -> usize {
-        self.history.load_entries(entries)
-    }
+    /// Read line from `stdin`, starting with the content of the
+    /// previous line instead of an empty buffer. The cursor is placed
+    /// at the end of the line.
+    ///
+    /// This is useful for letting the user fix a rejected command
+    /// without retyping it. Note that the buffer also holds the
+    /// previous content if `readline` was aborted.
+    pub fn readline_preserving_buffer<'a, 'item, IO, I>(
+        &'a mut self,
+        prompt: impl Into<Prompt<I>>,
+        io: &mut IO,
+    ) -> Result<&'a str, NolineError>
+    where
+        IO: Read + Write,
+        I: Iterator<Item = &'item str> + Clone,
+    {
+        let mut prompt: Prompt<I> = prompt.into();
 
-    /// Get history as iterator over circular slices
-    pub fn get_history(&self) -> impl Iterator<Item = CircularSlice<'_>> {
-        get_history_entries(&self.history)
+        self.readline_inner(&mut prompt, "", io, true, None)
     }
-}
 
-#[cfg(test)]
-pub mod tests {
-    //! IO implementation for `std`. Requires feature `std`.
+    /// Read line from `stdin`, starting with `initial` in the buffer
+    /// and the cursor at the end of it, e.g. to edit an existing
+    /// value. The initial text is edited like typed text.
+    ///
+    /// The initial text is truncated at a char boundary if it doesn't
+    /// fit in the buffer.
+    ///
+    /// Like `readline`, this abandons a line in progress with
+    /// [`Editor::readline_poll`]. The new line is drawn over it, and
+    /// its text is replaced by `initial`, so use [`Editor::last_line`]
+    /// before to keep it.
+    pub fn readline_with_initial<'a, 'item, IO, I>(
+        &'a mut self,
+        prompt: impl Into<Prompt<I>>,
+        initial: &str,
+        io: &mut IO,
+    ) -> Result<&'a str, NolineError>
+    where
+        IO: Read + Write,
+        I: Iterator<Item = &'item str> + Clone,
+    {
+        // The initial text is valid UTF-8
+        unsafe {
+            self.buffer.replace_with_slices(initial.as_bytes(), &[]);
+        }
 
-    use std::string::ToString;
-    use std::{thread, vec::Vec};
+        let mut prompt: Prompt<I> = prompt.into();
 
-    use crossbeam::channel::{unbounded, Receiver, Sender};
-    use embedded_io::{Read, Write};
+        self.readline_inner(&mut prompt, "", io, true, None)
+    }
 
-    use crate::builder::EditorBuilder;
-    use crate::testlib::{test_cases, test_editor_with_case, MockTerminal};
+    /// Read value with a fixed format like `___.___.___.___` for an
+    /// IP address. The template is displayed with `fill` marking the
+    /// cells to fill in, and the other chars of the template are
+    /// skipped by the cursor. Chars are accepted in a cell at char
+    /// index `index` of the template when `validator(c, index)`
+    /// returns true. Typing overwrites cells and deleting resets them
+    /// to `fill`, without shifting the rest of the line.
+    ///
+    /// Returns the template with the cells filled in, with unfilled
+    /// cells as `fill`. The template is truncated if it doesn't fit in
+    /// the buffer. Lines read this way are not added to history.
+    pub fn readline_masked_template<'a, 'item, IO, I>(
+        &'a mut self,
+        prompt: impl Into<Prompt<I>>,
+        template: &str,
+        fill: char,
+        validator: fn(char, usize) -> bool,
+        io: &mut IO,
+    ) -> Result<&'a str, NolineError>
+    where
+        IO: Read + Write,
+        I: Iterator<Item = &'item str> + Clone,
+    {
+        let mask = Mask::new(template, fill, validator);
+        let mut prompt: Prompt<I> = prompt.into();
 
-    struct MockStdout {
-        buffer: Vec<u8>,
-        tx: Sender<u8>,
+        self.readline_inner(&mut prompt, "", io, true, Some(&mask))
     }
 
-    impl MockStdout {
-        fn new(tx: Sender<u8>) -> Self {
-            Self {
-                buffer: Vec::new(),
-                tx,
-            }
-        }
-    }
+    /// Read line from `stdin` like [`Editor::readline`], calling `idle`
+    /// while waiting for input, e.g. to feed a watchdog. Input is read
+    /// when [`ReadReady::read_ready`] reports it available, and `idle`
+    /// is called each time it doesn't, so it should return quickly.
+    /// For more control over when input is read, see
+    /// [`Editor::readline_poll`].
+    ///
+    /// Returns [`NolineError::IdleAborted`] when `idle` returns
+    /// [`ControlFlow::Break`]. The line is left on the screen as it is,
+    /// and is available from [`Editor::last_line`].
+    pub fn readline_with_idle<'a, 'item, IO, I>(
+        &'a mut self,
+        prompt: impl Into<Prompt<I>>,
+        io: &mut IO,
+        idle: impl FnMut() -> ControlFlow<()>,
+    ) -> Result<&'a str, NolineError>
+    where
+        IO: Read + ReadReady + Write,
+        I: Iterator<Item = &'item str> + Clone,
+    {
+        let mut prompt: Prompt<I> = prompt.into();
+        let mut io = IdleIO {
+            io,
+            idle,
+            stopped: false,
+        };
 
-    struct MockStdin {
-        rx: Receiver<u8>,
-    }
+        let result = self.readline_inner(&mut prompt, "", &mut io, false, None);
 
-    impl MockStdin {
-        fn new(rx: Receiver<u8>) -> Self {
-            Self { rx }
+        if io.stopped {
+            Err(NolineError::IdleAborted)
+        } else {
+            result
         }
     }
 
-    struct MockIO {
-        stdin: MockStdin,
-        stdout: MockStdout,
-    }
+    fn readline_inner<'a, 'item, IO>(
+        &'a mut self,
+        prompt: &mut (dyn PromptSource<'item> + 'item),
+        rprompt: &str,
+        io: &mut IO,
+        preserve_buffer: bool,
+        mask: Option<&Mask<'_>>,
+    ) -> Result<&'a str, NolineError>
+    where
+        IO: Read + Write,
+    {
+        // Abandon line being polled
+        self.poll = None;
+        self.redraw = false;
+        self.hidden = None;
+        self.overflows = 0;
 
-    impl MockIO {
-        fn new(stdin: MockStdin, stdout: MockStdout) -> Self {
-            Self { stdout, stdin }
+        while !self.link_state.connected() {
+            self.link_state.wait();
         }
 
-        fn from_terminal(terminal: &mut MockTerminal) -> Self {
-            let (tx, rx) = terminal.take_io();
+        self.write_all_held(io)?;
 
-            Self::new(MockStdin::new(rx), MockStdout::new(tx.unwrap()))
-        }
+        let read_size = self.settings.read_size();
+        let input = &mut self.input;
+        let link = &mut self.link_state;
+        let mut history = self.history.borrow_history();
+        let mut line = Line::new(
+            prompt,
+            &mut self.buffer,
+            &mut self.terminal,
+            &mut *history,
+            self.settings,
+        )
+        .with_completer(self.completer.as_ref())
+        .with_validator(self.validator.as_ref())
+        .with_hinter(self.hinter.as_ref())
+        .with_kill_buffer(self.kill_buffer.as_mut())
+        .with_undo_buffer(self.undo_buffer.as_mut())
+        .with_stash_buffer(self.stash_buffer.as_mut())
+        .with_rprompt(rprompt);
 
-        fn get_pipes(self) -> (MockStdin, MockStdout) {
-            (self.stdin, self.stdout)
+        if let Some(mask) = mask {
+            line.set_mask(mask);
         }
-    }
 
-    impl embedded_io::ErrorType for MockIO {
-        type Error = embedded_io::ErrorKind;
-    }
+        let mut reset = if preserve_buffer {
+            line.reset_preserving_buffer()
+        } else {
+            line.reset()
+        };
 
-    impl embedded_io::Read for MockIO {
-        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
-            for place in &mut *buf {
-                match self.stdin.rx.recv() {
-                    Ok(byte) => *place = byte,
-                    // This should never happen as the error type is Infalliable
-                    Err(_) => return Err(Self::Error::Other),
-                }
-            }
+        input.hold();
 
-            Ok(buf.len())
+        Self::handle_output(reset.start(), io, link).map_err(NolineError::during_probe)?;
+
+        while !reset.is_done() {
+            let byte = Self::read_byte(io, link, input, 1).map_err(NolineError::during_probe)?;
+
+            if let Some(output) = reset.advance(byte).map_err(NolineError::ProbeFailed)? {
+                Self::handle_output(output, io, link).map_err(NolineError::during_probe)?;
+            }
         }
-    }
 
-    impl embedded_io::Write for MockIO {
-        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
-            self.stdout.buffer.extend(buf);
-            Ok(buf.len())
+        input.release();
+
+        Self::handle_output(line.print_rprompt(), io, link)?;
+
+        if preserve_buffer {
+            Self::handle_output(line.print_buffer(), io, link)?;
         }
 
-        fn flush(&mut self) -> Result<(), Self::Error> {
-            for byte in self.stdout.buffer.drain(0..) {
-                self.stdout.tx.send(byte).unwrap();
+        loop {
+            let byte = match line.take_type_ahead() {
+                Some(byte) => byte,
+                None => Self::read_byte(io, link, input, read_size)?,
+            };
+
+            if self.pending_newline.take() == Some(byte) {
+                continue;
             }
 
-            Ok(())
+            let output = line.advance(byte);
+
+            if output.is_overflow() {
+                self.overflows += 1;
+            }
+
+            if Self::handle_output(output, io, link)?.is_some() {
+                self.pending_newline = newline_pair(byte);
+                break;
+            }
+        }
+
+        Ok(self.buffer.as_str())
+    }
+
+    /// Read line from `stdin` without blocking, for superloop designs
+    /// where other work has to be done while waiting for input.
+    ///
+    /// Reads input as long as [`ReadReady::read_ready`] reports it
+    /// available, and returns [`PollResult::Pending`] until the line is
+    /// done. The first call starts a new line and prints the prompt,
+    /// so pass the same prompt to all calls for a line. Returns
+    /// [`PollResult::Aborted`] on Ctrl+C. Other errors are returned
+    /// like for [`Editor::readline`] and also end the line. Calling
+    /// `readline` abandons a line in progress. While the link is lost,
+    /// no line is started and [`PollResult::Pending`] is returned.
+    ///
+    /// Repeated arrow keys read in one call are coalesced, so the line
+    /// is only drawn again for the last of them. While the line is
+    /// hidden with [`Editor::hide`], no input is read and
+    /// [`PollResult::Pending`] is returned.
+    ///
+    /// With a write buffer, output isn't written while `write` returns
+    /// 0, see
+    /// [`crate::builder::EditorBuilder::with_write_buffer`]. Input
+    /// isn't read until held output is written, and
+    /// [`PollResult::Pending`] is returned. Use [`Editor::pump_output`]
+    /// to write held output without reading input, e.g. after the line
+    /// is done.
+    pub fn readline_poll<'a, 'item, IO, I>(
+        &'a mut self,
+        prompt: impl Into<Prompt<I>>,
+        io: &mut IO,
+    ) -> Result<PollResult<'a>, NolineError>
+    where
+        IO: Read + ReadReady + Write,
+        I: Iterator<Item = &'item str> + Clone,
+    {
+        if self.hidden.is_some() {
+            return Ok(PollResult::Pending);
+        }
+
+        let state = self.poll.take();
+        let start = state.is_none();
+
+        // A line in progress ends when the link is lost, and a new
+        // line isn't started until it's back
+        if !self.link_state.connected() {
+            return match state {
+                Some(_) => Err(NolineError::Disconnected),
+                None => Ok(PollResult::Pending),
+            };
+        }
+
+        if !self.pump_output(io)? {
+            self.poll = state;
+            return Ok(PollResult::Pending);
+        }
+
+        let read_size = self.settings.read_size();
+        let input = &mut self.input;
+        let link = &mut self.link_state;
+        let held = &mut self.write_buffer;
+        let mut history = self.history.borrow_history();
+        // Held as `dyn PromptSource`, so the line is compiled once
+        // for all prompt types
+        let mut prompt: Prompt<I> = prompt.into();
+        let mut line = Line::new(
+            &mut prompt as &mut dyn PromptSource<'_>,
+            &mut self.buffer,
+            &mut self.terminal,
+            &mut *history,
+            self.settings,
+        )
+        .with_completer(self.completer.as_ref())
+        .with_validator(self.validator.as_ref())
+        .with_hinter(self.hinter.as_ref())
+        .with_kill_buffer(self.kill_buffer.as_mut())
+        .with_undo_buffer(self.undo_buffer.as_mut())
+        .with_stash_buffer(self.stash_buffer.as_mut())
+        .with_state(state.unwrap_or_default());
+
+        if start {
+            self.overflows = 0;
+
+            let mut reset = line.reset();
+
+            input.hold();
+
+            Self::handle_output_holding(reset.start(), io, link, held.as_mut())
+                .map_err(NolineError::during_probe)?;
+
+            if reset.is_done() {
+                input.release();
+            }
+        } else if self.redraw && line.is_reset_done() {
+            self.redraw = false;
+
+            let pos = line.current_position();
+
+            Self::handle_output_holding(line.redraw(), io, link, held.as_mut())?;
+            Self::handle_output_holding(line.restore_cursor(pos), io, link, held.as_mut())?;
+        }
+
+        while held.as_ref().is_none_or(WriteBuffer::is_empty)
+            && ((line.is_reset_done() && line.has_type_ahead())
+                || input.has_input()
+                || io.read_ready().map_err(NolineError::read_error)?)
+        {
+            if line.is_reset_done() {
+                let byte = match line.take_type_ahead() {
+                    Some(byte) => byte,
+                    None => Self::read_byte(io, link, input, read_size)?,
+                };
+
+                line.set_input_pending(
+                    line.has_type_ahead()
+                        || input.has_input()
+                        || io.read_ready().map_err(NolineError::read_error)?,
+                );
+
+                if self.pending_newline.take() == Some(byte) {
+                    continue;
+                }
+
+                let output = line.advance(byte);
+
+                if output.is_overflow() {
+                    self.overflows += 1;
+                }
+
+                match Self::handle_output_holding(output, io, link, held.as_mut()) {
+                    Ok(Some(())) => {
+                        self.pending_newline = newline_pair(byte);
+                        return Ok(PollResult::Done(self.buffer.as_str()));
+                    }
+                    Ok(None) => (),
+                    Err(NolineError::Aborted) => return Ok(PollResult::Aborted),
+                    Err(err) => return Err(err),
+                }
+            } else {
+                let byte =
+                    Self::read_byte(io, link, input, 1).map_err(NolineError::during_probe)?;
+                let mut reset = line.resume_reset();
+
+                if let Some(output) = reset.advance(byte).map_err(NolineError::ProbeFailed)? {
+                    Self::handle_output_holding(output, io, link, held.as_mut())
+                        .map_err(NolineError::during_probe)?;
+                }
+
+                // Bytes kept from the previous line are handled next
+                if reset.is_done() {
+                    input.release();
+                }
+            }
+        }
+
+        self.poll = Some(line.into_state());
+
+        Ok(PollResult::Pending)
+    }
+
+    /// Read line from `stdin` without blocking like
+    /// [`Editor::readline_poll`], with the prompt set with
+    /// [`crate::builder::EditorBuilder::with_prompt`]
+    pub fn readline_poll_default<IO>(&mut self, io: &mut IO) -> Result<PollResult<'_>, NolineError>
+    where
+        IO: Read + ReadReady + Write,
+    {
+        let prompt = self.prompt;
+
+        self.readline_poll(prompt, io)
+    }
+
+    /// Write output held back by [`Editor::readline_poll`] because the
+    /// IO didn't accept it, see
+    /// [`crate::builder::EditorBuilder::with_write_buffer`]. Returns
+    /// true when all output is written. Call it after a line is done
+    /// and before writing other output, so the output of the line comes
+    /// first. Blocking calls like [`Editor::readline`] write held
+    /// output first, and fail if the IO doesn't accept it.
+    pub fn pump_output<IO: Write>(&mut self, io: &mut IO) -> Result<bool, NolineError> {
+        match self.write_buffer.as_mut() {
+            Some(held) => Self::write_held(io, held)
+                .map_err(|err| Self::check_link(err, &mut self.link_state)),
+            None => Ok(true),
+        }
+    }
+
+    /// Print `text` above a line in progress with
+    /// [`Editor::readline_poll`], e.g. log output, and draw the line
+    /// again below it with the cursor in place. Pass the same prompt as
+    /// to `readline_poll`. Each line of `text` is printed with a
+    /// newline, and shouldn't contain other control characters. Without
+    /// a line in progress, the text is just printed. If the terminal is
+    /// still being probed, the line is started again after the text.
+    /// While the line is hidden with [`Editor::hide`], the text is
+    /// printed where the cursor is.
+    pub fn print_above<'item, IO, I>(
+        &mut self,
+        prompt: impl Into<Prompt<I>>,
+        text: &str,
+        io: &mut IO,
+    ) -> Result<(), NolineError>
+    where
+        IO: Read + Write,
+        I: Iterator<Item = &'item str> + Clone,
+    {
+        let text = text.strip_suffix('\n').unwrap_or(text);
+
+        let state = match self.poll.take() {
+            // Drawn again when unhidden
+            Some(state) if self.hidden.is_some() => {
+                self.poll = Some(state);
+                return Self::print_lines(text, io, self.write_buffer.as_mut());
+            }
+            Some(state) if state.is_reset_done() => state,
+            state => {
+                if state.is_some() {
+                    self.clear_row(io)?;
+                }
+
+                return Self::print_lines(text, io, self.write_buffer.as_mut());
+            }
+        };
+
+        let link = &mut self.link_state;
+        let held = &mut self.write_buffer;
+        let mut history = self.history.borrow_history();
+        let mut prompt: Prompt<I> = prompt.into();
+        let mut line = Line::new(
+            &mut prompt as &mut dyn PromptSource<'_>,
+            &mut self.buffer,
+            &mut self.terminal,
+            &mut *history,
+            self.settings,
+        )
+        .with_completer(self.completer.as_ref())
+        .with_validator(self.validator.as_ref())
+        .with_hinter(self.hinter.as_ref())
+        .with_kill_buffer(self.kill_buffer.as_mut())
+        .with_undo_buffer(self.undo_buffer.as_mut())
+        .with_stash_buffer(self.stash_buffer.as_mut())
+        .with_state(state);
+
+        let pos = line.current_position();
+
+        Self::handle_output_holding(line.erase(), io, link, held.as_mut())?;
+        Self::print_lines(text, io, held.as_mut())?;
+        line.start_below(text);
+        Self::handle_output_holding(line.redraw(), io, link, held.as_mut())?;
+        Self::handle_output_holding(line.restore_cursor(pos), io, link, held.as_mut())?;
+
+        self.poll = Some(line.into_state());
+        self.redraw = false;
+
+        Ok(())
+    }
+
+    /// Erase a line in progress with [`Editor::readline_poll`] and
+    /// leave the cursor where the prompt started, e.g. before running a
+    /// full-screen program. The buffer and the rest of the line state
+    /// are kept until [`Editor::unhide`]. Pass the same prompt as to
+    /// `readline_poll`. Without a line in progress, nothing is done.
+    pub fn hide<'item, IO, I>(
+        &mut self,
+        prompt: impl Into<Prompt<I>>,
+        io: &mut IO,
+    ) -> Result<(), NolineError>
+    where
+        IO: Read + Write,
+        I: Iterator<Item = &'item str> + Clone,
+    {
+        let state = match self.poll.take() {
+            Some(state) if self.hidden.is_none() => state,
+            state => {
+                self.poll = state;
+                return Ok(());
+            }
+        };
+
+        // Not drawn yet, the probe is started again when unhiding
+        if !state.is_reset_done() {
+            self.clear_row(io)?;
+            io.flush().map_err(NolineError::write_error)?;
+
+            self.poll = Some(state);
+            self.hidden = Some(0);
+
+            return Ok(());
+        }
+
+        let link = &mut self.link_state;
+        let mut history = self.history.borrow_history();
+        let mut prompt: Prompt<I> = prompt.into();
+        let mut line = Line::new(
+            &mut prompt as &mut dyn PromptSource<'_>,
+            &mut self.buffer,
+            &mut self.terminal,
+            &mut *history,
+            self.settings,
+        )
+        .with_completer(self.completer.as_ref())
+        .with_validator(self.validator.as_ref())
+        .with_hinter(self.hinter.as_ref())
+        .with_kill_buffer(self.kill_buffer.as_mut())
+        .with_undo_buffer(self.undo_buffer.as_mut())
+        .with_stash_buffer(self.stash_buffer.as_mut())
+        .with_state(state);
+
+        let pos = line.current_position();
+
+        Self::handle_output_holding(line.erase(), io, link, self.write_buffer.as_mut())?;
+
+        self.poll = Some(line.into_state());
+        self.hidden = Some(pos);
+
+        Ok(())
+    }
+
+    /// Draw a line hidden with [`Editor::hide`] again, with the cursor
+    /// where it was. The terminal is probed first, like at the start of
+    /// a line, so the screen may have been scrolled or cleared while the
+    /// line was hidden. The line is drawn from the start of the row the
+    /// cursor is on. Blocks until the terminal replies. An error ends
+    /// the line, like for [`Editor::readline_poll`].
+    pub fn unhide<'item, IO, I>(
+        &mut self,
+        prompt: impl Into<Prompt<I>>,
+        io: &mut IO,
+    ) -> Result<(), NolineError>
+    where
+        IO: Read + Write,
+        I: Iterator<Item = &'item str> + Clone,
+    {
+        let (Some(pos), Some(state)) = (self.hidden.take(), self.poll.take()) else {
+            return Ok(());
+        };
+
+        self.write_all_held(io)?;
+
+        let input = &mut self.input;
+        let link = &mut self.link_state;
+        let mut history = self.history.borrow_history();
+        let mut prompt: Prompt<I> = prompt.into();
+        let mut line = Line::new(
+            &mut prompt as &mut dyn PromptSource<'_>,
+            &mut self.buffer,
+            &mut self.terminal,
+            &mut *history,
+            self.settings,
+        )
+        .with_completer(self.completer.as_ref())
+        .with_validator(self.validator.as_ref())
+        .with_hinter(self.hinter.as_ref())
+        .with_kill_buffer(self.kill_buffer.as_mut())
+        .with_undo_buffer(self.undo_buffer.as_mut())
+        .with_stash_buffer(self.stash_buffer.as_mut())
+        .with_state(state);
+
+        let mut reset = line.reset_preserving_buffer();
+
+        input.hold();
+
+        Self::handle_output(reset.start(), io, link).map_err(NolineError::during_probe)?;
+
+        while !reset.is_done() {
+            let byte = Self::read_byte(io, link, input, 1).map_err(NolineError::during_probe)?;
+
+            if let Some(output) = reset.advance(byte).map_err(NolineError::ProbeFailed)? {
+                Self::handle_output(output, io, link).map_err(NolineError::during_probe)?;
+            }
+        }
+
+        input.release();
+
+        Self::handle_output(line.redraw(), io, link)?;
+        Self::handle_output(line.restore_cursor(pos), io, link)?;
+
+        self.poll = Some(line.into_state());
+        self.redraw = false;
+
+        Ok(())
+    }
+
+    fn print_lines<IO: Write>(
+        text: &str,
+        io: &mut IO,
+        mut held: Option<&mut WriteBuffer<B>>,
+    ) -> Result<(), NolineError> {
+        for line in text.split('\n') {
+            Self::write_all(io, line.as_bytes(), held.as_deref_mut())?;
+            Self::write_all(io, b"\r\n", held.as_deref_mut())?;
+        }
+
+        io.flush().map_err(NolineError::write_error)?;
+
+        Ok(())
+    }
+
+    /// Set terminal size, e.g. when the terminal emulator window has
+    /// been resized. A line in progress with [`Editor::readline_poll`]
+    /// is printed again with the new size on the next call, assuming
+    /// it still starts on the same row. The size is also probed at the
+    /// start of each line, so this is only needed mid-line, or with
+    /// [`crate::builder::EditorBuilder::with_fixed_terminal_size`],
+    /// where it replaces the fixed size.
+    pub fn resize(&mut self, rows: usize, columns: usize) {
+        self.terminal.resize(rows, columns);
+        self.settings.resize(rows, columns);
+        self.redraw = self.poll.is_some();
+    }
+
+    /// Content of the line last read, also if `readline` returned an
+    /// error such as [`NolineError::Aborted`]. The content is kept
+    /// until the next `readline` call begins, or is dropped by
+    /// [`Editor::readline_into`] once it is copied.
+    pub fn last_line(&self) -> &str {
+        self.buffer.as_str()
+    }
+
+    /// Number of insertions rejected because the line buffer was full,
+    /// counted from the start of the line last read or in progress.
+    /// With [`Echo::Local`], rejected characters are counted too, and
+    /// erased from the display.
+    ///
+    /// [`Echo::Local`]: crate::builder::Echo::Local
+    pub fn overflow_count(&self) -> usize {
+        self.overflows
+    }
+
+    /// Bytes left in the line buffer, or None if it is unbounded. Can
+    /// be used to warn before the line is full.
+    pub fn remaining_capacity(&self) -> Option<usize> {
+        self.buffer.remaining_capacity()
+    }
+
+    /// Terminal size as rows and columns, as last probed. Before the
+    /// first probe, the size is assumed to be 24 rows and 80 columns.
+    pub fn terminal_size(&self) -> (usize, usize) {
+        (self.terminal.rows(), self.terminal.columns())
+    }
+
+    /// Cursor position as row and column from the top left corner,
+    /// counted from 0, as last known. The position is found when a
+    /// line starts and followed while editing. Before the first line,
+    /// it is assumed to be (0, 0).
+    pub fn cursor_position(&self) -> (usize, usize) {
+        let cursor = self.terminal.get_cursor();
+
+        (cursor.row, cursor.column)
+    }
+
+    /// Probe the terminal for its size again outside of
+    /// [`Editor::readline`], e.g. before printing output formatted to
+    /// the width of the terminal. The cursor is left where it was, and
+    /// input typed while probing is dropped. Nothing is sent with a
+    /// fixed terminal size. A line in progress with
+    /// [`Editor::readline_poll`] is printed again on the next call.
+    pub fn refresh_size<IO: Read + Write>(&mut self, io: &mut IO) -> Result<(), NolineError> {
+        self.write_all_held(io)?;
+
+        let cursor = self.terminal.get_cursor();
+        let input = &mut self.input;
+        let link = &mut self.link_state;
+        let mut history = self.history.borrow_history();
+        let mut prompt = Prompt::from("");
+        let mut line: Line<'_, B, H::History, dyn PromptSource<'_>> = Line::new(
+            &mut prompt,
+            &mut self.buffer,
+            &mut self.terminal,
+            &mut *history,
+            self.settings,
+        );
+        let mut probe = line.probe_size();
+
+        Self::handle_output(probe.start(), io, link).map_err(NolineError::during_probe)?;
+
+        while !probe.is_done() {
+            let byte = Self::read_byte(io, link, input, 1).map_err(NolineError::during_probe)?;
+
+            if let Some(output) = probe.advance(byte).map_err(NolineError::ProbeFailed)? {
+                Self::handle_output(output, io, link).map_err(NolineError::during_probe)?;
+            }
+        }
+
+        if self.poll.is_some() {
+            self.redraw = true;
+        } else {
+            self.terminal.restore_cursor(cursor);
+        }
+
+        Ok(())
+    }
+
+    /// Load history from iterator
+    pub fn load_history<'a>(&mut self, entries: impl Iterator<Item = &'a str>) -> usize {
+        self.history.borrow_history().load_entries(entries)
+    }
+
+    /// Remove all history entries
+    pub fn clear_history(&mut self) {
+        self.history.borrow_history().clear();
+    }
+
+    /// Add history entries read from `io` until end of input, see
+    /// [`crate::history::deserialize`]. Entries are decoded in the line
+    /// buffer, so entries longer than the buffer are truncated, and the
+    /// last line is cleared. A line in progress with
+    /// [`Editor::readline_poll`] is abandoned. Returns the number of
+    /// entries added.
+    pub fn restore_history<IO: Read>(&mut self, io: &mut IO) -> Result<usize, NolineError> {
+        self.poll = None;
+        self.hidden = None;
+
+        deserialize_with(io, &mut *self.history.borrow_history(), &mut self.buffer)
+            .map_err(NolineError::read_error)
+    }
+}
+
+// History owned by the editor. Shared history is accessed through its
+// `RefCell` instead.
+impl<B, H, C, L, V, T> Editor<B, H, C, L, V, T>
+where
+    B: Buffer,
+    H: History,
+    C: Completer,
+    L: LinkState,
+    V: Validator,
+    T: Hinter,
+{
+    /// Write history to `io`, see [`crate::history::serialize`]
+    pub fn save_history<IO: Write>(&self, io: &mut IO) -> Result<(), NolineError> {
+        serialize(&self.history, io).map_err(NolineError::write_error)
+    }
+
+    /// Get history as iterator over circular slices
+    pub fn get_history(&self) -> impl Iterator<Item = CircularSlice<'_>> {
+        self.history.iter_entries()
+    }
+}
+
+#[cfg(any(test, feature = "alloc", feature = "std"))]
+mod alloc {
+    extern crate alloc;
+
+    use self::alloc::string::String;
+    use super::*;
+
+    impl<B, H, C, L, V, T> Editor<B, H, C, L, V, T>
+    where
+        B: Buffer,
+        H: BorrowHistory,
+        C: Completer,
+        L: LinkState,
+        V: Validator,
+        T: Hinter,
+    {
+        /// Read line like [`Editor::readline`], returning it as an owned
+        /// string so the editor isn't borrowed by the line
+        pub fn readline_owned<'item, IO, I>(
+            &mut self,
+            prompt: impl Into<Prompt<I>>,
+            io: &mut IO,
+        ) -> Result<String, NolineError>
+        where
+            IO: Read + Write,
+            I: Iterator<Item = &'item str> + Clone,
+        {
+            self.readline(prompt, io).map(Into::into)
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    //! IO implementation for `std`. Requires feature `std`.
+
+    use core::cell::{Cell, RefCell};
+    use core::ops::ControlFlow;
+    use std::collections::VecDeque;
+    use std::format;
+    use std::rc::Rc;
+    use std::string::{String, ToString};
+    use std::{thread, vec::Vec};
+
+    use crossbeam::channel::{unbounded, Receiver, Sender};
+    use embedded_io::{Read, Write};
+
+    use crate::builder::{Echo, EditorBuilder, OverflowPolicy, TrimMode};
+    use crate::caps::{Sequence, Style, TerminalCaps};
+    use crate::conformance::{run_io_conformance, run_io_conformance_lockstep, MockPort};
+    use crate::error::{NolineError, ProbeFailure};
+    use crate::hint::HistoryHinter;
+    use crate::history::{History, NoHistory, SharedHistory, UnboundedHistory};
+    use crate::input::{Action, Parser, CSI};
+    use crate::line_buffer::LineBuffer;
+    use crate::link::LinkState;
+    use crate::push::PushIO;
+    use crate::sync_editor::{Editor, PollResult};
+    use crate::terminal::Cursor;
+    use crate::testlib::MockTerminal;
+
+    struct MockStdout {
+        buffer: Vec<u8>,
+        tx: Sender<u8>,
+    }
+
+    impl MockStdout {
+        fn new(tx: Sender<u8>) -> Self {
+            Self {
+                buffer: Vec::new(),
+                tx,
+            }
+        }
+    }
+
+    struct MockStdin {
+        rx: Receiver<u8>,
+    }
+
+    impl MockStdin {
+        fn new(rx: Receiver<u8>) -> Self {
+            Self { rx }
+        }
+    }
+
+    struct MockIO {
+        stdin: MockStdin,
+        stdout: MockStdout,
+    }
+
+    impl MockIO {
+        fn new(stdin: MockStdin, stdout: MockStdout) -> Self {
+            Self { stdout, stdin }
+        }
+
+        fn from_terminal(terminal: &mut MockTerminal) -> Self {
+            let (tx, rx) = terminal.take_io();
+
+            Self::new(MockStdin::new(rx), MockStdout::new(tx.unwrap()))
+        }
+    }
+
+    impl embedded_io::ErrorType for MockIO {
+        type Error = embedded_io::ErrorKind;
+    }
+
+    impl embedded_io::Read for MockIO {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            let Some((first, rest)) = buf.split_first_mut() else {
+                return Ok(0);
+            };
+
+            // Block for the first byte only, like a serial port
+            match self.stdin.rx.recv() {
+                Ok(byte) => *first = byte,
+                // This should never happen as the error type is Infalliable
+                Err(_) => return Err(Self::Error::Other),
+            }
+
+            let mut len = 1;
+
+            for place in rest {
+                match self.stdin.rx.try_recv() {
+                    Ok(byte) => *place = byte,
+                    Err(_) => break,
+                }
+
+                len += 1;
+            }
+
+            Ok(len)
+        }
+    }
+
+    impl embedded_io::ReadReady for MockIO {
+        fn read_ready(&mut self) -> Result<bool, Self::Error> {
+            Ok(!self.stdin.rx.is_empty())
+        }
+    }
+
+    impl embedded_io::Write for MockIO {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            assert!(!buf.is_empty(), "empty write");
+
+            self.stdout.buffer.extend(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            for byte in self.stdout.buffer.drain(0..) {
+                self.stdout.tx.send(byte).unwrap();
+            }
+
+            Ok(())
         }
     }
 
@@ -248,96 +1499,2299 @@ pub mod tests {
         }
     }
 
-    #[test]
-    fn simple_test() {
-        let (input_tx, input_rx) = unbounded();
-        let (output_tx, output_rx) = unbounded();
+    #[test]
+    fn simple_test() {
+        let (input_tx, input_rx) = unbounded();
+        let (output_tx, output_rx) = unbounded();
+
+        let mut io = MockIO::new(MockStdin::new(input_rx), MockStdout::new(output_tx));
+
+        let handle = thread::spawn(move || {
+            let mut editor = EditorBuilder::new_unbounded().build_sync(&mut io).unwrap();
+
+            if let Ok(s) = editor.readline("> ", &mut io) {
+                Some(s.to_string())
+            } else {
+                None
+            }
+        });
+
+        for &b in b"\x1b7\x1b[999;999H\x1b[6n\x1b8" {
+            let received = output_rx
+                .recv_timeout(::core::time::Duration::from_millis(1000))
+                .unwrap();
+            println!("Received {:x}, expected: {:x}", received, b);
+            assert_eq!(received, b);
+        }
+
+        for &b in b"\x1b[20;80R" {
+            input_tx.send(b).unwrap();
+        }
+
+        for &b in b"\r\x1b[J> \x1b[6n" {
+            let received = output_rx
+                .recv_timeout(::core::time::Duration::from_millis(1000))
+                .unwrap();
+            println!("Received {:x}, expected: {:x}", received, b);
+            assert_eq!(received, b);
+        }
+
+        for &b in b"\x1b[1;3R" {
+            input_tx.send(b).unwrap();
+        }
+
+        for &b in "abc\r".as_bytes() {
+            input_tx.send(b).unwrap();
+        }
+
+        assert_eq!(handle.join().unwrap(), Some("abc".to_string()));
+    }
+
+    #[test]
+    fn readline_poll() {
+        let (input_tx, input_rx) = unbounded();
+        let (output_tx, _output_rx) = unbounded();
+
+        let mut io = MockIO::new(MockStdin::new(input_rx), MockStdout::new(output_tx));
+        let mut editor = EditorBuilder::new_unbounded()
+            .with_unbounded_history()
+            .build_sync(&mut io)
+            .unwrap();
+
+        let send = |bytes: &[u8]| {
+            for &b in bytes {
+                input_tx.send(b).unwrap();
+            }
+        };
+
+        // Probe and escape sequences are split between polls
+        for input in [
+            &b""[..],
+            b"\x1b[20;8",
+            b"0R\x1b[1;3R",
+            b"ab",
+            b"c\x1b[",
+            b"D",
+        ] {
+            send(input);
+            assert_eq!(
+                editor.readline_poll("> ", &mut io).unwrap(),
+                PollResult::Pending
+            );
+        }
+
+        send(b"\r");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Done("abc")
+        );
+
+        // Next poll starts a new line
+        send(b"\x1b[20;80R\x1b[2;3Rxy\x03");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Aborted
+        );
+        assert_eq!(editor.last_line(), "xy");
+
+        // History navigation is kept between polls
+        send(b"\x1b[20;80R\x1b[3;3R\x1b[A");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Pending
+        );
+
+        send(b"\r");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Done("abc")
+        );
+    }
+
+    // Writes markers in place of every control sequence
+    struct MarkedCaps;
+
+    impl TerminalCaps for MarkedCaps {
+        fn move_to(&self, seq: &mut Sequence, row: usize, column: usize) {
+            seq.push(b"<H");
+            seq.push_decimal(row);
+            seq.push(b",");
+            seq.push_decimal(column);
+            seq.push(b">");
+        }
+
+        fn move_up(&self, seq: &mut Sequence, rows: usize) {
+            marker(seq, b"<A", rows);
+        }
+
+        fn move_down(&self, seq: &mut Sequence, rows: usize) {
+            marker(seq, b"<B", rows);
+        }
+
+        fn move_right(&self, seq: &mut Sequence, columns: usize) {
+            marker(seq, b"<C", columns);
+        }
+
+        fn scroll_up(&self, seq: &mut Sequence, rows: usize) {
+            marker(seq, b"<S", rows);
+        }
+
+        fn scroll_down(&self, seq: &mut Sequence, rows: usize) {
+            marker(seq, b"<T", rows);
+        }
+
+        fn carriage_return(&self) -> &'static [u8] {
+            b"<CR>"
+        }
+
+        fn erase_below(&self) -> &'static [u8] {
+            b"<J>"
+        }
+
+        fn bell(&self) -> &'static [u8] {
+            b"<BEL>"
+        }
+
+        fn request_position(&self) -> &'static [u8] {
+            b"<DSR>"
+        }
+
+        fn save_cursor(&self) -> &'static [u8] {
+            b"<SC>"
+        }
+
+        fn restore_cursor(&self) -> &'static [u8] {
+            b"<RC>"
+        }
+
+        fn bracketed_paste(&self, enabled: bool) -> &'static [u8] {
+            if enabled {
+                b"<PASTE>"
+            } else {
+                b"</PASTE>"
+            }
+        }
+
+        fn style(&self, style: Style) -> &'static [u8] {
+            match style {
+                Style::Plain => b"</STYLE>",
+                Style::Reverse => b"<REVERSE>",
+                Style::Dim => b"<DIM>",
+            }
+        }
+    }
+
+    fn marker(seq: &mut Sequence, name: &[u8], n: usize) {
+        seq.push(name);
+        seq.push_decimal(n);
+        seq.push(b">");
+    }
+
+    #[test]
+    fn terminal_caps() {
+        let (input_tx, input_rx) = unbounded();
+        let (output_tx, output_rx) = unbounded();
+
+        let mut io = MockIO::new(MockStdin::new(input_rx), MockStdout::new(output_tx));
+        let mut editor = EditorBuilder::new_unbounded()
+            .with_unbounded_history()
+            .with_hinter(HistoryHinter)
+            .with_bracketed_paste(true)
+            .with_terminal_caps(&MarkedCaps)
+            .build_sync(&mut io)
+            .unwrap();
+
+        let send = |bytes: &[u8]| {
+            for &b in bytes {
+                input_tx.send(b).unwrap();
+            }
+
+            String::from_utf8(output_rx.try_iter().collect()).unwrap()
+        };
+
+        send(b"\x1b[4;10R\x1b[2;3Rabc\r");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Done("abc")
+        );
+        let out = send(b"");
+        assert_eq!(
+            out,
+            "<SC><H998,998><DSR><RC><PASTE><CR><J>> <DSR>abc</PASTE>\n\r"
+        );
+
+        send(b"\x1b[4;10R\x1b[4;3Ra");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Pending
+        );
+        assert_eq!(
+            send(b""),
+            "<SC><H998,998><DSR><RC><PASTE><CR><J>> <DSR>a<DIM>bc</STYLE><H3,3>"
+        );
+
+        // Bell, erase, wrapping and clearing the screen
+        send(b"\x01\x02defghijkl\x0b\x01\x0c\r");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Done("defghijkl")
+        );
+
+        let output = send(b"");
+
+        for marker in ["<BEL>", "<J>", "<H2,9>", "<T2>", "<H0,0>", "</PASTE>"] {
+            assert!(output.contains(marker), "{} missing", marker);
+        }
+
+        assert!(!output.contains(['\x1b', '\x07']));
+
+        // Relative movement with a fixed size
+        let mut editor = EditorBuilder::new_unbounded()
+            .with_fixed_terminal_size(4, 10)
+            .with_terminal_caps(&MarkedCaps)
+            .build_sync(&mut io)
+            .unwrap();
+
+        send(b"abcdefghijkl\x01");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Pending
+        );
+        assert_eq!(send(b""), "<CR><J>> abcdefgh\n\rijkl<A1><CR><C2>");
+
+        send(b"\x05");
+        editor.readline_poll("> ", &mut io).unwrap();
+        assert_eq!(send(b""), "<B1><CR><C4>");
+    }
+
+    #[test]
+    fn resize() {
+        let (input_tx, input_rx) = unbounded();
+        let (output_tx, output_rx) = unbounded();
+
+        let mut io = MockIO::new(MockStdin::new(input_rx), MockStdout::new(output_tx));
+        let mut editor = EditorBuilder::new_unbounded().build_sync(&mut io).unwrap();
+
+        let send = |bytes: &[u8]| {
+            for &b in bytes {
+                input_tx.send(b).unwrap();
+            }
+        };
+
+        send(b"\x1b[20;80R\x1b[1;3Rabcdef\x1b[D");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Pending
+        );
+
+        editor.resize(20, 4);
+        output_rx.try_iter().count();
+
+        // Line is printed again with cursor at the same char
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Pending
+        );
+        assert_eq!(
+            output_rx.try_iter().collect::<Vec<u8>>(),
+            b"\x1b[1;1H\x1b[J> ab\n\rcdef\n\r\x1b[2;4H"
+        );
+
+        // but only once
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Pending
+        );
+        assert!(output_rx.try_iter().next().is_none());
+
+        send(b"x\r");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Done("abcdexf")
+        );
+    }
+
+    #[test]
+    fn prompt_types() {
+        let (input_tx, input_rx) = unbounded();
+        let (output_tx, output_rx) = unbounded();
+
+        let mut io = MockIO::new(MockStdin::new(input_rx), MockStdout::new(output_tx));
+        let mut editor = EditorBuilder::new_unbounded().build_sync(&mut io).unwrap();
+
+        let send = |bytes: &[u8]| {
+            for &b in bytes {
+                input_tx.send(b).unwrap();
+            }
+        };
+        let printed = |prompt: &[u8]| {
+            output_rx
+                .try_iter()
+                .collect::<Vec<u8>>()
+                .windows(prompt.len())
+                .any(|window| window == prompt)
+        };
+
+        // The prompt type is erased before the line is created, so all
+        // three share the same line and output code
+        send(b"\x1b[20;80R\x1b[1;3Rab\r");
+        assert_eq!(editor.readline("> ", &mut io).unwrap(), "ab");
+        assert!(printed(b"\r\x1b[J> "));
+
+        send(b"\x1b[20;80R\x1b[2;7Rcd\r");
+        assert_eq!(
+            editor
+                .readline(["dev", ":", "> "].into_iter(), &mut io)
+                .unwrap(),
+            "cd"
+        );
+        assert!(printed(b"\r\x1b[Jdev:> "));
+
+        send(b"\x1b[20;80R\x1b[3;10Ref\r");
+        let parts = "usr/bin".split('/').chain(core::iter::once(" > "));
+        assert_eq!(editor.readline(parts, &mut io).unwrap(), "ef");
+        assert!(printed(b"\r\x1b[Jusrbin > "));
+    }
+
+    #[test]
+    fn default_prompt() {
+        let (input_tx, input_rx) = unbounded();
+        let (output_tx, output_rx) = unbounded();
+
+        let mut io = MockIO::new(MockStdin::new(input_rx), MockStdout::new(output_tx));
+        let mut editor = EditorBuilder::new_unbounded()
+            .with_prompt("$ ")
+            .build_sync(&mut io)
+            .unwrap();
+
+        let send = |bytes: &[u8]| {
+            for &b in bytes {
+                input_tx.send(b).unwrap();
+            }
+        };
+        let printed = |prompt: &[u8]| {
+            output_rx
+                .try_iter()
+                .collect::<Vec<u8>>()
+                .windows(prompt.len())
+                .any(|window| window == prompt)
+        };
+
+        send(b"\x1b[20;80R\x1b[1;3Rab\r");
+        assert_eq!(editor.readline_default(&mut io).unwrap(), "ab");
+        assert!(printed(b"$ "));
+
+        send(b"\x1b[20;80R\x1b[2;3Rcd\r");
+        assert_eq!(editor.readline("# ", &mut io).unwrap(), "cd");
+        assert!(printed(b"# "));
+
+        send(b"\x1b[20;80R\x1b[3;3Refgh");
+        assert_eq!(
+            editor.readline_poll_default(&mut io).unwrap(),
+            PollResult::Pending
+        );
+        assert!(printed(b"$ "));
+
+        // Redrawn with the default prompt
+        editor.resize(20, 4);
+        assert_eq!(
+            editor.readline_poll_default(&mut io).unwrap(),
+            PollResult::Pending
+        );
+        assert_eq!(
+            output_rx.try_iter().collect::<Vec<u8>>(),
+            b"\x1b[3;1H\x1b[J$ ef\n\rgh\x1b[4;3H"
+        );
+
+        send(b"\r");
+        assert_eq!(
+            editor.readline_poll_default(&mut io).unwrap(),
+            PollResult::Done("efgh")
+        );
+    }
+
+    #[test]
+    fn editors_sharing_io() {
+        let (input_tx, input_rx) = unbounded();
+        let (output_tx, _output_rx) = unbounded();
+
+        let mut io = MockIO::new(MockStdin::new(input_rx), MockStdout::new(output_tx));
+        let mut first = EditorBuilder::new_unbounded().build_sync(&mut io).unwrap();
+        let mut second = EditorBuilder::new_unbounded().build_sync(&mut io).unwrap();
+
+        let send = |bytes: &[u8]| {
+            for &b in bytes {
+                input_tx.send(b).unwrap();
+            }
+        };
+
+        // One line at a time works
+        send(b"\x1b[20;80R\x1b[1;3Rabc\r");
+        assert_eq!(
+            first.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Done("abc")
+        );
+
+        send(b"\x1b[20;80R\x1b[2;4Rdef\r");
+        assert_eq!(
+            second.readline_poll(">> ", &mut io).unwrap(),
+            PollResult::Done("def")
+        );
+
+        // Both editors probe at once, so the first editor gets the
+        // reply to the second editor's size probe
+        assert_eq!(
+            first.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Pending
+        );
+        assert_eq!(
+            second.readline_poll(">> ", &mut io).unwrap(),
+            PollResult::Pending
+        );
+
+        send(b"\x1b[20;80R\x1b[20;80R");
+        assert!(matches!(
+            first.readline_poll("> ", &mut io),
+            Err(NolineError::ProbeFailed(ProbeFailure::UnexpectedReply(
+                20, 80
+            )))
+        ));
+    }
+
+    #[test]
+    fn mock_stdin() {
+        let (tx, rx) = unbounded();
+
+        let mut io = MockIO::new(MockStdin::new(rx), MockStdout::new(tx));
+        for i in 0u8..10 {
+            io.write(&[i]).unwrap();
+        }
+
+        io.flush().unwrap();
+
+        let mut buf = [0];
+        for i in 0..10 {
+            io.read(&mut buf).unwrap();
+
+            assert_eq!(buf[0], i);
+        }
+    }
+
+    #[test]
+    fn editor() {
+        run_io_conformance(MockIO::from_terminal).unwrap();
+    }
+
+    #[test]
+    fn editor_lockstep() {
+        run_io_conformance_lockstep(|port| port).unwrap();
+    }
+
+    // Port sending a key to the terminal before each write, like a
+    // wrapper mixing up input and output
+    struct EchoKeyIO(MockPort);
+
+    impl embedded_io::ErrorType for EchoKeyIO {
+        type Error = embedded_io::ErrorKind;
+    }
+
+    impl embedded_io::Read for EchoKeyIO {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            self.0.read(buf)
+        }
+    }
+
+    impl embedded_io::Write for EchoKeyIO {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.0.write(b"\x1b[1~")?;
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            self.0.flush()
+        }
+    }
+
+    #[test]
+    fn editor_lockstep_unsupported() {
+        let failure = run_io_conformance_lockstep(EchoKeyIO).unwrap_err();
+
+        assert_eq!(failure.actual_lines, failure.expected_lines);
+        assert_eq!(failure.actual_screen, failure.expected_screen);
+        assert_eq!(failure.unsupported[0], "ControlSequenceIntroducer(Home)");
+        assert!(failure.to_string().contains("unsupported output"));
+    }
+
+    /// IO replying with fixed input, then end of file
+    struct ScriptedIO<'a> {
+        input: &'a [u8],
+    }
+
+    impl embedded_io::ErrorType for ScriptedIO<'_> {
+        type Error = embedded_io::ErrorKind;
+    }
+
+    impl embedded_io::Read for ScriptedIO<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            let len = buf.len().min(self.input.len());
+            let (head, tail) = self.input.split_at(len);
+
+            buf[..len].copy_from_slice(head);
+            self.input = tail;
+
+            Ok(len)
+        }
+    }
+
+    impl embedded_io::Write for ScriptedIO<'_> {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn interrupted_and_eof() {
+        fn readline(input: &[u8]) -> NolineError {
+            let mut io = ScriptedIO { input };
+            let mut editor = EditorBuilder::new_unbounded().build_sync(&mut io).unwrap();
+
+            editor.readline("> ", &mut io).unwrap_err()
+        }
+
+        assert!(matches!(
+            readline(b"\x1b[20;80R\x1b[1;3R\x03"),
+            NolineError::Aborted
+        ));
+
+        assert!(matches!(
+            readline(b"\x1b[20;80R\x1b[1;3R\x04"),
+            NolineError::Eof
+        ));
+
+        // Ctrl+D on a non-empty line rings the bell, so input runs out
+        assert!(matches!(
+            readline(b"\x1b[20;80R\x1b[1;3Rabc\x03"),
+            NolineError::Aborted
+        ));
+
+        assert!(matches!(
+            readline(b"\x1b[20;80R\x1b[1;3Rabc\x04"),
+            NolineError::Aborted
+        ));
+    }
+
+    #[test]
+    fn pasted_lines() {
+        // Lines after the first are left from the first read, as the
+        // input then runs out
+        let mut io = ScriptedIO {
+            input: b"abc\rdef\rghi\r",
+        };
+        let mut editor = EditorBuilder::new_unbounded()
+            .with_fixed_terminal_size(4, 20)
+            .build_sync(&mut io)
+            .unwrap();
+
+        assert_eq!(editor.readline("> ", &mut io).unwrap(), "abc");
+        assert!(io.input.is_empty());
+        assert_eq!(editor.readline("> ", &mut io).unwrap(), "def");
+        assert_eq!(editor.readline("> ", &mut io).unwrap(), "ghi");
+
+        // With a smaller read size, the rest is read by the next line
+        let mut io = ScriptedIO {
+            input: b"abc\rdef\r",
+        };
+        let mut editor = EditorBuilder::new_unbounded()
+            .with_fixed_terminal_size(4, 20)
+            .with_read_size(2)
+            .build_sync(&mut io)
+            .unwrap();
+
+        assert_eq!(editor.readline("> ", &mut io).unwrap(), "abc");
+        assert_eq!(io.input, b"def\r");
+        assert_eq!(editor.readline("> ", &mut io).unwrap(), "def");
+    }
+
+    #[test]
+    fn last_line_after_abort() {
+        let mut io = ScriptedIO {
+            input: b"\x1b[20;80R\x1b[1;3Rhello\x03",
+        };
+        let mut editor = EditorBuilder::new_unbounded().build_sync(&mut io).unwrap();
+
+        assert!(matches!(
+            editor.readline("> ", &mut io),
+            Err(NolineError::Aborted)
+        ));
+        assert_eq!(editor.last_line(), "hello");
+
+        let mut io = ScriptedIO {
+            input: b"\x1b[20;80R\x1b[1;3Rworld\r",
+        };
+
+        assert_eq!(editor.readline("> ", &mut io).unwrap(), "world");
+        assert_eq!(editor.last_line(), "world");
+    }
+
+    #[test]
+    fn validator() {
+        let mut io = ScriptedIO {
+            input: b"\x1b[20;80R\x1b[1;3Rfoo\r\x01cmd \r",
+        };
+        let mut editor = EditorBuilder::new_unbounded()
+            .with_validator(|line: &str| line.starts_with("cmd"))
+            .build_sync(&mut io)
+            .unwrap();
+
+        assert_eq!(editor.readline("> ", &mut io).unwrap(), "cmd foo");
+    }
+
+    #[test]
+    fn masked_template() {
+        let mut io = ScriptedIO {
+            input: b"\x1b[20;80R\x1b[1;5R1000a1\r",
+        };
+        let mut editor = EditorBuilder::new_unbounded().build_sync(&mut io).unwrap();
+
+        let value = editor
+            .readline_masked_template("IP: ", "___.___", '_', |c, _| c.is_ascii_digit(), &mut io)
+            .unwrap();
+
+        assert_eq!(value, "100.01_");
+    }
+
+    #[test]
+    fn input_while_probing() {
+        let mut io = ScriptedIO {
+            input: b"junk\x1b[20;80R\njunk\x1b[1;3R!\r",
+        };
+        let mut editor = EditorBuilder::new_unbounded().build_sync(&mut io).unwrap();
+
+        // Printable input is kept, other input is discarded
+        assert_eq!(editor.readline("> ", &mut io).unwrap(), "junkjunk!");
+    }
+
+    /// IO with fixed input and output drawn on a mock terminal that
+    /// never replies
+    struct SilentTerminalIO<'a> {
+        input: ScriptedIO<'a>,
+        terminal: MockTerminal,
+        written: Vec<u8>,
+    }
+
+    impl embedded_io::ErrorType for SilentTerminalIO<'_> {
+        type Error = embedded_io::ErrorKind;
+    }
+
+    impl embedded_io::Read for SilentTerminalIO<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            self.input.read(buf)
+        }
+    }
+
+    impl embedded_io::Write for SilentTerminalIO<'_> {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            for &b in buf {
+                self.terminal.advance(b);
+            }
+
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn fixed_terminal_size() {
+        let mut io = SilentTerminalIO {
+            input: ScriptedIO {
+                input: b"abcdefghijklmnopqrstu\x01\x1b[C\x1b[CX\r",
+            },
+            terminal: MockTerminal::new(6, 10, Cursor::new(2, 0)),
+            written: Vec::new(),
+        };
+        let mut editor = EditorBuilder::new_unbounded()
+            .with_fixed_terminal_size(6, 10)
+            .build_sync(&mut io)
+            .unwrap();
+
+        assert_eq!(
+            editor.readline("> ", &mut io).unwrap(),
+            "abXcdefghijklmnopqrstu"
+        );
+
+        // Drawn where the cursor was, without asking for or setting
+        // the cursor position
+        assert_eq!(io.terminal.row_as_string(2), "> abXcdefg");
+        assert_eq!(io.terminal.row_as_string(3), "hijklmnopq");
+        assert_eq!(io.terminal.row_as_string(4), "rstu");
+
+        let mut parser = Parser::new();
+
+        for &b in &io.written {
+            assert!(!matches!(
+                parser.advance(b),
+                Action::ControlSequenceIntroducer(CSI::CUP(..) | CSI::DSR)
+            ));
+        }
+    }
+
+    #[test]
+    fn initial_line() {
+        let mut io = SilentTerminalIO {
+            input: ScriptedIO {
+                input: b"\x7f\x7f20\r\x1b[A\x1b[B\x01!\r",
+            },
+            terminal: MockTerminal::new(6, 10, Cursor::new(0, 0)),
+            written: Vec::new(),
+        };
+        let mut editor = EditorBuilder::new_unbounded()
+            .with_unbounded_history()
+            .with_fixed_terminal_size(6, 10)
+            .build_sync(&mut io)
+            .unwrap();
+
+        assert_eq!(
+            editor
+                .readline_with_initial("> ", "192.168.1.10", &mut io)
+                .unwrap(),
+            "192.168.1.20"
+        );
+
+        // Wrapped when first drawn
+        assert_eq!(io.terminal.row_as_string(0), "> 192.168.");
+        assert_eq!(io.terminal.row_as_string(1), "1.20");
+
+        // Navigating history comes back to the initial text
+        assert_eq!(
+            editor
+                .readline_with_initial("> ", "10.0.0.1", &mut io)
+                .unwrap(),
+            "!10.0.0.1"
+        );
+        assert_eq!(
+            editor
+                .get_history()
+                .map(|entry| entry.as_str(&mut [0; 16]).unwrap().to_string())
+                .collect::<Vec<_>>(),
+            ["192.168.1.20", "!10.0.0.1"]
+        );
+    }
+
+    #[test]
+    fn initial_line_abandons_poll() {
+        let mut io = TerminalIO {
+            terminal: MockTerminal::new(4, 20, Cursor::new(0, 0)),
+            input: b"abc".iter().copied().collect(),
+        };
+        let mut editor = EditorBuilder::new_unbounded()
+            .with_fixed_terminal_size(4, 20)
+            .build_sync(&mut io)
+            .unwrap();
+
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Pending
+        );
+        assert_eq!(editor.last_line(), "abc");
+
+        io.input.extend(b"z\r");
+        assert_eq!(
+            editor.readline_with_initial("> ", "xy", &mut io).unwrap(),
+            "xyz"
+        );
+        // Drawn over the abandoned line
+        assert_eq!(io.terminal.screen_as_string(), "> xyz");
+
+        // Polling starts a new line
+        io.input.extend(b"d\r");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Done("d")
+        );
+    }
+
+    #[test]
+    fn initial_line_truncated() {
+        let mut io = ScriptedIO {
+            input: b"\x1b[20;80R\x1b[1;3R\r",
+        };
+        let mut buffer = [0; 8];
+        let mut editor = EditorBuilder::from_slice(&mut buffer)
+            .build_sync(&mut io)
+            .unwrap();
+
+        assert_eq!(
+            editor
+                .readline_with_initial("> ", "192.168.1.10", &mut io)
+                .unwrap(),
+            "192.168."
+        );
+    }
+
+    // Counts flushes between reads
+    struct FlushCountingIO<'a> {
+        io: SilentTerminalIO<'a>,
+        flushes: usize,
+        // Flushes before each read, i.e. for each byte handled
+        flushes_per_read: Vec<usize>,
+    }
+
+    impl embedded_io::ErrorType for FlushCountingIO<'_> {
+        type Error = embedded_io::ErrorKind;
+    }
+
+    impl embedded_io::Read for FlushCountingIO<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            self.flushes_per_read.push(self.flushes);
+            self.flushes = 0;
+            self.io.read(buf)
+        }
+    }
+
+    impl embedded_io::Write for FlushCountingIO<'_> {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.io.write(buf)
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            self.flushes += 1;
+            self.io.flush()
+        }
+    }
+
+    #[test]
+    fn flush_per_keystroke() {
+        let mut io = FlushCountingIO {
+            io: SilentTerminalIO {
+                input: ScriptedIO {
+                    input: b"ab\x01\x1b[Cx\r",
+                },
+                terminal: MockTerminal::new(4, 10, Cursor::new(0, 0)),
+                written: Vec::new(),
+            },
+            flushes: 0,
+            flushes_per_read: Vec::new(),
+        };
+        // A byte per read, so flushes are counted per byte handled
+        let mut editor = EditorBuilder::new_unbounded()
+            .with_fixed_terminal_size(4, 10)
+            .with_read_size(1)
+            .build_sync(&mut io)
+            .unwrap();
+
+        assert_eq!(editor.readline("> ", &mut io).unwrap(), "axb");
+        assert_eq!(io.io.terminal.screen_as_string(), "> axb");
+
+        io.flushes_per_read.push(io.flushes);
+
+        // Prompt, then one flush for each key echoed, none for the
+        // start of the escape sequence
+        assert_eq!(io.flushes_per_read, [1, 1, 1, 1, 0, 0, 1, 1, 1]);
+    }
+
+    // Writes at most `max_write` bytes per call
+    struct PartialWriteIO<'a> {
+        io: SilentTerminalIO<'a>,
+        max_write: usize,
+    }
+
+    impl embedded_io::ErrorType for PartialWriteIO<'_> {
+        type Error = embedded_io::ErrorKind;
+    }
+
+    impl embedded_io::Read for PartialWriteIO<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            self.io.read(buf)
+        }
+    }
+
+    impl embedded_io::Write for PartialWriteIO<'_> {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            let len = buf.len().min(self.max_write);
+
+            self.io.write(&buf[..len])
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            self.io.flush()
+        }
+    }
+
+    #[test]
+    fn partial_writes() {
+        fn readline(max_write: usize) -> (Result<String, NolineError>, Vec<u8>, String) {
+            let mut io = PartialWriteIO {
+                io: SilentTerminalIO {
+                    input: ScriptedIO {
+                        input: b"hello world\x01\x1b[Cx\x1b[D\x0b\r",
+                    },
+                    terminal: MockTerminal::new(4, 10, Cursor::new(0, 0)),
+                    written: Vec::new(),
+                },
+                max_write,
+            };
+            let mut editor = EditorBuilder::new_unbounded()
+                .with_fixed_terminal_size(4, 10)
+                .build_sync(&mut io)
+                .unwrap();
+
+            let line = editor.readline("> ", &mut io).map(|line| line.to_string());
+
+            (line, io.io.written, io.io.terminal.screen_as_string())
+        }
+
+        let (line, written, screen) = readline(usize::MAX);
+        assert_eq!(line.unwrap(), "h");
+        assert_eq!(screen, "> h");
+
+        let (partial_line, partial_written, partial_screen) = readline(3);
+        assert_eq!(partial_line.unwrap(), "h");
+        assert_eq!(partial_written, written);
+        assert_eq!(partial_screen, screen);
+
+        // The prompt is written while probing
+        let (line, _, _) = readline(0);
+        assert!(matches!(
+            line,
+            Err(NolineError::ProbeFailed(ProbeFailure::IoError(
+                embedded_io::ErrorKind::WriteZero
+            )))
+        ));
+    }
+
+    // Read line from history with all input queued at once, or one
+    // byte per poll. Returns line, output and screen.
+    fn navigate_history(input: &[u8], queued: bool) -> (String, Vec<u8>, String) {
+        let written = RefCell::new(Vec::new());
+        let mut io: PushIO<_, 512> = PushIO::new(
+            |bytes: &[u8]| written.borrow_mut().extend_from_slice(bytes),
+            usize::MAX,
+        );
+        let mut editor = EditorBuilder::new_unbounded()
+            .with_unbounded_history()
+            .with_fixed_terminal_size(4, 20)
+            .build_sync(&mut io)
+            .unwrap();
+        let entries: Vec<String> = (0..150).map(|i| format!("entry {}", i)).collect();
+
+        editor.load_history(entries.iter().map(|entry| entry.as_str()));
+
+        let chunk = if queued { input.len() } else { 1 };
+        let mut chunks = input.chunks(chunk);
+
+        let line = loop {
+            match editor.readline_poll("> ", &mut io).unwrap() {
+                PollResult::Done(line) => break line.to_string(),
+                PollResult::Aborted => panic!("aborted"),
+                PollResult::Pending => {
+                    io.push_input(chunks.next().expect("line not done"));
+                }
+            }
+        };
+
+        let output = written.borrow().clone();
+        let mut terminal = MockTerminal::new(4, 20, Cursor::new(0, 0));
+
+        for &b in &output {
+            terminal.advance(b);
+        }
+
+        (line, output, terminal.screen_as_string())
+    }
+
+    #[test]
+    fn coalesce_navigation() {
+        fn redraws(output: &[u8]) -> usize {
+            output.windows(3).filter(|w| w == b"\x1b[J").count()
+        }
+
+        let mut input = b"\x1b[A".repeat(100);
+        input.extend_from_slice(b"\x1b[D\x1b[D\x1b[DX\r");
+
+        let (line, output, screen) = navigate_history(&input, false);
+        assert_eq!(line, "entryX 50");
+        assert_eq!(screen, "> entryX 50");
+        assert!(redraws(&output) > 100);
+
+        // Queued keys draw the line once, after drawing the prompt
+        let (queued_line, queued_output, queued_screen) = navigate_history(&input, true);
+        assert_eq!(queued_line, line);
+        assert_eq!(queued_screen, screen);
+        assert_eq!(redraws(&queued_output), 2);
+
+        // Left and Right only move the cursor
+        let input = b"abc\x1b[D\x1b[D\x1b[D\x1b[C\x02X\r";
+        let (line, output, screen) = navigate_history(input, false);
+        let (queued_line, queued_output, queued_screen) = navigate_history(input, true);
+        assert_eq!(queued_line, line);
+        assert_eq!(queued_line, "Xabc");
+        assert_eq!(queued_screen, screen);
+        assert_eq!(redraws(&queued_output), redraws(&output));
+        assert!(queued_output.len() < output.len());
+
+        // Other keys, here Home, are handled after drawing the line
+        let input = b"\x1b[A\x1b[A\x1b[HY\r";
+        let (line, _, screen) = navigate_history(input, false);
+        let (queued_line, _, queued_screen) = navigate_history(input, true);
+        assert_eq!(queued_line, line);
+        assert_eq!(queued_line, "Yentry 148");
+        assert_eq!(queued_screen, screen);
+
+        // Keys too long to be replayed, here Delete with a padded
+        // argument, are handled after drawing the line too
+        let input = b"\x1b[A\x1b[A\x1b[D\x1b[D\x1b[D\x1b[0000000000003~Z\r";
+        let (line, _, screen) = navigate_history(input, false);
+        let (queued_line, _, queued_screen) = navigate_history(input, true);
+        assert_eq!(queued_line, line);
+        assert_eq!(queued_line, "entry Z48");
+        assert_eq!(queued_screen, screen);
+    }
+
+    #[test]
+    fn newline_pairs() {
+        for input in [b"abc\r\ndef\r\n", b"abc\n\rdef\n\r"] {
+            let mut io = ScriptedIO { input };
+            let mut editor = EditorBuilder::new_unbounded()
+                .with_fixed_terminal_size(4, 20)
+                .build_sync(&mut io)
+                .unwrap();
+
+            assert_eq!(editor.readline("> ", &mut io).unwrap(), "abc");
+            assert_eq!(editor.readline("> ", &mut io).unwrap(), "def");
+            assert!(matches!(
+                editor.readline("> ", &mut io),
+                Err(NolineError::Aborted)
+            ));
+        }
+
+        // Bytes read before probing are handled after it
+        let mut io = TerminalIO {
+            terminal: MockTerminal::new(4, 20, Cursor::new(0, 0)),
+            input: VecDeque::new(),
+        };
+        let mut editor = EditorBuilder::new_unbounded()
+            .with_read_size(16)
+            .build_sync(&mut io)
+            .unwrap();
+
+        // Only a pair of different bytes is one newline
+        io.input.extend(b"abc\r\n\r\n\n");
+        assert_eq!(editor.readline("> ", &mut io).unwrap(), "abc");
+        assert_eq!(editor.readline("> ", &mut io).unwrap(), "");
+        assert_eq!(editor.readline("> ", &mut io).unwrap(), "");
+        assert_eq!(io.terminal.screen_as_string(), "> abc\n> \n> ");
+    }
+
+    #[test]
+    fn probe_failed() {
+        fn readline(input: &[u8]) -> NolineError {
+            let mut io = ScriptedIO { input };
+            let mut editor = EditorBuilder::new_unbounded().build_sync(&mut io).unwrap();
+
+            editor.readline("> ", &mut io).unwrap_err()
+        }
+
+        assert!(matches!(
+            readline(&[b"\n".as_slice(), &[b'j'; 64]].concat()),
+            NolineError::ProbeFailed(ProbeFailure::UnexpectedInput(sequence))
+                if sequence.as_bytes() == b"\n"
+        ));
+
+        assert!(matches!(
+            readline(&[b"\x1b[20;80R".as_slice(), &[b'j'; 64]].concat()),
+            NolineError::ProbeFailed(ProbeFailure::UnexpectedInput(sequence))
+                if sequence.as_bytes() == b"j"
+        ));
+
+        // The whole sequence is kept, e.g. a device attributes reply
+        // from a terminal answering the wrong query
+        assert!(matches!(
+            readline(&[b"\x1b[<0;1;1M\x1b[?1;2c".as_slice(), &[b'j'; 64]].concat()),
+            NolineError::ProbeFailed(ProbeFailure::UnexpectedInput(sequence))
+                if sequence.as_bytes() == b"\x1b[?1;2c"
+        ));
+
+        assert!(matches!(
+            readline(b"\x1b[20;80Rjunk"),
+            NolineError::ProbeFailed(ProbeFailure::Timeout)
+        ));
+
+        assert!(matches!(
+            readline(b""),
+            NolineError::ProbeFailed(ProbeFailure::Timeout)
+        ));
+
+        assert!(matches!(
+            readline(b"\x1b[20;80R\x1b[1;3R"),
+            NolineError::Aborted
+        ));
+
+        assert_eq!(
+            readline(b"").to_string(),
+            "terminal probe failed: terminal did not reply to cursor position query"
+        );
+    }
+
+    // IO failing reads when input runs out, and writes of output
+    // containing `fail_write_on`
+    struct FailingIO<'a> {
+        input: &'a [u8],
+        fail_write_on: u8,
+    }
+
+    impl embedded_io::ErrorType for FailingIO<'_> {
+        type Error = embedded_io::ErrorKind;
+    }
+
+    impl embedded_io::Read for FailingIO<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            if self.input.is_empty() {
+                return Err(embedded_io::ErrorKind::TimedOut);
+            }
+
+            let len = buf.len().min(self.input.len());
+            let (head, tail) = self.input.split_at(len);
+
+            buf[..len].copy_from_slice(head);
+            self.input = tail;
+
+            Ok(len)
+        }
+    }
+
+    impl embedded_io::Write for FailingIO<'_> {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            if buf.contains(&self.fail_write_on) {
+                Err(embedded_io::ErrorKind::BrokenPipe)
+            } else {
+                Ok(buf.len())
+            }
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn read_and_write_errors() {
+        fn readline(input: &[u8]) -> NolineError {
+            let mut io = FailingIO {
+                input,
+                fail_write_on: b'x',
+            };
+            let mut editor = EditorBuilder::new_unbounded().build_sync(&mut io).unwrap();
+
+            editor.readline("> ", &mut io).unwrap_err()
+        }
+
+        assert!(matches!(
+            readline(b"\x1b[20;80R\x1b[1;3Rabc"),
+            NolineError::ReadError(embedded_io::ErrorKind::TimedOut)
+        ));
+
+        // Echo of x fails
+        assert!(matches!(
+            readline(b"\x1b[20;80R\x1b[1;3Rabcx\r"),
+            NolineError::WriteError(embedded_io::ErrorKind::BrokenPipe)
+        ));
+
+        // Errors while probing are probe failures
+        assert!(matches!(
+            readline(b"\x1b[20;80R"),
+            NolineError::ProbeFailed(ProbeFailure::IoError(embedded_io::ErrorKind::TimedOut))
+        ));
+
+        let mut io = FailingIO {
+            input: b"",
+            fail_write_on: 0,
+        };
+        let mut editor = EditorBuilder::new_unbounded()
+            .with_unbounded_history()
+            .build_sync(&mut io)
+            .unwrap();
+
+        assert!(matches!(
+            editor.restore_history(&mut io),
+            Err(NolineError::ReadError(embedded_io::ErrorKind::TimedOut))
+        ));
+
+        editor.load_history(["one", "two"].into_iter());
+
+        assert!(matches!(
+            editor.save_history(&mut io),
+            Err(NolineError::WriteError(embedded_io::ErrorKind::BrokenPipe))
+        ));
+    }
+
+    // IO failing reads and dropping the link when input runs out, like
+    // a USB CDC port closed by the host
+    struct LinkIO<'a> {
+        input: &'a [u8],
+        output: Vec<u8>,
+        connected: &'a Cell<bool>,
+    }
+
+    impl embedded_io::ErrorType for LinkIO<'_> {
+        type Error = embedded_io::ErrorKind;
+    }
+
+    impl embedded_io::Read for LinkIO<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            if self.input.is_empty() {
+                self.connected.set(false);
+                return Err(embedded_io::ErrorKind::NotConnected);
+            }
+
+            let len = buf.len().min(self.input.len());
+            let (head, tail) = self.input.split_at(len);
+
+            buf[..len].copy_from_slice(head);
+            self.input = tail;
+
+            Ok(len)
+        }
+    }
+
+    impl embedded_io::ReadReady for LinkIO<'_> {
+        fn read_ready(&mut self) -> Result<bool, Self::Error> {
+            Ok(!self.input.is_empty())
+        }
+    }
+
+    impl embedded_io::Write for LinkIO<'_> {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.output.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn link_lost() {
+        let connected = Cell::new(true);
+        let polls = Cell::new(0);
+        let mut io = LinkIO {
+            input: b"\x1b[20;80R\x1b[1;3Rabc",
+            output: Vec::new(),
+            connected: &connected,
+        };
+
+        // Reconnect after polling a few times
+        let link = || {
+            if !connected.get() {
+                polls.set(polls.get() + 1);
+                connected.set(polls.get() == 3);
+            }
+
+            connected.get()
+        };
+
+        let mut editor = EditorBuilder::new_unbounded()
+            .with_link_state(link)
+            .build_sync(&mut io)
+            .unwrap();
+
+        assert!(matches!(
+            editor.readline("> ", &mut io),
+            Err(NolineError::Disconnected)
+        ));
+        assert_eq!(editor.last_line(), "abc");
+        assert_eq!(polls.get(), 1);
+
+        // Waits for the link and probes the terminal again
+        io.input = b"\x1b[20;80R\x1b[1;3Rdef\r";
+        io.output.clear();
+
+        assert_eq!(editor.readline("> ", &mut io).unwrap(), "def");
+        assert_eq!(polls.get(), 3);
+        assert!(io.output.starts_with(b"\x1b7\x1b[999;999H\x1b[6n\x1b8"));
+    }
+
+    // Link reconnected after waiting for it a few times
+    struct WaitingLink<'a> {
+        connected: &'a Cell<bool>,
+        waits: usize,
+    }
+
+    impl LinkState for WaitingLink<'_> {
+        fn connected(&mut self) -> bool {
+            self.connected.get()
+        }
+
+        fn wait(&mut self) {
+            self.waits += 1;
+            self.connected.set(self.waits == 3);
+        }
+    }
+
+    #[test]
+    fn link_wait() {
+        let connected = Cell::new(true);
+        let mut io = LinkIO {
+            input: b"\x1b[20;80R\x1b[1;3Rabc",
+            output: Vec::new(),
+            connected: &connected,
+        };
+
+        let mut editor = EditorBuilder::new_unbounded()
+            .with_link_state(WaitingLink {
+                connected: &connected,
+                waits: 0,
+            })
+            .build_sync(&mut io)
+            .unwrap();
+
+        assert!(matches!(
+            editor.readline("> ", &mut io),
+            Err(NolineError::Disconnected)
+        ));
+
+        io.input = b"\x1b[20;80R\x1b[1;3Rdef\r";
+
+        assert_eq!(editor.readline("> ", &mut io).unwrap(), "def");
+        assert_eq!(editor.link_state.waits, 3);
+    }
+
+    #[test]
+    fn link_lost_while_polling() {
+        let connected = Cell::new(false);
+        let mut io = LinkIO {
+            input: b"",
+            output: Vec::new(),
+            connected: &connected,
+        };
+        let mut editor = EditorBuilder::new_unbounded()
+            .with_fixed_terminal_size(4, 20)
+            .with_link_state(|| connected.get())
+            .build_sync(&mut io)
+            .unwrap();
+
+        // Line isn't started until connected
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Pending
+        );
+        assert!(io.output.is_empty());
+
+        connected.set(true);
+        io.input = b"ab";
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Pending
+        );
+        assert!(io.output.windows(2).any(|bytes| bytes == b"> "));
+
+        connected.set(false);
+        assert!(matches!(
+            editor.readline_poll("> ", &mut io),
+            Err(NolineError::Disconnected)
+        ));
+        assert_eq!(editor.last_line(), "ab");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Pending
+        );
+
+        // New line after reconnecting
+        connected.set(true);
+        io.input = b"c\r";
+        io.output.clear();
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Done("c")
+        );
+        assert!(io.output.windows(2).any(|bytes| bytes == b"> "));
+    }
+
+    /// IO drawing output on a mock terminal, with its replies read
+    /// before other input
+    struct TerminalIO {
+        terminal: MockTerminal,
+        input: VecDeque<u8>,
+    }
+
+    impl embedded_io::ErrorType for TerminalIO {
+        type Error = embedded_io::ErrorKind;
+    }
+
+    impl embedded_io::Read for TerminalIO {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            match (buf.first_mut(), self.input.pop_front()) {
+                (Some(place), Some(byte)) => {
+                    *place = byte;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    impl embedded_io::ReadReady for TerminalIO {
+        fn read_ready(&mut self) -> Result<bool, Self::Error> {
+            Ok(!self.input.is_empty())
+        }
+    }
+
+    impl embedded_io::Write for TerminalIO {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            // Like usbd-serial
+            assert!(!buf.is_empty(), "empty write");
+
+            for &b in buf {
+                if let Some(reply) = self.terminal.advance(b) {
+                    for byte in reply.into_iter().rev() {
+                        self.input.push_front(byte);
+                    }
+                }
+            }
+
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    // Accepts a byte per write, and none on every third write, like a
+    // busy USB endpoint
+    struct BusyIO {
+        io: TerminalIO,
+        writes: usize,
+    }
 
-        let mut io = MockIO::new(MockStdin::new(input_rx), MockStdout::new(output_tx));
+    impl embedded_io::ErrorType for BusyIO {
+        type Error = embedded_io::ErrorKind;
+    }
 
-        let handle = thread::spawn(move || {
-            let mut editor = EditorBuilder::new_unbounded().build_sync(&mut io).unwrap();
+    impl embedded_io::Read for BusyIO {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            self.io.read(buf)
+        }
+    }
 
-            if let Ok(s) = editor.readline("> ", &mut io) {
-                Some(s.to_string())
-            } else {
-                None
+    impl embedded_io::ReadReady for BusyIO {
+        fn read_ready(&mut self) -> Result<bool, Self::Error> {
+            self.io.read_ready()
+        }
+    }
+
+    impl embedded_io::Write for BusyIO {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.writes += 1;
+
+            if self.writes.is_multiple_of(3) {
+                return Ok(0);
             }
-        });
 
-        for &b in b"\x1b7\x1b[999;999H\x1b[6n\x1b8" {
-            let received = output_rx
-                .recv_timeout(::core::time::Duration::from_millis(1000))
-                .unwrap();
-            println!("Received {:x}, expected: {:x}", received, b);
-            assert_eq!(received, b);
+            self.io.write(&buf[..1])
         }
 
-        for &b in b"\x1b[20;80R" {
-            input_tx.send(b).unwrap();
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            self.io.flush()
         }
+    }
 
-        for &b in b"\r\x1b[J> \x1b[6n" {
-            let received = output_rx
-                .recv_timeout(::core::time::Duration::from_millis(1000))
-                .unwrap();
-            println!("Received {:x}, expected: {:x}", received, b);
-            assert_eq!(received, b);
-        }
+    #[test]
+    fn held_output() {
+        let input = b"hello world\x01\x1b[Cx\x1b[D\x0b\r";
 
-        for &b in b"\x1b[1;3R" {
-            input_tx.send(b).unwrap();
+        let mut io = TerminalIO {
+            terminal: MockTerminal::new(4, 10, Cursor::new(0, 0)),
+            input: input.iter().copied().collect(),
+        };
+        let mut editor = EditorBuilder::new_unbounded()
+            .with_fixed_terminal_size(4, 10)
+            .build_sync(&mut io)
+            .unwrap();
+
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Done("h")
+        );
+
+        let mut busy = BusyIO {
+            io: TerminalIO {
+                terminal: MockTerminal::new(4, 10, Cursor::new(0, 0)),
+                input: input.iter().copied().collect(),
+            },
+            writes: 0,
+        };
+        let mut editor = EditorBuilder::new_unbounded()
+            .with_unbounded_write_buffer()
+            .with_fixed_terminal_size(4, 10)
+            .build_sync(&mut busy)
+            .unwrap();
+
+        // Input isn't read while output is held
+        assert_eq!(
+            editor.readline_poll("> ", &mut busy).unwrap(),
+            PollResult::Pending
+        );
+        assert_eq!(busy.io.input.len(), input.len());
+
+        let mut polls = 1;
+
+        loop {
+            polls += 1;
+
+            match editor.readline_poll("> ", &mut busy).unwrap() {
+                PollResult::Done(line) => {
+                    assert_eq!(line, "h");
+                    break;
+                }
+                PollResult::Pending => (),
+                PollResult::Aborted => panic!("aborted"),
+            }
         }
 
-        for &b in "abc\r".as_bytes() {
-            input_tx.send(b).unwrap();
+        while !editor.pump_output(&mut busy).unwrap() {
+            polls += 1;
         }
 
-        assert_eq!(handle.join().unwrap(), Some("abc".to_string()));
+        assert!(polls > input.len());
+        assert_eq!(
+            busy.io.terminal.screen_as_string(),
+            io.terminal.screen_as_string()
+        );
+        assert_eq!(busy.io.terminal.get_cursor(), io.terminal.get_cursor());
+
+        // Output that doesn't fit the write buffer is an error, as
+        // without one. The prompt is written while probing.
+        let mut buffer = [0; 16];
+        let mut write_buffer = [0; 1];
+        let mut editor = EditorBuilder::from_slice(&mut buffer)
+            .with_write_buffer(&mut write_buffer)
+            .with_fixed_terminal_size(4, 10)
+            .build_sync(&mut busy)
+            .unwrap();
+
+        assert!(matches!(
+            editor.readline_poll("> ", &mut busy),
+            Err(NolineError::ProbeFailed(ProbeFailure::IoError(
+                embedded_io::ErrorKind::WriteZero
+            )))
+        ));
     }
 
     #[test]
-    fn mock_stdin() {
-        let (tx, rx) = unbounded();
+    fn new_probed() {
+        let mut io = TerminalIO {
+            terminal: MockTerminal::new(10, 30, Cursor::new(2, 5)),
+            input: VecDeque::new(),
+        };
 
-        let mut io = MockIO::new(MockStdin::new(rx), MockStdout::new(tx));
-        for i in 0u8..10 {
-            io.write(&[i]).unwrap();
+        let editor = Editor::new(LineBuffer::new_unbounded(), NoHistory {}, &mut io).unwrap();
+        assert_eq!(editor.terminal_size(), (24, 80));
+
+        let mut editor =
+            Editor::new_probed(LineBuffer::new_unbounded(), NoHistory {}, &mut io).unwrap();
+        assert_eq!(editor.terminal_size(), (10, 30));
+
+        // Nothing printed, and the cursor is back where it was
+        assert_eq!(io.terminal.get_cursor(), Cursor::new(2, 5));
+        assert_eq!(io.terminal.screen_as_string(), "");
+        assert!(io.input.is_empty());
+
+        io.input.extend(b"ab\r");
+        assert_eq!(editor.readline("> ", &mut io).unwrap(), "ab");
+    }
+
+    #[test]
+    fn size_and_cursor_position() {
+        let mut io = TerminalIO {
+            terminal: MockTerminal::new(10, 30, Cursor::new(2, 5)),
+            input: VecDeque::new(),
+        };
+
+        let mut editor = EditorBuilder::new_unbounded().build_sync(&mut io).unwrap();
+        assert_eq!(editor.terminal_size(), (24, 80));
+        assert_eq!(editor.cursor_position(), (0, 0));
+
+        io.input.extend(b"ab");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Pending
+        );
+        assert_eq!(editor.terminal_size(), (10, 30));
+        assert_eq!(editor.cursor_position(), (2, 4));
+
+        io.input.extend(b"\r");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Done("ab")
+        );
+        assert_eq!(editor.cursor_position(), (3, 0));
+
+        // Probing again finds the new size, leaving the screen as it is
+        io.terminal.resize(12, 40);
+        editor.refresh_size(&mut io).unwrap();
+        assert_eq!(editor.terminal_size(), (12, 40));
+        assert_eq!(editor.cursor_position(), (3, 0));
+        assert_eq!(io.terminal.get_cursor(), Cursor::new(3, 0));
+        assert_eq!(io.terminal.screen_as_string(), "> ab");
+
+        // Nothing is sent with a fixed size
+        let mut editor = EditorBuilder::new_unbounded()
+            .with_fixed_terminal_size(5, 20)
+            .build_sync(&mut io)
+            .unwrap();
+        editor.refresh_size(&mut io).unwrap();
+        assert_eq!(editor.terminal_size(), (5, 20));
+        assert_eq!(io.terminal.get_cursor(), Cursor::new(3, 0));
+    }
+
+    #[test]
+    fn prompt_from_fn_poll() {
+        const PROMPTS: [&str; 2] = ["> ", "long> "];
+
+        let current = Cell::new(0);
+        let prompt = || crate::Prompt::from_fn(|| core::iter::once(PROMPTS[current.get()]));
+        let mut io = TerminalIO {
+            terminal: MockTerminal::new(4, 20, Cursor::new(3, 0)),
+            input: b"ab".iter().copied().collect(),
+        };
+        let mut editor = EditorBuilder::new_unbounded().build_sync(&mut io).unwrap();
+
+        assert_eq!(
+            editor.readline_poll(prompt(), &mut io).unwrap(),
+            PollResult::Pending
+        );
+        assert_eq!(io.terminal.get_cursor(), Cursor::new(3, 4));
+
+        // The prompt as drawn is used until it is drawn again
+        current.set(1);
+        io.input.extend(b"\x1b[D");
+        assert_eq!(
+            editor.readline_poll(prompt(), &mut io).unwrap(),
+            PollResult::Pending
+        );
+        assert_eq!(io.terminal.get_cursor(), Cursor::new(3, 3));
+        assert_eq!(io.terminal.screen_as_string(), "> ab");
+
+        io.input.extend(b"\x0cx");
+        assert_eq!(
+            editor.readline_poll(prompt(), &mut io).unwrap(),
+            PollResult::Pending
+        );
+        assert_eq!(io.terminal.get_cursor(), Cursor::new(0, 8));
+        assert_eq!(io.terminal.screen_as_string(), "long> axb");
+    }
+
+    #[test]
+    fn print_above() {
+        let mut io = TerminalIO {
+            terminal: MockTerminal::new(6, 10, Cursor::new(3, 0)),
+            input: VecDeque::new(),
+        };
+        let mut editor = EditorBuilder::new_unbounded().build_sync(&mut io).unwrap();
+
+        // Line wrapped on the three last rows, with the cursor on the
+        // last
+        io.input
+            .extend(b"abcdefghijklmnopqrstuvwx\x1b[D\x1b[D\x1b[D");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Pending
+        );
+        assert_eq!(io.terminal.get_cursor(), Cursor::new(5, 3));
+
+        // Printed where the line started, scrolling up to fit the line
+        // below
+        editor.print_above("> ", "log 1\n", &mut io).unwrap();
+        assert_eq!(io.terminal.row_as_string(2), "log 1");
+        assert_eq!(io.terminal.row_as_string(3), "> abcdefgh");
+        assert_eq!(io.terminal.row_as_string(4), "ijklmnopqr");
+        assert_eq!(io.terminal.row_as_string(5), "stuvwx");
+        assert_eq!(io.terminal.get_cursor(), Cursor::new(5, 3));
+
+        editor.print_above("> ", "log 2\nlog 3", &mut io).unwrap();
+        assert_eq!(
+            io.terminal.screen_as_string(),
+            "log 1\nlog 2\nlog 3\n> abcdefgh\nijklmnopqr\nstuvwx"
+        );
+        assert_eq!(io.terminal.get_cursor(), Cursor::new(5, 3));
+
+        // Home and End land on the line as drawn
+        io.input.extend(b"\x01X");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Pending
+        );
+        assert_eq!(io.terminal.get_cursor(), Cursor::new(3, 3));
+
+        io.input.extend(b"\x05Y");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Pending
+        );
+        assert_eq!(io.terminal.get_cursor(), Cursor::new(5, 8));
+        assert_eq!(io.terminal.row_as_string(3), "> Xabcdefg");
+        assert_eq!(io.terminal.row_as_string(5), "rstuvwxY");
+
+        io.input.extend(b"\r");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Done("XabcdefghijklmnopqrstuvwxY")
+        );
+
+        // Without a line in progress, the text is just printed
+        editor.print_above("> ", "log 4", &mut io).unwrap();
+        assert_eq!(io.terminal.row_as_string(4), "log 4");
+    }
+
+    #[test]
+    fn hide_and_unhide() {
+        let mut io = TerminalIO {
+            terminal: MockTerminal::new(6, 20, Cursor::new(1, 0)),
+            input: VecDeque::new(),
+        };
+        let mut editor = EditorBuilder::new_unbounded()
+            .with_unbounded_history()
+            .build_sync(&mut io)
+            .unwrap();
+
+        io.input
+            .extend(b"hello world\x1b[D\x1b[D\x1b[D\x1b[D\x1b[D");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Pending
+        );
+
+        editor.hide("> ", &mut io).unwrap();
+        assert_eq!(io.terminal.screen_as_string(), "");
+        assert_eq!(io.terminal.get_cursor(), Cursor::new(1, 0));
+
+        // Full-screen program clearing the screen and moving the cursor
+        for &b in b"\x1b[1;1H\x1b[Jtop\r\nload 0.5\x1b[4;9Hxx" {
+            io.terminal.advance(b);
         }
 
-        io.flush().unwrap();
+        // Input isn't read while hidden
+        io.input.extend(b"X");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Pending
+        );
+        assert_eq!(io.input.len(), 1);
 
-        let mut buf = [0];
-        for i in 0..10 {
-            io.read(&mut buf).unwrap();
+        editor.unhide("> ", &mut io).unwrap();
+        assert_eq!(
+            io.terminal.screen_as_string(),
+            "top\nload 0.5\n> hello world"
+        );
+        assert_eq!(io.terminal.get_cursor(), Cursor::new(3, 8));
 
-            assert_eq!(buf[0], i);
+        io.input.extend(b"\r");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Done("hello Xworld")
+        );
+
+        // Nothing to hide without a line in progress
+        editor.hide("> ", &mut io).unwrap();
+        editor.unhide("> ", &mut io).unwrap();
+        assert_eq!(io.terminal.get_cursor(), Cursor::new(4, 0));
+    }
+
+    #[test]
+    fn shared_history() {
+        let history = RefCell::new(UnboundedHistory::new());
+
+        let mut uart = TerminalIO {
+            terminal: MockTerminal::new(10, 20, Cursor::new(0, 0)),
+            input: VecDeque::new(),
+        };
+        let mut usb = TerminalIO {
+            terminal: MockTerminal::new(10, 20, Cursor::new(0, 0)),
+            input: VecDeque::new(),
+        };
+
+        let mut uart_editor = EditorBuilder::new_unbounded()
+            .with_history(SharedHistory::new(&history))
+            .build_sync(&mut uart)
+            .unwrap();
+        let mut usb_editor = EditorBuilder::new_unbounded()
+            .with_history(SharedHistory::new(&history))
+            .build_sync(&mut usb)
+            .unwrap();
+
+        uart.input.extend(b"one\r");
+        assert_eq!(uart_editor.readline("> ", &mut uart).unwrap(), "one");
+
+        usb.input.extend(b"\x1b[A\r");
+        assert_eq!(usb_editor.readline("> ", &mut usb).unwrap(), "one");
+
+        usb.input.extend(b"two\r");
+        assert_eq!(usb_editor.readline("> ", &mut usb).unwrap(), "two");
+
+        uart.input.extend(b"\x1b[A\x1b[A\x1b[A\r");
+        assert_eq!(uart_editor.readline("> ", &mut uart).unwrap(), "one");
+
+        // Lines in progress see entries added in between
+        uart.input.extend(b"th");
+        assert_eq!(
+            uart_editor.readline_poll("> ", &mut uart).unwrap(),
+            PollResult::Pending
+        );
+
+        usb.input.extend(b"three\r");
+        assert_eq!(usb_editor.readline("> ", &mut usb).unwrap(), "three");
+
+        uart.input.extend(b"\x1b[A\r");
+        assert_eq!(
+            uart_editor.readline_poll("> ", &mut uart).unwrap(),
+            PollResult::Done("three")
+        );
+
+        uart_editor.clear_history();
+        assert_eq!(history.borrow().number_of_entries(), 0);
+    }
+
+    #[test]
+    fn alloc_history() {
+        let mut io = TerminalIO {
+            terminal: MockTerminal::new(10, 20, Cursor::new(0, 0)),
+            input: VecDeque::new(),
+        };
+        let mut editor = EditorBuilder::new_unbounded()
+            .with_alloc_history(3)
+            .build_sync(&mut io)
+            .unwrap();
+
+        for line in ["one", "two", "three", "four", "five"] {
+            io.input.extend(line.as_bytes());
+            io.input.extend(b"\r");
+            assert_eq!(editor.readline("> ", &mut io).unwrap(), line);
         }
+
+        assert_eq!(
+            editor
+                .get_history()
+                .map(|entry| entry.as_str(&mut [0; 16]).unwrap().to_string())
+                .collect::<Vec<_>>(),
+            ["three", "four", "five"]
+        );
+
+        // Only the three newest entries are reachable
+        io.input.extend(b"\x1b[A\x1b[A\x1b[A\x1b[A\r");
+        assert_eq!(editor.readline("> ", &mut io).unwrap(), "three");
+        assert!(io.terminal.bell);
+
+        io.input.extend(b"\x1b[A\x1b[A\x1b[A\r");
+        assert_eq!(editor.readline("> ", &mut io).unwrap(), "four");
     }
 
     #[test]
-    fn editor() {
-        let prompt = "> ";
-
-        for case in test_cases() {
-            test_editor_with_case(
-                case,
-                prompt,
-                |term| MockIO::from_terminal(term).get_pipes(),
-                |(stdin, stdout), string_tx| {
-                    thread::spawn(move || {
-                        let mut io = MockIO::new(stdin, stdout);
-                        let mut editor = EditorBuilder::new_unbounded()
-                            .with_unbounded_history()
-                            .build_sync(&mut io)
-                            .unwrap();
-
-                        while let Ok(s) = editor.readline(prompt, &mut io) {
-                            string_tx.send(s.to_string()).unwrap();
-                        }
-                    })
-                },
-            )
+    fn no_empty_writes() {
+        // Writing an empty slice panics in [`TerminalIO`]
+        let mut io = TerminalIO {
+            terminal: MockTerminal::new(4, 10, Cursor::new(0, 0)),
+            input: VecDeque::new(),
+        };
+        let mut editor = EditorBuilder::new_unbounded()
+            .with_unbounded_history()
+            .build_sync(&mut io)
+            .unwrap();
+
+        editor.history.add_entry("").unwrap();
+        editor.history.add_entry("abc").unwrap();
+        editor.history.add_entry("").unwrap();
+
+        let prompt = || ["", "", "> ", ""].into_iter();
+
+        // Empty line, then history navigation through empty entries
+        io.input
+            .extend(b"\r\x1b[A\x1b[A\x1b[A\x1b[B\x08\x12\x12\x07\r");
+        assert_eq!(editor.readline(prompt(), &mut io).unwrap(), "");
+        assert_eq!(editor.readline(prompt(), &mut io).unwrap(), "ab");
+
+        io.input.extend(b"\t\x0b\x15\x17\x0c\x1b[3~");
+        assert_eq!(
+            editor.readline_poll(prompt(), &mut io).unwrap(),
+            PollResult::Pending
+        );
+        editor.print_above(prompt(), "", &mut io).unwrap();
+        editor.print_above(prompt(), "\n", &mut io).unwrap();
+
+        io.input.extend(b"\x04");
+        assert!(matches!(
+            editor.readline_poll(prompt(), &mut io),
+            Err(NolineError::Eof)
+        ));
+    }
+
+    #[test]
+    fn overflow() {
+        let mut io = TerminalIO {
+            terminal: MockTerminal::new(4, 30, Cursor::new(0, 0)),
+            input: VecDeque::new(),
+        };
+        let mut buffer = [0; 20];
+        let mut editor = EditorBuilder::from_slice(&mut buffer)
+            .with_overflow_indicator(true)
+            .build_sync(&mut io)
+            .unwrap();
+
+        assert_eq!(editor.remaining_capacity(), Some(20));
+
+        io.input.extend(b"0123456789abcdefg");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Pending
+        );
+        assert_eq!(editor.remaining_capacity(), Some(3));
+        assert_eq!(editor.overflow_count(), 0);
+
+        io.input.extend(b"hijkl");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Pending
+        );
+        assert_eq!(editor.remaining_capacity(), Some(0));
+        assert_eq!(editor.overflow_count(), 2);
+        assert_eq!(io.terminal.screen_as_string(), "> 0123456789abcdefghij!");
+        assert_eq!(io.terminal.get_cursor(), Cursor::new(0, 22));
+
+        // The mark goes away with editing, and typing works again
+        io.input.extend(b"\x08\x08xy\r");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Done("0123456789abcdefghxy")
+        );
+        assert_eq!(editor.overflow_count(), 2);
+        assert_eq!(io.terminal.screen_as_string(), "> 0123456789abcdefghxy");
+
+        // Counted per line
+        io.input.extend(b"\r");
+        assert_eq!(editor.readline("> ", &mut io).unwrap(), "");
+        assert_eq!(editor.overflow_count(), 0);
+        assert_eq!(editor.remaining_capacity(), Some(20));
+
+        // Echoed by the terminal
+        let mut buffer = [0; 4];
+        let mut editor = EditorBuilder::from_slice(&mut buffer)
+            .with_echo(Echo::Local)
+            .build_sync(&mut io)
+            .unwrap();
+
+        io.input.extend(b"abcdef\r");
+        assert_eq!(editor.readline("> ", &mut io).unwrap(), "abcd");
+        assert_eq!(editor.overflow_count(), 2);
+    }
+
+    #[test]
+    fn hint_outside_buffer() {
+        let mut io = TerminalIO {
+            terminal: MockTerminal::new(4, 30, Cursor::new(0, 0)),
+            input: VecDeque::new(),
+        };
+        let mut buffer = [0; 8];
+        let mut editor = EditorBuilder::from_slice(&mut buffer)
+            .with_unbounded_history()
+            .with_hinter(HistoryHinter)
+            .build_sync(&mut io)
+            .unwrap();
+
+        io.input.extend(b"abc\r");
+        assert_eq!(editor.readline("> ", &mut io).unwrap(), "abc");
+
+        // The hint is shown, but isn't part of the line
+        io.input.extend(b"a");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Pending
+        );
+        assert_eq!(io.terminal.screen_as_string(), "> abc\n> abc");
+        assert_eq!(io.terminal.dimmed_as_string(), "bc");
+        assert_eq!(editor.last_line(), "a");
+        assert_eq!(editor.remaining_capacity(), Some(7));
+
+        // Accepting it inserts it into the line
+        io.input.extend(b"\x1b[C");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Pending
+        );
+        assert_eq!(editor.last_line(), "abc");
+        assert_eq!(editor.remaining_capacity(), Some(5));
+    }
+
+    // Input is ready only after `delay` idle calls since the last read
+    struct DelayedIO {
+        io: TerminalIO,
+        idle_calls: Rc<Cell<usize>>,
+        delay: usize,
+    }
+
+    impl embedded_io::ErrorType for DelayedIO {
+        type Error = embedded_io::ErrorKind;
+    }
+
+    impl embedded_io::Read for DelayedIO {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            self.idle_calls.set(0);
+            self.io.read(buf)
+        }
+    }
+
+    impl embedded_io::ReadReady for DelayedIO {
+        fn read_ready(&mut self) -> Result<bool, Self::Error> {
+            Ok(self.idle_calls.get() >= self.delay && self.io.read_ready()?)
+        }
+    }
+
+    impl embedded_io::Write for DelayedIO {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.io.write(buf)
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
         }
     }
+
+    #[test]
+    fn readline_with_idle() {
+        let idle_calls = Rc::new(Cell::new(0));
+        let mut io = DelayedIO {
+            io: TerminalIO {
+                terminal: MockTerminal::new(4, 20, Cursor::new(0, 0)),
+                input: VecDeque::new(),
+            },
+            idle_calls: idle_calls.clone(),
+            delay: 3,
+        };
+        let mut editor = EditorBuilder::new_unbounded().build_sync(&mut io).unwrap();
+        let mut total = 0;
+
+        io.io.input.extend(b"abc\x08d\r");
+        let line = editor.readline_with_idle("> ", &mut io, || {
+            idle_calls.set(idle_calls.get() + 1);
+            total += 1;
+            ControlFlow::Continue(())
+        });
+        assert_eq!(line.unwrap(), "abd");
+        assert_eq!(io.io.terminal.screen_as_string(), "> abd");
+
+        // Waited before every read, including the probe replies
+        assert!(total >= 6 * 3);
+
+        // Stop when no input arrives for a while
+        io.io.input.extend(b"xy");
+        let result = editor.readline_with_idle("> ", &mut io, || {
+            idle_calls.set(idle_calls.get() + 1);
+
+            if idle_calls.get() > 10 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert!(matches!(result, Err(NolineError::IdleAborted)));
+        assert_eq!(editor.last_line(), "xy");
+    }
+
+    #[test]
+    fn reject_overflowing_line() {
+        let mut io = TerminalIO {
+            terminal: MockTerminal::new(4, 40, Cursor::new(0, 0)),
+            input: VecDeque::new(),
+        };
+        let mut buffer = [0; 20];
+        let mut kill_buffer = [0; 20];
+        let mut editor = EditorBuilder::from_slice(&mut buffer)
+            .with_kill_buffer(&mut kill_buffer)
+            .with_overflow_policy(OverflowPolicy::RejectLine)
+            .build_sync(&mut io)
+            .unwrap();
+
+        // Pasted without bracketed paste, so the line is ended by the
+        // carriage return in the pasted text
+        io.input.extend(b"set name 0123456789abcdefghij\r");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Pending
+        );
+        assert!(io.terminal.bell);
+        assert_eq!(io.terminal.screen_as_string(), "> set name 0123456789a");
+
+        // Still refused after moving the cursor
+        io.terminal.bell = false;
+        io.input.extend(b"\x02\r");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Pending
+        );
+        assert!(io.terminal.bell);
+
+        // Deleting and typing again makes the line acceptable
+        io.input.extend(b"\x05\x08\x08b\r");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Done("set name 012345678b")
+        );
+
+        // Refused until the line is shorter than when text was refused,
+        // also when the refused text didn't fill the buffer. The second
+        // yank doesn't fit.
+        io.input.extend(b"0123456789\x01\x0bx\x19\x19\r");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Pending
+        );
+        io.input.extend(b"\x08\r");
+        assert_eq!(
+            editor.readline_poll("> ", &mut io).unwrap(),
+            PollResult::Done("x012345678")
+        );
+    }
+
+    #[test]
+    fn trim() {
+        let mut io = TerminalIO {
+            terminal: MockTerminal::new(4, 20, Cursor::new(0, 0)),
+            input: VecDeque::new(),
+        };
+        let mut editor = EditorBuilder::new_unbounded()
+            .with_unbounded_history()
+            .with_trim(TrimMode::Trailing)
+            .build_sync(&mut io)
+            .unwrap();
+
+        io.input.extend(b"cmd   \r");
+        assert_eq!(editor.readline("> ", &mut io).unwrap(), "cmd");
+        assert_eq!(io.terminal.screen_as_string(), "> cmd   ");
+
+        // Stored once, as the trimmed lines are the same
+        io.input.extend(b"cmd\r");
+        assert_eq!(editor.readline("> ", &mut io).unwrap(), "cmd");
+        io.input.extend(b"  cmd\t \r");
+        assert_eq!(editor.readline("> ", &mut io).unwrap(), "  cmd");
+        assert_eq!(
+            editor
+                .get_history()
+                .map(|entry| entry.as_str(&mut [0; 16]).unwrap().to_string())
+                .collect::<Vec<_>>(),
+            ["cmd", "  cmd"]
+        );
+
+        let mut editor = EditorBuilder::new_unbounded()
+            .with_unbounded_history()
+            .with_trim(TrimMode::Both)
+            .build_sync(&mut io)
+            .unwrap();
+
+        io.input.extend(b"  cmd  \r");
+        assert_eq!(editor.readline("> ", &mut io).unwrap(), "cmd");
+
+        // Cursor in the part that is trimmed
+        io.input.extend(b"  a b  \x01\x06\r");
+        assert_eq!(editor.readline("> ", &mut io).unwrap(), "a b");
+
+        io.input.extend(b"   \r");
+        assert_eq!(editor.readline("> ", &mut io).unwrap(), "");
+        assert_eq!(editor.get_history().count(), 2);
+    }
+
+    #[test]
+    fn readline_into() {
+        let mut io = TerminalIO {
+            terminal: MockTerminal::new(8, 20, Cursor::new(0, 0)),
+            input: VecDeque::new(),
+        };
+        let mut buffer = [0; 32];
+        let mut editor = EditorBuilder::from_slice(&mut buffer)
+            .with_unbounded_history()
+            .build_sync(&mut io)
+            .unwrap();
+
+        let mut command = [0; 16];
+
+        io.input.extend(b"rm *\r");
+        let len = editor.readline_into("> ", &mut io, &mut command).unwrap();
+        let command = core::str::from_utf8(&command[..len]).unwrap();
+
+        // The editor is used again while the command is held
+        io.input.extend(b"yes\r");
+        let mut answer = [0; 16];
+        let len = editor
+            .readline_into("sure? ", &mut io, &mut answer)
+            .unwrap();
+
+        assert_eq!(command, "rm *");
+        assert_eq!(&answer[..len], b"yes");
+        assert_eq!(editor.get_history().count(), 2);
+
+        let mut out = [b'x'; 4];
+
+        io.input.extend(b"abcde\r");
+        assert!(matches!(
+            editor.readline_into("> ", &mut io, &mut out),
+            Err(NolineError::OutputTooSmall(5))
+        ));
+        assert_eq!(&out, b"xxxx");
+        assert_eq!(editor.last_line(), "abcde");
+
+        io.input.extend(b"abcd\r");
+        assert_eq!(editor.readline_into("> ", &mut io, &mut out).unwrap(), 4);
+        assert_eq!(&out, b"abcd");
+        assert_eq!(editor.last_line(), "");
+        assert_eq!(editor.remaining_capacity(), Some(32));
+
+        io.input.extend(b"ls\r");
+        let line = editor.readline_owned("> ", &mut io).unwrap();
+
+        io.input.extend(b"cd\r");
+        assert_eq!(editor.readline("> ", &mut io).unwrap(), "cd");
+        assert_eq!(line, "ls");
+        assert_eq!(
+            io.terminal.screen_as_string(),
+            "> rm *\nsure? yes\n> abcde\n> abcd\n> ls\n> cd"
+        );
+    }
+
+    #[test]
+    fn save_and_restore_history() {
+        let mut io = ScriptedIO { input: b"" };
+        let mut editor = EditorBuilder::new_unbounded()
+            .with_unbounded_history()
+            .build_sync(&mut io)
+            .unwrap();
+
+        editor.load_history(["ls", "cd /tmp"].into_iter());
+
+        let mut saved = [0; 32];
+        let mut writer = &mut saved[..];
+        editor.save_history(&mut writer).unwrap();
+        let len = 32 - writer.len();
+
+        let mut buffer = [0; 16];
+        let mut history = [0; 32];
+        let mut editor = EditorBuilder::from_slice(&mut buffer)
+            .with_slice_history(&mut history)
+            .build_sync(&mut io)
+            .unwrap();
+
+        assert_eq!(editor.restore_history(&mut &saved[..len]).unwrap(), 2);
+        assert_eq!(
+            editor
+                .get_history()
+                .map(|entry| entry.as_str(&mut [0; 16]).unwrap().to_string())
+                .collect::<Vec<_>>(),
+            ["ls", "cd /tmp"]
+        );
+    }
 }
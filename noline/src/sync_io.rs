@@ -0,0 +1,334 @@
+//! IO adapters for the sync editor
+//!
+//! Many HALs only implement the `nb` based serial traits from
+//! `embedded-hal` 0.2. [`NbSerialIO`] wraps such a serial port so it
+//! can be used with [`crate::sync_editor::Editor`]. Requires feature
+//! `embedded-hal-02`.
+//!
+//! ```no_run
+//! # use noline::{builder::EditorBuilder, sync_io::NbSerialIO};
+//! # use core::convert::Infallible;
+//! # struct Serial;
+//! # impl embedded_hal_02::serial::Read<u8> for Serial {
+//! #     type Error = Infallible;
+//! #     fn read(&mut self) -> nb::Result<u8, Infallible> { unimplemented!() }
+//! # }
+//! # impl embedded_hal_02::serial::Write<u8> for Serial {
+//! #     type Error = Infallible;
+//! #     fn write(&mut self, _: u8) -> nb::Result<(), Infallible> { unimplemented!() }
+//! #     fn flush(&mut self) -> nb::Result<(), Infallible> { unimplemented!() }
+//! # }
+//! # let serial = Serial;
+//! let mut io = NbSerialIO::new(serial); // e.g. a HAL UART
+//! let mut buffer = [0; 100];
+//! let mut editor = EditorBuilder::from_slice(&mut buffer)
+//!     .build_sync(&mut io)
+//!     .unwrap();
+//!
+//! while let Ok(line) = editor.readline("> ", &mut io) {
+//!     // handle line
+//! }
+//! ```
+
+use core::fmt;
+
+use embedded_hal_02::serial;
+use embedded_io::ErrorKind;
+
+/// Adapter implementing [`embedded_io`] traits for a serial port
+/// implementing the `embedded-hal` 0.2 traits [`serial::Read`] and
+/// [`serial::Write`]. Reads and writes block until the serial port is
+/// ready.
+pub struct NbSerialIO<RW> {
+    serial: RW,
+    // Byte read by `read_ready`, returned by the next read
+    peeked: Option<u8>,
+}
+
+impl<RW> NbSerialIO<RW> {
+    /// Wrap serial port
+    pub fn new(serial: RW) -> Self {
+        Self {
+            serial,
+            peeked: None,
+        }
+    }
+
+    /// Return the wrapped serial port. A byte taken by
+    /// [`embedded_io::ReadReady::read_ready`] and not yet read is lost.
+    pub fn into_inner(self) -> RW {
+        self.serial
+    }
+}
+
+/// Error from the wrapped serial port
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NbSerialError<RE, WE> {
+    /// Reading failed
+    Read(RE),
+    /// Writing or flushing failed
+    Write(WE),
+}
+
+impl<RE, WE> fmt::Display for NbSerialError<RE, WE>
+where
+    RE: fmt::Debug,
+    WE: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NbSerialError::Read(err) => write!(f, "serial read failed: {:?}", err),
+            NbSerialError::Write(err) => write!(f, "serial write failed: {:?}", err),
+        }
+    }
+}
+
+/// Serial errors carry no kind, so all map to [`ErrorKind::Other`]
+impl<RE, WE> embedded_io::Error for NbSerialError<RE, WE>
+where
+    RE: fmt::Debug,
+    WE: fmt::Debug,
+{
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+impl<RW> embedded_io::ErrorType for NbSerialIO<RW>
+where
+    RW: serial::Read<u8> + serial::Write<u8>,
+    <RW as serial::Read<u8>>::Error: fmt::Debug,
+    <RW as serial::Write<u8>>::Error: fmt::Debug,
+{
+    type Error = NbSerialError<<RW as serial::Read<u8>>::Error, <RW as serial::Write<u8>>::Error>;
+}
+
+impl<RW> embedded_io::Read for NbSerialIO<RW>
+where
+    RW: serial::Read<u8> + serial::Write<u8>,
+    <RW as serial::Read<u8>>::Error: fmt::Debug,
+    <RW as serial::Write<u8>>::Error: fmt::Debug,
+{
+    /// Block until one byte is read, then read any bytes available
+    /// without blocking
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        buf[0] = match self.peeked.take() {
+            Some(byte) => byte,
+            None => nb::block!(self.serial.read()).map_err(NbSerialError::Read)?,
+        };
+
+        let mut count = 1;
+
+        for place in &mut buf[1..] {
+            match self.serial.read() {
+                Ok(byte) => *place = byte,
+                Err(nb::Error::WouldBlock) => break,
+                Err(nb::Error::Other(err)) => return Err(NbSerialError::Read(err)),
+            }
+
+            count += 1;
+        }
+
+        Ok(count)
+    }
+}
+
+impl<RW> embedded_io::ReadReady for NbSerialIO<RW>
+where
+    RW: serial::Read<u8> + serial::Write<u8>,
+    <RW as serial::Read<u8>>::Error: fmt::Debug,
+    <RW as serial::Write<u8>>::Error: fmt::Debug,
+{
+    /// The nb traits can't tell if a byte is available without reading
+    /// it, so the byte is kept for the next read
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        if self.peeked.is_none() {
+            match self.serial.read() {
+                Ok(byte) => self.peeked = Some(byte),
+                Err(nb::Error::WouldBlock) => (),
+                Err(nb::Error::Other(err)) => return Err(NbSerialError::Read(err)),
+            }
+        }
+
+        Ok(self.peeked.is_some())
+    }
+}
+
+impl<RW> embedded_io::Write for NbSerialIO<RW>
+where
+    RW: serial::Read<u8> + serial::Write<u8>,
+    <RW as serial::Read<u8>>::Error: fmt::Debug,
+    <RW as serial::Write<u8>>::Error: fmt::Debug,
+{
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        for &byte in buf {
+            nb::block!(self.serial.write(byte)).map_err(NbSerialError::Write)?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        nb::block!(self.serial.flush()).map_err(NbSerialError::Write)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::string::ToString;
+    use std::vec::Vec;
+
+    use embedded_io::{Error, Read, ReadReady, Write};
+
+    use super::*;
+    use crate::builder::EditorBuilder;
+    use crate::sync_editor::PollResult;
+    use crate::terminal::Cursor;
+    use crate::testlib::MockTerminal;
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct MockError;
+
+    // Serial port answering like a terminal. Every other write returns
+    // `WouldBlock`, like a UART with a one byte transmit buffer.
+    struct MockSerial {
+        terminal: MockTerminal,
+        input: VecDeque<u8>,
+        output: Vec<u8>,
+        busy: bool,
+        fail: bool,
+    }
+
+    impl MockSerial {
+        fn new(input: &[u8]) -> Self {
+            Self {
+                terminal: MockTerminal::new(4, 20, Cursor::new(0, 0)),
+                input: input.iter().copied().collect(),
+                output: Vec::new(),
+                busy: false,
+                fail: false,
+            }
+        }
+
+        fn poll(&mut self) -> nb::Result<(), MockError> {
+            if self.fail {
+                return Err(nb::Error::Other(MockError));
+            }
+
+            self.busy = !self.busy;
+
+            if self.busy {
+                Err(nb::Error::WouldBlock)
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    impl serial::Read<u8> for MockSerial {
+        type Error = MockError;
+
+        fn read(&mut self) -> nb::Result<u8, MockError> {
+            if self.fail {
+                return Err(nb::Error::Other(MockError));
+            }
+
+            self.input.pop_front().ok_or(nb::Error::WouldBlock)
+        }
+    }
+
+    impl serial::Write<u8> for MockSerial {
+        type Error = MockError;
+
+        fn write(&mut self, byte: u8) -> nb::Result<(), MockError> {
+            self.poll()?;
+            self.output.push(byte);
+
+            // Replies go before typed input, which isn't typed until
+            // the prompt is shown
+            if let Some(reply) = self.terminal.advance(byte) {
+                for byte in reply.into_iter().rev() {
+                    self.input.push_front(byte);
+                }
+            }
+
+            Ok(())
+        }
+
+        fn flush(&mut self) -> nb::Result<(), MockError> {
+            self.poll()
+        }
+    }
+
+    #[test]
+    fn read_write() {
+        let mut io = NbSerialIO::new(MockSerial::new(b"abc"));
+        let mut buf = [0; 4];
+
+        assert_eq!(io.read(&mut buf[..0]).unwrap(), 0);
+
+        // Reads what's available without blocking for more
+        assert_eq!(io.read(&mut buf).unwrap(), 3);
+        assert_eq!(&buf[..3], b"abc");
+
+        assert!(!io.read_ready().unwrap());
+        io.serial.input.push_back(b'd');
+        assert!(io.read_ready().unwrap());
+        assert!(io.read_ready().unwrap());
+        assert_eq!(io.read(&mut buf).unwrap(), 1);
+        assert_eq!(buf[0], b'd');
+
+        io.write_all(b"xyz").unwrap();
+        io.flush().unwrap();
+        assert_eq!(io.into_inner().output, b"xyz");
+    }
+
+    #[test]
+    fn errors() {
+        let mut io = NbSerialIO::new(MockSerial::new(b"abc"));
+        io.serial.fail = true;
+
+        let err = io.read(&mut [0; 4]).unwrap_err();
+        assert_eq!(err, NbSerialError::Read(MockError));
+        assert_eq!(err.kind(), ErrorKind::Other);
+        assert_eq!(err.to_string(), "serial read failed: MockError");
+
+        assert_eq!(io.read_ready(), Err(NbSerialError::Read(MockError)));
+        assert_eq!(io.write(b"x"), Err(NbSerialError::Write(MockError)));
+        assert_eq!(io.flush(), Err(NbSerialError::Write(MockError)));
+    }
+
+    #[test]
+    fn editor() {
+        let mut io = NbSerialIO::new(MockSerial::new(b""));
+        let mut editor = EditorBuilder::new_unbounded().build_sync(&mut io).unwrap();
+
+        io.serial.input.extend(b"abc\x02X\r");
+        assert_eq!(editor.readline("> ", &mut io).unwrap(), "abXc");
+
+        io.serial.input.extend(b"de");
+        let mut result = None;
+
+        for _ in 0..100 {
+            match editor.readline_poll("> ", &mut io).unwrap() {
+                PollResult::Pending if io.serial.input.is_empty() && io.peeked.is_none() => {
+                    io.serial.input.push_back(b'\r');
+                }
+                PollResult::Pending => (),
+                PollResult::Done(line) => {
+                    result = Some(line.to_string());
+                    break;
+                }
+                PollResult::Aborted => panic!("aborted"),
+            }
+        }
+
+        assert_eq!(result.as_deref(), Some("de"));
+        assert_eq!(io.serial.terminal.screen_as_string(), "> abXc\n> de");
+    }
+}
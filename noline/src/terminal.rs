@@ -1,3 +1,7 @@
+use core::fmt;
+
+use crate::caps::{TerminalCaps, Vt100};
+
 fn distance_from_window(start: isize, end: isize, point: isize) -> isize {
     if point < start {
         point - start
@@ -8,8 +12,8 @@ fn distance_from_window(start: isize, end: isize, point: isize) -> isize {
     }
 }
 
-#[cfg_attr(test, derive(Debug))]
-#[derive(Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Cursor {
     pub row: usize,
     pub column: usize,
@@ -21,8 +25,8 @@ impl Cursor {
     }
 }
 
-#[cfg_attr(test, derive(Debug))]
-#[derive(Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Position {
     pub row: usize,
     pub column: usize,
@@ -34,12 +38,63 @@ impl Position {
     }
 }
 
-#[cfg_attr(test, derive(Debug, PartialEq, Eq))]
+// State of the right-side prompt on the first row of the line
+#[cfg_attr(test, derive(PartialEq, Eq))]
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) enum RightPrompt {
+    Hidden,
+    // Printed at column
+    Visible(usize),
+    // Partially overwritten, rest of the row must be erased
+    Overwritten,
+}
+
+// Control sequences written to the terminal
+#[derive(Copy, Clone)]
+struct Caps(&'static (dyn TerminalCaps + Sync));
+
+impl fmt::Debug for Caps {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Caps")
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Caps {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "Caps")
+    }
+}
+
+#[cfg(test)]
+impl PartialEq for Caps {
+    fn eq(&self, other: &Self) -> bool {
+        core::ptr::addr_eq(self.0, other.0)
+    }
+}
+
+#[cfg(test)]
+impl Eq for Caps {}
+
+#[cfg_attr(test, derive(PartialEq, Eq))]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Terminal {
     rows: usize,
     columns: usize,
     cursor: Cursor,
     row_offset: isize,
+    right_prompt: RightPrompt,
+    // Offset after the last char drawn on the line
+    line_end: isize,
+    // Erase by overwriting with spaces, for terminals without erase
+    // support
+    padded_erase: bool,
+    // Move the cursor relative to where it is, for terminals where
+    // the row is unknown
+    relative_movement: bool,
+    caps: Caps,
 }
 
 impl Default for Terminal {
@@ -57,17 +112,101 @@ impl Terminal {
             columns,
             cursor,
             row_offset,
+            right_prompt: RightPrompt::Hidden,
+            line_end: cursor.column as isize,
+            padded_erase: false,
+            relative_movement: false,
+            caps: Caps(&Vt100),
         }
     }
 
+    // Change size, keeping the cursor at the same offset from the
+    // start of the line. The line is assumed to still start on the same
+    // row, unless that would put the cursor outside the screen.
     pub fn resize(&mut self, rows: usize, columns: usize) {
-        self.rows = rows;
-        self.columns = columns;
+        let offset = self.current_offset();
+        let start_row = -self.row_offset;
+
+        self.rows = rows.max(1);
+        self.columns = columns.max(1);
+
+        let position = self.position_from_offset(offset);
+        let start_row = start_row.clamp(
+            -(position.row as isize),
+            self.rows as isize - 1 - position.row as isize,
+        );
+
+        self.row_offset = -start_row;
+        self.cursor = Cursor::new(
+            (start_row + position.row as isize) as usize,
+            position.column,
+        );
+        self.right_prompt = RightPrompt::Hidden;
     }
 
     pub fn reset(&mut self, cursor: Cursor) {
         self.cursor = cursor;
         self.row_offset = -(cursor.row as isize);
+        self.right_prompt = RightPrompt::Hidden;
+        self.line_end = cursor.column as isize;
+    }
+
+    // Put the cursor back after a resize between lines. Terminals
+    // don't reflow text already printed, so it stays where it was, or
+    // at the nearest cell on screen.
+    pub(crate) fn restore_cursor(&mut self, cursor: Cursor) {
+        self.reset(Cursor::new(
+            cursor.row.min(self.rows - 1),
+            cursor.column.min(self.columns - 1),
+        ));
+    }
+
+    pub(crate) fn line_end(&self) -> isize {
+        self.line_end
+    }
+
+    pub(crate) fn set_line_end(&mut self, offset: isize) {
+        self.line_end = offset;
+    }
+
+    pub(crate) fn padded_erase(&self) -> bool {
+        self.padded_erase
+    }
+
+    pub(crate) fn set_padded_erase(&mut self, enabled: bool) {
+        self.padded_erase = enabled;
+    }
+
+    pub(crate) fn relative_movement(&self) -> bool {
+        self.relative_movement
+    }
+
+    pub(crate) fn set_relative_movement(&mut self, enabled: bool) {
+        self.relative_movement = enabled;
+    }
+
+    pub(crate) fn caps(&self) -> &'static (dyn TerminalCaps + Sync) {
+        self.caps.0
+    }
+
+    pub(crate) fn set_caps(&mut self, caps: &'static (dyn TerminalCaps + Sync)) {
+        self.caps = Caps(caps);
+    }
+
+    pub(crate) fn right_prompt(&self) -> RightPrompt {
+        self.right_prompt
+    }
+
+    pub(crate) fn set_right_prompt(&mut self, right_prompt: RightPrompt) {
+        self.right_prompt = right_prompt;
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
     }
 
     pub fn get_cursor(&self) -> Cursor {
@@ -292,6 +431,51 @@ mod tests {
         assert_eq!(term.get_position(), Position::new(0, 0));
     }
 
+    #[test]
+    fn resize() {
+        let mut term = Terminal::new(4, 10, Cursor::new(1, 0));
+
+        term.move_cursor(Position::new(1, 5));
+        assert_eq!(term.current_offset(), 15);
+
+        // Shrinking wraps the line at the new width
+        term.resize(4, 6);
+        assert_eq!(term.get_size(), (4, 6));
+        assert_eq!(term.get_cursor(), Cursor::new(3, 3));
+        assert_eq!(term.get_position(), Position::new(2, 3));
+        assert_eq!(term.current_offset(), 15);
+
+        // and moves the start of the line up if the cursor no longer
+        // fits below it
+        term.resize(4, 4);
+        assert_eq!(term.get_cursor(), Cursor::new(3, 3));
+        assert_eq!(term.get_position(), Position::new(3, 3));
+        assert_eq!(
+            term.position_to_cursor(Position::new(0, 0)),
+            Some(Cursor::new(0, 0))
+        );
+
+        term.resize(2, 4);
+        assert_eq!(term.get_cursor(), Cursor::new(1, 3));
+        assert_eq!(term.position_to_cursor(Position::new(0, 0)), None);
+        assert_eq!(term.current_offset(), 15);
+
+        // Growing moves it down again, keeping the cursor on screen
+        term.resize(4, 20);
+        assert_eq!(term.get_cursor(), Cursor::new(0, 15));
+        assert_eq!(term.get_position(), Position::new(0, 15));
+
+        let mut term = Terminal::new(4, 10, Cursor::new(1, 0));
+
+        term.move_cursor(Position::new(1, 5));
+        term.resize(8, 20);
+        assert_eq!(term.get_cursor(), Cursor::new(1, 15));
+        assert_eq!(
+            term.position_to_cursor(Position::new(0, 0)),
+            Some(Cursor::new(1, 0))
+        );
+    }
+
     #[test]
     fn offset() {
         let term = Terminal::new(4, 10, Cursor::new(1, 0));
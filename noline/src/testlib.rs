@@ -1,4 +1,3 @@
-use core::time::Duration;
 use std::string::String;
 use std::thread;
 use std::thread::JoinHandle;
@@ -7,6 +6,7 @@ use std::vec::Vec;
 use crossbeam::channel::{unbounded, Receiver, Sender};
 
 use crate::input::{Action, ControlCharacter, Parser, CSI};
+use crate::output::TAB_STOP;
 use crate::terminal::Cursor;
 
 use ControlCharacter::*;
@@ -16,19 +16,35 @@ pub mod csi {
     pub const DOWN: &str = "\x1b[B";
     pub const LEFT: &str = "\x1b[D";
     pub const RIGHT: &str = "\x1b[C";
+    pub const ALT_UP: &str = "\x1b[1;3A";
+    pub const ALT_DOWN: &str = "\x1b[1;3B";
     pub const HOME: &str = "\x1b[1~";
+    pub const INSERT: &str = "\x1b[2~";
     pub const DELETE: &str = "\x1b[3~";
     pub const END: &str = "\x1b[4~";
+    pub const SHIFT_TAB: &str = "\x1b[Z";
 }
 
+// Second cell of a wide char
+const WIDE_CHAR_TAIL: char = '\u{ffff}';
+
 pub struct MockTerminal {
     parser: Parser,
     screen: Vec<Vec<char>>,
+    // Graphic rendition (SGR parameter) of each cell
+    styles: Vec<Vec<usize>>,
+    style: usize,
     pub cursor: Cursor,
     pub rows: usize,
     pub columns: usize,
     saved_cursor: Option<Cursor>,
     pub bell: bool,
+    pub bracketed_paste: bool,
+    // Ignore erase in display, like a terminal without support for it
+    pub ignore_erase: bool,
+    /// Output the terminal doesn't implement, in the order received.
+    /// It's recorded instead of handled, and fails conformance tests.
+    pub unsupported: Vec<String>,
     pub terminal_tx: Option<Sender<u8>>,
     pub terminal_rx: Receiver<u8>,
     pub keyboard_tx: Sender<u8>,
@@ -43,11 +59,16 @@ impl MockTerminal {
         Self {
             parser: Parser::new(),
             screen: vec![vec!['\0'; columns]; rows],
+            styles: vec![vec![0; columns]; rows],
+            style: 0,
             cursor: origin,
             rows,
             columns,
             saved_cursor: None,
             bell: false,
+            bracketed_paste: false,
+            ignore_erase: false,
+            unsupported: Vec::new(),
             terminal_tx: Some(terminal_tx),
             terminal_rx,
             keyboard_tx,
@@ -64,29 +85,72 @@ impl MockTerminal {
     pub fn screen_as_string(&self) -> String {
         self.screen
             .iter()
-            .map(|v| v.iter().take_while(|&&c| c != '\0').collect::<String>())
+            .map(|v| {
+                v.iter()
+                    .take_while(|&&c| c != '\0')
+                    .filter(|&&c| c != WIDE_CHAR_TAIL)
+                    .collect::<String>()
+            })
             .filter(|s| !s.is_empty())
             .collect::<Vec<String>>()
             .join("\n")
     }
 
+    /// Return row with unwritten cells as spaces, without trailing
+    /// spaces
+    pub fn row_as_string(&self, row: usize) -> String {
+        let s = self.screen[row]
+            .iter()
+            .filter(|&&c| c != WIDE_CHAR_TAIL)
+            .map(|&c| if c == '\0' { ' ' } else { c })
+            .collect::<String>();
+
+        s.trim_end().into()
+    }
+
     pub fn current_line_as_string(&self) -> String {
         self.screen[self.cursor.row]
             .iter()
             .take_while(|&&c| c != '\0')
+            .filter(|&&c| c != WIDE_CHAR_TAIL)
             .collect()
     }
 
+    /// Text printed with graphic rendition `sgr`, in screen order
+    pub fn styled_as_string(&self, sgr: usize) -> String {
+        self.screen
+            .iter()
+            .flatten()
+            .zip(self.styles.iter().flatten())
+            .filter_map(|(&c, &style)| (style == sgr && c != WIDE_CHAR_TAIL).then_some(c))
+            .collect()
+    }
+
+    /// Text printed with reverse video, in screen order
+    pub fn highlighted_as_string(&self) -> String {
+        self.styled_as_string(7)
+    }
+
+    /// Text printed faint, in screen order
+    pub fn dimmed_as_string(&self) -> String {
+        self.styled_as_string(2)
+    }
+
     fn move_column(&mut self, steps: isize) {
         self.cursor.column =
             0.max((self.cursor.column as isize + steps).min(self.columns as isize - 1)) as usize;
-        dbg!(self.cursor.column);
+    }
+
+    fn record_unsupported(&mut self, what: impl core::fmt::Debug) {
+        self.unsupported.push(format!("{:?}", what));
     }
 
     fn scroll_up(&mut self, lines: usize) {
         for _ in 0..lines {
             self.screen.remove(0);
             self.screen.push(vec!['\0'; self.columns]);
+            self.styles.remove(0);
+            self.styles.push(vec![0; self.columns]);
         }
     }
 
@@ -94,34 +158,53 @@ impl MockTerminal {
         for _ in 0..lines {
             self.screen.pop();
             self.screen.insert(0, vec!['\0'; self.columns]);
+            self.styles.pop();
+            self.styles.insert(0, vec![0; self.columns]);
         }
     }
 
     pub fn advance(&mut self, byte: u8) -> Option<Vec<u8>> {
+        // Like a terminal, start over on ESC in the middle of a
+        // sequence, e.g. one cut short by a cancelled write
+        if byte == 0x1b && !self.parser.is_ground() {
+            self.parser = Parser::new();
+        }
+
         let mock_term_action = self.parser.advance(byte);
 
-        dbg!(mock_term_action);
         match mock_term_action {
             Action::Ignore => (),
             Action::Print(c) => {
-                let pos = self.cursor.column;
-                let line = self.current_line();
+                let Cursor { row, column } = self.cursor;
+
+                self.screen[row][column] = c.as_char();
+                self.styles[row][column] = self.style;
+
+                // Wide chars take two cells. Like printing in the last
+                // column, the cursor stays there until the next newline.
+                if c.width() > 1 && column + 1 < self.columns {
+                    self.screen[row][column + 1] = WIDE_CHAR_TAIL;
+                    self.styles[row][column + 1] = self.style;
+                }
 
-                line[pos] = c.as_char();
-                self.move_column(1);
+                self.move_column(c.width() as isize);
             }
             Action::ControlSequenceIntroducer(csi) => match csi {
-                CSI::CUU(_) => unimplemented!(),
-                CSI::CUD(_) => unimplemented!(),
-                CSI::CUF(_) => unimplemented!(),
-                CSI::CUB(_) => unimplemented!(),
-                CSI::CPR(_, _) => unimplemented!(),
+                CSI::CUU(rows) => self.cursor.row = self.cursor.row.saturating_sub(rows),
+                CSI::CUD(rows) => self.cursor.row = (self.cursor.row + rows).min(self.rows - 1),
+                CSI::CUF(columns) => self.move_column(columns as isize),
+                CSI::CUB(columns) => self.move_column(-(columns as isize)),
+                // Back to the previous tab stop, or the first column
+                CSI::CBT => {
+                    self.cursor.column = self.cursor.column.saturating_sub(1) / TAB_STOP * TAB_STOP;
+                }
                 CSI::CUP(row, column) => {
                     self.cursor = Cursor::new(
                         (row - 1).min(self.rows - 1),
                         (column - 1).min(self.columns - 1),
                     );
                 }
+                CSI::ED(_) if self.ignore_erase => (),
                 CSI::ED(_) => {
                     let cursor = self.get_cursor();
 
@@ -129,9 +212,19 @@ impl MockTerminal {
                         let start = if row == cursor.row { cursor.column } else { 0 };
                         for column in (start)..self.columns {
                             self.screen[row][column] = '\0';
+                            self.styles[row][column] = 0;
                         }
                     }
                 }
+                // Erase to the end of the row, the only mode used
+                CSI::EL(_) => {
+                    let cursor = self.get_cursor();
+
+                    for column in cursor.column..self.columns {
+                        self.screen[cursor.row][column] = '\0';
+                        self.styles[cursor.row][column] = 0;
+                    }
+                }
                 CSI::DSR => {
                     return Some(
                         format!("\x1b[{};{}R", self.cursor.row + 1, self.cursor.column + 1)
@@ -139,54 +232,89 @@ impl MockTerminal {
                             .collect::<Vec<u8>>(),
                     );
                 }
-                CSI::Unknown(b) => {
-                    dbg!(b as char);
-                    unimplemented!()
-                }
                 CSI::SU(lines) => {
                     self.scroll_up(lines);
                 }
                 CSI::SD(lines) => {
                     self.scroll_down(lines);
                 }
-                CSI::Home => unimplemented!(),
-                CSI::Delete => unimplemented!(),
-                CSI::End => unimplemented!(),
+                CSI::SGR(style @ (0 | 2 | 7 | 30..=37)) => self.style = style,
+                CSI::SM(2004) => self.bracketed_paste = true,
+                CSI::RM(2004) => self.bracketed_paste = false,
+                // Keys and reports sent by terminals, and modes the
+                // editor doesn't use
+                CSI::CUUMod(_)
+                | CSI::CUDMod(_)
+                | CSI::CUFMod(_)
+                | CSI::CUBMod(_)
+                | CSI::CPR(_, _)
+                | CSI::Unknown(_)
+                | CSI::SGR(_)
+                | CSI::SM(_)
+                | CSI::RM(_)
+                | CSI::Home
+                | CSI::Insert
+                | CSI::Delete
+                | CSI::End
+                | CSI::HomeMod(_)
+                | CSI::InsertMod(_)
+                | CSI::DeleteMod(_)
+                | CSI::EndMod(_)
+                | CSI::PasteStart
+                | CSI::PasteEnd => self.record_unsupported(mock_term_action),
             },
-            Action::InvalidUtf8 => unreachable!(),
-            Action::ControlCharacter(ctrl) => {
-                dbg!(ctrl);
-
-                match ctrl {
-                    ControlCharacter::CarriageReturn => self.cursor.column = 0,
-                    ControlCharacter::LineFeed => {
-                        if self.cursor.row + 1 == self.rows {
-                            self.scroll_up(1);
-                        } else {
-                            self.cursor.row += 1;
-                        }
+            Action::InvalidUtf8 => self.record_unsupported(mock_term_action),
+            Action::ControlCharacter(ctrl) => match ctrl {
+                ControlCharacter::CarriageReturn => self.cursor.column = 0,
+                ControlCharacter::LineFeed => {
+                    if self.cursor.row + 1 == self.rows {
+                        self.scroll_up(1);
+                    } else {
+                        self.cursor.row += 1;
                     }
-                    ControlCharacter::CtrlG => self.bell = true,
-                    _ => (),
                 }
-            }
+                ControlCharacter::CtrlG => self.bell = true,
+                _ => (),
+            },
             Action::EscapeSequence(esc) => match esc {
                 0x37 => {
                     self.saved_cursor = Some(self.cursor);
                 }
-                0x38 => {
-                    let cursor = self.saved_cursor.unwrap();
-                    self.cursor = cursor;
-                }
-                _ => {
-                    dbg!(esc);
-                }
+                // Restoring without saving first is left undefined
+                0x38 => match self.saved_cursor {
+                    Some(cursor) => self.cursor = cursor,
+                    None => self.record_unsupported(mock_term_action),
+                },
+                _ => self.record_unsupported(mock_term_action),
             },
         }
 
         None
     }
 
+    /// Resize without reflowing text, like xterm. Rows are removed
+    /// from the top if the cursor would end up below the screen.
+    pub fn resize(&mut self, rows: usize, columns: usize) {
+        if self.cursor.row >= rows {
+            self.scroll_up(self.cursor.row + 1 - rows);
+            self.cursor.row = rows - 1;
+        }
+
+        for row in self.screen.iter_mut() {
+            row.resize(columns, '\0');
+        }
+
+        for row in self.styles.iter_mut() {
+            row.resize(columns, 0);
+        }
+
+        self.screen.resize(rows, vec!['\0'; columns]);
+        self.styles.resize(rows, vec![0; columns]);
+        self.cursor.column = self.cursor.column.min(columns - 1);
+        self.rows = rows;
+        self.columns = columns;
+    }
+
     pub fn get_cursor(&self) -> Cursor {
         self.cursor
     }
@@ -213,6 +341,21 @@ impl MockTerminal {
     }
 }
 
+// Unit tests of the crate fail on unsupported output, wherever the
+// terminal is used
+#[cfg(test)]
+impl Drop for MockTerminal {
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            assert!(
+                self.unsupported.is_empty(),
+                "unsupported output: {:?}",
+                self.unsupported
+            );
+        }
+    }
+}
+
 impl ToByteVec for &str {
     fn to_byte_vec(self) -> Vec<u8> {
         self.bytes().collect()
@@ -337,62 +480,3 @@ pub fn test_cases() -> Vec<TestCase> {
         TestCase::new(["abc", "def"], ["abc", "def"]),
     ]
 }
-
-pub fn test_editor_with_case<IO: Send + 'static>(
-    case: TestCase,
-    prompt: &str,
-    get_io: impl FnOnce(&mut MockTerminal) -> IO,
-    spawn_thread: impl FnOnce(IO, Sender<String>) -> JoinHandle<()>,
-) {
-    let (rows, columns) = (20, 80);
-
-    let (string_tx, string_rx) = unbounded();
-
-    let mut term = MockTerminal::new(rows, columns, Cursor::new(0, 0));
-
-    let keyboard_tx = term.keyboard_tx.clone();
-
-    let io = get_io(&mut term);
-
-    let term = term.start_thread();
-    let handle = spawn_thread(io, string_tx);
-
-    let output: Vec<String> = case
-        .input
-        .iter()
-        .map(|seq| {
-            // To avoid race with prompt reset, we need to wait a
-            // little. This is not ideal, but will do for now.
-            thread::sleep(core::time::Duration::from_millis(100));
-
-            for &b in seq {
-                keyboard_tx.send(b).unwrap();
-            }
-
-            keyboard_tx.send(0xd).unwrap();
-
-            string_rx.recv().unwrap()
-        })
-        .collect();
-
-    // Added delay to prevent race with terminal reset
-    std::thread::sleep(Duration::from_millis(100));
-
-    keyboard_tx.send(0x3).unwrap();
-
-    drop(keyboard_tx);
-    let term = term.join().unwrap();
-
-    handle.join().unwrap();
-
-    assert_eq!(output.len(), case.output.len());
-
-    for (seen, expected) in output.iter().zip(case.output.iter()) {
-        assert_eq!(seen, expected);
-    }
-
-    assert_eq!(
-        term.screen_as_string(),
-        case.screen_as_string(prompt, columns)
-    );
-}
@@ -0,0 +1,314 @@
+//! Undo of edits to the line, with Ctrl-_ or Ctrl-X Ctrl-U
+
+use crate::line_buffer::Buffer;
+
+// Numbers in records are little-endian u16, so lines longer than
+// u16::MAX bytes aren't undone
+const NUMBER_LEN: usize = 2;
+const HEADER_LEN: usize = 4 * NUMBER_LEN;
+
+// Record of a single edit, or a run of characters typed one after
+// another
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct Record {
+    // Byte offset of the edit
+    start: usize,
+    // Bytes inserted by the edit
+    inserted: usize,
+    // Cursor position before the edit
+    cursor: usize,
+    // Bytes removed by the edit, stored after the header
+    removed: usize,
+}
+
+impl Record {
+    fn header(&self) -> Option<[u8; HEADER_LEN]> {
+        let mut header = [0; HEADER_LEN];
+        let numbers = [self.start, self.inserted, self.cursor, self.removed];
+
+        for (chunk, &n) in header.chunks_mut(NUMBER_LEN).zip(numbers.iter()) {
+            chunk.copy_from_slice(&u16::try_from(n).ok()?.to_le_bytes());
+        }
+
+        Some(header)
+    }
+
+    fn from_header(bytes: &[u8]) -> Self {
+        let number = |i: usize| {
+            usize::from(u16::from_le_bytes([
+                bytes[i * NUMBER_LEN],
+                bytes[i * NUMBER_LEN + 1],
+            ]))
+        };
+
+        Self {
+            start: number(0),
+            inserted: number(1),
+            cursor: number(2),
+            removed: number(3),
+        }
+    }
+
+    fn len(&self) -> usize {
+        HEADER_LEN + self.removed
+    }
+}
+
+// Edits to the line, oldest first, followed by the line as it was
+// when last checked with [`UndoBuffer::checkpoint`]. Each record holds
+// a header followed by the bytes removed by the edit. Undoing an edit
+// replaces the bytes it inserted with the bytes it removed.
+//
+// The oldest records are dropped when the buffer is full. When the
+// line itself doesn't fit, all records are dropped.
+pub(crate) struct UndoBuffer<B: Buffer> {
+    buf: B,
+    // Offset of the line after the records, None if not stored
+    line_start: Option<usize>,
+    // Cursor position when the line was last checked
+    cursor: usize,
+}
+
+impl<B: Buffer> UndoBuffer<B> {
+    pub(crate) fn new(buf: B) -> Self {
+        Self {
+            buf,
+            line_start: None,
+            cursor: 0,
+        }
+    }
+
+    // Drop all records, for a new line
+    pub(crate) fn clear(&mut self) {
+        self.buf.truncate_buffer(0);
+        self.line_start = None;
+    }
+
+    fn stored_line(&self) -> Option<&[u8]> {
+        self.line_start.map(|start| &self.buf.as_slice()[start..])
+    }
+
+    fn fits(&self, len: usize) -> bool {
+        self.buf.capacity().is_none_or(|capacity| len <= capacity)
+    }
+
+    // Offset of each record
+    fn record_offsets(&self) -> impl Iterator<Item = usize> + '_ {
+        let end = self.line_start.unwrap_or(0);
+        let bytes = self.buf.as_slice();
+
+        core::iter::successors(Some(0).filter(|&offset| offset < end), move |&offset| {
+            let next = offset + Record::from_header(&bytes[offset..]).len();
+            Some(next).filter(|&next| next < end)
+        })
+    }
+
+    fn last_record(&self) -> Option<(usize, Record)> {
+        self.record_offsets()
+            .last()
+            .map(|offset| (offset, Record::from_header(&self.buf.as_slice()[offset..])))
+    }
+
+    fn remove_bytes(&mut self, offset: usize, len: usize) {
+        for _ in 0..len {
+            self.buf.remove_byte(offset);
+        }
+    }
+
+    fn insert_bytes(&mut self, offset: usize, bytes: &[u8]) {
+        for (i, &byte) in bytes.iter().enumerate() {
+            self.buf.insert_byte(offset + i, byte);
+        }
+    }
+
+    fn store_line(&mut self, line: &str, cursor: usize) {
+        self.clear();
+
+        if self.fits(line.len()) {
+            self.insert_bytes(0, line.as_bytes());
+            self.line_start = Some(0);
+        }
+
+        self.cursor = cursor;
+    }
+
+    // Record the edit made since the line was last checked, if any.
+    // Call before handling each key, with the line and the cursor
+    // position.
+    pub(crate) fn checkpoint(&mut self, line: &str, cursor: usize) {
+        let Some(stored) = self.stored_line() else {
+            return self.store_line(line, cursor);
+        };
+
+        let (old, new) = (stored, line.as_bytes());
+
+        if old == new {
+            self.cursor = cursor;
+            return;
+        }
+
+        let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+        let suffix = old[prefix..]
+            .iter()
+            .rev()
+            .zip(new[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        let mut record = Record {
+            start: prefix,
+            inserted: new.len() - prefix - suffix,
+            cursor: self.cursor,
+            removed: old.len() - prefix - suffix,
+        };
+
+        let line_start = self.line_start.unwrap_or(0);
+        let last = self.last_record();
+
+        // Characters typed one after another are undone together
+        let merged = match last {
+            Some((offset, last))
+                if record.removed == 0
+                    && last.removed == 0
+                    && last.start + last.inserted == record.start =>
+            {
+                record = Record {
+                    inserted: last.inserted + record.inserted,
+                    ..last
+                };
+                Some(offset)
+            }
+            _ => None,
+        };
+
+        let Some(header) = record.header() else {
+            return self.store_line(line, cursor);
+        };
+
+        let (offset, len) = match merged {
+            Some(offset) => (offset, line_start - offset + line.len()),
+            None => (line_start, record.len() + line.len()),
+        };
+
+        // Drop the oldest records until the edit fits. The line is
+        // stored again on its own if even the edit itself doesn't.
+        let mut dropped = 0;
+
+        while !self.fits(offset - dropped + len) {
+            if dropped >= offset {
+                return self.store_line(line, cursor);
+            }
+
+            dropped += Record::from_header(&self.buf.as_slice()[dropped..]).len();
+        }
+
+        self.remove_bytes(0, dropped);
+        let line_start = line_start - dropped;
+
+        match merged {
+            Some(offset) => {
+                self.remove_bytes(offset - dropped, HEADER_LEN);
+                self.insert_bytes(offset - dropped, &header);
+                self.buf.truncate_buffer(line_start);
+            }
+            None => {
+                // The removed bytes are kept in place, after the header
+                self.remove_bytes(line_start, prefix);
+                self.buf.truncate_buffer(line_start + record.removed);
+                self.insert_bytes(line_start, &header);
+            }
+        }
+
+        let end = self.buf.buffer_len();
+        self.insert_bytes(end, line.as_bytes());
+        self.line_start = Some(end);
+        self.cursor = cursor;
+    }
+
+    // Undo the last edit, returning the line as it was before it and
+    // the cursor position. Call [`UndoBuffer::checkpoint`] first, so
+    // the last edit is recorded.
+    pub(crate) fn undo(&mut self) -> Option<(&[u8], usize)> {
+        let (offset, record) = self.last_record()?;
+
+        // Move the bytes before the edit in front of the removed
+        // bytes, then drop the inserted bytes after them
+        self.remove_bytes(offset, HEADER_LEN);
+
+        let line_start = offset + record.removed;
+
+        for i in 0..record.start {
+            let byte = self.buf.remove_byte(line_start + i);
+            self.buf.insert_byte(offset + i, byte);
+        }
+
+        self.remove_bytes(offset + record.start + record.removed, record.inserted);
+
+        self.line_start = Some(offset);
+        self.cursor = record.cursor;
+
+        Some((&self.buf.as_slice()[offset..], record.cursor))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::string::String;
+
+    use crate::line_buffer::SliceBuffer;
+
+    use super::*;
+
+    fn undo<B: Buffer>(undo: &mut UndoBuffer<B>) -> Option<(String, usize)> {
+        undo.undo()
+            .map(|(line, cursor)| (String::from_utf8(line.to_vec()).unwrap(), cursor))
+    }
+
+    #[test]
+    fn undo_edits() {
+        let mut storage = [0; 64];
+        let mut buffer = UndoBuffer::new(SliceBuffer::new(&mut storage));
+
+        buffer.checkpoint("", 0);
+        buffer.checkpoint("a", 1);
+        buffer.checkpoint("ab", 2);
+        buffer.checkpoint("abc", 3);
+        buffer.checkpoint("abc", 1);
+        buffer.checkpoint("æbc", 1);
+        buffer.checkpoint("æbc", 3);
+        buffer.checkpoint("æ", 1);
+
+        assert_eq!(undo(&mut buffer), Some(("æbc".into(), 3)));
+        assert_eq!(undo(&mut buffer), Some(("abc".into(), 1)));
+        assert_eq!(undo(&mut buffer), Some(("".into(), 0)));
+        assert_eq!(undo(&mut buffer), None);
+
+        // Undone edits are replaced by new ones
+        buffer.checkpoint("x", 1);
+        assert_eq!(undo(&mut buffer), Some(("".into(), 0)));
+        assert_eq!(undo(&mut buffer), None);
+    }
+
+    #[test]
+    fn full_undo_buffer() {
+        let mut storage = [0; 32];
+        let mut buffer = UndoBuffer::new(SliceBuffer::new(&mut storage));
+
+        buffer.checkpoint("abc", 3);
+        buffer.checkpoint("ab", 2);
+        buffer.checkpoint("a", 1);
+        buffer.checkpoint("", 0);
+        buffer.checkpoint("xyz", 3);
+
+        // The oldest record was dropped to make room for the last one
+        assert_eq!(undo(&mut buffer), Some(("".into(), 0)));
+        assert_eq!(undo(&mut buffer), Some(("a".into(), 1)));
+        assert_eq!(undo(&mut buffer), Some(("ab".into(), 2)));
+        assert_eq!(undo(&mut buffer), None);
+
+        // Lines that don't fit clear the buffer
+        buffer.checkpoint(&"x".repeat(33), 0);
+        buffer.checkpoint("", 0);
+        assert_eq!(undo(&mut buffer), None);
+    }
+}
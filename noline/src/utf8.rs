@@ -1,3 +1,4 @@
+#[cfg(not(feature = "ascii-only"))]
 enum Utf8ByteType {
     SingleByte,
     StartTwoByte,
@@ -7,11 +8,13 @@ enum Utf8ByteType {
     Invalid,
 }
 
+#[cfg(not(feature = "ascii-only"))]
 trait Utf8Byte {
     fn utf8_byte_type(&self) -> Utf8ByteType;
     fn utf8_is_continuation(&self) -> bool;
 }
 
+#[cfg(not(feature = "ascii-only"))]
 impl Utf8Byte for u8 {
     fn utf8_byte_type(&self) -> Utf8ByteType {
         let byte = *self;
@@ -36,6 +39,7 @@ impl Utf8Byte for u8 {
     }
 }
 
+#[cfg(not(feature = "ascii-only"))]
 #[derive(Debug, Eq, PartialEq)]
 enum Utf8DecoderState {
     New,
@@ -45,20 +49,33 @@ enum Utf8DecoderState {
     Done,
 }
 
+#[cfg(not(feature = "ascii-only"))]
 #[derive(Eq, PartialEq, Copy, Clone)]
 pub struct Utf8Char {
     buf: [u8; 4],
     len: u8,
 }
 
-#[cfg(test)]
-impl std::fmt::Debug for Utf8Char {
+impl core::fmt::Debug for Utf8Char {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_tuple("Utf8Char").field(&self.as_char()).finish()
     }
 }
 
+#[cfg(feature = "defmt")]
+impl defmt::Format for Utf8Char {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "Utf8Char({})", self.as_char())
+    }
+}
+
+#[cfg(not(feature = "ascii-only"))]
 impl Utf8Char {
+    pub(crate) const SPACE: Self = Self {
+        buf: [b' ', 0, 0, 0],
+        len: 1,
+    };
+
     fn new(bytes: &[u8; 4], len: usize) -> Self {
         Self {
             len: len as u8,
@@ -83,7 +100,6 @@ impl Utf8Char {
         c
     }
 
-    #[cfg(test)]
     pub(crate) fn as_char(&self) -> char {
         char::from_u32(
             self.as_bytes()
@@ -105,21 +121,23 @@ impl Utf8Char {
     }
 }
 
-#[cfg_attr(test, derive(Debug))]
-#[derive(Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq)]
 pub enum Utf8DecoderStatus {
+    #[cfg(not(feature = "ascii-only"))]
     Continuation,
     Done(Utf8Char),
     Error,
 }
 
+#[cfg(not(feature = "ascii-only"))]
 #[derive(Debug, Eq, PartialEq)]
 pub struct Utf8Decoder {
     state: Utf8DecoderState,
     buf: [u8; 4],
-    pos: usize,
+    pos: u8,
 }
 
+#[cfg(not(feature = "ascii-only"))]
 impl Utf8Decoder {
     pub fn new() -> Self {
         Self {
@@ -134,7 +152,7 @@ impl Utf8Decoder {
             return Err(());
         }
 
-        self.buf[self.pos] = byte;
+        self.buf[self.pos as usize] = byte;
         self.pos += 1;
 
         Ok(())
@@ -171,7 +189,7 @@ impl Utf8Decoder {
             Utf8DecoderState::ExpectingOneByte => {
                 if self.insert_byte(byte).is_ok() {
                     self.state = Utf8DecoderState::Done;
-                    Utf8DecoderStatus::Done(Utf8Char::new(&self.buf, self.pos))
+                    Utf8DecoderStatus::Done(Utf8Char::new(&self.buf, self.pos as usize))
                 } else {
                     Utf8DecoderStatus::Error
                 }
@@ -197,6 +215,142 @@ impl Utf8Decoder {
     }
 }
 
+// With the `ascii-only` feature, input is restricted to printable
+// ASCII, so every char is a single byte and no decoding is needed
+#[cfg(feature = "ascii-only")]
+#[derive(Eq, PartialEq, Copy, Clone)]
+pub struct Utf8Char {
+    byte: [u8; 1],
+}
+
+#[cfg(feature = "ascii-only")]
+impl Utf8Char {
+    pub(crate) const SPACE: Self = Self { byte: [b' '] };
+
+    #[cfg(test)]
+    pub(crate) fn from_str(s: &str) -> Self {
+        assert!(s.len() == 1 && s.is_ascii());
+
+        Self {
+            byte: [s.as_bytes()[0]],
+        }
+    }
+
+    pub(crate) fn as_char(&self) -> char {
+        self.byte[0] as char
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.byte
+    }
+}
+
+impl Utf8Char {
+    // Number of terminal columns used to display the char
+    pub(crate) fn width(&self) -> usize {
+        char_width(self.as_char())
+    }
+}
+
+// Ranges of chars displayed in two columns: East Asian wide and
+// fullwidth chars, and emoji
+#[cfg(not(feature = "ascii-only"))]
+const WIDE_CHARS: [(char, char); 17] = [
+    ('\u{1100}', '\u{115f}'),
+    ('\u{231a}', '\u{231b}'),
+    ('\u{2329}', '\u{232a}'),
+    ('\u{2e80}', '\u{303e}'),
+    ('\u{3041}', '\u{33ff}'),
+    ('\u{3400}', '\u{4dbf}'),
+    ('\u{4e00}', '\u{9fff}'),
+    ('\u{a000}', '\u{a4cf}'),
+    ('\u{ac00}', '\u{d7a3}'),
+    ('\u{f900}', '\u{faff}'),
+    ('\u{fe30}', '\u{fe4f}'),
+    ('\u{ff00}', '\u{ff60}'),
+    ('\u{ffe0}', '\u{ffe6}'),
+    ('\u{1f300}', '\u{1f64f}'),
+    ('\u{1f680}', '\u{1f6ff}'),
+    ('\u{1f900}', '\u{1f9ff}'),
+    ('\u{20000}', '\u{3fffd}'),
+];
+
+// Number of terminal columns used to display `c`. Combining and other
+// zero-width chars are counted as one column.
+#[cfg(not(feature = "ascii-only"))]
+pub(crate) fn char_width(c: char) -> usize {
+    if c >= WIDE_CHARS[0].0
+        && WIDE_CHARS
+            .iter()
+            .any(|&(start, end)| (start..=end).contains(&c))
+    {
+        2
+    } else {
+        1
+    }
+}
+
+// Only ASCII is typed with the `ascii-only` feature, so the table of
+// wide chars is left out
+#[cfg(feature = "ascii-only")]
+pub(crate) fn char_width(_c: char) -> usize {
+    1
+}
+
+// Number of terminal columns used to display `s`
+pub(crate) fn str_width(s: &str) -> usize {
+    s.chars().map(char_width).sum()
+}
+
+// Single char of a case mapping, or `c` if it maps to several, like
+// 'ß' to "SS"
+#[cfg(not(feature = "ascii-only"))]
+fn single_char(c: char, mut mapping: impl Iterator<Item = char>) -> char {
+    match (mapping.next(), mapping.next()) {
+        (Some(mapped), None) => mapped,
+        _ => c,
+    }
+}
+
+#[cfg(not(feature = "ascii-only"))]
+pub(crate) fn to_uppercase(c: char) -> char {
+    single_char(c, c.to_uppercase())
+}
+
+#[cfg(not(feature = "ascii-only"))]
+pub(crate) fn to_lowercase(c: char) -> char {
+    single_char(c, c.to_lowercase())
+}
+
+// Case mapping of ASCII only, leaving out the Unicode tables
+#[cfg(feature = "ascii-only")]
+pub(crate) fn to_uppercase(c: char) -> char {
+    c.to_ascii_uppercase()
+}
+
+#[cfg(feature = "ascii-only")]
+pub(crate) fn to_lowercase(c: char) -> char {
+    c.to_ascii_lowercase()
+}
+
+#[cfg(feature = "ascii-only")]
+#[derive(Debug, Eq, PartialEq)]
+pub struct Utf8Decoder {}
+
+#[cfg(feature = "ascii-only")]
+impl Utf8Decoder {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn advance(&mut self, byte: u8) -> Utf8DecoderStatus {
+        match byte {
+            0x20..=0x7e => Utf8DecoderStatus::Done(Utf8Char { byte: [byte] }),
+            _ => Utf8DecoderStatus::Error,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -210,9 +364,11 @@ mod tests {
             Utf8DecoderStatus::Done(Utf8Char::from_str("a"))
         );
 
+        #[cfg(not(feature = "ascii-only"))]
         assert_eq!(parser.advance(b'a'), Utf8DecoderStatus::Error);
     }
 
+    #[cfg(not(feature = "ascii-only"))]
     #[test]
     fn twobyte() {
         let mut parser = Utf8Decoder::new();
@@ -229,6 +385,7 @@ mod tests {
         assert_eq!(parser.advance(b'a'), Utf8DecoderStatus::Error);
     }
 
+    #[cfg(not(feature = "ascii-only"))]
     #[test]
     fn threebyte() {
         let mut parser = Utf8Decoder::new();
@@ -246,6 +403,7 @@ mod tests {
         assert_eq!(parser.advance(b'a'), Utf8DecoderStatus::Error);
     }
 
+    #[cfg(not(feature = "ascii-only"))]
     #[test]
     fn fourbyte() {
         let mut parser = Utf8Decoder::new();
@@ -267,6 +425,20 @@ mod tests {
         assert_eq!(parser.advance(b'a'), Utf8DecoderStatus::Error);
     }
 
+    #[cfg(feature = "ascii-only")]
+    #[test]
+    fn ascii_only() {
+        assert_eq!(
+            Utf8Decoder::new().advance(b'~'),
+            Utf8DecoderStatus::Done(Utf8Char::from_str("~"))
+        );
+        assert_eq!(Utf8Char::from_str("~").as_char(), '~');
+
+        for byte in [0x7f, 0x80, 0xc3, 0xff] {
+            assert_eq!(Utf8Decoder::new().advance(byte), Utf8DecoderStatus::Error);
+        }
+    }
+
     #[test]
     fn invalid_start() {
         let mut parser = Utf8Decoder::new();
@@ -274,6 +446,7 @@ mod tests {
         assert_eq!(parser.advance(0b10000000), Utf8DecoderStatus::Error);
     }
 
+    #[cfg(not(feature = "ascii-only"))]
     #[test]
     fn invalid_continuation() {
         let mut parser = Utf8Decoder::new();
@@ -282,8 +455,22 @@ mod tests {
         assert_eq!(parser.advance(0b00000000), Utf8DecoderStatus::Error);
     }
 
+    #[cfg(not(feature = "ascii-only"))]
     #[test]
     fn to_char() {
         assert_eq!(Utf8Char::from_str("€").as_char(), '€');
     }
+
+    #[cfg(not(feature = "ascii-only"))]
+    #[test]
+    fn width() {
+        assert_eq!(Utf8Char::from_str("a").width(), 1);
+        assert_eq!(Utf8Char::from_str("æ").width(), 1);
+        assert_eq!(Utf8Char::from_str("你").width(), 2);
+        assert_eq!(Utf8Char::from_str("😂").width(), 2);
+        assert_eq!(Utf8Char::from_str("Ａ").width(), 2);
+        assert_eq!(Utf8Char::from_str("한").width(), 2);
+
+        assert_eq!(str_width("ab你好cd"), 8);
+    }
 }
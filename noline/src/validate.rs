@@ -0,0 +1,40 @@
+//! Line validation
+//!
+//! Implement [`Validator`] and add it to the editor with
+//! [`crate::builder::EditorBuilder::with_validator`]. When Enter is
+//! pressed on a line the validator rejects, the editor rings the bell
+//! and the line can be edited further.
+//!
+//! Functions and closures taking the line and returning `bool`
+//! implement [`Validator`]:
+//! ```
+//! use noline::builder::EditorBuilder;
+//!
+//! let mut buffer = [0; 64];
+//! let builder = EditorBuilder::from_slice(&mut buffer)
+//!     .with_validator(|line: &str| line.starts_with("cmd"));
+//! ```
+
+/// Check of the line before it is accepted
+pub trait Validator {
+    /// Return true if the line can be accepted
+    fn validate(&self, line: &str) -> bool;
+}
+
+/// Validator accepting all lines, used when no [`Validator`] is given
+pub struct NoValidator;
+
+impl Validator for NoValidator {
+    fn validate(&self, _line: &str) -> bool {
+        true
+    }
+}
+
+impl<F> Validator for F
+where
+    F: Fn(&str) -> bool,
+{
+    fn validate(&self, line: &str) -> bool {
+        self(line)
+    }
+}
@@ -0,0 +1,162 @@
+//! Output held back by the editors while the IO can't take it
+
+use crate::line_buffer::Buffer;
+
+// Bytes not yet accepted by the IO, oldest first. Output is added
+// behind held bytes, so it's written in order once the IO takes more.
+//
+// The bytes are kept in a ring, so written bytes are dropped without
+// moving the rest. The ring is the bytes of the buffer, and grows up to
+// its capacity when more bytes are held than fit.
+pub(crate) struct WriteBuffer<B: Buffer> {
+    buf: B,
+    // Start of the held bytes in the ring, and number of them
+    head: usize,
+    len: usize,
+}
+
+impl<B: Buffer> WriteBuffer<B> {
+    pub(crate) fn new(buf: B) -> Self {
+        Self {
+            buf,
+            head: 0,
+            len: 0,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Oldest held bytes, up to where they wrap around the end of the
+    // ring. The rest follows once these are consumed.
+    pub(crate) fn front(&self) -> &[u8] {
+        let ring = self.buf.as_slice();
+        let end = ring.len().min(self.head + self.len);
+
+        &ring[self.head..end]
+    }
+
+    // Hold bytes after the ones already held. Returns false, holding
+    // none of them, if they don't fit.
+    pub(crate) fn push(&mut self, bytes: &[u8]) -> bool {
+        let len = self.len + bytes.len();
+
+        if self.buf.capacity().is_some_and(|capacity| len > capacity) {
+            return false;
+        }
+
+        if len > self.buf.buffer_len() {
+            self.grow(len);
+        }
+
+        let slots = self.buf.buffer_len();
+
+        for (i, &byte) in bytes.iter().enumerate() {
+            self.buf
+                .replace_byte((self.head + self.len + i) % slots, byte);
+        }
+
+        self.len = len;
+
+        true
+    }
+
+    // Drop the first `len` bytes, once written
+    pub(crate) fn consume(&mut self, len: usize) {
+        let len = len.min(self.len);
+
+        self.len -= len;
+        self.head = match self.len {
+            0 => 0,
+            _ => (self.head + len) % self.buf.buffer_len(),
+        };
+    }
+
+    // Grow the ring to hold at least `len` bytes, doubling it so it's
+    // rarely grown. Held bytes are moved to the start first, so the new
+    // slots follow them.
+    fn grow(&mut self, len: usize) {
+        let slots = self.buf.buffer_len();
+        let capacity = self.buf.capacity().unwrap_or(usize::MAX);
+
+        self.reverse(0, self.head);
+        self.reverse(self.head, slots);
+        self.reverse(0, slots);
+        self.head = 0;
+
+        for index in slots..len.max(2 * slots).min(capacity) {
+            self.buf.insert_byte(index, 0);
+        }
+    }
+
+    fn reverse(&mut self, mut start: usize, mut end: usize) {
+        while start + 1 < end {
+            end -= 1;
+
+            let (first, last) = (self.buf.as_slice()[start], self.buf.as_slice()[end]);
+            self.buf.replace_byte(start, last);
+            self.buf.replace_byte(end, first);
+
+            start += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::line_buffer::{SliceBuffer, UnboundedBuffer};
+
+    use super::*;
+
+    // Held bytes in order, across the end of the ring
+    fn held<B: Buffer>(buffer: &WriteBuffer<B>) -> std::vec::Vec<u8> {
+        let ring = buffer.buf.as_slice();
+
+        (0..buffer.len)
+            .map(|i| ring[(buffer.head + i) % ring.len()])
+            .collect()
+    }
+
+    #[test]
+    fn write_buffer() {
+        let mut storage = [0; 4];
+        let mut buffer = WriteBuffer::new(SliceBuffer::new(&mut storage));
+
+        assert!(buffer.is_empty());
+        assert!(buffer.push(b"abc"));
+        assert!(!buffer.push(b"de"));
+        assert_eq!(buffer.front(), b"abc");
+
+        buffer.consume(2);
+        assert!(buffer.push(b"de"));
+        assert_eq!(held(&buffer), b"cde");
+
+        // Wrapped around the end of the ring
+        assert_eq!(buffer.front(), b"c");
+        buffer.consume(1);
+        assert_eq!(buffer.front(), b"de");
+
+        buffer.consume(2);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn unbounded_write_buffer() {
+        let mut buffer = WriteBuffer::new(UnboundedBuffer::new());
+
+        assert!(buffer.push(b"abcd"));
+        buffer.consume(3);
+        assert!(buffer.push(b"efg"));
+        assert_eq!(held(&buffer), b"defg");
+
+        // Held bytes wrapping around are kept in order when the ring
+        // grows
+        assert!(buffer.push(b"hijklm"));
+        assert_eq!(held(&buffer), b"defghijklm");
+        assert_eq!(buffer.front(), b"defghijklm");
+
+        buffer.consume(10);
+        assert!(buffer.is_empty());
+    }
+}